@@ -143,6 +143,7 @@ CONST = struct(
     MANUF_STATE = struct(
         PERSO_INITIAL = 0x00000000,
         PERSONALIZED = 0x53524550,  # ASCII `PERS`.
+        PRE_PRODUCTION = 0x50455250,  # ASCII `PREP`.
     ),
 )
 
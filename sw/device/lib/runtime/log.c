@@ -20,6 +20,17 @@
 static_assert(sizeof(log_fields_t) == 20,
               "log_fields_t must always be 20 bytes.");
 
+/**
+ * Minimum severity of the lines logged by `base_log_internal_core()`.
+ */
+static log_severity_t min_severity = kLogSeverityInfo;
+
+void base_log_set_min_severity(log_severity_t severity) {
+  min_severity = severity;
+}
+
+log_severity_t base_log_min_severity(void) { return min_severity; }
+
 /**
  * Converts a severity to a static string.
  */
@@ -45,6 +56,17 @@ static const char *stringify_severity(log_severity_t severity) {
  * @param ... format parameters matching the format string.
  */
 void base_log_internal_core(const log_fields_t *log, ...) {
+  // A small global counter that increments with each log line. This can be
+  // useful for seeing how many times this function has been called, even if
+  // nothing was printed for some time, or lines were dropped for their
+  // severity.
+  static uint16_t global_log_counter = 0;
+
+  if (log->severity < min_severity) {
+    ++global_log_counter;
+    return;
+  }
+
   size_t file_name_len =
       (size_t)(((const char *)memchr(log->file_name, '\0', PTRDIFF_MAX)) -
                log->file_name);
@@ -55,11 +77,6 @@ void base_log_internal_core(const log_fields_t *log, ...) {
     ++base_name;  // Remove the final '/'.
   }
 
-  // A small global counter that increments with each log line. This can be
-  // useful for seeing how many times this function has been called, even if
-  // nothing was printed for some time.
-  static uint16_t global_log_counter = 0;
-
   base_printf("%s%05d %s:%d] ", stringify_severity(log->severity),
               global_log_counter, base_name, log->line);
   ++global_log_counter;
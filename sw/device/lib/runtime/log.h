@@ -98,6 +98,24 @@ void base_log_internal_core(const log_fields_t *log, ...);
  */
 void base_log_internal_dv(const log_fields_t *log, uint32_t nargs, ...);
 
+/**
+ * Sets the minimum severity of the lines logged to the console.
+ *
+ * Lines of a lower severity are dropped, except for those logged with
+ * `LOG_PROMPT()`. Logs through the DV log bypass are not filtered. The default
+ * is `kLogSeverityInfo`, which logs all lines.
+ *
+ * @param severity the minimum severity to log.
+ */
+void base_log_set_min_severity(log_severity_t severity);
+
+/**
+ * Returns the minimum severity of the lines logged to the console.
+ *
+ * @return the severity set with `base_log_set_min_severity()`.
+ */
+log_severity_t base_log_min_severity(void);
+
 extern char _dv_log_offset[];
 
 /**
@@ -170,6 +188,24 @@ extern char _dv_log_offset[];
  */
 #define LOG_INFO(...) LOG(kLogSeverityInfo, __VA_ARGS__)
 
+/**
+ * Log an informational message a host synchronizes on.
+ *
+ * Unlike `LOG_INFO()`, the message is logged whatever the minimum severity set
+ * with `base_log_set_min_severity()`.
+ *
+ * @param format a format string, as described in print.h. This must be a string
+ * literal.
+ * @param ... format parameters matching the format string.
+ */
+#define LOG_PROMPT(...)                                          \
+  do {                                                           \
+    log_severity_t log_min_severity_ = base_log_min_severity();  \
+    base_log_set_min_severity(kLogSeverityInfo);                 \
+    LOG_INFO(__VA_ARGS__);                                       \
+    base_log_set_min_severity(log_min_severity_);                \
+  } while (false)
+
 /**
  * Log a warning
  *
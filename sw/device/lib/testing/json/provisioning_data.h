@@ -53,15 +53,75 @@ UJSON_SERDE_STRUCT(ManufCpTestData, \
 
 /**
  * Provisioning data imported onto the device in FT during individualization.
+ *
+ * `partitions` is a bitmask of the OTP partitions to individualize, see
+ * `manuf_ft_individualize_partition_t` in sram_ft_individualize.c.
+ *
+ * `ast_trim_mask` and `ast_trim_value` override bits of the AST calibration
+ * data written to flash during CP, before it is copied to the CREATOR_SW_CFG
+ * partition. The array size must match
+ * `kFlashInfoAstCalibrationDataSizeIn32BitWords`.
  */
 // clang-format off
 #define STRUCT_MANUF_FT_INDIVIDUALIZE_DATA(field, string) \
-    field(device_id, uint32_t, 8)
+    field(device_id, uint32_t, 8) \
+    field(partitions, uint32_t) \
+    field(ast_trim_mask, uint32_t, 39) \
+    field(ast_trim_value, uint32_t, 39)
 UJSON_SERDE_STRUCT(ManufFtIndividualizeData, \
                    manuf_ft_individualize_data_t, \
                    STRUCT_MANUF_FT_INDIVIDUALIZE_DATA);
 // clang-format on
 
+/**
+ * Alert handler configuration fields of the OWNER_SW_CFG partition.
+ *
+ * The host sends the fields to provision instead of the values of the SKU OTP
+ * image, as a bitmask of `manuf_alert_cfg_field_t` in `fields`, see
+ * sw/device/silicon_creator/manuf/lib/individualize_sw_cfg.h. The device
+ * answers with all the fields read back from OTP once the partition is
+ * written, and the `fields` it overrode.
+ *
+ * `digest` holds the ROM_ALERT_DIGEST_{PROD,PROD_END,DEV,RMA} fields, in this
+ * order.
+ */
+// clang-format off
+#define STRUCT_MANUF_OWNER_SW_CFG_ALERT_CFG(field, string) \
+    field(fields, uint32_t) \
+    field(class_en, uint32_t) \
+    field(escalation, uint32_t) \
+    field(accum_thresh, uint32_t, 4) \
+    field(timeout_cycles, uint32_t, 4) \
+    field(phase_cycles, uint32_t, 16) \
+    field(digest, uint32_t, 4)
+UJSON_SERDE_STRUCT(ManufOwnerSwCfgAlertCfg, \
+                   manuf_owner_sw_cfg_alert_cfg_t, \
+                   STRUCT_MANUF_OWNER_SW_CFG_ALERT_CFG);
+// clang-format on
+
+/**
+ * Chunk of an OTP partition dump exported off the device for failure analysis.
+ *
+ * `partition` indexes the readable partitions dumped by sram_otp_dump.c,
+ * `address` is the absolute OTP byte address of the partition, `offset` is the
+ * byte offset of `data` from the start of the partition, and only the first
+ * `num_words` words of `data` are valid. `last` is set on the final chunk of
+ * the dump.
+ */
+// clang-format off
+#define STRUCT_MANUF_OTP_DUMP_CHUNK(field, string) \
+    field(partition, uint32_t) \
+    field(address, uint32_t) \
+    field(offset, uint32_t) \
+    field(num_words, uint32_t) \
+    field(data, uint32_t, 64) \
+    field(digest, uint64_t) \
+    field(last, bool)
+UJSON_SERDE_STRUCT(ManufOtpDumpChunk, \
+                   manuf_otp_dump_chunk_t, \
+                   STRUCT_MANUF_OTP_DUMP_CHUNK);
+// clang-format on
+
 /**
  * ECC P256 public key.
  */
@@ -124,6 +184,181 @@ UJSON_SERDE_STRUCT(PersoBlob, \
                    STRUCT_PERSO_BLOB);
 // clang-format on
 
+/**
+ * Options negotiated with the host before personalization data is exported off
+ * the device.
+ *
+ * The host sends a bitmask of the `perso_blob_compression_t` schemes it can
+ * decompress in `compression`, and the device answers with the single scheme
+ * it picked, or zero if the perso blob is exported uncompressed. See
+ * sw/device/silicon_creator/manuf/base/perso_lz4.h.
+ *
+ * `health_snapshot` requests a `manuf_health_snapshot_t` at the end of
+ * personalization; the device answers whether it will send one.
+ *
+ * `chunked_import` requests the endorsed certificates be imported as
+ * `perso_blob_import_chunk_t` chunks acknowledged one by one, instead of a
+ * single `perso_blob_t`; the device answers whether it accepts.
+ *
+ * `cmd_id` is an ID of the host command, echoed in the answer of the device,
+ * for the host to tell it apart from stale answers buffered across a reset.
+ * The host never sends a zero `cmd_id`.
+ */
+// clang-format off
+#define STRUCT_MANUF_PERSO_EXPORT_OPTIONS(field, string) \
+    field(cmd_id, uint32_t) \
+    field(compression, uint32_t) \
+    field(health_snapshot, bool) \
+    field(chunked_import, bool)
+UJSON_SERDE_STRUCT(ManufPersoExportOptions, \
+                   manuf_perso_export_options_t, \
+                   STRUCT_MANUF_PERSO_EXPORT_OPTIONS);
+// clang-format on
+
+/**
+ * Minimum severity of the lines the device logs to the console.
+ *
+ * The host sends a `log_severity_t` at the start of each boot of the
+ * personalization firmware, and the device answers with the severity it
+ * applies for the rest of the boot, echoing the `cmd_id` of the host. Lines
+ * the host synchronizes on are logged whatever the severity. See
+ * sw/device/lib/runtime/log.h.
+ */
+// clang-format off
+#define STRUCT_MANUF_LOG_LEVEL(field, string) \
+    field(cmd_id, uint32_t) \
+    field(min_severity, uint32_t)
+UJSON_SERDE_STRUCT(ManufLogLevel, \
+                   manuf_log_level_t, \
+                   STRUCT_MANUF_LOG_LEVEL);
+// clang-format on
+
+/**
+ * CREATOR_SW_CFG_MANUF_STATE value provisioned at the end of personalization.
+ *
+ * The host sends the `value` to provision, or zero to provision the value of
+ * the SKU OTP image. The device answers with the value it will provision, and
+ * reports the value read back from OTP once the partition is locked, both
+ * echoing the `cmd_id` of the host.
+ */
+// clang-format off
+#define STRUCT_MANUF_CREATOR_MANUF_STATE(field, string) \
+    field(cmd_id, uint32_t) \
+    field(value, uint32_t)
+UJSON_SERDE_STRUCT(ManufCreatorManufState, \
+                   manuf_creator_manuf_state_t, \
+                   STRUCT_MANUF_CREATOR_MANUF_STATE);
+// clang-format on
+
+/**
+ * Sealing binding values of the keymgr stages of personalization.
+ *
+ * The host sends the sealing binding values of the OwnerIntermediate and
+ * Owner keymgr stages it derived for the `device_id`, or all zeros to keep the
+ * defaults of the personalization firmware. The device answers with the values
+ * it binds and the device ID read from the HW_CFG0 partition, echoing the
+ * `cmd_id` of the host.
+ */
+// clang-format off
+#define STRUCT_MANUF_KEYMGR_BINDING(field, string) \
+    field(cmd_id, uint32_t) \
+    field(device_id, uint32_t, 8) \
+    field(owner_int_sealing, uint32_t, 8) \
+    field(owner_sealing, uint32_t, 8)
+UJSON_SERDE_STRUCT(ManufKeymgrBinding, \
+                   manuf_keymgr_binding_t, \
+                   STRUCT_MANUF_KEYMGR_BINDING);
+// clang-format on
+
+/**
+ * Chunk of a compressed `perso_blob_t` exported off the device.
+ *
+ * `num_objs` and `next_free` are those of the uncompressed perso blob, and
+ * `crc32` is the CRC32 of its first `next_free` body bytes. `size` is the
+ * total size of the compressed body, `offset` is the offset of `data` in the
+ * compressed body, and only the first `num_bytes` bytes of `data` are valid.
+ * `last` is set on the final chunk.
+ */
+// clang-format off
+#define STRUCT_PERSO_BLOB_CHUNK(field, string) \
+    field(compression, uint32_t) \
+    field(num_objs, size_t) \
+    field(next_free, size_t) \
+    field(crc32, uint32_t) \
+    field(size, size_t) \
+    field(offset, size_t) \
+    field(num_bytes, size_t) \
+    field(data, uint8_t, 512) \
+    field(last, bool)
+UJSON_SERDE_STRUCT(PersoBlobChunk, \
+                   perso_blob_chunk_t, \
+                   STRUCT_PERSO_BLOB_CHUNK);
+// clang-format on
+
+/**
+ * Chunk of the `perso_blob_t` of endorsed certificates imported into the
+ * device.
+ *
+ * `num_objs` and `next_free` are those of the whole perso blob. `offset` is
+ * the offset of `data` in the body, only the first `num_bytes` bytes of `data`
+ * are valid, and `crc32` is their CRC32. `last` is set on the final chunk.
+ * `cmd_id` is a new ID for each chunk sent, including resent chunks.
+ */
+// clang-format off
+#define STRUCT_PERSO_BLOB_IMPORT_CHUNK(field, string) \
+    field(cmd_id, uint32_t) \
+    field(num_objs, size_t) \
+    field(next_free, size_t) \
+    field(offset, size_t) \
+    field(num_bytes, size_t) \
+    field(crc32, uint32_t) \
+    field(data, uint8_t, 512) \
+    field(last, bool)
+UJSON_SERDE_STRUCT(PersoBlobImportChunk, \
+                   perso_blob_import_chunk_t, \
+                   STRUCT_PERSO_BLOB_IMPORT_CHUNK);
+// clang-format on
+
+/**
+ * Acknowledgment of a `perso_blob_import_chunk_t` by the device.
+ *
+ * `accepted` is cleared if the chunk was malformed, corrupted or out of order,
+ * and must be resent. `offset` is the offset of the next body byte the device
+ * expects. `cmd_id` is the `cmd_id` of the chunk, or zero if the chunk could
+ * not be parsed.
+ */
+// clang-format off
+#define STRUCT_PERSO_BLOB_CHUNK_ACK(field, string) \
+    field(cmd_id, uint32_t) \
+    field(offset, size_t) \
+    field(accepted, bool)
+UJSON_SERDE_STRUCT(PersoBlobChunkAck, \
+                   perso_blob_chunk_ack_t, \
+                   STRUCT_PERSO_BLOB_CHUNK_ACK);
+// clang-format on
+
+/**
+ * Health snapshot of a personalized device in its mission mode LC state.
+ *
+ * `lc_state` is the `dif_lc_ctrl_state_t` of the device,
+ * `rom_ext_measurement` the ROM_EXT measurement recorded by the ROM, and
+ * `keymgr_state` the `sc_keymgr_state_t` of the key manager. The `flash_*`
+ * fields are the `multi_bit_bool_t` scrambling, ECC and high endurance
+ * settings of the flash data partition.
+ */
+// clang-format off
+#define STRUCT_MANUF_HEALTH_SNAPSHOT(field, string) \
+    field(lc_state, uint32_t) \
+    field(rom_ext_measurement, uint32_t, 8) \
+    field(keymgr_state, uint32_t) \
+    field(flash_scrambling, uint32_t) \
+    field(flash_ecc, uint32_t) \
+    field(flash_high_endurance, uint32_t)
+UJSON_SERDE_STRUCT(ManufHealthSnapshot, \
+                   manuf_health_snapshot_t, \
+                   STRUCT_MANUF_HEALTH_SNAPSHOT);
+// clang-format on
+
 /**
  * Sha256 hash digest.
  */
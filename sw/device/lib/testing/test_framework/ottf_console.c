@@ -65,6 +65,9 @@ void ottf_console_init(void) {
                                  kOttfTestConfig.console.putbuf_buffered,
                                  main_spi_buf, sizeof(main_spi_buf));
       break;
+    case kOttfConsoleDmi:
+      ottf_console_configure_dmi(&main_console);
+      break;
     default:
       CHECK(false, "unsupported OTTF console interface.");
       break;
@@ -108,6 +108,18 @@ void ottf_console_configure_spi_device(ottf_console_t *console,
                                        uint32_t tx_ready_gpio,
                                        uint32_t tx_ready_mio);
 
+/**
+ * Configures the RAM mailbox of the debug module to be used by the OTTF
+ * console.
+ *
+ * The host reads and writes the mailbox over JTAG: writes to the console block
+ * until the host makes room in the mailbox, and fail with `kDataLoss` if the
+ * host does not drain it within a second.
+ *
+ * @param console Console pointer
+ */
+void ottf_console_configure_dmi(ottf_console_t *console);
+
 /**
  * Configures software buffering of the console.
  *
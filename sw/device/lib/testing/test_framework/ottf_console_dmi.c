@@ -0,0 +1,84 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+#include <stdbool.h>
+#include <stdint.h>
+
+#include "sw/device/lib/base/status.h"
+#include "sw/device/lib/runtime/ibex.h"
+#include "sw/device/lib/testing/test_framework/ottf_console.h"
+#include "sw/device/lib/testing/test_framework/ottf_console_internal.h"
+
+#define MODULE_ID MAKE_MODULE_ID('o', 'c', 'd')
+
+/**
+ * Mailbox of the DMI console, accessed by the host over the system bus of the
+ * RISC-V debug module while the CPU runs.
+ *
+ * The host finds the mailbox by scanning the main SRAM for the magic number
+ * followed by its complement. Each ring has a single writer: the device only
+ * moves `tx_head` and `rx_tail`, the host only moves `tx_tail` and `rx_head`.
+ * Heads and tails are free-running byte counts; a ring is full once its head
+ * is `size` bytes ahead of its tail.
+ */
+typedef struct ottf_console_dmi_mailbox {
+  uint32_t magic;
+  uint32_t magic_inv;
+  uint32_t tx_size;
+  uint32_t rx_size;
+  uint32_t tx_head;
+  uint32_t tx_tail;
+  uint32_t rx_head;
+  uint32_t rx_tail;
+  uint8_t tx[kDmiConsoleTxBufferSizeBytes];
+  uint8_t rx[kDmiConsoleRxBufferSizeBytes];
+} ottf_console_dmi_mailbox_t;
+
+static volatile ottf_console_dmi_mailbox_t ottf_console_dmi_mailbox;
+
+static status_t ottf_console_dmi_getc(void *io) {
+  volatile ottf_console_dmi_mailbox_t *mailbox = &ottf_console_dmi_mailbox;
+  uint32_t tail = mailbox->rx_tail;
+  while (mailbox->rx_head == tail) {
+    // Wait for the host to send a byte.
+  }
+  uint8_t byte = mailbox->rx[tail % kDmiConsoleRxBufferSizeBytes];
+  mailbox->rx_tail = tail + 1;
+  return OK_STATUS(byte);
+}
+
+static size_t ottf_console_dmi_sink(void *io, const char *buf, size_t len) {
+  volatile ottf_console_dmi_mailbox_t *mailbox = &ottf_console_dmi_mailbox;
+  for (size_t i = 0; i < len; ++i) {
+    uint32_t head = mailbox->tx_head;
+    ibex_timeout_t timeout = ibex_timeout_init(kDmiConsoleTxTimeoutUsec);
+    while (head - mailbox->tx_tail >= kDmiConsoleTxBufferSizeBytes) {
+      // Wait for the host to make room in the ring. A short write makes the
+      // OTTF console report the dropped bytes as `kDataLoss`.
+      if (ibex_timeout_check(&timeout)) {
+        return i;
+      }
+    }
+    mailbox->tx[head % kDmiConsoleTxBufferSizeBytes] = (uint8_t)buf[i];
+    mailbox->tx_head = head + 1;
+  }
+  return len;
+}
+
+void ottf_console_configure_dmi(ottf_console_t *console) {
+  volatile ottf_console_dmi_mailbox_t *mailbox = &ottf_console_dmi_mailbox;
+  console->type = kOttfConsoleDmi;
+  mailbox->tx_size = kDmiConsoleTxBufferSizeBytes;
+  mailbox->rx_size = kDmiConsoleRxBufferSizeBytes;
+  mailbox->tx_head = 0;
+  mailbox->tx_tail = 0;
+  mailbox->rx_head = 0;
+  mailbox->rx_tail = 0;
+  // Publish the mailbox to the host once it is initialized.
+  mailbox->magic_inv = ~(uint32_t)kDmiConsoleMagicNumber;
+  mailbox->magic = kDmiConsoleMagicNumber;
+
+  console->getc = ottf_console_dmi_getc;
+  console->sink = ottf_console_dmi_sink;
+}
@@ -29,6 +29,21 @@ enum {
   kSpiDeviceFrameMagicNumber = 0xa5a5beef,
 };
 
+/**
+ * DMI console mailbox constants, must be kept in sync with
+ * `DmiConsoleDevice` in sw/host/opentitanlib/src/console/dmi.rs.
+ */
+enum {
+  kDmiConsoleMagicNumber = 0x4d44544f,  // "OTDM"
+  kDmiConsoleTxBufferSizeBytes = 1024,
+  kDmiConsoleRxBufferSizeBytes = 256,
+  /**
+   * How long a write waits for the host to make room in the TX ring before
+   * the console drops the rest of the write.
+   */
+  kDmiConsoleTxTimeoutUsec = 1000000,
+};
+
 void ottf_console_uart_flow_control_enable(ottf_console_t *console);
 bool ottf_console_uart_flow_control_isr(uint32_t *exc_info,
                                         ottf_console_t *console);
@@ -13,6 +13,11 @@
 typedef enum ottf_console_type {
   kOttfConsoleUart = 0,
   kOttfConsoleSpiDevice,
+  /**
+   * RAM mailbox read and written by the host over the system bus of the RISC-V
+   * debug module, for packages without an accessible UART.
+   */
+  kOttfConsoleDmi,
 } ottf_console_type_t;
 
 typedef struct ottf_console_opt {
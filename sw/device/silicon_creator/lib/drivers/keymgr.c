@@ -142,6 +142,12 @@ rom_error_t sc_keymgr_state_check(sc_keymgr_state_t expected_state) {
   return expected_state_check(expected_state);
 }
 
+sc_keymgr_state_t sc_keymgr_state_get(void) {
+  uint32_t reg = abs_mmio_read32(kBase + KEYMGR_WORKING_STATE_REG_OFFSET);
+  return (sc_keymgr_state_t)bitfield_field32_read(
+      reg, KEYMGR_WORKING_STATE_STATE_FIELD);
+}
+
 /**
  * Fails if the keymgr is not idle.
  *
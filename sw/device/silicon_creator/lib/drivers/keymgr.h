@@ -193,6 +193,17 @@ void sc_keymgr_advance_state(void);
 OT_WARN_UNUSED_RESULT
 rom_error_t sc_keymgr_state_check(sc_keymgr_state_t expected_state);
 
+/**
+ * Reads the working state of the key manager.
+ *
+ * Unlike `sc_keymgr_state_check()`, this function neither checks nor clears
+ * the status and error code registers.
+ *
+ * @return The current key manager state.
+ */
+OT_WARN_UNUSED_RESULT
+sc_keymgr_state_t sc_keymgr_state_get(void);
+
 /**
  * Keymgr output-generate key types (attestation or sealing).
  */
@@ -189,6 +189,12 @@ TEST_F(KeymgrTest, CheckState) {
   EXPECT_EQ(sc_keymgr_state_check(kScKeymgrStateCreatorRootKey), kErrorOk);
 }
 
+TEST_F(KeymgrTest, GetState) {
+  EXPECT_ABS_READ32(base_ + KEYMGR_WORKING_STATE_REG_OFFSET,
+                    KEYMGR_WORKING_STATE_STATE_VALUE_OWNER_KEY);
+  EXPECT_EQ(sc_keymgr_state_get(), kScKeymgrStateOwnerKey);
+}
+
 TEST_F(KeymgrTest, CheckStateInvalidResponse) {
   ExpectStatusCheck(KEYMGR_OP_STATUS_STATUS_VALUE_IDLE,
                     KEYMGR_WORKING_STATE_STATE_VALUE_INVALID,
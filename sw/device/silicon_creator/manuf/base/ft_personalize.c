@@ -5,6 +5,7 @@
 #include <stdalign.h>
 
 #include "sw/device/lib/arch/device.h"
+#include "sw/device/lib/base/crc32.h"
 #include "sw/device/lib/base/macros.h"
 #include "sw/device/lib/crypto/drivers/entropy.h"
 #include "sw/device/lib/dif/dif_flash_ctrl.h"
@@ -34,12 +35,15 @@
 #include "sw/device/silicon_creator/lib/drivers/hmac.h"
 #include "sw/device/silicon_creator/lib/drivers/keymgr.h"
 #include "sw/device/silicon_creator/lib/drivers/kmac.h"
+#include "sw/device/silicon_creator/lib/drivers/lifecycle.h"
 #include "sw/device/silicon_creator/lib/drivers/otp.h"
 #include "sw/device/silicon_creator/lib/error.h"
 #include "sw/device/silicon_creator/lib/manifest.h"
 #include "sw/device/silicon_creator/lib/otbn_boot_services.h"
 #include "sw/device/silicon_creator/lib/ownership/owner_block.h"
 #include "sw/device/silicon_creator/lib/ownership/ownership_key.h"
+#include "sw/device/silicon_creator/manuf/base/manuf_console.h"
+#include "sw/device/silicon_creator/manuf/base/perso_lz4.h"
 #include "sw/device/silicon_creator/manuf/base/perso_tlv_data.h"
 #include "sw/device/silicon_creator/manuf/base/personalize_ext.h"
 #include "sw/device/silicon_creator/manuf/lib/flash_info_fields.h"
@@ -49,7 +53,7 @@
 #include "flash_ctrl_regs.h"  // Generated.
 #include "hw/top_earlgrey/sw/autogen/top_earlgrey.h"
 
-OTTF_DEFINE_TEST_CONFIG(.console.type = kOttfConsoleSpiDevice,
+OTTF_DEFINE_TEST_CONFIG(.console.type = MANUF_CONSOLE_TYPE,
                         .console.base_addr = TOP_EARLGREY_SPI_DEVICE_BASE_ADDR,
                         .console.test_may_clobber = false, );
 
@@ -61,6 +65,11 @@ enum {
       (OTP_CTRL_PARAM_OWNER_SW_CFG_SIZE -
        OTP_CTRL_PARAM_OWNER_SW_CFG_DIGEST_SIZE) /
       sizeof(uint32_t),
+  /**
+   * Number of times in a row a chunk of the endorsed certificates may be
+   * rejected before the import fails.
+   */
+  kPersoBlobImportMaxRetries = 3,
 };
 
 static uint32_t otp_state[kDiceMeasuredOtpPartitionMaxSizeIn32bitWords] = {0};
@@ -118,6 +127,18 @@ static ecdsa_p256_public_key_t uds_pubkey = {.x = {0}, .y = {0}};
 static perso_blob_t perso_blob_to_host;    // Perso data device => host.
 static perso_blob_t perso_blob_from_host;  // Perso data host => device.
 
+/**
+ * Compressed perso data export, negotiated with the host.
+ */
+static manuf_perso_export_options_t export_options;
+static manuf_log_level_t log_level;
+static manuf_creator_manuf_state_t creator_manuf_state;
+static manuf_keymgr_binding_t keymgr_binding;
+static perso_blob_chunk_t perso_blob_chunk;
+static perso_blob_import_chunk_t perso_blob_import_chunk;
+static uint8_t perso_blob_compressed[PERSO_LZ4_COMPRESS_BOUND(
+    sizeof(perso_blob_to_host.body))];
+
 /**
  * Certificates flash info page layout.
  */
@@ -271,6 +292,22 @@ static status_t measure_otp_partition(otp_partition_t partition,
   return OK_STATUS();
 }
 
+/**
+ * Sets the minimum severity of the console logs for the rest of the boot, as
+ * requested by the host.
+ */
+static status_t set_log_level(ujson_t *uj) {
+  // DO NOT CHANGE THE BELOW STRING without modifying the host code in
+  // sw/host/provisioning/ft_lib/src/log_level.rs
+  LOG_PROMPT("Waiting for log level ...");
+  TRY(ujson_deserialize_manuf_log_level_t(uj, &log_level));
+  if (log_level.min_severity > kLogSeverityError) {
+    log_level.min_severity = kLogSeverityError;
+  }
+  base_log_set_min_severity((log_severity_t)log_level.min_severity);
+  return RESP_OK(ujson_serialize_manuf_log_level_t, uj, &log_level);
+}
+
 /**
  * Provision OTP SECRET{1,2} partitions, keymgr flash info pages, enable flash
  * scrambling, and reboot.
@@ -286,7 +323,7 @@ static status_t personalize_otp_and_flash_secrets(ujson_t *uj) {
     TRY(manuf_individualize_device_field_cfg(
         &otp_ctrl,
         OTP_CTRL_PARAM_CREATOR_SW_CFG_FLASH_DATA_DEFAULT_CFG_OFFSET));
-    LOG_INFO("Bootstrap requested.");
+    LOG_PROMPT("Bootstrap requested.");
     wait_for_interrupt();
   }
 
@@ -296,7 +333,7 @@ static status_t personalize_otp_and_flash_secrets(ujson_t *uj) {
     lc_token_hash_t token_hash;
     // Wait for host the host generated RMA unlock token hash to arrive over the
     // console.
-    LOG_INFO("Waiting For RMA Unlock Token Hash ...");
+    LOG_PROMPT("Waiting For RMA Unlock Token Hash ...");
     CHECK_STATUS_OK(
         UJSON_WITH_CRC(ujson_deserialize_lc_token_hash_t, uj, &token_hash));
 
@@ -325,23 +362,55 @@ static status_t personalize_otp_and_flash_secrets(ujson_t *uj) {
 }
 
 /**
- * Sets the attestation binding to the ROM_EXT measurement, and the sealing
- * binding to all zeros.
+ * Returns whether all the words of a keymgr binding value are zero.
+ */
+static bool keymgr_binding_is_zero(const uint32_t *value) {
+  for (size_t i = 0; i < kDiceMeasurementSizeInBytes / sizeof(uint32_t); ++i) {
+    if (value[i] != 0) {
+      return false;
+    }
+  }
+  return true;
+}
+
+/**
+ * Receives the sealing binding values derived by the host, and answers with the
+ * values bound and the device ID in OTP.
  *
- * The sealing binding value is set to all zeros as it is unused in the current
- * personalization flow. This may be changed in the future.
+ * All-zero values are replaced with the defaults: all zeros for the
+ * OwnerIntermediate stage, unused in the current personalization flow, and the
+ * PROD application key domain for the Owner stage.
+ */
+static status_t recv_keymgr_binding(ujson_t *uj) {
+  // DO NOT CHANGE THE BELOW STRING without modifying the host code in
+  // sw/host/provisioning/ft_lib/src/keymgr_binding.rs
+  LOG_PROMPT("Waiting for keymgr binding values ...");
+  TRY(ujson_deserialize_manuf_keymgr_binding_t(uj, &keymgr_binding));
+  if (keymgr_binding_is_zero(keymgr_binding.owner_sealing)) {
+    keymgr_binding.owner_sealing[0] = kOwnerAppDomainProd;
+  }
+  static_assert(
+      sizeof(keymgr_binding.device_id) == sizeof(lifecycle_device_id_t),
+      "Unexpected device ID size");
+  lifecycle_device_id_get((lifecycle_device_id_t *)keymgr_binding.device_id);
+  return RESP_OK(ujson_serialize_manuf_keymgr_binding_t, uj, &keymgr_binding);
+}
+
+/**
+ * Sets the attestation binding to the ROM_EXT measurement, and the sealing
+ * binding to the OwnerIntermediate value received from the host.
  */
 static void compute_keymgr_owner_int_binding(manuf_certgen_inputs_t *inputs) {
   memcpy(attestation_binding_value.data, inputs->rom_ext_measurement,
          kDiceMeasurementSizeInBytes);
-  memset(sealing_binding_value.data, 0, kDiceMeasurementSizeInBytes);
+  memcpy(sealing_binding_value.data, keymgr_binding.owner_int_sealing,
+         kDiceMeasurementSizeInBytes);
 }
 
 /**
  * Sets the attestation binding to a combination of the Owner firmware and
- * Ownership Manifest measurements, and the sealing binding to all zeros.
- *
- * The sealing binding value is set to the TEST application key domain.
+ * Ownership Manifest measurements, and the sealing binding to the Owner value
+ * received from the host.
  */
 static void compute_keymgr_owner_binding(manuf_certgen_inputs_t *inputs) {
   hmac_digest_t combined_measurements;
@@ -354,8 +423,8 @@ static void compute_keymgr_owner_binding(manuf_certgen_inputs_t *inputs) {
   hmac_sha256_final(&combined_measurements);
   memcpy(attestation_binding_value.data, combined_measurements.digest,
          kDiceMeasurementSizeInBytes);
-  memset(sealing_binding_value.data, 0, kDiceMeasurementSizeInBytes);
-  sealing_binding_value.data[0] = kOwnerAppDomainProd;
+  memcpy(sealing_binding_value.data, keymgr_binding.owner_sealing,
+         kDiceMeasurementSizeInBytes);
 }
 
 /**
@@ -449,13 +518,36 @@ static status_t personalize_gen_dice_certificates(ujson_t *uj) {
   // Retrieve certificate provisioning data.
   // DO NOT CHANGE THE BELOW STRING without modifying the host code in
   // sw/host/provisioning/ft_lib/src/lib.rs
-  LOG_INFO("Waiting for certificate inputs ...");
+  LOG_PROMPT("Waiting for certificate inputs ...");
   TRY(ujson_deserialize_manuf_certgen_inputs_t(uj, &certgen_inputs));
   // We copy over the UDS endorsement key ID to an SHA256 digest type, since
   // this is the format of key IDs generated on-dice.
   memcpy(uds_endorsement_key_id.digest, certgen_inputs.dice_auth_key_key_id,
          kCertKeyIdSizeInBytes);
 
+  // Negotiate the compression of the exported perso data.
+  // DO NOT CHANGE THE BELOW STRING without modifying the host code in
+  // sw/host/provisioning/ft_lib/src/lib.rs
+  LOG_PROMPT("Waiting for export options ...");
+  TRY(ujson_deserialize_manuf_perso_export_options_t(uj, &export_options));
+  export_options.compression &= 1u << kPersoBlobCompressionLz4;
+  RESP_OK(ujson_serialize_manuf_perso_export_options_t, uj, &export_options);
+
+  // Set the creator manufacturing state before the CREATOR_SW_CFG partition is
+  // measured below.
+  // DO NOT CHANGE THE BELOW STRING without modifying the host code in
+  // sw/host/provisioning/ft_lib/src/lib.rs
+  LOG_PROMPT("Waiting for creator manufacturing state ...");
+  TRY(ujson_deserialize_manuf_creator_manuf_state_t(uj, &creator_manuf_state));
+  manuf_individualize_device_creator_manuf_state_set(creator_manuf_state.value);
+  creator_manuf_state.value =
+      manuf_individualize_device_creator_manuf_state_get();
+  RESP_OK(ujson_serialize_manuf_creator_manuf_state_t, uj,
+          &creator_manuf_state);
+
+  // Receive the sealing binding values of the keymgr stages advanced below.
+  TRY(recv_keymgr_binding(uj));
+
   // Initialize entropy complex / KMAC for key manager operations.
   TRY(entropy_complex_init());
   TRY(kmac_keymgr_configure());
@@ -697,6 +789,95 @@ static status_t write_cert_to_flash_info_page(
   return OK_STATUS();
 }
 
+/**
+ * Exports `perso_blob_to_host`, compressed if negotiated with the host.
+ */
+static status_t export_perso_blob(ujson_t *uj) {
+  if (export_options.compression == 0) {
+    return RESP_OK(ujson_serialize_perso_blob_t, uj, &perso_blob_to_host);
+  }
+
+  size_t size;
+  TRY(perso_lz4_compress(perso_blob_to_host.body, perso_blob_to_host.next_free,
+                         perso_blob_compressed, sizeof(perso_blob_compressed),
+                         &size));
+  uint32_t body_crc32 =
+      crc32(perso_blob_to_host.body, perso_blob_to_host.next_free);
+  size_t offset = 0;
+  do {
+    memset(&perso_blob_chunk, 0, sizeof(perso_blob_chunk));
+    perso_blob_chunk.compression = kPersoBlobCompressionLz4;
+    perso_blob_chunk.num_objs = perso_blob_to_host.num_objs;
+    perso_blob_chunk.next_free = perso_blob_to_host.next_free;
+    perso_blob_chunk.crc32 = body_crc32;
+    perso_blob_chunk.size = size;
+    perso_blob_chunk.offset = offset;
+    perso_blob_chunk.num_bytes = size - offset;
+    if (perso_blob_chunk.num_bytes > sizeof(perso_blob_chunk.data)) {
+      perso_blob_chunk.num_bytes = sizeof(perso_blob_chunk.data);
+    }
+    memcpy(perso_blob_chunk.data, &perso_blob_compressed[offset],
+           perso_blob_chunk.num_bytes);
+    offset += perso_blob_chunk.num_bytes;
+    perso_blob_chunk.last = offset == size;
+    RESP_OK(ujson_serialize_perso_blob_chunk_t, uj, &perso_blob_chunk);
+  } while (offset < size);
+  return OK_STATUS();
+}
+
+/**
+ * Imports `perso_blob_from_host`, in acknowledged chunks if negotiated with the
+ * host.
+ *
+ * A chunk which fails to parse, is out of order, or does not match its CRC32
+ * is rejected, for the host to resend it.
+ */
+static status_t import_perso_blob(ujson_t *uj) {
+  if (!export_options.chunked_import) {
+    return ujson_deserialize_perso_blob_t(uj, &perso_blob_from_host);
+  }
+
+  memset(&perso_blob_from_host, 0, sizeof(perso_blob_from_host));
+  perso_blob_import_chunk_t *chunk = &perso_blob_import_chunk;
+  perso_blob_chunk_ack_t ack = {.cmd_id = 0, .offset = 0, .accepted = false};
+  size_t retries = 0;
+  do {
+    memset(chunk, 0, sizeof(*chunk));
+    bool parsed =
+        status_ok(ujson_deserialize_perso_blob_import_chunk_t(uj, chunk));
+    // Echo the ID of the chunk, for the host to match the acknowledgment.
+    ack.cmd_id = chunk->cmd_id;
+    ack.accepted =
+        parsed && chunk->offset == ack.offset &&
+        chunk->num_bytes <= sizeof(chunk->data) &&
+        chunk->num_bytes <= sizeof(perso_blob_from_host.body) - ack.offset &&
+        crc32(chunk->data, chunk->num_bytes) == chunk->crc32;
+    if (ack.accepted) {
+      memcpy(&perso_blob_from_host.body[ack.offset], chunk->data,
+             chunk->num_bytes);
+      ack.offset += chunk->num_bytes;
+      retries = 0;
+    } else {
+      ++retries;
+    }
+    RESP_OK(ujson_serialize_perso_blob_chunk_ack_t, uj, &ack);
+    if (retries > kPersoBlobImportMaxRetries) {
+      LOG_ERROR("Endorsed certificates chunk at offset %d rejected %d times.",
+                ack.offset, retries);
+      return DATA_LOSS();
+    }
+  } while (!(ack.accepted && chunk->last));
+
+  if (ack.offset != chunk->next_free) {
+    LOG_ERROR("Endorsed certificates truncated: %d of %d bytes.", ack.offset,
+              chunk->next_free);
+    return DATA_LOSS();
+  }
+  perso_blob_from_host.num_objs = chunk->num_objs;
+  perso_blob_from_host.next_free = chunk->next_free;
+  return OK_STATUS();
+}
+
 static status_t personalize_endorse_certificates(ujson_t *uj) {
   /*****************************************************************************
    * Certificate Export and Endorsement.
@@ -704,15 +885,14 @@ static status_t personalize_endorse_certificates(ujson_t *uj) {
   // Export the certificates to the provisioning appliance.
   // DO NOT CHANGE THE BELOW STRING without modifying the host code in
   // sw/host/provisioning/ft_lib/src/lib.rs
-  LOG_INFO("Exporting TBS certificates ...");
-
-  RESP_OK(ujson_serialize_perso_blob_t, uj, &perso_blob_to_host);
+  LOG_PROMPT("Exporting TBS certificates ...");
+  TRY(export_perso_blob(uj));
 
   // Import endorsed certificates from the provisioning appliance.
   // DO NOT CHANGE THE BELOW STRING without modifying the host code in
   // sw/host/provisioning/ft_lib/src/lib.rs
-  LOG_INFO("Importing endorsed certificates ...");
-  TRY(ujson_deserialize_perso_blob_t(uj, &perso_blob_from_host));
+  LOG_PROMPT("Importing endorsed certificates ...");
+  TRY(import_perso_blob(uj));
 
   /*****************************************************************************
    * Rearrange certificates to prepare for writing to flash.
@@ -811,7 +991,7 @@ static status_t personalize_endorse_certificates(ujson_t *uj) {
 
   // DO NOT CHANGE THE BELOW STRING without modifying the host code in
   // sw/host/provisioning/ft_lib/src/lib.rs
-  LOG_INFO("Finished importing certificates.");
+  LOG_PROMPT("Finished importing certificates.");
 
   return OK_STATUS();
 }
@@ -885,10 +1065,54 @@ static status_t finalize_otp_partitions(void) {
   return OK_STATUS();
 }
 
+/**
+ * Sends the creator manufacturing state read back from the locked
+ * CREATOR_SW_CFG partition to the host.
+ */
+static status_t send_creator_manuf_state(ujson_t *uj) {
+  // `creator_manuf_state.cmd_id` still holds the ID of the host request.
+  creator_manuf_state.value =
+      otp_read32(OTP_CTRL_PARAM_CREATOR_SW_CFG_MANUF_STATE_OFFSET);
+  // DO NOT CHANGE THE BELOW STRING without modifying the host code in
+  // sw/host/provisioning/ft_lib/src/lib.rs
+  LOG_PROMPT("Exporting creator manufacturing state ...");
+  return RESP_OK(ujson_serialize_manuf_creator_manuf_state_t, uj,
+                 &creator_manuf_state);
+}
+
+/**
+ * Sends a health snapshot of the device to the host, if it requested one in
+ * the export options.
+ */
+static status_t send_health_snapshot(ujson_t *uj) {
+  if (!export_options.health_snapshot) {
+    return OK_STATUS();
+  }
+  manuf_health_snapshot_t snapshot;
+  memset(&snapshot, 0, sizeof(snapshot));
+
+  dif_lc_ctrl_state_t lc_state;
+  TRY(dif_lc_ctrl_get_state(&lc_ctrl, &lc_state));
+  snapshot.lc_state = lc_state;
+  memcpy(snapshot.rom_ext_measurement, boot_measurements.rom_ext.data,
+         sizeof(snapshot.rom_ext_measurement));
+  snapshot.keymgr_state = sc_keymgr_state_get();
+  flash_ctrl_cfg_t flash_cfg = flash_ctrl_data_default_cfg_get();
+  snapshot.flash_scrambling = flash_cfg.scrambling;
+  snapshot.flash_ecc = flash_cfg.ecc;
+  snapshot.flash_high_endurance = flash_cfg.he;
+
+  // DO NOT CHANGE THE BELOW STRING without modifying the host code in
+  // sw/host/provisioning/ft_lib/src/lib.rs
+  LOG_PROMPT("Exporting health snapshot ...");
+  return RESP_OK(ujson_serialize_manuf_health_snapshot_t, uj, &snapshot);
+}
+
 bool test_main(void) {
   CHECK_STATUS_OK(peripheral_handles_init());
   CHECK_STATUS_OK(entropy_complex_init());
   ujson_t uj = ujson_ottf_console();
+  CHECK_STATUS_OK(set_log_level(&uj));
   log_self_hash();
   CHECK_STATUS_OK(lc_ctrl_testutils_operational_state_check(&lc_ctrl));
   CHECK_STATUS_OK(personalize_otp_and_flash_secrets(&uj));
@@ -930,9 +1154,11 @@ bool test_main(void) {
            hash.data[2], hash.data[1], hash.data[0]);
 
   CHECK_STATUS_OK(finalize_otp_partitions());
+  CHECK_STATUS_OK(send_creator_manuf_state(&uj));
+  CHECK_STATUS_OK(send_health_snapshot(&uj));
   // DO NOT CHANGE THE BELOW STRING without modifying the host code in
   // sw/host/provisioning/ft_lib/src/lib.rs
-  LOG_INFO("Personalization done.");
+  LOG_PROMPT("Personalization done.");
 
   return true;
 }
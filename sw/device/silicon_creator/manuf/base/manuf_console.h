@@ -0,0 +1,24 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+#ifndef OPENTITAN_SW_DEVICE_SILICON_CREATOR_MANUF_BASE_MANUF_CONSOLE_H_
+#define OPENTITAN_SW_DEVICE_SILICON_CREATOR_MANUF_BASE_MANUF_CONSOLE_H_
+
+#include "sw/device/lib/testing/test_framework/ottf_test_config.h"
+
+/**
+ * OTTF console type of the provisioning firmware.
+ *
+ * The SPI device by default, or the debug module console when built with
+ * `--//sw/device/silicon_creator/manuf/base:console=dmi`, for packages without
+ * an accessible SPI device. The host must select the same console, see
+ * `--console` of the FT harness.
+ */
+#ifdef MANUF_CONSOLE_DMI
+#define MANUF_CONSOLE_TYPE kOttfConsoleDmi
+#else
+#define MANUF_CONSOLE_TYPE kOttfConsoleSpiDevice
+#endif
+
+#endif  // OPENTITAN_SW_DEVICE_SILICON_CREATOR_MANUF_BASE_MANUF_CONSOLE_H_
@@ -0,0 +1,111 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+#include "sw/device/silicon_creator/manuf/base/perso_lz4.h"
+
+#include "sw/device/lib/base/memory.h"
+
+enum {
+  kLz4MinMatch = 4,
+  kLz4HashBits = 12,
+  // The LZ4 block format requires the last 5 bytes to be literals, and the
+  // last match to start at least 12 bytes before the end of the block.
+  kLz4LastLiterals = 5,
+  kLz4MatchSafeDistance = 12,
+  kLz4MaxOffset = UINT16_MAX,
+  kLz4TokenMax = 15,
+};
+
+static uint16_t hash_table[1 << kLz4HashBits];
+
+static size_t lz4_hash(uint32_t sequence) {
+  return (sequence * 2654435761u) >> (32 - kLz4HashBits);
+}
+
+static status_t write_byte(uint8_t **out, const uint8_t *end, uint8_t byte) {
+  if (*out >= end) {
+    return OUT_OF_RANGE();
+  }
+  *(*out)++ = byte;
+  return OK_STATUS();
+}
+
+static status_t write_length(uint8_t **out, const uint8_t *end, size_t len) {
+  for (; len >= 255; len -= 255) {
+    TRY(write_byte(out, end, 255));
+  }
+  return write_byte(out, end, (uint8_t)len);
+}
+
+/**
+ * Emits one LZ4 sequence: `literal_len` literals followed by a match of
+ * `match_len` bytes at `offset` bytes back, or no match if `match_len` is zero.
+ */
+static status_t emit_sequence(uint8_t **out, const uint8_t *end,
+                              const uint8_t *literals, size_t literal_len,
+                              size_t offset, size_t match_len) {
+  size_t match_code = match_len == 0 ? 0 : match_len - kLz4MinMatch;
+  uint8_t token =
+      (uint8_t)((literal_len < kLz4TokenMax ? literal_len : kLz4TokenMax)
+                << 4) |
+      (uint8_t)(match_code < kLz4TokenMax ? match_code : kLz4TokenMax);
+  TRY(write_byte(out, end, token));
+  if (literal_len >= kLz4TokenMax) {
+    TRY(write_length(out, end, literal_len - kLz4TokenMax));
+  }
+  if ((size_t)(end - *out) < literal_len) {
+    return OUT_OF_RANGE();
+  }
+  memcpy(*out, literals, literal_len);
+  *out += literal_len;
+  if (match_len == 0) {
+    return OK_STATUS();
+  }
+  TRY(write_byte(out, end, (uint8_t)offset));
+  TRY(write_byte(out, end, (uint8_t)(offset >> 8)));
+  if (match_code >= kLz4TokenMax) {
+    TRY(write_length(out, end, match_code - kLz4TokenMax));
+  }
+  return OK_STATUS();
+}
+
+status_t perso_lz4_compress(const uint8_t *in, size_t in_len, uint8_t *out,
+                            size_t out_cap, size_t *out_len) {
+  if (in == NULL || out == NULL || out_len == NULL || in_len > UINT16_MAX) {
+    return INVALID_ARGUMENT();
+  }
+  memset(hash_table, 0, sizeof(hash_table));
+
+  uint8_t *op = out;
+  const uint8_t *end = out + out_cap;
+  size_t anchor = 0;
+  size_t pos = 0;
+  if (in_len > kLz4MatchSafeDistance) {
+    size_t match_limit = in_len - kLz4MatchSafeDistance;
+    while (pos < match_limit) {
+      uint32_t sequence = read_32(in + pos);
+      size_t hash = lz4_hash(sequence);
+      size_t candidate = hash_table[hash];
+      hash_table[hash] = (uint16_t)pos;
+      if (candidate >= pos || pos - candidate > kLz4MaxOffset ||
+          read_32(in + candidate) != sequence) {
+        ++pos;
+        continue;
+      }
+      size_t match_len = kLz4MinMatch;
+      while (pos + match_len < in_len - kLz4LastLiterals &&
+             in[candidate + match_len] == in[pos + match_len]) {
+        ++match_len;
+      }
+      TRY(emit_sequence(&op, end, in + anchor, pos - anchor, pos - candidate,
+                        match_len));
+      pos += match_len;
+      anchor = pos;
+    }
+  }
+  TRY(emit_sequence(&op, end, in + anchor, in_len - anchor, 0, 0));
+
+  *out_len = (size_t)(op - out);
+  return OK_STATUS();
+}
@@ -0,0 +1,50 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+#ifndef OPENTITAN_SW_DEVICE_SILICON_CREATOR_MANUF_BASE_PERSO_LZ4_H_
+#define OPENTITAN_SW_DEVICE_SILICON_CREATOR_MANUF_BASE_PERSO_LZ4_H_
+
+#include <stddef.h>
+#include <stdint.h>
+
+#include "sw/device/lib/base/status.h"
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+/**
+ * Compression schemes of personalization data exported off the device.
+ *
+ * The host advertises the schemes it supports as a bitmask of `1 << scheme` in
+ * `manuf_perso_export_options_t`.
+ */
+typedef enum perso_blob_compression {
+  kPersoBlobCompressionNone = 0,
+  kPersoBlobCompressionLz4 = 1,
+} perso_blob_compression_t;
+
+/**
+ * Worst case size of the LZ4 compressed form of `size` bytes.
+ */
+#define PERSO_LZ4_COMPRESS_BOUND(size) ((size) + (size) / 255 + 16)
+
+/**
+ * Compresses `in` into a single LZ4 block (without the LZ4 frame header).
+ *
+ * @param in Data to compress; at most 64KiB.
+ * @param in_len Size of `in` in bytes.
+ * @param[out] out Buffer receiving the compressed block.
+ * @param out_cap Size of `out` in bytes.
+ * @param[out] out_len Size of the compressed block in bytes.
+ * @return The result of the operation.
+ */
+status_t perso_lz4_compress(const uint8_t *in, size_t in_len, uint8_t *out,
+                            size_t out_cap, size_t *out_len);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif  // OPENTITAN_SW_DEVICE_SILICON_CREATOR_MANUF_BASE_PERSO_LZ4_H_
@@ -0,0 +1,170 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+#include "sw/device/silicon_creator/manuf/base/perso_lz4.h"
+
+#include <algorithm>
+#include <cstdint>
+#include <cstring>
+#include <vector>
+
+#include "gtest/gtest.h"
+
+namespace perso_lz4_unittest {
+namespace {
+
+/**
+ * Reads an LZ4 length extension at `*pos` of `in`, adding it to `*len`.
+ */
+bool ReadLength(const std::vector<uint8_t> &in, size_t *pos, size_t *len) {
+  uint8_t byte;
+  do {
+    if (*pos >= in.size()) {
+      return false;
+    }
+    byte = in[(*pos)++];
+    *len += byte;
+  } while (byte == 255);
+  return true;
+}
+
+/**
+ * Reference LZ4 block decoder, with the checks of `lz4_decompress` in
+ * sw/host/provisioning/ft_lib/src/perso_compression.rs. Also checks the end of
+ * block condition of the LZ4 block format: the last 5 bytes are literals.
+ */
+bool Decompress(const std::vector<uint8_t> &in, std::vector<uint8_t> *out) {
+  size_t pos = 0;
+  while (pos < in.size()) {
+    uint8_t token = in[pos++];
+    size_t literal_len = token >> 4;
+    if (literal_len == 15 && !ReadLength(in, &pos, &literal_len)) {
+      return false;
+    }
+    if (in.size() - pos < literal_len) {
+      return false;
+    }
+    out->insert(out->end(), in.begin() + pos, in.begin() + pos + literal_len);
+    pos += literal_len;
+    if (pos == in.size()) {
+      return literal_len >= std::min<size_t>(5, out->size());
+    }
+    if (in.size() - pos < 2) {
+      return false;
+    }
+    size_t offset = in[pos] | in[pos + 1] << 8;
+    pos += 2;
+    if (offset == 0 || offset > out->size()) {
+      return false;
+    }
+    size_t match_len = token & 0xf;
+    if (match_len == 15 && !ReadLength(in, &pos, &match_len)) {
+      return false;
+    }
+    match_len += 4;
+    // Matches may overlap the bytes they produce.
+    size_t start = out->size() - offset;
+    for (size_t i = 0; i < match_len; ++i) {
+      out->push_back((*out)[start + i]);
+    }
+  }
+  // A block ends with literals.
+  return false;
+}
+
+std::vector<uint8_t> Compress(const std::vector<uint8_t> &in) {
+  std::vector<uint8_t> out(PERSO_LZ4_COMPRESS_BOUND(in.size()));
+  size_t out_len = 0;
+  // `in.data()` may be null if `in` is empty.
+  static const uint8_t kEmpty[1] = {0};
+  EXPECT_TRUE(status_ok(perso_lz4_compress(in.empty() ? kEmpty : in.data(),
+                                           in.size(), out.data(), out.size(),
+                                           &out_len)));
+  out.resize(out_len);
+  return out;
+}
+
+void ExpectRoundtrip(const std::vector<uint8_t> &in) {
+  std::vector<uint8_t> compressed = Compress(in);
+  std::vector<uint8_t> decompressed;
+  ASSERT_TRUE(Decompress(compressed, &decompressed));
+  EXPECT_EQ(decompressed, in);
+}
+
+TEST(PersoLz4Test, Empty) {
+  EXPECT_EQ(Compress({}), std::vector<uint8_t>({0x00}));
+}
+
+TEST(PersoLz4Test, Literals) {
+  std::vector<uint8_t> in = {'a', 'b', 'c'};
+  EXPECT_EQ(Compress(in), std::vector<uint8_t>({0x30, 'a', 'b', 'c'}));
+  ExpectRoundtrip(in);
+}
+
+TEST(PersoLz4Test, KnownVector) {
+  // One literal, a 26 byte match one byte back, and the 5 last literals.
+  std::vector<uint8_t> in(32, 'a');
+  EXPECT_EQ(Compress(in), std::vector<uint8_t>({0x1f, 'a', 0x01, 0x00, 0x07,
+                                                0x50, 'a', 'a', 'a', 'a',
+                                                'a'}));
+}
+
+TEST(PersoLz4Test, Roundtrip) {
+  // Certificates are mostly unique bytes, with repeated names and extensions.
+  std::vector<uint8_t> in;
+  uint32_t state = 1;
+  for (size_t i = 0; i < 4096; ++i) {
+    state = state * 1103515245 + 12345;
+    in.push_back(i % 512 < 256 ? static_cast<uint8_t>(state >> 16)
+                               : in[i - 256]);
+  }
+  std::vector<uint8_t> compressed = Compress(in);
+  EXPECT_LT(compressed.size(), in.size());
+  ExpectRoundtrip(in);
+
+  // Long literal runs and matches, with length extensions.
+  std::vector<uint8_t> runs(1000, 0x5a);
+  for (size_t i = 0; i < 300; ++i) {
+    runs.push_back(static_cast<uint8_t>(i * 7));
+  }
+  ExpectRoundtrip(runs);
+
+  for (size_t len = 0; len < 32; ++len) {
+    ExpectRoundtrip(std::vector<uint8_t>(len, 'x'));
+  }
+}
+
+TEST(PersoLz4Test, OutputTooSmall) {
+  std::vector<uint8_t> in(64);
+  for (size_t i = 0; i < in.size(); ++i) {
+    in[i] = static_cast<uint8_t>(i);
+  }
+  std::vector<uint8_t> out(in.size());
+  size_t out_len = 0;
+  status_t status = perso_lz4_compress(in.data(), in.size(), out.data(),
+                                       out.size(), &out_len);
+  EXPECT_EQ(status_err(status), kOutOfRange);
+  out.resize(PERSO_LZ4_COMPRESS_BOUND(in.size()));
+  EXPECT_TRUE(status_ok(perso_lz4_compress(in.data(), in.size(), out.data(),
+                                           out.size(), &out_len)));
+  EXPECT_EQ(out_len, in.size() + 2);
+}
+
+TEST(PersoLz4Test, InvalidArguments) {
+  std::vector<uint8_t> in(UINT16_MAX + 1);
+  std::vector<uint8_t> out(PERSO_LZ4_COMPRESS_BOUND(in.size()));
+  size_t out_len = 0;
+  EXPECT_EQ(status_err(perso_lz4_compress(in.data(), in.size(), out.data(),
+                                          out.size(), &out_len)),
+            kInvalidArgument);
+  EXPECT_EQ(status_err(perso_lz4_compress(nullptr, 0, out.data(), out.size(),
+                                          &out_len)),
+            kInvalidArgument);
+  EXPECT_EQ(status_err(perso_lz4_compress(in.data(), 0, out.data(), out.size(),
+                                          nullptr)),
+            kInvalidArgument);
+}
+
+}  // namespace
+}  // namespace perso_lz4_unittest
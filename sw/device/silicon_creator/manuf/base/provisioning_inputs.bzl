@@ -19,11 +19,15 @@ EARLGREY_OTP_CFGS = {
 # A dictionary of SKU configurations that will be used to generate FT
 # personalization binaries that configure OTP and flash info pages as defined
 # in these bazel targets.
+#
+# A SKU whose key management policy mandates an RMA unlock token wrapping suite
+# sets "rma_token_wrapping" to it, e.g. "ecdh-p384-hkdf-sha384-aes-256-gcm", and
+# FT then refuses key bundles whose RMA wrap key is of another suite.
 EARLGREY_SKUS = {
     # OTP Config: Emulation; DICE Certs: X.509; Additional Certs: None
     "emulation": {
         "otp": "emulation",
-        "ca_config": "//sw/device/silicon_creator/manuf/keys/fake:ca_config.json",
+        "key_bundle": "//sw/device/silicon_creator/manuf/keys/fake:key_bundle.json",
         "ca_data": ["//sw/device/silicon_creator/manuf/keys/fake:ca_data"],
         "dice_libs": ["//sw/device/silicon_creator/lib/cert:dice"],
         "host_ext_libs": ["@provisioning_exts//:default_ft_ext_lib"],
@@ -37,7 +41,7 @@ EARLGREY_SKUS = {
     # OTP Config: Emulation; DICE Certs: CWT; Additional Certs: None
     "emulation_dice_cwt": {
         "otp": "emulation",
-        "ca_config": "//sw/device/silicon_creator/manuf/keys/fake:ca_config.json",
+        "key_bundle": "//sw/device/silicon_creator/manuf/keys/fake:key_bundle.json",
         "ca_data": ["//sw/device/silicon_creator/manuf/keys/fake:ca_data"],
         "dice_libs": ["//sw/device/silicon_creator/lib/cert:dice_cwt"],
         "host_ext_libs": ["@provisioning_exts//:default_ft_ext_lib"],
@@ -51,7 +55,7 @@ EARLGREY_SKUS = {
     # OTP Config: Emulation; DICE Certs: X.509; Additional Certs: TPM EK
     "emulation_tpm": {
         "otp": "emulation",
-        "ca_config": "//sw/device/silicon_creator/manuf/keys/fake:ca_config.json",
+        "key_bundle": "//sw/device/silicon_creator/manuf/keys/fake:key_bundle.json",
         "ca_data": ["//sw/device/silicon_creator/manuf/keys/fake:ca_data"],
         "dice_libs": ["//sw/device/silicon_creator/lib/cert:dice"],
         "host_ext_libs": ["@provisioning_exts//:default_ft_ext_lib"],
@@ -69,7 +73,7 @@ EARLGREY_SKUS = {
     # a more appropriate solution is found.
     # "sival": {
     #     "otp": "sival",
-    #     "ca_config": "//sw/device/silicon_creator/manuf/keys/fake:ca_config.json",
+    #     "key_bundle": "//sw/device/silicon_creator/manuf/keys/fake:key_bundle.json",
     #     "ca_data": ["//sw/device/silicon_creator/manuf/keys/fake:ca_data"],
     #     "dice_libs": ["//sw/device/silicon_creator/lib/cert:dice"],
     #     "host_ext_libs": ["@provisioning_exts//:default_ft_ext_lib"],
@@ -98,7 +102,10 @@ FT_PROVISIONING_INPUTS = _TEST_TOKENS + """
   --device-id="0x11111111_22222222_33333333_44444444_55555555_66666666_77777777_88888888"
   --target-mission-mode-lc-state="prod"
   --rma-unlock-token="0x01234567_89abcdef_01234567_89abcdef"
-  --token-encrypt-key-der-file="sw/device/silicon_creator/manuf/keys/fake/rma_unlock_enc_rsa3072.pub.der"
+  --key-bundle-signature="sw/device/silicon_creator/manuf/keys/fake/key_bundle.json.sig"
+  --key-bundle-key="sw/device/silicon_creator/manuf/keys/fake/key_bundle_signer.pub.der"
+  --raw-ca-key="dice=sw/device/silicon_creator/manuf/keys/fake/sk.pkcs8.der"
+  --raw-ca-key="ext=sw/device/silicon_creator/manuf/keys/fake/sk.pkcs8.der"
   --rom-ext-measurement="0x11111111_11111111_11111111_11111111_11111111_11111111_11111111_11111111"
   --owner-manifest-measurement="0x22222222_22222222_22222222_22222222_22222222_22222222_22222222_22222222"
   --owner-measurement="0x33333333_33333333_33333333_33333333_33333333_33333333_33333333_33333333"
@@ -6,6 +6,7 @@
 
 #include "sw/device/lib/arch/device.h"
 #include "sw/device/lib/base/abs_mmio.h"
+#include "sw/device/lib/base/macros.h"
 #include "sw/device/lib/crypto/drivers/entropy.h"
 #include "sw/device/lib/dif/dif_flash_ctrl.h"
 #include "sw/device/lib/dif/dif_otp_ctrl.h"
@@ -20,6 +21,7 @@
 #include "sw/device/lib/testing/test_framework/ottf_test_config.h"
 #include "sw/device/lib/testing/test_framework/ujson_ottf.h"
 #include "sw/device/silicon_creator/manuf/base/flash_info_permissions.h"
+#include "sw/device/silicon_creator/manuf/base/manuf_console.h"
 #include "sw/device/silicon_creator/manuf/lib/flash_info_fields.h"
 #include "sw/device/silicon_creator/manuf/lib/individualize.h"
 #include "sw/device/silicon_creator/manuf/lib/individualize_sw_cfg.h"
@@ -27,7 +29,7 @@
 
 #include "hw/top_earlgrey/sw/autogen/top_earlgrey.h"
 
-OTTF_DEFINE_TEST_CONFIG(.console.type = kOttfConsoleSpiDevice,
+OTTF_DEFINE_TEST_CONFIG(.console.type = MANUF_CONSOLE_TYPE,
                         .console.base_addr = TOP_EARLGREY_SPI_DEVICE_BASE_ADDR,
                         .console.test_may_clobber = false, );
 
@@ -35,7 +37,26 @@ static dif_flash_ctrl_state_t flash_ctrl_state;
 static dif_otp_ctrl_t otp_ctrl;
 static dif_pinmux_t pinmux;
 
+/**
+ * OTP partitions that can be selected for individualization, as bits of the
+ * `partitions` field of the FT individualize payload.
+ *
+ * Must be kept in sync with `IndividualizePartition` in
+ * sw/host/provisioning/ft_lib/src/lib.rs.
+ */
+typedef enum manuf_ft_individualize_partition {
+  kManufFtIndividualizeHwCfg = 1 << 0,
+  kManufFtIndividualizeCreatorSwCfg = 1 << 1,
+  kManufFtIndividualizeOwnerSwCfg = 1 << 2,
+  kManufFtIndividualizeRotCreatorAuthCodesign = 1 << 3,
+  kManufFtIndividualizeRotCreatorAuthState = 1 << 4,
+} manuf_ft_individualize_partition_t;
+
 static manuf_ft_individualize_data_t in_data;
+static manuf_owner_sw_cfg_alert_cfg_t alert_cfg;
+static_assert(ARRAYSIZE(in_data.ast_trim_mask) ==
+                  kFlashInfoAstCalibrationDataSizeIn32BitWords,
+              "AST trim payload size must match the AST calibration data size");
 static uint32_t cp_device_id[kFlashInfoFieldCpDeviceIdSizeIn32BitWords];
 static uint32_t ast_cfg_data[kFlashInfoAstCalibrationDataSizeIn32BitWords];
 
@@ -90,6 +111,23 @@ static void manually_init_ast(uint32_t *data) {
   }
 }
 
+/**
+ * Provision the OWNER_SW_CFG partition, with the alert handler configuration
+ * fields overridden by the host, and export the alert handler configuration
+ * read back from OTP for the host to verify.
+ */
+static status_t provision_owner_sw_cfg(ujson_t *uj) {
+  LOG_INFO("Waiting for OWNER_SW_CFG alert configuration ...");
+  TRY(ujson_deserialize_manuf_owner_sw_cfg_alert_cfg_t(uj, &alert_cfg));
+  TRY(manuf_individualize_device_owner_sw_cfg_alert_cfg_set(&alert_cfg));
+  TRY(manuf_individualize_device_owner_sw_cfg(&otp_ctrl));
+  TRY(manuf_individualize_device_owner_sw_cfg_alert_cfg_read(&otp_ctrl,
+                                                             &alert_cfg));
+  LOG_INFO("Exporting OWNER_SW_CFG alert configuration ...");
+  return RESP_OK(ujson_serialize_manuf_owner_sw_cfg_alert_cfg_t, uj,
+                 &alert_cfg);
+}
+
 /**
  * Provision OTP {CreatorSw,OwnerSw,Hw}Cfg and RotCreatorAuth{Codesign,State}
  * partitions.
@@ -100,13 +138,31 @@ static void manually_init_ast(uint32_t *data) {
 static status_t provision(ujson_t *uj) {
   LOG_INFO("Waiting for FT SRAM provisioning data ...");
   TRY(ujson_deserialize_manuf_ft_individualize_data_t(uj, &in_data));
-  TRY(manuf_individualize_device_hw_cfg(&flash_ctrl_state, &otp_ctrl,
-                                        kFlashInfoPage0Permissions,
-                                        in_data.device_id));
-  TRY(manuf_individualize_device_creator_sw_cfg(&otp_ctrl, &flash_ctrl_state));
-  TRY(manuf_individualize_device_owner_sw_cfg(&otp_ctrl));
-  TRY(manuf_individualize_device_rot_creator_auth_codesign(&otp_ctrl));
-  TRY(manuf_individualize_device_rot_creator_auth_state(&otp_ctrl));
+  if (in_data.partitions & kManufFtIndividualizeHwCfg) {
+    TRY(manuf_individualize_device_hw_cfg(&flash_ctrl_state, &otp_ctrl,
+                                          kFlashInfoPage0Permissions,
+                                          in_data.device_id));
+  }
+  if (in_data.partitions & kManufFtIndividualizeCreatorSwCfg) {
+    // Re-initialize the AST with the trimmed calibration values.
+    for (size_t i = 0; i < kFlashInfoAstCalibrationDataSizeIn32BitWords; ++i) {
+      ast_cfg_data[i] = (ast_cfg_data[i] & ~in_data.ast_trim_mask[i]) |
+                        (in_data.ast_trim_value[i] & in_data.ast_trim_mask[i]);
+    }
+    manually_init_ast(ast_cfg_data);
+    TRY(manuf_individualize_device_creator_sw_cfg_with_ast_trim(
+        &otp_ctrl, &flash_ctrl_state, in_data.ast_trim_mask,
+        in_data.ast_trim_value));
+  }
+  if (in_data.partitions & kManufFtIndividualizeOwnerSwCfg) {
+    TRY(provision_owner_sw_cfg(uj));
+  }
+  if (in_data.partitions & kManufFtIndividualizeRotCreatorAuthCodesign) {
+    TRY(manuf_individualize_device_rot_creator_auth_codesign(&otp_ctrl));
+  }
+  if (in_data.partitions & kManufFtIndividualizeRotCreatorAuthState) {
+    TRY(manuf_individualize_device_rot_creator_auth_state(&otp_ctrl));
+  }
   LOG_INFO("FT SRAM provisioning done.");
   return OK_STATUS();
 }
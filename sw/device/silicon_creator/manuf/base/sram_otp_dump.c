@@ -0,0 +1,157 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+#include <stdint.h>
+
+#include "sw/device/lib/base/macros.h"
+#include "sw/device/lib/base/memory.h"
+#include "sw/device/lib/dif/dif_otp_ctrl.h"
+#include "sw/device/lib/dif/dif_pinmux.h"
+#include "sw/device/lib/runtime/log.h"
+#include "sw/device/lib/testing/json/provisioning_data.h"
+#include "sw/device/lib/testing/otp_ctrl_testutils.h"
+#include "sw/device/lib/testing/pinmux_testutils.h"
+#include "sw/device/lib/testing/test_framework/check.h"
+#include "sw/device/lib/testing/test_framework/ottf_console.h"
+#include "sw/device/lib/testing/test_framework/ottf_test_config.h"
+#include "sw/device/lib/testing/test_framework/ujson_ottf.h"
+#include "sw/device/silicon_creator/manuf/base/manuf_console.h"
+
+#include "hw/top_earlgrey/sw/autogen/top_earlgrey.h"
+#include "otp_ctrl_regs.h"  // Generated.
+
+OTTF_DEFINE_TEST_CONFIG(.console.type = MANUF_CONSOLE_TYPE,
+                        .console.base_addr = TOP_EARLGREY_SPI_DEVICE_BASE_ADDR,
+                        .console.test_may_clobber = false, );
+
+static dif_otp_ctrl_t otp_ctrl;
+static dif_pinmux_t pinmux;
+
+static manuf_otp_dump_chunk_t chunk;
+
+typedef struct otp_dump_partition {
+  /**
+   * The partition to dump.
+   */
+  dif_otp_ctrl_partition_t partition;
+  /**
+   * The absolute OTP address at which this partition starts.
+   */
+  uint32_t start_addr;
+  /**
+   * Size (in bytes) of the partition, excluding the digest field.
+   */
+  size_t size;
+} otp_dump_partition_t;
+
+/**
+ * OTP partitions readable through the DAI, in the order they are dumped.
+ *
+ * The index of a partition in this table is reported in the `partition` field
+ * of each dump chunk, and must be kept in sync with `OtpDumpPartition` in
+ * sw/host/provisioning/ft_lib/src/otp_dump.rs.
+ */
+static const otp_dump_partition_t kDumpPartitions[] = {
+    {
+        .partition = kDifOtpCtrlPartitionVendorTest,
+        .start_addr = OTP_CTRL_PARAM_VENDOR_TEST_OFFSET,
+        .size = OTP_CTRL_PARAM_VENDOR_TEST_SIZE -
+                OTP_CTRL_PARAM_VENDOR_TEST_DIGEST_SIZE,
+    },
+    {
+        .partition = kDifOtpCtrlPartitionCreatorSwCfg,
+        .start_addr = OTP_CTRL_PARAM_CREATOR_SW_CFG_OFFSET,
+        .size = OTP_CTRL_PARAM_CREATOR_SW_CFG_SIZE -
+                OTP_CTRL_PARAM_CREATOR_SW_CFG_DIGEST_SIZE,
+    },
+    {
+        .partition = kDifOtpCtrlPartitionOwnerSwCfg,
+        .start_addr = OTP_CTRL_PARAM_OWNER_SW_CFG_OFFSET,
+        .size = OTP_CTRL_PARAM_OWNER_SW_CFG_SIZE -
+                OTP_CTRL_PARAM_OWNER_SW_CFG_DIGEST_SIZE,
+    },
+    {
+        .partition = kDifOtpCtrlPartitionRotCreatorAuthCodesign,
+        .start_addr = OTP_CTRL_PARAM_ROT_CREATOR_AUTH_CODESIGN_OFFSET,
+        .size = OTP_CTRL_PARAM_ROT_CREATOR_AUTH_CODESIGN_SIZE -
+                OTP_CTRL_PARAM_ROT_CREATOR_AUTH_CODESIGN_DIGEST_SIZE,
+    },
+    {
+        .partition = kDifOtpCtrlPartitionRotCreatorAuthState,
+        .start_addr = OTP_CTRL_PARAM_ROT_CREATOR_AUTH_STATE_OFFSET,
+        .size = OTP_CTRL_PARAM_ROT_CREATOR_AUTH_STATE_SIZE -
+                OTP_CTRL_PARAM_ROT_CREATOR_AUTH_STATE_DIGEST_SIZE,
+    },
+    {
+        .partition = kDifOtpCtrlPartitionHwCfg0,
+        .start_addr = OTP_CTRL_PARAM_HW_CFG0_OFFSET,
+        .size = OTP_CTRL_PARAM_HW_CFG0_SIZE - OTP_CTRL_PARAM_HW_CFG0_DIGEST_SIZE,
+    },
+    {
+        .partition = kDifOtpCtrlPartitionHwCfg1,
+        .start_addr = OTP_CTRL_PARAM_HW_CFG1_OFFSET,
+        .size = OTP_CTRL_PARAM_HW_CFG1_SIZE - OTP_CTRL_PARAM_HW_CFG1_DIGEST_SIZE,
+    },
+};
+
+/**
+ * Initializes all DIF handles used in this SRAM program.
+ */
+static status_t peripheral_handles_init(void) {
+  TRY(dif_otp_ctrl_init(
+      mmio_region_from_addr(TOP_EARLGREY_OTP_CTRL_CORE_BASE_ADDR), &otp_ctrl));
+  TRY(dif_pinmux_init(mmio_region_from_addr(TOP_EARLGREY_PINMUX_AON_BASE_ADDR),
+                      &pinmux));
+  return OK_STATUS();
+}
+
+/**
+ * Sends the contents of the partition at `index` in `kDumpPartitions` to the
+ * host, in chunks of `ARRAYSIZE(chunk.data)` words.
+ */
+static status_t dump_partition(ujson_t *uj, size_t index) {
+  const otp_dump_partition_t *partition = &kDumpPartitions[index];
+  size_t num_words = partition->size / sizeof(uint32_t);
+
+  // The digest reads as an error while the partition is not locked.
+  uint64_t digest = 0;
+  if (dif_otp_ctrl_get_digest(&otp_ctrl, partition->partition, &digest) !=
+      kDifOk) {
+    digest = 0;
+  }
+
+  for (size_t word = 0; word < num_words; word += ARRAYSIZE(chunk.data)) {
+    memset(&chunk, 0, sizeof(chunk));
+    chunk.partition = index;
+    chunk.address = partition->start_addr;
+    chunk.offset = word * sizeof(uint32_t);
+    chunk.num_words = num_words - word;
+    if (chunk.num_words > ARRAYSIZE(chunk.data)) {
+      chunk.num_words = ARRAYSIZE(chunk.data);
+    }
+    chunk.digest = digest;
+    chunk.last = index == ARRAYSIZE(kDumpPartitions) - 1 &&
+                 word + chunk.num_words == num_words;
+    TRY(otp_ctrl_testutils_dai_read32_array(&otp_ctrl, partition->partition,
+                                            chunk.offset, chunk.data,
+                                            chunk.num_words));
+    RESP_OK(ujson_serialize_manuf_otp_dump_chunk_t, uj, &chunk);
+  }
+  return OK_STATUS();
+}
+
+bool test_main(void) {
+  CHECK_STATUS_OK(peripheral_handles_init());
+  pinmux_testutils_init(&pinmux);
+  ottf_console_init();
+  ujson_t uj = ujson_ottf_console();
+
+  LOG_INFO("Dumping OTP partitions ...");
+  for (size_t i = 0; i < ARRAYSIZE(kDumpPartitions); ++i) {
+    CHECK_STATUS_OK(dump_partition(&uj, i));
+  }
+  LOG_INFO("OTP dump done.");
+
+  return true;
+}
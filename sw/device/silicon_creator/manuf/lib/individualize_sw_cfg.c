@@ -4,6 +4,8 @@
 
 #include "sw/device/silicon_creator/manuf/lib/individualize_sw_cfg.h"
 
+#include <stddef.h>
+
 #include "sw/device/lib/base/macros.h"
 #include "sw/device/lib/base/memory.h"
 #include "sw/device/lib/crypto/include/datatypes.h"
@@ -31,6 +33,168 @@ enum {
 static uint32_t
     flash_info_page_buf[FLASH_CTRL_PARAM_BYTES_PER_PAGE / sizeof(uint32_t)];
 
+/**
+ * CREATOR_SW_CFG_MANUF_STATE value set by the host, or zero to provision
+ * `kCreatorSwCfgManufStateValue`.
+ */
+static uint32_t creator_manuf_state_override;
+
+void manuf_individualize_device_creator_manuf_state_set(uint32_t value) {
+  creator_manuf_state_override = value;
+}
+
+uint32_t manuf_individualize_device_creator_manuf_state_get(void) {
+  return creator_manuf_state_override != 0 ? creator_manuf_state_override
+                                           : kCreatorSwCfgManufStateValue;
+}
+
+/**
+ * Alert handler configuration fields of the OWNER_SW_CFG partition set by the
+ * host. Only the fields selected by `fields` are provisioned from here.
+ */
+static manuf_owner_sw_cfg_alert_cfg_t alert_cfg_override;
+
+/**
+ * Location of an alert handler configuration OTP field in
+ * `manuf_owner_sw_cfg_alert_cfg_t`.
+ */
+typedef struct alert_cfg_field {
+  manuf_alert_cfg_field_t field;
+  uint32_t otp_offset;
+  size_t cfg_offset;
+  size_t num_words;
+} alert_cfg_field_t;
+
+static const alert_cfg_field_t kAlertCfgFields[] = {
+    {
+        .field = kManufAlertCfgFieldClassEn,
+        .otp_offset = OTP_CTRL_PARAM_OWNER_SW_CFG_ROM_ALERT_CLASS_EN_OFFSET,
+        .cfg_offset = offsetof(manuf_owner_sw_cfg_alert_cfg_t, class_en),
+        .num_words = 1,
+    },
+    {
+        .field = kManufAlertCfgFieldEscalation,
+        .otp_offset = OTP_CTRL_PARAM_OWNER_SW_CFG_ROM_ALERT_ESCALATION_OFFSET,
+        .cfg_offset = offsetof(manuf_owner_sw_cfg_alert_cfg_t, escalation),
+        .num_words = 1,
+    },
+    {
+        .field = kManufAlertCfgFieldAccumThresh,
+        .otp_offset = OTP_CTRL_PARAM_OWNER_SW_CFG_ROM_ALERT_ACCUM_THRESH_OFFSET,
+        .cfg_offset = offsetof(manuf_owner_sw_cfg_alert_cfg_t, accum_thresh),
+        .num_words = OTP_CTRL_PARAM_OWNER_SW_CFG_ROM_ALERT_ACCUM_THRESH_SIZE /
+                     sizeof(uint32_t),
+    },
+    {
+        .field = kManufAlertCfgFieldTimeoutCycles,
+        .otp_offset =
+            OTP_CTRL_PARAM_OWNER_SW_CFG_ROM_ALERT_TIMEOUT_CYCLES_OFFSET,
+        .cfg_offset = offsetof(manuf_owner_sw_cfg_alert_cfg_t, timeout_cycles),
+        .num_words = OTP_CTRL_PARAM_OWNER_SW_CFG_ROM_ALERT_TIMEOUT_CYCLES_SIZE /
+                     sizeof(uint32_t),
+    },
+    {
+        .field = kManufAlertCfgFieldPhaseCycles,
+        .otp_offset = OTP_CTRL_PARAM_OWNER_SW_CFG_ROM_ALERT_PHASE_CYCLES_OFFSET,
+        .cfg_offset = offsetof(manuf_owner_sw_cfg_alert_cfg_t, phase_cycles),
+        .num_words = OTP_CTRL_PARAM_OWNER_SW_CFG_ROM_ALERT_PHASE_CYCLES_SIZE /
+                     sizeof(uint32_t),
+    },
+    {
+        .field = kManufAlertCfgFieldDigest,
+        .otp_offset = OTP_CTRL_PARAM_OWNER_SW_CFG_ROM_ALERT_DIGEST_PROD_OFFSET,
+        .cfg_offset = offsetof(manuf_owner_sw_cfg_alert_cfg_t, digest),
+        .num_words = 1,
+    },
+    {
+        .field = kManufAlertCfgFieldDigest,
+        .otp_offset =
+            OTP_CTRL_PARAM_OWNER_SW_CFG_ROM_ALERT_DIGEST_PROD_END_OFFSET,
+        .cfg_offset = offsetof(manuf_owner_sw_cfg_alert_cfg_t, digest) +
+                      1 * sizeof(uint32_t),
+        .num_words = 1,
+    },
+    {
+        .field = kManufAlertCfgFieldDigest,
+        .otp_offset = OTP_CTRL_PARAM_OWNER_SW_CFG_ROM_ALERT_DIGEST_DEV_OFFSET,
+        .cfg_offset = offsetof(manuf_owner_sw_cfg_alert_cfg_t, digest) +
+                      2 * sizeof(uint32_t),
+        .num_words = 1,
+    },
+    {
+        .field = kManufAlertCfgFieldDigest,
+        .otp_offset = OTP_CTRL_PARAM_OWNER_SW_CFG_ROM_ALERT_DIGEST_RMA_OFFSET,
+        .cfg_offset = offsetof(manuf_owner_sw_cfg_alert_cfg_t, digest) +
+                      3 * sizeof(uint32_t),
+        .num_words = 1,
+    },
+};
+
+/**
+ * Returns whether the OTP field at `otp_offset` is overridden by the host.
+ */
+static bool alert_cfg_overridden(uint32_t otp_offset) {
+  for (size_t i = 0; i < ARRAYSIZE(kAlertCfgFields); ++i) {
+    if (kAlertCfgFields[i].otp_offset == otp_offset) {
+      return (alert_cfg_override.fields & kAlertCfgFields[i].field) != 0;
+    }
+  }
+  return false;
+}
+
+status_t manuf_individualize_device_owner_sw_cfg_alert_cfg_set(
+    const manuf_owner_sw_cfg_alert_cfg_t *cfg) {
+  if ((cfg->fields & ~(uint32_t)kManufAlertCfgFieldAll) != 0) {
+    return INVALID_ARGUMENT();
+  }
+  if (cfg->fields != 0 && (cfg->fields & kManufAlertCfgFieldDigest) == 0) {
+    return INVALID_ARGUMENT();
+  }
+  alert_cfg_override = *cfg;
+  return OK_STATUS();
+}
+
+status_t manuf_individualize_device_owner_sw_cfg_alert_cfg_read(
+    const dif_otp_ctrl_t *otp_ctrl, manuf_owner_sw_cfg_alert_cfg_t *cfg) {
+  memset(cfg, 0, sizeof(*cfg));
+  cfg->fields = alert_cfg_override.fields;
+  for (size_t i = 0; i < ARRAYSIZE(kAlertCfgFields); ++i) {
+    const alert_cfg_field_t *field = &kAlertCfgFields[i];
+    uint32_t relative_addr;
+    TRY(dif_otp_ctrl_relative_address(kDifOtpCtrlPartitionOwnerSwCfg,
+                                      field->otp_offset, &relative_addr));
+    TRY(otp_ctrl_testutils_dai_read32_array(
+        otp_ctrl, kDifOtpCtrlPartitionOwnerSwCfg, relative_addr,
+        (uint32_t *)((uint8_t *)cfg + field->cfg_offset), field->num_words));
+  }
+  return OK_STATUS();
+}
+
+/**
+ * Writes the alert handler configuration fields overridden by the host.
+ *
+ * @param otp OTP Controller instance.
+ * @return OK_STATUS if the overridden fields were written.
+ */
+OT_WARN_UNUSED_RESULT
+static status_t alert_cfg_override_write(const dif_otp_ctrl_t *otp) {
+  for (size_t i = 0; i < ARRAYSIZE(kAlertCfgFields); ++i) {
+    const alert_cfg_field_t *field = &kAlertCfgFields[i];
+    if ((alert_cfg_override.fields & field->field) == 0) {
+      continue;
+    }
+    uint32_t relative_addr;
+    TRY(dif_otp_ctrl_relative_address(kDifOtpCtrlPartitionOwnerSwCfg,
+                                      field->otp_offset, &relative_addr));
+    TRY(otp_ctrl_testutils_dai_write32(
+        otp, kDifOtpCtrlPartitionOwnerSwCfg, relative_addr,
+        (const uint32_t *)((const uint8_t *)&alert_cfg_override +
+                           field->cfg_offset),
+        field->num_words));
+  }
+  return OK_STATUS();
+}
+
 /**
  * Writes OTP values to target OTP `partition`.
  *
@@ -78,6 +242,9 @@ static status_t otp_img_write(const dif_otp_ctrl_t *otp,
     // Additionally, we skip the provisioning of the AST configuration data, as
     // this should already be written to a flash info page. We will pull the
     // data directly from there.
+    //
+    // Finally, we skip the alert handler configuration fields overridden by
+    // the host, which are provisioned by `alert_cfg_override_write()`.
     if (kv[i].offset ==
             OTP_CTRL_PARAM_CREATOR_SW_CFG_FLASH_DATA_DEFAULT_CFG_OFFSET ||
         kv[i].offset == OTP_CTRL_PARAM_CREATOR_SW_CFG_MANUF_STATE_OFFSET ||
@@ -85,7 +252,9 @@ static status_t otp_img_write(const dif_otp_ctrl_t *otp,
             OTP_CTRL_PARAM_CREATOR_SW_CFG_IMMUTABLE_ROM_EXT_EN_OFFSET ||
         kv[i].offset == OTP_CTRL_PARAM_OWNER_SW_CFG_ROM_BOOTSTRAP_DIS_OFFSET ||
         (kv[i].offset >= kValidAstCfgOtpAddrLow &&
-         kv[i].offset < kInvalidAstCfgOtpAddrHigh)) {
+         kv[i].offset < kInvalidAstCfgOtpAddrHigh) ||
+        (partition == kDifOtpCtrlPartitionOwnerSwCfg &&
+         alert_cfg_overridden(kv[i].offset))) {
       continue;
     }
     uint32_t offset;
@@ -128,10 +297,12 @@ static status_t otp_img_expected_value_read(dif_otp_ctrl_partition_t partition,
       memcpy(buffer + relative_addr, &kOwnerSwCfgRomBootstrapDisValue,
              sizeof(uint32_t));
       break;
-    case OTP_CTRL_PARAM_CREATOR_SW_CFG_MANUF_STATE_OFFSET:
-      memcpy(buffer + relative_addr, &kCreatorSwCfgManufStateValue,
-             sizeof(uint32_t));
+    case OTP_CTRL_PARAM_CREATOR_SW_CFG_MANUF_STATE_OFFSET: {
+      uint32_t manuf_state =
+          manuf_individualize_device_creator_manuf_state_get();
+      memcpy(buffer + relative_addr, &manuf_state, sizeof(uint32_t));
       break;
+    }
     case OTP_CTRL_PARAM_CREATOR_SW_CFG_IMMUTABLE_ROM_EXT_EN_OFFSET:
       memcpy(buffer + relative_addr, &kCreatorSwCfgImmutableRomExtEnValue,
              sizeof(uint32_t));
@@ -179,7 +350,8 @@ static status_t lock_otp_partition(const dif_otp_ctrl_t *otp_ctrl,
 }
 
 static status_t manuf_individualize_device_ast_cfg(
-    const dif_otp_ctrl_t *otp_ctrl, dif_flash_ctrl_state_t *flash_state) {
+    const dif_otp_ctrl_t *otp_ctrl, dif_flash_ctrl_state_t *flash_state,
+    const uint32_t *ast_trim_mask, const uint32_t *ast_trim_value) {
   // Clear flash info page buffer.
   memset(flash_info_page_buf, UINT8_MAX, FLASH_CTRL_PARAM_BYTES_PER_PAGE);
 
@@ -212,6 +384,10 @@ static status_t manuf_individualize_device_ast_cfg(
     uint32_t addr =
         OTP_CTRL_PARAM_CREATOR_SW_CFG_AST_CFG_OFFSET + i * sizeof(uint32_t);
     uint32_t data = flash_info_page_buf[ast_cfg_offset + i];
+    if (ast_trim_mask != NULL) {
+      data =
+          (data & ~ast_trim_mask[i]) | (ast_trim_value[i] & ast_trim_mask[i]);
+    }
     uint32_t relative_addr;
     // Check the range is valid.
     if (addr < kValidAstCfgOtpAddrLow || addr >= kInvalidAstCfgOtpAddrHigh) {
@@ -243,9 +419,21 @@ static status_t manuf_individualize_device_ast_cfg(
 
 status_t manuf_individualize_device_creator_sw_cfg(
     const dif_otp_ctrl_t *otp_ctrl, dif_flash_ctrl_state_t *flash_state) {
+  return manuf_individualize_device_creator_sw_cfg_with_ast_trim(
+      otp_ctrl, flash_state, /*ast_trim_mask=*/NULL,
+      /*ast_trim_value=*/NULL);
+}
+
+status_t manuf_individualize_device_creator_sw_cfg_with_ast_trim(
+    const dif_otp_ctrl_t *otp_ctrl, dif_flash_ctrl_state_t *flash_state,
+    const uint32_t *ast_trim_mask, const uint32_t *ast_trim_value) {
+  if ((ast_trim_mask == NULL) != (ast_trim_value == NULL)) {
+    return INVALID_ARGUMENT();
+  }
   TRY(otp_img_write(otp_ctrl, kDifOtpCtrlPartitionCreatorSwCfg,
                     kOtpKvCreatorSwCfg, kOtpKvCreatorSwCfgSize));
-  TRY(manuf_individualize_device_ast_cfg(otp_ctrl, flash_state));
+  TRY(manuf_individualize_device_ast_cfg(otp_ctrl, flash_state, ast_trim_mask,
+                                         ast_trim_value));
   return OK_STATUS();
 }
 
@@ -253,6 +441,7 @@ status_t manuf_individualize_device_field_cfg(const dif_otp_ctrl_t *otp_ctrl,
                                               uint32_t field_offset) {
   uint32_t relative_addr;
   const uint32_t *field_value_addr;
+  uint32_t manuf_state;
   dif_otp_ctrl_partition_t partition;
   switch (field_offset) {
     case OTP_CTRL_PARAM_OWNER_SW_CFG_ROM_BOOTSTRAP_DIS_OFFSET:
@@ -268,7 +457,8 @@ status_t manuf_individualize_device_field_cfg(const dif_otp_ctrl_t *otp_ctrl,
       partition = kDifOtpCtrlPartitionCreatorSwCfg;
       break;
     case OTP_CTRL_PARAM_CREATOR_SW_CFG_MANUF_STATE_OFFSET:
-      field_value_addr = &kCreatorSwCfgManufStateValue;
+      manuf_state = manuf_individualize_device_creator_manuf_state_get();
+      field_value_addr = &manuf_state;
       partition = kDifOtpCtrlPartitionCreatorSwCfg;
       break;
     default:
@@ -313,6 +503,7 @@ status_t manuf_individualize_device_owner_sw_cfg(
     const dif_otp_ctrl_t *otp_ctrl) {
   TRY(otp_img_write(otp_ctrl, kDifOtpCtrlPartitionOwnerSwCfg, kOtpKvOwnerSwCfg,
                     kOtpKvOwnerSwCfgSize));
+  TRY(alert_cfg_override_write(otp_ctrl));
   return OK_STATUS();
 }
 
@@ -8,6 +8,7 @@
 #include "sw/device/lib/base/status.h"
 #include "sw/device/lib/dif/dif_flash_ctrl.h"
 #include "sw/device/lib/dif/dif_otp_ctrl.h"
+#include "sw/device/lib/testing/json/provisioning_data.h"
 #include "sw/device/silicon_creator/manuf/lib/otp_img_types.h"
 
 /**
@@ -68,6 +69,27 @@ OT_WARN_UNUSED_RESULT
 status_t manuf_individualize_device_creator_sw_cfg(
     const dif_otp_ctrl_t *otp_ctrl, dif_flash_ctrl_state_t *flash_state);
 
+/**
+ * Same as `manuf_individualize_device_creator_sw_cfg()`, but overrides bits of
+ * the AST configuration data stored in flash info page 0 before it is written
+ * to OTP.
+ *
+ * Each AST configuration word written to OTP is computed as:
+ * `(flash & ~ast_trim_mask[i]) | (ast_trim_value[i] & ast_trim_mask[i])`.
+ *
+ * @param otp_ctrl OTP controller instance.
+ * @param flash_state Flash controller instance.
+ * @param ast_trim_mask Bits of each AST configuration word to override, or NULL
+ * to use the flash values as is. Must hold
+ * `kFlashInfoAstCalibrationDataSizeIn32BitWords` words.
+ * @param ast_trim_value Override values, or NULL if `ast_trim_mask` is NULL.
+ * @return OK_STATUS if the CREATOR_SW_CFG partition was provisioned.
+ */
+OT_WARN_UNUSED_RESULT
+status_t manuf_individualize_device_creator_sw_cfg_with_ast_trim(
+    const dif_otp_ctrl_t *otp_ctrl, dif_flash_ctrl_state_t *flash_state,
+    const uint32_t *ast_trim_mask, const uint32_t *ast_trim_value);
+
 /**
  * This must be called before both
  * `manuf_individualize_device_creator_sw_cfg_lock()` and
@@ -80,6 +102,28 @@ OT_WARN_UNUSED_RESULT
 status_t manuf_individualize_device_field_cfg(const dif_otp_ctrl_t *otp_ctrl,
                                               uint32_t field_offset);
 
+/**
+ * Overrides the CREATOR_SW_CFG_MANUF_STATE value provisioned by
+ * `manuf_individualize_device_field_cfg()`, and used to compute the expected
+ * CREATOR_SW_CFG partition contents.
+ *
+ * This must be called before either of the above operations.
+ *
+ * @param value Manufacturing state to provision, or zero to provision the
+ * `kCreatorSwCfgManufStateValue` of the OTP image.
+ */
+void manuf_individualize_device_creator_manuf_state_set(uint32_t value);
+
+/**
+ * Returns the CREATOR_SW_CFG_MANUF_STATE value to provision.
+ *
+ * @return The value set with
+ * `manuf_individualize_device_creator_manuf_state_set()`, or
+ * `kCreatorSwCfgManufStateValue` if none was set.
+ */
+OT_WARN_UNUSED_RESULT
+uint32_t manuf_individualize_device_creator_manuf_state_get(void);
+
 /**
  * Checks the FLASH_DATA_DEFAULT_CFG field in the CREATOR_SW_CFG OTP
  * partition.
@@ -136,6 +180,57 @@ OT_WARN_UNUSED_RESULT
 status_t manuf_individualize_device_owner_sw_cfg(
     const dif_otp_ctrl_t *otp_ctrl);
 
+/**
+ * Alert handler configuration fields of the OWNER_SW_CFG partition the host may
+ * override, as bits of `manuf_owner_sw_cfg_alert_cfg_t.fields`.
+ *
+ * Only these fields may differ from the SKU OTP image. The ROM checks the alert
+ * handler configuration against the ROM_ALERT_DIGEST_* fields, so the digests
+ * must be overridden along with any other field.
+ *
+ * Must be kept in sync with `AlertCfgField` in
+ * sw/host/provisioning/ft_lib/src/alert_cfg.rs.
+ */
+typedef enum manuf_alert_cfg_field {
+  kManufAlertCfgFieldClassEn = 1 << 0,
+  kManufAlertCfgFieldEscalation = 1 << 1,
+  kManufAlertCfgFieldAccumThresh = 1 << 2,
+  kManufAlertCfgFieldTimeoutCycles = 1 << 3,
+  kManufAlertCfgFieldPhaseCycles = 1 << 4,
+  kManufAlertCfgFieldDigest = 1 << 5,
+  kManufAlertCfgFieldAll = (1 << 6) - 1,
+} manuf_alert_cfg_field_t;
+
+/**
+ * Overrides the alert handler configuration fields of the OWNER_SW_CFG
+ * partition provisioned by `manuf_individualize_device_owner_sw_cfg()`.
+ *
+ * This must be called before `manuf_individualize_device_owner_sw_cfg()`.
+ *
+ * @param cfg Fields to provision instead of the values of the OTP image, see
+ * `manuf_alert_cfg_field_t`. Fields not selected by `cfg->fields` are ignored.
+ * @return OK_STATUS if the override was accepted, INVALID_ARGUMENT if
+ * `cfg->fields` selects unknown fields, or alert handler fields without the
+ * digests.
+ */
+OT_WARN_UNUSED_RESULT
+status_t manuf_individualize_device_owner_sw_cfg_alert_cfg_set(
+    const manuf_owner_sw_cfg_alert_cfg_t *cfg);
+
+/**
+ * Reads back the alert handler configuration fields of the OWNER_SW_CFG
+ * partition.
+ *
+ * @param otp_ctrl OTP controller instance.
+ * @param[out] cfg All alert handler configuration fields, with `fields` set to
+ * the fields overridden with
+ * `manuf_individualize_device_owner_sw_cfg_alert_cfg_set()`.
+ * @return OK_STATUS if the fields were read.
+ */
+OT_WARN_UNUSED_RESULT
+status_t manuf_individualize_device_owner_sw_cfg_alert_cfg_read(
+    const dif_otp_ctrl_t *otp_ctrl, manuf_owner_sw_cfg_alert_cfg_t *cfg);
+
 /**
  * Locks the OWNER_SW_CFG OTP partition.
  *
@@ -18,7 +18,7 @@ use crate::io::ioexpander::IoExpander;
 use crate::io::jtag::{JtagChain, JtagParams};
 use crate::io::nonblocking_help::NonblockingHelp;
 use crate::io::spi::{Target, TransferMode};
-use crate::io::uart::Uart;
+use crate::io::uart::{Uart, UART_QUIET_TIME};
 use crate::transport::{
     ioexpander, Capability, MaintainConnection, ProgressIndicator, ProxyOps, Transport,
     TransportError, TransportInterfaceType,
@@ -1095,8 +1095,8 @@ impl TransportWrapper {
         self.pin_strapping("RESET")?.apply()?;
         std::thread::sleep(reset_delay);
         if clear_uart_rx {
-            log::info!("Clearing the UART RX buffer");
-            self.uart("console")?.clear_rx_buffer()?;
+            log::info!("Draining the UART RX buffer");
+            self.uart("console")?.drain_until_quiet(UART_QUIET_TIME)?;
         }
         log::info!("Deasserting the reset signal");
         self.pin_strapping("RESET")?.remove()?;
@@ -22,6 +22,7 @@ mod chip_whisperer;
 mod ftdi;
 mod hyperdebug;
 mod proxy;
+mod qemu;
 mod ti50emulator;
 mod verilator;
 
@@ -60,6 +61,9 @@ pub struct BackendOpts {
     #[command(flatten)]
     pub ti50emulator_opts: ti50emulator::Ti50EmulatorOpts,
 
+    #[command(flatten)]
+    pub qemu_opts: qemu::QemuOpts,
+
     /// Configuration files.
     #[arg(long, num_args = 1)]
     pub conf: Vec<PathBuf>,
@@ -93,6 +97,10 @@ pub fn create(args: &BackendOpts) -> Result<TransportWrapper> {
             verilator::create(&args.verilator_opts)?,
             Some(Path::new("/__builtin__/opentitan_verilator.json")),
         ),
+        "qemu" => (
+            qemu::create(&args.qemu_opts)?,
+            Some(Path::new("/__builtin__/qemu.json")),
+        ),
         "ti50emulator" => (
             ti50emulator::create(&args.ti50emulator_opts)?,
             Some(Path::new("/__builtin__/ti50emulator.json")),
@@ -0,0 +1,47 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::Result;
+use clap::Args;
+use humantime::parse_duration;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::transport::qemu::subprocess::Options;
+use crate::transport::qemu::Qemu;
+use crate::transport::Transport;
+
+#[derive(Debug, Args)]
+pub struct QemuOpts {
+    #[arg(long, default_value = "qemu-system-riscv32")]
+    qemu_bin: String,
+
+    /// Machine specific QEMU arguments, e.g. the machine and its ROM, flash and OTP images.
+    #[arg(long, required = false, allow_hyphen_values = true)]
+    qemu_args: Vec<String>,
+
+    /// Fake OTP state (JSON) backing the host model of the LC TAP, updated by LC transitions.
+    /// For unit-level tests only: the firmware does not see the transitions.
+    #[arg(long)]
+    qemu_otp_state: Option<PathBuf>,
+
+    /// TCP port of the QEMU JTAG remote bitbang server.
+    #[arg(long, default_value_t = 3335)]
+    qemu_jtag_port: u16,
+
+    /// QEMU startup timeout.
+    #[arg(long, value_parser = parse_duration, default_value = "10s")]
+    qemu_timeout: Duration,
+}
+
+pub fn create(args: &QemuOpts) -> Result<Box<dyn Transport>> {
+    let options = Options {
+        executable: args.qemu_bin.clone(),
+        extra_args: args.qemu_args.clone(),
+        otp_state: args.qemu_otp_state.clone(),
+        jtag_port: args.qemu_jtag_port,
+        timeout: args.qemu_timeout,
+    };
+    Ok(Box::new(Qemu::from_options(options)?))
+}
@@ -5,7 +5,9 @@
 use anyhow::Result;
 
 use crate::app::TransportWrapper;
+use crate::bootstrap::resume::PageLedger;
 use crate::bootstrap::{Bootstrap, UpdateProtocol};
+use crate::io::spi::Target;
 use crate::spiflash::SpiFlash;
 use crate::transport::{Capability, ProgressIndicator};
 
@@ -46,9 +48,62 @@ impl UpdateProtocol for Eeprom {
     ) -> Result<()> {
         let spi = container.spi_params.create(transport, "BOOTSTRAP")?;
         let flash = SpiFlash::from_spi(&*spi)?;
-        flash.chip_erase(&*spi)?;
-        flash.program_with_progress(&*spi, 0, payload, progress)?;
+        let mut ledger = PageLedger::new(flash.program_size, Self::sector_size(&flash));
+        let mut erased = false;
+        let mut resumes = 0;
+        while let Err(e) =
+            Self::transfer(&flash, &*spi, payload, &mut ledger, &mut erased, progress)
+        {
+            if resumes >= container.resume_retries {
+                return Err(e);
+            }
+            resumes += 1;
+            log::warn!(
+                "Bootstrap transfer interrupted after {} sent pages: {e:#}; resuming from \
+                 {:#x} (attempt {resumes}/{})",
+                ledger.len(),
+                ledger.resume_offset(payload),
+                container.resume_retries
+            );
+        }
         SpiFlash::chip_reset(&*spi)?;
         Ok(())
     }
 }
+
+impl Eeprom {
+    /// Returns the size of the smallest erase of `flash`, as used by `SpiFlash::erase`.
+    fn sector_size(flash: &SpiFlash) -> u32 {
+        flash.erase.last().map_or(4096, |e| e.size)
+    }
+
+    /// Transfers the pages of `payload` not sent in `ledger` yet.
+    ///
+    /// The flash is only erased as a whole on the first attempt; a resumed transfer erases the
+    /// sector it resumes from, which may hold a partially programmed page.
+    fn transfer(
+        flash: &SpiFlash,
+        spi: &dyn Target,
+        payload: &[u8],
+        ledger: &mut PageLedger,
+        erased: &mut bool,
+        progress: &dyn ProgressIndicator,
+    ) -> Result<()> {
+        let offset = ledger.resume_offset(payload);
+        if !*erased {
+            flash.chip_erase(spi)?;
+            *erased = true;
+        } else if (offset as usize) < payload.len() {
+            ledger.forget_from(offset);
+            flash.erase(spi, offset, Self::sector_size(flash))?;
+        }
+        progress.new_stage("", payload.len());
+        progress.progress(offset as usize);
+        for (address, page) in ledger.pages(payload, offset).collect::<Vec<_>>() {
+            flash.program(spi, address, page)?;
+            ledger.record(address, page);
+            progress.progress(address as usize + page.len());
+        }
+        Ok(())
+    }
+}
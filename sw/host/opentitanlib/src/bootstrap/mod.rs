@@ -2,7 +2,7 @@
 // Licensed under the Apache License, Version 2.0, see LICENSE for details.
 // SPDX-License-Identifier: Apache-2.0
 
-use anyhow::Result;
+use anyhow::{ensure, Result};
 use clap::{Args, ValueEnum};
 use humantime::parse_duration;
 use serde::{Deserialize, Serialize};
@@ -21,6 +21,7 @@ mod eeprom;
 mod legacy;
 mod legacy_rescue;
 mod primitive;
+mod resume;
 
 pub use legacy::LegacyBootstrapError;
 pub use legacy_rescue::LegacyRescueError;
@@ -82,6 +83,11 @@ pub struct BootstrapOptions {
     /// Bootstrap protocol to use.
     #[arg(short, long, value_enum, ignore_case = true, default_value = "eeprom")]
     pub protocol: BootstrapProtocol,
+    /// Comma-separated list of bootstrap protocols to fall back to, in order, if the primary
+    /// protocol fails (e.g. `legacy-rescue` if the SPI bootstrap strap is not detected).
+    #[arg(long, value_enum, ignore_case = true, value_delimiter = ',')]
+    #[serde(default)]
+    pub fallback_protocol: Vec<BootstrapProtocol>,
     /// Whether to reset target and clear UART RX buffer after bootstrap. For Chip Whisperer board only.
     #[arg(long)]
     pub clear_uart: Option<bool>,
@@ -101,6 +107,24 @@ pub struct BootstrapOptions {
     /// Duration of the flash-erase delay.
     #[arg(long, value_parser = parse_duration)]
     pub flash_erase_delay: Option<Duration>,
+    /// Number of times to resume an interrupted `eeprom` protocol transfer from its last sent page,
+    /// instead of failing the bootstrap. The device must remain in bootstrap mode.
+    #[arg(long, default_value_t = 0)]
+    #[serde(default)]
+    pub resume_retries: u32,
+}
+
+impl BootstrapOptions {
+    /// Returns the protocols to attempt, in order: the primary protocol, then the fallbacks.
+    pub fn protocols(&self) -> Vec<BootstrapProtocol> {
+        let mut protocols = vec![self.protocol];
+        for protocol in &self.fallback_protocol {
+            if !protocols.contains(protocol) {
+                protocols.push(*protocol);
+            }
+        }
+        protocols
+    }
 }
 
 /// Bootstrap wraps and drives the various bootstrap protocols.
@@ -113,6 +137,7 @@ pub struct Bootstrap<'a> {
     reset_delay: Duration,
     leave_in_reset: bool,
     leave_in_bootstrap: bool,
+    resume_retries: u32,
 }
 
 impl<'a> Bootstrap<'a> {
@@ -147,27 +172,70 @@ impl<'a> Bootstrap<'a> {
             transport.proxy_ops()?.bootstrap(options, payload)?;
             return Ok(());
         }
-        let updater: Box<dyn UpdateProtocol> = match options.protocol {
-            BootstrapProtocol::Primitive => Box::new(primitive::Primitive::new(options)),
-            BootstrapProtocol::Legacy => Box::new(legacy::Legacy::new(options)),
-            BootstrapProtocol::LegacyRescue => Box::new(legacy_rescue::LegacyRescue::new(options)),
-            BootstrapProtocol::Eeprom => Box::new(eeprom::Eeprom::new()),
-            BootstrapProtocol::Emulator => {
-                // Not intended to be implemented by this struct.
-                unimplemented!();
+        let protocols = options.protocols();
+        ensure!(
+            options.fallback_protocol.is_empty()
+                || !protocols.contains(&BootstrapProtocol::Emulator),
+            "The emulator bootstrap protocol cannot be combined with fallback protocols"
+        );
+        let mut failures = Vec::new();
+        for (i, &protocol) in protocols.iter().enumerate() {
+            let updater: Box<dyn UpdateProtocol> = match protocol {
+                BootstrapProtocol::Primitive => Box::new(primitive::Primitive::new(options)),
+                BootstrapProtocol::Legacy => Box::new(legacy::Legacy::new(options)),
+                BootstrapProtocol::LegacyRescue => {
+                    Box::new(legacy_rescue::LegacyRescue::new(options))
+                }
+                BootstrapProtocol::Eeprom => Box::new(eeprom::Eeprom::new()),
+                BootstrapProtocol::Emulator => {
+                    // Not intended to be implemented by this struct.
+                    unimplemented!();
+                }
+            };
+            let result = Bootstrap {
+                protocol,
+                clear_uart_rx: options.clear_uart.unwrap_or(false),
+                uart_params: &options.uart_params,
+                spi_params: &options.spi_params,
+                reset_pin: transport.gpio_pin("RESET")?,
+                reset_delay: options.reset_delay,
+                leave_in_reset: options.leave_in_reset,
+                leave_in_bootstrap: options.leave_in_bootstrap,
+                resume_retries: options.resume_retries,
+            }
+            .do_update(updater, transport, payload, progress);
+            let err = match result {
+                Ok(()) => {
+                    if i > 0 {
+                        log::warn!(
+                            "Bootstrap succeeded with fallback protocol {protocol:?}, after: {}",
+                            failures.join("; ")
+                        );
+                    } else if protocols.len() > 1 {
+                        log::info!("Bootstrap succeeded with primary protocol {protocol:?}.");
+                    }
+                    return Ok(());
+                }
+                Err(e) => e,
+            };
+            if protocols.len() == 1 {
+                // No fallback configured, keep the error as is.
+                return Err(err);
+            }
+            failures.push(format!("{protocol:?} failed: {err:#}"));
+            match protocols.get(i + 1) {
+                Some(next) => log::warn!(
+                    "Bootstrap with protocol {protocol:?} failed: {err:#}; falling back to {next:?}."
+                ),
+                None => {
+                    return Err(err.context(format!(
+                        "All bootstrap protocols failed: {}",
+                        failures.join("; ")
+                    )))
+                }
             }
-        };
-        Bootstrap {
-            protocol: options.protocol,
-            clear_uart_rx: options.clear_uart.unwrap_or(false),
-            uart_params: &options.uart_params,
-            spi_params: &options.spi_params,
-            reset_pin: transport.gpio_pin("RESET")?,
-            reset_delay: options.reset_delay,
-            leave_in_reset: options.leave_in_reset,
-            leave_in_bootstrap: options.leave_in_bootstrap,
         }
-        .do_update(updater, transport, payload, progress)
+        unreachable!()
     }
 
     fn do_update(
@@ -209,3 +277,41 @@ impl<'a> Bootstrap<'a> {
         result
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::Parser;
+
+    #[derive(Parser)]
+    struct Opts {
+        #[command(flatten)]
+        bootstrap: BootstrapOptions,
+    }
+
+    #[test]
+    fn test_protocols_order() {
+        let opts = Opts::parse_from([
+            "test",
+            "--protocol=eeprom",
+            "--fallback-protocol=legacy-rescue,eeprom,primitive",
+        ]);
+        assert_eq!(
+            opts.bootstrap.protocols(),
+            vec![
+                BootstrapProtocol::Eeprom,
+                BootstrapProtocol::LegacyRescue,
+                BootstrapProtocol::Primitive,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_no_fallback() {
+        let opts = Opts::parse_from(["test", "--protocol=primitive"]);
+        assert_eq!(
+            opts.bootstrap.protocols(),
+            vec![BootstrapProtocol::Primitive]
+        );
+    }
+}
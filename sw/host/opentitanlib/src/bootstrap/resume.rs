@@ -0,0 +1,120 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::BTreeMap;
+
+use crate::crypto::sha256::{sha256, Sha256Digest};
+
+/// Host-side record of the flash pages a bootstrap transfer has sent (i.e. the device reported the
+/// page program complete), along with the SHA256 digests of the contents the host sent.
+///
+/// The ROM bootstrap protocol has no read command, so the device's flash is never read back: a
+/// sent page is only known to be programmed, with the expected contents or not. The contents are
+/// checked when the ROM verifies the signature of the image on the next boot.
+///
+/// An interrupted transfer can resume from the last sent page rather than restarting the transfer
+/// of the whole payload, as long as the device stayed in bootstrap mode.
+pub struct PageLedger {
+    page_size: u32,
+    sector_size: u32,
+    pages: BTreeMap<u32, Sha256Digest>,
+}
+
+impl PageLedger {
+    /// Creates an empty ledger for a flash with `page_size` program pages and `sector_size`
+    /// erase sectors.
+    pub fn new(page_size: u32, sector_size: u32) -> Self {
+        PageLedger {
+            page_size,
+            sector_size,
+            pages: BTreeMap::new(),
+        }
+    }
+
+    /// Splits `payload` into `(address, page)` chunks, starting at `offset`.
+    pub fn pages<'a>(
+        &self,
+        payload: &'a [u8],
+        offset: u32,
+    ) -> impl Iterator<Item = (u32, &'a [u8])> + 'a {
+        let page_size = self.page_size;
+        payload[offset as usize..]
+            .chunks(page_size as usize)
+            .enumerate()
+            .map(move |(i, page)| (offset + i as u32 * page_size, page))
+    }
+
+    /// Records the page at `address` as sent with `data`.
+    pub fn record(&mut self, address: u32, data: &[u8]) {
+        self.pages.insert(address, sha256(data));
+    }
+
+    /// Returns the offset to resume the transfer of `payload` from: the start of the erase sector
+    /// of the first page not sent with its contents, or the length of `payload` if all the pages
+    /// are sent.
+    pub fn resume_offset(&self, payload: &[u8]) -> u32 {
+        for (address, page) in self.pages(payload, 0) {
+            if self.pages.get(&address) != Some(&sha256(page)) {
+                return address - address % self.sector_size;
+            }
+        }
+        payload.len() as u32
+    }
+
+    /// Forgets the pages from `address` on, e.g. once their sectors are erased.
+    pub fn forget_from(&mut self, address: u32) {
+        self.pages.split_off(&address);
+    }
+
+    /// Returns the number of sent pages.
+    pub fn len(&self) -> usize {
+        self.pages.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn payload(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7) as u8).collect()
+    }
+
+    #[test]
+    fn test_resume_offset() {
+        let payload = payload(5000);
+        let mut ledger = PageLedger::new(256, 1024);
+        assert_eq!(ledger.resume_offset(&payload), 0);
+
+        // Pages 0..=6 sent: resume from the start of the sector of page 7.
+        for (address, page) in ledger.pages(&payload, 0).take(7).collect::<Vec<_>>() {
+            ledger.record(address, page);
+        }
+        assert_eq!(ledger.resume_offset(&payload), 1024);
+
+        // All pages sent, including the last partial page.
+        for (address, page) in ledger.pages(&payload, 1024).collect::<Vec<_>>() {
+            ledger.record(address, page);
+        }
+        assert_eq!(ledger.len(), 20);
+        assert_eq!(ledger.resume_offset(&payload), 5000);
+    }
+
+    #[test]
+    fn test_resume_offset_mismatch() {
+        let payload = payload(4096);
+        let mut ledger = PageLedger::new(256, 1024);
+        for (address, page) in ledger.pages(&payload, 0).collect::<Vec<_>>() {
+            ledger.record(address, page);
+        }
+        // A page sent with other contents must be programmed again.
+        let mut other = payload.clone();
+        other[2100] ^= 0xff;
+        assert_eq!(ledger.resume_offset(&other), 2048);
+
+        ledger.forget_from(2048);
+        assert_eq!(ledger.len(), 8);
+        assert_eq!(ledger.resume_offset(&payload), 2048);
+    }
+}
@@ -0,0 +1,339 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Console over a RAM mailbox of the device, accessed through the system bus of the RISC-V debug
+//! module while the CPU runs, for packages without an accessible UART.
+//!
+//! The device side is the `kOttfConsoleDmi` OTTF console, see `ottf_console_dmi.c` for the
+//! mailbox layout. The JTAG adapter is connected to the RISC-V TAP on the first access, with the
+//! `PINMUX_TAP_RISCV` strap applied, and held until `DmiConsoleDevice::release`: other users of
+//! the adapter, e.g. LC transitions over the LC TAP, must wait for the console to be released.
+
+use anyhow::{bail, ensure, Context, Result};
+use std::cell::{Cell, RefCell};
+use std::ops::Range;
+use std::time::{Duration, Instant};
+
+use crate::app::TransportWrapper;
+use crate::io::console::ConsoleDevice;
+use crate::io::jtag::{Jtag, JtagParams, JtagTap};
+
+/// Location of the mailbox in the device memory.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Mailbox {
+    addr: u32,
+    tx_size: u32,
+    rx_size: u32,
+}
+
+/// Ring indices of the mailbox, as free-running byte counts.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct Indices {
+    tx_head: u32,
+    tx_tail: u32,
+    rx_head: u32,
+    rx_tail: u32,
+}
+
+impl Mailbox {
+    const MAGIC_NUMBER: u32 = 0x4d44544f;
+    const HEADER_WORDS: usize = 8;
+    const TX_TAIL_WORD: u32 = 5;
+    const RX_HEAD_WORD: u32 = 6;
+    const MAX_BUFFER_SIZE: u32 = 0x1_0000;
+
+    /// Parses the mailbox header at `addr`.
+    fn parse(addr: u32, header: &[u32]) -> Option<(Self, Indices)> {
+        let &[magic, magic_inv, tx_size, rx_size, tx_head, tx_tail, rx_head, rx_tail] = header
+        else {
+            return None;
+        };
+        // The ring indices wrap around with the sizes only if they are powers of two.
+        let valid_size = |size: u32| size.is_power_of_two() && size <= Self::MAX_BUFFER_SIZE;
+        if magic != Self::MAGIC_NUMBER
+            || magic_inv != !Self::MAGIC_NUMBER
+            || !valid_size(tx_size)
+            || !valid_size(rx_size)
+        {
+            return None;
+        }
+        let indices = Indices {
+            tx_head,
+            tx_tail,
+            rx_head,
+            rx_tail,
+        };
+        // Indices further apart than the ring size mean the mailbox is stale or overwritten.
+        if tx_head.wrapping_sub(tx_tail) > tx_size || rx_head.wrapping_sub(rx_tail) > rx_size {
+            return None;
+        }
+        Some((
+            Self {
+                addr,
+                tx_size,
+                rx_size,
+            },
+            indices,
+        ))
+    }
+
+    /// Finds the mailbox in `words`, read from `base`.
+    fn find(base: u32, words: &[u32]) -> Option<(Self, Indices)> {
+        (0..words.len().saturating_sub(Self::HEADER_WORDS - 1))
+            .find_map(|i| Self::parse(base + 4 * i as u32, &words[i..i + Self::HEADER_WORDS]))
+    }
+
+    fn word_addr(&self, word: u32) -> u32 {
+        self.addr + 4 * word
+    }
+
+    fn tx_addr(&self) -> u32 {
+        self.word_addr(Self::HEADER_WORDS as u32)
+    }
+
+    fn rx_addr(&self) -> u32 {
+        self.tx_addr() + self.tx_size
+    }
+}
+
+/// Splits the `len` bytes of a `size` bytes ring starting at byte count `index` into their
+/// offsets and lengths before and after the end of the ring.
+fn ring_segments(index: u32, len: u32, size: u32) -> [(u32, u32); 2] {
+    let offset = index % size;
+    let first = len.min(size - offset);
+    [(offset, first), (0, len - first)]
+}
+
+pub struct DmiConsoleDevice<'t> {
+    transport: &'t TransportWrapper,
+    jtag_params: JtagParams,
+    search: Range<u32>,
+    write_timeout: Duration,
+    jtag: RefCell<Option<Box<dyn Jtag>>>,
+    mailbox: Cell<Option<Mailbox>>,
+}
+
+impl<'t> DmiConsoleDevice<'t> {
+    /// Main SRAM of Earl Grey, where the OTTF keeps its mailbox.
+    const DEFAULT_SEARCH: Range<u32> = 0x1000_0000..0x1002_0000;
+    const SEARCH_CHUNK_WORDS: usize = 1024;
+    const POLL_INTERVAL: Duration = Duration::from_millis(10);
+
+    pub fn new(transport: &'t TransportWrapper, jtag_params: &JtagParams) -> Self {
+        Self {
+            transport,
+            jtag_params: jtag_params.clone(),
+            search: Self::DEFAULT_SEARCH,
+            write_timeout: Duration::from_secs(10),
+            jtag: RefCell::new(None),
+            mailbox: Cell::new(None),
+        }
+    }
+
+    /// Returns this console, looking for the mailbox at `addr` only, e.g. as resolved from the
+    /// `ottf_console_dmi_mailbox` symbol of the firmware ELF.
+    pub fn with_address(mut self, addr: u32) -> Self {
+        self.search = addr..addr + 4 * Mailbox::HEADER_WORDS as u32;
+        self
+    }
+
+    /// Returns this console, failing writes the device does not consume within `timeout`.
+    pub fn with_write_timeout(mut self, timeout: Duration) -> Self {
+        self.write_timeout = timeout;
+        self
+    }
+
+    /// Disconnects from the JTAG adapter and removes the `PINMUX_TAP_RISCV` strap, until the next
+    /// access to the console.
+    pub fn release(&self) -> Result<()> {
+        self.mailbox.set(None);
+        if let Some(jtag) = self.jtag.borrow_mut().take() {
+            jtag.disconnect()?;
+            self.transport.pin_strapping("PINMUX_TAP_RISCV")?.remove()?;
+        }
+        Ok(())
+    }
+
+    fn with_jtag<T>(&self, f: impl FnOnce(&mut dyn Jtag) -> Result<T>) -> Result<T> {
+        let mut jtag = self.jtag.borrow_mut();
+        if jtag.is_none() {
+            self.transport.pin_strapping("PINMUX_TAP_RISCV")?.apply()?;
+            *jtag = Some(
+                self.jtag_params
+                    .create(self.transport)?
+                    .connect(JtagTap::RiscvTap)
+                    .context("Failed to connect to the debug module console")?,
+            );
+        }
+        let result = f(&mut **jtag.as_mut().unwrap());
+        if result.is_err() {
+            // The device may have been reset, look for the mailbox again.
+            self.mailbox.set(None);
+        }
+        result
+    }
+
+    /// Reads the mailbox header, looking for the mailbox if it is not known yet.
+    fn header(&self, jtag: &mut dyn Jtag) -> Result<Option<(Mailbox, Indices)>> {
+        if let Some(mailbox) = self.mailbox.get() {
+            let mut header = [0u32; Mailbox::HEADER_WORDS];
+            jtag.read_memory32(mailbox.addr, &mut header)?;
+            match Mailbox::parse(mailbox.addr, &header) {
+                Some(found) if found.0 == mailbox => return Ok(Some(found)),
+                _ => self.mailbox.set(None),
+            }
+        }
+
+        let mut addr = self.search.start;
+        loop {
+            let words = Self::SEARCH_CHUNK_WORDS
+                .min(self.search.end.saturating_sub(addr) as usize / 4)
+                .max(Mailbox::HEADER_WORDS);
+            let mut chunk = vec![0u32; words];
+            jtag.read_memory32(addr, &mut chunk)?;
+            if let Some(found) = Mailbox::find(addr, &chunk) {
+                log::info!("Found the debug module console at {:#010x}", found.0.addr);
+                self.mailbox.set(Some(found.0));
+                return Ok(Some(found));
+            }
+            if addr + 4 * words as u32 >= self.search.end {
+                return Ok(None);
+            }
+            // Overlap the chunks so that a header across two chunks is found.
+            addr += 4 * (words - (Mailbox::HEADER_WORDS - 1)) as u32;
+        }
+    }
+}
+
+impl ConsoleDevice for DmiConsoleDevice<'_> {
+    fn console_read(&self, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        let read = self.with_jtag(|jtag| {
+            let Some((mailbox, indices)) = self.header(jtag)? else {
+                return Ok(0);
+            };
+            let len = indices
+                .tx_head
+                .wrapping_sub(indices.tx_tail)
+                .min(u32::try_from(buf.len()).unwrap_or(u32::MAX));
+            let mut pos = 0;
+            for (offset, len) in ring_segments(indices.tx_tail, len, mailbox.tx_size) {
+                if len > 0 {
+                    let end = pos + len as usize;
+                    let read = jtag.read_memory(mailbox.tx_addr() + offset, &mut buf[pos..end])?;
+                    ensure!(read == len as usize, "Read {read} of {len} console bytes");
+                    pos = end;
+                }
+            }
+            if len > 0 {
+                jtag.write_memory32(
+                    mailbox.word_addr(Mailbox::TX_TAIL_WORD),
+                    &[indices.tx_tail.wrapping_add(len)],
+                )?;
+            }
+            Ok(pos)
+        })?;
+        if read == 0 {
+            std::thread::sleep(timeout.min(Self::POLL_INTERVAL));
+        }
+        Ok(read)
+    }
+
+    fn console_write(&self, buf: &[u8]) -> Result<()> {
+        let deadline = Instant::now() + self.write_timeout;
+        let mut written = 0;
+        while written < buf.len() {
+            let sent = self.with_jtag(|jtag| {
+                let Some((mailbox, indices)) = self.header(jtag)? else {
+                    return Ok(0);
+                };
+                let free = mailbox.rx_size - indices.rx_head.wrapping_sub(indices.rx_tail);
+                let len = free.min(u32::try_from(buf.len() - written).unwrap_or(u32::MAX));
+                let mut pos = written;
+                for (offset, len) in ring_segments(indices.rx_head, len, mailbox.rx_size) {
+                    if len > 0 {
+                        let end = pos + len as usize;
+                        jtag.write_memory(mailbox.rx_addr() + offset, &buf[pos..end])?;
+                        pos = end;
+                    }
+                }
+                if len > 0 {
+                    jtag.write_memory32(
+                        mailbox.word_addr(Mailbox::RX_HEAD_WORD),
+                        &[indices.rx_head.wrapping_add(len)],
+                    )?;
+                }
+                Ok(len as usize)
+            })?;
+            written += sent;
+            if sent == 0 {
+                if Instant::now() >= deadline {
+                    bail!(
+                        "Debug module console did not consume {} of {} bytes within {:?}",
+                        buf.len() - written,
+                        buf.len(),
+                        self.write_timeout
+                    );
+                }
+                std::thread::sleep(Self::POLL_INTERVAL);
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn header(tx_head: u32, tx_tail: u32) -> [u32; Mailbox::HEADER_WORDS] {
+        [
+            Mailbox::MAGIC_NUMBER,
+            !Mailbox::MAGIC_NUMBER,
+            1024,
+            256,
+            tx_head,
+            tx_tail,
+            0,
+            0,
+        ]
+    }
+
+    #[test]
+    fn find_mailbox() {
+        let mut words = vec![0xdeadbeef; 64];
+        words[37..45].copy_from_slice(&header(10, 4));
+        let (mailbox, indices) = Mailbox::find(0x1000_0000, &words).unwrap();
+        assert_eq!(
+            mailbox,
+            Mailbox {
+                addr: 0x1000_0000 + 37 * 4,
+                tx_size: 1024,
+                rx_size: 256,
+            }
+        );
+        assert_eq!(indices.tx_head.wrapping_sub(indices.tx_tail), 6);
+        assert_eq!(mailbox.rx_addr(), mailbox.addr + 32 + 1024);
+
+        // A header cut by the end of the words is not found.
+        assert_eq!(Mailbox::find(0x1000_0000, &words[..44]), None);
+    }
+
+    #[test]
+    fn reject_stale_mailbox() {
+        let mut bad_magic = header(0, 0);
+        bad_magic[1] = 0;
+        assert_eq!(Mailbox::parse(0, &bad_magic), None);
+        // More pending bytes than the ring holds.
+        assert_eq!(Mailbox::parse(0, &header(2048, 0)), None);
+        // The indices are free-running and wrap.
+        assert!(Mailbox::parse(0, &header(5, u32::MAX - 2)).is_some());
+    }
+
+    #[test]
+    fn ring_wrap_around() {
+        assert_eq!(ring_segments(1030, 10, 1024), [(6, 10), (0, 0)]);
+        assert_eq!(ring_segments(1020, 10, 1024), [(1020, 4), (0, 6)]);
+        assert_eq!(ring_segments(u32::MAX, 2, 256), [(255, 1), (0, 1)]);
+    }
+}
@@ -2,4 +2,6 @@
 // Licensed under the Apache License, Version 2.0, see LICENSE for details.
 // SPDX-License-Identifier: Apache-2.0
 
+pub mod dmi;
+pub mod parallel;
 pub mod spi;
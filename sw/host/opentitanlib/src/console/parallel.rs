@@ -0,0 +1,65 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::Result;
+use std::cell::Cell;
+use std::time::Duration;
+
+use crate::io::console::ConsoleDevice;
+
+/// Console reading from two devices in parallel, e.g. the debug module console of the firmware
+/// and the UART the ROM prints to.
+///
+/// Writes go to the primary device only. The output of the devices is interleaved in the chunks
+/// they are read in, so only one of them should carry the ujson messages.
+pub struct ParallelConsole<'a, P: ?Sized, S: ?Sized> {
+    primary: &'a P,
+    secondary: &'a S,
+    secondary_first: Cell<bool>,
+}
+
+impl<'a, P, S> ParallelConsole<'a, P, S>
+where
+    P: ConsoleDevice + ?Sized,
+    S: ConsoleDevice + ?Sized,
+{
+    pub fn new(primary: &'a P, secondary: &'a S) -> Self {
+        Self {
+            primary,
+            secondary,
+            secondary_first: Cell::new(false),
+        }
+    }
+}
+
+impl<P, S> ConsoleDevice for ParallelConsole<'_, P, S>
+where
+    P: ConsoleDevice + ?Sized,
+    S: ConsoleDevice + ?Sized,
+{
+    fn console_read(&self, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        // Split the timeout between the devices and alternate which one is read first, so that a
+        // chatty device does not starve the other one.
+        let timeout = timeout / 2;
+        if self.secondary_first.replace(!self.secondary_first.get()) {
+            match self.secondary.console_read(buf, timeout)? {
+                0 => self.primary.console_read(buf, timeout),
+                len => Ok(len),
+            }
+        } else {
+            match self.primary.console_read(buf, timeout)? {
+                0 => self.secondary.console_read(buf, timeout),
+                len => Ok(len),
+            }
+        }
+    }
+
+    fn console_write(&self, buf: &[u8]) -> Result<()> {
+        self.primary.console_write(buf)
+    }
+
+    fn set_break(&self, enable: bool) -> Result<()> {
+        self.primary.set_break(enable)
+    }
+}
@@ -238,6 +238,10 @@ impl OpenOcd {
 pub struct OpenOcdJtagChain {
     /// OpenOCD server instance.
     openocd: OpenOcd,
+    /// Commands declaring the TAPs of the other parts of the scan chain before the OpenTitan TAP.
+    taps_before: Vec<String>,
+    /// Commands declaring the TAPs of the other parts of the scan chain after the OpenTitan TAP.
+    taps_after: Vec<String>,
 }
 
 /// Errors related to the OpenOCD server.
@@ -255,6 +259,9 @@ impl_serializable_error!(OpenOcdError);
 impl OpenOcdJtagChain {
     /// Start OpenOCD with given JTAG options but do not connect any TAP.
     pub fn new(adapter_command: &str, opts: &JtagParams) -> Result<OpenOcdJtagChain> {
+        let (before, after) = opts.other_taps()?;
+        let taps_before = before.iter().map(|tap| tap.newtap_command()).collect();
+        let taps_after = after.iter().map(|tap| tap.newtap_command()).collect();
         let mut openocd = OpenOcd::spawn(&opts.openocd, opts.log_stdio)?;
 
         openocd.execute(adapter_command)?;
@@ -262,18 +269,28 @@ impl OpenOcdJtagChain {
         openocd.execute("transport select jtag")?;
         openocd.execute("scan_chain")?;
 
-        Ok(OpenOcdJtagChain { openocd })
+        Ok(OpenOcdJtagChain {
+            openocd,
+            taps_before,
+            taps_after,
+        })
     }
 }
 
 impl JtagChain for OpenOcdJtagChain {
     fn connect(mut self: Box<Self>, tap: JtagTap) -> Result<Box<dyn Jtag>> {
-        // Pass through the config for the chosen TAP.
+        // Pass through the config for the chosen TAP, declared in its position on the scan chain.
         let target = match tap {
             JtagTap::RiscvTap => include_str!(env!("openocd_riscv_target_cfg")),
             JtagTap::LcTap => include_str!(env!("openocd_lc_target_cfg")),
         };
+        for cmd in &self.taps_before {
+            self.openocd.execute(cmd)?;
+        }
         self.openocd.execute(target)?;
+        for cmd in &self.taps_after {
+            self.openocd.execute(cmd)?;
+        }
 
         // Capture outputs during initialization to see if error has occurred during the process.
         let resp = self.openocd.execute("capture init")?;
@@ -2,12 +2,14 @@
 // Licensed under the Apache License, Version 2.0, see LICENSE for details.
 // SPDX-License-Identifier: Apache-2.0
 
-use anyhow::Result;
+use anyhow::{bail, ensure, Context, Result};
 use clap::Args;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
+use std::collections::HashSet;
 use std::path::PathBuf;
+use std::str::FromStr;
 use std::time::Duration;
 
 use crate::app::TransportWrapper;
@@ -26,6 +28,13 @@ pub struct JtagParams {
 
     #[arg(long, default_value = "false")]
     pub log_stdio: bool,
+
+    /// TAPs of the JTAG scan chain of the device, in their OpenOCD declaration order: the TAPs of
+    /// the other parts as `<name>:<irlen>[:<expected IDCODE>]`, and `opentitan` at the position of
+    /// the OpenTitan TAP, e.g. `fpga:6,opentitan,cpld:8:0x020a10dd`. Unset for a chain of the
+    /// OpenTitan TAP alone.
+    #[arg(long, value_delimiter = ',')]
+    pub jtag_chain: Vec<JtagChainEntry>,
 }
 
 impl JtagParams {
@@ -33,6 +42,112 @@ impl JtagParams {
         let jtag = transport.jtag(self)?;
         Ok(jtag)
     }
+
+    /// Returns the TAPs of the other parts before and after the OpenTitan TAP on the scan chain.
+    pub fn other_taps(&self) -> Result<(Vec<&OtherTap>, Vec<&OtherTap>)> {
+        let mut before = Vec::new();
+        let mut after = Vec::new();
+        let mut opentitan = false;
+        let mut names = HashSet::new();
+        for entry in &self.jtag_chain {
+            match entry {
+                JtagChainEntry::OpenTitan => {
+                    ensure!(!opentitan, "The JTAG chain lists the OpenTitan TAP twice");
+                    opentitan = true;
+                }
+                JtagChainEntry::Other(tap) => {
+                    ensure!(
+                        names.insert(&tap.name),
+                        "The JTAG chain lists the TAP {:?} twice",
+                        tap.name
+                    );
+                    if opentitan {
+                        after.push(tap);
+                    } else {
+                        before.push(tap);
+                    }
+                }
+            }
+        }
+        ensure!(
+            opentitan || self.jtag_chain.is_empty(),
+            "The JTAG chain must list the position of the OpenTitan TAP as `opentitan`"
+        );
+        Ok((before, after))
+    }
+}
+
+/// The TAP of another part sitting on the JTAG scan chain of the device, kept in BYPASS.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OtherTap {
+    /// Name of the TAP, unique on the chain.
+    pub name: String,
+    /// Length of its instruction register, in bits.
+    pub irlen: u32,
+    /// IDCODE the TAP is expected to report, if checked.
+    pub expected_id: Option<u32>,
+}
+
+impl OtherTap {
+    /// Returns the OpenOCD command declaring this TAP.
+    pub fn newtap_command(&self) -> String {
+        let mut cmd = format!("jtag newtap {} tap -irlen {}", self.name, self.irlen);
+        if let Some(id) = self.expected_id {
+            cmd += &format!(" -expected-id {id:#010x}");
+        }
+        cmd
+    }
+}
+
+/// An entry of the JTAG scan chain of the device, see `JtagParams::jtag_chain`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JtagChainEntry {
+    /// The OpenTitan TAP, i.e. the RISC-V or LC TAP selected by the strapping.
+    OpenTitan,
+    Other(OtherTap),
+}
+
+impl FromStr for JtagChainEntry {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        if s == "opentitan" {
+            return Ok(Self::OpenTitan);
+        }
+        let mut fields = s.split(':');
+        let name = fields.next().unwrap_or_default();
+        ensure!(
+            !name.is_empty() && name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_'),
+            "Invalid JTAG TAP name in {s:?}"
+        );
+        ensure!(
+            !matches!(name, "riscv" | "lc_ctrl"),
+            "JTAG TAP name {name:?} is reserved for the OpenTitan TAP"
+        );
+        let Some(irlen) = fields.next() else {
+            bail!("Expected <name>:<irlen>[:<expected IDCODE>], got {s:?}");
+        };
+        let irlen: u32 = irlen
+            .parse()
+            .with_context(|| format!("Invalid IR length in {s:?}"))?;
+        ensure!(irlen > 0, "Invalid IR length in {s:?}");
+        let expected_id = fields
+            .next()
+            .map(|id| {
+                u32::from_str_radix(id.trim_start_matches("0x"), 16)
+                    .with_context(|| format!("Invalid IDCODE in {s:?}"))
+            })
+            .transpose()?;
+        ensure!(
+            fields.next().is_none(),
+            "Expected <name>:<irlen>[:<expected IDCODE>], got {s:?}"
+        );
+        Ok(Self::Other(OtherTap {
+            name: name.to_string(),
+            irlen,
+            expected_id,
+        }))
+    }
 }
 
 /// Errors related to the JTAG interface.
@@ -366,3 +481,58 @@ impl From<RiscvCsr> for RiscvReg {
         Self::Csr(csr)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn params(chain: &str) -> Result<JtagParams> {
+        Ok(JtagParams {
+            openocd: PathBuf::from("openocd"),
+            adapter_speed_khz: 1000,
+            log_stdio: false,
+            jtag_chain: chain
+                .split(',')
+                .map(JtagChainEntry::from_str)
+                .collect::<Result<_>>()?,
+        })
+    }
+
+    #[test]
+    fn test_parse_chain_entry() -> Result<()> {
+        assert_eq!(
+            JtagChainEntry::from_str("opentitan")?,
+            JtagChainEntry::OpenTitan
+        );
+        let JtagChainEntry::Other(tap) = JtagChainEntry::from_str("cpld:8:0x020a10dd")? else {
+            panic!("Expected another TAP");
+        };
+        assert_eq!(
+            tap.newtap_command(),
+            "jtag newtap cpld tap -irlen 8 -expected-id 0x020a10dd"
+        );
+        assert!(JtagChainEntry::from_str("fpga").is_err());
+        assert!(JtagChainEntry::from_str("fpga:0").is_err());
+        assert!(JtagChainEntry::from_str("riscv:5").is_err());
+        assert!(JtagChainEntry::from_str("fpga:6:0x1:2").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_other_taps() -> Result<()> {
+        let params = params("fpga:6,opentitan,cpld:8,pmic:4")?;
+        let (before, after) = params.other_taps()?;
+        assert_eq!(
+            before.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(),
+            ["fpga"]
+        );
+        assert_eq!(
+            after.iter().map(|t| t.name.as_str()).collect::<Vec<_>>(),
+            ["cpld", "pmic"]
+        );
+        assert!(params("fpga:6,cpld:8")?.other_taps().is_err());
+        assert!(params("opentitan,fpga:6,opentitan")?.other_taps().is_err());
+        assert!(params("fpga:6,opentitan,fpga:4")?.other_taps().is_err());
+        Ok(())
+    }
+}
@@ -4,12 +4,12 @@
 
 use std::io::{self, Read};
 use std::rc::Rc;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::Result;
 use clap::Args;
 use serde::{Deserialize, Serialize};
-pub use serialport::Parity;
+pub use serialport::{Parity, StopBits};
 use thiserror::Error;
 
 use super::nonblocking_help::{NoNonblockingHelp, NonblockingHelp};
@@ -18,6 +18,12 @@ use crate::impl_serializable_error;
 use crate::io::console::ConsoleDevice;
 use crate::transport::TransportError;
 
+/// Default RX silence `Uart::drain_until_quiet` waits for before returning.
+pub const UART_QUIET_TIME: Duration = Duration::from_millis(50);
+
+/// Upper bound on the time spent in `Uart::drain_until_quiet`, in case the line never goes quiet.
+const UART_MAX_DRAIN_TIME: Duration = Duration::from_secs(2);
+
 #[derive(Clone, Debug, Args, Serialize, Deserialize)]
 pub struct UartParams {
     /// UART instance.
@@ -105,6 +111,34 @@ pub trait Uart {
         Ok(())
     }
 
+    /// Discards received data until no data was received for `quiet`, returning the number of
+    /// bytes discarded.
+    ///
+    /// Unlike `clear_rx_buffer`, this does not strand the tail of a message still being
+    /// transmitted (e.g. by the ROM), which could otherwise break later line-based matches.
+    /// Only use it while the device is held in reset: output of a running device arriving within
+    /// the drain, e.g. the line a test waits for next, is discarded.
+    fn drain_until_quiet(&self, quiet: Duration) -> Result<usize> {
+        self.clear_rx_buffer()?;
+        let start = Instant::now();
+        let mut buf = [0u8; 256];
+        let mut drained = 0;
+        loop {
+            let n = self.read_timeout(&mut buf, quiet)?;
+            if n == 0 {
+                return Ok(drained);
+            }
+            drained += n;
+            if start.elapsed() > UART_MAX_DRAIN_TIME {
+                log::warn!(
+                    "UART RX still active after {:?}, giving up draining",
+                    UART_MAX_DRAIN_TIME
+                );
+                return Ok(drained);
+            }
+        }
+    }
+
     fn set_break(&self, _enable: bool) -> Result<()> {
         Err(TransportError::UnsupportedOperation.into())
     }
@@ -117,6 +151,14 @@ pub trait Uart {
         Err(TransportError::UnsupportedOperation.into())
     }
 
+    fn set_stop_bits(&self, _stop_bits: StopBits) -> Result<()> {
+        Err(TransportError::UnsupportedOperation.into())
+    }
+
+    fn get_stop_bits(&self) -> Result<StopBits> {
+        Err(TransportError::UnsupportedOperation.into())
+    }
+
     /// Query if nonblocking mio mode is supported.
     fn supports_nonblocking_read(&self) -> Result<bool> {
         Ok(false)
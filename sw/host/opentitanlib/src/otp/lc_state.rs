@@ -6,6 +6,7 @@ use anyhow::{bail, Result};
 use serde::Deserialize;
 use std::fs;
 use std::path::Path;
+use std::str::FromStr;
 
 /// SECDED matrix used for ECC in OTP.
 #[derive(Deserialize, Debug)]
@@ -36,12 +37,7 @@ pub enum LcStateVal {
 
 impl LcSecded {
     pub fn new(in_file: &Path) -> Result<LcSecded> {
-        let json_text = fs::read_to_string(in_file)?;
-        let res: LcState = deser_hjson::from_str(&json_text)?;
-        if res.secded.ecc_matrix.len() != res.secded.ecc_width {
-            bail!("Bad ecc matrix length {}", res.secded.ecc_matrix.len());
-        }
-        Ok(res.secded)
+        fs::read_to_string(in_file)?.parse()
     }
 
     fn bit_index(data: &[u8], index: usize) -> bool {
@@ -71,6 +67,10 @@ impl LcSecded {
         Ok(data)
     }
 
+    pub fn data_byte_len(&self) -> usize {
+        self.data_width / 8
+    }
+
     pub fn ecc_byte_len(&self) -> usize {
         if self.ecc_width == 0 {
             0
@@ -80,6 +80,19 @@ impl LcSecded {
     }
 }
 
+impl FromStr for LcSecded {
+    type Err = anyhow::Error;
+
+    /// Parses the SECDED configuration out of the contents of lc_ctrl_state.hjson.
+    fn from_str(json_text: &str) -> Result<LcSecded> {
+        let res: LcState = deser_hjson::from_str(json_text)?;
+        if res.secded.ecc_matrix.len() != res.secded.ecc_width {
+            bail!("Bad ecc matrix length {}", res.secded.ecc_matrix.len());
+        }
+        Ok(res.secded)
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
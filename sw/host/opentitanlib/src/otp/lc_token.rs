@@ -0,0 +1,318 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Life cycle tokens and the hashes of them stored in OTP.
+//!
+//! lc_ctrl compares the cSHAKE128 hash (empty function name, customization string `LC_CTRL`) of
+//! the 128-bit token written to the TRANSITION_TOKEN registers with the value stored in OTP.
+//! The same hashing must be used wherever a token hash is produced: when generating OTP images
+//! (see `util/design/lib/LcStEnc.py`), when escrowing tokens, and when provisioning the hashes of
+//! the test unlock / exit and RMA unlock tokens.
+//!
+//! The hashes are stored in OTP as 128-bit values, see `LcTokenItem`: `lc_token_overlay` builds
+//! the OTP image overlay of the hashes of raw tokens, and `LcToken::check_otp_hash` checks a raw
+//! token against an OTP image, e.g. before attempting a transition with it.
+
+use std::fmt;
+
+use anyhow::{bail, ensure, Context, Result};
+use tiny_keccak::{CShake, Hasher};
+
+use crate::dif::lc_ctrl::DifLcCtrlState;
+use crate::otp::otp_img::{OtpImg, OtpImgItem, OtpImgPartition, OtpImgValue, OtpRead};
+
+/// Customization string of the cSHAKE128 token hashing.
+const LC_TOKEN_CUSTOMIZATION: &[u8] = b"LC_CTRL";
+
+/// Size of a life cycle token and of its hash, in bytes.
+pub const LC_TOKEN_SIZE: usize = 16;
+
+/// OTP items holding the hash of a life cycle token.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LcTokenItem {
+    TestUnlock,
+    TestExit,
+    Rma,
+}
+
+impl LcTokenItem {
+    /// Returns the name of the OTP item.
+    pub fn name(&self) -> &'static str {
+        match self {
+            LcTokenItem::TestUnlock => "TEST_UNLOCK_TOKEN",
+            LcTokenItem::TestExit => "TEST_EXIT_TOKEN",
+            LcTokenItem::Rma => "RMA_TOKEN",
+        }
+    }
+
+    /// Returns the name of the OTP partition holding the item.
+    pub fn partition(&self) -> &'static str {
+        match self {
+            LcTokenItem::TestUnlock | LcTokenItem::TestExit => "SECRET0",
+            LcTokenItem::Rma => "SECRET2",
+        }
+    }
+
+    /// Returns the item holding the hash of the token of a transition from `lc_state` to
+    /// `target`, for the transitions requiring a token.
+    ///
+    /// Transitions out of the RAW state use the raw unlock token, which is not stored in OTP.
+    pub fn for_transition(lc_state: DifLcCtrlState, target: DifLcCtrlState) -> Option<Self> {
+        match (lc_state, target) {
+            (DifLcCtrlState::Raw, _) => None,
+            (
+                _,
+                DifLcCtrlState::TestUnlocked0
+                | DifLcCtrlState::TestUnlocked1
+                | DifLcCtrlState::TestUnlocked2
+                | DifLcCtrlState::TestUnlocked3
+                | DifLcCtrlState::TestUnlocked4
+                | DifLcCtrlState::TestUnlocked5
+                | DifLcCtrlState::TestUnlocked6
+                | DifLcCtrlState::TestUnlocked7,
+            ) => Some(LcTokenItem::TestUnlock),
+            (_, DifLcCtrlState::Dev | DifLcCtrlState::Prod | DifLcCtrlState::ProdEnd) => {
+                Some(LcTokenItem::TestExit)
+            }
+            (_, DifLcCtrlState::Rma) => Some(LcTokenItem::Rma),
+            _ => None,
+        }
+    }
+}
+
+/// A 128-bit life cycle token.
+///
+/// The token is stored as the bytes fed to lc_ctrl: the TRANSITION_TOKEN registers in order, each
+/// in little-endian byte order. This is the little-endian representation of the integer value of
+/// the token used by the OTP image generation tools.
+#[derive(Clone, PartialEq, Eq)]
+pub struct LcToken([u8; LC_TOKEN_SIZE]);
+
+/// The hash of a life cycle token, as stored in OTP.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct HashedLcToken([u8; LC_TOKEN_SIZE]);
+
+impl LcToken {
+    pub fn from_bytes(bytes: [u8; LC_TOKEN_SIZE]) -> Self {
+        LcToken(bytes)
+    }
+
+    /// Builds a token from the values of the TRANSITION_TOKEN registers.
+    pub fn from_words(words: [u32; 4]) -> Self {
+        let mut bytes = [0u8; LC_TOKEN_SIZE];
+        for (chunk, word) in bytes.chunks_exact_mut(4).zip(words) {
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        LcToken(bytes)
+    }
+
+    /// Builds a token from a slice of TRANSITION_TOKEN register values.
+    pub fn from_word_slice(words: &[u32]) -> Result<Self> {
+        ensure!(
+            words.len() == 4,
+            "LC token must be 4 words long, got {}",
+            words.len()
+        );
+        Ok(LcToken::from_words(words.try_into().unwrap()))
+    }
+
+    /// Builds a token from its integer value, as used by the OTP image generation tools.
+    pub fn from_u128(value: u128) -> Self {
+        LcToken(value.to_le_bytes())
+    }
+
+    pub fn as_bytes(&self) -> &[u8; LC_TOKEN_SIZE] {
+        &self.0
+    }
+
+    /// Returns the values to write to the TRANSITION_TOKEN registers.
+    pub fn to_words(&self) -> [u32; 4] {
+        let mut words = [0u32; 4];
+        for (word, chunk) in words.iter_mut().zip(self.0.chunks_exact(4)) {
+            *word = u32::from_le_bytes(chunk.try_into().unwrap());
+        }
+        words
+    }
+
+    pub fn to_u128(&self) -> u128 {
+        u128::from_le_bytes(self.0)
+    }
+
+    /// Hashes the token the way lc_ctrl does.
+    pub fn hash(&self) -> HashedLcToken {
+        let mut csh = CShake::v128(b"", LC_TOKEN_CUSTOMIZATION);
+        let mut output = [0u8; LC_TOKEN_SIZE];
+        csh.update(&self.0);
+        csh.finalize(&mut output);
+        HashedLcToken(output)
+    }
+
+    /// Checks the token hashes to the value of `item` in `otp`.
+    pub fn check_otp_hash(&self, otp: &impl OtpRead, item: &str) -> Result<()> {
+        let expected = HashedLcToken::from_otp(otp, item)?;
+        ensure!(
+            self.hash() == expected,
+            "Token does not match the {item} hash of the OTP image"
+        );
+        Ok(())
+    }
+}
+
+impl fmt::Debug for LcToken {
+    // Tokens are secrets, keep them out of logs.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("LcToken(..)")
+    }
+}
+
+impl HashedLcToken {
+    /// Reads the hash stored in the `item` of `otp`, e.g. `TEST_UNLOCK_TOKEN`.
+    pub fn from_otp(otp: &impl OtpRead, item: &str) -> Result<Self> {
+        let mut bytes = [0u8; LC_TOKEN_SIZE];
+        for (i, chunk) in bytes.chunks_exact_mut(4).enumerate() {
+            let word = otp
+                .read32_offset(item, 4 * i)
+                .with_context(|| format!("OTP image holds no fixed {item} hash"))?;
+            chunk.copy_from_slice(&word.to_le_bytes());
+        }
+        Ok(HashedLcToken(bytes))
+    }
+
+    pub fn as_bytes(&self) -> &[u8; LC_TOKEN_SIZE] {
+        &self.0
+    }
+
+    /// Returns the hash as the two little-endian 64-bit words stored in OTP.
+    pub fn to_u64s(&self) -> [u64; 2] {
+        let (lo, hi) = self.0.split_at(8);
+        [
+            u64::from_le_bytes(lo.try_into().unwrap()),
+            u64::from_le_bytes(hi.try_into().unwrap()),
+        ]
+    }
+
+    /// Returns the integer value of the hash, as output by the OTP image generation tools.
+    pub fn to_u128(&self) -> u128 {
+        u128::from_le_bytes(self.0)
+    }
+}
+
+/// Builds the OTP image overlay of the hashes of `tokens`, for the OTP image generation tools.
+pub fn lc_token_overlay(tokens: &[(LcTokenItem, LcToken)]) -> Result<OtpImg> {
+    let mut partitions = Vec::<OtpImgPartition>::new();
+    for (item, token) in tokens {
+        let otp_item = OtpImgItem {
+            name: item.name().to_owned(),
+            value: OtpImgValue::Wide(token.hash().to_u128()),
+        };
+        match partitions.iter_mut().find(|p| p.name == item.partition()) {
+            Some(partition) => {
+                let items = partition.items.get_or_insert_with(Vec::new);
+                if items.iter().any(|i| i.name == otp_item.name) {
+                    bail!("Duplicate {} token", otp_item.name);
+                }
+                items.push(otp_item);
+            }
+            None => partitions.push(OtpImgPartition {
+                name: item.partition().to_owned(),
+                items: Some(vec![otp_item]),
+            }),
+        }
+    }
+    Ok(OtpImg {
+        seed: None,
+        partitions,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // Expected values computed with the cSHAKE128 implementation of `util/design/lib/LcStEnc.py`.
+    const VECTORS: [([u32; 4], u128); 3] = [
+        ([0; 4], 0x3852305baecf5ff1d5c1d25f6db9058d),
+        ([0xffffffff; 4], 0x58be9cc5f06dc54801d9192f968d6b69),
+        (
+            [0x03020100, 0x07060504, 0x0b0a0908, 0x0f0e0d0c],
+            0x547070d7503264af5b9a971b894ef3be,
+        ),
+    ];
+
+    #[test]
+    fn test_hash() {
+        for (words, hash) in VECTORS {
+            let hashed = LcToken::from_words(words).hash();
+            assert_eq!(hashed.to_u128(), hash);
+            assert_eq!(hashed.to_u64s(), [hash as u64, (hash >> 64) as u64]);
+        }
+    }
+
+    #[test]
+    fn test_token_representations() {
+        let words = [0x03020100, 0x07060504, 0x0b0a0908, 0x0f0e0d0c];
+        let token = LcToken::from_words(words);
+        let bytes: Vec<u8> = (0..16).collect();
+        assert_eq!(token.as_bytes().as_slice(), bytes.as_slice());
+        assert_eq!(token.to_u128(), 0x0f0e0d0c0b0a09080706050403020100);
+        assert_eq!(LcToken::from_u128(token.to_u128()), token);
+        assert_eq!(token.to_words(), words);
+        assert_eq!(LcToken::from_word_slice(&words).unwrap(), token);
+        assert!(LcToken::from_word_slice(&words[..3]).is_err());
+        assert_eq!(format!("{token:?}"), "LcToken(..)");
+    }
+
+    #[test]
+    fn test_otp_overlay() {
+        let (unlock, exit) = (
+            LcToken::from_words(VECTORS[0].0),
+            LcToken::from_words(VECTORS[2].0),
+        );
+        let otp = lc_token_overlay(&[
+            (LcTokenItem::TestUnlock, unlock.clone()),
+            (LcTokenItem::TestExit, exit.clone()),
+        ])
+        .unwrap();
+        assert_eq!(otp.partitions.len(), 1);
+        assert_eq!(otp.partitions[0].name, "SECRET0");
+        assert_eq!(
+            HashedLcToken::from_otp(&otp, "TEST_EXIT_TOKEN")
+                .unwrap()
+                .to_u128(),
+            VECTORS[2].1
+        );
+        unlock.check_otp_hash(&otp, "TEST_UNLOCK_TOKEN").unwrap();
+        assert!(exit.check_otp_hash(&otp, "TEST_UNLOCK_TOKEN").is_err());
+        assert!(unlock.check_otp_hash(&otp, "RMA_TOKEN").is_err());
+        assert!(lc_token_overlay(&[
+            (LcTokenItem::TestUnlock, unlock.clone()),
+            (LcTokenItem::TestUnlock, exit.clone()),
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_transition_items() {
+        assert_eq!(
+            LcTokenItem::for_transition(DifLcCtrlState::Raw, DifLcCtrlState::TestUnlocked0),
+            None
+        );
+        assert_eq!(
+            LcTokenItem::for_transition(DifLcCtrlState::TestLocked0, DifLcCtrlState::TestUnlocked1),
+            Some(LcTokenItem::TestUnlock)
+        );
+        assert_eq!(
+            LcTokenItem::for_transition(DifLcCtrlState::TestUnlocked0, DifLcCtrlState::Prod),
+            Some(LcTokenItem::TestExit)
+        );
+        assert_eq!(
+            LcTokenItem::for_transition(DifLcCtrlState::Dev, DifLcCtrlState::Rma),
+            Some(LcTokenItem::Rma)
+        );
+        assert_eq!(
+            LcTokenItem::for_transition(DifLcCtrlState::Prod, DifLcCtrlState::Scrap),
+            None
+        );
+    }
+}
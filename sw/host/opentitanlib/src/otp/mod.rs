@@ -5,6 +5,7 @@
 pub mod alert_handler;
 pub mod alert_handler_regs;
 pub mod lc_state;
+pub mod lc_token;
 // TODO(lowRISC/opentitan#15443): Fix this lint.
 #[allow(clippy::module_inception)]
 pub mod otp_img;
@@ -18,6 +18,9 @@ use serde_annotate::Annotate;
 #[serde(untagged)]
 pub enum OtpImgValue {
     Word(u64),
+    /// A value wider than 64 bits, e.g. a 128-bit token hash.
+    #[serde(serialize_with = "serialize_wide")]
+    Wide(u128),
     Bool(bool),
     Sequence(Vec<u32>),
     #[serde(serialize_with = "serialize_random")]
@@ -31,6 +34,14 @@ where
     serializer.serialize_str("<random>")
 }
 
+fn serialize_wide<S>(value: &u128, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: serde::Serializer,
+{
+    // As the OTP image generation tools expect wide values: a hex string.
+    serializer.serialize_str(&format!("{value:#x}"))
+}
+
 impl<'de> Deserialize<'de> for OtpImgValue {
     fn deserialize<D>(deserializer: D) -> Result<OtpImgValue, D::Error>
     where
@@ -53,10 +64,12 @@ impl<'de> Deserialize<'de> for OtpImgValue {
                     "<random>" => OtpImgValue::Random,
                     "true" => OtpImgValue::Bool(true),
                     "false" => OtpImgValue::Bool(false),
-                    _ => OtpImgValue::Word(
-                        u64::from_str(val)
-                            .map_err(|_| de::Error::invalid_value(Unexpected::Str(val), &self))?,
-                    ),
+                    _ => {
+                        let val = u128::from_str(val)
+                            .map_err(|_| de::Error::invalid_value(Unexpected::Str(val), &self))?;
+                        // Values which fit are kept as words.
+                        u64::try_from(val).map_or(OtpImgValue::Wide(val), OtpImgValue::Word)
+                    }
                 })
             }
 
@@ -146,6 +159,13 @@ impl OtpRead for OtpImg {
                         bail!("invalid OTP address {} + {:#08x}", name, offset)
                     }
                 }
+                Some(OtpImgValue::Wide(v)) => {
+                    if offset < 16 {
+                        (v >> (8 * offset)) as u32
+                    } else {
+                        bail!("invalid OTP address {} + {:#08x}", name, offset)
+                    }
+                }
                 Some(OtpImgValue::Sequence(v)) => *v
                     .get(offset / 4)
                     .ok_or_else(|| anyhow!("invalid OTP address {} + {:#08x}", name, offset))?,
@@ -296,4 +316,38 @@ mod tests {
         assert_eq!(otp.read32_offset("CREATOR_SEQ", 4).unwrap(), 0xcd);
         assert_eq!(otp.read32_offset("CREATOR_SEQ", 8).unwrap(), 0xef);
     }
+
+    #[test]
+    fn test_wide_value() {
+        let otp = OtpImg::from_str(
+            r#"{
+                partitions: [
+                    {
+                        name: "SECRET0",
+                        items: [
+                            {
+                                name: "TEST_UNLOCK_TOKEN",
+                                value: "0x0f0e0d0c0b0a09080706050403020100",
+                            },
+                        ]
+                    }
+                ]
+            }"#,
+        )
+        .unwrap();
+        let value = &otp.partitions[0].items.as_ref().unwrap()[0].value;
+        assert_eq!(
+            *value,
+            OtpImgValue::Wide(0x0f0e0d0c0b0a09080706050403020100)
+        );
+        assert_eq!(otp.read32("TEST_UNLOCK_TOKEN").unwrap(), 0x03020100);
+        assert_eq!(
+            otp.read32_offset("TEST_UNLOCK_TOKEN", 12).unwrap(),
+            0x0f0e0d0c
+        );
+        assert!(otp.read32_offset("TEST_UNLOCK_TOKEN", 16).is_err());
+
+        let json = serialize(&otp).unwrap().to_hjson().to_string();
+        assert!(json.contains(r#"value: "0xf0e0d0c0b0a09080706050403020100""#));
+    }
 }
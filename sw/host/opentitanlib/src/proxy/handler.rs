@@ -277,6 +277,14 @@ impl<'a> TransportCommandHandler<'a> {
                         instance.set_parity(*parity)?;
                         Ok(Response::Uart(UartResponse::SetParity))
                     }
+                    UartRequest::GetStopBits => {
+                        let stop_bits = instance.get_stop_bits()?;
+                        Ok(Response::Uart(UartResponse::GetStopBits { stop_bits }))
+                    }
+                    UartRequest::SetStopBits(stop_bits) => {
+                        instance.set_stop_bits(*stop_bits)?;
+                        Ok(Response::Uart(UartResponse::SetStopBits))
+                    }
                     UartRequest::GetFlowControl => {
                         let flow_control = instance.get_flow_control()?;
                         Ok(Response::Uart(UartResponse::GetFlowControl {
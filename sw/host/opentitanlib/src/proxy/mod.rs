@@ -14,6 +14,7 @@ use std::collections::HashMap;
 use std::net::SocketAddr;
 
 use crate::app::TransportWrapper;
+use crate::util::service::ServiceLifetime;
 
 pub mod errors;
 mod handler;
@@ -91,4 +92,9 @@ impl<'a> SessionHandler<'a> {
     pub fn run_loop(&mut self) -> Result<()> {
         self.socket_server.run_loop()
     }
+
+    /// Runs the event loop until `service` is requested to stop, see `util::service`.
+    pub fn run_service_loop(&mut self, service: &ServiceLifetime) -> Result<()> {
+        self.socket_server.run_service_loop(service)
+    }
 }
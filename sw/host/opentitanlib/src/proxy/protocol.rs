@@ -12,7 +12,7 @@ use crate::io::gpio::{
 };
 use crate::io::i2c::DeviceStatus;
 use crate::io::spi::{MaxSizes, TransferMode};
-use crate::io::uart::{FlowControl, Parity};
+use crate::io::uart::{FlowControl, Parity, StopBits};
 use crate::proxy::errors::SerializedError;
 use crate::transport::Capabilities;
 use crate::util::voltage::Voltage;
@@ -185,6 +185,8 @@ pub enum UartRequest {
     SetBreak(bool),
     GetParity,
     SetParity(Parity),
+    GetStopBits,
+    SetStopBits(StopBits),
     GetFlowControl,
     SetFlowControl(bool),
     GetDevicePath,
@@ -206,6 +208,8 @@ pub enum UartResponse {
     SetBreak,
     GetParity { parity: Parity },
     SetParity,
+    GetStopBits { stop_bits: StopBits },
+    SetStopBits,
     GetFlowControl { flow_control: FlowControl },
     SetFlowControl,
     GetDevicePath { path: String },
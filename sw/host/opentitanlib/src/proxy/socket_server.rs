@@ -15,12 +15,16 @@ use std::collections::HashMap;
 use std::io::{ErrorKind, Read, Write};
 use std::marker::PhantomData;
 use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
 
 use super::CommandHandler;
 use super::ExtraEventHandler;
+use crate::util::service::ServiceLifetime;
 
 const BUFFER_SIZE: usize = 8192;
 const EOL_CODE: u8 = b'\n';
+/// Interval at which a service checks whether it is requested to stop.
+const SERVICE_STOP_POLL_INTERVAL: Duration = Duration::from_millis(200);
 
 pub fn get_next_token() -> Token {
     static TOCKEN_COUNTER: AtomicUsize = AtomicUsize::new(0);
@@ -86,9 +90,24 @@ impl<Msg: DeserializeOwned + Serialize, T: CommandHandler<Msg, E>, E: ExtraEvent
     }
 
     pub fn run_loop(&mut self) -> Result<()> {
+        self.run_loop_until(None)
+    }
+
+    /// Runs the command processing loop until a signal is received, or `service` is requested to
+    /// stop.
+    pub fn run_service_loop(&mut self, service: &ServiceLifetime) -> Result<()> {
+        self.run_loop_until(Some(service))
+    }
+
+    fn run_loop_until(&mut self, service: Option<&ServiceLifetime>) -> Result<()> {
         let mut events = Events::with_capacity(1024);
+        let poll_timeout = service.map(|_| SERVICE_STOP_POLL_INTERVAL);
         while !self.exit_requested {
-            match self.poll.poll(&mut events, None) {
+            if service.is_some_and(ServiceLifetime::stop_requested) {
+                log::info!("Got stop request");
+                break;
+            }
+            match self.poll.poll(&mut events, poll_timeout) {
                 Ok(()) => (),
                 Err(err) if err.kind() == ErrorKind::Interrupted => {
                     continue;
@@ -16,12 +16,47 @@ pub fn read_lc_state(
     jtag_params: &JtagParams,
     reset_delay: Duration,
 ) -> Result<DifLcCtrlState> {
+    let [raw_lc_state] = read_lc_regs(transport, jtag_params, reset_delay, [&LcCtrlReg::LcState])?;
+    DifLcCtrlState::from_redundant_encoding(raw_lc_state)
+}
+
+/// Reads the LC state over the LC TAP without resetting the device.
+///
+/// The LC TAP is only selected without a reset in the `TEST_UNLOCKED*` states, whose TAP straps
+/// are sampled continuously.
+pub fn read_lc_state_without_reset(
+    transport: &TransportWrapper,
+    jtag_params: &JtagParams,
+) -> Result<DifLcCtrlState> {
+    let [raw_lc_state] =
+        connect_and_read_lc_regs(transport, jtag_params, None, [&LcCtrlReg::LcState])?;
+    DifLcCtrlState::from_redundant_encoding(raw_lc_state)
+}
+
+/// Reads the `regs` of the LC controller over the LC TAP, after resetting the device.
+pub fn read_lc_regs<const N: usize>(
+    transport: &TransportWrapper,
+    jtag_params: &JtagParams,
+    reset_delay: Duration,
+    regs: [&LcCtrlReg; N],
+) -> Result<[u32; N]> {
+    connect_and_read_lc_regs(transport, jtag_params, Some(reset_delay), regs)
+}
+
+fn connect_and_read_lc_regs<const N: usize>(
+    transport: &TransportWrapper,
+    jtag_params: &JtagParams,
+    reset_delay: Option<Duration>,
+    regs: [&LcCtrlReg; N],
+) -> Result<[u32; N]> {
     transport.pin_strapping("PINMUX_TAP_LC")?.apply()?;
 
-    // Apply bootstrap pin to be able to connect to JTAG when ROM execution is
-    // enabled.
-    transport.pin_strapping("ROM_BOOTSTRAP")?.apply()?;
-    transport.reset_target(reset_delay, true)?;
+    if let Some(reset_delay) = reset_delay {
+        // Apply bootstrap pin to be able to connect to JTAG when ROM execution is
+        // enabled.
+        transport.pin_strapping("ROM_BOOTSTRAP")?.apply()?;
+        transport.reset_target(reset_delay, true)?;
+    }
     let mut jtag = jtag_params.create(transport)?.connect(JtagTap::LcTap)?;
     // We must wait for the lc_ctrl to initialize before the LC state is exposed.
     wait_for_status(
@@ -29,9 +64,14 @@ pub fn read_lc_state(
         Duration::from_secs(1),
         LcCtrlStatus::INITIALIZED,
     )?;
-    let raw_lc_state = jtag.read_lc_ctrl_reg(&LcCtrlReg::LcState)?;
+    let mut values = [0u32; N];
+    for (value, reg) in values.iter_mut().zip(regs) {
+        *value = jtag.read_lc_ctrl_reg(reg)?;
+    }
     jtag.disconnect()?;
     transport.pin_strapping("PINMUX_TAP_LC")?.remove()?;
-    transport.pin_strapping("ROM_BOOTSTRAP")?.remove()?;
-    DifLcCtrlState::from_redundant_encoding(raw_lc_state)
+    if reset_delay.is_some() {
+        transport.pin_strapping("ROM_BOOTSTRAP")?.remove()?;
+    }
+    Ok(values)
 }
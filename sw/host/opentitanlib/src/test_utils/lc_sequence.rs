@@ -0,0 +1,235 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Recording of the LC controller register writes performed by the host, and comparison against
+//! the golden sequence of the programmer's guide (hw/ip/lc_ctrl/doc/programmers_guide.md).
+
+use std::cell::RefCell;
+use std::fmt;
+use std::rc::Rc;
+use std::time::Duration;
+
+use anyhow::{bail, Result};
+
+use crate::chip::boolean::MultiBitBool8;
+use crate::debug::openocd::OpenOcd;
+use crate::dif::lc_ctrl::{DifLcCtrlState, LcCtrlReg, LcCtrlTransitionCmd, LcCtrlTransitionCtrl};
+use crate::io::jtag::{Jtag, JtagTap, RiscvReg};
+
+/// The TRANSITION_TOKEN multi-register, in programming order.
+pub(crate) const TRANSITION_TOKEN_REGS: [&LcCtrlReg; 4] = [
+    &LcCtrlReg::TransitionToken0,
+    &LcCtrlReg::TransitionToken1,
+    &LcCtrlReg::TransitionToken2,
+    &LcCtrlReg::TransitionToken3,
+];
+
+/// A write to an LC controller register.
+#[derive(Clone, Copy, Debug)]
+pub struct LcRegWrite {
+    pub reg: LcCtrlReg,
+    pub value: u32,
+}
+
+impl PartialEq for LcRegWrite {
+    fn eq(&self, other: &Self) -> bool {
+        self.reg.byte_offset() == other.reg.byte_offset() && self.value == other.value
+    }
+}
+
+impl fmt::Display for LcRegWrite {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?} <- {:#010x}", self.reg, self.value)
+    }
+}
+
+/// Returns the register writes of a transition to `target_lc_state`, as prescribed by the
+/// programmer's guide:
+///
+/// 1. Claim the transition interface mutex.
+/// 2. Configure the external clock and volatile raw unlock in TRANSITION_CTRL.
+/// 3. Program the target state, and the token (zero for unconditional transitions).
+/// 4. Start the transition.
+pub fn golden_transition_writes(
+    target_lc_state: DifLcCtrlState,
+    token: Option<[u32; 4]>,
+    ctrl: LcCtrlTransitionCtrl,
+) -> Vec<LcRegWrite> {
+    let write = |reg, value| LcRegWrite { reg, value };
+    let mut writes = vec![
+        write(
+            LcCtrlReg::ClaimTransitionIf,
+            u8::from(MultiBitBool8::True) as u32,
+        ),
+        write(LcCtrlReg::TransitionCtrl, ctrl.bits()),
+        write(
+            LcCtrlReg::TransitionTarget,
+            target_lc_state.redundant_encoding(),
+        ),
+    ];
+    for (reg, value) in std::iter::zip(TRANSITION_TOKEN_REGS, token.unwrap_or_default()) {
+        writes.push(write(*reg, value));
+    }
+    writes.push(write(
+        LcCtrlReg::TransitionCmd,
+        LcCtrlTransitionCmd::START.bits(),
+    ));
+    writes
+}
+
+/// Checks the `recorded` register writes match the `golden` sequence.
+pub fn check_lc_writes(recorded: &[LcRegWrite], golden: &[LcRegWrite]) -> Result<()> {
+    for (i, (r, g)) in recorded.iter().zip(golden).enumerate() {
+        if r != g {
+            bail!("LC register write #{i} deviates from the golden sequence: {r}, expected {g}");
+        }
+    }
+    if recorded.len() != golden.len() {
+        let (longer, what) = if recorded.len() > golden.len() {
+            (recorded, "unexpected")
+        } else {
+            (golden, "missing")
+        };
+        let extra: Vec<String> = longer[recorded.len().min(golden.len())..]
+            .iter()
+            .map(|w| w.to_string())
+            .collect();
+        bail!(
+            "LC register sequence has {} writes, golden sequence has {}; {what}: {}",
+            recorded.len(),
+            golden.len(),
+            extra.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Records the LC controller register writes performed through the JTAG interfaces it wraps.
+#[derive(Clone, Default)]
+pub struct LcWriteRecorder {
+    writes: Rc<RefCell<Vec<LcRegWrite>>>,
+}
+
+impl LcWriteRecorder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Wraps `jtag` so that its LC controller register writes are recorded.
+    pub fn wrap<'a>(&self, jtag: Box<dyn Jtag + 'a>) -> Box<dyn Jtag + 'a> {
+        Box::new(RecordingJtag {
+            inner: jtag,
+            writes: Rc::clone(&self.writes),
+        })
+    }
+
+    /// Returns the register writes recorded so far, and clears the recording.
+    pub fn take(&self) -> Vec<LcRegWrite> {
+        self.writes.take()
+    }
+
+    /// Checks the register writes recorded so far match the `golden` sequence, and clears the
+    /// recording.
+    pub fn check(&self, golden: &[LcRegWrite]) -> Result<()> {
+        check_lc_writes(&self.take(), golden)
+    }
+}
+
+struct RecordingJtag<'a> {
+    inner: Box<dyn Jtag + 'a>,
+    writes: Rc<RefCell<Vec<LcRegWrite>>>,
+}
+
+impl Jtag for RecordingJtag<'_> {
+    fn into_raw(self: Box<Self>) -> Result<OpenOcd> {
+        self.inner.into_raw()
+    }
+
+    fn as_raw(&mut self) -> Result<&mut OpenOcd> {
+        self.inner.as_raw()
+    }
+
+    fn disconnect(self: Box<Self>) -> Result<()> {
+        self.inner.disconnect()
+    }
+
+    fn tap(&self) -> JtagTap {
+        self.inner.tap()
+    }
+
+    fn read_lc_ctrl_reg(&mut self, reg: &LcCtrlReg) -> Result<u32> {
+        self.inner.read_lc_ctrl_reg(reg)
+    }
+
+    fn write_lc_ctrl_reg(&mut self, reg: &LcCtrlReg, value: u32) -> Result<()> {
+        self.writes
+            .borrow_mut()
+            .push(LcRegWrite { reg: *reg, value });
+        self.inner.write_lc_ctrl_reg(reg, value)
+    }
+
+    fn read_memory(&mut self, addr: u32, buf: &mut [u8]) -> Result<usize> {
+        self.inner.read_memory(addr, buf)
+    }
+
+    fn read_memory32(&mut self, addr: u32, buf: &mut [u32]) -> Result<usize> {
+        self.inner.read_memory32(addr, buf)
+    }
+
+    fn write_memory(&mut self, addr: u32, buf: &[u8]) -> Result<()> {
+        self.inner.write_memory(addr, buf)
+    }
+
+    fn write_memory32(&mut self, addr: u32, buf: &[u32]) -> Result<()> {
+        self.inner.write_memory32(addr, buf)
+    }
+
+    fn halt(&mut self) -> Result<()> {
+        self.inner.halt()
+    }
+
+    fn wait_halt(&mut self, timeout: Duration) -> Result<()> {
+        self.inner.wait_halt(timeout)
+    }
+
+    fn resume(&mut self) -> Result<()> {
+        self.inner.resume()
+    }
+
+    fn resume_at(&mut self, addr: u32) -> Result<()> {
+        self.inner.resume_at(addr)
+    }
+
+    fn step(&mut self) -> Result<()> {
+        self.inner.step()
+    }
+
+    fn step_at(&mut self, addr: u32) -> Result<()> {
+        self.inner.step_at(addr)
+    }
+
+    fn reset(&mut self, run: bool) -> Result<()> {
+        self.inner.reset(run)
+    }
+
+    fn read_riscv_reg(&mut self, reg: &RiscvReg) -> Result<u32> {
+        self.inner.read_riscv_reg(reg)
+    }
+
+    fn write_riscv_reg(&mut self, reg: &RiscvReg, val: u32) -> Result<()> {
+        self.inner.write_riscv_reg(reg, val)
+    }
+
+    fn set_breakpoint(&mut self, addr: u32, hw: bool) -> Result<()> {
+        self.inner.set_breakpoint(addr, hw)
+    }
+
+    fn remove_breakpoint(&mut self, addr: u32) -> Result<()> {
+        self.inner.remove_breakpoint(addr)
+    }
+
+    fn remove_all_breakpoints(&mut self) -> Result<()> {
+        self.inner.remove_all_breakpoints()
+    }
+}
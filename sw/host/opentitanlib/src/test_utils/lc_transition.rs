@@ -3,9 +3,12 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::iter;
+use std::thread;
 use std::time::Duration;
 
-use anyhow::{bail, Context, Result};
+use anyhow::{Context, Result};
+use clap::Args;
+use humantime::parse_duration;
 use serde::{Deserialize, Serialize};
 use thiserror::Error;
 
@@ -16,6 +19,7 @@ use crate::dif::lc_ctrl::{
 };
 use crate::impl_serializable_error;
 use crate::io::jtag::{Jtag, JtagParams, JtagTap};
+use crate::test_utils::lc_sequence::TRANSITION_TOKEN_REGS;
 use crate::test_utils::poll;
 
 use top_earlgrey::top_earlgrey;
@@ -39,6 +43,12 @@ pub enum LcTransitionError {
     TransitionFailed(LcCtrlStatus),
     #[error("Bad post transition LC state: 0x{0:x}.")]
     BadPostTransitionState(u32),
+    #[error("Unexpected LC state 0x{actual:x} after {polls} reads (expected: 0x{expected:x}).")]
+    UnexpectedLcState {
+        expected: u32,
+        actual: u32,
+        polls: u32,
+    },
     #[error("Invalid LC state: {0:x}")]
     InvalidState(u32),
     #[error("Generic error {0}")]
@@ -46,10 +56,85 @@ pub enum LcTransitionError {
 }
 impl_serializable_error!(LcTransitionError);
 
+/// Default timeout of an LC transition, from its start to the `TRANSITION_SUCCESSFUL` status.
+pub const LC_TRANSITION_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// Longest delay between two reads of the LC state in [`verify_lc_state`].
+const MAX_LC_STATE_BACKOFF: Duration = Duration::from_secs(1);
+
+/// Options controlling how strictly [`verify_lc_state`] checks the LC state.
+///
+/// The LC state register can take a moment to settle after a reset, e.g. on slow FPGAs, so it
+/// is read again with an exponential backoff until it matches.
+#[derive(Clone, Debug, Args, Serialize, Deserialize)]
+pub struct LcStateCheck {
+    /// Number of reads of the LC state before failing its verification; 1 fails on the first
+    /// mismatching read.
+    #[arg(long, default_value = "10", value_parser = clap::value_parser!(u32).range(1..))]
+    pub lc_state_polls: u32,
+    /// Delay before the first re-read of the LC state, doubled after each read (up to 1s).
+    #[arg(long, value_parser = parse_duration, default_value = "50ms")]
+    pub lc_state_backoff: Duration,
+}
+
+impl Default for LcStateCheck {
+    fn default() -> Self {
+        Self {
+            lc_state_polls: 10,
+            lc_state_backoff: Duration::from_millis(50),
+        }
+    }
+}
+
+impl LcStateCheck {
+    /// Options failing on the first mismatching read.
+    pub fn strict() -> Self {
+        Self {
+            lc_state_polls: 1,
+            ..Default::default()
+        }
+    }
+}
+
+/// Checks the LC state is `expected`, reading it through the LC TAP as allowed by `check`.
+pub fn verify_lc_state(
+    jtag: &mut dyn Jtag,
+    expected: DifLcCtrlState,
+    check: &LcStateCheck,
+) -> Result<()> {
+    let expected = expected.redundant_encoding();
+    let mut backoff = check.lc_state_backoff;
+    let mut polls = 0;
+    loop {
+        let actual = jtag.read_lc_ctrl_reg(&LcCtrlReg::LcState)?;
+        polls += 1;
+        if actual == expected {
+            if polls > 1 {
+                log::info!("LC state 0x{actual:x} verified after {polls} reads.");
+            }
+            return Ok(());
+        }
+        if polls >= check.lc_state_polls {
+            return Err(LcTransitionError::UnexpectedLcState {
+                expected,
+                actual,
+                polls,
+            }
+            .into());
+        }
+        log::warn!("Unexpected LC state 0x{actual:x} (expected: 0x{expected:x}), reading again.");
+        thread::sleep(backoff);
+        backoff = (backoff * 2).min(MAX_LC_STATE_BACKOFF);
+    }
+}
+
+/// Prepares a transition following the sequence in hw/ip/lc_ctrl/doc/programmers_guide.md, which
+/// is modelled by [`crate::test_utils::lc_sequence::golden_transition_writes`].
 fn setup_lc_transition(
     jtag: &mut dyn Jtag,
     target_lc_state: DifLcCtrlState,
     token: Option<[u32; 4]>,
+    ctrl: LcCtrlTransitionCtrl,
 ) -> Result<()> {
     // Check the lc_ctrl is initialized and ready to accept a transition request.
     let status = jtag.read_lc_ctrl_reg(&LcCtrlReg::Status)?;
@@ -76,6 +161,9 @@ fn setup_lc_transition(
         return Err(LcTransitionError::FailedToClaimMutex.into());
     }
 
+    // Configure the external clock and volatile raw unlock.
+    jtag.write_lc_ctrl_reg(&LcCtrlReg::TransitionCtrl, ctrl.bits())?;
+
     // Program the target LC state.
     jtag.write_lc_ctrl_reg(
         &LcCtrlReg::TransitionTarget,
@@ -92,23 +180,53 @@ fn setup_lc_transition(
         );
     }
 
-    // If the transition requires a token, write it to the multi-register.
-    if let Some(token_words) = token {
-        let token_regs = [
-            &LcCtrlReg::TransitionToken0,
-            &LcCtrlReg::TransitionToken1,
-            &LcCtrlReg::TransitionToken2,
-            &LcCtrlReg::TransitionToken3,
-        ];
-
-        for (reg, value) in iter::zip(token_regs, token_words) {
-            jtag.write_lc_ctrl_reg(reg, value)?;
-        }
+    // Write the token to the multi-register. Unconditional transitions require a zero token.
+    for (reg, value) in iter::zip(TRANSITION_TOKEN_REGS, token.unwrap_or_default()) {
+        jtag.write_lc_ctrl_reg(reg, value)?;
     }
 
     Ok(())
 }
 
+fn transition_ctrl(use_external_clk: bool) -> LcCtrlTransitionCtrl {
+    if use_external_clk {
+        LcCtrlTransitionCtrl::EXT_CLOCK_EN
+    } else {
+        LcCtrlTransitionCtrl::empty()
+    }
+}
+
+/// Runs a transition through the LC TAP, up to the device entering the post transition state.
+fn run_lc_transition(
+    jtag: &mut dyn Jtag,
+    target_lc_state: DifLcCtrlState,
+    token: Option<[u32; 4]>,
+    use_external_clk: bool,
+    timeout: Duration,
+) -> Result<()> {
+    // Wait for the lc_ctrl to become initialized, claim the mutex, configure the external clock,
+    // and program the target state and token CSRs.
+    setup_lc_transition(
+        jtag,
+        target_lc_state,
+        token,
+        transition_ctrl(use_external_clk),
+    )?;
+
+    // Initiate LC transition and poll status register until transition is completed.
+    jtag.write_lc_ctrl_reg(&LcCtrlReg::TransitionCmd, LcCtrlTransitionCmd::START.bits())?;
+
+    wait_for_status(jtag, timeout, LcCtrlStatus::TRANSITION_SUCCESSFUL)
+        .context("failed waiting for TRANSITION_SUCCESSFUL status.")?;
+
+    // Check we have entered the post transition state.
+    let post_transition_lc_state = jtag.read_lc_ctrl_reg(&LcCtrlReg::LcState)?;
+    if post_transition_lc_state != DifLcCtrlState::PostTransition.redundant_encoding() {
+        return Err(LcTransitionError::BadPostTransitionState(post_transition_lc_state).into());
+    }
+    Ok(())
+}
+
 /// Perform a lifecycle transition through the JTAG interface to the LC CTRL.
 ///
 /// Requires the `jtag` to be already connected to the LC TAP.
@@ -161,42 +279,45 @@ fn setup_lc_transition(
 /// ```
 pub fn trigger_lc_transition(
     transport: &TransportWrapper,
-    mut jtag: Box<dyn Jtag + '_>,
+    jtag: Box<dyn Jtag + '_>,
     target_lc_state: DifLcCtrlState,
     token: Option<[u32; 4]>,
     use_external_clk: bool,
     reset_delay: Duration,
     reset_tap_straps: Option<JtagTap>,
 ) -> Result<()> {
-    // Wait for the lc_ctrl to become initialized, claim the mutex, and program the target state
-    // and token CSRs.
-    setup_lc_transition(&mut *jtag, target_lc_state, token)?;
-
-    // Configure external clock.
-    if use_external_clk {
-        jtag.write_lc_ctrl_reg(
-            &LcCtrlReg::TransitionCtrl,
-            LcCtrlTransitionCtrl::EXT_CLOCK_EN.bits(),
-        )?;
-    } else {
-        jtag.write_lc_ctrl_reg(&LcCtrlReg::TransitionCtrl, 0)?;
-    }
-
-    // Initiate LC transition and poll status register until transition is completed.
-    jtag.write_lc_ctrl_reg(&LcCtrlReg::TransitionCmd, LcCtrlTransitionCmd::START.bits())?;
-
-    wait_for_status(
-        &mut *jtag,
-        Duration::from_secs(3),
-        LcCtrlStatus::TRANSITION_SUCCESSFUL,
+    trigger_lc_transition_with_timeout(
+        transport,
+        jtag,
+        target_lc_state,
+        token,
+        use_external_clk,
+        reset_delay,
+        reset_tap_straps,
+        LC_TRANSITION_TIMEOUT,
     )
-    .context("failed waiting for TRANSITION_SUCCESSFUL status.")?;
+}
 
-    // Check we have entered the post transition state.
-    let post_transition_lc_state = jtag.read_lc_ctrl_reg(&LcCtrlReg::LcState)?;
-    if post_transition_lc_state != DifLcCtrlState::PostTransition.redundant_encoding() {
-        return Err(LcTransitionError::BadPostTransitionState(post_transition_lc_state).into());
-    }
+/// Performs a lifecycle transition as [`trigger_lc_transition`], waiting up to `timeout` for the
+/// transition to complete, e.g. longer on slow FPGAs.
+#[allow(clippy::too_many_arguments)]
+pub fn trigger_lc_transition_with_timeout(
+    transport: &TransportWrapper,
+    mut jtag: Box<dyn Jtag + '_>,
+    target_lc_state: DifLcCtrlState,
+    token: Option<[u32; 4]>,
+    use_external_clk: bool,
+    reset_delay: Duration,
+    reset_tap_straps: Option<JtagTap>,
+    timeout: Duration,
+) -> Result<()> {
+    run_lc_transition(
+        &mut *jtag,
+        target_lc_state,
+        token,
+        use_external_clk,
+        timeout,
+    )?;
 
     // Reset the chip, selecting the requested JTAG TAP if necessary
     jtag.disconnect()?;
@@ -234,16 +355,10 @@ pub fn trigger_volatile_raw_unlock<'t>(
     jtag_params: &JtagParams,
     expect_raw_unlock_supported: bool,
 ) -> Result<Box<dyn Jtag + 't>> {
-    // Wait for the lc_ctrl to become initialized, claim the mutex, and program the target state
-    // and token CSRs.
-    setup_lc_transition(&mut *jtag, target_lc_state, hashed_token)?;
-
-    // Configure external clock and set volatile raw unlock bit.
-    let mut ctrl = LcCtrlTransitionCtrl::VOLATILE_RAW_UNLOCK;
-    if use_external_clk {
-        ctrl |= LcCtrlTransitionCtrl::EXT_CLOCK_EN;
-    }
-    jtag.write_lc_ctrl_reg(&LcCtrlReg::TransitionCtrl, ctrl.bits())?;
+    // Wait for the lc_ctrl to become initialized, claim the mutex, set the volatile raw unlock
+    // bit, configure the external clock, and program the target state and token CSRs.
+    let ctrl = LcCtrlTransitionCtrl::VOLATILE_RAW_UNLOCK | transition_ctrl(use_external_clk);
+    setup_lc_transition(&mut *jtag, target_lc_state, hashed_token, ctrl)?;
 
     // Read back the volatile raw unlock bit to see if the feature is supported in the silicon.
     let read = jtag.read_lc_ctrl_reg(&LcCtrlReg::TransitionCtrl)?;
@@ -310,11 +425,239 @@ pub fn wait_for_status(jtag: &mut dyn Jtag, timeout: Duration, status: LcCtrlSta
 
         // Check for any error bits set - however, we exclude the status that
         // we are looking for in this comparison, since otherwise this
-        // function would just bail.
+        // function would just fail.
         if polled_status.intersects(LcCtrlStatus::ERRORS & !status) {
-            bail!("status {polled_status:#b} has error bits set");
+            return Err(LcTransitionError::TransitionFailed(polled_status).into());
         }
 
         Ok(polled_status.contains(status))
     })
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{HashMap, VecDeque};
+
+    use super::*;
+    use crate::debug::openocd::OpenOcd;
+    use crate::io::jtag::RiscvReg;
+    use crate::test_utils::lc_sequence::{golden_transition_writes, LcWriteRecorder};
+
+    /// Minimal model of the LC controller registers seen through the LC TAP.
+    #[derive(Default)]
+    struct FakeLcCtrl {
+        regs: HashMap<u32, u32>,
+        /// Values read from the LC state register before it settles to its `regs` value.
+        unsettled_lc_states: VecDeque<u32>,
+        /// Status once the transition started, if it does not succeed.
+        failed_status: Option<LcCtrlStatus>,
+    }
+
+    impl Jtag for FakeLcCtrl {
+        fn into_raw(self: Box<Self>) -> Result<OpenOcd> {
+            unimplemented!()
+        }
+        fn as_raw(&mut self) -> Result<&mut OpenOcd> {
+            unimplemented!()
+        }
+        fn disconnect(self: Box<Self>) -> Result<()> {
+            Ok(())
+        }
+        fn tap(&self) -> JtagTap {
+            JtagTap::LcTap
+        }
+        fn read_lc_ctrl_reg(&mut self, reg: &LcCtrlReg) -> Result<u32> {
+            let started = self
+                .regs
+                .contains_key(&LcCtrlReg::TransitionCmd.byte_offset());
+            Ok(match reg {
+                LcCtrlReg::Status if started && self.failed_status.is_some() => {
+                    self.failed_status.unwrap().bits()
+                }
+                LcCtrlReg::Status if started => {
+                    (LcCtrlStatus::INITIALIZED | LcCtrlStatus::TRANSITION_SUCCESSFUL).bits()
+                }
+                LcCtrlReg::Status => (LcCtrlStatus::INITIALIZED | LcCtrlStatus::READY).bits(),
+                LcCtrlReg::LcState if started => {
+                    DifLcCtrlState::PostTransition.redundant_encoding()
+                }
+                LcCtrlReg::LcState if !self.unsettled_lc_states.is_empty() => {
+                    self.unsettled_lc_states.pop_front().unwrap()
+                }
+                _ => *self.regs.get(&reg.byte_offset()).unwrap_or(&0),
+            })
+        }
+        fn write_lc_ctrl_reg(&mut self, reg: &LcCtrlReg, value: u32) -> Result<()> {
+            self.regs.insert(reg.byte_offset(), value);
+            Ok(())
+        }
+        fn read_memory(&mut self, _addr: u32, _buf: &mut [u8]) -> Result<usize> {
+            unimplemented!()
+        }
+        fn read_memory32(&mut self, _addr: u32, _buf: &mut [u32]) -> Result<usize> {
+            unimplemented!()
+        }
+        fn write_memory(&mut self, _addr: u32, _buf: &[u8]) -> Result<()> {
+            unimplemented!()
+        }
+        fn write_memory32(&mut self, _addr: u32, _buf: &[u32]) -> Result<()> {
+            unimplemented!()
+        }
+        fn halt(&mut self) -> Result<()> {
+            unimplemented!()
+        }
+        fn wait_halt(&mut self, _timeout: Duration) -> Result<()> {
+            unimplemented!()
+        }
+        fn resume(&mut self) -> Result<()> {
+            unimplemented!()
+        }
+        fn resume_at(&mut self, _addr: u32) -> Result<()> {
+            unimplemented!()
+        }
+        fn step(&mut self) -> Result<()> {
+            unimplemented!()
+        }
+        fn step_at(&mut self, _addr: u32) -> Result<()> {
+            unimplemented!()
+        }
+        fn reset(&mut self, _run: bool) -> Result<()> {
+            unimplemented!()
+        }
+        fn read_riscv_reg(&mut self, _reg: &RiscvReg) -> Result<u32> {
+            unimplemented!()
+        }
+        fn write_riscv_reg(&mut self, _reg: &RiscvReg, _val: u32) -> Result<()> {
+            unimplemented!()
+        }
+        fn set_breakpoint(&mut self, _addr: u32, _hw: bool) -> Result<()> {
+            unimplemented!()
+        }
+        fn remove_breakpoint(&mut self, _addr: u32) -> Result<()> {
+            unimplemented!()
+        }
+        fn remove_all_breakpoints(&mut self) -> Result<()> {
+            unimplemented!()
+        }
+    }
+
+    fn check_transition(
+        target: DifLcCtrlState,
+        token: Option<[u32; 4]>,
+        use_external_clk: bool,
+    ) -> Result<()> {
+        let recorder = LcWriteRecorder::new();
+        let mut jtag = recorder.wrap(Box::<FakeLcCtrl>::default());
+        run_lc_transition(
+            &mut *jtag,
+            target,
+            token,
+            use_external_clk,
+            LC_TRANSITION_TIMEOUT,
+        )?;
+        recorder.check(&golden_transition_writes(
+            target,
+            token,
+            transition_ctrl(use_external_clk),
+        ))
+    }
+
+    #[test]
+    fn test_conditional_transition_matches_golden() -> Result<()> {
+        let token = [0x11111111, 0x22222222, 0x33333333, 0x44444444];
+        check_transition(DifLcCtrlState::TestUnlocked1, Some(token), true)?;
+        check_transition(DifLcCtrlState::Prod, Some(token), false)
+    }
+
+    #[test]
+    fn test_unconditional_transition_matches_golden() -> Result<()> {
+        check_transition(DifLcCtrlState::Scrap, None, false)
+    }
+
+    #[test]
+    fn test_deviation_is_reported() -> Result<()> {
+        let recorder = LcWriteRecorder::new();
+        let mut jtag = recorder.wrap(Box::<FakeLcCtrl>::default());
+        run_lc_transition(
+            &mut *jtag,
+            DifLcCtrlState::Prod,
+            None,
+            true,
+            LC_TRANSITION_TIMEOUT,
+        )?;
+        // The golden sequence does not enable the external clock.
+        let golden =
+            golden_transition_writes(DifLcCtrlState::Prod, None, LcCtrlTransitionCtrl::empty());
+        assert!(recorder.check(&golden).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_token_error_is_reported() {
+        let mut lc_ctrl = FakeLcCtrl {
+            failed_status: Some(LcCtrlStatus::INITIALIZED | LcCtrlStatus::TOKEN_ERROR),
+            ..Default::default()
+        };
+        let err = run_lc_transition(
+            &mut lc_ctrl,
+            DifLcCtrlState::Prod,
+            Some([0x11111111; 4]),
+            false,
+            LC_TRANSITION_TIMEOUT,
+        )
+        .unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LcTransitionError>(),
+            Some(LcTransitionError::TransitionFailed(status))
+                if status.contains(LcCtrlStatus::TOKEN_ERROR)
+        ));
+    }
+
+    fn settling_lc_ctrl(state: DifLcCtrlState, unsettled_reads: usize) -> FakeLcCtrl {
+        let mut lc_ctrl = FakeLcCtrl::default();
+        lc_ctrl
+            .regs
+            .insert(LcCtrlReg::LcState.byte_offset(), state.redundant_encoding());
+        lc_ctrl.unsettled_lc_states =
+            vec![DifLcCtrlState::PostTransition.redundant_encoding(); unsettled_reads].into();
+        lc_ctrl
+    }
+
+    #[test]
+    fn test_verify_lc_state_retries() -> Result<()> {
+        let check = LcStateCheck {
+            lc_state_polls: 3,
+            lc_state_backoff: Duration::ZERO,
+        };
+        let mut lc_ctrl = settling_lc_ctrl(DifLcCtrlState::TestUnlocked0, 2);
+        verify_lc_state(&mut lc_ctrl, DifLcCtrlState::TestUnlocked0, &check)
+    }
+
+    #[test]
+    fn test_verify_lc_state_fails() {
+        let check = LcStateCheck {
+            lc_state_polls: 3,
+            lc_state_backoff: Duration::ZERO,
+        };
+        let mut lc_ctrl = settling_lc_ctrl(DifLcCtrlState::TestUnlocked0, 3);
+        let err = verify_lc_state(&mut lc_ctrl, DifLcCtrlState::TestUnlocked0, &check).unwrap_err();
+        assert!(matches!(
+            err.downcast_ref::<LcTransitionError>(),
+            Some(LcTransitionError::UnexpectedLcState { polls: 3, .. })
+        ));
+        // A settled but different state is not accepted either.
+        let mut lc_ctrl = settling_lc_ctrl(DifLcCtrlState::TestLocked0, 0);
+        assert!(verify_lc_state(&mut lc_ctrl, DifLcCtrlState::TestUnlocked0, &check).is_err());
+    }
+
+    #[test]
+    fn test_verify_lc_state_strict() {
+        let mut lc_ctrl = settling_lc_ctrl(DifLcCtrlState::TestUnlocked0, 1);
+        assert!(verify_lc_state(
+            &mut lc_ctrl,
+            DifLcCtrlState::TestUnlocked0,
+            &LcStateCheck::strict()
+        )
+        .is_err());
+    }
+}
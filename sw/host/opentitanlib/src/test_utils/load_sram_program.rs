@@ -5,7 +5,7 @@
 use std::fs;
 use std::path::PathBuf;
 use std::str::FromStr;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::{ensure, Context, Result};
 use bindgen::sram_program::{SRAM_MAGIC_SP_CRC_ERROR, SRAM_MAGIC_SP_EXECUTION_DONE};
@@ -39,6 +39,23 @@ pub struct SramProgramParams {
     pub load_addr: Option<u32>,
 }
 
+/// Command-line parameters of the JTAG clock ramping of SRAM program loads.
+///
+/// Control operations (halting the core, ePMP and register setup) run at the clock configured for
+/// the JTAG adapter, and only the bulk transfer of the program runs at the faster clock. If the
+/// program reports a CRC mismatch of the data transferred at the faster clock, it is reloaded at
+/// the adapter clock.
+#[derive(Debug, Args, Clone, Default)]
+pub struct JtagClockRamp {
+    /// JTAG clock used to transfer SRAM programs, in kHz (default: the adapter clock).
+    #[arg(long)]
+    pub sram_load_speed_khz: Option<u64>,
+
+    /// Time to wait for the CRC self-check of an SRAM program transferred at the faster clock.
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "100ms")]
+    pub sram_crc_check_timeout: Duration,
+}
+
 /// Describe a file to load to SRAM.
 #[derive(Debug, Clone)]
 pub enum SramProgramFile {
@@ -77,6 +94,17 @@ impl SramProgramParams {
     ) -> Result<ExecutionResult> {
         load_and_execute_sram_program(jtag, &self.get_file(), exec_mode)
     }
+
+    /// Loads and jumps to the SRAM program, ramping the JTAG clock up from
+    /// `control_speed_khz` for the transfer of the program as configured by `ramp`.
+    pub fn load_and_jump_ramped(
+        &self,
+        jtag: &mut dyn Jtag,
+        control_speed_khz: u64,
+        ramp: &JtagClockRamp,
+    ) -> Result<ExecutionResult> {
+        load_and_jump_sram_program_ramped(jtag, &self.get_file(), control_speed_khz, ramp)
+    }
 }
 
 /// Execution mode for a SRAM program.
@@ -444,3 +472,60 @@ pub fn load_and_execute_sram_program(
     let prog_info = load_sram_program(jtag, file)?;
     execute_sram_program(jtag, &prog_info, exec_mode)
 }
+
+fn set_adapter_speed(jtag: &mut dyn Jtag, speed_khz: u64) -> Result<()> {
+    jtag.as_raw()?
+        .execute(&format!("adapter speed {speed_khz}"))?;
+    Ok(())
+}
+
+/// Waits for the CRC self-check of a running SRAM program, and returns whether it failed.
+fn sram_crc_check_failed(jtag: &mut dyn Jtag, timeout: Duration) -> Result<bool> {
+    // The program halts on a CRC mismatch; a program still running passed the check.
+    if jtag.wait_halt(timeout).is_err() {
+        return Ok(false);
+    }
+    let sp = jtag.read_riscv_reg(&RiscvReg::Gpr(RiscvGpr::SP))?;
+    if sp == SRAM_MAGIC_SP_CRC_ERROR {
+        return Ok(true);
+    }
+    jtag.resume()?;
+    Ok(false)
+}
+
+/// Loads and jumps to a SRAM program, transferring it at the JTAG clock of `ramp`, and falling
+/// back to loading it at `control_speed_khz` if the transfer fails.
+pub fn load_and_jump_sram_program_ramped(
+    jtag: &mut dyn Jtag,
+    file: &SramProgramFile,
+    control_speed_khz: u64,
+    ramp: &JtagClockRamp,
+) -> Result<ExecutionResult> {
+    let Some(load_speed_khz) = ramp
+        .sram_load_speed_khz
+        .filter(|&speed| speed > control_speed_khz)
+    else {
+        return load_and_execute_sram_program(jtag, file, ExecutionMode::Jump);
+    };
+
+    let t0 = Instant::now();
+    log::info!("Transferring SRAM program at {load_speed_khz} kHz.");
+    set_adapter_speed(jtag, load_speed_khz)?;
+    let loaded = load_sram_program(jtag, file);
+    set_adapter_speed(jtag, control_speed_khz)?;
+    match loaded {
+        Ok(prog_info) => {
+            execute_sram_program(jtag, &prog_info, ExecutionMode::Jump)?;
+            if !sram_crc_check_failed(jtag, ramp.sram_crc_check_timeout)? {
+                log::info!("SRAM program transferred in {:?}.", t0.elapsed());
+                return Ok(ExecutionResult::Executing);
+            }
+            log::warn!("SRAM program CRC mismatch after transfer at {load_speed_khz} kHz.");
+        }
+        Err(e) => log::warn!("SRAM program transfer at {load_speed_khz} kHz failed: {e:#}"),
+    }
+
+    // The core is halted, either by the loader or by the CRC self-check.
+    log::warn!("Reloading SRAM program at {control_speed_khz} kHz.");
+    load_and_execute_sram_program(jtag, file, ExecutionMode::Jump)
+}
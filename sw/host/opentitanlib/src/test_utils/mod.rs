@@ -15,6 +15,7 @@ pub mod gpio_monitor;
 pub mod i2c_target;
 pub mod init;
 pub mod lc;
+pub mod lc_sequence;
 pub mod lc_transition;
 pub mod load_bitstream;
 pub mod load_sram_program;
@@ -26,6 +27,7 @@ pub mod otp_ctrl;
 #[cfg(not(feature = "english_breakfast"))]
 pub mod pinmux_config;
 pub mod poll;
+pub mod rollback;
 pub mod rpc;
 pub mod spi_passthru;
 pub mod status;
@@ -0,0 +1,80 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{Context, Result};
+use std::path::PathBuf;
+
+use crate::app::TransportWrapper;
+use crate::test_utils::load_bitstream::LoadBitstream;
+use crate::transport::common::fpga::ClearBitstream;
+
+type Stage<'a> = Box<dyn Fn(&TransportWrapper) -> Result<()> + 'a>;
+
+/// Test-only rollback of the OTP and flash state of an FPGA-emulated device.
+///
+/// On FPGA targets, OTP and flash are backed by BRAM initialized from the bitstream, so
+/// reloading the bitstream reverts both to their initial contents. A checkpoint is the
+/// bitstream plus the (deterministic) stages run on the device since it was loaded: restoring a
+/// checkpoint reloads the bitstream and replays those stages, which brings the device back to
+/// the state it was in right before the destructive step under test.
+///
+/// This is only meaningful on FPGA: on silicon, OTP writes and LC transitions are permanent.
+pub struct FpgaRollback<'a> {
+    load_bitstream: &'a LoadBitstream,
+    bitstream: PathBuf,
+    stages: Vec<(String, Stage<'a>)>,
+}
+
+impl<'a> FpgaRollback<'a> {
+    /// Creates a rollback checkpoint at the initial state of the bitstream in `load_bitstream`.
+    pub fn new(load_bitstream: &'a LoadBitstream) -> Result<Self> {
+        let bitstream = load_bitstream
+            .bitstream
+            .clone()
+            .context("FPGA rollback requires a bitstream")?;
+        Ok(Self {
+            load_bitstream,
+            bitstream,
+            stages: Vec::new(),
+        })
+    }
+
+    /// Runs `stage` and records it for replay, moving the checkpoint past it.
+    ///
+    /// Stages must be deterministic: replaying them on a freshly loaded bitstream must produce
+    /// the same OTP and flash contents.
+    pub fn run_stage(
+        &mut self,
+        transport: &TransportWrapper,
+        name: &str,
+        stage: impl Fn(&TransportWrapper) -> Result<()> + 'a,
+    ) -> Result<()> {
+        log::info!("Running stage {name}.");
+        stage(transport).with_context(|| format!("stage {name} failed"))?;
+        self.stages.push((name.into(), Box::new(stage)));
+        Ok(())
+    }
+
+    /// Returns the names of the stages recorded in the checkpoint, in order.
+    pub fn stages(&self) -> impl Iterator<Item = &str> {
+        self.stages.iter().map(|(name, _)| name.as_str())
+    }
+
+    /// Reverts the device to the checkpoint, discarding all changes made after it.
+    pub fn restore(&self, transport: &TransportWrapper) -> Result<()> {
+        log::info!(
+            "Rolling back to checkpoint after {} stage(s).",
+            self.stages.len()
+        );
+        // Programming the same bitstream again is skipped unless it is cleared first.
+        transport.dispatch(&ClearBitstream)?;
+        self.load_bitstream.load(transport, &self.bitstream)?;
+        transport.apply_default_configuration(None)?;
+        for (name, stage) in &self.stages {
+            log::info!("Replaying stage {name}.");
+            stage(transport).with_context(|| format!("replay of stage {name} failed"))?;
+        }
+        Ok(())
+    }
+}
@@ -3,11 +3,11 @@
 // SPDX-License-Identifier: Apache-2.0
 use anyhow::{anyhow, Result};
 use crc::{Crc, CRC_32_ISO_HDLC};
-use regex::Regex;
+use regex::{Captures, Regex};
 use serde::de::DeserializeOwned;
 use serde::Serialize;
 use std::io::Write;
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use crate::io::console::{ConsoleDevice, ConsoleError};
 use crate::test_utils::status::Status;
@@ -54,6 +54,46 @@ where
     fn recv(device: &T, timeout: Duration, quiet: bool) -> Result<Self>
     where
         Self: Sized;
+
+    /// Like `recv`, but skips the responses that are not a `Self` within the `timeout` window,
+    /// e.g. responses interleaved by the firmware between a command and its own response.
+    ///
+    /// A `RESP_ERR` status still fails the reception.
+    fn recv_window(device: &T, timeout: Duration, quiet: bool) -> Result<Self>
+    where
+        Self: Sized;
+}
+
+/// A response frame found in the console output.
+enum Frame<U> {
+    /// A valid `RESP_OK` frame of the expected type.
+    Ok(U),
+    /// A valid `RESP_ERR` frame.
+    Err(Status),
+    /// A frame that is not a response of the expected type, and why.
+    Skip(String),
+}
+
+impl<U: DeserializeOwned> Frame<U> {
+    const RX: &'static str = r"RESP_(OK|ERR):(.*) CRC:([0-9]+)\n";
+
+    /// Decodes the `Frame::RX` frame captured in `cap`.
+    fn decode(cap: &Captures) -> Self {
+        let json_str = &cap[2];
+        if let Err(e) = check_crc(json_str, &cap[3]) {
+            return Frame::Skip(format!("{e}: {json_str}"));
+        }
+        if &cap[1] == "ERR" {
+            return match serde_json::from_str::<Status>(json_str) {
+                Ok(status) => Frame::Err(status),
+                Err(e) => Frame::Skip(format!("{e}: {json_str}")),
+            };
+        }
+        match serde_json::from_str::<U>(json_str) {
+            Ok(value) => Frame::Ok(value),
+            Err(e) => Frame::Skip(format!("{e}: {json_str}")),
+        }
+    }
 }
 
 impl<T, U> ConsoleRecv<T> for U
@@ -106,6 +146,67 @@ where
             _ => Err(anyhow!("Impossible result: {:?}", result)),
         }
     }
+
+    fn recv_window(device: &T, timeout: Duration, quiet: bool) -> Result<Self>
+    where
+        Self: Sized,
+    {
+        let deadline = Instant::now() + timeout;
+        let rx = Regex::new(Frame::<Self>::RX)?;
+        let mut console = UartConsole {
+            timestamp: true,
+            newline: true,
+            exit_success: Some(rx.clone()),
+            ..Default::default()
+        };
+        let mut stdout = std::io::stdout();
+        let mut skipped = Vec::new();
+        loop {
+            // Consume the frames received so far, in order.
+            while let Some(cap) = rx.captures(&console.buffer) {
+                let end = cap.get(0).expect("frame").end();
+                let frame = Frame::<Self>::decode(&cap);
+                console.buffer.drain(..end);
+                match frame {
+                    Frame::Ok(value) => {
+                        println!();
+                        return Ok(value);
+                    }
+                    Frame::Err(status) => {
+                        println!();
+                        return Err(status.into());
+                    }
+                    Frame::Skip(reason) => {
+                        log::warn!("Skipping unexpected response: {reason}");
+                        skipped.push(reason);
+                    }
+                }
+            }
+
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            console.timeout = Some(remaining);
+            let out = if !quiet {
+                let w: &mut dyn Write = &mut stdout;
+                Some(w)
+            } else {
+                None
+            };
+            match console.interact(device, None, out)? {
+                ExitStatus::ExitSuccess => {}
+                ExitStatus::Timeout => break,
+                result => return Err(anyhow!("Impossible result: {:?}", result)),
+            }
+        }
+        println!();
+        Err(ConsoleError::GenericError(format!(
+            "Timed Out, after skipping {} unexpected response(s)",
+            skipped.len()
+        ))
+        .into())
+    }
 }
 
 fn check_crc(json_str: &str, crc_str: &str) -> Result<()> {
@@ -118,3 +219,58 @@ fn check_crc(json_str: &str, crc_str: &str) -> Result<()> {
     }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Point {
+        x: u32,
+        y: u32,
+    }
+
+    fn frame(kind: &str, json: &str) -> String {
+        let crc = Crc::<u32>::new(&CRC_32_ISO_HDLC).checksum(json.as_bytes());
+        format!("RESP_{kind}:{json} CRC:{crc}\n")
+    }
+
+    fn decode(output: &str) -> Vec<Frame<Point>> {
+        let rx = Regex::new(Frame::<Point>::RX).unwrap();
+        rx.captures_iter(output)
+            .map(|c| Frame::decode(&c))
+            .collect()
+    }
+
+    #[test]
+    fn test_decode_expected_frame() {
+        let frames = decode(&frame("OK", r#"{"x":1,"y":2}"#));
+        assert!(matches!(frames[..], [Frame::Ok(Point { x: 1, y: 2 })]));
+    }
+
+    #[test]
+    fn test_skip_interleaved_frames() {
+        let output = [
+            "I00001 ft_personalize.c:100] Some log line\n".to_string(),
+            frame("OK", r#"{"data":[1,2,3]}"#),
+            "RESP_OK:{\"x\":3,\"y\":4} CRC:1\n".to_string(),
+            frame("OK", r#"{"x":5,"y":6}"#),
+        ]
+        .concat();
+        let frames = decode(&output);
+        assert_eq!(frames.len(), 3);
+        assert!(matches!(frames[0], Frame::Skip(_)));
+        assert!(matches!(frames[1], Frame::Skip(_)));
+        assert!(matches!(frames[2], Frame::Ok(Point { x: 5, y: 6 })));
+    }
+
+    #[test]
+    fn test_decode_error_status() {
+        let frames = decode(&frame("ERR", r#"{"Internal":["abc",10]}"#));
+        assert!(matches!(
+            &frames[..],
+            [Frame::Err(Status::Internal(module, 10))] if module == "abc"
+        ));
+    }
+}
@@ -15,7 +15,7 @@ use crate::io::gpio::GpioPin;
 use crate::io::spi::Target;
 use crate::io::uart::{Uart, UartError};
 use crate::transport::common::fpga::{ClearBitstream, FpgaProgram};
-use crate::transport::common::uart::SerialPortUart;
+use crate::transport::common::uart::{compare_port_names, SerialPortUart};
 use crate::transport::{
     Capabilities, Capability, Transport, TransportError, TransportInterfaceType,
 };
@@ -74,7 +74,7 @@ impl<B: Board> ChipWhisperer<B> {
             });
             // The CW board seems to have the last port connected as OpenTitan UART 0.
             // Reverse the sort order so the last port will be instance 0.
-            ports.sort_by(|a, b| b.port_name.cmp(&a.port_name));
+            ports.sort_by(|a, b| compare_port_names(&b.port_name, &a.port_name));
 
             let port = ports.get(instance as usize).ok_or_else(|| {
                 TransportError::InvalidInstance(TransportInterfaceType::Uart, instance.to_string())
@@ -7,7 +7,7 @@ use std::time::Duration;
 
 use crate::app::TransportWrapper;
 use crate::io::gpio::GpioPin;
-use crate::io::uart::Uart;
+use crate::io::uart::{Uart, UART_QUIET_TIME};
 use crate::transport::ProgressIndicator;
 use crate::util::rom_detect::RomDetect;
 
@@ -33,7 +33,7 @@ impl FpgaProgram<'_> {
         reset_pin.write(false)?;
         std::thread::sleep(self.rom_reset_pulse);
         // Also clear the UART RX buffer for improved robustness.
-        uart.clear_rx_buffer()?;
+        uart.drain_until_quiet(UART_QUIET_TIME)?;
         reset_pin.write(true)?;
 
         // Now read the uart until the ROM prints it's version.
@@ -3,24 +3,40 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::cell::{Cell, RefCell};
+use std::cmp::Ordering;
 use std::collections::VecDeque;
 use std::io::{ErrorKind, Read, Write};
+#[cfg(unix)]
 use std::os::fd::{AsRawFd, BorrowedFd};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use anyhow::{Context, Result};
-use serialport::{ClearBuffer, Parity, SerialPort, TTYPort};
+#[cfg(windows)]
+use serialport::COMPort;
+#[cfg(unix)]
+use serialport::TTYPort;
+use serialport::{ClearBuffer, Parity, SerialPort, StopBits};
 
 //use crate::io::uart::{Uart, UartError};
 use crate::io::uart::{FlowControl, Uart, UartError};
+#[cfg(unix)]
 use crate::transport::TransportError;
+#[cfg(unix)]
 use crate::util;
 
-/// Implementation of the `Uart` trait on top of a serial device, such as `/dev/ttyUSB0`.
+/// Serial port type of the host OS: a tty on Unix, a COM port on Windows.
+#[cfg(unix)]
+pub type NativePort = TTYPort;
+/// Serial port type of the host OS: a tty on Unix, a COM port on Windows.
+#[cfg(windows)]
+pub type NativePort = COMPort;
+
+/// Implementation of the `Uart` trait on top of a serial device, such as `/dev/ttyUSB0` or
+/// `COM3`.
 pub struct SerialPortUart {
     port_name: String,
     flow_control: Cell<FlowControl>,
-    port: RefCell<TTYPort>,
+    port: RefCell<NativePort>,
     rxbuf: RefCell<VecDeque<u8>>,
     pseudo_baud: Cell<u32>,
 }
@@ -33,11 +49,12 @@ impl SerialPortUart {
     // longer than any invocation of this program.
     const FOREVER: Duration = Duration::from_secs(100 * 365 * 86400);
 
-    /// Open the given serial device, such as `/dev/ttyUSB0`.
+    /// Time a blocked write waits for the device to drain its buffers.
+    const WRITE_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// Open the given serial device, such as `/dev/ttyUSB0` or `COM3`.
     pub fn open(port_name: &str, baud: u32) -> Result<Self> {
-        let port = TTYPort::open(&serialport::new(port_name, baud).preserve_dtr_on_open())
-            .map_err(|e| UartError::OpenError(e.to_string()))?;
-        flock_serial(&port, port_name)?;
+        let port = open_native(port_name, baud)?;
         Ok(SerialPortUart {
             port_name: port_name.to_string(),
             flow_control: Cell::new(FlowControl::None),
@@ -49,9 +66,7 @@ impl SerialPortUart {
 
     /// Open a pseudo port (e.g. a verilator pts device).
     pub fn open_pseudo(port_name: &str, baud: u32) -> Result<Self> {
-        let port = TTYPort::open(&serialport::new(port_name, baud).preserve_dtr_on_open())
-            .map_err(|e| UartError::OpenError(e.to_string()))?;
-        flock_serial(&port, port_name)?;
+        let port = open_native(port_name, baud)?;
         Ok(SerialPortUart {
             port_name: port_name.to_string(),
             flow_control: Cell::new(FlowControl::None),
@@ -177,19 +192,15 @@ impl Uart for SerialPortUart {
             // file descriptor into non-blocking mode.
             let mut port = self.port.borrow_mut();
             let mut idx = 0;
+            let mut deadline = Instant::now() + Self::WRITE_TIMEOUT;
             while idx < buf.len() {
                 match port.write(&buf[idx..]) {
-                    Ok(n) => idx += n,
+                    Ok(n) => {
+                        idx += n;
+                        deadline = Instant::now() + Self::WRITE_TIMEOUT;
+                    }
                     Err(ioerr) if ioerr.kind() == ErrorKind::TimedOut => {
-                        // Buffers are full, file descriptor is non-blocking.  Explicitly wait for
-                        // this one file descriptor to again become ready for writing.  Since this
-                        // is a UART, we know that it will become ready in bounded time.
-                        util::file::wait_timeout(
-                            // SAFETY: The file descriptor is owned by `port` and is valid.
-                            unsafe { BorrowedFd::borrow_raw(port.as_raw_fd()) },
-                            rustix::event::PollFlags::OUT,
-                            Duration::from_secs(5),
-                        )?;
+                        wait_writable(&port, deadline)?;
                     }
                     Err(ioerr) => return Err(ioerr).context("UART communication error"),
                 }
@@ -235,6 +246,15 @@ impl Uart for SerialPortUart {
         Ok(())
     }
 
+    fn set_stop_bits(&self, stop_bits: StopBits) -> Result<()> {
+        self.port.borrow_mut().set_stop_bits(stop_bits)?;
+        Ok(())
+    }
+
+    fn get_stop_bits(&self) -> Result<StopBits> {
+        Ok(self.port.borrow().stop_bits()?)
+    }
+
     /// Clears the UART RX buffer.
     fn clear_rx_buffer(&self) -> Result<()> {
         // Clear the host input buffer.
@@ -255,10 +275,12 @@ impl Uart for SerialPortUart {
         Ok(())
     }
 
+    /// COM ports can't be registered with `mio`, their consoles poll the port instead.
     fn supports_nonblocking_read(&self) -> Result<bool> {
-        Ok(true)
+        Ok(cfg!(unix))
     }
 
+    #[cfg(unix)]
     fn register_nonblocking_read(&self, registry: &mio::Registry, token: mio::Token) -> Result<()> {
         let port: &mut TTYPort = &mut self.port.borrow_mut();
         registry.register(
@@ -270,8 +292,49 @@ impl Uart for SerialPortUart {
     }
 }
 
+/// Opens the serial device `port_name`, and locks it for the lifetime of the port.
+fn open_native(port_name: &str, baud: u32) -> Result<NativePort> {
+    let port = serialport::new(port_name, baud)
+        .preserve_dtr_on_open()
+        .open_native()
+        .map_err(|e| UartError::OpenError(e.to_string()))?;
+    flock_serial(&port, port_name)?;
+    Ok(port)
+}
+
+/// Waits for the buffers of `port` to drain, until `deadline`.
+#[cfg(unix)]
+fn wait_writable(port: &NativePort, deadline: Instant) -> Result<()> {
+    // Buffers are full, file descriptor is non-blocking.  Explicitly wait for this one file
+    // descriptor to again become ready for writing.  Since this is a UART, we know that it will
+    // become ready in bounded time.
+    util::file::wait_timeout(
+        // SAFETY: The file descriptor is owned by `port` and is valid.
+        unsafe { BorrowedFd::borrow_raw(port.as_raw_fd()) },
+        rustix::event::PollFlags::OUT,
+        deadline.saturating_duration_since(Instant::now()),
+    )
+}
+
+/// Waits for the buffers of `port` to drain, until `deadline`.
+#[cfg(windows)]
+fn wait_writable(_port: &NativePort, deadline: Instant) -> Result<()> {
+    // A COM port write times out once the device stops draining its buffers; there is no
+    // readiness to wait for, so retry until the deadline.
+    anyhow::ensure!(
+        Instant::now() < deadline,
+        std::io::Error::new(
+            ErrorKind::TimedOut,
+            "timed out waiting for the UART to drain"
+        )
+    );
+    std::thread::sleep(Duration::from_millis(1));
+    Ok(())
+}
+
 /// Invoke Linux `flock()` on the given serial port, lock will be released when the file
 /// descriptor is closed (or when the process terminates).
+#[cfg(unix)]
 pub fn flock_serial(port: &TTYPort, port_name: &str) -> Result<()> {
     // SAFETY: `fd` is owned by `port` and is valid.
     let fd = unsafe { BorrowedFd::borrow_raw(port.as_raw_fd()) };
@@ -280,3 +343,51 @@ pub fn flock_serial(port: &TTYPort, port_name: &str) -> Result<()> {
     })?;
     Ok(())
 }
+
+/// COM ports are opened for exclusive access, so a port held by another process fails to open
+/// instead, with an access denied error.
+#[cfg(windows)]
+pub fn flock_serial(_port: &COMPort, _port_name: &str) -> Result<()> {
+    Ok(())
+}
+
+/// Orders serial port names by their prefix, then by their number, so that e.g. `COM10` comes
+/// after `COM9` and `/dev/ttyACM10` after `/dev/ttyACM9`.
+pub fn compare_port_names(a: &str, b: &str) -> Ordering {
+    fn split(name: &str) -> (&str, Option<u64>) {
+        let prefix = name.trim_end_matches(|c: char| c.is_ascii_digit());
+        (prefix, name[prefix.len()..].parse().ok())
+    }
+    split(a).cmp(&split(b)).then_with(|| a.cmp(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compare_port_names() {
+        let mut names = [
+            "COM10",
+            "COM9",
+            "COM1",
+            "/dev/ttyACM10",
+            "/dev/ttyACM2",
+            "COM",
+        ];
+        names.sort_by(|a, b| compare_port_names(a, b));
+        assert_eq!(
+            names,
+            [
+                "/dev/ttyACM2",
+                "/dev/ttyACM10",
+                "COM",
+                "COM1",
+                "COM9",
+                "COM10"
+            ]
+        );
+        // Leading zeros don't make names equal.
+        assert_eq!(compare_port_names("COM01", "COM1"), Ordering::Less);
+    }
+}
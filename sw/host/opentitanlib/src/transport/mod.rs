@@ -26,6 +26,7 @@ pub mod ftdi;
 pub mod hyperdebug;
 pub mod ioexpander;
 pub mod proxy;
+pub mod qemu;
 pub mod ti50emulator;
 pub mod verilator;
 
@@ -10,7 +10,7 @@ use std::time::Duration;
 
 use super::ProxyError;
 use crate::io::nonblocking_help::NonblockingHelp;
-use crate::io::uart::{FlowControl, Parity, Uart};
+use crate::io::uart::{FlowControl, Parity, StopBits, Uart};
 use crate::proxy::protocol::{Request, Response, UartRequest, UartResponse};
 use crate::transport::proxy::{Inner, Proxy};
 
@@ -101,6 +101,20 @@ impl Uart for ProxyUart {
         }
     }
 
+    fn get_stop_bits(&self) -> Result<StopBits> {
+        match self.execute_command(UartRequest::GetStopBits)? {
+            UartResponse::GetStopBits { stop_bits } => Ok(stop_bits),
+            _ => bail!(ProxyError::UnexpectedReply()),
+        }
+    }
+
+    fn set_stop_bits(&self, stop_bits: StopBits) -> Result<()> {
+        match self.execute_command(UartRequest::SetStopBits(stop_bits))? {
+            UartResponse::SetStopBits => Ok(()),
+            _ => bail!(ProxyError::UnexpectedReply()),
+        }
+    }
+
     fn get_flow_control(&self) -> Result<FlowControl> {
         match self.execute_command(UartRequest::GetFlowControl)? {
             UartResponse::GetFlowControl { flow_control } => Ok(flow_control),
@@ -0,0 +1,90 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::Result;
+use std::cell::Cell;
+use std::rc::Rc;
+
+use crate::io::gpio::{GpioPin, PinMode, PullMode};
+use crate::transport::qemu::Inner;
+
+/// A pin which is not connected to the emulated chip, only latching the level written to it.
+pub struct QemuGpioPin {
+    level: Cell<bool>,
+}
+
+impl Default for QemuGpioPin {
+    fn default() -> Self {
+        QemuGpioPin {
+            level: Cell::new(true),
+        }
+    }
+}
+
+impl GpioPin for QemuGpioPin {
+    fn read(&self) -> Result<bool> {
+        Ok(self.level.get())
+    }
+
+    fn write(&self, value: bool) -> Result<()> {
+        self.level.set(value);
+        Ok(())
+    }
+
+    fn set_mode(&self, _mode: PinMode) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_pull_mode(&self, _mode: PullMode) -> Result<()> {
+        Ok(())
+    }
+}
+
+/// The active low reset pin: the emulated chip is stopped while the pin is low, and reset when
+/// the pin is released.
+pub struct QemuResetPin {
+    inner: Rc<Inner>,
+    level: Cell<bool>,
+}
+
+impl QemuResetPin {
+    pub fn new(inner: &Rc<Inner>) -> Self {
+        QemuResetPin {
+            inner: Rc::clone(inner),
+            level: Cell::new(true),
+        }
+    }
+}
+
+impl GpioPin for QemuResetPin {
+    fn read(&self) -> Result<bool> {
+        Ok(self.level.get())
+    }
+
+    fn write(&self, value: bool) -> Result<()> {
+        if value == self.level.get() {
+            return Ok(());
+        }
+        let mut monitor = self.inner.monitor.borrow_mut();
+        if value {
+            if let Some(lc) = &self.inner.lc {
+                lc.borrow_mut().reset();
+            }
+            monitor.execute("system_reset")?;
+            monitor.execute("cont")?;
+        } else {
+            monitor.execute("stop")?;
+        }
+        self.level.set(value);
+        Ok(())
+    }
+
+    fn set_mode(&self, _mode: PinMode) -> Result<()> {
+        Ok(())
+    }
+
+    fn set_pull_mode(&self, _mode: PullMode) -> Result<()> {
+        Ok(())
+    }
+}
@@ -0,0 +1,154 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{bail, Result};
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::time::Duration;
+
+use crate::debug::openocd::{OpenOcd, OpenOcdJtagChain};
+use crate::dif::lc_ctrl::LcCtrlReg;
+use crate::io::jtag::{Jtag, JtagChain, JtagError, JtagParams, JtagTap, RiscvReg};
+use crate::transport::qemu::lc::LcModel;
+use crate::transport::TransportError;
+
+/// JTAG chain of the emulated chip.
+///
+/// The RISC-V TAP is reached through OpenOCD, connected to the JTAG remote bitbang server of
+/// QEMU. The LC TAP is served by the host model of lc_ctrl, which is independent of the lc_ctrl
+/// of the emulated chip.
+pub struct QemuJtagChain {
+    lc: Option<Rc<RefCell<LcModel>>>,
+    jtag_port: u16,
+    opts: JtagParams,
+}
+
+impl QemuJtagChain {
+    pub fn new(lc: Option<Rc<RefCell<LcModel>>>, jtag_port: u16, opts: &JtagParams) -> Self {
+        QemuJtagChain {
+            lc,
+            jtag_port,
+            opts: opts.clone(),
+        }
+    }
+
+    fn openocd(&self) -> Result<OpenOcdJtagChain> {
+        let adapter = format!(
+            "adapter driver remote_bitbang\nremote_bitbang host 127.0.0.1\nremote_bitbang port {}",
+            self.jtag_port
+        );
+        OpenOcdJtagChain::new(&adapter, &self.opts)
+    }
+}
+
+impl JtagChain for QemuJtagChain {
+    fn connect(self: Box<Self>, tap: JtagTap) -> Result<Box<dyn Jtag>> {
+        match tap {
+            JtagTap::LcTap => match self.lc {
+                Some(lc) => Ok(Box::new(QemuLcTap { lc })),
+                None => bail!("The emulated LC TAP requires a fake OTP state (--qemu-otp-state)"),
+            },
+            JtagTap::RiscvTap => Box::new(self.openocd()?).connect(tap),
+        }
+    }
+
+    fn into_raw(self: Box<Self>) -> Result<OpenOcd> {
+        Box::new(self.openocd()?).into_raw()
+    }
+}
+
+/// LC TAP backed by the host model of lc_ctrl.
+pub struct QemuLcTap {
+    lc: Rc<RefCell<LcModel>>,
+}
+
+impl Jtag for QemuLcTap {
+    fn into_raw(self: Box<Self>) -> Result<OpenOcd> {
+        Err(TransportError::UnsupportedOperation.into())
+    }
+
+    fn as_raw(&mut self) -> Result<&mut OpenOcd> {
+        Err(TransportError::UnsupportedOperation.into())
+    }
+
+    fn disconnect(self: Box<Self>) -> Result<()> {
+        Ok(())
+    }
+
+    fn tap(&self) -> JtagTap {
+        JtagTap::LcTap
+    }
+
+    fn read_lc_ctrl_reg(&mut self, reg: &LcCtrlReg) -> Result<u32> {
+        self.lc.borrow().read(reg)
+    }
+
+    fn write_lc_ctrl_reg(&mut self, reg: &LcCtrlReg, value: u32) -> Result<()> {
+        self.lc.borrow_mut().write(reg, value)
+    }
+
+    fn read_memory(&mut self, _addr: u32, _buf: &mut [u8]) -> Result<usize> {
+        Err(JtagError::Tap(JtagTap::LcTap).into())
+    }
+
+    fn read_memory32(&mut self, _addr: u32, _buf: &mut [u32]) -> Result<usize> {
+        Err(JtagError::Tap(JtagTap::LcTap).into())
+    }
+
+    fn write_memory(&mut self, _addr: u32, _buf: &[u8]) -> Result<()> {
+        Err(JtagError::Tap(JtagTap::LcTap).into())
+    }
+
+    fn write_memory32(&mut self, _addr: u32, _buf: &[u32]) -> Result<()> {
+        Err(JtagError::Tap(JtagTap::LcTap).into())
+    }
+
+    fn halt(&mut self) -> Result<()> {
+        Err(JtagError::Tap(JtagTap::LcTap).into())
+    }
+
+    fn wait_halt(&mut self, _timeout: Duration) -> Result<()> {
+        Err(JtagError::Tap(JtagTap::LcTap).into())
+    }
+
+    fn resume(&mut self) -> Result<()> {
+        Err(JtagError::Tap(JtagTap::LcTap).into())
+    }
+
+    fn resume_at(&mut self, _addr: u32) -> Result<()> {
+        Err(JtagError::Tap(JtagTap::LcTap).into())
+    }
+
+    fn step(&mut self) -> Result<()> {
+        Err(JtagError::Tap(JtagTap::LcTap).into())
+    }
+
+    fn step_at(&mut self, _addr: u32) -> Result<()> {
+        Err(JtagError::Tap(JtagTap::LcTap).into())
+    }
+
+    fn reset(&mut self, _run: bool) -> Result<()> {
+        Err(JtagError::Tap(JtagTap::LcTap).into())
+    }
+
+    fn read_riscv_reg(&mut self, _reg: &RiscvReg) -> Result<u32> {
+        Err(JtagError::Tap(JtagTap::LcTap).into())
+    }
+
+    fn write_riscv_reg(&mut self, _reg: &RiscvReg, _val: u32) -> Result<()> {
+        Err(JtagError::Tap(JtagTap::LcTap).into())
+    }
+
+    fn set_breakpoint(&mut self, _addr: u32, _hw: bool) -> Result<()> {
+        Err(JtagError::Tap(JtagTap::LcTap).into())
+    }
+
+    fn remove_breakpoint(&mut self, _addr: u32) -> Result<()> {
+        Err(JtagError::Tap(JtagTap::LcTap).into())
+    }
+
+    fn remove_all_breakpoints(&mut self) -> Result<()> {
+        Err(JtagError::Tap(JtagTap::LcTap).into())
+    }
+}
@@ -0,0 +1,358 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Host model of lc_ctrl, as seen through the LC TAP.
+//!
+//! The model is meant for unit-level testing of the host side of LC transitions, e.g. token
+//! handling and status polling. It is not connected to the lc_ctrl of the emulated chip: the
+//! firmware keeps seeing the LC state of the OTP image QEMU was started with, so device behavior
+//! that depends on the new LC state is not covered.
+//!
+//! The model is backed by a fake OTP state file holding the LIFE_CYCLE partition, the hashed LC
+//! tokens and the device ID, e.g.:
+//!
+//! ```json
+//! {
+//!   "lc_state": "test_locked0",
+//!   "lc_transition_count": 2,
+//!   "test_unlock_token_hash": "0x0b7f3daf1ba9a2d8ec2ab4cd63d26e48",
+//!   "test_exit_token_hash": "0x7a9a1d5e4b8d1f2f0f6c3b8e5a1d2c3b"
+//! }
+//! ```
+//!
+//! LC transitions are checked against the transition matrix and the hashed tokens like the
+//! hardware does, and are written back to the state file, so they persist across emulator runs.
+//! The new state is reported over the LC TAP after the next reset.
+
+use anyhow::{ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+use std::path::{Path, PathBuf};
+
+use crate::chip::boolean::MultiBitBool8;
+use crate::dif::lc_ctrl::{
+    DifLcCtrlState, LcCtrlReg, LcCtrlStatus, LcCtrlTransitionCmd, LcCtrlTransitionCtrl,
+};
+use crate::otp::lc_token::LcToken;
+
+/// Maximum number of LC transitions, see LC_CTRL_LC_TRANSITION_CNT.
+const MAX_TRANSITION_COUNT: u32 = 24;
+
+/// OTP fields read by lc_ctrl.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(deny_unknown_fields)]
+pub struct OtpState {
+    /// Life cycle state, e.g. `test_unlocked0`.
+    pub lc_state: String,
+    /// Number of LC transitions attempted so far.
+    #[serde(default)]
+    pub lc_transition_count: u32,
+    /// Hashed LC tokens (see `LcToken::hash`), as 128-bit hex integers. A transition requiring a
+    /// token whose hash is absent fails with a token error.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub raw_unlock_token_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub test_unlock_token_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub test_exit_token_hash: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub rma_token_hash: Option<String>,
+    /// HW_CFG0 device ID.
+    #[serde(default)]
+    pub device_id: [u32; 8],
+}
+
+impl OtpState {
+    /// Loads and validates a fake OTP state file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let doc = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read OTP state {path:?}"))?;
+        let state: OtpState = serde_json::from_str(&doc)
+            .with_context(|| format!("Failed to parse OTP state {path:?}"))?;
+        state.lc_state()?;
+        Ok(state)
+    }
+
+    /// Writes the OTP state to `path`.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let doc = serde_json::to_string_pretty(self)?;
+        std::fs::write(path, doc).with_context(|| format!("Failed to write OTP state {path:?}"))
+    }
+
+    pub fn lc_state(&self) -> Result<DifLcCtrlState> {
+        let state = DifLcCtrlState::parse_lc_state_str(&self.lc_state)?;
+        ensure!(
+            state != DifLcCtrlState::StateInvalid
+                && state != DifLcCtrlState::PostTransition
+                && state != DifLcCtrlState::Escalate,
+            "Invalid OTP LC state {:?}",
+            self.lc_state
+        );
+        Ok(state)
+    }
+
+    /// Returns the hash of the token unlocking the `from` -> `to` transition, if provisioned.
+    fn token_hash(&self, from: DifLcCtrlState, to: DifLcCtrlState) -> Result<Option<u128>> {
+        let hash = match (from, to) {
+            (DifLcCtrlState::Raw, _) => &self.raw_unlock_token_hash,
+            (_, DifLcCtrlState::Rma) => &self.rma_token_hash,
+            (_, DifLcCtrlState::Dev | DifLcCtrlState::Prod | DifLcCtrlState::ProdEnd) => {
+                &self.test_exit_token_hash
+            }
+            _ => &self.test_unlock_token_hash,
+        };
+        hash.as_deref()
+            .map(|hash| {
+                u128::from_str_radix(hash.trim_start_matches("0x"), 16)
+                    .with_context(|| format!("Invalid LC token hash {hash:?}"))
+            })
+            .transpose()
+    }
+}
+
+/// The lc_ctrl registers accessible over the LC TAP.
+pub struct LcModel {
+    otp: OtpState,
+    /// File the OTP state is persisted to, if any.
+    path: Option<PathBuf>,
+    claimed: bool,
+    ctrl: LcCtrlTransitionCtrl,
+    target: u32,
+    token: [u32; 4],
+    /// Status of the last transition, cleared on reset.
+    result: Option<LcCtrlStatus>,
+}
+
+impl LcModel {
+    pub fn new(otp: OtpState, path: Option<PathBuf>) -> Result<Self> {
+        otp.lc_state()?;
+        Ok(LcModel {
+            otp,
+            path,
+            claimed: false,
+            ctrl: LcCtrlTransitionCtrl::empty(),
+            target: 0,
+            token: [0; 4],
+            result: None,
+        })
+    }
+
+    /// Loads the model from a fake OTP state file, which LC transitions are written back to.
+    pub fn load(path: &Path) -> Result<Self> {
+        LcModel::new(OtpState::load(path)?, Some(path.to_owned()))
+    }
+
+    pub fn otp(&self) -> &OtpState {
+        &self.otp
+    }
+
+    /// Resets lc_ctrl, applying the outcome of the last transition.
+    pub fn reset(&mut self) {
+        self.claimed = false;
+        self.ctrl = LcCtrlTransitionCtrl::empty();
+        self.target = 0;
+        self.token = [0; 4];
+        self.result = None;
+    }
+
+    pub fn read(&self, reg: &LcCtrlReg) -> Result<u32> {
+        let value = match reg {
+            LcCtrlReg::Status => match self.result {
+                Some(result) => (LcCtrlStatus::INITIALIZED | result).bits(),
+                None => (LcCtrlStatus::INITIALIZED | LcCtrlStatus::READY).bits(),
+            },
+            LcCtrlReg::ClaimTransitionIf => u32::from(u8::from(if self.claimed {
+                MultiBitBool8::True
+            } else {
+                MultiBitBool8::False
+            })),
+            LcCtrlReg::TransitionRegwen => u32::from(self.claimed && self.result.is_none()),
+            LcCtrlReg::TransitionCtrl => self.ctrl.bits(),
+            LcCtrlReg::TransitionToken0 => self.token[0],
+            LcCtrlReg::TransitionToken1 => self.token[1],
+            LcCtrlReg::TransitionToken2 => self.token[2],
+            LcCtrlReg::TransitionToken3 => self.token[3],
+            LcCtrlReg::TransitionTarget => self.target,
+            LcCtrlReg::LcState => match self.result {
+                Some(_) => DifLcCtrlState::PostTransition.redundant_encoding(),
+                None => self.otp.lc_state()?.redundant_encoding(),
+            },
+            LcCtrlReg::LcTransitionCnt => self.otp.lc_transition_count,
+            LcCtrlReg::DeviceId0 => self.otp.device_id[0],
+            LcCtrlReg::DeviceId1 => self.otp.device_id[1],
+            LcCtrlReg::DeviceId2 => self.otp.device_id[2],
+            LcCtrlReg::DeviceId3 => self.otp.device_id[3],
+            LcCtrlReg::DeviceId4 => self.otp.device_id[4],
+            LcCtrlReg::DeviceId5 => self.otp.device_id[5],
+            LcCtrlReg::DeviceId6 => self.otp.device_id[6],
+            LcCtrlReg::DeviceId7 => self.otp.device_id[7],
+            _ => 0,
+        };
+        Ok(value)
+    }
+
+    pub fn write(&mut self, reg: &LcCtrlReg, value: u32) -> Result<()> {
+        if matches!(reg, LcCtrlReg::ClaimTransitionIf) {
+            self.claimed = value == u32::from(u8::from(MultiBitBool8::True));
+            return Ok(());
+        }
+        // The transition registers are only writable while holding the transition mutex.
+        if !self.claimed || self.result.is_some() {
+            return Ok(());
+        }
+        match reg {
+            // Volatile raw unlock is not modeled.
+            LcCtrlReg::TransitionCtrl => {
+                self.ctrl = LcCtrlTransitionCtrl::from_bits_truncate(value)
+                    & LcCtrlTransitionCtrl::EXT_CLOCK_EN
+            }
+            LcCtrlReg::TransitionToken0 => self.token[0] = value,
+            LcCtrlReg::TransitionToken1 => self.token[1] = value,
+            LcCtrlReg::TransitionToken2 => self.token[2] = value,
+            LcCtrlReg::TransitionToken3 => self.token[3] = value,
+            LcCtrlReg::TransitionTarget => self.target = value,
+            LcCtrlReg::TransitionCmd if value & LcCtrlTransitionCmd::START.bits() != 0 => {
+                let result = self.transition()?;
+                self.result = Some(result);
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn transition(&mut self) -> Result<LcCtrlStatus> {
+        let mut result = if self.ctrl.contains(LcCtrlTransitionCtrl::EXT_CLOCK_EN) {
+            LcCtrlStatus::EXT_CLOCK_SWITCHED
+        } else {
+            LcCtrlStatus::empty()
+        };
+        let from = self.otp.lc_state()?;
+        // The transition counter is incremented before the transition is checked.
+        if self.otp.lc_transition_count >= MAX_TRANSITION_COUNT {
+            return Ok(result | LcCtrlStatus::TRANSITION_COUNT_ERROR);
+        }
+        self.otp.lc_transition_count += 1;
+        self.persist()?;
+
+        let Ok(to) = DifLcCtrlState::from_redundant_encoding(self.target) else {
+            return Ok(result | LcCtrlStatus::TRANSITION_ERROR);
+        };
+        let check = from.check_transition(to);
+        if !check.valid || to.redundant_encoding() != self.target {
+            return Ok(result | LcCtrlStatus::TRANSITION_ERROR);
+        }
+        if check.token {
+            let hash = LcToken::from_words(self.token).hash().to_u128();
+            if self.otp.token_hash(from, to)? != Some(hash) {
+                return Ok(result | LcCtrlStatus::TOKEN_ERROR);
+            }
+        }
+        log::info!(
+            "Emulated LC transition {} -> {}",
+            from.lc_state_to_str(),
+            to.lc_state_to_str()
+        );
+        self.otp.lc_state = to.lc_state_to_str().to_string();
+        self.persist()?;
+        result |= LcCtrlStatus::TRANSITION_SUCCESSFUL;
+        Ok(result)
+    }
+
+    fn persist(&self) -> Result<()> {
+        match &self.path {
+            Some(path) => self.otp.save(path),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TOKEN: [u32; 4] = [0x1111_1111, 0x2222_2222, 0x3333_3333, 0x4444_4444];
+
+    fn model(lc_state: DifLcCtrlState) -> LcModel {
+        let hash = format!("{:#x}", LcToken::from_words(TOKEN).hash().to_u128());
+        let otp = OtpState {
+            lc_state: lc_state.lc_state_to_str().to_string(),
+            test_unlock_token_hash: Some(hash.clone()),
+            test_exit_token_hash: Some(hash),
+            ..Default::default()
+        };
+        LcModel::new(otp, None).unwrap()
+    }
+
+    fn transition(lc: &mut LcModel, target: DifLcCtrlState, token: [u32; 4]) -> LcCtrlStatus {
+        lc.write(
+            &LcCtrlReg::ClaimTransitionIf,
+            u32::from(u8::from(MultiBitBool8::True)),
+        )
+        .unwrap();
+        lc.write(&LcCtrlReg::TransitionTarget, target.redundant_encoding())
+            .unwrap();
+        for (reg, word) in [
+            LcCtrlReg::TransitionToken0,
+            LcCtrlReg::TransitionToken1,
+            LcCtrlReg::TransitionToken2,
+            LcCtrlReg::TransitionToken3,
+        ]
+        .iter()
+        .zip(token)
+        {
+            lc.write(reg, word).unwrap();
+        }
+        lc.write(&LcCtrlReg::TransitionCmd, LcCtrlTransitionCmd::START.bits())
+            .unwrap();
+        LcCtrlStatus::from_bits(lc.read(&LcCtrlReg::Status).unwrap()).unwrap()
+    }
+
+    fn lc_state(lc: &LcModel) -> DifLcCtrlState {
+        DifLcCtrlState::from_redundant_encoding(lc.read(&LcCtrlReg::LcState).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn test_exit_applies_on_reset() {
+        let mut lc = model(DifLcCtrlState::TestUnlocked1);
+        let status = transition(&mut lc, DifLcCtrlState::Prod, TOKEN);
+        assert!(status.contains(LcCtrlStatus::TRANSITION_SUCCESSFUL));
+        assert_eq!(lc_state(&lc), DifLcCtrlState::PostTransition);
+        lc.reset();
+        assert_eq!(lc_state(&lc), DifLcCtrlState::Prod);
+        assert_eq!(lc.read(&LcCtrlReg::LcTransitionCnt).unwrap(), 1);
+    }
+
+    #[test]
+    fn bad_token_is_rejected() {
+        let mut lc = model(DifLcCtrlState::TestLocked0);
+        let status = transition(&mut lc, DifLcCtrlState::TestUnlocked1, [0; 4]);
+        assert!(status.contains(LcCtrlStatus::TOKEN_ERROR));
+        lc.reset();
+        assert_eq!(lc_state(&lc), DifLcCtrlState::TestLocked0);
+        // Failed transitions still count.
+        assert_eq!(lc.read(&LcCtrlReg::LcTransitionCnt).unwrap(), 1);
+    }
+
+    #[test]
+    fn invalid_transition_is_rejected() {
+        let mut lc = model(DifLcCtrlState::Prod);
+        let status = transition(&mut lc, DifLcCtrlState::Dev, TOKEN);
+        assert!(status.contains(LcCtrlStatus::TRANSITION_ERROR));
+        lc.reset();
+        assert_eq!(lc_state(&lc), DifLcCtrlState::Prod);
+    }
+
+    #[test]
+    fn registers_require_mutex() {
+        let mut lc = model(DifLcCtrlState::TestUnlocked1);
+        lc.write(
+            &LcCtrlReg::TransitionTarget,
+            DifLcCtrlState::Dev.redundant_encoding(),
+        )
+        .unwrap();
+        assert_eq!(lc.read(&LcCtrlReg::TransitionTarget).unwrap(), 0);
+        lc.write(&LcCtrlReg::TransitionCmd, LcCtrlTransitionCmd::START.bits())
+            .unwrap();
+        assert_eq!(lc_state(&lc), DifLcCtrlState::TestUnlocked1);
+    }
+}
@@ -0,0 +1,177 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Transport driving an instruction-set simulator (QEMU) of the chip.
+//!
+//! The emulated chip executes the device firmware, while the LC TAP is served by a host model of
+//! lc_ctrl backed by a fake OTP state (see [`lc`]), so the host side of the provisioning flows can
+//! be run without FPGA hardware. The model does not drive the lc_ctrl of the emulated chip, so it
+//! only suits unit-level tests of the host logic. The reset pin is driven through the QEMU
+//! monitor. Other pins, such as the bootstrap straps, are not connected to the emulated chip.
+
+use anyhow::{ensure, Context, Result};
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+use std::rc::Rc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::io::gpio::GpioPin;
+use crate::io::jtag::{JtagChain, JtagParams};
+use crate::io::spi::Target;
+use crate::io::uart::Uart;
+use crate::transport::{
+    Capabilities, Capability, Transport, TransportError, TransportInterfaceType,
+};
+
+pub mod gpio;
+pub mod jtag;
+pub mod lc;
+pub mod monitor;
+pub mod spi;
+pub mod subprocess;
+pub mod uart;
+
+use gpio::{QemuGpioPin, QemuResetPin};
+use jtag::QemuJtagChain;
+use lc::LcModel;
+use monitor::Monitor;
+use spi::QemuSpi;
+use subprocess::{Options, Subprocess, QMP_SOCKET, SPI_SOCKET, UART_SOCKET};
+use uart::QemuUart;
+
+pub(crate) struct Inner {
+    monitor: RefCell<Monitor>,
+    lc: Option<Rc<RefCell<LcModel>>>,
+}
+
+/// Represents the QEMU transport object.
+pub struct Qemu {
+    subprocess: Option<Subprocess>,
+    runtime_dir: PathBuf,
+    jtag_port: u16,
+    inner: Rc<Inner>,
+    uart: RefCell<Option<Rc<dyn Uart>>>,
+    spi: RefCell<Option<Rc<dyn Target>>>,
+    pins: RefCell<HashMap<String, Rc<dyn GpioPin>>>,
+}
+
+impl Qemu {
+    /// Creates a QEMU subprocess-hosting transport from `options`.
+    pub fn from_options(options: Options) -> Result<Self> {
+        let lc = options
+            .otp_state
+            .as_deref()
+            .map(LcModel::load)
+            .transpose()?
+            .map(|lc| Rc::new(RefCell::new(lc)));
+        if lc.is_some() {
+            log::warn!(
+                "The emulated LC TAP is a host model, LC transitions are not seen by the firmware"
+            );
+        }
+
+        let tstamp = SystemTime::now().duration_since(UNIX_EPOCH)?;
+        let runtime_dir =
+            std::env::temp_dir().join(format!("qemu_{}_{}", process::id(), tstamp.as_nanos()));
+        fs::create_dir(&runtime_dir).context("Failed to create QEMU runtime directory")?;
+
+        let subprocess = Subprocess::from_options(&options, &runtime_dir)?;
+        let monitor = Monitor::connect(&runtime_dir.join(QMP_SOCKET))?;
+        log::info!(
+            "QEMU started with runtime directory {}",
+            runtime_dir.display()
+        );
+
+        Ok(Qemu {
+            subprocess: Some(subprocess),
+            runtime_dir,
+            jtag_port: options.jtag_port,
+            inner: Rc::new(Inner {
+                monitor: RefCell::new(monitor),
+                lc,
+            }),
+            uart: RefCell::default(),
+            spi: RefCell::default(),
+            pins: RefCell::default(),
+        })
+    }
+
+    /// Shuts down the QEMU subprocess.
+    pub fn shutdown(&mut self) -> Result<()> {
+        if let Some(mut subprocess) = self.subprocess.take() {
+            subprocess.kill()?;
+        }
+        if self.runtime_dir.exists() {
+            fs::remove_dir_all(&self.runtime_dir)?;
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Qemu {
+    fn drop(&mut self) {
+        self.shutdown().expect("Kill QEMU subprocess");
+    }
+}
+
+impl Transport for Qemu {
+    fn capabilities(&self) -> Result<Capabilities> {
+        Ok(Capabilities::new(
+            Capability::UART | Capability::SPI | Capability::GPIO | Capability::JTAG,
+        ))
+    }
+
+    fn uart(&self, instance: &str) -> Result<Rc<dyn Uart>> {
+        ensure!(
+            instance == "0",
+            TransportError::InvalidInstance(TransportInterfaceType::Uart, instance.to_string())
+        );
+        let mut uart = self.uart.borrow_mut();
+        if uart.is_none() {
+            *uart = Some(Rc::new(QemuUart::connect(
+                &self.runtime_dir.join(UART_SOCKET),
+            )?));
+        }
+        Ok(Rc::clone(uart.as_ref().unwrap()))
+    }
+
+    fn spi(&self, instance: &str) -> Result<Rc<dyn Target>> {
+        ensure!(
+            instance == "0",
+            TransportError::InvalidInstance(TransportInterfaceType::Spi, instance.to_string())
+        );
+        let mut spi = self.spi.borrow_mut();
+        if spi.is_none() {
+            *spi = Some(Rc::new(QemuSpi::connect(
+                &self.runtime_dir.join(SPI_SOCKET),
+            )?));
+        }
+        Ok(Rc::clone(spi.as_ref().unwrap()))
+    }
+
+    fn gpio_pin(&self, instance: &str) -> Result<Rc<dyn GpioPin>> {
+        let mut pins = self.pins.borrow_mut();
+        if let Some(pin) = pins.get(instance) {
+            return Ok(Rc::clone(pin));
+        }
+        let pin: Rc<dyn GpioPin> = if instance == "RESET" {
+            Rc::new(QemuResetPin::new(&self.inner))
+        } else {
+            Rc::new(QemuGpioPin::default())
+        };
+        pins.insert(instance.to_string(), Rc::clone(&pin));
+        Ok(pin)
+    }
+
+    fn jtag(&self, opts: &JtagParams) -> Result<Box<dyn JtagChain + '_>> {
+        Ok(Box::new(QemuJtagChain::new(
+            self.inner.lc.clone(),
+            self.jtag_port,
+            opts,
+        )))
+    }
+}
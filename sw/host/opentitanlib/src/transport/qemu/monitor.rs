@@ -0,0 +1,57 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+use std::io::{BufRead, BufReader, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+
+/// Client of the QEMU Machine Protocol (QMP), used to drive the emulated reset.
+pub struct Monitor {
+    reader: BufReader<UnixStream>,
+    writer: UnixStream,
+}
+
+impl Monitor {
+    /// Connects to the QMP socket at `path` and negotiates the command mode.
+    pub fn connect(path: &Path) -> Result<Self> {
+        let stream = UnixStream::connect(path)
+            .with_context(|| format!("Failed to connect to QEMU monitor {path:?}"))?;
+        let mut monitor = Monitor {
+            reader: BufReader::new(stream.try_clone()?),
+            writer: stream,
+        };
+        let greeting = monitor.recv()?;
+        if greeting.get("QMP").is_none() {
+            bail!("Unexpected QEMU monitor greeting: {greeting}");
+        }
+        monitor.execute("qmp_capabilities")?;
+        Ok(monitor)
+    }
+
+    fn recv(&mut self) -> Result<Value> {
+        let mut line = String::new();
+        if self.reader.read_line(&mut line)? == 0 {
+            bail!("QEMU monitor connection closed");
+        }
+        Ok(serde_json::from_str(&line)?)
+    }
+
+    /// Executes `command`, returning its result.
+    pub fn execute(&mut self, command: &str) -> Result<Value> {
+        writeln!(self.writer, "{}", json!({ "execute": command }))?;
+        loop {
+            let reply = self.recv()?;
+            if let Some(event) = reply.get("event") {
+                log::debug!("QEMU event: {event}");
+                continue;
+            }
+            if let Some(error) = reply.get("error") {
+                bail!("QEMU monitor command {command} failed: {error}");
+            }
+            return Ok(reply.get("return").cloned().unwrap_or_default());
+        }
+    }
+}
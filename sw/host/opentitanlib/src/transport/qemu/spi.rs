@@ -0,0 +1,150 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! SPI host connected to the emulated spi_device through a socket bridge.
+//!
+//! Each transfer is sent as a frame made of a one byte opcode, a little-endian `u32` length and,
+//! for writes, the data:
+//!
+//! - `W`: write `length` bytes, no response.
+//! - `R`: read, the bridge responds with `length` bytes.
+//! - `X`: full duplex, the bridge responds with `length` bytes.
+//! - `D`: deassert CS (`length` is zero), no response.
+
+use anyhow::{bail, Context, Result};
+use std::cell::Cell;
+use std::io::{Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::rc::Rc;
+
+use crate::io::spi::{
+    AssertChipSelect, MaxSizes, SpiError, Target, TargetChipDeassert, Transfer, TransferMode,
+};
+use crate::transport::TransportError;
+
+/// Maximum size of a single read or write frame.
+const MAX_TRANSFER_SIZE: usize = 4096;
+
+pub struct QemuSpi {
+    socket: UnixStream,
+    /// Number of outstanding `AssertChipSelect` objects.
+    cs_asserted: Cell<usize>,
+}
+
+impl QemuSpi {
+    pub fn connect(path: &Path) -> Result<Self> {
+        Ok(QemuSpi {
+            socket: UnixStream::connect(path)
+                .with_context(|| format!("Failed to connect to QEMU SPI bridge {path:?}"))?,
+            cs_asserted: Cell::new(0),
+        })
+    }
+
+    fn send(&self, opcode: u8, len: usize, data: &[u8]) -> Result<()> {
+        let mut frame = Vec::with_capacity(5 + data.len());
+        frame.push(opcode);
+        frame.extend_from_slice(&u32::try_from(len)?.to_le_bytes());
+        frame.extend_from_slice(data);
+        (&self.socket).write_all(&frame)?;
+        Ok(())
+    }
+
+    fn recv(&self, buf: &mut [u8]) -> Result<()> {
+        (&self.socket).read_exact(buf)?;
+        Ok(())
+    }
+}
+
+impl Target for QemuSpi {
+    fn get_transfer_mode(&self) -> Result<TransferMode> {
+        Ok(TransferMode::Mode0)
+    }
+
+    fn set_transfer_mode(&self, mode: TransferMode) -> Result<()> {
+        match mode {
+            TransferMode::Mode0 => Ok(()),
+            _ => Err(SpiError::InvalidTransferMode(format!("{mode:?}")).into()),
+        }
+    }
+
+    fn get_bits_per_word(&self) -> Result<u32> {
+        Ok(8)
+    }
+
+    fn set_bits_per_word(&self, bits_per_word: u32) -> Result<()> {
+        match bits_per_word {
+            8 => Ok(()),
+            _ => Err(SpiError::InvalidWordSize(bits_per_word).into()),
+        }
+    }
+
+    fn get_max_speed(&self) -> Result<u32> {
+        Err(TransportError::UnsupportedOperation.into())
+    }
+
+    fn set_max_speed(&self, _max_speed: u32) -> Result<()> {
+        // The bridge is not clocked, any speed is accepted.
+        Ok(())
+    }
+
+    fn supports_bidirectional_transfer(&self) -> Result<bool> {
+        Ok(true)
+    }
+
+    fn supports_tpm_poll(&self) -> Result<bool> {
+        Ok(false)
+    }
+
+    fn get_max_transfer_count(&self) -> Result<usize> {
+        Ok(usize::MAX)
+    }
+
+    fn get_max_transfer_sizes(&self) -> Result<MaxSizes> {
+        Ok(MaxSizes {
+            read: MAX_TRANSFER_SIZE,
+            write: MAX_TRANSFER_SIZE,
+        })
+    }
+
+    fn run_transaction(&self, transaction: &mut [Transfer]) -> Result<()> {
+        for transfer in transaction.iter_mut() {
+            match transfer {
+                Transfer::Read(rbuf) => {
+                    self.send(b'R', rbuf.len(), &[])?;
+                    self.recv(rbuf)?;
+                }
+                Transfer::Write(wbuf) => self.send(b'W', wbuf.len(), wbuf)?,
+                Transfer::Both(wbuf, rbuf) => {
+                    if wbuf.len() != rbuf.len() {
+                        bail!(SpiError::MismatchedDataLength(wbuf.len(), rbuf.len()));
+                    }
+                    self.send(b'X', wbuf.len(), wbuf)?;
+                    self.recv(rbuf)?;
+                }
+                _ => bail!(TransportError::UnsupportedOperation),
+            }
+        }
+        if self.cs_asserted.get() == 0 {
+            self.send(b'D', 0, &[])?;
+        }
+        Ok(())
+    }
+
+    fn assert_cs(self: Rc<Self>) -> Result<AssertChipSelect> {
+        self.cs_asserted.set(self.cs_asserted.get() + 1);
+        Ok(AssertChipSelect::new(self))
+    }
+}
+
+impl TargetChipDeassert for QemuSpi {
+    fn deassert_cs(&self) {
+        self.cs_asserted.set(self.cs_asserted.get() - 1);
+        if self.cs_asserted.get() == 0 {
+            if let Err(e) = self.send(b'D', 0, &[]) {
+                log::error!("Failed to deassert QEMU SPI CS: {e}");
+            }
+        }
+    }
+}
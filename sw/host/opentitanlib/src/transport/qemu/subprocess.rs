@@ -0,0 +1,118 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{bail, Result};
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::process::{Child, Command, Stdio};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::util::printer;
+
+/// Name of the UART socket in the runtime directory.
+pub const UART_SOCKET: &str = "uart0.sock";
+/// Name of the SPI device bridge socket in the runtime directory.
+pub const SPI_SOCKET: &str = "spidev.sock";
+/// Name of the QEMU monitor (QMP) socket in the runtime directory.
+pub const QMP_SOCKET: &str = "qmp.sock";
+
+/// QEMU startup options.
+pub struct Options {
+    /// The QEMU executable.
+    pub executable: String,
+    /// Machine specific arguments, e.g. the machine and its ROM, flash and OTP images.
+    pub extra_args: Vec<String>,
+    /// Fake OTP state backing the LC TAP model.
+    pub otp_state: Option<PathBuf>,
+    /// TCP port of the JTAG remote bitbang server.
+    pub jtag_port: u16,
+    /// Timeout for starting QEMU.
+    pub timeout: Duration,
+}
+
+pub struct Subprocess {
+    child: Child,
+}
+
+impl Subprocess {
+    /// Starts a QEMU [`Subprocess`] based on [`Options`], creating its sockets in `runtime_dir`.
+    ///
+    /// The UART, the SPI device bridge and the JTAG remote bitbang server are exposed as the
+    /// `uart0`, `spidev` and `taprbb` chardevs, which the machine arguments must connect to the
+    /// emulated peripherals.
+    pub fn from_options(options: &Options, runtime_dir: &Path) -> Result<Self> {
+        let socket = |name: &str| runtime_dir.join(name).display().to_string();
+        let mut args = vec![
+            "-display".to_string(),
+            "none".to_string(),
+            "-chardev".to_string(),
+            format!(
+                "socket,id=uart0,path={},server=on,wait=off",
+                socket(UART_SOCKET)
+            ),
+            "-serial".to_string(),
+            "chardev:uart0".to_string(),
+            "-chardev".to_string(),
+            format!(
+                "socket,id=spidev,path={},server=on,wait=off",
+                socket(SPI_SOCKET)
+            ),
+            "-chardev".to_string(),
+            format!(
+                "socket,id=taprbb,host=127.0.0.1,port={},server=on,wait=off",
+                options.jtag_port
+            ),
+            "-qmp".to_string(),
+            format!("unix:{},server=on,wait=off", socket(QMP_SOCKET)),
+        ];
+        args.extend_from_slice(&options.extra_args);
+
+        log::info!(
+            "Spawning QEMU: {:?} {:?}",
+            options.executable,
+            args.join(" ")
+        );
+        let mut child = Command::new(&options.executable)
+            .args(&args)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::inherit())
+            .spawn()?;
+        let stdout = child.stdout.take().unwrap();
+        let accumulator: Arc<Mutex<String>> = Default::default();
+        std::thread::spawn(move || {
+            printer::accumulate(stdout, concat!(module_path!(), "::stdout"), accumulator)
+        });
+
+        let mut subprocess = Subprocess { child };
+        let deadline = Instant::now() + options.timeout;
+        for name in [UART_SOCKET, SPI_SOCKET, QMP_SOCKET] {
+            subprocess.wait_for_socket(&runtime_dir.join(name), deadline)?;
+        }
+        Ok(subprocess)
+    }
+
+    /// Waits for QEMU to create the socket at `path`.
+    fn wait_for_socket(&mut self, path: &Path, deadline: Instant) -> Result<()> {
+        while !path.exists() {
+            if let Some(status) = self.child.try_wait()? {
+                bail!("QEMU exited during startup: {status}");
+            }
+            if Instant::now() > deadline {
+                bail!("Timed out waiting for QEMU to create {path:?}");
+            }
+            std::thread::sleep(Duration::from_millis(50));
+        }
+        Ok(())
+    }
+
+    /// Kill the QEMU subprocess.
+    pub fn kill(&mut self) -> Result<()> {
+        match self.child.kill() {
+            Err(error) if error.kind() != ErrorKind::InvalidInput => Err(error.into()),
+            _ => Ok(()),
+        }
+    }
+}
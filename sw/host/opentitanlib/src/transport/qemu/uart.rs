@@ -0,0 +1,60 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use anyhow::{Context, Result};
+use std::io::{ErrorKind, Read, Write};
+use std::os::unix::net::UnixStream;
+use std::path::Path;
+use std::time::Duration;
+
+use crate::io::uart::Uart;
+
+const QEMU_UART_BAUDRATE: u32 = 115200;
+
+/// UART connected to a QEMU socket chardev.
+pub struct QemuUart {
+    socket: UnixStream,
+}
+
+impl QemuUart {
+    pub fn connect(path: &Path) -> Result<Self> {
+        Ok(QemuUart {
+            socket: UnixStream::connect(path)
+                .with_context(|| format!("Failed to connect to QEMU UART {path:?}"))?,
+        })
+    }
+}
+
+impl Uart for QemuUart {
+    fn get_baudrate(&self) -> Result<u32> {
+        // As a virtual uart, the value is set only for compatibility with common hardware.
+        Ok(QEMU_UART_BAUDRATE)
+    }
+
+    fn set_baudrate(&self, _baudrate: u32) -> Result<()> {
+        // As a virtual uart, setting the baudrate is a no-op.
+        Ok(())
+    }
+
+    fn read(&self, buf: &mut [u8]) -> Result<usize> {
+        self.socket.set_read_timeout(None)?;
+        Ok((&self.socket).read(buf)?)
+    }
+
+    fn read_timeout(&self, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        // A zero timeout is rejected by the socket API.
+        self.socket
+            .set_read_timeout(Some(timeout.max(Duration::from_millis(1))))?;
+        match (&self.socket).read(buf) {
+            Ok(n) => Ok(n),
+            Err(e) if matches!(e.kind(), ErrorKind::WouldBlock | ErrorKind::TimedOut) => Ok(0),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn write(&self, buf: &[u8]) -> Result<()> {
+        (&self.socket).write_all(buf)?;
+        Ok(())
+    }
+}
@@ -3,15 +3,21 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use anyhow::{anyhow, bail, Result};
+#[cfg(unix)]
 use mio::{Events, Interest, Poll, Token};
 use regex::{Captures, Regex};
 use std::fs::File;
-use std::io::{ErrorKind, Read, Write};
+#[cfg(unix)]
+use std::io::ErrorKind;
+use std::io::{Read, Write};
+#[cfg(unix)]
 use std::os::fd::{AsFd, AsRawFd};
+#[cfg(unix)]
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::time::{Duration, Instant, SystemTime};
 
 use crate::io::console::{ConsoleDevice, ConsoleError};
+#[cfg(unix)]
 use crate::util::file;
 
 #[derive(Default)]
@@ -25,6 +31,13 @@ pub struct UartConsole {
     pub buffer: String,
     pub newline: bool,
     pub break_en: bool,
+    /// Maximum length of a console line kept for the exit regexes to match against; longer lines
+    /// are truncated.  Defaults to `UartConsole::DEFAULT_MAX_LINE_LEN`.
+    pub max_line_len: Option<usize>,
+    /// Length of the current line of `buffer`, in bytes.
+    pub line_len: usize,
+    /// Incomplete UTF-8 sequence at the end of the data read so far, completed by the next read.
+    pub partial_char: Vec<u8>,
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
@@ -37,13 +50,25 @@ pub enum ExitStatus {
 }
 
 // Creates a vtable for implementors of Read and AsFd traits.
+#[cfg(unix)]
 pub trait ReadAsFd: Read + AsFd {}
+#[cfg(unix)]
 impl<T: Read + AsFd> ReadAsFd for T {}
 
+// Console input can't be polled without blocking on other hosts, where `interact` only streams
+// the output of the device.
+#[cfg(not(unix))]
+pub trait ReadAsFd: Read {}
+#[cfg(not(unix))]
+impl<T: Read> ReadAsFd for T {}
+
 impl UartConsole {
     const CTRL_B: u8 = 2;
     const CTRL_C: u8 = 3;
     const BUFFER_LEN: usize = 32768;
+    // By default, a line may fill the whole buffer: the RPC responses of some tests are single
+    // lines of tens of kilobytes.
+    pub const DEFAULT_MAX_LINE_LEN: usize = UartConsole::BUFFER_LEN;
 
     // Runs an interactive console until CTRL_C is received.
     pub fn interact<T>(
@@ -58,9 +83,15 @@ impl UartConsole {
         if let Some(timeout) = &self.timeout {
             self.deadline = Some(Instant::now() + *timeout);
         }
+        #[cfg(unix)]
         if device.supports_nonblocking_read()? {
             return self.interact_mio(device, stdin, stdout);
         }
+        #[cfg(not(unix))]
+        anyhow::ensure!(
+            stdin.is_none(),
+            "Interactive console input is only supported on Unix hosts"
+        );
         loop {
             match self.interact_once(device, &mut stdin, &mut stdout)? {
                 ExitStatus::None => {}
@@ -71,6 +102,7 @@ impl UartConsole {
 
     // Runs an interactive console until CTRL_C is received.  Uses `mio` library to simultaneously
     // wait for data from UART or from stdin, without need for timeouts and repeated calls.
+    #[cfg(unix)]
     fn interact_mio<T>(
         &mut self,
         device: &T,
@@ -176,6 +208,7 @@ impl UartConsole {
         }
     }
 
+    #[cfg(unix)]
     fn get_next_token() -> Token {
         static TOKEN_COUNTER: AtomicUsize = AtomicUsize::new(0);
         Token(TOKEN_COUNTER.fetch_add(1, Ordering::Relaxed))
@@ -189,14 +222,76 @@ impl UartConsole {
         self.exit_success.is_some() || self.exit_failure.is_some()
     }
 
-    // Maintain a buffer for the exit regexes to match against.
+    // Maintain a buffer for the exit regexes to match against.  The buffer is binary-safe: lines
+    // longer than `max_line_len` are truncated, and invalid UTF-8 sequences and control characters
+    // other than whitespace and ANSI escapes are replaced, so a burst of binary output can neither
+    // grow the buffer past one line nor produce invalid strings.  The data is decoded
+    // incrementally: a character split across reads is kept whole.
     fn append_buffer(&mut self, data: &[u8]) {
-        self.buffer.push_str(&String::from_utf8_lossy(data));
-        while self.buffer.len() > UartConsole::BUFFER_LEN {
-            self.buffer.remove(0);
+        let mut input = std::mem::take(&mut self.partial_char);
+        input.extend_from_slice(data);
+        let mut rest = &input[..];
+        while !rest.is_empty() {
+            match std::str::from_utf8(rest) {
+                Ok(text) => {
+                    self.push_str(text);
+                    break;
+                }
+                Err(err) => {
+                    let (valid, invalid) = rest.split_at(err.valid_up_to());
+                    self.push_str(std::str::from_utf8(valid).unwrap());
+                    match err.error_len() {
+                        Some(len) => {
+                            self.push_char(char::REPLACEMENT_CHARACTER);
+                            rest = &invalid[len..];
+                        }
+                        None => {
+                            // The sequence may be completed by the next read.
+                            self.partial_char = invalid.to_vec();
+                            break;
+                        }
+                    }
+                }
+            }
+        }
+        if self.buffer.len() > UartConsole::BUFFER_LEN {
+            let mut start = self.buffer.len() - UartConsole::BUFFER_LEN;
+            while !self.buffer.is_char_boundary(start) {
+                start += 1;
+            }
+            self.buffer.drain(..start);
+        }
+    }
+
+    fn push_str(&mut self, text: &str) {
+        for c in text.chars() {
+            self.push_char(c);
         }
     }
 
+    fn push_char(&mut self, c: char) {
+        let c = match c {
+            '\t' | '\n' | '\r' | '\x1b' => c,
+            '\0'..='\x1f' => char::REPLACEMENT_CHARACTER,
+            _ => c,
+        };
+        let max_line_len = self
+            .max_line_len
+            .unwrap_or(UartConsole::DEFAULT_MAX_LINE_LEN);
+        if c == '\n' {
+            self.line_len = 0;
+        } else if self.line_len + c.len_utf8() > max_line_len {
+            if self.line_len <= max_line_len {
+                log::warn!("Console line longer than {max_line_len} bytes, truncating it");
+                self.line_len = max_line_len + 1;
+            }
+            return;
+        } else {
+            self.line_len += c.len_utf8();
+        }
+        self.buffer.push(c);
+    }
+
     // Read from the console device and process the data read.
     fn uart_read<T>(
         &mut self,
@@ -244,6 +339,7 @@ impl UartConsole {
         Ok(true)
     }
 
+    #[cfg(unix)]
     fn process_input<T>(
         &mut self,
         device: &T,
@@ -284,6 +380,18 @@ impl UartConsole {
         Ok(ExitStatus::None)
     }
 
+    #[cfg(not(unix))]
+    fn process_input<T>(
+        &mut self,
+        _device: &T,
+        _stdin: &mut Option<&mut (dyn ReadAsFd)>,
+    ) -> Result<ExitStatus>
+    where
+        T: ConsoleDevice + ?Sized,
+    {
+        Ok(ExitStatus::None)
+    }
+
     fn interact_once<T>(
         &mut self,
         device: &T,
@@ -375,3 +483,69 @@ impl UartConsole {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_append_buffer_truncates_long_lines() {
+        let mut console = UartConsole {
+            max_line_len: Some(8),
+            ..Default::default()
+        };
+        console.append_buffer(b"0123456789abcdef\nPASS\n");
+        assert_eq!(console.buffer, "01234567\nPASS\n");
+    }
+
+    #[test]
+    fn test_append_buffer_binary() {
+        let mut console = UartConsole::default();
+        console.append_buffer(b"\x00\xff\x1b[0mok\r\n");
+        assert_eq!(console.buffer, "\u{fffd}\u{fffd}\x1b[0mok\r\n");
+
+        // The buffer keeps the most recent output only.
+        let line = [b'x'; 1023];
+        for _ in 0..40 {
+            console.append_buffer(&line);
+            console.append_buffer(b"\n");
+        }
+        assert_eq!(console.buffer.len(), UartConsole::BUFFER_LEN);
+        assert!(console.buffer.ends_with("x\n"));
+    }
+
+    #[test]
+    fn test_append_buffer_utf8() {
+        let mut console = UartConsole::default();
+        console.append_buffer("\u{2713} PASS\n".as_bytes());
+        assert_eq!(console.buffer, "\u{2713} PASS\n");
+
+        // A character split across reads, e.g. when reading one byte at a time.
+        let mut console = UartConsole::default();
+        console.append_buffer(b"caf\xc3");
+        assert_eq!(console.buffer, "caf");
+        console.append_buffer(b"\xa9\n");
+        assert_eq!(console.buffer, "caf\u{e9}\n");
+        for byte in "\u{1f600}".as_bytes() {
+            console.append_buffer(&[*byte]);
+        }
+        assert_eq!(console.buffer, "caf\u{e9}\n\u{1f600}");
+
+        // An incomplete sequence is replaced once, and the following byte is kept.
+        let mut console = UartConsole::default();
+        console.append_buffer(b"\xe2\x9c");
+        console.append_buffer(b"ok\n");
+        assert_eq!(console.buffer, "\u{fffd}ok\n");
+    }
+
+    #[test]
+    fn test_append_buffer_truncates_long_lines_in_bytes() {
+        let mut console = UartConsole {
+            max_line_len: Some(5),
+            ..Default::default()
+        };
+        // The replacement characters count as the 3 bytes they take in the buffer.
+        console.append_buffer(b"ab\x00\x01\n\xe2\x9c\x93\xe2\x9c\x93\n");
+        assert_eq!(console.buffer, "ab\u{fffd}\n\u{2713}\n");
+    }
+}
@@ -14,6 +14,7 @@ pub mod printer;
 pub mod raw_tty;
 pub mod rom_detect;
 pub mod serde;
+pub mod service;
 pub mod status;
 pub mod testing;
 pub mod unknown;
@@ -0,0 +1,177 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Lifetime of a long-running process under a service manager: systemd, or a service wrapper
+//! such as WinSW or NSSM on Windows.
+//!
+//! While it runs, a service records its process ID in `<name>.pid` of its run directory.  It is
+//! stopped by creating `<name>.stop` next to it, see `request_stop`, e.g. from the stop command
+//! of the service wrapper: unlike signals, this works the same on hosts where a service gets no
+//! SIGTERM.  The service polls `stop_requested` between units of work, and removes both files
+//! once it stops.
+
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+
+use anyhow::{bail, ensure, Context, Result};
+
+/// Interval at which `request_stop` checks whether the service stopped.
+const STOP_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Returns the run directory of services: `$XDG_RUNTIME_DIR`, or the temporary directory on
+/// hosts without one.
+pub fn default_run_dir() -> PathBuf {
+    std::env::var_os("XDG_RUNTIME_DIR")
+        .map(PathBuf::from)
+        .unwrap_or_else(std::env::temp_dir)
+}
+
+fn pid_file(run_dir: &Path, name: &str) -> PathBuf {
+    run_dir.join(format!("{name}.pid"))
+}
+
+fn stop_file(run_dir: &Path, name: &str) -> PathBuf {
+    run_dir.join(format!("{name}.stop"))
+}
+
+/// A running service, removing its pid and stop files when dropped.
+#[derive(Debug)]
+pub struct ServiceLifetime {
+    pid_file: PathBuf,
+    stop_file: PathBuf,
+}
+
+impl ServiceLifetime {
+    /// Records the service `name` as running in `run_dir`.
+    ///
+    /// A stop requested of a previous instance which did not stop, e.g. because it was killed, is
+    /// discarded.
+    pub fn start(run_dir: &Path, name: &str) -> Result<Self> {
+        let lifetime = Self {
+            pid_file: pid_file(run_dir, name),
+            stop_file: stop_file(run_dir, name),
+        };
+        remove_if_exists(&lifetime.stop_file)?;
+        fs::write(&lifetime.pid_file, format!("{}\n", std::process::id()))
+            .with_context(|| format!("Failed to write {:?}", lifetime.pid_file))?;
+        Ok(lifetime)
+    }
+
+    /// Returns whether a stop of the service was requested.
+    pub fn stop_requested(&self) -> bool {
+        self.stop_file.exists()
+    }
+
+    /// Returns the file recording the process ID of the service.
+    pub fn pid_file(&self) -> &Path {
+        &self.pid_file
+    }
+}
+
+impl Drop for ServiceLifetime {
+    fn drop(&mut self) {
+        for path in [&self.pid_file, &self.stop_file] {
+            if let Err(e) = remove_if_exists(path) {
+                log::warn!("{e:#}");
+            }
+        }
+    }
+}
+
+/// Returns whether the service `name` is running in `run_dir`.
+pub fn is_running(run_dir: &Path, name: &str) -> bool {
+    pid_file(run_dir, name).exists()
+}
+
+/// Requests the service `name` running in `run_dir` to stop, and waits up to `timeout` for it to
+/// stop.
+pub fn request_stop(run_dir: &Path, name: &str, timeout: Duration) -> Result<()> {
+    let pid_file = pid_file(run_dir, name);
+    ensure!(
+        pid_file.exists(),
+        "Service {name} is not running in {run_dir:?}"
+    );
+    let stop_file = stop_file(run_dir, name);
+    fs::write(&stop_file, "").with_context(|| format!("Failed to write {stop_file:?}"))?;
+    let deadline = Instant::now() + timeout;
+    while pid_file.exists() {
+        if Instant::now() >= deadline {
+            bail!("Service {name} did not stop within {timeout:?}");
+        }
+        std::thread::sleep(STOP_POLL_INTERVAL);
+    }
+    Ok(())
+}
+
+fn remove_if_exists(path: &Path) -> Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != ErrorKind::NotFound => {
+            Err(e).with_context(|| format!("Failed to remove {path:?}"))
+        }
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::tmpfilename;
+
+    fn run_dir(name: &str) -> PathBuf {
+        let dir = PathBuf::from(tmpfilename(name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn test_lifetime() -> Result<()> {
+        let dir = run_dir("test_service_lifetime");
+        assert!(!is_running(&dir, "station"));
+        // A stop left over by a killed instance.
+        fs::write(stop_file(&dir, "station"), "")?;
+
+        let lifetime = ServiceLifetime::start(&dir, "station")?;
+        assert!(is_running(&dir, "station"));
+        assert!(!lifetime.stop_requested());
+        assert_eq!(
+            fs::read_to_string(lifetime.pid_file())?,
+            format!("{}\n", std::process::id())
+        );
+        drop(lifetime);
+        assert!(!is_running(&dir, "station"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_request_stop() -> Result<()> {
+        let dir = run_dir("test_service_request_stop");
+        let err = request_stop(&dir, "station", Duration::ZERO).unwrap_err();
+        assert!(err.to_string().contains("not running"), "{err}");
+
+        let lifetime = ServiceLifetime::start(&dir, "station")?;
+        let service = std::thread::spawn(move || {
+            while !lifetime.stop_requested() {
+                std::thread::sleep(Duration::from_millis(10));
+            }
+        });
+        request_stop(&dir, "station", Duration::from_secs(10))?;
+        service.join().unwrap();
+        assert!(!is_running(&dir, "station"));
+        assert!(!stop_file(&dir, "station").exists());
+        Ok(())
+    }
+
+    #[test]
+    fn test_request_stop_timeout() -> Result<()> {
+        let dir = run_dir("test_service_request_stop_timeout");
+        let lifetime = ServiceLifetime::start(&dir, "station")?;
+        let err = request_stop(&dir, "station", Duration::from_millis(50)).unwrap_err();
+        assert!(err.to_string().contains("did not stop"), "{err}");
+        assert!(lifetime.stop_requested());
+        Ok(())
+    }
+}
@@ -20,6 +20,10 @@ use std::time::Duration;
 
 use opentitanlib::backend;
 use opentitanlib::proxy::SessionHandler;
+use opentitanlib::util::service::{self, ServiceLifetime};
+
+/// Time `--stop` waits for a service session to stop.
+const SERVICE_STOP_TIMEOUT: Duration = Duration::from_secs(30);
 
 #[derive(Debug, Parser)]
 #[command(
@@ -52,6 +56,17 @@ struct Opts {
     /// Internal, used to tell the child process to run as a daemon.
     #[arg(long)]
     child: bool,
+
+    /// Run as the service of a service manager (systemd, or WinSW or NSSM on Windows): stay in
+    /// the foreground, and stop when `--stop` is invoked, which the service wrapper runs as its
+    /// stop command.  Does not rely on signals, so also works for Windows services.
+    #[arg(long, conflicts_with_all = ["stop", "foreground", "child"])]
+    service: bool,
+}
+
+/// Service name of the session on `port`, see `opentitanlib::util::service`.
+fn service_name(port: u16) -> String {
+    format!("opentitansession-service.{}", port)
 }
 
 // Given some existing option configuration, maybe re-evaluate command
@@ -245,6 +260,32 @@ fn main() -> Result<()> {
         return Ok(());
     }
 
+    if opts.service {
+        let transport = backend::create(&opts.backend_opts)?;
+        // As for a daemon, clients go through the session.
+        let _maintain_connection = transport.maintain_connection()?;
+        let mut session = SessionHandler::init(&transport, opts.listen_port)?;
+        let service = ServiceLifetime::start(
+            &service::default_run_dir(),
+            &service_name(session.get_port()),
+        )?;
+        println!("Listening on port {}", session.get_port());
+        session.run_service_loop(&service)?;
+        return Ok(());
+    }
+
+    // A service session is stopped through its run directory, on any host.
+    let stop_port = opts.listen_port.unwrap_or(9900);
+    if opts.stop && service::is_running(&service::default_run_dir(), &service_name(stop_port)) {
+        service::request_stop(
+            &service::default_run_dir(),
+            &service_name(stop_port),
+            SERVICE_STOP_TIMEOUT,
+        )?;
+        println!("{}", serde_json::to_string_pretty(&SessionStopResult {})?);
+        return Ok(());
+    }
+
     if opts.child {
         // This process is a child, which is supposed to stay running as a daemon.
         match session_child(opts.listen_port, &opts.backend_opts) {
@@ -273,7 +314,7 @@ fn main() -> Result<()> {
 
     let value = if opts.stop {
         // Send signal to daemon process to stop
-        stop_session(run_file_fn, opts.listen_port.unwrap_or(9900))?
+        stop_session(run_file_fn, stop_port)?
     } else {
         // Fork a daemon process
         start_session(run_file_fn)?
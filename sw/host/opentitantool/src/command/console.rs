@@ -51,6 +51,10 @@ pub struct Console {
     /// Exit with failure if the specified regex is matched.
     #[arg(long)]
     exit_failure: Option<String>,
+
+    /// Maximum length of a console line matched by the exit regexes; longer lines are truncated.
+    #[arg(long, default_value_t = UartConsole::DEFAULT_MAX_LINE_LEN)]
+    max_line_len: usize,
 }
 
 impl CommandDispatch for Console {
@@ -78,6 +82,7 @@ impl CommandDispatch for Console {
                 .transpose()?,
             timestamp: self.timestamp,
             newline: true,
+            max_line_len: Some(self.max_line_len),
             ..Default::default()
         };
 
@@ -3,9 +3,10 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::any::Any;
+use std::path::PathBuf;
 use std::time::Duration;
 
-use anyhow::{ensure, Result};
+use anyhow::{ensure, Context, Result};
 use clap::{Args, Subcommand};
 use hex::decode;
 use humantime::parse_duration;
@@ -15,6 +16,8 @@ use opentitanlib::app::command::CommandDispatch;
 use opentitanlib::app::TransportWrapper;
 use opentitanlib::dif::lc_ctrl::{DifLcCtrlState, DifLcCtrlToken, LcCtrlReg, LcCtrlStatus};
 use opentitanlib::io::jtag::{Jtag, JtagParams, JtagTap};
+use opentitanlib::otp::lc_token::{LcToken, LcTokenItem};
+use opentitanlib::otp::otp_img::OtpImg;
 use opentitanlib::test_utils::lc_transition::{trigger_lc_transition, trigger_volatile_raw_unlock};
 
 #[derive(serde::Serialize)]
@@ -23,10 +26,13 @@ pub struct LcStateReadResult {
 }
 
 /// Read and decode the current life cycle state, and check whether the requested transition is valid.
+///
+/// If `otp_image` is provided, the token is also checked against the hash it holds.
 fn check_lc_transition(
     jtag: &mut dyn Jtag,
     target: DifLcCtrlState,
     token: DifLcCtrlToken,
+    otp_image: Option<&OtpImg>,
 ) -> Result<()> {
     let lc_state =
         DifLcCtrlState::from_redundant_encoding(jtag.read_lc_ctrl_reg(&LcCtrlReg::LcState)?)?;
@@ -40,6 +46,13 @@ fn check_lc_transition(
             !token.is_zero(),
             "Transition from {lc_state} to {target} requires a non-zero token."
         );
+        if let (Some(otp_image), Some(item)) =
+            (otp_image, LcTokenItem::for_transition(lc_state, target))
+        {
+            LcToken::from_words(token.into_register_values())
+                .check_otp_hash(otp_image, item.name())
+                .with_context(|| format!("Wrong token for the transition to {target}"))?;
+        }
     } else {
         ensure!(
             token.is_zero(),
@@ -50,7 +63,7 @@ fn check_lc_transition(
 }
 
 /// Parses an unlock token string.
-fn parse_token_str(token: &str) -> Result<DifLcCtrlToken> {
+pub(crate) fn parse_token_str(token: &str) -> Result<DifLcCtrlToken> {
     let hex_str_no_sep = token.replace('_', "");
     let hex_str_prefix = "0x";
     let sanitized_hex_str = if token.starts_with(hex_str_prefix) {
@@ -233,7 +246,7 @@ impl CommandDispatch for RawUnlock {
             .connect(JtagTap::LcTap)?;
 
         let token = parse_token_str(self.token.as_str())?;
-        check_lc_transition(&mut *jtag, DifLcCtrlState::TestUnlocked0, token)?;
+        check_lc_transition(&mut *jtag, DifLcCtrlState::TestUnlocked0, token, None)?;
 
         // ROM execution is not enabled in the OTP so we can safely reconnect to
         // the LC TAP after the transition without risking the chip resetting.
@@ -272,6 +285,11 @@ pub struct Transition {
     #[arg(long, default_value = "0x00000000000000000000000000000000")]
     pub token: String,
 
+    /// OTP image (HJSON) holding the hashes of the tokens of the device, to check the token
+    /// against before attempting the transition.
+    #[arg(long)]
+    pub otp_image: Option<PathBuf>,
+
     /// Reset duration when switching the LC TAP straps.
     #[arg(long, value_parser = parse_duration, default_value = "100ms")]
     pub reset_delay: Duration,
@@ -286,6 +304,13 @@ impl CommandDispatch for Transition {
         _context: &dyn Any,
         transport: &TransportWrapper,
     ) -> Result<Option<Box<dyn Annotate>>> {
+        // Load the OTP image before touching the device.
+        let otp_image = self
+            .otp_image
+            .as_deref()
+            .map(OtpImg::from_file)
+            .transpose()?;
+
         // Set the TAP straps for the lifecycle controller and reset.
         transport.pin_strapping("PINMUX_TAP_LC")?.apply()?;
         transport.reset_target(self.reset_delay, true)?;
@@ -307,7 +332,7 @@ impl CommandDispatch for Transition {
 
         // Check whether this is a valid transition.
         let token = parse_token_str(self.token.as_str())?;
-        check_lc_transition(&mut *jtag, self.target_lc_state, token)?;
+        check_lc_transition(&mut *jtag, self.target_lc_state, token, otp_image.as_ref())?;
 
         trigger_lc_transition(
             transport,
@@ -466,7 +491,7 @@ impl CommandDispatch for VolatileRawUnlock {
             .connect(JtagTap::LcTap)?;
 
         let token = parse_token_str(self.token.as_str())?;
-        check_lc_transition(&mut *jtag, DifLcCtrlState::TestUnlocked0, token)?;
+        check_lc_transition(&mut *jtag, DifLcCtrlState::TestUnlocked0, token, None)?;
 
         // ROM execution is not enabled in the OTP so we can safely reconnect to
         // the LC TAP after the transition without risking the chip resetting.
@@ -7,7 +7,7 @@ use std::fs::File;
 use std::io::Write;
 use std::path::PathBuf;
 
-use anyhow::Result;
+use anyhow::{ensure, Context, Result};
 
 use serde_annotate::{serialize, Annotate, Base};
 
@@ -17,8 +17,11 @@ use opentitanlib::app::command::CommandDispatch;
 use opentitanlib::app::TransportWrapper;
 use opentitanlib::otp::alert_handler::AlertRegs;
 use opentitanlib::otp::lc_state::LcStateVal;
+use opentitanlib::otp::lc_token::{lc_token_overlay, LcToken, LcTokenItem};
 use opentitanlib::otp::otp_img::{OtpImg, OtpImgItem, OtpImgPartition, OtpImgValue};
 
+use crate::command::lc::parse_token_str;
+
 /// Generate CRC magic value for alert_handler configuration.
 #[derive(Debug, Args)]
 pub struct AlertDigest {
@@ -82,19 +85,64 @@ impl CommandDispatch for AlertDigest {
             }],
         };
 
-        if let Some(output) = &self.output {
-            let mut file = File::create(output)?;
-            file.write_all(
-                serialize(&img_out)?
-                    .to_json()
-                    .bases(&[Base::Hex])
-                    .to_string()
-                    .as_bytes(),
-            )?;
-            Ok(None)
-        } else {
-            Ok(Some(Box::new(img_out)))
+        write_overlay(img_out, self.output.as_ref())
+    }
+}
+
+/// Writes an OTP image overlay to `output`, or returns it to be printed.
+fn write_overlay(img_out: OtpImg, output: Option<&PathBuf>) -> Result<Option<Box<dyn Annotate>>> {
+    if let Some(output) = output {
+        let mut file = File::create(output)?;
+        file.write_all(
+            serialize(&img_out)?
+                .to_json()
+                .bases(&[Base::Hex])
+                .to_string()
+                .as_bytes(),
+        )?;
+        Ok(None)
+    } else {
+        Ok(Some(Box::new(img_out)))
+    }
+}
+
+/// Hash raw life cycle tokens into an OTP image overlay.
+#[derive(Debug, Args)]
+pub struct LcTokenHash {
+    /// Raw test unlock token: the TRANSITION_TOKEN register values in order, as a hexstring.
+    #[arg(long)]
+    test_unlock_token: Option<String>,
+    /// Raw test exit token, as a hexstring.
+    #[arg(long)]
+    test_exit_token: Option<String>,
+    /// Raw RMA unlock token, as a hexstring.
+    #[arg(long)]
+    rma_token: Option<String>,
+    /// Output file to write the new OTP overlay to instead of printing.
+    #[arg(long)]
+    output: Option<PathBuf>,
+}
+
+impl CommandDispatch for LcTokenHash {
+    fn run(
+        &self,
+        _context: &dyn Any,
+        _transport: &TransportWrapper,
+    ) -> Result<Option<Box<dyn Annotate>>> {
+        let mut tokens = Vec::<(LcTokenItem, LcToken)>::new();
+        for (item, token) in [
+            (LcTokenItem::TestUnlock, &self.test_unlock_token),
+            (LcTokenItem::TestExit, &self.test_exit_token),
+            (LcTokenItem::Rma, &self.rma_token),
+        ] {
+            if let Some(token) = token {
+                let token = parse_token_str(token)
+                    .with_context(|| format!("Invalid {} value", item.name()))?;
+                tokens.push((item, LcToken::from_words(token.into_register_values())));
+            }
         }
+        ensure!(!tokens.is_empty(), "No token to hash");
+        write_overlay(lc_token_overlay(&tokens)?, self.output.as_ref())
     }
 }
 
@@ -102,4 +150,5 @@ impl CommandDispatch for AlertDigest {
 /// OTP related commands.
 pub enum Otp {
     AlertDigest(AlertDigest),
+    LcTokenHash(LcTokenHash),
 }
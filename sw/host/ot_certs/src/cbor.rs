@@ -34,7 +34,7 @@ enum ArgType {
 ///   1. how many additional bytes to encode them
 ///   2. the value to be filled in the header's additional information
 pub fn arg_size(arg: u64) -> (u64, u8) {
-    if arg <= ArgType::U8 as u64 {
+    if arg < ArgType::U8 as u64 {
         (0, arg as u8)
     } else if arg <= u8::MAX.into() {
         (1, ArgType::U8 as u8)
@@ -0,0 +1,212 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use base64ct::{Base64, Encoding};
+use clap::ValueEnum;
+use openssl::nid::Nid;
+use openssl::x509::X509;
+use serde::Serialize;
+
+use crate::EndorsedCert;
+use ot_certs::{cbor, CertFormat};
+
+/// Names of the DICE certificates, from the root of the chain to its leaf.
+const DICE_CERT_NAMES: [&str; 3] = ["UDS", "CDI_0", "CDI_1"];
+
+/// Formats the DICE certificate chain of a device can be exported in, for attestation verifiers.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum AttestationFormat {
+    /// PEM certificates from the leaf (CDI_1) to the DICE CA, as taken by `openssl verify
+    /// -untrusted` and Chrome OS style verifiers.
+    PemChain,
+    /// JSON object holding an RFC 7515 `x5c` array of the base64 DER certificates, from the leaf
+    /// to the DICE CA.
+    X5c,
+    /// CBOR `UdsCerts` map of an Android RKP certificate request (v3), i.e. the DICE CA subject
+    /// common name mapped to the X.509 chain from the DICE CA to the UDS certificate.
+    RkpUdsCerts,
+}
+
+impl AttestationFormat {
+    fn extension(self) -> &'static str {
+        match self {
+            AttestationFormat::PemChain => "dice_chain.pem",
+            AttestationFormat::X5c => "x5c.json",
+            AttestationFormat::RkpUdsCerts => "uds_certs.cbor",
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct X5c {
+    x5c: Vec<String>,
+}
+
+/// The X.509 DICE certificate chain of a device, and the certificate of the DICE CA issuing it.
+pub struct DiceChain {
+    ca: X509,
+    /// UDS, CDI_0 and CDI_1 certificates.
+    certs: Vec<X509>,
+}
+
+impl DiceChain {
+    /// Collects the DICE certificates of `certs`, issued by the DICE CA certificate `ca`.
+    pub fn new<'a>(certs: impl IntoIterator<Item = &'a EndorsedCert>, ca: X509) -> Result<Self> {
+        let certs = certs.into_iter().collect::<Vec<_>>();
+        let certs = DICE_CERT_NAMES
+            .iter()
+            .map(|name| {
+                let Some(cert) = certs.iter().find(|cert| cert.name == *name) else {
+                    bail!("No {name} cert to export");
+                };
+                if !matches!(cert.format, CertFormat::X509) {
+                    bail!("Attestation export unsupported for {name} cert format");
+                }
+                X509::from_der(&cert.bytes).with_context(|| format!("failed to parse {name} cert"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        Ok(DiceChain { ca, certs })
+    }
+
+    /// Returns the certificates from the leaf of the chain to the DICE CA.
+    fn leaf_to_root(&self) -> impl Iterator<Item = &X509> {
+        self.certs.iter().rev().chain([&self.ca])
+    }
+
+    pub fn to_pem_chain(&self) -> Result<Vec<u8>> {
+        let mut pem = Vec::new();
+        for cert in self.leaf_to_root() {
+            pem.extend(cert.to_pem()?);
+        }
+        Ok(pem)
+    }
+
+    pub fn to_x5c(&self) -> Result<String> {
+        let x5c = self
+            .leaf_to_root()
+            .map(|cert| Ok(Base64::encode_string(&cert.to_der()?)))
+            .collect::<Result<Vec<_>>>()?;
+        Ok(serde_json::to_string_pretty(&X5c { x5c })?)
+    }
+
+    pub fn to_rkp_uds_certs(&self) -> Result<Vec<u8>> {
+        let signer = self
+            .ca
+            .subject_name()
+            .entries_by_nid(Nid::COMMONNAME)
+            .next()
+            .context("DICE CA cert has no common name")?
+            .data()
+            .as_utf8()?
+            .to_string();
+        // UdsCerts = { SignerName => UdsCertChain }, UdsCertChain = [ 2* X509Certificate ].
+        let mut cbor = cbor::map_header(1);
+        cbor.extend(cbor::string_header(signer.len() as u64));
+        cbor.extend(signer.as_bytes());
+        cbor.extend(cbor::array_header(2));
+        for cert in [&self.ca, &self.certs[0]] {
+            let der = cert.to_der()?;
+            cbor.extend(cbor::byte_array_header(der.len() as u64));
+            cbor.extend(der);
+        }
+        Ok(cbor)
+    }
+
+    pub fn encode(&self, format: AttestationFormat) -> Result<Vec<u8>> {
+        match format {
+            AttestationFormat::PemChain => self.to_pem_chain(),
+            AttestationFormat::X5c => Ok(self.to_x5c()?.into_bytes()),
+            AttestationFormat::RkpUdsCerts => self.to_rkp_uds_certs(),
+        }
+    }
+}
+
+/// Writes the DICE chain to `<dir>/<device_id>.<extension>` in each of `formats`, and returns the
+/// paths written.
+pub fn export_attestation_bundles(
+    chain: &DiceChain,
+    formats: &[AttestationFormat],
+    device_id: &str,
+    dir: &Path,
+) -> Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("failed to create attestation export dir {dir:?}"))?;
+    let mut paths = Vec::new();
+    for format in formats {
+        let path = dir.join(format!("{device_id}.{}", format.extension()));
+        fs::write(&path, chain.encode(*format)?)
+            .with_context(|| format!("failed to write {path:?}"))?;
+        log::info!("Exported DICE chain as {format:?} to {path:?}");
+        paths.push(path);
+    }
+    Ok(paths)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_certs::{self, key};
+
+    fn cert(cn: &str) -> X509 {
+        let key = key();
+        test_certs::cert(cn, &key, &key)
+    }
+
+    fn chain() -> DiceChain {
+        let certs = DICE_CERT_NAMES
+            .iter()
+            .map(|name| EndorsedCert {
+                format: CertFormat::X509,
+                name: name.to_string(),
+                bytes: cert(name).to_der().unwrap(),
+                ignore_critical: true,
+            })
+            .collect::<Vec<_>>();
+        DiceChain::new(&certs, cert("OpenTitan DICE CA")).unwrap()
+    }
+
+    fn common_name(cert: &X509) -> String {
+        let entry = cert.subject_name().entries_by_nid(Nid::COMMONNAME).next();
+        entry.unwrap().data().as_utf8().unwrap().to_string()
+    }
+
+    #[test]
+    fn pem_chain_order() {
+        let certs = X509::stack_from_pem(&chain().to_pem_chain().unwrap()).unwrap();
+        let names = certs.iter().map(common_name).collect::<Vec<_>>();
+        assert_eq!(names, ["CDI_1", "CDI_0", "UDS", "OpenTitan DICE CA"]);
+    }
+
+    /// Splits the CBOR byte string at the start of `cbor` from the rest of `cbor`.
+    fn bstr(cbor: &[u8]) -> (&[u8], &[u8]) {
+        let (len, rest) = match cbor[0] {
+            0x58 => (cbor[1] as usize, &cbor[2..]),
+            0x59 => (u16::from_be_bytes([cbor[1], cbor[2]]) as usize, &cbor[3..]),
+            header => panic!("unexpected byte string header {header:#x}"),
+        };
+        rest.split_at(len)
+    }
+
+    #[test]
+    fn rkp_uds_certs_encoding() {
+        let chain = chain();
+        let cbor = chain.to_rkp_uds_certs().unwrap();
+        let signer = b"OpenTitan DICE CA";
+        // A map of one entry, keyed by a text string, holding an array of two byte strings.
+        assert_eq!(cbor[0], 0xa1);
+        assert_eq!(cbor[1], 0x60 | signer.len() as u8);
+        assert_eq!(&cbor[2..2 + signer.len()], signer);
+        assert_eq!(cbor[2 + signer.len()], 0x82);
+        let (ca, rest) = bstr(&cbor[3 + signer.len()..]);
+        let (uds, rest) = bstr(rest);
+        assert_eq!(ca, chain.ca.to_der().unwrap());
+        assert_eq!(uds, chain.certs[0].to_der().unwrap());
+        assert!(rest.is_empty());
+    }
+}
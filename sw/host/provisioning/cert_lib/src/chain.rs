@@ -0,0 +1,167 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Structural checks of the DICE certificate chain of a device.
+//!
+//! `validate_cert_chain` leaves the chain building to `openssl verify`, which accepts any path to
+//! the CA. The chain of a device is fixed instead: each certificate must be issued by the previous
+//! one, the first by the expected root, with matching names and key identifiers, and a signature
+//! by the key of its issuer. This also covers the certificates the device endorses itself.
+
+use std::path::Path;
+
+use anyhow::{ensure, Context, Result};
+use openssl::nid::Nid;
+use openssl::x509::{X509Ref, X509};
+
+use ot_certs::x509::extension::{self, X509ExtensionRef};
+use ot_certs::CertFormat;
+
+use crate::EndorsedCert;
+
+/// Loads the CA certificate at `path`, in PEM or DER.
+pub fn load_ca_cert(path: &Path) -> Result<X509> {
+    let bytes = std::fs::read(path).with_context(|| format!("Failed to read CA cert {path:?}"))?;
+    X509::from_pem(&bytes)
+        .or_else(|_| X509::from_der(&bytes))
+        .with_context(|| format!("Failed to parse CA cert {path:?}"))
+}
+
+/// Checks that `chain`, ordered from root to leaf, links up to `root`.
+pub fn check_cert_chain(root: &X509, chain: &[EndorsedCert]) -> Result<()> {
+    let mut issuer = root.clone();
+    let mut issuer_name = "root CA".to_string();
+    for cert in chain {
+        ensure!(
+            matches!(cert.format, CertFormat::X509),
+            "{} cert is not an X.509 cert",
+            cert.name
+        );
+        let x509 = X509::from_der(&cert.bytes)
+            .with_context(|| format!("Failed to parse {} cert", cert.name))?;
+        check_issued_by(&x509, &issuer).with_context(|| {
+            format!("{} cert is not issued by the {issuer_name} cert", cert.name)
+        })?;
+        issuer = x509;
+        issuer_name = format!("{} cert", cert.name);
+    }
+    Ok(())
+}
+
+fn check_issued_by(cert: &X509Ref, issuer: &X509Ref) -> Result<()> {
+    ensure!(
+        cert.issuer_name().to_der()? == issuer.subject_name().to_der()?,
+        "issuer name does not match the subject name of the issuer"
+    );
+    let authority_key_id = key_id(
+        cert,
+        Nid::AUTHORITY_KEY_IDENTIFIER,
+        extension::parse_authority_key_id,
+    )?
+    .context("no authority key identifier")?;
+    let issuer_key_id = key_id(
+        issuer,
+        Nid::SUBJECT_KEY_IDENTIFIER,
+        extension::parse_subject_key_id,
+    )?
+    .context("issuer has no subject key identifier")?;
+    ensure!(
+        authority_key_id == issuer_key_id,
+        "authority key identifier {} does not match the subject key identifier {} of the issuer",
+        hex::encode(&authority_key_id),
+        hex::encode(&issuer_key_id)
+    );
+    let issuer_key = issuer.public_key()?;
+    ensure!(
+        cert.verify(&issuer_key)?,
+        "signature does not verify with the key of the issuer"
+    );
+    Ok(())
+}
+
+fn key_id(
+    cert: &X509Ref,
+    nid: Nid,
+    parse: fn(&X509ExtensionRef) -> Result<Vec<u8>>,
+) -> Result<Option<Vec<u8>>> {
+    // Parse the key identifiers directly, as in the signing policy.
+    extension::x509_get_extensions(&cert.to_owned())?
+        .iter()
+        .find(|ext| ext.object.nid() == nid)
+        .map(parse)
+        .transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use openssl::hash::MessageDigest;
+    use openssl::pkey::{PKey, Private};
+    use openssl::x509::extension::{AuthorityKeyIdentifier, SubjectKeyIdentifier};
+
+    use crate::test_certs::{cert_builder, key};
+
+    /// Returns a cert for `key` named `name`, issued by `issuer` or self-signed.
+    fn cert(name: &str, key: &PKey<Private>, issuer: Option<(&X509, &PKey<Private>)>) -> X509 {
+        let mut builder = cert_builder(name, key);
+        if let Some((issuer, _)) = issuer {
+            builder.set_issuer_name(issuer.subject_name()).unwrap();
+        }
+        let ski = SubjectKeyIdentifier::new()
+            .build(&builder.x509v3_context(None, None))
+            .unwrap();
+        builder.append_extension(ski).unwrap();
+        if let Some((issuer, _)) = issuer {
+            let aki = AuthorityKeyIdentifier::new()
+                .keyid(true)
+                .build(&builder.x509v3_context(Some(issuer), None))
+                .unwrap();
+            builder.append_extension(aki).unwrap();
+        }
+        let signing_key = issuer.map_or(key, |(_, key)| key);
+        builder.sign(signing_key, MessageDigest::sha256()).unwrap();
+        builder.build()
+    }
+
+    fn endorsed(name: &str, cert: &X509) -> EndorsedCert {
+        EndorsedCert {
+            format: CertFormat::X509,
+            name: name.to_string(),
+            bytes: cert.to_der().unwrap(),
+            ignore_critical: true,
+        }
+    }
+
+    #[test]
+    fn linked_chain() {
+        let (root_key, uds_key, cdi_0_key) = (key(), key(), key());
+        let root = cert("Root", &root_key, None);
+        let uds = cert("UDS", &uds_key, Some((&root, &root_key)));
+        let cdi_0 = cert("CDI_0", &cdi_0_key, Some((&uds, &uds_key)));
+        let chain = [endorsed("UDS", &uds), endorsed("CDI_0", &cdi_0)];
+        check_cert_chain(&root, &chain).unwrap();
+
+        // Out of order.
+        assert!(check_cert_chain(&root, &[chain[1].clone(), chain[0].clone()]).is_err());
+        // Another root.
+        let other_root = cert("Root", &key(), None);
+        assert!(check_cert_chain(&other_root, &chain).is_err());
+    }
+
+    #[test]
+    fn bad_signature() {
+        let root_key = key();
+        let root = cert("Root", &root_key, None);
+        // Names and key identifiers match, but another key signed the cert.
+        let uds = endorsed("UDS", &cert("UDS", &key(), Some((&root, &key()))));
+        assert!(check_cert_chain(&root, &[uds]).is_err());
+
+        let garbage = EndorsedCert {
+            bytes: vec![0x30, 0x03, 0x02, 0x01, 0x00],
+            ..endorsed("CDI_0", &root)
+        };
+        assert!(check_cert_chain(&root, &[garbage]).is_err());
+    }
+}
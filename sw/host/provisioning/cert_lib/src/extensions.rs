@@ -0,0 +1,242 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Human-readable decoding of the extensions of the device certificates.
+//!
+//! The DICE certificates exported by a device carry the measurements of its firmware in a DICE
+//! TCB info extension, which is opaque DER in the certificate bytes. The fields of the OpenTitan
+//! extensions, and the key identifiers linking the certificates, are decoded into a
+//! `CertExtensions` for the report. DICE certificates carry no dedicated device ID extension: a
+//! certificate is identified by its subject key ID instead.
+
+use serde::Serialize;
+use serde_json::{json, Value as JsonValue};
+
+use ot_certs::template::{
+    Certificate, CertificateExtension, DiceTcbInfoExtension, DiceTcbInfoFlags, HashAlgorithm, Value,
+};
+
+/// A firmware measurement of the DICE TCB info extension.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct FirmwareMeasurement {
+    pub hash_algorithm: HashAlgorithm,
+    /// Hex encoded digest.
+    pub digest: String,
+}
+
+/// Flags of the DICE TCB info extension.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct TcbFlags {
+    pub not_configured: bool,
+    pub not_secure: bool,
+    pub recovery: bool,
+    pub debug: bool,
+}
+
+/// Decoded DICE TCB info extension. The fields absent from the extension are omitted.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct TcbInfo {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub vendor: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+    /// Security version number, in decimal.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub svn: Option<String>,
+    /// DICE layer, in decimal.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub layer: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub fw_measurements: Vec<FirmwareMeasurement>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub flags: Option<TcbFlags>,
+}
+
+/// Decoded extensions of a certificate, as recorded in the report.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CertExtensions {
+    /// Hex encoded subject key ID.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject_key_id: Option<String>,
+    /// Hex encoded authority key ID.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub authority_key_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub tcb_info: Option<TcbInfo>,
+}
+
+/// Returns the value of a parsed field; fields of a parsed certificate are always literals.
+fn literal<T>(value: &Value<T>) -> Option<&T> {
+    match value {
+        Value::Literal(v) => Some(v),
+        Value::Variable(_) => None,
+    }
+}
+
+fn decode_flags(flags: &DiceTcbInfoFlags) -> Option<TcbFlags> {
+    Some(TcbFlags {
+        not_configured: *literal(&flags.not_configured)?,
+        not_secure: *literal(&flags.not_secure)?,
+        recovery: *literal(&flags.recovery)?,
+        debug: *literal(&flags.debug)?,
+    })
+}
+
+impl TcbInfo {
+    pub fn decode(ext: &DiceTcbInfoExtension) -> Self {
+        TcbInfo {
+            vendor: ext.vendor.as_ref().and_then(literal).cloned(),
+            model: ext.model.as_ref().and_then(literal).cloned(),
+            version: ext.version.as_ref().and_then(literal).cloned(),
+            svn: ext.svn.as_ref().and_then(literal).map(|v| v.to_string()),
+            layer: ext.layer.as_ref().and_then(literal).map(|v| v.to_string()),
+            fw_measurements: ext
+                .fw_ids
+                .iter()
+                .flatten()
+                .filter_map(|id| {
+                    Some(FirmwareMeasurement {
+                        hash_algorithm: id.hash_algorithm,
+                        digest: hex::encode(literal(&id.digest)?),
+                    })
+                })
+                .collect(),
+            flags: ext.flags.as_ref().and_then(decode_flags),
+        }
+    }
+}
+
+impl CertExtensions {
+    /// Decodes the extensions of a certificate parsed with `ot_certs::x509::parse_certificate`.
+    pub fn decode(cert: &Certificate) -> Self {
+        let key_id = |id: &Option<Value<Vec<u8>>>| id.as_ref().and_then(literal).map(hex::encode);
+        CertExtensions {
+            subject_key_id: key_id(&cert.subject_key_identifier),
+            authority_key_id: key_id(&cert.authority_key_identifier),
+            tcb_info: cert.private_extensions.iter().find_map(|ext| match ext {
+                CertificateExtension::DiceTcbInfo(tcb) => Some(TcbInfo::decode(tcb)),
+            }),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        *self == CertExtensions::default()
+    }
+}
+
+/// Returns the JSON Schema of the decoded certificate extensions of the report.
+pub fn cert_extensions_schema() -> JsonValue {
+    let hex = json!({ "type": "string", "pattern": "^[0-9a-f]*$" });
+    let decimal = json!({ "type": "string", "pattern": "^[0-9]+$" });
+    let tcb_info = json!({
+        "type": "object",
+        "properties": {
+            "vendor": { "type": "string" },
+            "model": { "type": "string" },
+            "version": { "type": "string" },
+            "svn": decimal,
+            "layer": decimal,
+            "fw_measurements": {
+                "type": "array",
+                "items": {
+                    "type": "object",
+                    "required": ["hash_algorithm", "digest"],
+                    "properties": {
+                        "hash_algorithm": { "enum": ["sha256"] },
+                        "digest": hex
+                    }
+                }
+            },
+            "flags": {
+                "type": "object",
+                "required": ["not_configured", "not_secure", "recovery", "debug"],
+                "properties": {
+                    "not_configured": { "type": "boolean" },
+                    "not_secure": { "type": "boolean" },
+                    "recovery": { "type": "boolean" },
+                    "debug": { "type": "boolean" }
+                }
+            }
+        }
+    });
+    json!({
+        "type": "object",
+        "additionalProperties": {
+            "type": "object",
+            "properties": {
+                "subject_key_id": hex,
+                "authority_key_id": hex,
+                "tcb_info": tcb_info
+            }
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use num_bigint_dig::BigUint;
+    use ot_certs::template::FirmwareId;
+
+    fn tcb_info() -> DiceTcbInfoExtension {
+        DiceTcbInfoExtension {
+            model: Some(Value::Literal("ROM_EXT".into())),
+            vendor: Some(Value::Literal("OpenTitan".into())),
+            version: None,
+            svn: Some(Value::Literal(BigUint::from(3u32))),
+            layer: Some(Value::Literal(BigUint::from(1u32))),
+            fw_ids: Some(vec![FirmwareId {
+                hash_algorithm: HashAlgorithm::Sha256,
+                digest: Value::Literal(vec![0xde, 0xad, 0xbe, 0xef]),
+            }]),
+            flags: Some(DiceTcbInfoFlags {
+                not_configured: Value::Literal(false),
+                not_secure: Value::Literal(false),
+                recovery: Value::Literal(false),
+                debug: Value::Literal(true),
+            }),
+        }
+    }
+
+    #[test]
+    fn decode_tcb_info() {
+        let decoded = TcbInfo::decode(&tcb_info());
+        assert_eq!(decoded.vendor.as_deref(), Some("OpenTitan"));
+        assert_eq!(decoded.model.as_deref(), Some("ROM_EXT"));
+        assert_eq!(decoded.version, None);
+        assert_eq!(decoded.svn.as_deref(), Some("3"));
+        assert_eq!(decoded.layer.as_deref(), Some("1"));
+        assert_eq!(
+            decoded.fw_measurements,
+            vec![FirmwareMeasurement {
+                hash_algorithm: HashAlgorithm::Sha256,
+                digest: "deadbeef".into(),
+            }]
+        );
+        assert!(decoded.flags.unwrap().debug);
+    }
+
+    #[test]
+    fn decode_tcb_info_omits_variables() {
+        let mut ext = tcb_info();
+        ext.svn = Some(Value::variable("svn"));
+        let json = serde_json::to_value(TcbInfo::decode(&ext)).unwrap();
+        assert!(json.get("svn").is_none());
+        assert!(json.get("version").is_none());
+        assert_eq!(json["fw_measurements"][0]["hash_algorithm"], "sha256");
+    }
+
+    #[test]
+    fn empty_extensions() {
+        assert!(CertExtensions::default().is_empty());
+        assert!(!CertExtensions {
+            subject_key_id: Some("01".into()),
+            ..Default::default()
+        }
+        .is_empty());
+    }
+}
@@ -2,18 +2,18 @@
 // Licensed under the Apache License, Version 2.0, see LICENSE for details.
 // SPDX-License-Identifier: Apache-2.0
 
+use std::fmt;
 use std::fs::{self, OpenOptions};
 use std::io::{Read, Write};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::process::Command;
+use std::rc::Rc;
 
 use anyhow::{bail, Context, Result};
 use base64ct::{Base64, Encoding};
-use elliptic_curve::SecretKey;
 use num_bigint_dig::BigUint;
 use openssl::ecdsa::EcdsaSig;
-use p256::ecdsa::SigningKey;
-use p256::NistP256;
+use openssl::x509::X509;
 use serde::{Deserialize, Serialize, Serializer};
 
 use opentitanlib::crypto::sha256::sha256;
@@ -21,23 +21,76 @@ use opentitanlib::util::tmpfilename;
 use ot_certs::template::{EcdsaSignature, Signature, Value};
 use ot_certs::x509::generate_certificate_from_tbs;
 use ot_certs::CertFormat;
+use provisioning::host_key::{HostKey, HostKeySpec};
+
+pub mod attestation;
+pub mod chain;
+pub mod extensions;
+pub mod offline;
+pub mod piv;
+pub mod policy;
+pub mod pubkey;
+#[cfg(test)]
+mod test_certs;
+
+use piv::PivKey;
 
 /// Certificate Authority key type.
 #[derive(Debug, Clone, Deserialize)]
 pub enum CaKeyType {
     Raw,
     Token,
+    Piv,
 }
 
 /// Certificate Authority key input formats.
 ///
 /// The following ECC P256 private key representations are supported:
-///   1. RawKey: provided as a file path pointing to a DER encoded key file.
+///   1. HostKey: the key of a CA with a `Raw` key type, see `HostCaKey`.
 ///   2. TokenKey: provided as a PKCS#11 token ID string.
+///   3. PivKey: held in a PIV slot of a hardware token, see `piv`.
 #[derive(Debug, Clone)]
 pub enum CaKey {
-    RawKey(SecretKey<NistP256>),
+    HostKey(HostCaKey),
     TokenKey(String),
+    PivKey(PivKey),
+}
+
+/// A CA key given as a host key, see `provisioning::host_key`: a PKCS#8 DER key file, or a key
+/// which never leaves the HSM or cloud KMS keeping it.
+#[derive(Clone)]
+pub struct HostCaKey {
+    spec: HostKeySpec,
+    key: Rc<dyn HostKey>,
+}
+
+impl fmt::Debug for HostCaKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never print the key itself.
+        f.debug_struct("HostCaKey")
+            .field("spec", &format_args!("{}", self.spec))
+            .finish_non_exhaustive()
+    }
+}
+
+impl HostCaKey {
+    /// Loads the key file, or opens the key of the HSM or KMS, `spec`.
+    pub fn load(spec: &HostKeySpec) -> Result<Self> {
+        Ok(Self {
+            spec: spec.clone(),
+            key: Rc::from(spec.load()?),
+        })
+    }
+
+    /// Returns where the key is.
+    pub fn spec(&self) -> &HostKeySpec {
+        &self.spec
+    }
+
+    /// Returns the key.
+    pub fn key(&self) -> &dyn HostKey {
+        &*self.key
+    }
 }
 
 /// Certificate Authority (CA) parameters.
@@ -49,7 +102,7 @@ pub struct CaConfig {
     pub key_id: String,
     /// CA key type.
     pub key_type: CaKeyType,
-    /// CA key (file path to raw key DER file or Cloud KMS key ID).
+    /// CA key (file path to raw key DER file, Cloud KMS key ID or PIV slot).
     pub key: String,
 }
 
@@ -96,22 +149,20 @@ pub fn get_cert_size(cert: &[u8]) -> Result<usize> {
 pub fn parse_and_endorse_x509_cert(tbs: Vec<u8>, key: &CaKey) -> Result<Vec<u8>> {
     match key {
         CaKey::TokenKey(key_id) => parse_and_endorse_x509_cert_token(tbs, key_id),
-        CaKey::RawKey(sk) => parse_and_endorse_x509_cert_raw(tbs, sk),
+        CaKey::HostKey(key) => parse_and_endorse_x509_cert_host(tbs, key.key()),
+        CaKey::PivKey(key) => parse_and_endorse_x509_cert_piv(tbs, key),
     }
 }
 
-fn parse_and_endorse_x509_cert_raw(tbs: Vec<u8>, ca_sk: &SecretKey<NistP256>) -> Result<Vec<u8>> {
+fn parse_and_endorse_x509_cert_host(tbs: Vec<u8>, ca_key: &dyn HostKey) -> Result<Vec<u8>> {
     // Hash and sign the TBS.
-    let tbs_digest = sha256(&tbs);
-    let signing_key = SigningKey::from(ca_sk);
-    let (tbs_signature, _) = signing_key.sign_prehash_recoverable(&tbs_digest.to_be_bytes())?;
-    let (r, s) = tbs_signature.split_bytes();
+    let tbs_signature = ca_key.sign(&sha256(&tbs))?;
 
-    // Reformat the signature.
+    // Reformat the signature, whose raw components are little-endian.
     let signature = Signature::EcdsaWithSha256 {
         value: Some(EcdsaSignature {
-            r: Value::Literal(BigUint::from_bytes_be(&r)),
-            s: Value::Literal(BigUint::from_bytes_be(&s)),
+            r: Value::Literal(BigUint::from_bytes_le(&tbs_signature.r)),
+            s: Value::Literal(BigUint::from_bytes_le(&tbs_signature.s)),
         }),
     };
 
@@ -177,6 +228,26 @@ fn parse_and_endorse_x509_cert_token(tbs: Vec<u8>, key_id: &str) -> Result<Vec<u
     generate_certificate_from_tbs(tbs, &signature)
 }
 
+fn parse_and_endorse_x509_cert_piv(tbs: Vec<u8>, key: &PivKey) -> Result<Vec<u8>> {
+    // Hash the TBS, and let the token sign its digest.
+    let tbs_digest = sha256(&tbs);
+    let asn1_sig = key.sign_prehash(&tbs_digest.to_be_bytes())?;
+
+    // Parse the ASN.1 string into signature components.
+    let ecdsa_sig =
+        EcdsaSig::from_der(&asn1_sig).context("cannot extract ECDSA signature from blob")?;
+
+    let signature = Signature::EcdsaWithSha256 {
+        value: Some(EcdsaSignature {
+            r: Value::Literal(BigUint::from_bytes_be(&ecdsa_sig.r().to_vec())),
+            s: Value::Literal(BigUint::from_bytes_be(&ecdsa_sig.s().to_vec())),
+        }),
+    };
+
+    // Generate the (endorsed) certificate.
+    generate_certificate_from_tbs(tbs, &signature)
+}
+
 fn write_cert_to_temp_pem_file(der_cert_bytes: &[u8], base_filename: &str) -> Result<String> {
     // Build temp file names for DER and PEM cert files.
     let base_name = tmpfilename(base_filename);
@@ -295,6 +366,35 @@ pub fn validate_cert_chain(ca_pem: &str, cert_chain: &[EndorsedCert]) -> Result<
     Ok(())
 }
 
+/// Writes each X.509 certificate to `<dir>/<device_id>_<cert name>.der` and `.pem`, and returns
+/// the paths written. Certificates in other formats are skipped.
+pub fn export_certs<'a>(
+    certs: impl IntoIterator<Item = &'a EndorsedCert>,
+    device_id: &str,
+    dir: &Path,
+) -> Result<Vec<PathBuf>> {
+    fs::create_dir_all(dir).with_context(|| format!("failed to create cert export dir {dir:?}"))?;
+    let mut paths = Vec::new();
+    for cert in certs {
+        if !matches!(cert.format, CertFormat::X509) {
+            log::info!("Skipping export of {} cert, not an X.509 cert", cert.name);
+            continue;
+        }
+        let x509 = X509::from_der(&cert.bytes)
+            .with_context(|| format!("failed to parse {} cert", cert.name))?;
+        let stem = format!("{device_id}_{}", cert.name);
+        for (path, bytes) in [
+            (dir.join(format!("{stem}.der")), x509.to_der()?),
+            (dir.join(format!("{stem}.pem")), x509.to_pem()?),
+        ] {
+            fs::write(&path, bytes).with_context(|| format!("failed to write {path:?}"))?;
+            paths.push(path);
+        }
+        log::info!("Exported {} cert to {dir:?}", cert.name);
+    }
+    Ok(paths)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -0,0 +1,135 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Device certificates issued offline.
+//!
+//! Some CAs cannot be reached from the line. For those, the device public keys exported by a
+//! previous run (see `pubkey`) are sent to the CA, which issues the device certificates offline.
+//! The FT station then injects the supplied certificates instead of endorsing the TBS produced by
+//! the device, after checking each one certifies the key and subject of that TBS.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{ensure, Context, Result};
+use openssl::x509::X509;
+
+use ot_certs::template::Signature;
+use ot_certs::x509::generate_certificate_from_tbs;
+
+/// Directory of device certificates issued offline.
+///
+/// Certificates are looked up as `<dir>/<device_id>_<cert name>.der`, as named by `export_certs`.
+#[derive(Clone, Debug)]
+pub struct OfflineCerts {
+    dir: PathBuf,
+}
+
+impl OfflineCerts {
+    pub fn new(dir: &Path) -> Result<Self> {
+        ensure!(
+            dir.is_dir(),
+            "Offline certificates directory {dir:?} does not exist"
+        );
+        Ok(Self {
+            dir: dir.to_path_buf(),
+        })
+    }
+
+    /// Returns the path of the `cert_name` certificate of `device_id`.
+    pub fn path(&self, device_id: &str, cert_name: &str) -> PathBuf {
+        self.dir.join(format!("{device_id}_{cert_name}.der"))
+    }
+
+    /// Returns the `cert_name` certificate of `device_id` to inject in place of the endorsement
+    /// of `tbs`, the TBS produced by the device.
+    pub fn endorse(&self, device_id: &str, cert_name: &str, tbs: &[u8]) -> Result<Vec<u8>> {
+        let path = self.path(device_id, cert_name);
+        let bytes = std::fs::read(&path)
+            .with_context(|| format!("No offline {cert_name} cert for device {device_id}"))?;
+        let cert = X509::from_der(&bytes)
+            .with_context(|| format!("Failed to parse offline {cert_name} cert {path:?}"))?;
+        check_matches_tbs(&cert, tbs).with_context(|| {
+            format!("Offline {cert_name} cert {path:?} does not match the TBS of the device")
+        })?;
+        log::info!("Using offline {cert_name} cert {path:?}");
+        Ok(cert.to_der()?)
+    }
+}
+
+/// Checks that `cert` certifies the subject and public key of `tbs`.
+///
+/// The rest of the TBS, e.g. its validity window, is up to the CA which issued `cert`.
+fn check_matches_tbs(cert: &X509, tbs: &[u8]) -> Result<()> {
+    // Wrap the TBS in a certificate with an empty signature for OpenSSL to parse it.
+    let tbs =
+        generate_certificate_from_tbs(tbs.to_vec(), &Signature::EcdsaWithSha256 { value: None })?;
+    let tbs = X509::from_der(&tbs).context("Failed to parse the TBS")?;
+    ensure!(
+        cert.subject_name().to_der()? == tbs.subject_name().to_der()?,
+        "subject name does not match"
+    );
+    let public_key = tbs.public_key()?;
+    ensure!(
+        cert.public_key()?.public_eq(&public_key),
+        "public key does not match"
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::test_certs::{cert, key};
+
+    /// Returns the TBS of `cert`, the first element of its SEQUENCE.
+    fn tbs(cert: &X509) -> Vec<u8> {
+        fn header(der: &[u8]) -> (usize, usize) {
+            match der[1] {
+                len @ 0..=0x7f => (2, len as usize),
+                n => {
+                    let n = (n & 0x7f) as usize;
+                    let len = der[2..2 + n]
+                        .iter()
+                        .fold(0, |len, b| len << 8 | *b as usize);
+                    (2 + n, len)
+                }
+            }
+        }
+        let der = cert.to_der().unwrap();
+        let (cert_header, _) = header(&der);
+        let (tbs_header, tbs_len) = header(&der[cert_header..]);
+        der[cert_header..cert_header + tbs_header + tbs_len].to_vec()
+    }
+
+    #[test]
+    fn matching_cert() {
+        let device_key = key();
+        let device_tbs = tbs(&cert("UDS", &device_key, &device_key));
+        let dir = std::env::temp_dir().join(format!("offline_certs_{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let offline = OfflineCerts::new(&dir).unwrap();
+
+        // Issued by the CA for the key and subject of the device.
+        let issued = cert("UDS", &device_key, &key());
+        std::fs::write(offline.path("0123", "UDS"), issued.to_der().unwrap()).unwrap();
+        assert_eq!(
+            offline.endorse("0123", "UDS", &device_tbs).unwrap(),
+            issued.to_der().unwrap()
+        );
+        // Another device.
+        assert!(offline.endorse("4567", "UDS", &device_tbs).is_err());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn mismatching_cert() {
+        let device_key = key();
+        let device_tbs = tbs(&cert("UDS", &device_key, &device_key));
+        let ca_key = key();
+        assert!(check_matches_tbs(&cert("UDS", &key(), &ca_key), &device_tbs).is_err());
+        assert!(check_matches_tbs(&cert("CDI_0", &device_key, &ca_key), &device_tbs).is_err());
+    }
+}
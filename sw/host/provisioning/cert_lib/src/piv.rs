@@ -0,0 +1,291 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! CA keys held in a PIV slot of a hardware token, e.g. a YubiKey or a Nitrokey, for small-volume
+//! labs protecting their personalization keys without a full HSM.
+//!
+//! The token is accessed over PC/SC. A PIV key is referenced by its slot, optionally followed by
+//! the name (or part of the name) of the reader holding the token, e.g. `9c` or `9c@YubiKey`.
+//! Only ECC P-256 keys are supported.
+//!
+//! Tokens may require the operator to touch them for each signature (the touch policy of the
+//! slot). The touch policy is read from the slot metadata where the token reports it (YubiKey),
+//! and the operator is prompted before each signature that needs a touch, or may need one.
+
+use std::ffi::CString;
+use std::fmt;
+
+use anyhow::{bail, ensure, Context as _, Result};
+use pcsc::{Card, Context, Protocols, Scope, ShareMode, MAX_BUFFER_SIZE};
+
+/// Application ID of the PIV application.
+const PIV_AID: [u8; 5] = [0xa0, 0x00, 0x00, 0x03, 0x08];
+
+/// PIV algorithm identifier of ECC P-256 keys.
+const ALG_ECC_P256: u8 = 0x11;
+
+/// PIV key reference of the application PIN.
+const PIN_REF: u8 = 0x80;
+
+/// Status words.
+const SW_OK: u16 = 0x9000;
+const SW_SECURITY_STATUS: u16 = 0x6982;
+const SW_PIN_BLOCKED: u16 = 0x6983;
+
+/// Touch policy of a PIV slot, as reported by the slot metadata.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum TouchPolicy {
+    Never,
+    Always,
+    Cached,
+}
+
+/// A P-256 key in a PIV slot of a hardware token.
+#[derive(Clone)]
+pub struct PivKey {
+    slot: u8,
+    reader: Option<String>,
+    pin: Option<String>,
+}
+
+impl fmt::Debug for PivKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // Never print the PIN.
+        f.debug_struct("PivKey")
+            .field("slot", &format_args!("{:02x}", self.slot))
+            .field("reader", &self.reader)
+            .finish_non_exhaustive()
+    }
+}
+
+impl PivKey {
+    /// Parses a PIV key reference, `<slot>[@<reader>]`, of a key unlocked with `pin`.
+    pub fn new(key: &str, pin: Option<String>) -> Result<Self> {
+        let (slot, reader) = match key.split_once('@') {
+            Some((slot, reader)) => (slot, Some(reader.to_string())),
+            None => (key, None),
+        };
+        let slot = u8::from_str_radix(slot, 16)
+            .with_context(|| format!("Invalid PIV slot {slot:?} in key {key:?}"))?;
+        // Authentication, signature, key management, card authentication and retired slots.
+        ensure!(
+            matches!(slot, 0x9a | 0x9c | 0x9d | 0x9e | 0x82..=0x95),
+            "PIV slot {slot:02x} does not hold a private key"
+        );
+        Ok(Self { slot, reader, pin })
+    }
+
+    /// Signs the SHA256 `digest` of a message, returning the ASN.1 DER ECDSA signature.
+    pub fn sign_prehash(&self, digest: &[u8]) -> Result<Vec<u8>> {
+        ensure!(digest.len() == 32, "Expected a SHA256 digest");
+        let (mut card, reader) = self.connect()?;
+        let card = card.transaction().context("Failed to lock the PIV token")?;
+        select(&card)?;
+        let touch = match metadata(&card, self.slot)? {
+            Some((algorithm, touch)) => {
+                ensure!(
+                    algorithm == ALG_ECC_P256,
+                    "PIV slot {:02x} does not hold an ECC P-256 key (algorithm {algorithm:#04x})",
+                    self.slot
+                );
+                touch
+            }
+            // Not all tokens report the slot metadata; assume a touch may be needed.
+            None => None,
+        };
+        if let Some(pin) = &self.pin {
+            verify_pin(&card, pin)?;
+        }
+
+        // GENERAL AUTHENTICATE, with an empty response template and the digest as challenge.
+        let mut template = vec![0x82, 0x00, 0x81, digest.len() as u8];
+        template.extend_from_slice(digest);
+        let mut data = vec![0x7c, template.len() as u8];
+        data.extend_from_slice(&template);
+        let mut apdu = vec![0x00, 0x87, ALG_ECC_P256, self.slot, data.len() as u8];
+        apdu.extend_from_slice(&data);
+        apdu.push(0x00);
+
+        match touch {
+            Some(TouchPolicy::Never) => {}
+            Some(TouchPolicy::Cached) => log::warn!(
+                "Touch the PIV token in {reader:?} if it is flashing, to sign with slot {:02x}.",
+                self.slot
+            ),
+            _ => log::warn!(
+                "Touch the PIV token in {reader:?} to sign with slot {:02x}.",
+                self.slot
+            ),
+        }
+        let (response, sw) = transmit(&card, &apdu)?;
+        match sw {
+            SW_OK => {}
+            SW_SECURITY_STATUS if self.pin.is_none() => bail!(
+                "PIV slot {:02x} requires the PIN of the token; it is not set",
+                self.slot
+            ),
+            SW_SECURITY_STATUS => bail!(
+                "PIV token in {reader:?} did not sign with slot {:02x}: touch not detected in time",
+                self.slot
+            ),
+            _ => bail!(
+                "PIV token in {reader:?} failed to sign with slot {:02x} (status {sw:04x})",
+                self.slot
+            ),
+        }
+        let template = tlv(&response, 0x7c).context("Malformed PIV signature response")?;
+        let signature = tlv(template, 0x82).context("Malformed PIV signature response")?;
+        Ok(signature.to_vec())
+    }
+
+    /// Connects to the token holding the key, returning it and the name of its reader.
+    fn connect(&self) -> Result<(Card, String)> {
+        let context = Context::establish(Scope::User).context("Failed to connect to PC/SC")?;
+        let readers = context
+            .list_readers_owned()
+            .context("Failed to list the PC/SC readers")?;
+        let names = readers
+            .iter()
+            .map(|r| r.to_string_lossy().into_owned())
+            .collect::<Vec<_>>();
+        let matching = names
+            .iter()
+            .zip(&readers)
+            .filter(|(name, _)| self.reader.as_deref().is_none_or(|r| name.contains(r)))
+            .collect::<Vec<_>>();
+        let (name, reader): (&String, &CString) = match matching.as_slice() {
+            [reader] => *reader,
+            [] => bail!(
+                "No PC/SC reader matching {:?} found; is the PIV token plugged in? Readers: {names:?}",
+                self.reader.as_deref().unwrap_or("")
+            ),
+            _ => bail!(
+                "Multiple PC/SC readers found, select the PIV token with <slot>@<reader>: {names:?}"
+            ),
+        };
+        let card = context
+            .connect(reader, ShareMode::Shared, Protocols::ANY)
+            .with_context(|| format!("Failed to connect to the PIV token in {name:?}"))?;
+        Ok((card, name.clone()))
+    }
+}
+
+/// Sends `apdu` to `card`, returning the (possibly chained) response data and status word.
+fn transmit(card: &Card, apdu: &[u8]) -> Result<(Vec<u8>, u16)> {
+    let mut buf = [0u8; MAX_BUFFER_SIZE];
+    let mut data = Vec::new();
+    let mut apdu = apdu.to_vec();
+    loop {
+        let response = card
+            .transmit(&apdu, &mut buf)
+            .context("PIV token APDU failed")?;
+        ensure!(response.len() >= 2, "Truncated PIV token response");
+        let (body, sw) = response.split_at(response.len() - 2);
+        data.extend_from_slice(body);
+        // 61xx: more response data is available, fetched with GET RESPONSE.
+        if sw[0] != 0x61 {
+            return Ok((data, u16::from_be_bytes([sw[0], sw[1]])));
+        }
+        apdu = vec![0x00, 0xc0, 0x00, 0x00, sw[1]];
+    }
+}
+
+/// Selects the PIV application.
+fn select(card: &Card) -> Result<()> {
+    let mut apdu = vec![0x00, 0xa4, 0x04, 0x00, PIV_AID.len() as u8];
+    apdu.extend_from_slice(&PIV_AID);
+    apdu.push(0x00);
+    let (_, sw) = transmit(card, &apdu)?;
+    ensure!(
+        sw == SW_OK,
+        "Token has no PIV application (status {sw:04x})"
+    );
+    Ok(())
+}
+
+/// Returns the algorithm and the touch policy of `slot`, if the token reports them (GET METADATA,
+/// a YubiKey extension).
+fn metadata(card: &Card, slot: u8) -> Result<Option<(u8, Option<TouchPolicy>)>> {
+    let (response, sw) = transmit(card, &[0x00, 0xf7, 0x00, slot, 0x00])?;
+    if sw != SW_OK {
+        return Ok(None);
+    }
+    let algorithm = tlv_find(&response, 0x01)
+        .and_then(|v| v.first().copied())
+        .context("PIV slot metadata lacks the key algorithm")?;
+    let touch = tlv_find(&response, 0x02)
+        .and_then(|v| v.get(1))
+        .and_then(|policy| match policy {
+            0x01 => Some(TouchPolicy::Never),
+            0x02 => Some(TouchPolicy::Always),
+            0x03 => Some(TouchPolicy::Cached),
+            _ => None,
+        });
+    Ok(Some((algorithm, touch)))
+}
+
+/// Verifies the PIN of the token.
+fn verify_pin(card: &Card, pin: &str) -> Result<()> {
+    ensure!(
+        (6..=8).contains(&pin.len()),
+        "The PIV PIN must be 6 to 8 characters long"
+    );
+    // The PIN is padded to 8 bytes with 0xff.
+    let mut apdu = vec![0x00, 0x20, 0x00, PIN_REF, 0x08];
+    apdu.extend_from_slice(pin.as_bytes());
+    apdu.resize(5 + 8, 0xff);
+    let (_, sw) = transmit(card, &apdu)?;
+    match sw {
+        SW_OK => Ok(()),
+        SW_PIN_BLOCKED => bail!("The PIV PIN is blocked; the token must be unblocked with its PUK"),
+        sw if sw & 0xfff0 == 0x63c0 => bail!(
+            "Wrong PIV PIN, {} retries left before the PIN is blocked",
+            sw & 0xf
+        ),
+        _ => bail!("PIV PIN verification failed (status {sw:04x})"),
+    }
+}
+
+/// Decodes the BER-TLV at the start of `data`, which must be tagged `tag`.
+fn tlv(data: &[u8], tag: u8) -> Result<&[u8]> {
+    let (actual, value, _) = tlv_next(data).context("Truncated TLV")?;
+    ensure!(
+        actual == tag,
+        "Expected TLV tag {tag:#04x}, got {actual:#04x}"
+    );
+    Ok(value)
+}
+
+/// Returns the value of the first TLV tagged `tag` in a list of TLVs.
+fn tlv_find(mut data: &[u8], tag: u8) -> Option<&[u8]> {
+    while !data.is_empty() {
+        let (actual, value, rest) = tlv_next(data)?;
+        if actual == tag {
+            return Some(value);
+        }
+        data = rest;
+    }
+    None
+}
+
+/// Splits the BER-TLV, with a single byte tag, at the start of `data` from the rest of `data`.
+fn tlv_next(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, data) = data.split_first()?;
+    let (&len, mut data) = data.split_first()?;
+    let len = match len {
+        0x81 => {
+            let (&len, rest) = data.split_first()?;
+            data = rest;
+            len as usize
+        }
+        0x82 => {
+            let len = u16::from_be_bytes([*data.first()?, *data.get(1)?]);
+            data = &data[2..];
+            len as usize
+        }
+        len if len < 0x80 => len as usize,
+        _ => return None,
+    };
+    (data.len() >= len).then(|| (tag, &data[..len], &data[len..]))
+}
@@ -0,0 +1,265 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Signing policy of the device certificates endorsed by the host.
+//!
+//! The device produces the TBS of its certificates and the host only signs them: changing a TBS
+//! would invalidate the certificate hashes the device keeps. The policy is therefore checked, not
+//! applied: the host refuses to endorse a TBS whose validity window, serial number or path length
+//! constraint is outside the policy.
+//!
+//! Policies are JSON files. The top-level fields apply to all the certificates, unless the cert
+//! has its own entry in `certs`, which replaces them:
+//!
+//! ```json
+//! {
+//!   "not_before": "20180101000000Z",
+//!   "not_after": "99991231235959Z",
+//!   "serial_number": "key-id",
+//!   "max_path_len": 2,
+//!   "certs": { "CDI_1": { "serial_number": "key-id", "max_path_len": 0 } }
+//! }
+//! ```
+
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{ensure, Context, Result};
+use openssl::asn1::{Asn1Time, Asn1TimeRef};
+use openssl::nid::Nid;
+use openssl::x509::X509;
+use serde::Deserialize;
+
+use ot_certs::template::{Signature, Value};
+use ot_certs::x509::extension;
+use ot_certs::x509::generate_certificate_from_tbs;
+
+/// Serial number schemes of the device certificates.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum SerialNumberScheme {
+    /// Any positive serial number.
+    #[default]
+    Any,
+    /// The subject key identifier with its most significant bit set, as the DICE certificate
+    /// templates derive it.
+    KeyId,
+}
+
+/// Policy of a device certificate.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct CertPolicy {
+    /// Earliest not-before time, as an ASN.1 GeneralizedTime, e.g. `20180101000000Z`.
+    pub not_before: Option<String>,
+    /// Latest not-after time, as an ASN.1 GeneralizedTime.
+    pub not_after: Option<String>,
+    #[serde(default)]
+    pub serial_number: SerialNumberScheme,
+    /// Largest path length constraint of CA certificates. CA certificates without a path length
+    /// constraint are refused.
+    pub max_path_len: Option<u32>,
+}
+
+impl CertPolicy {
+    /// Checks `cert` against the policy.
+    pub fn check(&self, cert: &X509) -> Result<()> {
+        if let Some(not_before) = &self.not_before {
+            ensure!(
+                compare_time(cert.not_before(), not_before)? != Ordering::Less,
+                "not-before {} is earlier than {not_before}",
+                cert.not_before()
+            );
+        }
+        if let Some(not_after) = &self.not_after {
+            ensure!(
+                compare_time(cert.not_after(), not_after)? != Ordering::Greater,
+                "not-after {} is later than {not_after}",
+                cert.not_after()
+            );
+        }
+
+        let serial = cert.serial_number().to_bn()?;
+        ensure!(
+            !serial.is_negative() && serial.num_bits() > 0,
+            "serial number {serial} is not positive"
+        );
+        if self.serial_number == SerialNumberScheme::KeyId {
+            let mut key_id = subject_key_id(cert)?.context("no subject key identifier")?;
+            if let Some(msb) = key_id.first_mut() {
+                *msb |= 0x80;
+            }
+            ensure!(
+                serial.to_vec() == key_id,
+                "serial number {} does not match the subject key identifier",
+                hex::encode(serial.to_vec())
+            );
+        }
+
+        if let Some(max_path_len) = self.max_path_len {
+            // OpenSSL only reports a path length constraint for CA certificates.
+            match cert.pathlen() {
+                Some(path_len) => ensure!(
+                    path_len <= max_path_len,
+                    "path length constraint {path_len} is larger than {max_path_len}"
+                ),
+                None => ensure!(
+                    !is_ca(cert)?,
+                    "CA certificate has no path length constraint, at most {max_path_len} is allowed"
+                ),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Signing policy of the device certificates, see the module documentation.
+#[derive(Clone, Debug, Default, Deserialize)]
+pub struct SigningPolicy {
+    #[serde(flatten)]
+    pub default: CertPolicy,
+    /// Policies of specific certificates, by cert name.
+    #[serde(default)]
+    pub certs: HashMap<String, CertPolicy>,
+}
+
+impl SigningPolicy {
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read signing policy {path:?}"))?;
+        let policy: Self = serde_json::from_str(&json)
+            .with_context(|| format!("Failed to parse signing policy {path:?}"))?;
+        // Catch malformed times before the first device is provisioned.
+        for cert in std::iter::once(&policy.default).chain(policy.certs.values()) {
+            for time in cert.not_before.iter().chain(&cert.not_after) {
+                Asn1Time::from_str(time)
+                    .with_context(|| format!("Invalid time {time:?} in signing policy {path:?}"))?;
+            }
+        }
+        Ok(policy)
+    }
+
+    /// Returns the policy of the `cert_name` certificate.
+    pub fn cert_policy(&self, cert_name: &str) -> &CertPolicy {
+        self.certs.get(cert_name).unwrap_or(&self.default)
+    }
+
+    /// Checks the TBS of the `cert_name` certificate, as produced by the device, against the
+    /// policy before it is endorsed.
+    pub fn check_tbs(&self, cert_name: &str, tbs: &[u8]) -> Result<()> {
+        // Wrap the TBS in a certificate with an empty signature for OpenSSL to parse it.
+        let cert = generate_certificate_from_tbs(
+            tbs.to_vec(),
+            &Signature::EcdsaWithSha256 { value: None },
+        )?;
+        let cert =
+            X509::from_der(&cert).with_context(|| format!("Failed to parse {cert_name} TBS"))?;
+        self.cert_policy(cert_name)
+            .check(&cert)
+            .with_context(|| format!("{cert_name} TBS violates the signing policy"))
+    }
+}
+
+fn compare_time(time: &Asn1TimeRef, bound: &str) -> Result<Ordering> {
+    let bound =
+        Asn1Time::from_str(bound).with_context(|| format!("Invalid time {bound:?} in policy"))?;
+    Ok(time.compare(&bound)?)
+}
+
+fn subject_key_id(cert: &X509) -> Result<Option<Vec<u8>>> {
+    // OpenSSL may hide the key identifiers of unusual certificates, parse them directly.
+    extension::x509_get_extensions(cert)?
+        .iter()
+        .find(|ext| ext.object.nid() == Nid::SUBJECT_KEY_IDENTIFIER)
+        .map(extension::parse_subject_key_id)
+        .transpose()
+}
+
+fn is_ca(cert: &X509) -> Result<bool> {
+    let Some(ext) = extension::x509_get_extensions(cert)?
+        .into_iter()
+        .find(|ext| ext.object.nid() == Nid::BASIC_CONSTRAINTS)
+    else {
+        return Ok(false);
+    };
+    let constraints = extension::parse_basic_constraints(&ext)?;
+    Ok(matches!(constraints.ca, Value::Literal(true)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use openssl::asn1::{Asn1Object, Asn1OctetString};
+    use openssl::bn::BigNum;
+    use openssl::hash::MessageDigest;
+    use openssl::x509::extension::BasicConstraints;
+    use openssl::x509::X509Extension;
+
+    use crate::test_certs::{cert_builder, key};
+
+    const KEY_ID: [u8; 20] = [0x12; 20];
+
+    fn cert(serial: &[u8], path_len: Option<u32>) -> X509 {
+        let key = key();
+        let mut builder = cert_builder("UDS", &key);
+        let serial = BigNum::from_slice(serial).unwrap();
+        builder
+            .set_serial_number(&serial.to_asn1_integer().unwrap())
+            .unwrap();
+        let mut constraints = BasicConstraints::new();
+        constraints.critical().ca();
+        if let Some(path_len) = path_len {
+            constraints.pathlen(path_len);
+        }
+        builder
+            .append_extension(constraints.build().unwrap())
+            .unwrap();
+        // KeyIdentifier ::= OCTET STRING
+        let mut ski = vec![0x04, KEY_ID.len() as u8];
+        ski.extend_from_slice(&KEY_ID);
+        let ski = X509Extension::new_from_der(
+            &Asn1Object::from_str("2.5.29.14").unwrap(),
+            false,
+            &Asn1OctetString::new_from_bytes(&ski).unwrap(),
+        )
+        .unwrap();
+        builder.append_extension(ski).unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        builder.build()
+    }
+
+    fn check(policy: &str, cert_name: &str, cert: &X509) -> Result<()> {
+        let policy: SigningPolicy = serde_json::from_str(policy).unwrap();
+        policy.cert_policy(cert_name).check(cert)
+    }
+
+    #[test]
+    fn validity_window() {
+        let cert = cert(&KEY_ID, None);
+        let window = r#"{"not_before": "20180101000000Z", "not_after": "99991231235959Z"}"#;
+        check(window, "UDS", &cert).unwrap();
+        assert!(check(r#"{"not_before": "20200101000000Z"}"#, "UDS", &cert).is_err());
+        assert!(check(r#"{"not_after": "20491231235959Z"}"#, "UDS", &cert).is_err());
+    }
+
+    #[test]
+    fn key_id_serial_number() {
+        let policy = r#"{"serial_number": "key-id"}"#;
+        let mut serial = KEY_ID;
+        serial[0] |= 0x80;
+        check(policy, "UDS", &cert(&serial, None)).unwrap();
+        assert!(check(policy, "UDS", &cert(&KEY_ID, None)).is_err());
+        assert!(check("{}", "UDS", &cert(&[0], None)).is_err());
+    }
+
+    #[test]
+    fn path_length_per_cert() {
+        let policy = r#"{"max_path_len": 2, "certs": {"CDI_1": {"max_path_len": 0}}}"#;
+        check(policy, "UDS", &cert(&KEY_ID, Some(2))).unwrap();
+        check(policy, "CDI_1", &cert(&KEY_ID, Some(0))).unwrap();
+        assert!(check(policy, "CDI_1", &cert(&KEY_ID, Some(1))).is_err());
+        assert!(check(policy, "UDS", &cert(&KEY_ID, None)).is_err());
+    }
+}
@@ -0,0 +1,171 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use base64ct::{Base64, Base64UrlUnpadded, Encoding};
+use openssl::bn::{BigNum, BigNumContext};
+use openssl::nid::Nid;
+use openssl::x509::X509;
+use serde::Serialize;
+
+use crate::EndorsedCert;
+use ot_certs::CertFormat;
+
+/// Size of a P256 affine coordinate in bytes.
+const P256_COORD_SIZE: usize = 32;
+
+/// OpenSSH key type and curve identifiers for ECDSA P256 keys (RFC 5656).
+const OPENSSH_P256_KEY_TYPE: &str = "ecdsa-sha2-nistp256";
+const OPENSSH_P256_CURVE: &str = "nistp256";
+
+/// ECC P256 public key, stored as big-endian affine coordinates.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct EcP256PublicKey {
+    pub x: [u8; P256_COORD_SIZE],
+    pub y: [u8; P256_COORD_SIZE],
+}
+
+/// JSON Web Key (RFC 7517) representation of an EC public key.
+#[derive(Clone, Debug, Serialize)]
+struct EcJwk<'a> {
+    kty: &'a str,
+    crv: &'a str,
+    x: String,
+    y: String,
+    kid: &'a str,
+}
+
+impl EcP256PublicKey {
+    /// Extracts the subject public key of an X.509 certificate.
+    pub fn from_cert(cert: &EndorsedCert) -> Result<Self> {
+        if !matches!(cert.format, CertFormat::X509) {
+            bail!(
+                "Public key export unsupported for {} cert format",
+                cert.name
+            );
+        }
+        let x509 = X509::from_der(&cert.bytes)
+            .with_context(|| format!("failed to parse {} cert", cert.name))?;
+        let eckey = x509.public_key()?.ec_key()?;
+        if eckey.group().curve_name() != Some(Nid::X9_62_PRIME256V1) {
+            bail!("{} cert public key is not a P256 key", cert.name);
+        }
+        let mut ctx = BigNumContext::new()?;
+        let mut x = BigNum::new()?;
+        let mut y = BigNum::new()?;
+        eckey
+            .public_key()
+            .affine_coordinates(eckey.group(), &mut x, &mut y, &mut ctx)?;
+        Ok(EcP256PublicKey {
+            x: x.to_vec_padded(P256_COORD_SIZE as i32)?.try_into().unwrap(),
+            y: y.to_vec_padded(P256_COORD_SIZE as i32)?.try_into().unwrap(),
+        })
+    }
+
+    /// Encodes the key as a JWK JSON document, using `kid` as the key ID.
+    pub fn to_jwk(&self, kid: &str) -> Result<String> {
+        let jwk = EcJwk {
+            kty: "EC",
+            crv: "P-256",
+            x: Base64UrlUnpadded::encode_string(&self.x),
+            y: Base64UrlUnpadded::encode_string(&self.y),
+            kid,
+        };
+        Ok(serde_json::to_string_pretty(&jwk)?)
+    }
+
+    /// Encodes the key as an OpenSSH `authorized_keys` line, with `comment`
+    /// appended after the key blob.
+    pub fn to_openssh(&self, comment: &str) -> String {
+        let mut point = vec![0x04u8];
+        point.extend_from_slice(&self.x);
+        point.extend_from_slice(&self.y);
+
+        let mut blob = Vec::new();
+        for field in [
+            OPENSSH_P256_KEY_TYPE.as_bytes(),
+            OPENSSH_P256_CURVE.as_bytes(),
+            point.as_slice(),
+        ] {
+            blob.extend_from_slice(&(field.len() as u32).to_be_bytes());
+            blob.extend_from_slice(field);
+        }
+        format!(
+            "{} {} {}",
+            OPENSSH_P256_KEY_TYPE,
+            Base64::encode_string(&blob),
+            comment
+        )
+    }
+}
+
+/// Writes the public key of each certificate to `<dir>/<cert name>.jwk` and
+/// `<dir>/<cert name>.pub` (OpenSSH format).
+///
+/// `device_id` is used as the OpenSSH key comment and as part of the JWK key
+/// ID, so exported keys can be traced back to the device they belong to.
+pub fn export_cert_public_keys<'a>(
+    certs: impl IntoIterator<Item = &'a EndorsedCert>,
+    device_id: &str,
+    dir: &Path,
+) -> Result<()> {
+    fs::create_dir_all(dir)
+        .with_context(|| format!("failed to create public key export dir {dir:?}"))?;
+    for cert in certs {
+        let pubkey = EcP256PublicKey::from_cert(cert)?;
+        let kid = format!("{}-{}", device_id, cert.name);
+        let jwk_path = dir.join(format!("{}.jwk", cert.name));
+        fs::write(&jwk_path, pubkey.to_jwk(&kid)?)
+            .with_context(|| format!("failed to write {jwk_path:?}"))?;
+        let ssh_path = dir.join(format!("{}.pub", cert.name));
+        fs::write(&ssh_path, pubkey.to_openssh(&kid) + "\n")
+            .with_context(|| format!("failed to write {ssh_path:?}"))?;
+        log::info!("Exported {} public key to {:?}", cert.name, dir);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn openssh_encoding() {
+        let key = EcP256PublicKey {
+            x: [0x11; P256_COORD_SIZE],
+            y: [0x22; P256_COORD_SIZE],
+        };
+        let line = key.to_openssh("dev");
+        let fields: Vec<&str> = line.split(' ').collect();
+        assert_eq!(fields.len(), 3);
+        assert_eq!(fields[0], OPENSSH_P256_KEY_TYPE);
+        assert_eq!(fields[2], "dev");
+
+        let blob = Base64::decode_vec(fields[1]).unwrap();
+        // 3 length-prefixed fields: key type, curve name and uncompressed point.
+        assert_eq!(blob.len(), 4 + 19 + 4 + 8 + 4 + 65);
+        assert_eq!(&blob[4..23], OPENSSH_P256_KEY_TYPE.as_bytes());
+        assert_eq!(&blob[27..35], OPENSSH_P256_CURVE.as_bytes());
+        assert_eq!(blob[39], 0x04);
+        assert_eq!(&blob[40..72], &key.x);
+        assert_eq!(&blob[72..], &key.y);
+    }
+
+    #[test]
+    fn jwk_encoding() {
+        let key = EcP256PublicKey {
+            x: [0x11; P256_COORD_SIZE],
+            y: [0x22; P256_COORD_SIZE],
+        };
+        let jwk: serde_json::Value = serde_json::from_str(&key.to_jwk("dev-UDS").unwrap()).unwrap();
+        assert_eq!(jwk["kty"], "EC");
+        assert_eq!(jwk["crv"], "P-256");
+        assert_eq!(jwk["kid"], "dev-UDS");
+        let x = Base64UrlUnpadded::decode_vec(jwk["x"].as_str().unwrap()).unwrap();
+        assert_eq!(x, key.x);
+    }
+}
@@ -0,0 +1,50 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Key and certificate fixtures shared by the unit tests.
+
+use openssl::asn1::Asn1Time;
+use openssl::bn::BigNum;
+use openssl::ec::{EcGroup, EcKey};
+use openssl::hash::MessageDigest;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Private};
+use openssl::x509::{X509Builder, X509NameBuilder, X509};
+
+/// Returns a fresh P-256 key.
+pub fn key() -> PKey<Private> {
+    PKey::from_ec_key(
+        EcKey::generate(&EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap()).unwrap(),
+    )
+    .unwrap()
+}
+
+/// Returns an unsigned, self-issued v3 cert for `key` named `name`, with serial number 1.
+pub fn cert_builder(name: &str, key: &PKey<Private>) -> X509Builder {
+    let mut subject = X509NameBuilder::new().unwrap();
+    subject.append_entry_by_nid(Nid::COMMONNAME, name).unwrap();
+    let subject = subject.build();
+    let mut builder = X509Builder::new().unwrap();
+    builder.set_version(2).unwrap();
+    builder
+        .set_serial_number(&BigNum::from_u32(1).unwrap().to_asn1_integer().unwrap())
+        .unwrap();
+    builder.set_subject_name(&subject).unwrap();
+    builder.set_issuer_name(&subject).unwrap();
+    builder.set_pubkey(key).unwrap();
+    builder
+        .set_not_before(&Asn1Time::from_str("20180322235959Z").unwrap())
+        .unwrap();
+    builder
+        .set_not_after(&Asn1Time::from_str("99991231235959Z").unwrap())
+        .unwrap();
+    builder
+}
+
+/// Returns a cert for `key` named `name`, signed by `signing_key`.
+pub fn cert(name: &str, key: &PKey<Private>, signing_key: &PKey<Private>) -> X509 {
+    let mut builder = cert_builder(name, key);
+    builder.sign(signing_key, MessageDigest::sha256()).unwrap();
+    builder.build()
+}
@@ -2,7 +2,7 @@
 // Licensed under the Apache License, Version 2.0, see LICENSE for details.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::time::Duration;
+use std::path::PathBuf;
 
 use anyhow::Result;
 use clap::Parser;
@@ -12,8 +12,13 @@ use cp_lib::{reset_and_lock, run_sram_cp_provision, CpResponse, ManufCpProvision
 use opentitanlib::console::spi::SpiConsoleDevice;
 use opentitanlib::dif::lc_ctrl::DifLcCtrlState;
 use opentitanlib::test_utils::init::InitializeTest;
-use opentitanlib::test_utils::lc::read_lc_state;
+use opentitanlib::test_utils::lc_transition::LcStateCheck;
 use opentitanlib::test_utils::load_sram_program::SramProgramParams;
+use provisioning::report::{report_line, CP_REPORT_PREFIX};
+use provisioning::session::DeviceSession;
+use provisioning::timeouts::TimeoutArgs;
+use provisioning::token_escrow::EscrowRecord;
+use ujson_lib::limits::{LC_TOKEN_WORDS, WAFER_AUTH_SECRET_WORDS};
 use ujson_lib::provisioning_data::ManufCpProvisioningData;
 use util_lib::{hash_lc_token, hex_string_to_u32_arrayvec};
 
@@ -28,33 +33,46 @@ struct Opts {
     #[command(flatten)]
     provisioning_data: ManufCpProvisioningDataInput,
 
-    /// Console receive timeout.
-    #[arg(long, value_parser = humantime::parse_duration, default_value = "600s")]
-    timeout: Duration,
+    #[command(flatten)]
+    lc_state_check: LcStateCheck,
+
+    #[command(flatten)]
+    timeouts: TimeoutArgs,
 
     /// Name of the SPI interface to connect to the OTTF console.
     #[arg(long, default_value = "BOOTSTRAP")]
     console_spi: String,
+
+    /// File to escrow the provisioned tokens to, keyed by the CP device ID, for FT to look them
+    /// up.
+    #[arg(long)]
+    token_escrow: Option<PathBuf>,
 }
 
 fn main() -> Result<()> {
     let opts = Opts::parse();
     opts.init.init_logging();
     let transport = opts.init.init_target()?;
+    let session = DeviceSession::new(&transport, &opts.init);
+    let timeouts = opts.timeouts.resolve()?;
     let spi = transport.spi(&opts.console_spi)?;
     let spi_console_device = SpiConsoleDevice::new(&*spi, None)?;
 
     let provisioning_data = ManufCpProvisioningData {
-        wafer_auth_secret: hex_string_to_u32_arrayvec::<8>(
+        wafer_auth_secret: hex_string_to_u32_arrayvec::<WAFER_AUTH_SECRET_WORDS>(
             opts.provisioning_data.wafer_auth_secret.as_str(),
         )?,
         test_unlock_token_hash: hash_lc_token(
-            hex_string_to_u32_arrayvec::<4>(opts.provisioning_data.test_unlock_token.as_str())?
-                .as_bytes(),
+            hex_string_to_u32_arrayvec::<LC_TOKEN_WORDS>(
+                opts.provisioning_data.test_unlock_token.as_str(),
+            )?
+            .as_bytes(),
         )?,
         test_exit_token_hash: hash_lc_token(
-            hex_string_to_u32_arrayvec::<4>(opts.provisioning_data.test_exit_token.as_str())?
-                .as_bytes(),
+            hex_string_to_u32_arrayvec::<LC_TOKEN_WORDS>(
+                opts.provisioning_data.test_exit_token.as_str(),
+            )?
+            .as_bytes(),
         )?,
     };
 
@@ -63,11 +81,7 @@ fn main() -> Result<()> {
     // Only run CP provisioning if requested in any of the TestUnlocked states, except the last
     // state (TestUnlocked7), as this state requires special handling of the wafer authentication
     // secret, which is not yet implemented.
-    let lc_state = read_lc_state(
-        &transport,
-        &opts.init.jtag_params,
-        opts.init.bootstrap.options.reset_delay,
-    )?;
+    let lc_state = session.read_lc_state()?;
     log::info!("CP starting LC state: {:?}", lc_state.lc_state_to_str());
     match lc_state {
         DifLcCtrlState::TestUnlocked0
@@ -78,23 +92,29 @@ fn main() -> Result<()> {
         | DifLcCtrlState::TestUnlocked5
         | DifLcCtrlState::TestUnlocked6 => {
             run_sram_cp_provision(
-                &transport,
-                &opts.init.jtag_params,
-                opts.init.bootstrap.options.reset_delay,
+                &session,
                 &opts.sram_program,
                 &provisioning_data,
                 &spi_console_device,
                 &mut response,
-                opts.timeout,
+                &timeouts,
             )?;
+            if let Some(path) = &opts.token_escrow {
+                EscrowRecord {
+                    cp_device_id: response.cp_device_id.clone(),
+                    test_unlock_token: opts.provisioning_data.test_unlock_token.clone(),
+                    test_exit_token: opts.provisioning_data.test_exit_token.clone(),
+                }
+                .append(path)?;
+                log::info!(
+                    "Tokens of CP device ID {} escrowed to {path:?}",
+                    response.cp_device_id
+                );
+            }
             // Only perform lock if we are in TEST_UNLOCKED0, otherwise we are running from a later
             // stage and want to run FT stage directly after.
             if lc_state == DifLcCtrlState::TestUnlocked0 {
-                reset_and_lock(
-                    &transport,
-                    &opts.init.jtag_params,
-                    opts.init.bootstrap.options.reset_delay,
-                )?;
+                reset_and_lock(&session, &opts.lc_state_check)?;
             } else {
                 log::info!("Skipping resetting and locking the device.");
             }
@@ -104,8 +124,7 @@ fn main() -> Result<()> {
         }
     };
 
-    let doc = serde_json::to_string(&response)?;
-    println!("CHIP_PROBE_DATA: {doc}");
+    println!("{}", report_line(CP_REPORT_PREFIX, &response, false)?);
 
     Ok(())
 }
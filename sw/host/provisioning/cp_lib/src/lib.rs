@@ -10,19 +10,26 @@ use serde::Serialize;
 
 use opentitanlib::app::TransportWrapper;
 use opentitanlib::console::spi::SpiConsoleDevice;
-use opentitanlib::dif::lc_ctrl::{DifLcCtrlState, DifLcCtrlToken, LcCtrlReg};
+use opentitanlib::dif::lc_ctrl::{DifLcCtrlState, DifLcCtrlToken};
 use opentitanlib::io::jtag::{JtagParams, JtagTap};
-use opentitanlib::test_utils::lc_transition::trigger_lc_transition;
+use opentitanlib::test_utils::lc_transition::{
+    trigger_lc_transition, verify_lc_state, LcStateCheck,
+};
 use opentitanlib::test_utils::load_sram_program::{
     ExecutionMode, ExecutionResult, SramProgramParams,
 };
 use opentitanlib::test_utils::rpc::{ConsoleRecv, ConsoleSend};
 use opentitanlib::uart::console::UartConsole;
+use provisioning::session::DeviceSession;
+use provisioning::timeouts::Timeouts;
+use provisioning::token_escrow::format_cp_device_id;
 use ujson_lib::provisioning_data::{ManufCpProvisioningData, ManufCpProvisioningDataOut};
 
 // Generated by the `lc_raw_unlock_token` Bazel rule from `//rules/lc.bzl`.
 mod lc_raw_unlock_token;
 
+pub use provisioning::token_escrow;
+
 /// Provisioning data command-line parameters.
 #[derive(Debug, Args, Clone)]
 pub struct ManufCpProvisioningDataInput {
@@ -48,6 +55,7 @@ pub fn unlock_raw(
     transport: &TransportWrapper,
     jtag_params: &JtagParams,
     reset_delay: Duration,
+    lc_state_check: &LcStateCheck,
 ) -> Result<()> {
     // Set the TAP straps for the lifecycle controller and reset.
     transport
@@ -85,25 +93,26 @@ pub fn unlock_raw(
     jtag = jtag_params.create(transport)?.connect(JtagTap::LcTap)?;
 
     // Check that LC state is `TEST_UNLOCKED0`.
-    let state = jtag.read_lc_ctrl_reg(&LcCtrlReg::LcState)?;
-    assert_eq!(state, DifLcCtrlState::TestUnlocked0.redundant_encoding());
+    verify_lc_state(&mut *jtag, DifLcCtrlState::TestUnlocked0, lc_state_check)?;
     jtag.disconnect()?;
     transport.pin_strapping("PINMUX_TAP_LC")?.remove()?;
 
     Ok(())
 }
 
-#[allow(clippy::too_many_arguments)]
 pub fn run_sram_cp_provision(
-    transport: &TransportWrapper,
-    jtag_params: &JtagParams,
-    reset_delay: Duration,
+    session: &DeviceSession,
     sram_program: &SramProgramParams,
     data_in: &ManufCpProvisioningData,
     spi_console: &SpiConsoleDevice,
     response: &mut CpResponse,
-    timeout: Duration,
+    timeouts: &Timeouts,
 ) -> Result<()> {
+    let DeviceSession {
+        transport,
+        jtag_params,
+        reset_delay,
+    } = *session;
     // Set CPU TAP straps, reset, and connect to the JTAG interface.
     transport.pin_strapping("PINMUX_TAP_RISCV")?.apply()?;
     transport.reset_target(reset_delay, true)?;
@@ -124,24 +133,25 @@ pub fn run_sram_cp_provision(
     let _ = UartConsole::wait_for(
         spi_console,
         r"Waiting for CP provisioning data ...",
-        timeout,
+        timeouts.sram_start,
     )?;
 
     // Inject provisioning data into the device.
     data_in.send(spi_console)?;
 
     // Wait to receive CP device ID, and encode in big-endian in response.
-    let _ = UartConsole::wait_for(spi_console, r"Exporting CP device ID ...", timeout)?;
-    response.cp_device_id = ManufCpProvisioningDataOut::recv(spi_console, timeout, true)?
-        .cp_device_id
-        .iter()
-        .rev()
-        .map(|v| format!("{v:08X}"))
-        .collect::<Vec<String>>()
-        .join("");
+    let _ = UartConsole::wait_for(
+        spi_console,
+        r"Exporting CP device ID ...",
+        timeouts.perso_data_exchange,
+    )?;
+    let cp_device_id =
+        ManufCpProvisioningDataOut::recv(spi_console, timeouts.perso_data_exchange, true)?
+            .cp_device_id;
+    response.cp_device_id = format_cp_device_id(&cp_device_id);
 
     // Wait for provisioning operations to complete.
-    let _ = UartConsole::wait_for(spi_console, r"CP provisioning done.", timeout)?;
+    let _ = UartConsole::wait_for(spi_console, r"CP provisioning done.", timeouts.otp_write)?;
 
     jtag.disconnect()?;
     transport.pin_strapping("PINMUX_TAP_RISCV")?.remove()?;
@@ -149,11 +159,12 @@ pub fn run_sram_cp_provision(
     Ok(())
 }
 
-pub fn reset_and_lock(
-    transport: &TransportWrapper,
-    jtag_params: &JtagParams,
-    reset_delay: Duration,
-) -> Result<()> {
+pub fn reset_and_lock(session: &DeviceSession, lc_state_check: &LcStateCheck) -> Result<()> {
+    let DeviceSession {
+        transport,
+        jtag_params,
+        reset_delay,
+    } = *session;
     // Set the TAP straps for the lifecycle controller and reset.
     transport
         .pin_strapping("PINMUX_TAP_LC")?
@@ -186,8 +197,7 @@ pub fn reset_and_lock(
     jtag = jtag_params.create(transport)?.connect(JtagTap::LcTap)?;
 
     // Check that LC state is `TEST_LOCKED0`.
-    let state = jtag.read_lc_ctrl_reg(&LcCtrlReg::LcState)?;
-    assert_eq!(state, DifLcCtrlState::TestLocked0.redundant_encoding());
+    verify_lc_state(&mut *jtag, DifLcCtrlState::TestLocked0, lc_state_check)?;
     jtag.disconnect()?;
     transport.pin_strapping("PINMUX_TAP_LC")?.remove()?;
 
@@ -0,0 +1,169 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::io::Write;
+
+use anyhow::Result;
+use clap::{Arg, Command, ValueEnum};
+
+/// Shells supported by the completion script generator.
+#[derive(Clone, Copy, Debug, ValueEnum)]
+pub enum Shell {
+    Bash,
+    Fish,
+}
+
+fn visible_args(cmd: &Command) -> impl Iterator<Item = &Arg> {
+    cmd.get_arguments()
+        .filter(|arg| !arg.is_hide_set() && arg.get_long().is_some())
+}
+
+fn possible_values(arg: &Arg) -> Vec<String> {
+    arg.get_possible_values()
+        .iter()
+        .filter(|v| !v.is_hide_set())
+        .map(|v| v.get_name().to_string())
+        .collect()
+}
+
+fn help(arg: &Arg) -> String {
+    arg.get_help()
+        .map(|h| h.to_string())
+        .unwrap_or_default()
+        .lines()
+        .next()
+        .unwrap_or_default()
+        .replace('\'', "\\'")
+}
+
+fn generate_bash(cmd: &Command, bin_name: &str, out: &mut dyn Write) -> Result<()> {
+    let func = format!("_{}", bin_name.replace(['-', '.'], "_"));
+    let subcmds: Vec<&Command> = cmd.get_subcommands().collect();
+    let subcmd_names: Vec<&str> = subcmds.iter().map(|c| c.get_name()).collect();
+    let flags = |c: &Command| {
+        visible_args(c)
+            .map(|a| format!("--{}", a.get_long().unwrap()))
+            .collect::<Vec<String>>()
+            .join(" ")
+    };
+
+    writeln!(out, "{func}() {{")?;
+    writeln!(out, "    local cur=\"${{COMP_WORDS[COMP_CWORD]}}\"")?;
+    writeln!(out, "    local prev=\"${{COMP_WORDS[COMP_CWORD-1]}}\"")?;
+
+    // Complete the values of enumerated arguments.
+    writeln!(out, "    case \"${{prev}}\" in")?;
+    let mut seen = Vec::new();
+    for arg in std::iter::once(cmd)
+        .chain(subcmds.iter().copied())
+        .flat_map(visible_args)
+    {
+        let long = arg.get_long().unwrap();
+        let values = possible_values(arg);
+        if values.is_empty() || seen.contains(&long) {
+            continue;
+        }
+        seen.push(long);
+        writeln!(
+            out,
+            "        --{long}) COMPREPLY=($(compgen -W \"{}\" -- \"${{cur}}\")); return ;;",
+            values.join(" ")
+        )?;
+    }
+    writeln!(out, "    esac")?;
+
+    // Find the active subcommand, if any.
+    writeln!(out, "    local subcmd=\"\"")?;
+    writeln!(out, "    local word")?;
+    writeln!(
+        out,
+        "    for word in \"${{COMP_WORDS[@]:1:COMP_CWORD-1}}\"; do"
+    )?;
+    writeln!(out, "        case \"${{word}}\" in")?;
+    writeln!(
+        out,
+        "            {}) subcmd=\"${{word}}\"; break ;;",
+        subcmd_names.join("|")
+    )?;
+    writeln!(out, "        esac")?;
+    writeln!(out, "    done")?;
+
+    writeln!(out, "    local opts")?;
+    writeln!(out, "    case \"${{subcmd}}\" in")?;
+    for subcmd in subcmds.iter() {
+        writeln!(
+            out,
+            "        {}) opts=\"{}\" ;;",
+            subcmd.get_name(),
+            flags(subcmd)
+        )?;
+    }
+    writeln!(
+        out,
+        "        *) opts=\"{} {}\" ;;",
+        flags(cmd),
+        subcmd_names.join(" ")
+    )?;
+    writeln!(out, "    esac")?;
+    writeln!(
+        out,
+        "    COMPREPLY=($(compgen -W \"${{opts}}\" -- \"${{cur}}\"))"
+    )?;
+    writeln!(out, "}}")?;
+    writeln!(out, "complete -o default -F {func} {bin_name}")?;
+    Ok(())
+}
+
+fn generate_fish_args(
+    cmd: &Command,
+    bin_name: &str,
+    condition: &str,
+    out: &mut dyn Write,
+) -> Result<()> {
+    for arg in visible_args(cmd) {
+        let values = possible_values(arg);
+        let values = if values.is_empty() {
+            String::new()
+        } else {
+            format!(" -r -f -a '{}'", values.join(" "))
+        };
+        writeln!(
+            out,
+            "complete -c {bin_name} -n '{condition}' -l {}{values} -d '{}'",
+            arg.get_long().unwrap(),
+            help(arg)
+        )?;
+    }
+    Ok(())
+}
+
+fn generate_fish(cmd: &Command, bin_name: &str, out: &mut dyn Write) -> Result<()> {
+    generate_fish_args(cmd, bin_name, "__fish_use_subcommand", out)?;
+    for subcmd in cmd.get_subcommands() {
+        let about = subcmd
+            .get_about()
+            .map(|a| a.to_string().replace('\'', "\\'"))
+            .unwrap_or_default();
+        writeln!(
+            out,
+            "complete -c {bin_name} -f -n '__fish_use_subcommand' -a {} -d '{about}'",
+            subcmd.get_name()
+        )?;
+        generate_fish_args(
+            subcmd,
+            bin_name,
+            &format!("__fish_seen_subcommand_from {}", subcmd.get_name()),
+            out,
+        )?;
+    }
+    Ok(())
+}
+
+/// Writes a completion script for `cmd`, installed as `bin_name`, to `out`.
+pub fn generate(shell: Shell, cmd: &Command, bin_name: &str, out: &mut dyn Write) -> Result<()> {
+    match shell {
+        Shell::Bash => generate_bash(cmd, bin_name, out),
+        Shell::Fish => generate_fish(cmd, bin_name, out),
+    }
+}
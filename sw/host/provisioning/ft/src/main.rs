@@ -3,57 +3,293 @@
 // SPDX-License-Identifier: Apache-2.0
 
 use std::collections::HashMap;
-use std::path::PathBuf;
-use std::time::{Duration, Instant};
+use std::fs::File;
+use std::io::{LineWriter, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{Instant, SystemTime};
 
-use anyhow::{bail, Context, Result};
+use anyhow::{bail, ensure, Context, Result};
+use arrayvec::ArrayVec;
 use base64ct::{Base64, Encoding};
-use clap::{Args, Parser};
-use elliptic_curve::pkcs8::DecodePrivateKey;
-use elliptic_curve::SecretKey;
-use p256::NistP256;
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use openssl::x509::X509;
 
-use cert_lib::{CaConfig, CaKey, CaKeyType};
+use cert_lib::attestation::{export_attestation_bundles, AttestationFormat, DiceChain};
+use cert_lib::offline::OfflineCerts;
+use cert_lib::piv::PivKey;
+use cert_lib::policy::SigningPolicy;
+use cert_lib::pubkey::export_cert_public_keys;
+use cert_lib::{export_certs, CaConfig, CaKey, CaKeyType, HostCaKey};
+use ft_lib::alert_cfg::AlertCfg;
+use ft_lib::audit::{AuditResult, SavedReport};
+use ft_lib::checkpoint::Checkpoint;
+use ft_lib::entropy::EntropyCheck;
+use ft_lib::events::{EventSink, ProvisioningEvent};
+use ft_lib::flow::{Flow, ProvisioningStep, Session};
+use ft_lib::flow_result::FlowResult;
+use ft_lib::handoff::{token_id, HandoffBundle, HANDOFF_SCHEMA_VERSION};
+use ft_lib::host_key::HostKeySpec;
+use ft_lib::key_bundle::ProvisioningKeyBundle;
+use ft_lib::keymgr_binding::KeymgrDiversification;
+use ft_lib::log_level::DeviceLogLevel;
+use ft_lib::manuf_state::CreatorManufState;
+use ft_lib::otp_dump::OtpDump;
+use ft_lib::pass_criteria::PassCriteria;
+use ft_lib::perso_compression::PersoCompression;
+#[cfg(feature = "debug-tools")]
+use ft_lib::provisioner::Capability;
+use ft_lib::provisioner::{Capabilities, FtProvisioner, IndividualizeBackend};
+use ft_lib::release::{ReleaseManifest, ReleasePolicy};
 use ft_lib::response::PersonalizeResponse;
-use ft_lib::{
-    check_slot_b_boot_up, run_ft_personalize, run_sram_ft_individualize, test_exit, test_unlock,
-};
+use ft_lib::retry::RetryPolicy;
+use ft_lib::rma_escrow::{load_recipient_cert, RmaEscrowRecord};
+use ft_lib::rma_token::{unwrap_rma_token, RmaUnwrapKey, WrappedRmaToken};
+use ft_lib::rma_token_escrow::{FileEscrow, TokenEscrow, TokenEscrowConfig};
+use ft_lib::secret::LcTokenSecret;
+use ft_lib::smoke_test::SmokeTestSuite;
+use ft_lib::step_plan::{FtStep, StepPlan};
+use ft_lib::step_state::StepJournal;
+use ft_lib::telemetry::Telemetry;
+use ft_lib::timeouts::TimeoutArgs;
+use ft_lib::trim::{AstTrim, TrimFile};
+use ft_lib::verify::VerifyParams;
+use ft_lib::{HwCfgPolicy, IndividualizePartition, PersoExportOptions};
+use opentitanlib::app::TransportWrapper;
 use opentitanlib::backend;
+use opentitanlib::console::dmi::DmiConsoleDevice;
+use opentitanlib::console::parallel::ParallelConsole;
 use opentitanlib::console::spi::SpiConsoleDevice;
+use opentitanlib::crypto::ecdsa::EcdsaPublicKey;
 use opentitanlib::dif::lc_ctrl::DifLcCtrlState;
+use opentitanlib::io::console::ConsoleDevice;
+use opentitanlib::io::uart::{Parity, StopBits, Uart};
+use opentitanlib::otp::otp_img::OtpImg;
 use opentitanlib::test_utils::init::InitializeTest;
-use opentitanlib::test_utils::lc::read_lc_state;
-use opentitanlib::test_utils::load_sram_program::SramProgramParams;
+use opentitanlib::test_utils::lc_transition::LcStateCheck;
+use opentitanlib::test_utils::load_sram_program::{JtagClockRamp, SramProgramParams};
+#[cfg(feature = "debug-tools")]
+use opentitanlib::util::parse_int::ParseInt;
+use provisioning::report::{report_line, FT_REPORT_PREFIX};
+use provisioning::token_escrow::{cp_device_id_of, EscrowRecord};
+use ujson_lib::limits::{
+    CP_DEVICE_ID_WORDS, DEVICE_ID_WORDS, KEY_ID_BYTES, LC_TOKEN_WORDS, MEASUREMENT_WORDS,
+};
 use ujson_lib::provisioning_data::{ManufCertgenInputs, ManufFtIndividualizeData};
 use util_lib::{
-    encrypt_token, hex_string_to_u32_arrayvec, hex_string_to_u8_arrayvec, load_rsa_public_key,
-    random_token,
+    check_lc_token_hash, format_device_id, hex_string_to_u32_arrayvec, hex_string_to_u8_arrayvec,
+    load_lc_token, parse_lc_token, random_token,
 };
 
-/// Provisioning data command-line parameters.
+mod completions;
+mod recipe;
+
+use completions::Shell;
+
+/// Device ID command-line parameters.
 #[derive(Debug, Args, Clone)]
-pub struct ManufFtProvisioningDataInput {
+pub struct DeviceIdInput {
     /// Device ID to provision.
     ///
     /// Must match the device ID provisioned in to flash during CP, if one was provisioned then.
     #[arg(long)]
     pub device_id: String,
+}
 
+/// Test unlock command-line parameters.
+#[derive(Debug, Args, Clone)]
+pub struct UnlockInput {
     /// TestUnlock token; a 128-bit hex string.
-    #[arg(long)]
-    pub test_unlock_token: String,
+    #[arg(
+        long,
+        required_unless_present_any = ["cp_token_escrow", "test_unlock_token_file"],
+        conflicts_with_all = ["cp_token_escrow", "test_unlock_token_file"]
+    )]
+    pub test_unlock_token: Option<String>,
 
-    /// TestExit token; a 128-bit hex string.
+    /// File to load the TestUnlock token from: an HJSON token file holding `test_unlock_token`
+    /// as a 128-bit hex string, or a raw 16-byte token.
+    #[arg(long, conflicts_with = "cp_token_escrow")]
+    pub test_unlock_token_file: Option<PathBuf>,
+
+    /// TEST_UNLOCKED* LC state to unlock the device to, after the TEST_LOCKED* state it is in,
+    /// e.g. for re-entrant test flows.
+    #[arg(
+        long,
+        value_parser = DifLcCtrlState::parse_lc_state_str,
+        default_value = "test_unlocked1"
+    )]
+    pub test_unlock_lc_state: DifLcCtrlState,
+}
+
+/// CP token escrow command-line parameters.
+#[derive(Debug, Args, Clone)]
+pub struct CpTokenEscrowInput {
+    /// Token escrow written by CP (see `cp --token-escrow`), to look the test unlock / exit tokens
+    /// up by the CP device ID, instead of passing them on the command line.
     #[arg(long)]
-    pub test_exit_token: String,
+    pub cp_token_escrow: Option<PathBuf>,
 
-    /// RMA unlock token; a 128-bit hex string.
+    /// CP device ID of the device (`cp_device_id` of the CP report) to look the tokens up by,
+    /// for commands without a `--device-id`: the CP device ID is the start of the device ID.
+    #[arg(long, requires = "cp_token_escrow")]
+    pub cp_device_id: Option<String>,
+}
+
+/// LC token check command-line parameters.
+#[derive(Debug, Args, Clone)]
+pub struct TokenCheckInput {
+    /// OTP image (HJSON) of the SECRET0 partition the device was provisioned with, to check the
+    /// test unlock / exit tokens against their hashes before touching the device.
     #[arg(long)]
-    pub rma_unlock_token: Option<String>,
+    pub token_otp_image: Option<PathBuf>,
+}
+
+impl TokenCheckInput {
+    fn load(&self) -> Result<Option<OtpImg>> {
+        self.token_otp_image
+            .as_deref()
+            .map(|path| {
+                OtpImg::from_file(path)
+                    .with_context(|| format!("Failed to load OTP image {path:?}"))
+            })
+            .transpose()
+    }
+}
+
+/// Test exit command-line parameters.
+#[derive(Debug, Args, Clone)]
+pub struct TestExitInput {
+    /// TestExit token; a 128-bit hex string.
+    #[arg(
+        long,
+        required_unless_present_any = ["cp_token_escrow", "test_exit_token_file"],
+        conflicts_with_all = ["cp_token_escrow", "test_exit_token_file"]
+    )]
+    pub test_exit_token: Option<String>,
+
+    /// File to load the TestExit token from: an HJSON token file holding `test_exit_token` as a
+    /// 128-bit hex string, or a raw 16-byte token.
+    #[arg(long, conflicts_with = "cp_token_escrow")]
+    pub test_exit_token_file: Option<PathBuf>,
+
+    /// Label of the generation of the test exit token, reported if OTP holds the hash of a test
+    /// exit token of another generation.
+    #[arg(long, default_value = "")]
+    token_generation: String,
 
     /// LC state to transition to from TEST_UNLOCKED*.
     #[arg(long, value_parser = DifLcCtrlState::parse_lc_state_str)]
     target_mission_mode_lc_state: DifLcCtrlState,
+}
+
+/// Individualization command-line parameters.
+#[derive(Debug, Args, Clone)]
+pub struct IndividualizeInput {
+    #[command(flatten)]
+    sram_program: SramProgramParams,
+
+    #[command(flatten)]
+    clock_ramp: JtagClockRamp,
+
+    #[command(flatten)]
+    test_exit: TestExitInput,
+
+    /// Comma-separated list of OTP partitions to individualize.
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_values_t = IndividualizePartition::value_variants().to_vec()
+    )]
+    partitions: Vec<IndividualizePartition>,
+
+    /// How to handle devices whose HW_CFG0 partition (device ID) was already programmed.
+    #[arg(long, value_enum, default_value_t = HwCfgPolicy::Verify)]
+    hw_cfg_policy: HwCfgPolicy,
+
+    /// Per-device AST calibration / trim data (JSON) from an earlier parametric test.
+    #[arg(long)]
+    trim_file: Option<PathBuf>,
+
+    /// Alert handler configuration (JSON) overriding the OWNER_SW_CFG fields of the SKU OTP image.
+    #[arg(long)]
+    owner_sw_cfg_alert_cfg: Option<PathBuf>,
+
+    /// OTP dump SRAM program, run after individualization to read back the programmed OTP.
+    #[arg(long, requires = "otp_export_dir")]
+    otp_dump_elf: Option<PathBuf>,
+
+    /// Directory to export the OTP contents confirmed by the device to, as JSON and vmem files.
+    #[arg(long, requires = "otp_dump_elf")]
+    otp_export_dir: Option<PathBuf>,
+
+    /// FPGA only: bitstream whose OTP image already holds the individualized partitions, loaded
+    /// instead of running the FT individualize SRAM program.
+    #[arg(long)]
+    otp_preload_bitstream: Option<PathBuf>,
+}
+
+impl IndividualizeInput {
+    /// Loads the AST trim data and the alert handler configuration to individualize with.
+    fn load(&self) -> Result<(Option<TrimFile>, AlertCfg)> {
+        let trim_file = self.trim_file.as_deref().map(TrimFile::load).transpose()?;
+        if trim_file.is_some()
+            && !self
+                .partitions
+                .contains(&IndividualizePartition::CreatorSwCfg)
+        {
+            bail!("AST trim data is only provisioned with the creator_sw_cfg partition.");
+        }
+        let alert_cfg = match &self.owner_sw_cfg_alert_cfg {
+            Some(path) => {
+                if !self
+                    .partitions
+                    .contains(&IndividualizePartition::OwnerSwCfg)
+                {
+                    bail!(
+                        "Alert configuration is only provisioned with the owner_sw_cfg partition."
+                    );
+                }
+                AlertCfg::load(path)?
+            }
+            None => AlertCfg::default(),
+        };
+        Ok((trim_file, alert_cfg))
+    }
+}
+
+/// Provisioning key bundle command-line parameters.
+#[derive(Debug, Args, Clone)]
+pub struct KeyBundleInput {
+    /// Signed provisioning key bundle (JSON) listing the RMA wrap key and the CAs to use.
+    #[arg(long)]
+    key_bundle: PathBuf,
+
+    /// Detached signature of the key bundle.
+    #[arg(long)]
+    key_bundle_signature: PathBuf,
+
+    /// Public key (DER) the key bundle is signed with.
+    #[arg(long)]
+    key_bundle_key: PathBuf,
+
+    /// Private key of a key bundle CA with a raw key type, as `<CA name>=<key>`: a PKCS#8 DER
+    /// file, or an HSM or KMS key as for `--handoff-signing-key`; may be repeated.
+    #[arg(long, value_parser = parse_raw_ca_key)]
+    raw_ca_key: Vec<(String, HostKeySpec)>,
+
+    /// PIN of the PIV token holding the key bundle CAs with a PIV key type.
+    #[arg(long, env = "FT_PIV_PIN")]
+    piv_pin: Option<String>,
+}
+
+/// Personalization command-line parameters.
+#[derive(Debug, Args, Clone)]
+pub struct PersonalizeInput {
+    /// RMA unlock token; a 128-bit hex string.
+    #[arg(long)]
+    pub rma_unlock_token: Option<String>,
 
     /// Measurement of the ROM_EXT image to be loaded onto the device.
     #[arg(long)]
@@ -75,140 +311,1043 @@ pub struct ManufFtProvisioningDataInput {
     #[arg(long, default_value = "0")]
     pub owner_security_version: u32,
 
-    /// Token Encryption public key (RSA) DER file path.
+    #[command(flatten)]
+    key_bundle: KeyBundleInput,
+
+    /// Second image (perso FW + ROM_EXT/Owner FW bundle) to bootstrap.
     #[arg(long)]
-    token_encrypt_key_der_file: PathBuf,
+    second_bootstrap: PathBuf,
 
-    /// Pretty-print the provisioning data output.
-    #[arg(long, default_value = "false")]
-    pretty: bool,
+    /// Owner's firmware string indicating successful start up.
+    #[arg(long)]
+    owner_success_text: Option<String>,
+
+    /// Directory to export the device certificate public keys to, in JWK and OpenSSH formats.
+    #[arg(long)]
+    pubkey_export_dir: Option<PathBuf>,
+
+    /// Directory to export the X.509 device certificates to, as `<device_id>_<cert name>.der`
+    /// and `.pem` files.
+    #[arg(long)]
+    cert_export_dir: Option<PathBuf>,
+
+    /// Directory to export the DICE certificate chain to, for attestation verifiers.
+    #[arg(long)]
+    attestation_export_dir: Option<PathBuf>,
+
+    /// Comma-separated list of formats to export the DICE certificate chain in.
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_values_t = [AttestationFormat::PemChain]
+    )]
+    attestation_formats: Vec<AttestationFormat>,
+
+    /// Certificate (PEM or DER) of the RMA support team, to escrow the RMA unlock token and the
+    /// certificate metadata of the device to, in a CMS EnvelopedData structure.
+    #[arg(long, requires = "rma_escrow_dir")]
+    rma_escrow_cert: Option<PathBuf>,
+
+    /// Directory to export the RMA escrow envelopes to, as `<device_id>.rma.p7m` DER files.
+    #[arg(long, requires = "rma_escrow_cert")]
+    rma_escrow_dir: Option<PathBuf>,
+
+    /// Directory to write the RMA unlock token, wrapped with the RMA wrap key of the key bundle,
+    /// to, as `<device_id>.rma_token.bin` (the raw wrapped token: the RSA ciphertext, or the
+    /// X25519 or P-384 ephemeral public key, ciphertext and tag) and `<device_id>.rma_token.json` (a
+    /// record naming the wrapping suite).
+    #[arg(long)]
+    rma_token_out: Option<PathBuf>,
+
+    /// Escrow configuration (JSON) of the storage of the wrapped RMA unlock token, instead of
+    /// `--rma-token-out`: a directory, a SQLite database or a secret manager.
+    #[arg(long, conflicts_with = "rma_token_out")]
+    rma_token_escrow: Option<PathBuf>,
+
+    /// Signing policy (JSON) the device certificates must comply with to be endorsed: validity
+    /// window, serial number scheme and path length constraints.
+    #[arg(long)]
+    cert_policy: Option<PathBuf>,
+
+    /// Directory of the device certificates issued offline by the CA, from the public keys
+    /// exported by a previous run, as `<device_id>_<cert name>.der` files. The certificates are
+    /// injected instead of being endorsed on the station; the CA keys are not used.
+    #[arg(long)]
+    offline_certs_dir: Option<PathBuf>,
+
+    /// Raw diversification key of the SKU (at least 32 bytes), deriving the keymgr sealing binding
+    /// values of each device; defaults to the values of the personalization firmware.
+    #[arg(long)]
+    keymgr_diversification_key: Option<PathBuf>,
+
+    /// Compression to request for the TBS certificates exported off the device.
+    #[arg(long, value_enum, default_value_t = PersoCompression::None)]
+    perso_compression: PersoCompression,
+
+    /// Creator manufacturing state to provision, per the SKU configuration; defaults to the
+    /// value of the SKU OTP image.
+    #[arg(long, value_enum)]
+    creator_manuf_state: Option<CreatorManufState>,
+
+    /// Retrieve a health snapshot of the device in mission mode and record it in the report.
+    #[arg(long)]
+    health_snapshot: bool,
+
+    /// Import the endorsed certificates into the device in chunks acknowledged one by one, so a
+    /// corrupted chunk is resent alone.
+    #[arg(long)]
+    chunked_import: bool,
+
+    /// Verbosity of the personalization firmware console logs, recorded in the report.
+    #[arg(long, value_enum, default_value_t = DeviceLogLevel::Verbose)]
+    device_log_level: DeviceLogLevel,
+
+    #[command(flatten)]
+    entropy_check: EntropyCheck,
+
+    /// Smoke tests (JSON) to run on the console of the device in mission mode, after
+    /// personalization; any failing test fails provisioning.
+    #[arg(long)]
+    smoke_tests: Option<PathBuf>,
 }
 
-#[derive(Debug, Parser)]
-struct Opts {
+#[derive(Debug, Args)]
+struct RunOpts {
     #[command(flatten)]
-    init: InitializeTest,
+    device_id: DeviceIdInput,
 
     #[command(flatten)]
-    sram_program: SramProgramParams,
+    unlock: UnlockInput,
+
+    #[command(flatten)]
+    individualize: IndividualizeInput,
+
+    #[command(flatten)]
+    personalize: PersonalizeInput,
+
+    #[command(flatten)]
+    cp_tokens: CpTokenEscrowInput,
+
+    #[command(flatten)]
+    token_check: TokenCheckInput,
+
+    /// Comma-separated list of the only steps of the flow to run. The prerequisite of each step
+    /// must be run too, unless the LC state of the device shows it was done.
+    #[arg(long, value_enum, value_delimiter = ',', conflicts_with = "skip")]
+    only: Vec<FtStep>,
+
+    /// Comma-separated list of steps of the flow to skip, with the same prerequisite checks.
+    #[arg(long, value_enum, value_delimiter = ',')]
+    skip: Vec<FtStep>,
+}
 
+#[derive(Debug, Args)]
+struct UnlockOpts {
     #[command(flatten)]
-    provisioning_data: ManufFtProvisioningDataInput,
+    unlock: UnlockInput,
 
-    /// CA HJSON configuration file.
+    #[command(flatten)]
+    cp_tokens: CpTokenEscrowInput,
+
+    #[command(flatten)]
+    token_check: TokenCheckInput,
+}
+
+#[derive(Debug, Args)]
+struct IndividualizeOpts {
+    #[command(flatten)]
+    device_id: DeviceIdInput,
+
+    #[command(flatten)]
+    individualize: IndividualizeInput,
+
+    #[command(flatten)]
+    cp_tokens: CpTokenEscrowInput,
+
+    #[command(flatten)]
+    token_check: TokenCheckInput,
+
+    /// File to export a signed handoff bundle to, for personalization at a later site.
+    #[arg(long, requires = "handoff_signing_key")]
+    handoff_bundle: Option<PathBuf>,
+
+    /// ECDSA P-256 private key the handoff bundle is signed with: a PKCS#8 DER file, the PKCS#11
+    /// URI of a key of the HSM, e.g. `pkcs11:object=handoff-signing-key`, or a cloud KMS key,
+    /// e.g. `gcpkms:projects/<p>/locations/<l>/keyRings/<r>/cryptoKeys/<k>/cryptoKeyVersions/<v>`
+    /// or `awskms:alias/handoff-signing-key`.
+    #[arg(long, requires = "handoff_bundle")]
+    handoff_signing_key: Option<HostKeySpec>,
+}
+
+#[derive(Debug, Args)]
+struct TestExitOpts {
+    #[command(flatten)]
+    test_exit: TestExitInput,
+
+    #[command(flatten)]
+    cp_tokens: CpTokenEscrowInput,
+
+    #[command(flatten)]
+    token_check: TokenCheckInput,
+}
+
+#[derive(Debug, Args)]
+struct PersonalizeOpts {
+    /// Device ID to personalize; defaults to the device ID of the handoff bundle.
+    #[arg(long, required_unless_present = "handoff_bundle")]
+    device_id: Option<String>,
+
+    /// Signed handoff bundle of the individualization site to resume personalization from.
+    #[arg(long, requires = "handoff_verify_key")]
+    handoff_bundle: Option<PathBuf>,
+
+    /// ECDSA P-256 public key (DER) the handoff bundle is signed with.
+    #[arg(long, requires = "handoff_bundle")]
+    handoff_verify_key: Option<PathBuf>,
+
+    #[command(flatten)]
+    personalize: PersonalizeInput,
+}
+
+#[derive(Debug, Args)]
+struct OtpDumpOpts {
+    #[command(flatten)]
+    sram_program: SramProgramParams,
+
+    /// File to archive the decoded OTP dump to, as JSON.
     #[arg(long)]
-    ca_config: PathBuf,
+    output: Option<PathBuf>,
 
-    /// Second image (perso FW + ROM_EXT/Owner FW bundle) to bootstrap.
+    /// File to export the OTP dump to, as an OTP MEM file with ECC.
     #[arg(long)]
-    second_bootstrap: PathBuf,
+    otp_vmem: Option<PathBuf>,
+
+    /// File to export the OTP dump to, as a vmem file of 32-bit words without ECC.
+    #[arg(long)]
+    hex_vmem: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct AuditOpts {
+    /// Report saved by an earlier provisioning run, as JSON or as the `PROVISIONING_DATA:` line.
+    #[arg(long)]
+    report: PathBuf,
+
+    /// Also compare the certificates logged by the device firmware on the console after reset,
+    /// waiting for this regex to mark the end of the certificate dump.
+    #[arg(long)]
+    cert_anchor: Option<String>,
+
+    /// File to archive the audit result to, as JSON.
+    #[arg(long)]
+    output: Option<PathBuf>,
+}
+
+#[derive(Debug, Args)]
+struct VerifyOpts {
+    /// Comma-separated list of the LC states the device may be in. Any mission mode LC state is
+    /// accepted if unset.
+    #[arg(long, value_delimiter = ',', value_parser = parse_entry_lc_state)]
+    lc_states: Vec<DifLcCtrlState>,
+
+    /// OTP dump SRAM program, to check the OTP partitions are locked. Only loaded in the
+    /// TEST_UNLOCKED*, DEV and RMA LC states.
+    #[arg(long)]
+    otp_dump_elf: Option<PathBuf>,
+
+    /// Comma-separated list of the OTP partitions which must be locked.
+    #[arg(
+        long,
+        value_enum,
+        value_delimiter = ',',
+        default_values_t = IndividualizePartition::value_variants().to_vec()
+    )]
+    locked_partitions: Vec<IndividualizePartition>,
+
+    /// Boot the device and fetch the certificates logged by its firmware on the console, waiting
+    /// for this regex to mark the end of the certificate dump.
+    #[arg(long)]
+    cert_anchor: Option<String>,
+
+    /// Comma-separated list of the DICE certificates logged by the firmware, from root to leaf.
+    #[arg(long, value_delimiter = ',', default_value = "UDS,CDI_0,CDI_1")]
+    dice_chain: Vec<String>,
+
+    /// DICE CA certificate (PEM or DER) the logged DICE certificates must link up to.
+    #[arg(long, requires = "cert_anchor")]
+    dice_ca_cert: Option<PathBuf>,
+
+    /// File to archive the verification result to, as JSON.
+    #[arg(long)]
+    output: Option<PathBuf>,
+}
+
+impl VerifyOpts {
+    fn params(&self) -> VerifyParams {
+        VerifyParams {
+            lc_states: self.lc_states.clone(),
+            otp_dump: self.otp_dump_elf.as_ref().map(|elf| SramProgramParams {
+                elf: Some(elf.clone()),
+                ..Default::default()
+            }),
+            locked_partitions: self.locked_partitions.clone(),
+            cert_anchor: self.cert_anchor.clone(),
+            dice_chain: self.dice_chain.clone(),
+            dice_ca_cert: self.dice_ca_cert.clone(),
+        }
+    }
+}
+
+/// Register peek/poke commands, see `ft_lib::debug_regs`.
+#[cfg(feature = "debug-tools")]
+#[derive(Debug, Subcommand)]
+enum DebugCommand {
+    /// Read consecutive 32-bit registers.
+    Peek {
+        /// Address of the first register.
+        #[arg(value_parser = u32::from_str)]
+        addr: u32,
+
+        /// Number of registers to read.
+        #[arg(default_value = "1")]
+        count: usize,
+    },
+    /// Write a 32-bit register.
+    Poke {
+        /// Address of the register.
+        #[arg(value_parser = u32::from_str)]
+        addr: u32,
+
+        /// Value to write.
+        #[arg(value_parser = u32::from_str)]
+        value: u32,
+    },
+}
+
+#[derive(Debug, Args)]
+struct UnwrapRmaTokenOpts {
+    /// Wrapped RMA unlock token written by `--rma-token-out`, as the `.rma_token.json` record or
+    /// the raw `.rma_token.bin` ciphertext.
+    #[arg(required_unless_present = "escrow", conflicts_with = "escrow")]
+    token: Option<PathBuf>,
 
-    /// Console receive timeout.
-    #[arg(long, value_parser = humantime::parse_duration, default_value = "600s")]
-    timeout: Duration,
+    /// Escrow configuration (JSON) of `--rma-token-escrow` to fetch the token of `--device-id`
+    /// from.
+    #[arg(long, requires = "device_id")]
+    escrow: Option<PathBuf>,
+
+    /// Device ID whose token is fetched from `--escrow`.
+    #[arg(long, requires = "escrow")]
+    device_id: Option<String>,
+
+    /// Private key matching the `rma_wrap_key` of the key bundle: an RSA key (PKCS#1 or PKCS#8
+    /// DER), or an X25519 or P-384 key (PKCS#8 DER or PEM).
+    #[arg(long)]
+    wrap_key: PathBuf,
+}
+
+#[derive(Debug, Subcommand)]
+enum FtCommand {
+    /// Run the complete FT flow: unlock, individualize and personalize.
+    #[command(visible_alias = "all")]
+    Run(RunOpts),
+    /// Transition a TEST_LOCKED* device to the next TEST_UNLOCKED* state.
+    #[command(visible_alias = "test-unlock")]
+    Unlock(UnlockOpts),
+    /// Individualize OTP and transition a TEST_UNLOCKED* device to its mission mode LC state.
+    Individualize(IndividualizeOpts),
+    /// Transition an already individualized TEST_UNLOCKED* device to its mission mode LC state,
+    /// e.g. to retry a test exit which failed after individualization.
+    TestExit(TestExitOpts),
+    /// Personalize a device already in its mission mode LC state.
+    Personalize(PersonalizeOpts),
+    /// Dump and decode all readable OTP partitions, for failure analysis.
+    OtpDump(OtpDumpOpts),
+    /// Check a previously saved report still matches the device, without modifying it.
+    Audit(AuditOpts),
+    /// Verify an already provisioned device, without modifying it: LC state, OTP partition locks
+    /// and certificates logged by its firmware.
+    Verify(VerifyOpts),
+    /// Unwrap an archived RMA unlock token and print it, without connecting to a device.
+    UnwrapRmaToken(UnwrapRmaTokenOpts),
+    /// Peek and poke device registers over JTAG, on devices not in a production LC state.
+    #[cfg(feature = "debug-tools")]
+    #[command(subcommand)]
+    Debug(DebugCommand),
+    /// Print a shell completion script to stdout.
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+}
+
+/// Interface of the OTTF console of the provisioning firmware.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+enum ConsoleBackend {
+    /// SPI device, read over the `--console-spi` SPI interface.
+    #[default]
+    Spi,
+    /// RAM mailbox read over the RISC-V debug module, for packages without an accessible UART or
+    /// SPI device. The firmware must be built with
+    /// `--//sw/device/silicon_creator/manuf/base:console=dmi`.
+    Dmi,
+}
+
+/// Parity of the console UART.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+enum UartParity {
+    None,
+    Odd,
+    Even,
+}
+
+/// Serial configuration of the console UART, for interposer boards whose level shifters require
+/// non-default settings. Unset parameters are left as set by the transport configuration.
+#[derive(Clone, Debug, Args)]
+struct ConsoleUartConfig {
+    /// Baud rate of the console UART.
+    #[arg(long, requires = "console_uart")]
+    console_uart_baudrate: Option<u32>,
+
+    /// Parity of the console UART.
+    #[arg(long, value_enum, requires = "console_uart")]
+    console_uart_parity: Option<UartParity>,
+
+    /// Number of stop bits of the console UART.
+    #[arg(
+        long,
+        value_parser = clap::value_parser!(u8).range(1..=2),
+        requires = "console_uart"
+    )]
+    console_uart_stop_bits: Option<u8>,
+}
+
+impl ConsoleUartConfig {
+    fn apply(&self, uart: &dyn Uart) -> Result<()> {
+        if let Some(baudrate) = self.console_uart_baudrate {
+            uart.set_baudrate(baudrate)?;
+        }
+        if let Some(parity) = self.console_uart_parity {
+            uart.set_parity(match parity {
+                UartParity::None => Parity::None,
+                UartParity::Odd => Parity::Odd,
+                UartParity::Even => Parity::Even,
+            })
+            .context("Failed to set the console UART parity")?;
+        }
+        if let Some(stop_bits) = self.console_uart_stop_bits {
+            uart.set_stop_bits(match stop_bits {
+                1 => StopBits::One,
+                _ => StopBits::Two,
+            })
+            .context("Failed to set the console UART stop bits")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Parser)]
+struct Opts {
+    /// HJSON recipe describing the flow of the SKU: options, command and steps, see
+    /// `sw/host/provisioning/ft/src/recipe.rs`. Options given on the command line override it.
+    #[arg(long)]
+    recipe: Option<PathBuf>,
+
+    #[command(flatten)]
+    init: InitializeTest,
+
+    #[command(flatten)]
+    timeouts: TimeoutArgs,
 
     /// Name of the SPI interface to connect to the OTTF console.
     #[arg(long, default_value = "BOOTSTRAP")]
     console_spi: String,
 
-    /// Owner's firmware string indicating successful start up.
+    /// Interface of the OTTF console.
+    #[arg(long, value_enum, default_value_t = ConsoleBackend::Spi)]
+    console: ConsoleBackend,
+
+    /// Name of a UART to read in parallel to the OTTF console, e.g. for the ROM output. Commands
+    /// are only sent to the OTTF console.
     #[arg(long)]
-    owner_success_text: Option<String>,
+    console_uart: Option<String>,
+
+    #[command(flatten)]
+    console_uart_config: ConsoleUartConfig,
+
+    /// Pretty-print the provisioning data output.
+    #[arg(long, default_value = "false")]
+    pretty: bool,
+
+    /// Signed release manifest approving tool versions and firmware images for this SKU.
+    #[arg(long, requires = "release_manifest_key")]
+    release_manifest: Option<PathBuf>,
+
+    /// Detached signature of the release manifest [default: <RELEASE_MANIFEST>.sig].
+    #[arg(long, requires = "release_manifest")]
+    release_manifest_signature: Option<PathBuf>,
+
+    /// ECDSA P-256 public key (DER) the release manifest is signed with.
+    #[arg(long, requires = "release_manifest")]
+    release_manifest_key: Option<PathBuf>,
+
+    /// How to handle a tool version or firmware image not approved by the release manifest.
+    #[arg(long, value_enum, default_value_t = ReleasePolicy::Enforce)]
+    release_policy: ReleasePolicy,
+
+    /// File to durably record the step of the flow in, before each irreversible action, to tell
+    /// where the device stopped after a station power loss. A device left with ROM execution
+    /// enabled before test exit is recorded too, and later commands refuse to reset it.
+    #[arg(long)]
+    step_state: Option<PathBuf>,
+
+    /// Parse and check all the inputs of the command and open the transport backend, then print
+    /// the FT steps the command would run, without configuring the transport or touching the
+    /// device.
+    #[arg(long)]
+    dry_run: bool,
+
+    /// File to record the FT steps each device completed in, as JSON keyed by device ID, after
+    /// every step.
+    #[arg(long)]
+    checkpoint: Option<PathBuf>,
+
+    /// Skip the steps the device already completed per the checkpoint, restarting the flow at the
+    /// step it failed at.
+    #[arg(long, requires = "checkpoint")]
+    resume: bool,
+
+    /// File to write the result of the flow to, as JSON, whether it passes or fails: status and
+    /// duration of each step and of its operations, device ID, certificate hashes and output files.
+    #[arg(long)]
+    result_json: Option<PathBuf>,
+
+    /// File to write the durations of the steps and of their operations to, in the Prometheus text
+    /// format, e.g. `<dir>/ft.prom` for the textfile collector of a node exporter.
+    #[arg(long)]
+    metrics_out: Option<PathBuf>,
+
+    /// File to stream the events of the flow to as they happen, one JSON object per line: steps
+    /// started and finished, commands sent, device responses and console lines, e.g. for a test
+    /// executive showing the live progress of the device.
+    #[arg(long)]
+    events_out: Option<PathBuf>,
+
+    /// Pass criteria of the SKU deciding the verdict of a passing flow, as an expression over the
+    /// fields of the result, e.g. `all_steps_ok AND cert_chain_valid AND lc_state == PROD`; see
+    /// `sw/host/provisioning/ft_lib/src/pass_criteria.rs`.
+    #[arg(long)]
+    pass_criteria: Option<PassCriteria>,
+
+    /// Comma-separated list of the LC states devices may enter this FT insertion in, per the SKU
+    /// configuration, e.g. `test_locked0`. Devices in another LC state fail before any other
+    /// step, as a wrong insertion. Devices in any LC state are accepted if unset.
+    #[arg(long, value_delimiter = ',', value_parser = parse_entry_lc_state)]
+    entry_lc_states: Vec<DifLcCtrlState>,
+
+    /// Sensors (JSON) of the fixture to read the supply voltages and temperature of the device
+    /// from, at the start and at the end of each OTP write and LC transition; the readings are
+    /// recorded in the report.
+    #[arg(long)]
+    telemetry: Option<PathBuf>,
+
+    #[command(flatten)]
+    lc_state_check: LcStateCheck,
+
+    #[command(flatten)]
+    retry: RetryPolicy,
+
+    #[command(subcommand)]
+    command: FtCommand,
 }
 
-fn main() -> Result<()> {
-    let opts = Opts::parse();
-    opts.init.init_logging();
+/// Returns the version the tool was stamped with, if any.
+fn tool_version() -> Option<&'static str> {
+    // Without stamping, the variable substitution in stamp-env.txt does not happen.
+    Some(env!("BUILD_GIT_VERSION")).filter(|v| !v.starts_with('{'))
+}
 
-    let mut response = PersonalizeResponse::default();
+impl Opts {
+    /// Returns how the command individualizes the device, if it does.
+    fn individualize_backend(&self) -> IndividualizeBackend {
+        let input = match &self.command {
+            FtCommand::Run(run) => &run.individualize,
+            FtCommand::Individualize(individ) => &individ.individualize,
+            _ => return IndividualizeBackend::default(),
+        };
+        match &input.otp_preload_bitstream {
+            Some(bitstream) => IndividualizeBackend::OtpPreload(bitstream.clone()),
+            None => IndividualizeBackend::SramProgram,
+        }
+    }
 
-    // We call the below functions, instead of calling `opts.init.init_target()` since we do not
-    // want to perform bootstrap yet.
-    let transport = backend::create(&opts.init.backend_opts)?;
-    transport.apply_default_configuration(None)?;
-    let spi = transport.spi(&opts.console_spi)?;
-    let spi_console_device = SpiConsoleDevice::new(&*spi, None)?;
-    InitializeTest::print_result("load_bitstream", opts.init.load_bitstream.init(&transport))?;
+    /// Returns the signing policy of the device certificates the command endorses.
+    fn cert_policy(&self) -> Result<SigningPolicy> {
+        let input = match &self.command {
+            FtCommand::Run(run) => &run.personalize,
+            FtCommand::Personalize(perso) => &perso.personalize,
+            _ => return Ok(SigningPolicy::default()),
+        };
+        match &input.cert_policy {
+            Some(path) => SigningPolicy::load(path),
+            None => Ok(SigningPolicy::default()),
+        }
+    }
+
+    /// Returns the device certificates issued offline the command injects, if any.
+    fn offline_certs(&self) -> Result<Option<OfflineCerts>> {
+        let input = match &self.command {
+            FtCommand::Run(run) => &run.personalize,
+            FtCommand::Personalize(perso) => &perso.personalize,
+            _ => return Ok(None),
+        };
+        input
+            .offline_certs_dir
+            .as_deref()
+            .map(OfflineCerts::new)
+            .transpose()
+    }
+
+    /// Returns the keymgr diversification key of the command, if any.
+    fn keymgr_diversification(&self) -> Result<Option<KeymgrDiversification>> {
+        let input = match &self.command {
+            FtCommand::Run(run) => &run.personalize,
+            FtCommand::Personalize(perso) => &perso.personalize,
+            _ => return Ok(None),
+        };
+        input
+            .keymgr_diversification_key
+            .as_deref()
+            .map(KeymgrDiversification::load)
+            .transpose()
+    }
+
+    /// Returns the firmware images (role and path) the command loads onto the device.
+    fn firmware_images(&self) -> Vec<(&'static str, &Path)> {
+        fn sram_program(params: &SramProgramParams) -> Option<&PathBuf> {
+            params.elf.as_ref().or(params.vmem.as_ref())
+        }
+        let mut images = vec![("personalize", self.init.bootstrap.bootstrap.as_ref())];
+        let (individualize, personalize) = match &self.command {
+            FtCommand::Run(run) => (Some(&run.individualize), Some(&run.personalize)),
+            FtCommand::Individualize(individ) => (Some(&individ.individualize), None),
+            FtCommand::Personalize(perso) => (None, Some(&perso.personalize)),
+            FtCommand::OtpDump(dump_opts) => {
+                images.push(("otp_dump", sram_program(&dump_opts.sram_program)));
+                (None, None)
+            }
+            FtCommand::Verify(verify) => {
+                images.push(("otp_dump", verify.otp_dump_elf.as_ref()));
+                (None, None)
+            }
+            FtCommand::Unlock(_)
+            | FtCommand::TestExit(_)
+            | FtCommand::Audit(_)
+            | FtCommand::UnwrapRmaToken(_)
+            | FtCommand::Completions { .. } => (None, None),
+            #[cfg(feature = "debug-tools")]
+            FtCommand::Debug(_) => (None, None),
+        };
+        if let Some(input) = individualize {
+            images.push(("individualize", sram_program(&input.sram_program)));
+            images.push(("otp_dump", input.otp_dump_elf.as_ref()));
+        }
+        if let Some(input) = personalize {
+            images.push(("fw_bundle", Some(&input.second_bootstrap)));
+        }
+        images
+            .into_iter()
+            .filter_map(|(role, path)| Some((role, path?.as_path())))
+            .collect()
+    }
+
+    /// Returns the step journal of the diagnostic commands: it persists nothing, but carries the
+    /// ROM execution state a previous flow left the device in, so they do not reset it.
+    fn read_only_journal(&self) -> Result<StepJournal> {
+        match &self.step_state {
+            Some(path) => StepJournal::read_only(path),
+            None => Ok(StepJournal::disabled()),
+        }
+    }
+
+    /// Checks the release manifest, if any, approves this tool and the firmware images of the
+    /// command.
+    fn check_release(&self) -> Result<()> {
+        let (Some(manifest), Some(key)) = (&self.release_manifest, &self.release_manifest_key)
+        else {
+            return Ok(());
+        };
+        let signature = self.release_manifest_signature.clone().unwrap_or_else(|| {
+            let mut signature = manifest.clone().into_os_string();
+            signature.push(".sig");
+            signature.into()
+        });
+        let manifest = ReleaseManifest::load_signed(manifest, &signature, key)?;
+        manifest.check(
+            self.release_policy,
+            env!("FT_SKU"),
+            tool_version(),
+            &self.firmware_images(),
+        )
+    }
+}
+
+/// Personalization inputs, parsed ahead of any device operation.
+struct PersonalizeData {
+    rma_unlock_token: LcTokenSecret,
+    wrapped_rma_unlock_token: Vec<u8>,
+    /// Wrapping suite of `wrapped_rma_unlock_token`.
+    rma_token_wrapping: &'static str,
+    key_bundle: String,
+    ca_cfgs: HashMap<String, CaConfig>,
+    ca_keys: HashMap<String, CaKey>,
+    certgen_inputs: ManufCertgenInputs,
+    rma_escrow_cert: Option<X509>,
+    rma_token_escrow: Option<Box<dyn TokenEscrow>>,
+    smoke_tests: SmokeTestSuite,
+}
 
-    // Parse and format LC tokens.
-    let _test_unlock_token =
-        hex_string_to_u32_arrayvec::<4>(opts.provisioning_data.test_unlock_token.as_str())?;
-    let _test_exit_token =
-        hex_string_to_u32_arrayvec::<4>(opts.provisioning_data.test_exit_token.as_str())?;
-    let rma_unlock_token = if let Some(token) = &opts.provisioning_data.rma_unlock_token {
-        hex_string_to_u32_arrayvec::<4>(token.as_str())?
+/// Parses a token passed on the command line, loaded from a token file, or escrowed during CP,
+/// and checks it against its hash in the OTP image, if provided.
+fn parse_token(
+    token: Option<&str>,
+    token_file: Option<&Path>,
+    escrowed: Option<&str>,
+    name: &str,
+    otp_image: Option<&OtpImg>,
+) -> Result<LcTokenSecret> {
+    // e.g. `test_unlock_token` in token files, and `TEST_UNLOCK_TOKEN` in OTP images.
+    let key = format!("{}_token", name.replace(' ', "_"));
+    let token = if let Some(path) = token_file {
+        load_lc_token(path, &key)?
     } else {
-        random_token::<4>()?
+        let token = token
+            .or(escrowed)
+            .with_context(|| format!("No {name} token provided"))?;
+        parse_lc_token(token).with_context(|| format!("Invalid {name} token"))?
     };
-    let token_encrypt_key =
-        load_rsa_public_key(&opts.provisioning_data.token_encrypt_key_der_file)?;
-    let encrypted_rma_unlock_token = encrypt_token(&token_encrypt_key, &rma_unlock_token)?;
-    response.rma_unlock_token = Base64::encode_string(&encrypted_rma_unlock_token);
-    log::info!("Encrypted rma_unlock_token = {}", response.rma_unlock_token);
+    if let Some(otp_image) = otp_image {
+        check_lc_token_hash(&token, otp_image, &key.to_uppercase())
+            .with_context(|| format!("Wrong {name} token"))?;
+    }
+    LcTokenSecret::from_words(token)
+}
 
-    // Parse and prepare individualization ujson data payload.
-    let ft_individualize_data_in = ManufFtIndividualizeData {
-        device_id: hex_string_to_u32_arrayvec::<8>(opts.provisioning_data.device_id.as_str())?,
+impl CpTokenEscrowInput {
+    /// Looks up the tokens CP escrowed for the device, by the CP device ID of `device_id`, if
+    /// provided, else by `--cp-device-id`.
+    ///
+    /// The device is not read: its HW_CFG0 device ID is only programmed at individualization.
+    fn lookup(&self, device_id: Option<&DeviceIdInput>) -> Result<Option<EscrowRecord>> {
+        let Some(path) = &self.cp_token_escrow else {
+            return Ok(None);
+        };
+        let of_device_id = device_id
+            .map(|d| hex_string_to_u32_arrayvec::<DEVICE_ID_WORDS>(d.device_id.as_str()))
+            .transpose()?
+            .map(|d| cp_device_id_of(&d))
+            .transpose()?;
+        let given = self
+            .cp_device_id
+            .as_deref()
+            .map(|id| hex_string_to_u32_arrayvec::<CP_DEVICE_ID_WORDS>(id))
+            .transpose()
+            .context("Invalid CP device ID")?
+            .map(|id| format_device_id(&id));
+        let cp_device_id = match (of_device_id, given) {
+            (Some(of_device_id), Some(given)) => {
+                ensure!(
+                    of_device_id == given,
+                    "CP device ID {given} does not match the device ID ({of_device_id})"
+                );
+                of_device_id
+            }
+            (Some(id), None) | (None, Some(id)) => id,
+            (None, None) => bail!("The CP token escrow needs a --device-id or a --cp-device-id"),
+        };
+        let record = EscrowRecord::lookup(path, &cp_device_id)?;
+        log::info!("Using the tokens escrowed by CP for CP device ID {cp_device_id}.");
+        Ok(Some(record))
+    }
+}
+
+fn parse_raw_ca_key(arg: &str) -> Result<(String, HostKeySpec)> {
+    let (name, key) = arg
+        .split_once('=')
+        .with_context(|| format!("Expected <CA name>=<key>, got {arg}"))?;
+    Ok((name.to_string(), key.parse()?))
+}
+
+fn parse_entry_lc_state(arg: &str) -> Result<DifLcCtrlState> {
+    let lc_state = DifLcCtrlState::parse_lc_state_str(arg)?;
+    ensure!(
+        lc_state != DifLcCtrlState::StateInvalid,
+        "Unknown LC state {arg}"
+    );
+    Ok(lc_state)
+}
+
+impl KeyBundleInput {
+    /// Loads the key bundle, after checking its signature and expiry, and the configurations of
+    /// the CAs it references.
+    fn load(&self) -> Result<(ProvisioningKeyBundle, HashMap<String, CaConfig>)> {
+        let bundle = ProvisioningKeyBundle::load_signed(
+            &self.key_bundle,
+            &self.key_bundle_signature,
+            &self.key_bundle_key,
+        )?;
+        bundle.check(env!("FT_SKU"), SystemTime::now())?;
+        let sku_wrapping = Some(env!("FT_RMA_TOKEN_WRAPPING")).filter(|w| !w.is_empty());
+        bundle
+            .rma_wrap_key()?
+            .check_sku_wrapping(env!("FT_SKU"), sku_wrapping)?;
+        for (name, _) in &self.raw_ca_key {
+            ensure!(
+                bundle.cas.contains_key(name),
+                "Key bundle {} has no {name} CA",
+                bundle.name
+            );
+        }
+        let ca_cfgs = bundle.ca_configs()?;
+        Ok((bundle, ca_cfgs))
+    }
+
+    /// Loads the keys of the CAs of the key bundle.
+    fn load_ca_keys(
+        &self,
+        bundle: &ProvisioningKeyBundle,
+        ca_cfgs: &HashMap<String, CaConfig>,
+    ) -> Result<HashMap<String, CaKey>> {
+        let mut ca_keys = HashMap::<String, CaKey>::new();
+        for (ca, cfg) in ca_cfgs {
+            ca_keys.insert(
+                ca.to_string(),
+                match cfg.key_type {
+                    CaKeyType::Raw => {
+                        let (_, spec) = self
+                            .raw_ca_key
+                            .iter()
+                            .find(|(name, _)| name == ca)
+                            .with_context(|| {
+                                format!("No raw key for the {ca} CA (--raw-ca-key={ca}=<key>)")
+                            })?;
+                        let key = HostCaKey::load(spec)?;
+                        bundle.check_raw_key(ca, &key)?;
+                        log::info!("Using raw key {spec} for cert endorsement.");
+                        CaKey::HostKey(key)
+                    }
+                    CaKeyType::Token => {
+                        log::info!("Using PKCS#11 token key for cert endorsement.");
+                        CaKey::TokenKey(cfg.key.clone())
+                    }
+                    CaKeyType::Piv => {
+                        log::info!("Using PIV token key {} for cert endorsement.", cfg.key);
+                        CaKey::PivKey(PivKey::new(&cfg.key, self.piv_pin.clone())?)
+                    }
+                },
+            );
+        }
+        Ok(ca_keys)
+    }
+}
+
+impl PersonalizeInput {
+    /// Checks the creator manufacturing state is allowed for a device in `lc_state`.
+    fn check_lc_state(&self, lc_state: DifLcCtrlState) -> Result<()> {
+        match self.creator_manuf_state {
+            Some(manuf_state) => manuf_state.check_lc_state(lc_state),
+            None => Ok(()),
+        }
+    }
+
+    fn parse(&self, response: &mut PersonalizeResponse) -> Result<PersonalizeData> {
+        // Parse and format the RMA token.
+        let rma_unlock_token = if let Some(token) = &self.rma_unlock_token {
+            parse_lc_token(token).context("Invalid RMA unlock token")?
+        } else {
+            random_token::<LC_TOKEN_WORDS>()?
+        };
+        let rma_unlock_token = LcTokenSecret::from_words(rma_unlock_token)?;
+        // Load the keys from the key bundle.
+        let (bundle, ca_cfgs) = self.key_bundle.load()?;
+        // Certs issued offline are injected as is, no CA key is needed to endorse them.
+        let ca_keys = match &self.offline_certs_dir {
+            Some(_) => HashMap::new(),
+            None => self.key_bundle.load_ca_keys(&bundle, &ca_cfgs)?,
+        };
+        response.stats.log_string("key-bundle", &bundle.name);
+        let rma_wrap_key = bundle.rma_wrap_key()?;
+        let encrypted_rma_unlock_token = rma_wrap_key.wrap(&rma_unlock_token)?;
+        response.rma_unlock_token = Base64::encode_string(&encrypted_rma_unlock_token);
+        log::info!("Encrypted rma_unlock_token = {}", response.rma_unlock_token);
+
+        // Parse and prepare personalization ujson data payload.
+        let dice_ca_key_id =
+            hex_string_to_u8_arrayvec::<KEY_ID_BYTES>(ca_cfgs["dice"].key_id.as_str())?;
+        let ext_ca_key_id =
+            hex_string_to_u8_arrayvec::<KEY_ID_BYTES>(ca_cfgs["ext"].key_id.as_str())?;
+        let certgen_inputs = ManufCertgenInputs {
+            rom_ext_measurement: hex_string_to_u32_arrayvec::<MEASUREMENT_WORDS>(
+                self.rom_ext_measurement.as_str(),
+            )?,
+            rom_ext_security_version: self.rom_ext_security_version,
+            owner_manifest_measurement: hex_string_to_u32_arrayvec::<MEASUREMENT_WORDS>(
+                self.owner_manifest_measurement.as_str(),
+            )?,
+            owner_measurement: hex_string_to_u32_arrayvec::<MEASUREMENT_WORDS>(
+                self.owner_measurement.as_str(),
+            )?,
+            owner_security_version: self.owner_security_version,
+            dice_auth_key_key_id: dice_ca_key_id,
+            ext_auth_key_key_id: ext_ca_key_id,
+        };
+
+        let rma_escrow_cert = self
+            .rma_escrow_cert
+            .as_deref()
+            .map(load_recipient_cert)
+            .transpose()?;
+        let rma_token_escrow: Option<Box<dyn TokenEscrow>> =
+            match (&self.rma_token_escrow, &self.rma_token_out) {
+                (Some(config), _) => Some(TokenEscrowConfig::load(config)?.open()),
+                (None, Some(dir)) => Some(Box::new(FileEscrow { dir: dir.clone() })),
+                (None, None) => None,
+            };
+        let smoke_tests = self
+            .smoke_tests
+            .as_deref()
+            .map(SmokeTestSuite::load)
+            .transpose()?
+            .unwrap_or_default();
+
+        Ok(PersonalizeData {
+            rma_unlock_token,
+            wrapped_rma_unlock_token: encrypted_rma_unlock_token,
+            rma_token_wrapping: rma_wrap_key.wrapping(),
+            key_bundle: bundle.name,
+            ca_cfgs,
+            ca_keys,
+            certgen_inputs,
+            rma_escrow_cert,
+            rma_token_escrow,
+            smoke_tests,
+        })
+    }
+}
+
+fn unwrap_rma_token_file(opts: &UnwrapRmaTokenOpts) -> Result<()> {
+    let record = match (&opts.token, &opts.escrow, &opts.device_id) {
+        (_, Some(config), Some(device_id)) => {
+            Some(TokenEscrowConfig::load(config)?.open().fetch(device_id)?)
+        }
+        (Some(token), _, _) if token.extension().is_some_and(|ext| ext == "json") => {
+            Some(WrappedRmaToken::load(token)?)
+        }
+        _ => None,
     };
-    response.device_id = ft_individualize_data_in
-        .device_id
-        .iter()
-        .map(|v| format!("{v:08X}"))
-        .collect::<Vec<String>>()
-        .join("");
-
-    // Parse and prepare CA key.
-    let mut ca_cfgs: HashMap<String, CaConfig> = serde_annotate::from_str(
-        &std::fs::read_to_string(opts.ca_config)
-            .with_context(|| "Failed to open CA config JSON.")?,
-    )?;
-    let mut ca_keys = HashMap::<String, CaKey>::new();
-    for (ca, cfg) in &mut ca_cfgs {
-        ca_keys.insert(
-            ca.to_string(),
-            match cfg.key_type {
-                CaKeyType::Raw => {
-                    log::info!("Using raw key for cert endorsement.");
-                    CaKey::RawKey(SecretKey::<NistP256>::read_pkcs8_der_file(
-                        cfg.key.as_str(),
-                    )?)
-                }
-                CaKeyType::Token => {
-                    log::info!("Using PKCS#11 token key for cert endorsement.");
-                    CaKey::TokenKey(cfg.key.clone())
-                }
-            },
+    let wrap_key = RmaUnwrapKey::load(&opts.wrap_key)?;
+    let (wrapping, wrapped_token) = if let Some(record) = record {
+        log::info!(
+            "Unwrapping the RMA unlock token of device {} ({}, key bundle {})",
+            record.device_id,
+            record.sku,
+            record.key_bundle
         );
+        (record.wrapping.clone(), record.wrapped_token()?)
+    } else {
+        let token = opts
+            .token
+            .as_ref()
+            .expect("clap requires a token or an escrow");
+        // Raw wrapped tokens don't name their suite, that of the key is assumed.
+        let wrapped_token = std::fs::read(token)
+            .with_context(|| format!("Failed to read wrapped RMA unlock token {token:?}"))?;
+        (wrap_key.wrapping().to_string(), wrapped_token)
+    };
+    let token = unwrap_rma_token(&wrap_key, &wrapping, &wrapped_token)?;
+    // In the format of `--rma-unlock-token`.
+    println!("{}", token.to_hex().expose());
+    Ok(())
+}
+
+#[cfg(feature = "debug-tools")]
+fn run_debug_command(ft: &FtProvisioner, command: &DebugCommand) -> Result<()> {
+    let mut session = ft.debug_session()?;
+    match command {
+        DebugCommand::Peek { addr, count } => {
+            for (i, value) in session.peek(*addr, *count)?.iter().enumerate() {
+                println!("{:#010x}: {value:#010x}", addr + 4 * i as u32);
+            }
+        }
+        DebugCommand::Poke { addr, value } => session.poke(*addr, *value)?,
     }
+    session.close()
+}
 
-    // Parse and prepare personalization ujson data payload.
-    let rom_ext_measurement =
-        hex_string_to_u32_arrayvec::<8>(opts.provisioning_data.rom_ext_measurement.as_str())?;
-    let rom_ext_security_version = opts.provisioning_data.rom_ext_security_version;
-    let owner_manifest_measurement = hex_string_to_u32_arrayvec::<8>(
-        opts.provisioning_data.owner_manifest_measurement.as_str(),
-    )?;
-    let owner_measurement =
-        hex_string_to_u32_arrayvec::<8>(opts.provisioning_data.owner_measurement.as_str())?;
-    let owner_security_version = opts.provisioning_data.owner_security_version;
-    let dice_ca_key_id = hex_string_to_u8_arrayvec::<20>(ca_cfgs["dice"].key_id.as_str())?;
-    let ext_ca_key_id = hex_string_to_u8_arrayvec::<20>(ca_cfgs["ext"].key_id.as_str())?;
-    let _perso_certgen_inputs = ManufCertgenInputs {
-        rom_ext_measurement: rom_ext_measurement.clone(),
-        rom_ext_security_version,
-        owner_manifest_measurement: owner_manifest_measurement.clone(),
-        owner_measurement: owner_measurement.clone(),
-        owner_security_version,
-        dice_auth_key_key_id: dice_ca_key_id.clone(),
-        ext_auth_key_key_id: ext_ca_key_id.clone(),
+fn write_otp_dump_json(dump: &OtpDump, path: &Path, pretty: bool) -> Result<()> {
+    let doc = if pretty {
+        serde_json::to_string_pretty(dump)?
+    } else {
+        serde_json::to_string(dump)?
+    };
+    std::fs::write(path, doc).with_context(|| format!("Failed to write OTP dump to {path:?}"))
+}
+
+fn write_audit_result(result: &AuditResult, path: &Path, pretty: bool) -> Result<()> {
+    let doc = if pretty {
+        serde_json::to_string_pretty(result)?
+    } else {
+        serde_json::to_string(result)?
     };
+    std::fs::write(path, doc).with_context(|| format!("Failed to write the result to {path:?}"))
+}
 
+fn write_otp_vmem(dump: &OtpDump, path: &Path) -> Result<()> {
+    std::fs::write(path, dump.to_otp_vmem()?)
+        .with_context(|| format!("Failed to write OTP MEM file to {path:?}"))
+}
+
+fn write_hex_vmem(dump: &OtpDump, path: &Path) -> Result<()> {
+    std::fs::write(path, dump.to_hex_vmem()?)
+        .with_context(|| format!("Failed to write OTP hex file to {path:?}"))
+}
+
+/// Reads back the OTP partitions programmed during individualization and exports them to `dir`.
+fn export_individualized_otp(
+    ft: &FtProvisioner,
+    otp_dump_elf: &Path,
+    dir: &Path,
+    device_id: &str,
+) -> Result<()> {
+    let dump = ft.otp_dump(&SramProgramParams {
+        elf: Some(otp_dump_elf.to_path_buf()),
+        vmem: None,
+        load_addr: None,
+    })?;
+    std::fs::create_dir_all(dir)
+        .with_context(|| format!("Failed to create OTP export directory {dir:?}"))?;
+    let json = dir.join(format!("{device_id}.otp.json"));
+    write_otp_dump_json(&dump, &json, true)?;
+    ft.events().artifact_written("otp-json", json);
+    let vmem = dir.join(format!("{device_id}.otp.vmem"));
+    write_otp_vmem(&dump, &vmem)?;
+    ft.events().artifact_written("otp-vmem", vmem);
+    let hex_vmem = dir.join(format!("{device_id}.otp.32.vmem"));
+    write_hex_vmem(&dump, &hex_vmem)?;
+    ft.events().artifact_written("otp-hex-vmem", hex_vmem);
+    log::info!("Individualized OTP contents exported to {dir:?}");
+    Ok(())
+}
+
+fn unlock(
+    ft: &FtProvisioner,
+    test_unlock_token: &LcTokenSecret,
+    test_unlock_lc_state: DifLcCtrlState,
+    response: &mut PersonalizeResponse,
+) -> Result<()> {
     // Only run test unlock operation if we are in a locked LC state.
-    response.lc_state.initial = read_lc_state(
-        &transport,
-        &opts.init.jtag_params,
-        opts.init.bootstrap.options.reset_delay,
-    )?;
+    response.lc_state.initial = ft.check_entry_lc_state()?;
     match response.lc_state.initial {
         DifLcCtrlState::TestLocked0
         | DifLcCtrlState::TestLocked1
@@ -218,11 +1357,10 @@ fn main() -> Result<()> {
         | DifLcCtrlState::TestLocked5
         | DifLcCtrlState::TestLocked6 => {
             let t0 = Instant::now();
-            test_unlock(
-                &transport,
-                &opts.init.jtag_params,
-                opts.init.bootstrap.options.reset_delay,
-                &_test_unlock_token,
+            ft.test_unlock(
+                test_unlock_token,
+                response.lc_state.initial,
+                test_unlock_lc_state,
             )?;
             response.stats.log_elapsed_time("test-unlock", t0);
         }
@@ -230,14 +1368,32 @@ fn main() -> Result<()> {
             log::info!("Skipping test unlock operation. Device is already unlocked.");
         }
     };
+    Ok(())
+}
+
+fn individualize(
+    ft: &FtProvisioner,
+    device_id: &DeviceIdInput,
+    input: &IndividualizeInput,
+    test_exit_token: Option<&LcTokenSecret>,
+    response: &mut PersonalizeResponse,
+) -> Result<()> {
+    let (trim_file, alert_cfg) = input.load()?;
+
+    // Parse and prepare individualization ujson data payload.
+    let no_trim = AstTrim::default();
+    let mut ft_individualize_data_in = ManufFtIndividualizeData {
+        device_id: hex_string_to_u32_arrayvec::<DEVICE_ID_WORDS>(device_id.device_id.as_str())?,
+        partitions: IndividualizePartition::bitmask(&input.partitions),
+        ast_trim_mask: ArrayVec::from(no_trim.mask),
+        ast_trim_value: ArrayVec::from(no_trim.value),
+    };
+    response.device_id = format_device_id(&ft_individualize_data_in.device_id);
+    ft.journal().set_device_id(&response.device_id)?;
 
     // Only run the SRAM individualize program in a test unlocked state. If we have transitioned to
     // a mission state already, then we can skip this step.
-    response.lc_state.unlocked = read_lc_state(
-        &transport,
-        &opts.init.jtag_params,
-        opts.init.bootstrap.options.reset_delay,
-    )?;
+    response.lc_state.unlocked = ft.read_lc_state()?;
     match response.lc_state.unlocked {
         DifLcCtrlState::TestUnlocked0 => {
             bail!("FT stage cannot be run from test unlocked 0. Run CP stage first.");
@@ -250,65 +1406,859 @@ fn main() -> Result<()> {
         | DifLcCtrlState::TestUnlocked6
         | DifLcCtrlState::TestUnlocked7 => {
             response.lc_state.individualize = Some(response.lc_state.unlocked);
+            if input.partitions.contains(&IndividualizePartition::HwCfg) {
+                ft_individualize_data_in.device_id = ft.check_hw_cfg_device_id(
+                    &ft_individualize_data_in.device_id,
+                    input.hw_cfg_policy,
+                )?;
+                response.device_id = format_device_id(&ft_individualize_data_in.device_id);
+                ft.journal().set_device_id(&response.device_id)?;
+            }
+            if let Some(trim_file) = &trim_file {
+                trim_file
+                    .ast_trim(&ft_individualize_data_in.device_id)?
+                    .apply(&mut ft_individualize_data_in);
+            }
             let t0 = Instant::now();
-            run_sram_ft_individualize(
-                &transport,
-                &opts.init.jtag_params,
-                opts.init.bootstrap.options.reset_delay,
-                &opts.sram_program,
+            ft.individualize(
+                &input.sram_program,
+                &input.clock_ramp,
                 &ft_individualize_data_in,
-                opts.timeout,
-                &spi_console_device,
+                &alert_cfg,
             )?;
             response.stats.log_elapsed_time("ft-individualize", t0);
+            // The CPU can no longer be debugged once in a mission mode, so the OTP contents must
+            // be read back before test exit.
+            if let (Some(elf), Some(dir)) = (&input.otp_dump_elf, &input.otp_export_dir) {
+                let t0 = Instant::now();
+                export_individualized_otp(ft, elf, dir, &response.device_id)?;
+                response.stats.log_elapsed_time("otp-export", t0);
+            }
+            let Some(test_exit_token) = test_exit_token else {
+                log::warn!("Skipping test exit operation. The device must not be reset.");
+                return Ok(());
+            };
             let t0 = Instant::now();
-            test_exit(
-                &transport,
-                &opts.init.jtag_params,
-                opts.init.bootstrap.options.reset_delay,
-                &_test_exit_token,
-                opts.provisioning_data.target_mission_mode_lc_state,
+            ft.test_exit(
+                test_exit_token,
+                &input.test_exit.token_generation,
+                input.test_exit.target_mission_mode_lc_state,
             )?;
-            response.lc_state.mission_mode =
-                Some(opts.provisioning_data.target_mission_mode_lc_state);
+            response.lc_state.mission_mode = Some(input.test_exit.target_mission_mode_lc_state);
             response.stats.log_elapsed_time("test-exit", t0);
         }
         _ => {
             log::info!("Skipping individualize operation. Device is already in a mission mode.");
         }
     };
+    Ok(())
+}
 
+/// Transitions a device already individualized, in the `response.lc_state.unlocked` LC state, to
+/// its mission mode LC state.
+fn test_exit(
+    ft: &FtProvisioner,
+    input: &TestExitInput,
+    test_exit_token: &LcTokenSecret,
+    response: &mut PersonalizeResponse,
+) -> Result<()> {
+    match response.lc_state.unlocked {
+        DifLcCtrlState::TestUnlocked1
+        | DifLcCtrlState::TestUnlocked2
+        | DifLcCtrlState::TestUnlocked3
+        | DifLcCtrlState::TestUnlocked4
+        | DifLcCtrlState::TestUnlocked5
+        | DifLcCtrlState::TestUnlocked6
+        | DifLcCtrlState::TestUnlocked7 => {
+            let t0 = Instant::now();
+            ft.test_exit(
+                test_exit_token,
+                &input.token_generation,
+                input.target_mission_mode_lc_state,
+            )?;
+            response.lc_state.mission_mode = Some(input.target_mission_mode_lc_state);
+            response.stats.log_elapsed_time("test-exit", t0);
+        }
+        state if FtStep::TestExit.done_in(state) => {
+            log::info!("Skipping test exit operation. Device is already in a mission mode.");
+        }
+        state => bail!(
+            "Test exit cannot be run from the {} LC state.",
+            state.lc_state_to_str()
+        ),
+    }
+    Ok(())
+}
+
+fn personalize(
+    ft: &FtProvisioner,
+    transport: &TransportWrapper,
+    input: &PersonalizeInput,
+    data: PersonalizeData,
+    response: &mut PersonalizeResponse,
+) -> Result<()> {
     // Once we are in a mission mode, we no longer need to provide a DFT strapping sequence on
     // every reset, as DFT is no longer enabled in mission modes.
     transport.ignore_dft_straps_on_reset()?;
 
-    run_ft_personalize(
-        &transport,
-        &opts.init,
-        &rma_unlock_token,
-        ca_cfgs,
-        ca_keys,
-        &_perso_certgen_inputs,
-        opts.second_bootstrap,
-        &spi_console_device,
-        opts.timeout,
-        &mut response,
+    // The device programs the token hash into SECRET2 as soon as it receives it, so archive the
+    // token before personalization starts: a failure of any later step must not lose it. If the
+    // device is personalized again, it gets a new token and the escrow serves the latest one.
+    if let Some(escrow) = &data.rma_token_escrow {
+        let record = WrappedRmaToken::new(
+            env!("FT_SKU"),
+            &response.device_id,
+            &data.key_bundle,
+            data.rma_token_wrapping,
+            &data.wrapped_rma_unlock_token,
+        );
+        for path in escrow.store(&record)? {
+            ft.events().artifact_written("wrapped-rma-token", path);
+        }
+    }
+
+    let dice_ca_cert = data.ca_cfgs["dice"].certificate.clone();
+    ft.personalize(
+        &data.rma_unlock_token,
+        data.ca_cfgs,
+        data.ca_keys,
+        &data.certgen_inputs,
+        PersoExportOptions {
+            compression: input.perso_compression,
+            health_snapshot: input.health_snapshot,
+            chunked_import: input.chunked_import,
+        },
+        input.creator_manuf_state,
+        input.device_log_level,
+        &input.entropy_check,
+        input.second_bootstrap.clone(),
+        response,
     )?;
 
-    check_slot_b_boot_up(
+    ft.check_slot_b_boot_up(response, input.owner_success_text.clone())?;
+    if let Some(dir) = &input.cert_export_dir {
+        for path in export_certs(response.certs.values(), &response.device_id, dir)? {
+            ft.events().artifact_written("cert", path);
+        }
+    }
+    if let Some(dir) = &input.pubkey_export_dir {
+        export_cert_public_keys(response.certs.values(), &response.device_id, dir)?;
+    }
+    if let Some(dir) = &input.attestation_export_dir {
+        let ca = std::fs::read(&dice_ca_cert)
+            .with_context(|| format!("Failed to read DICE CA certificate {dice_ca_cert:?}"))?;
+        let chain = DiceChain::new(response.certs.values(), X509::from_pem(&ca)?)?;
+        for path in export_attestation_bundles(
+            &chain,
+            &input.attestation_formats,
+            &response.device_id,
+            dir,
+        )? {
+            ft.events().artifact_written("attestation-bundle", path);
+        }
+    }
+    if let (Some(recipient), Some(dir)) = (&data.rma_escrow_cert, &input.rma_escrow_dir) {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create RMA escrow directory {dir:?}"))?;
+        let record = RmaEscrowRecord::new(
+            env!("FT_SKU"),
+            &response.device_id,
+            &data.rma_unlock_token,
+            response.certs.values(),
+        )
+        .with_keymgr_binding(response.keymgr_binding.clone());
+        let path = dir.join(format!("{}.rma.p7m", response.device_id));
+        record.save_envelope(&path, recipient)?;
+        log::info!("RMA escrow envelope exported to {path:?}");
+        ft.events().artifact_written("rma-escrow", path);
+    }
+    if !data.smoke_tests.0.is_empty() {
+        ft.run_smoke_tests(&data.smoke_tests, response)?;
+    }
+    Ok(())
+}
+
+/// The test unlock step of a `run` flow.
+struct TestUnlockStep<'r> {
+    token: &'r LcTokenSecret,
+    lc_state: DifLcCtrlState,
+}
+
+impl ProvisioningStep for TestUnlockStep<'_> {
+    fn name(&self) -> &'static str {
+        FtStep::TestUnlock.name()
+    }
+
+    fn run(&mut self, session: &mut Session) -> Result<()> {
+        unlock(session.ft, self.token, self.lc_state, session.response)
+    }
+
+    fn reports_outcome(&self) -> bool {
+        true
+    }
+}
+
+/// The individualization step of a `run` flow, followed by the test exit if given its token.
+struct IndividualizeStep<'r> {
+    device_id: &'r DeviceIdInput,
+    input: &'r IndividualizeInput,
+    test_exit_token: Option<&'r LcTokenSecret>,
+}
+
+impl ProvisioningStep for IndividualizeStep<'_> {
+    fn name(&self) -> &'static str {
+        FtStep::Individualize.name()
+    }
+
+    fn run(&mut self, session: &mut Session) -> Result<()> {
+        individualize(
+            session.ft,
+            self.device_id,
+            self.input,
+            self.test_exit_token,
+            session.response,
+        )
+    }
+
+    fn reports_outcome(&self) -> bool {
+        true
+    }
+}
+
+/// The test exit step of a `run` flow not individualizing the device.
+struct TestExitStep<'r> {
+    input: &'r TestExitInput,
+    token: &'r LcTokenSecret,
+}
+
+impl ProvisioningStep for TestExitStep<'_> {
+    fn name(&self) -> &'static str {
+        FtStep::TestExit.name()
+    }
+
+    fn run(&mut self, session: &mut Session) -> Result<()> {
+        session.response.lc_state.unlocked = session.ft.read_lc_state()?;
+        test_exit(session.ft, self.input, self.token, session.response)
+    }
+
+    fn reports_outcome(&self) -> bool {
+        true
+    }
+}
+
+/// The personalization step of a `run` flow.
+struct PersonalizeStep<'r> {
+    transport: &'r TransportWrapper,
+    input: &'r PersonalizeInput,
+    data: Option<PersonalizeData>,
+}
+
+impl ProvisioningStep for PersonalizeStep<'_> {
+    fn name(&self) -> &'static str {
+        FtStep::Personalize.name()
+    }
+
+    fn run(&mut self, session: &mut Session) -> Result<()> {
+        let data = self
+            .data
+            .take()
+            .context("The personalization step was already run")?;
+        personalize(
+            session.ft,
+            self.transport,
+            self.input,
+            data,
+            session.response,
+        )
+    }
+
+    fn reports_outcome(&self) -> bool {
+        true
+    }
+}
+
+fn plan_test_unlock(input: &UnlockInput) -> String {
+    format!(
+        "test-unlock: TEST_LOCKED* -> {}",
+        input.test_unlock_lc_state.lc_state_to_str()
+    )
+}
+
+fn plan_individualize(input: &IndividualizeInput) -> String {
+    let partitions: Vec<_> = input
+        .partitions
+        .iter()
+        .filter_map(|p| Some(p.to_possible_value()?.get_name().to_string()))
+        .collect();
+    format!("individualize: {}", partitions.join(", "))
+}
+
+fn plan_test_exit(input: &TestExitInput) -> String {
+    format!(
+        "test-exit: TEST_UNLOCKED* -> {}",
+        input.target_mission_mode_lc_state.lc_state_to_str()
+    )
+}
+
+/// Parses and checks all the inputs of `opts.command`, as `run_flow` does before touching the
+/// device, and returns the FT steps it would run.
+fn plan_flow(opts: &Opts) -> Result<Vec<String>> {
+    opts.cert_policy()?;
+    opts.offline_certs()?;
+    opts.keymgr_diversification()?;
+    if let Some(path) = &opts.telemetry {
+        Telemetry::load(path)?;
+    }
+    if let Some(path) = &opts.checkpoint {
+        Checkpoint::open(path, opts.resume)?;
+    }
+    for (role, path) in opts.firmware_images() {
+        ensure!(path.is_file(), "{role} image {path:?} does not exist");
+    }
+    let mut response = PersonalizeResponse::default();
+    let steps = match &opts.command {
+        FtCommand::Run(run) => {
+            run.personalize
+                .check_lc_state(run.individualize.test_exit.target_mission_mode_lc_state)?;
+            run.personalize.parse(&mut response)?;
+            let otp_image = run.token_check.load()?;
+            hex_string_to_u32_arrayvec::<DEVICE_ID_WORDS>(run.device_id.device_id.as_str())?;
+            let escrowed = run.cp_tokens.lookup(Some(&run.device_id))?;
+            parse_token(
+                run.unlock.test_unlock_token.as_deref(),
+                run.unlock.test_unlock_token_file.as_deref(),
+                escrowed.as_ref().map(|r| r.test_unlock_token.as_str()),
+                "test unlock",
+                otp_image.as_ref(),
+            )?;
+            parse_token(
+                run.individualize.test_exit.test_exit_token.as_deref(),
+                run.individualize.test_exit.test_exit_token_file.as_deref(),
+                escrowed.as_ref().map(|r| r.test_exit_token.as_str()),
+                "test exit",
+                otp_image.as_ref(),
+            )?;
+            run.individualize.load()?;
+            StepPlan::new(&run.only, &run.skip)
+                .steps()
+                .iter()
+                .map(|step| match step {
+                    FtStep::TestUnlock => plan_test_unlock(&run.unlock),
+                    FtStep::Individualize => plan_individualize(&run.individualize),
+                    FtStep::TestExit => plan_test_exit(&run.individualize.test_exit),
+                    FtStep::Personalize => step.name().to_string(),
+                })
+                .collect()
+        }
+        FtCommand::Unlock(unlock_opts) => {
+            let otp_image = unlock_opts.token_check.load()?;
+            let escrowed = unlock_opts.cp_tokens.lookup(None)?;
+            parse_token(
+                unlock_opts.unlock.test_unlock_token.as_deref(),
+                unlock_opts.unlock.test_unlock_token_file.as_deref(),
+                escrowed.as_ref().map(|r| r.test_unlock_token.as_str()),
+                "test unlock",
+                otp_image.as_ref(),
+            )?;
+            vec![plan_test_unlock(&unlock_opts.unlock)]
+        }
+        FtCommand::Individualize(individ) => {
+            if let Some(key) = &individ.handoff_signing_key {
+                key.load()?;
+            }
+            let otp_image = individ.token_check.load()?;
+            hex_string_to_u32_arrayvec::<DEVICE_ID_WORDS>(individ.device_id.device_id.as_str())?;
+            let escrowed = individ.cp_tokens.lookup(Some(&individ.device_id))?;
+            parse_token(
+                individ.individualize.test_exit.test_exit_token.as_deref(),
+                individ
+                    .individualize
+                    .test_exit
+                    .test_exit_token_file
+                    .as_deref(),
+                escrowed.as_ref().map(|r| r.test_exit_token.as_str()),
+                "test exit",
+                otp_image.as_ref(),
+            )?;
+            individ.individualize.load()?;
+            vec![
+                plan_individualize(&individ.individualize),
+                plan_test_exit(&individ.individualize.test_exit),
+            ]
+        }
+        FtCommand::TestExit(exit_opts) => {
+            let otp_image = exit_opts.token_check.load()?;
+            let escrowed = exit_opts.cp_tokens.lookup(None)?;
+            parse_token(
+                exit_opts.test_exit.test_exit_token.as_deref(),
+                exit_opts.test_exit.test_exit_token_file.as_deref(),
+                escrowed.as_ref().map(|r| r.test_exit_token.as_str()),
+                "test exit",
+                otp_image.as_ref(),
+            )?;
+            vec![plan_test_exit(&exit_opts.test_exit)]
+        }
+        FtCommand::Personalize(perso) => {
+            if let (Some(path), Some(key)) = (&perso.handoff_bundle, &perso.handoff_verify_key) {
+                HandoffBundle::load_signed(path, &EcdsaPublicKey::load(key)?)?;
+            }
+            if let Some(device_id) = &perso.device_id {
+                hex_string_to_u32_arrayvec::<DEVICE_ID_WORDS>(device_id)?;
+            }
+            perso.personalize.parse(&mut response)?;
+            vec!["personalize".to_string()]
+        }
+        FtCommand::OtpDump(_)
+        | FtCommand::Audit(_)
+        | FtCommand::Verify(_)
+        | FtCommand::UnwrapRmaToken(_)
+        | FtCommand::Completions { .. } => bail!("Dry runs only apply to provisioning commands"),
+        #[cfg(feature = "debug-tools")]
+        FtCommand::Debug(_) => bail!("Dry runs only apply to provisioning commands"),
+    };
+    Ok(steps)
+}
+
+/// Runs the FT flow of the provisioning `opts.command`.
+fn run_flow(
+    ft: &FtProvisioner,
+    transport: &TransportWrapper,
+    opts: &Opts,
+    response: &mut PersonalizeResponse,
+) -> Result<()> {
+    match &opts.command {
+        FtCommand::Run(run) => {
+            let plan = StepPlan::new(&run.only, &run.skip);
+            // Parse all inputs before touching the device.
+            run.personalize
+                .check_lc_state(run.individualize.test_exit.target_mission_mode_lc_state)?;
+            let perso_data = run.personalize.parse(response)?;
+            let otp_image = run.token_check.load()?;
+            let escrowed = run.cp_tokens.lookup(Some(&run.device_id))?;
+            let test_unlock_token = parse_token(
+                run.unlock.test_unlock_token.as_deref(),
+                run.unlock.test_unlock_token_file.as_deref(),
+                escrowed.as_ref().map(|r| r.test_unlock_token.as_str()),
+                "test unlock",
+                otp_image.as_ref(),
+            )?;
+            let test_exit_token = parse_token(
+                run.individualize.test_exit.test_exit_token.as_deref(),
+                run.individualize.test_exit.test_exit_token_file.as_deref(),
+                escrowed.as_ref().map(|r| r.test_exit_token.as_str()),
+                "test exit",
+                otp_image.as_ref(),
+            )?;
+            // Record the device ID before the test unlock, for its checkpoint.
+            response.device_id = format_device_id(&hex_string_to_u32_arrayvec::<DEVICE_ID_WORDS>(
+                run.device_id.device_id.as_str(),
+            )?);
+            ft.journal().set_device_id(&response.device_id)?;
+            if !plan.is_complete() {
+                response.lc_state.initial = ft.check_entry_lc_state()?;
+                plan.check(response.lc_state.initial)?;
+            }
+            let mut flow = Flow::new();
+            if plan.runs(FtStep::TestUnlock) {
+                flow = flow.then(TestUnlockStep {
+                    token: &test_unlock_token,
+                    lc_state: run.unlock.test_unlock_lc_state,
+                });
+            }
+            if plan.runs(FtStep::Individualize) {
+                flow = flow.then(IndividualizeStep {
+                    device_id: &run.device_id,
+                    input: &run.individualize,
+                    test_exit_token: plan.runs(FtStep::TestExit).then_some(&test_exit_token),
+                });
+            } else if plan.runs(FtStep::TestExit) {
+                flow = flow.then(TestExitStep {
+                    input: &run.individualize.test_exit,
+                    token: &test_exit_token,
+                });
+            }
+            if plan.runs(FtStep::Personalize) {
+                flow = flow.then(PersonalizeStep {
+                    transport,
+                    input: &run.personalize,
+                    data: Some(perso_data),
+                });
+            }
+            flow.run(&mut Session::new(ft, response))?;
+            if !plan.runs(FtStep::Individualize) && !plan.runs(FtStep::TestExit) {
+                response.lc_state.unlocked = ft.read_lc_state()?;
+            }
+        }
+        FtCommand::Unlock(unlock_opts) => {
+            let otp_image = unlock_opts.token_check.load()?;
+            let escrowed = unlock_opts.cp_tokens.lookup(None)?;
+            let test_unlock_token = parse_token(
+                unlock_opts.unlock.test_unlock_token.as_deref(),
+                unlock_opts.unlock.test_unlock_token_file.as_deref(),
+                escrowed.as_ref().map(|r| r.test_unlock_token.as_str()),
+                "test unlock",
+                otp_image.as_ref(),
+            )?;
+            unlock(
+                ft,
+                &test_unlock_token,
+                unlock_opts.unlock.test_unlock_lc_state,
+                response,
+            )?;
+            response.lc_state.unlocked = ft.read_lc_state()?;
+        }
+        FtCommand::Individualize(individ) => {
+            let handoff_key = individ
+                .handoff_signing_key
+                .as_ref()
+                .map(HostKeySpec::load)
+                .transpose()?;
+            let otp_image = individ.token_check.load()?;
+            let escrowed = individ.cp_tokens.lookup(Some(&individ.device_id))?;
+            let test_exit_token = parse_token(
+                individ.individualize.test_exit.test_exit_token.as_deref(),
+                individ
+                    .individualize
+                    .test_exit
+                    .test_exit_token_file
+                    .as_deref(),
+                escrowed.as_ref().map(|r| r.test_exit_token.as_str()),
+                "test exit",
+                otp_image.as_ref(),
+            )?;
+            ft.check_entry_lc_state()?;
+            individualize(
+                ft,
+                &individ.device_id,
+                &individ.individualize,
+                Some(&test_exit_token),
+                response,
+            )?;
+            response.lc_state.initial = response.lc_state.unlocked;
+            if let (Some(path), Some(key)) = (&individ.handoff_bundle, &handoff_key) {
+                let bundle = HandoffBundle {
+                    schema_version: HANDOFF_SCHEMA_VERSION,
+                    sku: env!("FT_SKU").into(),
+                    device_id: response.device_id.clone(),
+                    lc_state: response
+                        .lc_state
+                        .mission_mode
+                        .unwrap_or(response.lc_state.unlocked),
+                    test_exit_token_id: token_id(test_exit_token.expose()),
+                };
+                bundle.save_signed(path, key.as_ref())?;
+                log::info!("Handoff bundle exported to {path:?}");
+                ft.events().artifact_written("handoff-bundle", path.clone());
+            }
+        }
+        FtCommand::TestExit(exit_opts) => {
+            let otp_image = exit_opts.token_check.load()?;
+            let escrowed = exit_opts.cp_tokens.lookup(None)?;
+            let test_exit_token = parse_token(
+                exit_opts.test_exit.test_exit_token.as_deref(),
+                exit_opts.test_exit.test_exit_token_file.as_deref(),
+                escrowed.as_ref().map(|r| r.test_exit_token.as_str()),
+                "test exit",
+                otp_image.as_ref(),
+            )?;
+            response.lc_state.initial = ft.check_entry_lc_state()?;
+            response.lc_state.unlocked = response.lc_state.initial;
+            test_exit(ft, &exit_opts.test_exit, &test_exit_token, response)?;
+        }
+        FtCommand::Personalize(perso) => {
+            let bundle = match (&perso.handoff_bundle, &perso.handoff_verify_key) {
+                (Some(path), Some(key)) => Some(HandoffBundle::load_signed(
+                    path,
+                    &EcdsaPublicKey::load(key)?,
+                )?),
+                _ => None,
+            };
+            let device_id = perso
+                .device_id
+                .as_deref()
+                .or(bundle.as_ref().map(|b| b.device_id.as_str()))
+                .context("No device ID to personalize")?;
+            response.device_id =
+                format_device_id(&hex_string_to_u32_arrayvec::<DEVICE_ID_WORDS>(device_id)?);
+            ft.journal().set_device_id(&response.device_id)?;
+            if let Some(bundle) = &bundle {
+                ensure!(
+                    response.device_id == bundle.device_id,
+                    "Device ID {} does not match the handoff bundle device ID {}",
+                    response.device_id,
+                    bundle.device_id
+                );
+            }
+            let perso_data = perso.personalize.parse(response)?;
+            response.lc_state.initial = ft.check_entry_lc_state()?;
+            response.lc_state.unlocked = response.lc_state.initial;
+            if let Some(bundle) = &bundle {
+                bundle.check_resume(env!("FT_SKU"), response.lc_state.initial)?;
+                log::info!("Resuming personalization from the handoff bundle.");
+            }
+            perso
+                .personalize
+                .check_lc_state(response.lc_state.initial)?;
+            personalize(ft, transport, &perso.personalize, perso_data, response)?;
+        }
+        FtCommand::OtpDump(_)
+        | FtCommand::Audit(_)
+        | FtCommand::Verify(_)
+        | FtCommand::UnwrapRmaToken(_)
+        | FtCommand::Completions { .. } => unreachable!(),
+        #[cfg(feature = "debug-tools")]
+        FtCommand::Debug(_) => unreachable!(),
+    }
+
+    ft.journal().complete()
+}
+
+fn main() -> Result<()> {
+    let opts = Opts::parse_from(recipe::expand_args(std::env::args_os(), &Opts::command())?);
+
+    if let FtCommand::Completions { shell } = &opts.command {
+        let bin_name = std::env::args()
+            .next()
+            .as_deref()
+            .and_then(|arg0| Path::new(arg0).file_name()?.to_str().map(String::from))
+            .unwrap_or_else(|| "ft".to_string());
+        return completions::generate(*shell, &Opts::command(), &bin_name, &mut std::io::stdout());
+    }
+
+    opts.init.init_logging();
+    if let FtCommand::UnwrapRmaToken(unwrap_opts) = &opts.command {
+        return unwrap_rma_token_file(unwrap_opts);
+    }
+    opts.check_release()?;
+    let timeouts = opts.timeouts.resolve()?;
+
+    let mut response = PersonalizeResponse::default();
+
+    // We call the below functions, instead of calling `opts.init.init_target()` since we do not
+    // want to perform bootstrap yet.
+    let transport = backend::create(&opts.init.backend_opts)?;
+    if opts.dry_run {
+        // Only open the backend: its default configuration already drives the pins of the device.
+        let steps = plan_flow(&opts)?;
+        log::info!("Dry run: all inputs checked, the device was not touched.");
+        println!("Planned FT steps:");
+        for (i, step) in steps.iter().enumerate() {
+            println!("  {}. {step}", i + 1);
+        }
+        return Ok(());
+    }
+    transport.apply_default_configuration(None)?;
+    let spi = transport.spi(&opts.console_spi)?;
+    let spi_console = SpiConsoleDevice::new(&*spi, None)?;
+    let dmi_console = DmiConsoleDevice::new(&transport, &opts.init.jtag_params);
+    let ottf_console: &dyn ConsoleDevice = match opts.console {
+        ConsoleBackend::Spi => &spi_console,
+        ConsoleBackend::Dmi => &dmi_console,
+    };
+    let console_uart = opts
+        .console_uart
+        .as_deref()
+        .map(|uart| -> Result<_> {
+            let uart = transport.uart(uart)?;
+            opts.console_uart_config.apply(&*uart)?;
+            Ok(uart)
+        })
+        .transpose()?;
+    let parallel_console;
+    let console: &dyn ConsoleDevice = match &console_uart {
+        Some(uart) => {
+            parallel_console = ParallelConsole::new(ottf_console, &**uart);
+            &parallel_console
+        }
+        None => ottf_console,
+    };
+    InitializeTest::print_result("load_bitstream", opts.init.load_bitstream.init(&transport))?;
+
+    if let FtCommand::OtpDump(dump_opts) = &opts.command {
+        // Diagnostics only: never allow one-way operations.
+        let ft = FtProvisioner::new(
+            &transport,
+            &opts.init,
+            console,
+            opts.timeouts.timeout,
+            Capabilities::read_only(),
+        )
+        .with_timeouts(timeouts)
+        .with_journal(opts.read_only_journal()?)
+        .with_retry_policy(opts.retry.clone());
+        let dump = ft.otp_dump(&dump_opts.sram_program)?;
+        print!("{dump}");
+        if let Some(output) = &dump_opts.output {
+            write_otp_dump_json(&dump, output, opts.pretty)?;
+            log::info!("OTP dump archived to {output:?}");
+        }
+        if let Some(otp_vmem) = &dump_opts.otp_vmem {
+            write_otp_vmem(&dump, otp_vmem)?;
+            log::info!("OTP dump exported to {otp_vmem:?}");
+        }
+        if let Some(hex_vmem) = &dump_opts.hex_vmem {
+            write_hex_vmem(&dump, hex_vmem)?;
+            log::info!("OTP dump exported to {hex_vmem:?}");
+        }
+        return Ok(());
+    }
+
+    if let FtCommand::Audit(audit_opts) = &opts.command {
+        let ft = FtProvisioner::new(
+            &transport,
+            &opts.init,
+            console,
+            opts.timeouts.timeout,
+            Capabilities::read_only(),
+        )
+        .with_timeouts(timeouts)
+        .with_journal(opts.read_only_journal()?);
+        let report = SavedReport::load(&audit_opts.report)?;
+        let result = ft.audit(&report, audit_opts.cert_anchor.as_deref())?;
+        print!("{result}");
+        if let Some(output) = &audit_opts.output {
+            write_audit_result(&result, output, opts.pretty)?;
+        }
+        if !result.passed() {
+            bail!(
+                "Device {} does not match its report: {} mismatching check(s)",
+                report.device_id,
+                result.mismatches().count()
+            );
+        }
+        log::info!("Device {} matches its report.", report.device_id);
+        return Ok(());
+    }
+
+    if let FtCommand::Verify(verify_opts) = &opts.command {
+        let ft = FtProvisioner::new(
+            &transport,
+            &opts.init,
+            console,
+            opts.timeouts.timeout,
+            Capabilities::read_only(),
+        )
+        .with_timeouts(timeouts)
+        .with_journal(opts.read_only_journal()?)
+        .with_retry_policy(opts.retry.clone());
+        let result = ft.verify(&verify_opts.params())?;
+        print!("{result}");
+        if let Some(output) = &verify_opts.output {
+            write_audit_result(&result, output, opts.pretty)?;
+        }
+        if !result.passed() {
+            bail!(
+                "Device {} failed verification: {} failing check(s)",
+                result.device_id,
+                result.mismatches().count()
+            );
+        }
+        log::info!("Device {} passed verification.", result.device_id);
+        return Ok(());
+    }
+
+    #[cfg(feature = "debug-tools")]
+    if let FtCommand::Debug(command) = &opts.command {
+        let ft = FtProvisioner::new(
+            &transport,
+            &opts.init,
+            console,
+            opts.timeouts.timeout,
+            Capabilities::read_only().with(Capability::DebugRegs),
+        )
+        .with_timeouts(timeouts)
+        .with_journal(opts.read_only_journal()?);
+        return run_debug_command(&ft, command);
+    }
+
+    let journal = match &opts.step_state {
+        Some(path) => StepJournal::open(path)?,
+        None => StepJournal::disabled(),
+    };
+    let ft = FtProvisioner::new(
         &transport,
         &opts.init,
-        opts.timeout,
-        &mut response,
-        opts.owner_success_text,
-    )?;
-    log::info!("Provisioning Done");
-    let doc = if opts.provisioning_data.pretty {
-        serde_json::to_string_pretty(&response)?
+        console,
+        opts.timeouts.timeout,
+        Capabilities::all(),
+    )
+    .with_timeouts(timeouts)
+    .with_journal(journal)
+    .with_lc_state_check(opts.lc_state_check.clone())
+    .with_retry_policy(opts.retry.clone())
+    .with_entry_lc_states(opts.entry_lc_states.clone())
+    .with_individualize_backend(opts.individualize_backend())
+    .with_cert_policy(opts.cert_policy()?);
+    let ft = match &opts.telemetry {
+        Some(path) => ft.with_telemetry(Telemetry::load(path)?),
+        None => ft,
+    };
+    let ft = match &opts.checkpoint {
+        Some(path) => ft.with_checkpoint(Checkpoint::open(path, opts.resume)?),
+        None => ft,
+    };
+    let ft = match opts.offline_certs()? {
+        Some(certs) => ft.with_offline_certs(certs),
+        None => ft,
+    };
+    let ft = match opts.keymgr_diversification()? {
+        Some(diversification) => ft.with_keymgr_diversification(diversification),
+        None => ft,
+    };
+    let ft = match opts.console {
+        ConsoleBackend::Dmi => ft.with_dmi_console(&dmi_console),
+        ConsoleBackend::Spi => ft,
+    };
+    let (sink, events) =
+        if opts.result_json.is_some() || opts.metrics_out.is_some() || opts.pass_criteria.is_some()
+        {
+            let (sink, receiver) = EventSink::channel();
+            (sink, Some(receiver))
+        } else {
+            (EventSink::default(), None)
+        };
+    let sink = match &opts.events_out {
+        Some(path) => {
+            let file = File::create(path)
+                .with_context(|| format!("Failed to create events file {path:?}"))?;
+            let file = Mutex::new(LineWriter::new(file));
+            sink.with_sink(move |event: &ProvisioningEvent| {
+                if let Ok(line) = serde_json::to_string(event) {
+                    // A full disk must not fail the device being provisioned.
+                    let _ = writeln!(file.lock().unwrap(), "{line}");
+                }
+            })
+        }
+        None => sink,
+    };
+    let ft = if sink.is_empty() {
+        ft
     } else {
-        serde_json::to_string(&response)?
+        ft.with_events(sink)
     };
-    println!("PROVISIONING_DATA: {doc}");
+    let mut outcome = run_flow(&ft, &transport, &opts, &mut response);
+    response.telemetry = ft.telemetry_samples();
+    if let Some(events) = &events {
+        let mut result = FlowResult::new(
+            env!("FT_SKU"),
+            &response,
+            outcome.as_ref().err(),
+            events.try_iter(),
+        );
+        if let Some(criteria) = &opts.pass_criteria {
+            outcome = outcome.and_then(|()| result.check_pass_criteria(criteria));
+        }
+        if let Some(path) = &opts.result_json {
+            result.save(path)?;
+        }
+        if let Some(path) = &opts.metrics_out {
+            result.save_metrics(path)?;
+        }
+    }
+    outcome?;
+    log::info!("Provisioning Done");
+    println!("{}", report_line(FT_REPORT_PREFIX, &response, opts.pretty)?);
 
-    Ok(())
+    // Smoke test failures fail provisioning only once their results are reported.
+    response.check_smoke_tests()
 }
@@ -0,0 +1,192 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Provisioning recipes: HJSON files describing the whole FT flow of a SKU, so that
+//! `ft --recipe sku_a.hjson` needs no other option and a factory deployment is reproducible.
+//!
+//! ```hjson
+//! {
+//!   sku: "sival"
+//!   // Options of ft, before the command.
+//!   options: {
+//!     console: "spi"
+//!     bootstrap: "{recipe_dir}/ft_personalize.signed.bin"
+//!     result_json: "/var/log/ft/ft_result.json"
+//!   }
+//!   // Command to run, and the only steps of the flow it runs (`--only`).
+//!   command: "run"
+//!   steps: ["test-unlock", "individualize", "test-exit", "personalize"]
+//!   // Options of the command.
+//!   command_options: {
+//!     elf: "{recipe_dir}/sram_ft_individualize.elf"
+//!     cp_token_escrow: "/var/lib/cp/token_escrow.json"
+//!     target_mission_mode_lc_state: "prod"
+//!     key_bundle: "{recipe_dir}/key_bundle.json"
+//!     key_bundle_signature: "{recipe_dir}/key_bundle.json.sig"
+//!     key_bundle_key: "{recipe_dir}/key_bundle_pub.der"
+//!     raw_ca_key: ["int_ca=/secure/int_ca.der", "ext_ca=/secure/ext_ca.der"]
+//!   }
+//! }
+//! ```
+//!
+//! Each option is passed as `--<name>=<value>`, `_` being replaced with `-` in its name: lists
+//! repeat the option, `true` sets a flag and `false` leaves it unset. `{recipe_dir}` is replaced
+//! with the directory of the recipe in the values, to ship a recipe with its files.
+//!
+//! The options given on the command line are parsed after those of the recipe: single-valued
+//! options override the recipe, and lists extend it. A command other than the one of the recipe
+//! may be given, e.g. `ft --recipe sku_a.hjson otp-dump`, which only takes the `options` of the
+//! recipe.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::OsString;
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, ensure, Context, Result};
+use clap::Command;
+use serde::Deserialize;
+use serde_json::Value;
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Recipe {
+    /// SKU the recipe provisions; must be the SKU of this tool.
+    sku: String,
+    #[serde(default)]
+    options: BTreeMap<String, Value>,
+    #[serde(default = "default_command")]
+    command: String,
+    #[serde(default)]
+    steps: Vec<String>,
+    #[serde(default)]
+    command_options: BTreeMap<String, Value>,
+}
+
+fn default_command() -> String {
+    "run".to_string()
+}
+
+impl Recipe {
+    fn load(path: &Path) -> Result<Self> {
+        let doc = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read recipe {path:?}"))?;
+        let recipe: Self = deser_hjson::from_str(&doc)
+            .with_context(|| format!("Failed to parse recipe {path:?}"))?;
+        ensure!(
+            recipe.sku == env!("FT_SKU"),
+            "Recipe {path:?} is for SKU {}, not {}",
+            recipe.sku,
+            env!("FT_SKU")
+        );
+        ensure!(
+            !recipe.options.contains_key("recipe"),
+            "Recipe {path:?} must not include another recipe"
+        );
+        Ok(recipe)
+    }
+}
+
+/// Returns the command line arguments of `options`.
+fn option_args(options: &BTreeMap<String, Value>, recipe_dir: &str) -> Result<Vec<OsString>> {
+    let mut args = Vec::new();
+    for (name, value) in options {
+        let flag = format!("--{}", name.replace('_', "-"));
+        let values = match value {
+            Value::Bool(true) => {
+                args.push(flag.into());
+                continue;
+            }
+            Value::Bool(false) | Value::Null => continue,
+            Value::Array(values) => values.as_slice(),
+            value => std::slice::from_ref(value),
+        };
+        for value in values {
+            let value = match value {
+                Value::String(s) => s.replace("{recipe_dir}", recipe_dir),
+                Value::Number(n) => n.to_string(),
+                Value::Bool(b) => b.to_string(),
+                _ => bail!("Recipe option `{name}` must be a string, number, boolean or list"),
+            };
+            args.push(format!("{flag}={value}").into());
+        }
+    }
+    Ok(args)
+}
+
+/// Returns the path of the `--recipe` option in `args`, if any.
+fn recipe_path(args: &[OsString]) -> Option<PathBuf> {
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let Some(arg) = arg.to_str() else {
+            continue;
+        };
+        if arg == "--recipe" {
+            return args.next().map(PathBuf::from);
+        }
+        if let Some(path) = arg.strip_prefix("--recipe=") {
+            return Some(PathBuf::from(path));
+        }
+    }
+    None
+}
+
+/// Expands the `--recipe` option of the command line `args` of `cmd` with the options and the
+/// command of the recipe; `args` are returned as is without a recipe.
+pub fn expand_args(
+    args: impl IntoIterator<Item = OsString>,
+    cmd: &Command,
+) -> Result<Vec<OsString>> {
+    let args: Vec<OsString> = args.into_iter().collect();
+    // Names and aliases of the commands, to their names.
+    let commands: HashMap<&str, &str> = cmd
+        .get_subcommands()
+        .flat_map(|sub| {
+            let name = sub.get_name();
+            sub.get_all_aliases()
+                .chain([name])
+                .map(move |alias| (alias, name))
+        })
+        .collect();
+    let split = args
+        .iter()
+        .skip(1)
+        .position(|arg| arg.to_str().is_some_and(|arg| commands.contains_key(arg)))
+        .map_or(args.len(), |i| i + 1);
+    let (global_args, command_args) = args.split_at(split);
+    let Some(path) = recipe_path(global_args) else {
+        return Ok(args);
+    };
+    let recipe = Recipe::load(&path)?;
+    let recipe_dir = path
+        .parent()
+        .map(|dir| dir.to_string_lossy().into_owned())
+        .filter(|dir| !dir.is_empty())
+        .unwrap_or_else(|| ".".to_string());
+
+    let mut expanded = vec![global_args[0].clone()];
+    expanded.extend(option_args(&recipe.options, &recipe_dir)?);
+    expanded.extend_from_slice(&global_args[1..]);
+    let Some(&recipe_command) = commands.get(recipe.command.as_str()) else {
+        bail!("Unknown command `{}` in recipe {path:?}", recipe.command);
+    };
+    let command = command_args
+        .first()
+        .and_then(|arg| arg.to_str())
+        .map(|arg| commands[arg]);
+    if command.is_some_and(|command| command != recipe_command) {
+        expanded.extend_from_slice(command_args);
+    } else {
+        expanded.push(recipe_command.into());
+        expanded.extend(option_args(&recipe.command_options, &recipe_dir)?);
+        if !recipe.steps.is_empty() {
+            ensure!(
+                recipe_command == "run",
+                "The steps of recipe {path:?} are only run by the `run` command"
+            );
+            expanded.push(format!("--only={}", recipe.steps.join(",")).into());
+        }
+        expanded.extend(command_args.iter().skip(1).cloned());
+    }
+    Ok(expanded)
+}
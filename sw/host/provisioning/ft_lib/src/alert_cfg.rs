@@ -0,0 +1,256 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Alert handler / escalation configuration provisioned in the OWNER_SW_CFG partition.
+//!
+//! A SKU may override a whitelisted set of the alert handler configuration fields of its OTP
+//! image, so SKUs with different security configurations can share the same individualization
+//! firmware:
+//!
+//! ```json
+//! {
+//!   "class_en": "0xa9a9a9a9",
+//!   "escalation": "0xd1d1d1d1",
+//!   "accum_thresh": [0, 0, 0, 0],
+//!   "timeout_cycles": [0, 0, 0, 0],
+//!   "phase_cycles": [0, 10, 10, "0xffffffff", 0, 10, 10, "0xffffffff",
+//!                    0, 10, 10, "0xffffffff", 0, 10, 10, "0xffffffff"],
+//!   "digest": {
+//!     "prod": "0x8f3ce7c8",
+//!     "prod_end": "0x8f3ce7c8",
+//!     "dev": "0x8f3ce7c8",
+//!     "rma": "0x8f3ce7c8"
+//!   }
+//! }
+//! ```
+//!
+//! The ROM checks the alert handler configuration against the ROM_ALERT_DIGEST_* fields, so the
+//! digests (e.g. computed by the `otp_alert_digest` Bazel rule) must be provided along with any
+//! other field.
+
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{ensure, Context, Result};
+use arrayvec::ArrayVec;
+use serde::Deserialize;
+
+use opentitanlib::io::console::ConsoleDevice;
+use opentitanlib::test_utils::rpc::ConsoleSend;
+use opentitanlib::util::parse_int::{ParseInt, ParseIntError};
+use ujson_lib::provisioning_data::ManufOwnerSwCfgAlertCfg;
+
+use crate::retry::RetryPolicy;
+
+/// Alert handler configuration fields.
+///
+/// The discriminants are bit positions in `ManufOwnerSwCfgAlertCfg::fields`, and must match
+/// `manuf_alert_cfg_field_t` in sw/device/silicon_creator/manuf/lib/individualize_sw_cfg.h.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum AlertCfgField {
+    ClassEn = 0,
+    Escalation = 1,
+    AccumThresh = 2,
+    TimeoutCycles = 3,
+    PhaseCycles = 4,
+    Digest = 5,
+}
+
+impl AlertCfgField {
+    fn bit(self) -> u32 {
+        1 << self as u32
+    }
+}
+
+/// A 32-bit OTP word, given as an integer or a string such as `0xa9a9a9a9`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(try_from = "WordRepr")]
+pub struct Word(pub u32);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum WordRepr {
+    Int(u32),
+    Str(String),
+}
+
+impl TryFrom<WordRepr> for Word {
+    type Error = ParseIntError;
+
+    fn try_from(repr: WordRepr) -> Result<Self, Self::Error> {
+        match repr {
+            WordRepr::Int(value) => Ok(Word(value)),
+            WordRepr::Str(value) => Ok(Word(<u32 as ParseInt>::from_str(&value)?)),
+        }
+    }
+}
+
+/// ROM_ALERT_DIGEST_* fields, one per mission mode LC state.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertDigests {
+    pub prod: Word,
+    pub prod_end: Word,
+    pub dev: Word,
+    pub rma: Word,
+}
+
+/// Alert handler configuration fields overriding those of the SKU OTP image.
+///
+/// Fields left out keep the value of the OTP image. Unknown fields are rejected, so only the
+/// whitelisted fields can ever differ between SKUs sharing a firmware image.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AlertCfg {
+    /// OWNER_SW_CFG_ROM_ALERT_CLASS_EN.
+    pub class_en: Option<Word>,
+    /// OWNER_SW_CFG_ROM_ALERT_ESCALATION.
+    pub escalation: Option<Word>,
+    /// OWNER_SW_CFG_ROM_ALERT_ACCUM_THRESH, per alert class.
+    pub accum_thresh: Option<[Word; 4]>,
+    /// OWNER_SW_CFG_ROM_ALERT_TIMEOUT_CYCLES, per alert class.
+    pub timeout_cycles: Option<[Word; 4]>,
+    /// OWNER_SW_CFG_ROM_ALERT_PHASE_CYCLES, per alert class and escalation phase.
+    pub phase_cycles: Option<[Word; 16]>,
+    /// OWNER_SW_CFG_ROM_ALERT_DIGEST_*.
+    pub digest: Option<AlertDigests>,
+}
+
+fn words<const N: usize>(words: &Option<[Word; N]>) -> ArrayVec<u32, N> {
+    ArrayVec::from(words.map_or([0; N], |w| w.map(|w| w.0)))
+}
+
+impl AlertCfg {
+    /// Loads and validates an alert handler configuration file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let doc = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read alert handler configuration {path:?}"))?;
+        let cfg: AlertCfg = serde_json::from_str(&doc)
+            .with_context(|| format!("Failed to parse alert handler configuration {path:?}"))?;
+        cfg.validate()?;
+        Ok(cfg)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        ensure!(
+            self.digest.is_some() || self.fields() == 0,
+            "The alert handler digests must be provided along with the alert handler configuration"
+        );
+        Ok(())
+    }
+
+    /// Returns the `ManufOwnerSwCfgAlertCfg::fields` bitmask of the overridden fields.
+    pub fn fields(&self) -> u32 {
+        [
+            (self.class_en.is_some(), AlertCfgField::ClassEn),
+            (self.escalation.is_some(), AlertCfgField::Escalation),
+            (self.accum_thresh.is_some(), AlertCfgField::AccumThresh),
+            (self.timeout_cycles.is_some(), AlertCfgField::TimeoutCycles),
+            (self.phase_cycles.is_some(), AlertCfgField::PhaseCycles),
+            (self.digest.is_some(), AlertCfgField::Digest),
+        ]
+        .iter()
+        .filter(|(set, _)| *set)
+        .fold(0, |mask, (_, field)| mask | field.bit())
+    }
+
+    /// Returns the payload sent to the FT individualize SRAM program.
+    pub fn to_ujson(&self) -> ManufOwnerSwCfgAlertCfg {
+        let digest = self.digest.clone().unwrap_or_default();
+        ManufOwnerSwCfgAlertCfg {
+            fields: self.fields(),
+            class_en: self.class_en.unwrap_or_default().0,
+            escalation: self.escalation.unwrap_or_default().0,
+            accum_thresh: words(&self.accum_thresh),
+            timeout_cycles: words(&self.timeout_cycles),
+            phase_cycles: words(&self.phase_cycles),
+            digest: ArrayVec::from([digest.prod.0, digest.prod_end.0, digest.dev.0, digest.rma.0]),
+        }
+    }
+
+    /// Checks the overridden fields read back from OTP by the device hold the requested values.
+    pub fn verify(&self, read_back: &ManufOwnerSwCfgAlertCfg) -> Result<()> {
+        let expected = self.to_ujson();
+        ensure!(
+            read_back.fields == expected.fields,
+            "Device overrode alert handler fields {:#x}, expected {:#x}",
+            read_back.fields,
+            expected.fields
+        );
+        let fields = self.fields();
+        let check = |field: AlertCfgField, name: &str, expected: &[u32], actual: &[u32]| {
+            ensure!(
+                fields & field.bit() == 0 || expected == actual,
+                "OWNER_SW_CFG_{name} reads back as {actual:#x?}, expected {expected:#x?}"
+            );
+            Ok(())
+        };
+        check(
+            AlertCfgField::ClassEn,
+            "ROM_ALERT_CLASS_EN",
+            &[expected.class_en],
+            &[read_back.class_en],
+        )?;
+        check(
+            AlertCfgField::Escalation,
+            "ROM_ALERT_ESCALATION",
+            &[expected.escalation],
+            &[read_back.escalation],
+        )?;
+        check(
+            AlertCfgField::AccumThresh,
+            "ROM_ALERT_ACCUM_THRESH",
+            &expected.accum_thresh,
+            &read_back.accum_thresh,
+        )?;
+        check(
+            AlertCfgField::TimeoutCycles,
+            "ROM_ALERT_TIMEOUT_CYCLES",
+            &expected.timeout_cycles,
+            &read_back.timeout_cycles,
+        )?;
+        check(
+            AlertCfgField::PhaseCycles,
+            "ROM_ALERT_PHASE_CYCLES",
+            &expected.phase_cycles,
+            &read_back.phase_cycles,
+        )?;
+        check(
+            AlertCfgField::Digest,
+            "ROM_ALERT_DIGEST_*",
+            &expected.digest,
+            &read_back.digest,
+        )
+    }
+}
+
+/// Sends the alert handler configuration `cfg` to the FT individualize SRAM program, and checks
+/// the configuration read back by the device once the OWNER_SW_CFG partition is written.
+pub(crate) fn send_alert_cfg(
+    console: &dyn ConsoleDevice,
+    cfg: &AlertCfg,
+    timeout: Duration,
+    retry: &RetryPolicy,
+) -> Result<()> {
+    let _ = retry.wait_for(
+        console,
+        r"Waiting for OWNER_SW_CFG alert configuration ...",
+        timeout,
+    )?;
+    cfg.to_ujson().send(console)?;
+    let _ = retry.wait_for(
+        console,
+        r"Exporting OWNER_SW_CFG alert configuration ...",
+        timeout,
+    )?;
+    let read_back: ManufOwnerSwCfgAlertCfg = retry.recv(console, timeout, true)?;
+    cfg.verify(&read_back)?;
+    if cfg.fields() != 0 {
+        log::info!(
+            "Alert handler configuration fields {:#x} provisioned and verified.",
+            cfg.fields()
+        );
+    }
+    Ok(())
+}
@@ -0,0 +1,201 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fmt;
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use indexmap::IndexMap;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+
+use opentitanlib::app::TransportWrapper;
+use opentitanlib::dif::lc_ctrl::{DifLcCtrlState, LcCtrlReg};
+use opentitanlib::io::jtag::JtagParams;
+use opentitanlib::test_utils::lc::read_lc_regs;
+use provisioning::report::{find_report, FT_REPORT_PREFIX};
+use util_lib::format_device_id;
+
+use crate::error::wait_for;
+
+/// The facts of a saved `PersonalizeResponse` report that can be verified on the device.
+#[derive(Clone, Debug, Deserialize)]
+pub struct SavedReport {
+    pub schema_version: u32,
+    pub lc_state: SavedLcStateSequence,
+    pub device_id: String,
+    #[serde(default)]
+    pub certs: IndexMap<String, SavedCert>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SavedLcStateSequence {
+    pub unlocked: DifLcCtrlState,
+    pub mission_mode: Option<DifLcCtrlState>,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct SavedCert {
+    /// Base64 encoded certificate.
+    pub bytes: String,
+}
+
+impl SavedReport {
+    /// Loads a report saved as JSON, or as the `PROVISIONING_DATA:` line printed by `ft`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let doc = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read report {path:?}"))?;
+        serde_json::from_str(find_report(FT_REPORT_PREFIX, &doc))
+            .with_context(|| format!("Failed to parse report {path:?}"))
+    }
+
+    /// Returns the LC state the device was left in at the end of provisioning.
+    pub fn final_lc_state(&self) -> DifLcCtrlState {
+        self.lc_state.mission_mode.unwrap_or(self.lc_state.unlocked)
+    }
+}
+
+/// The result of comparing one fact of the report with the device.
+#[derive(Clone, Debug, Serialize)]
+pub struct AuditCheck {
+    pub name: String,
+    pub expected: String,
+    pub actual: String,
+    pub matches: bool,
+}
+
+/// The result of auditing a device against a saved report.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct AuditResult {
+    pub device_id: String,
+    pub checks: Vec<AuditCheck>,
+}
+
+impl AuditResult {
+    pub(crate) fn check(&mut self, name: &str, expected: &str, actual: &str) {
+        self.checks.push(AuditCheck {
+            name: name.into(),
+            expected: expected.into(),
+            actual: actual.into(),
+            matches: expected == actual,
+        });
+    }
+
+    /// Returns the checks on which the device deviates from the report.
+    pub fn mismatches(&self) -> impl Iterator<Item = &AuditCheck> {
+        self.checks.iter().filter(|c| !c.matches)
+    }
+
+    pub fn passed(&self) -> bool {
+        self.mismatches().next().is_none()
+    }
+}
+
+impl fmt::Display for AuditResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for check in &self.checks {
+            if check.matches {
+                writeln!(f, "{:<24} OK", check.name)?;
+            } else {
+                writeln!(
+                    f,
+                    "{:<24} MISMATCH: expected {}, device {}",
+                    check.name, check.expected, check.actual
+                )?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Reads the LC state and the device ID exposed over the LC TAP.
+pub(crate) fn read_lc_facts(
+    transport: &TransportWrapper,
+    jtag_params: &JtagParams,
+    reset_delay: Duration,
+) -> Result<(DifLcCtrlState, String)> {
+    let regs = read_lc_regs(
+        transport,
+        jtag_params,
+        reset_delay,
+        [
+            &LcCtrlReg::LcState,
+            &LcCtrlReg::DeviceId0,
+            &LcCtrlReg::DeviceId1,
+            &LcCtrlReg::DeviceId2,
+            &LcCtrlReg::DeviceId3,
+            &LcCtrlReg::DeviceId4,
+            &LcCtrlReg::DeviceId5,
+            &LcCtrlReg::DeviceId6,
+            &LcCtrlReg::DeviceId7,
+        ],
+    )?;
+    let lc_state = DifLcCtrlState::from_redundant_encoding(regs[0])?;
+    Ok((lc_state, format_device_id(&regs[1..])))
+}
+
+/// Checks the LC state and the device ID exposed over the LC TAP match `report`.
+pub(crate) fn audit_lc_facts(
+    transport: &TransportWrapper,
+    jtag_params: &JtagParams,
+    reset_delay: Duration,
+    report: &SavedReport,
+    result: &mut AuditResult,
+) -> Result<()> {
+    let (lc_state, device_id) = read_lc_facts(transport, jtag_params, reset_delay)?;
+    result.check(
+        "lc_state",
+        &report.final_lc_state().to_string(),
+        &lc_state.to_string(),
+    );
+    result.check("device_id", &report.device_id, &device_id);
+    Ok(())
+}
+
+/// Parses the `NAME: <base64>` certificate lines logged by the firmware, as done by
+/// sw/device/silicon_creator/rom_ext/e2e/attestation/print_certs.c, in the order they are logged.
+fn parse_console_certs(output: &str) -> Result<Vec<(String, String)>> {
+    let rx = Regex::new(r"(?m)\] ([A-Za-z0-9_]+): ([A-Za-z0-9+/]+=*)\r?$")?;
+    Ok(rx
+        .captures_iter(output)
+        .map(|c| (c[1].to_string(), c[2].to_string()))
+        .collect())
+}
+
+/// Resets the device and returns the `(name, base64)` certificates logged by its firmware on the
+/// console, up to the `anchor` regex.
+pub(crate) fn read_console_certs(
+    transport: &TransportWrapper,
+    reset_delay: Duration,
+    anchor: &str,
+    timeout: Duration,
+) -> Result<Vec<(String, String)>> {
+    let uart = transport.uart("console")?;
+    transport.reset_target(reset_delay, true)?;
+    let output = wait_for(&*uart, &format!(r"(?s)^(.*?){anchor}"), timeout)?;
+    parse_console_certs(&output[1])
+}
+
+/// Resets the device and checks the certificates logged by its firmware on the console, up to
+/// the `anchor` regex, match those of `report`.
+pub(crate) fn audit_console_certs(
+    transport: &TransportWrapper,
+    reset_delay: Duration,
+    anchor: &str,
+    timeout: Duration,
+    report: &SavedReport,
+    result: &mut AuditResult,
+) -> Result<()> {
+    let certs = read_console_certs(transport, reset_delay, anchor, timeout)?;
+    for (name, cert) in &report.certs {
+        let actual = certs
+            .iter()
+            .find(|(logged, _)| logged == name)
+            .map(|(_, logged)| logged.as_str())
+            .unwrap_or("<not logged>");
+        result.check(&format!("cert:{name}"), &cert.bytes, actual);
+    }
+    Ok(())
+}
@@ -0,0 +1,99 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Per-device record of the FT steps completed, to resume a flow which died mid-way.
+//!
+//! The checkpoint file maps each device ID to the steps (test unlock, individualize, test exit,
+//! personalize) it completed and the step it last failed at, if any. It is updated after every
+//! step. A resumed flow skips the steps the device already completed, and restarts at the failed
+//! one; a flow which is not resumed starts the record of the device over.
+
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::step_state::{now, write_durably};
+
+/// The steps a device completed.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DeviceCheckpoint {
+    /// Completed steps, in completion order.
+    pub completed: Vec<String>,
+    /// Step the last flow of the device failed at, if it did.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub failed: Option<String>,
+    /// Start of the flow the record belongs to, in seconds since the Unix epoch.
+    pub started: u64,
+    /// Time the record was last updated, in seconds since the Unix epoch.
+    pub updated: u64,
+}
+
+/// Checkpoint file of the devices going through the station.
+pub struct Checkpoint {
+    path: PathBuf,
+    resume: bool,
+    started: u64,
+    devices: RefCell<BTreeMap<String, DeviceCheckpoint>>,
+}
+
+impl Checkpoint {
+    /// Opens the checkpoint file at `path`, created on the first update if it does not exist.
+    ///
+    /// If `resume` is set, the steps devices completed in previous flows are skipped.
+    pub fn open(path: &Path, resume: bool) -> Result<Self> {
+        let devices = if path.exists() {
+            let doc = std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read checkpoint {path:?}"))?;
+            serde_json::from_str(&doc)
+                .with_context(|| format!("Failed to parse checkpoint {path:?}"))?
+        } else {
+            BTreeMap::new()
+        };
+        Ok(Self {
+            path: path.to_path_buf(),
+            resume,
+            started: now(),
+            devices: RefCell::new(devices),
+        })
+    }
+
+    /// Returns whether `step` is skipped for `device_id`, as completed by a previous flow.
+    pub fn skips(&self, device_id: &str, step: &str) -> bool {
+        self.resume
+            && self
+                .devices
+                .borrow()
+                .get(device_id)
+                .is_some_and(|d| d.completed.iter().any(|s| s == step))
+    }
+
+    /// Records `device_id` completed `step`, or failed at it.
+    pub fn record(&self, device_id: &str, step: &str, passed: bool) -> Result<()> {
+        let mut devices = self.devices.borrow_mut();
+        let device = devices.entry(device_id.to_string()).or_default();
+        if device.started != self.started {
+            if !self.resume {
+                device.completed.clear();
+            }
+            device.started = self.started;
+        }
+        if passed {
+            if !device.completed.iter().any(|s| s == step) {
+                device.completed.push(step.to_string());
+            }
+            device.failed = None;
+        } else {
+            device.failed = Some(step.to_string());
+        }
+        device.updated = now();
+        write_durably(
+            &self.path,
+            serde_json::to_string_pretty(&*devices)?.as_bytes(),
+        )
+        .with_context(|| format!("Failed to write checkpoint {:?}", self.path))
+    }
+}
@@ -0,0 +1,205 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! IDs matching the answers of the personalization firmware to the host commands.
+//!
+//! The console may still hold answers the device sent before a reset, e.g. the `ManufLogLevel`
+//! answer of the first boot of the personalization firmware while the host waits for the one of
+//! the second boot. Each host command is tagged with a new `cmd_id` the device echoes in its
+//! answer, and the answers echoing another ID are discarded as stale. The IDs start from the
+//! current time, so the answers to an earlier run on the same console are told apart too.
+
+use std::cell::Cell;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use anyhow::Result;
+
+use opentitanlib::io::console::ConsoleDevice;
+use opentitanlib::test_utils::rpc::ConsoleRecv;
+use ujson_lib::provisioning_data::{
+    ManufCreatorManufState, ManufKeymgrBinding, ManufLogLevel, ManufPersoExportOptions,
+    PersoBlobChunkAck,
+};
+
+use crate::retry::RetryPolicy;
+
+/// Allocator of the IDs of the host commands of a flow.
+#[derive(Debug)]
+pub struct CommandIds(Cell<u32>);
+
+impl Default for CommandIds {
+    fn default() -> Self {
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default();
+        Self(Cell::new(now.as_millis() as u32))
+    }
+}
+
+impl CommandIds {
+    /// Returns the ID of a new command; IDs are never zero.
+    pub fn next(&self) -> u32 {
+        let id = self.0.get().wrapping_add(1).max(1);
+        self.0.set(id);
+        id
+    }
+}
+
+/// An answer of the device echoing the `cmd_id` of the host command it answers.
+pub(crate) trait CommandAnswer {
+    fn cmd_id(&self) -> u32;
+
+    /// Whether this is the answer to the command `cmd_id`.
+    fn answers(&self, cmd_id: u32) -> bool {
+        self.cmd_id() == cmd_id
+    }
+}
+
+impl CommandAnswer for ManufLogLevel {
+    fn cmd_id(&self) -> u32 {
+        self.cmd_id
+    }
+}
+
+impl CommandAnswer for ManufPersoExportOptions {
+    fn cmd_id(&self) -> u32 {
+        self.cmd_id
+    }
+}
+
+impl CommandAnswer for ManufCreatorManufState {
+    fn cmd_id(&self) -> u32 {
+        self.cmd_id
+    }
+}
+
+impl CommandAnswer for ManufKeymgrBinding {
+    fn cmd_id(&self) -> u32 {
+        self.cmd_id
+    }
+}
+
+impl CommandAnswer for PersoBlobChunkAck {
+    fn cmd_id(&self) -> u32 {
+        self.cmd_id
+    }
+
+    /// The device can't echo the ID of a chunk it failed to parse, and rejects it.
+    fn answers(&self, cmd_id: u32) -> bool {
+        self.cmd_id == cmd_id || (self.cmd_id == 0 && !self.accepted)
+    }
+}
+
+/// Receives the answer `U` to the command `cmd_id`, as `RetryPolicy::recv`, discarding the stale
+/// answers to other commands.
+pub(crate) fn recv_answer<T, U>(
+    console: &T,
+    cmd_id: u32,
+    timeout: Duration,
+    retry: &RetryPolicy,
+) -> Result<U>
+where
+    T: ConsoleDevice + ?Sized,
+    U: ConsoleRecv<T> + CommandAnswer,
+{
+    let deadline = Instant::now() + timeout;
+    loop {
+        let answer: U = retry.recv(
+            console,
+            deadline.saturating_duration_since(Instant::now()),
+            true,
+        )?;
+        if answer.answers(cmd_id) {
+            return Ok(answer);
+        }
+        log::warn!(
+            "Discarding a stale {} answering command {}, expected command {cmd_id}.",
+            std::any::type_name::<U>().rsplit("::").next().unwrap(),
+            answer.cmd_id()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crc::{Crc, CRC_32_ISO_HDLC};
+    use std::cell::RefCell;
+    use std::collections::VecDeque;
+
+    /// Console replaying device output, one line per read.
+    #[derive(Default)]
+    struct LineConsole(RefCell<VecDeque<String>>);
+
+    impl LineConsole {
+        /// Queues the `RESP_OK` frames of `answers`.
+        fn new(answers: &[&str]) -> Self {
+            let crc = Crc::<u32>::new(&CRC_32_ISO_HDLC);
+            let lines = answers
+                .iter()
+                .map(|json| format!("RESP_OK:{json} CRC:{}\n", crc.checksum(json.as_bytes())))
+                .collect();
+            Self(RefCell::new(lines))
+        }
+    }
+
+    impl ConsoleDevice for LineConsole {
+        fn console_read(&self, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+            let Some(line) = self.0.borrow_mut().pop_front() else {
+                std::thread::sleep(timeout.min(Duration::from_millis(1)));
+                return Ok(0);
+            };
+            buf[..line.len()].copy_from_slice(line.as_bytes());
+            Ok(line.len())
+        }
+
+        fn console_write(&self, _buf: &[u8]) -> Result<()> {
+            Ok(())
+        }
+    }
+
+    fn ack(json: &str) -> PersoBlobChunkAck {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn test_next() {
+        let ids = CommandIds(Cell::new(u32::MAX - 1));
+        assert_eq!(ids.next(), u32::MAX);
+        assert_eq!(ids.next(), 1);
+        assert_eq!(ids.next(), 2);
+    }
+
+    #[test]
+    fn test_answers() {
+        assert!(ack(r#"{"cmd_id":5,"offset":0,"accepted":true}"#).answers(5));
+        assert!(!ack(r#"{"cmd_id":4,"offset":0,"accepted":true}"#).answers(5));
+        // The rejection of a chunk the device failed to parse.
+        assert!(ack(r#"{"cmd_id":0,"offset":0,"accepted":false}"#).answers(5));
+        assert!(!ack(r#"{"cmd_id":0,"offset":0,"accepted":true}"#).answers(5));
+    }
+
+    #[test]
+    fn test_recv_answer() {
+        let console = LineConsole::new(&[
+            r#"{"cmd_id":1,"min_severity":0}"#,
+            r#"{"cmd_id":3,"min_severity":0}"#,
+            r#"{"cmd_id":2,"min_severity":1}"#,
+        ]);
+        let retry = RetryPolicy::default();
+        let answer: ManufLogLevel =
+            recv_answer(&console, 2, Duration::from_secs(1), &retry).unwrap();
+        assert_eq!((answer.cmd_id, answer.min_severity), (2, 1));
+        assert!(console.0.borrow().is_empty());
+    }
+
+    #[test]
+    fn test_recv_answer_stale_only() {
+        let console = LineConsole::new(&[r#"{"cmd_id":1,"min_severity":0}"#]);
+        let retry = RetryPolicy::default();
+        let result: Result<ManufLogLevel> =
+            recv_answer(&console, 2, Duration::from_millis(50), &retry);
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,100 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Peek and poke of arbitrary device registers over JTAG, for lab triage without dropping out of
+//! the FT tooling into separate JTAG scripts.
+//!
+//! Only built with the `debug-tools` feature. Sessions are refused unless the device is in a
+//! TEST_UNLOCKED*, DEV or RMA LC state: production devices are never poked. Opening a session
+//! resets the device with the bootstrap strap applied, so the ROM does not boot the flash image,
+//! and halts the CPU over the RISC-V debug module; registers are then accessed over the system
+//! bus.
+
+use std::time::Duration;
+
+use anyhow::{ensure, Result};
+
+use opentitanlib::app::TransportWrapper;
+use opentitanlib::dif::lc_ctrl::DifLcCtrlState;
+use opentitanlib::io::jtag::{Jtag, JtagParams, JtagTap};
+use opentitanlib::test_utils::lc::read_lc_state;
+
+/// Whether registers may be poked on a device in `lc_state`.
+fn debug_allowed(lc_state: DifLcCtrlState) -> bool {
+    matches!(
+        lc_state,
+        DifLcCtrlState::TestUnlocked0
+            | DifLcCtrlState::TestUnlocked1
+            | DifLcCtrlState::TestUnlocked2
+            | DifLcCtrlState::TestUnlocked3
+            | DifLcCtrlState::TestUnlocked4
+            | DifLcCtrlState::TestUnlocked5
+            | DifLcCtrlState::TestUnlocked6
+            | DifLcCtrlState::TestUnlocked7
+            | DifLcCtrlState::Dev
+            | DifLcCtrlState::Rma
+    )
+}
+
+/// A JTAG connection to the halted CPU of a device, to access its registers.
+pub struct DebugSession<'t> {
+    transport: &'t TransportWrapper,
+    jtag: Box<dyn Jtag>,
+}
+
+impl<'t> DebugSession<'t> {
+    /// Resets the device and halts its CPU, once its LC state is checked to allow debugging.
+    pub fn open(
+        transport: &'t TransportWrapper,
+        jtag_params: &JtagParams,
+        reset_delay: Duration,
+    ) -> Result<Self> {
+        let lc_state = read_lc_state(transport, jtag_params, reset_delay)?;
+        ensure!(
+            debug_allowed(lc_state),
+            "Register peek/poke is refused on a device in the {} LC state",
+            lc_state.lc_state_to_str()
+        );
+
+        transport.pin_strapping("PINMUX_TAP_RISCV")?.apply()?;
+        transport.pin_strapping("ROM_BOOTSTRAP")?.apply()?;
+        transport.reset_target(reset_delay, true)?;
+        let mut jtag = jtag_params.create(transport)?.connect(JtagTap::RiscvTap)?;
+        jtag.halt()?;
+        Ok(Self { transport, jtag })
+    }
+
+    /// Reads `count` consecutive 32-bit registers from `addr`.
+    pub fn peek(&mut self, addr: u32, count: usize) -> Result<Vec<u32>> {
+        ensure!(
+            addr % 4 == 0,
+            "Register address {addr:#010x} is not word-aligned"
+        );
+        let mut values = vec![0u32; count];
+        let read = self.jtag.read_memory32(addr, &mut values)?;
+        ensure!(
+            read == count,
+            "Read {read} of {count} registers from {addr:#010x}"
+        );
+        Ok(values)
+    }
+
+    /// Writes `value` to the 32-bit register at `addr`.
+    pub fn poke(&mut self, addr: u32, value: u32) -> Result<()> {
+        ensure!(
+            addr % 4 == 0,
+            "Register address {addr:#010x} is not word-aligned"
+        );
+        log::warn!("Poking {value:#010x} to {addr:#010x}");
+        self.jtag.write_memory32(addr, &[value])
+    }
+
+    /// Disconnects from the device, leaving its CPU halted.
+    pub fn close(self) -> Result<()> {
+        self.jtag.disconnect()?;
+        self.transport.pin_strapping("PINMUX_TAP_RISCV")?.remove()?;
+        self.transport.pin_strapping("ROM_BOOTSTRAP")?.remove()?;
+        Ok(())
+    }
+}
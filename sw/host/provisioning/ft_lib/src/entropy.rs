@@ -0,0 +1,266 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, ErrorKind, Write};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Context, Result};
+use clap::Args;
+use sha2::{Digest, Sha256};
+
+/// A value generated on the device, e.g. a seed, a certificate serial number or a public key.
+#[derive(Clone, Debug)]
+pub struct GeneratedValue {
+    pub name: String,
+    pub bytes: Vec<u8>,
+    /// Whether the value is uniformly random, and its entropy estimate meaningful. Public keys
+    /// are encoded with a constant header, so they are only checked for repeats.
+    pub random: bool,
+}
+
+impl GeneratedValue {
+    pub fn new(name: impl Into<String>, bytes: &[u8]) -> Self {
+        Self {
+            name: name.into(),
+            bytes: bytes.to_vec(),
+            random: true,
+        }
+    }
+
+    /// Returns the public key `name`, as its DER SubjectPublicKeyInfo `der`.
+    pub fn public_key(name: impl Into<String>, der: &[u8]) -> Self {
+        Self {
+            random: false,
+            ..Self::new(name, der)
+        }
+    }
+
+    fn digest(&self) -> String {
+        hex::encode(Sha256::digest(&self.bytes))
+    }
+}
+
+/// Statistical sanity checks of the values generated on the device.
+///
+/// A device whose values are constant, repeat values of another device, or have a low entropy
+/// estimate fails personalization, guarding against lots with a broken CSRNG. In particular, a
+/// device exporting a public key already exported by another device, as recorded in the seen
+/// values file of the station, fails before its certificates are written back.
+#[derive(Clone, Debug, Args)]
+pub struct EntropyCheck {
+    /// Minimum entropy estimate of each device-generated value, in bits per nibble (at most 4).
+    #[arg(long, default_value_t = 2.5)]
+    pub min_nibble_entropy: f64,
+
+    /// File recording the SHA256 digests of the device-generated values, to reject values repeated
+    /// across devices.
+    #[arg(long)]
+    pub seen_values_file: Option<PathBuf>,
+}
+
+/// Returns the Shannon entropy of the nibbles of `bytes`, in bits per nibble.
+fn nibble_entropy(bytes: &[u8]) -> f64 {
+    let mut counts = [0usize; 16];
+    for b in bytes {
+        counts[usize::from(b >> 4)] += 1;
+        counts[usize::from(b & 0xf)] += 1;
+    }
+    let total = (2 * bytes.len()) as f64;
+    counts
+        .iter()
+        .filter(|&&c| c > 0)
+        .map(|&c| {
+            let p = c as f64 / total;
+            -p * p.log2()
+        })
+        .sum()
+}
+
+impl EntropyCheck {
+    /// Checks the values generated by the device `device_id`, and records them as seen.
+    ///
+    /// Returns the lowest entropy estimate of the values, in bits per nibble.
+    pub fn check(&self, device_id: &str, values: &[GeneratedValue]) -> Result<f64> {
+        let mut min_entropy = 4f64;
+        let mut digests = HashMap::new();
+        for value in values {
+            let Some(first) = value.bytes.first() else {
+                bail!("Device-generated {} is empty", value.name);
+            };
+            if let Some(other) = digests.insert(value.digest(), &value.name) {
+                bail!(
+                    "Device-generated {} repeats the {other} of the device",
+                    value.name
+                );
+            }
+            if !value.random {
+                continue;
+            }
+            if value.bytes.iter().all(|b| b == first) {
+                bail!(
+                    "Device-generated {} is constant: {}",
+                    value.name,
+                    hex::encode(&value.bytes)
+                );
+            }
+            let entropy = nibble_entropy(&value.bytes);
+            if entropy < self.min_nibble_entropy {
+                bail!(
+                    "Device-generated {} has an entropy estimate of {entropy:.2} bits per nibble \
+                     (minimum {:.2}): {}",
+                    value.name,
+                    self.min_nibble_entropy,
+                    hex::encode(&value.bytes)
+                );
+            }
+            min_entropy = min_entropy.min(entropy);
+        }
+        if let Some(path) = &self.seen_values_file {
+            check_unseen(path, device_id, &digests)?;
+        }
+        Ok(min_entropy)
+    }
+}
+
+/// Checks no other device generated the values of `digests`, and records them in `path`.
+///
+/// Values previously recorded for the same device, e.g. by a retried personalization, are not
+/// repeats.
+fn check_unseen(path: &Path, device_id: &str, digests: &HashMap<String, &String>) -> Result<()> {
+    let mut recorded = HashMap::new();
+    match File::open(path) {
+        Ok(file) => {
+            for line in BufReader::new(file).lines() {
+                let line = line.with_context(|| format!("Failed to read {path:?}"))?;
+                if let Some((digest, seen_by)) = line.split_once(' ') {
+                    recorded.insert(digest.to_string(), seen_by.to_string());
+                }
+            }
+        }
+        Err(e) if e.kind() == ErrorKind::NotFound => {}
+        Err(e) => return Err(e).with_context(|| format!("Failed to open {path:?}")),
+    }
+
+    let mut lines = String::new();
+    for (digest, name) in digests {
+        match recorded.get(digest) {
+            Some(seen_by) if seen_by == device_id => {}
+            Some(seen_by) => {
+                bail!("Device-generated {name} was previously generated by device {seen_by}")
+            }
+            None => lines.push_str(&format!("{digest} {device_id}\n")),
+        }
+    }
+    OpenOptions::new()
+        .create(true)
+        .append(true)
+        .open(path)
+        .and_then(|mut file| file.write_all(lines.as_bytes()))
+        .with_context(|| format!("Failed to record the device-generated values in {path:?}"))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentitanlib::util::tmpfilename;
+
+    /// A value using every byte value, of 8 bits of entropy per byte.
+    fn random(name: &str, seed: u8) -> GeneratedValue {
+        let bytes: Vec<u8> = (0..=255u8).map(|b| b.wrapping_mul(167) ^ seed).collect();
+        GeneratedValue::new(name, &bytes)
+    }
+
+    fn check() -> EntropyCheck {
+        EntropyCheck {
+            min_nibble_entropy: 2.5,
+            seen_values_file: None,
+        }
+    }
+
+    #[test]
+    fn test_nibble_entropy() {
+        assert_eq!(nibble_entropy(&[0; 32]), 0.0);
+        assert_eq!(nibble_entropy(&[0x0f; 32]), 1.0);
+        assert_eq!(
+            nibble_entropy(&[0x01, 0x23, 0x45, 0x67, 0x89, 0xab, 0xcd, 0xef]),
+            4.0
+        );
+    }
+
+    #[test]
+    fn test_check() {
+        let entropy = check().check("0x00", &[random("seed", 0), random("serial", 1)]);
+        assert_eq!(entropy.unwrap(), 4.0);
+
+        let err = check()
+            .check("0x00", &[GeneratedValue::new("seed", &[0; 32])])
+            .unwrap_err();
+        assert!(err.to_string().contains("seed is constant"), "{err}");
+        let err = check()
+            .check("0x00", &[GeneratedValue::new("seed", &[])])
+            .unwrap_err();
+        assert!(err.to_string().contains("seed is empty"), "{err}");
+
+        // Low entropy: two nibble values, 1 bit per nibble.
+        let err = check()
+            .check(
+                "0x00",
+                &[GeneratedValue::new("seed", &[0x0f, 0xf0].repeat(16))],
+            )
+            .unwrap_err();
+        assert!(
+            err.to_string().contains("entropy estimate of 1.00"),
+            "{err}"
+        );
+        let lenient = EntropyCheck {
+            min_nibble_entropy: 1.0,
+            ..check()
+        };
+        let values = [GeneratedValue::new("seed", &[0x0f, 0xf0].repeat(16))];
+        assert_eq!(lenient.check("0x00", &values).unwrap(), 1.0);
+    }
+
+    #[test]
+    fn test_check_repeats() {
+        let err = check()
+            .check("0x00", &[random("seed", 0), random("serial", 0)])
+            .unwrap_err();
+        assert!(err.to_string().contains("serial repeats the seed"), "{err}");
+
+        // Public keys are only checked for repeats.
+        let key = GeneratedValue::public_key("key", &[0x30; 91]);
+        assert_eq!(
+            check().check("0x00", std::slice::from_ref(&key)).unwrap(),
+            4.0
+        );
+        assert!(check().check("0x00", &[key.clone(), key]).is_err());
+    }
+
+    #[test]
+    fn test_check_seen_values() {
+        let path = PathBuf::from(tmpfilename("test_entropy_seen_values"));
+        let _ = std::fs::remove_file(&path);
+        let check = EntropyCheck {
+            seen_values_file: Some(path.clone()),
+            ..check()
+        };
+        let values = [random("seed", 0), random("serial", 1)];
+        check.check("0x01", &values).unwrap();
+        // A retried personalization of the same device.
+        check.check("0x01", &values).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 2);
+
+        let err = check.check("0x02", &[random("serial", 1)]).unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("serial was previously generated by device 0x01"),
+            "{err}"
+        );
+        check.check("0x02", &[random("serial", 2)]).unwrap();
+        assert_eq!(std::fs::read_to_string(&path).unwrap().lines().count(), 3);
+    }
+}
@@ -0,0 +1,146 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Typed errors of the FT flow.
+//!
+//! Most failures are reported as `anyhow` errors with a human-readable context. The failures a
+//! caller may want to handle, e.g. retry a device which timed out or bin a device in the wrong LC
+//! state, are raised as a `ProvisioningError` instead, which stays reachable through the context:
+//! see [`ProvisioningError::find`].
+
+use std::time::Duration;
+
+use anyhow::Result;
+use thiserror::Error;
+
+use opentitanlib::dif::lc_ctrl::{DifLcCtrlState, LcCtrlStatus};
+use opentitanlib::io::console::{ConsoleDevice, ConsoleError};
+use opentitanlib::io::jtag::Jtag;
+use opentitanlib::test_utils::lc_transition::{verify_lc_state, LcStateCheck, LcTransitionError};
+use opentitanlib::uart::console::UartConsole;
+
+#[derive(Debug, Error)]
+pub enum ProvisioningError {
+    #[error(
+        "Device is in the {} LC state, expected {}",
+        actual.lc_state_to_str(),
+        expected.lc_state_to_str()
+    )]
+    WrongLcState {
+        expected: DifLcCtrlState,
+        actual: DifLcCtrlState,
+    },
+    #[error(
+        "Wrong insertion: device is in the {} LC state, this insertion accepts {}",
+        actual.lc_state_to_str(),
+        allowed.iter().map(|s| s.lc_state_to_str()).collect::<Vec<_>>().join(", ")
+    )]
+    WrongInsertion {
+        allowed: Vec<DifLcCtrlState>,
+        actual: DifLcCtrlState,
+    },
+    #[error("SRAM program load/execution failed: {0}")]
+    SramLoadFailed(String),
+    #[error("Timed out after {timeout:?} waiting for the device to print `{expected}`")]
+    Timeout { expected: String, timeout: Duration },
+    #[error("Device reported: {0}")]
+    DeviceStatus(String),
+    #[error(
+        "Test exit token generation mismatch: the device holds the hash of a test exit token of \
+         another generation than the configured one ({generation})"
+    )]
+    TokenGenerationMismatch { generation: String },
+}
+
+impl ProvisioningError {
+    /// Returns the kind of the error, as reported in the FT result.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::WrongLcState { .. } => "wrong-lc-state",
+            Self::WrongInsertion { .. } => "wrong-insertion",
+            Self::SramLoadFailed(_) => "sram-load-failed",
+            Self::Timeout { .. } => "timeout",
+            Self::DeviceStatus(_) => "device-status",
+            Self::TokenGenerationMismatch { .. } => "token-generation-mismatch",
+        }
+    }
+
+    /// Finds the `ProvisioningError` `error` was raised with, under any context added since.
+    pub fn find(error: &anyhow::Error) -> Option<&Self> {
+        error.chain().find_map(|e| e.downcast_ref::<Self>())
+    }
+}
+
+/// Waits for the device to print `rx` on `console`, as `UartConsole::wait_for`, failing with a
+/// `ProvisioningError::Timeout` if it does not within `timeout`.
+pub(crate) fn wait_for<T>(console: &T, rx: &str, timeout: Duration) -> Result<Vec<String>>
+where
+    T: ConsoleDevice + ?Sized,
+{
+    UartConsole::wait_for(console, rx, timeout).map_err(|e| {
+        match e.downcast_ref::<ConsoleError>() {
+            Some(ConsoleError::GenericError(msg)) if msg == "Timed Out" => {
+                ProvisioningError::Timeout {
+                    expected: rx.to_string(),
+                    timeout,
+                }
+                .into()
+            }
+            _ => e,
+        }
+    })
+}
+
+/// Checks the LC state is `expected`, as `verify_lc_state`, failing with a
+/// `ProvisioningError::WrongLcState` if it is another valid LC state.
+pub(crate) fn check_lc_state(
+    jtag: &mut dyn Jtag,
+    expected: DifLcCtrlState,
+    check: &LcStateCheck,
+) -> Result<()> {
+    verify_lc_state(jtag, expected, check).map_err(|e| {
+        match e.downcast_ref::<LcTransitionError>() {
+            Some(LcTransitionError::UnexpectedLcState { actual, .. }) => {
+                // Raw values which are not a redundantly encoded LC state are kept as is.
+                match DifLcCtrlState::from_redundant_encoding(*actual) {
+                    Ok(state) if state.redundant_encoding() == *actual => {
+                        ProvisioningError::WrongLcState {
+                            expected,
+                            actual: state,
+                        }
+                        .into()
+                    }
+                    _ => e,
+                }
+            }
+            _ => e,
+        }
+    })
+}
+
+/// Maps a test exit which failed on an LC controller token error to a
+/// `ProvisioningError::TokenGenerationMismatch`: the host checked the transition target and the
+/// device was in the expected LC state, so the token is the only input the LC controller rejected.
+pub(crate) fn check_test_exit_token_error(e: anyhow::Error, generation: &str) -> anyhow::Error {
+    match e.downcast_ref::<LcTransitionError>() {
+        Some(LcTransitionError::TransitionFailed(status))
+            if status.contains(LcCtrlStatus::TOKEN_ERROR) =>
+        {
+            ProvisioningError::TokenGenerationMismatch {
+                generation: token_generation_label(generation).to_string(),
+            }
+            .into()
+        }
+        _ => e,
+    }
+}
+
+/// Returns the label of the test exit token generation `generation` in errors and logs.
+pub(crate) fn token_generation_label(generation: &str) -> &str {
+    if generation.is_empty() {
+        "<unlabelled>"
+    } else {
+        generation
+    }
+}
@@ -0,0 +1,241 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Stream of typed provisioning events, for embedders of `ft_lib` building their own UIs, loggers
+//! or analytics.
+//!
+//! An embedder either creates a channel with `EventSink::channel` and receives the events of the
+//! flow on the other end, e.g. on a UI thread, or registers a `ProgressSink` callback with
+//! `EventSink::from_sink`, e.g. to forward them to a test executive. The sink is handed to the
+//! provisioner with `FtProvisioner::with_events`. Emitting never blocks nor fails on a channel:
+//! events are dropped once the receiver is gone. Callbacks run on the provisioning thread, and
+//! should return quickly.
+//!
+//! Besides the steps and commands of the flow, the lines the device prints on the console of the
+//! provisioner are emitted as `DeviceMessage` events, see `EventConsole`.
+
+use std::cell::RefCell;
+use std::fmt;
+use std::path::PathBuf;
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use anyhow::Result;
+use serde::Serialize;
+
+use opentitanlib::io::console::ConsoleDevice;
+use opentitanlib::io::gpio::GpioPin;
+use opentitanlib::io::nonblocking_help::NonblockingHelp;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum ProvisioningEvent {
+    /// The device entered `step`/`sub_step`, see `StepJournal::enter`.
+    StepStarted { step: String, sub_step: String },
+    /// A step of the FT flow (test unlock, individualize, test exit or personalize), or a custom
+    /// step of its `Flow`, ended.
+    StepFinished {
+        step: &'static str,
+        passed: bool,
+        duration_us: u64,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        error: Option<String>,
+    },
+    /// A command or payload was sent to the device.
+    CommandSent { command: &'static str },
+    /// The device reported a status or a response.
+    StatusReceived { status: &'static str },
+    /// An output file of the flow was written.
+    ArtifactWritten { kind: &'static str, path: PathBuf },
+    /// An operation of a step, e.g. a reset, a JTAG connect or an OTP write, ended.
+    OperationTimed {
+        operation: &'static str,
+        duration_us: u64,
+    },
+    /// The device printed `line` on the console.
+    DeviceMessage { line: String },
+}
+
+/// Receiver of the provisioning events, called as they are emitted.
+pub trait ProgressSink: Send + Sync {
+    fn on_event(&self, event: &ProvisioningEvent);
+}
+
+impl<F> ProgressSink for F
+where
+    F: Fn(&ProvisioningEvent) + Send + Sync,
+{
+    fn on_event(&self, event: &ProvisioningEvent) {
+        self(event)
+    }
+}
+
+impl ProgressSink for Sender<ProvisioningEvent> {
+    fn on_event(&self, event: &ProvisioningEvent) {
+        // The receiver is free to stop listening.
+        let _ = self.send(event.clone());
+    }
+}
+
+/// Emitting end of a provisioning event stream, fanning the events out to the registered
+/// `ProgressSink`s; the default sink drops all events.
+#[derive(Clone, Default)]
+pub struct EventSink(Vec<Arc<dyn ProgressSink>>);
+
+impl fmt::Debug for EventSink {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EventSink({} sinks)", self.0.len())
+    }
+}
+
+impl EventSink {
+    /// Returns a sink and the receiver of the events emitted to it.
+    pub fn channel() -> (Self, Receiver<ProvisioningEvent>) {
+        let (sender, receiver) = mpsc::channel();
+        (Self::from_sink(sender), receiver)
+    }
+
+    /// Returns a sink emitting the events to `sink`.
+    pub fn from_sink(sink: impl ProgressSink + 'static) -> Self {
+        Self(vec![Arc::new(sink)])
+    }
+
+    /// Returns this sink, also emitting the events to `sink`.
+    pub fn with_sink(mut self, sink: impl ProgressSink + 'static) -> Self {
+        self.0.push(Arc::new(sink));
+        self
+    }
+
+    /// Whether the events are dropped, no sink being registered.
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn emit(&self, event: ProvisioningEvent) {
+        for sink in &self.0 {
+            sink.on_event(&event);
+        }
+    }
+
+    pub fn command_sent(&self, command: &'static str) {
+        self.emit(ProvisioningEvent::CommandSent { command });
+    }
+
+    pub fn status_received(&self, status: &'static str) {
+        self.emit(ProvisioningEvent::StatusReceived { status });
+    }
+
+    pub fn step_finished(
+        &self,
+        step: &'static str,
+        duration: Duration,
+        error: Option<&anyhow::Error>,
+    ) {
+        self.emit(ProvisioningEvent::StepFinished {
+            step,
+            passed: error.is_none(),
+            duration_us: duration.as_micros() as u64,
+            error: error.map(|e| format!("{e:#}")),
+        });
+    }
+
+    pub fn artifact_written(&self, kind: &'static str, path: PathBuf) {
+        self.emit(ProvisioningEvent::ArtifactWritten { kind, path });
+    }
+
+    /// Runs `operation`, and emits its wall-clock duration, whether it succeeded or not.
+    pub fn time<T>(&self, operation: &'static str, f: impl FnOnce() -> T) -> T {
+        let t0 = Instant::now();
+        let result = f();
+        self.emit(ProvisioningEvent::OperationTimed {
+            operation,
+            duration_us: t0.elapsed().as_micros() as u64,
+        });
+        result
+    }
+}
+
+/// Longest line emitted as a `DeviceMessage`; longer lines, e.g. binary output, are split.
+const MAX_LINE_LEN: usize = 4096;
+
+/// Console emitting the lines read from the device as `DeviceMessage` events.
+///
+/// All reads go through `console_read`, whoever reads the console (`UartConsole`, the ujson
+/// receivers), so every line the flow sees is emitted once, and only once it is complete.
+pub struct EventConsole<'a> {
+    inner: &'a dyn ConsoleDevice,
+    events: EventSink,
+    line: RefCell<Vec<u8>>,
+}
+
+impl<'a> EventConsole<'a> {
+    pub fn new(inner: &'a dyn ConsoleDevice, events: EventSink) -> Self {
+        Self {
+            inner,
+            events,
+            line: RefCell::new(Vec::new()),
+        }
+    }
+
+    pub fn set_events(&mut self, events: EventSink) {
+        self.events = events;
+    }
+
+    fn emit_line(&self, line: &mut Vec<u8>) {
+        let text = String::from_utf8_lossy(line);
+        let text = text.trim_end_matches('\r');
+        if !text.is_empty() {
+            self.events.emit(ProvisioningEvent::DeviceMessage {
+                line: text.to_string(),
+            });
+        }
+        line.clear();
+    }
+}
+
+impl ConsoleDevice for EventConsole<'_> {
+    fn console_read(&self, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        let len = self.inner.console_read(buf, timeout)?;
+        if !self.events.is_empty() {
+            let mut line = self.line.borrow_mut();
+            for &byte in &buf[..len] {
+                if byte == b'\n' {
+                    self.emit_line(&mut line);
+                } else {
+                    line.push(byte);
+                    if line.len() >= MAX_LINE_LEN {
+                        self.emit_line(&mut line);
+                    }
+                }
+            }
+        }
+        Ok(len)
+    }
+
+    fn console_write(&self, buf: &[u8]) -> Result<()> {
+        self.inner.console_write(buf)
+    }
+
+    fn set_break(&self, enable: bool) -> Result<()> {
+        self.inner.set_break(enable)
+    }
+
+    fn get_tx_ready_pin(&self) -> Result<Option<&Rc<dyn GpioPin>>> {
+        self.inner.get_tx_ready_pin()
+    }
+
+    fn supports_nonblocking_read(&self) -> Result<bool> {
+        self.inner.supports_nonblocking_read()
+    }
+
+    fn register_nonblocking_read(&self, registry: &mio::Registry, token: mio::Token) -> Result<()> {
+        self.inner.register_nonblocking_read(registry, token)
+    }
+
+    fn nonblocking_help(&self) -> Result<Rc<dyn NonblockingHelp>> {
+        self.inner.nonblocking_help()
+    }
+}
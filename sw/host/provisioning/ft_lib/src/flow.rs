@@ -0,0 +1,127 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Composable FT flows: a `Flow` runs a sequence of `ProvisioningStep`s on a device.
+//!
+//! The FT steps of the `ft` tool are provisioning steps, so the owner of a SKU inserts its own
+//! steps around them, e.g. to program extra OTP fields after individualization or to exchange
+//! extra perso data after personalization, without changing `ft_lib`:
+//!
+//! ```ignore
+//! flow.insert_after("individualize", OtpFieldsStep::new(fields))?;
+//! flow.run(&mut Session::new(&ft, &mut response))?;
+//! ```
+//!
+//! Custom steps are run as the FT steps: skipped when resuming a checkpoint they completed, and
+//! with their outcome and duration emitted as `StepFinished` events.
+
+use std::collections::HashSet;
+
+use anyhow::{ensure, Context, Result};
+
+use crate::provisioner::FtProvisioner;
+use crate::response::PersonalizeResponse;
+
+/// State shared by the steps of a flow.
+pub struct Session<'a, 'b> {
+    /// Provisioner of the device.
+    pub ft: &'b FtProvisioner<'a>,
+    /// Result of the flow, filled in by its steps.
+    pub response: &'b mut PersonalizeResponse,
+}
+
+impl<'a, 'b> Session<'a, 'b> {
+    pub fn new(ft: &'b FtProvisioner<'a>, response: &'b mut PersonalizeResponse) -> Self {
+        Self { ft, response }
+    }
+}
+
+/// A step of a flow.
+pub trait ProvisioningStep {
+    /// Returns the name of the step, as reported in the events of the flow and recorded in
+    /// checkpoints. The names of the steps of a flow are unique.
+    fn name(&self) -> &'static str;
+
+    /// Runs the step on the device of `session`.
+    fn run(&mut self, session: &mut Session) -> Result<()>;
+
+    /// Checks the step had its effect on the device, once run.
+    fn verify(&self, _session: &Session) -> Result<()> {
+        Ok(())
+    }
+
+    /// Whether `run` already reports the outcome of the step through the `FtProvisioner`, as the
+    /// FT steps do. The flow reports the outcome of the other steps.
+    fn reports_outcome(&self) -> bool {
+        false
+    }
+}
+
+/// A sequence of provisioning steps.
+#[derive(Default)]
+pub struct Flow<'s> {
+    steps: Vec<Box<dyn ProvisioningStep + 's>>,
+}
+
+impl<'s> Flow<'s> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns this flow, running `step` after its current steps.
+    pub fn then(mut self, step: impl ProvisioningStep + 's) -> Self {
+        self.steps.push(Box::new(step));
+        self
+    }
+
+    /// Returns the names of the steps of the flow, in order.
+    pub fn names(&self) -> Vec<&'static str> {
+        self.steps.iter().map(|step| step.name()).collect()
+    }
+
+    fn position(&self, name: &str) -> Result<usize> {
+        self.steps
+            .iter()
+            .position(|step| step.name() == name)
+            .with_context(|| format!("No step {name} in the flow {:?}", self.names()))
+    }
+
+    /// Inserts `step` before the step `name`.
+    pub fn insert_before(&mut self, name: &str, step: impl ProvisioningStep + 's) -> Result<()> {
+        let i = self.position(name)?;
+        self.steps.insert(i, Box::new(step));
+        Ok(())
+    }
+
+    /// Inserts `step` after the step `name`.
+    pub fn insert_after(&mut self, name: &str, step: impl ProvisioningStep + 's) -> Result<()> {
+        let i = self.position(name)?;
+        self.steps.insert(i + 1, Box::new(step));
+        Ok(())
+    }
+
+    /// Runs then verifies each step in order, stopping at the first failing one.
+    pub fn run(&mut self, session: &mut Session) -> Result<()> {
+        let mut names = HashSet::new();
+        for name in self.names() {
+            ensure!(names.insert(name), "The flow runs the step {name} twice");
+        }
+        for step in &mut self.steps {
+            let (name, reports_outcome) = (step.name(), step.reports_outcome());
+            log::info!("Running step {name}.");
+            let ft = session.ft;
+            let mut run = || {
+                step.run(session)?;
+                step.verify(session)
+                    .with_context(|| format!("Step {name} did not verify"))
+            };
+            if reports_outcome {
+                run()?;
+            } else {
+                ft.custom_step(name, run)?;
+            }
+        }
+        Ok(())
+    }
+}
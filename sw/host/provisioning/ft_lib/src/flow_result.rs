@@ -0,0 +1,293 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Machine-readable result of an FT flow, for factory MES systems.
+//!
+//! Unlike the `PROVISIONING_DATA:` report, the result is also written when the flow fails: it
+//! tells which step failed and why, along with what the earlier steps already did to the device.
+//!
+//! The wall-clock durations of the steps, and of their operations (resets, JTAG connects, SRAM
+//! loads, OTP writes, perso data exchanges...), can also be exported in the Prometheus text format,
+//! e.g. for the textfile collector of a node exporter, to characterize the throughput of a line.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use indexmap::IndexMap;
+use serde::Serialize;
+
+use crate::error::ProvisioningError;
+use crate::events::ProvisioningEvent;
+use crate::pass_criteria::{PassCriteria, Value};
+use crate::response::{LcStateSequence, PersonalizeResponse};
+use crate::rma_escrow::CertMetadata;
+use crate::step_plan::FtStep;
+use crate::telemetry::TelemetrySample;
+
+/// Version of the `FlowResult` JSON format.
+pub const FLOW_RESULT_SCHEMA_VERSION: u32 = 3;
+
+/// Outcome of a step of the FT flow.
+#[derive(Clone, Debug, Serialize)]
+pub struct StepResult {
+    /// One of `test-unlock`, `individualize`, `test-exit` or `personalize`.
+    pub step: &'static str,
+    pub passed: bool,
+    pub duration_us: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Wall-clock duration of an operation of the flow, e.g. `reset` or `otp-write-individualize`.
+#[derive(Clone, Debug, Serialize)]
+pub struct OperationTiming {
+    /// Step the operation ran in, if any.
+    pub step: Option<&'static str>,
+    pub operation: &'static str,
+    pub duration_us: u64,
+}
+
+/// An output file of the flow, e.g. a wrapped RMA unlock token.
+#[derive(Clone, Debug, Serialize)]
+pub struct Artifact {
+    pub kind: &'static str,
+    pub path: PathBuf,
+}
+
+#[derive(Clone, Debug, Serialize)]
+pub struct FlowResult {
+    pub schema_version: u32,
+    pub sku: String,
+    /// Device ID, once known to the flow.
+    pub device_id: String,
+    pub passed: bool,
+    /// Error the flow failed with, including failed smoke tests.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// Kind of the `ProvisioningError` the flow failed with, if any, e.g. `timeout`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error_kind: Option<&'static str>,
+    pub lc_state: LcStateSequence,
+    /// Steps run, in order; steps skipped because the device was past them are not listed.
+    pub steps: Vec<StepResult>,
+    /// Operations run, in order.
+    pub timings: Vec<OperationTiming>,
+    pub certs: Vec<CertMetadata>,
+    pub artifacts: Vec<Artifact>,
+    /// Fixture sensor readings taken around the steps run, see `telemetry`.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub telemetry: Vec<TelemetrySample>,
+}
+
+impl FlowResult {
+    /// Collects the result of a flow which ended with `error`, from its `response` and the
+    /// `events` it emitted.
+    pub fn new(
+        sku: &str,
+        response: &PersonalizeResponse,
+        error: Option<&anyhow::Error>,
+        events: impl IntoIterator<Item = ProvisioningEvent>,
+    ) -> Self {
+        let error_kind = error
+            .and_then(ProvisioningError::find)
+            .map(ProvisioningError::kind);
+        let error = match error {
+            Some(e) => Some(format!("{e:#}")),
+            None => response.check_smoke_tests().err().map(|e| e.to_string()),
+        };
+        let mut steps = Vec::new();
+        let mut timings: Vec<OperationTiming> = Vec::new();
+        // Operations are emitted before the end of the step they run in.
+        let mut step_timings = 0;
+        let mut artifacts = Vec::new();
+        for event in events {
+            match event {
+                ProvisioningEvent::StepFinished {
+                    step,
+                    passed,
+                    duration_us,
+                    error,
+                } => {
+                    for timing in &mut timings[step_timings..] {
+                        timing.step = Some(step);
+                    }
+                    step_timings = timings.len();
+                    steps.push(StepResult {
+                        step,
+                        passed,
+                        duration_us,
+                        error,
+                    })
+                }
+                ProvisioningEvent::OperationTimed {
+                    operation,
+                    duration_us,
+                } => timings.push(OperationTiming {
+                    step: None,
+                    operation,
+                    duration_us,
+                }),
+                ProvisioningEvent::ArtifactWritten { kind, path } => {
+                    artifacts.push(Artifact { kind, path })
+                }
+                _ => {}
+            }
+        }
+        Self {
+            schema_version: FLOW_RESULT_SCHEMA_VERSION,
+            sku: sku.to_string(),
+            device_id: response.device_id.clone(),
+            passed: error.is_none(),
+            error,
+            error_kind,
+            lc_state: response.lc_state.clone(),
+            steps,
+            timings,
+            certs: response.certs.values().map(CertMetadata::new).collect(),
+            artifacts,
+            telemetry: response.telemetry.clone(),
+        }
+    }
+
+    /// Returns the fields the pass criteria of a SKU are evaluated over:
+    ///
+    /// - `passed`: whether the flow passed, before the pass criteria.
+    /// - `all_steps_ok`: whether steps were run, and all passed.
+    /// - `step.test_unlock`, `step.individualize`, `step.test_exit`, `step.personalize`: whether
+    ///   the step passed, `null` if it was not run.
+    /// - `cert_chain_valid`: whether the personalize step passed with endorsed certificates, their
+    ///   chains being checked up to the CA certificates by the step.
+    /// - `certs`: number of endorsed certificates.
+    /// - `lc_state`: LC state the device was left in, by name, e.g. `prod`.
+    /// - `sku`, `device_id`, and `error_kind` (`null` without error).
+    pub fn criteria_fields(&self) -> BTreeMap<String, Value> {
+        let mut fields = BTreeMap::new();
+        fields.insert("passed".into(), Value::Bool(self.passed));
+        fields.insert(
+            "all_steps_ok".into(),
+            Value::Bool(!self.steps.is_empty() && self.steps.iter().all(|s| s.passed)),
+        );
+        for step in FtStep::ALL {
+            // A step run again, e.g. after a resume, counts with its last run.
+            let passed = self.steps.iter().rev().find(|s| s.step == step.name());
+            fields.insert(
+                format!("step.{}", step.name().replace('-', "_")),
+                passed.map_or(Value::Null, |s| Value::Bool(s.passed)),
+            );
+        }
+        let personalized = self
+            .steps
+            .iter()
+            .rev()
+            .find(|s| s.step == FtStep::Personalize.name())
+            .is_some_and(|s| s.passed);
+        fields.insert(
+            "cert_chain_valid".into(),
+            Value::Bool(personalized && !self.certs.is_empty()),
+        );
+        fields.insert("certs".into(), Value::Number(self.certs.len() as f64));
+        let lc_state = self
+            .lc_state
+            .mission_mode
+            .or(self.lc_state.individualize)
+            .unwrap_or(self.lc_state.unlocked);
+        fields.insert(
+            "lc_state".into(),
+            Value::String(lc_state.lc_state_to_str().into()),
+        );
+        fields.insert("sku".into(), Value::String(self.sku.clone()));
+        fields.insert("device_id".into(), Value::String(self.device_id.clone()));
+        fields.insert(
+            "error_kind".into(),
+            self.error_kind
+                .map_or(Value::Null, |kind| Value::String(kind.into())),
+        );
+        fields
+    }
+
+    /// Returns the names of the `criteria_fields`, which the pass criteria may refer to.
+    pub fn criteria_field_names() -> BTreeSet<String> {
+        Self::new("", &PersonalizeResponse::default(), None, [])
+            .criteria_fields()
+            .into_keys()
+            .collect()
+    }
+
+    /// Decides the verdict of a passing flow with the pass `criteria` of the SKU, failing the
+    /// result if they are not met.
+    pub fn check_pass_criteria(&mut self, criteria: &PassCriteria) -> Result<()> {
+        if !self.passed {
+            return Ok(());
+        }
+        let error = match criteria.is_met(&self.criteria_fields()) {
+            Ok(true) => return Ok(()),
+            Ok(false) => anyhow!("Pass criteria `{criteria}` not met"),
+            Err(e) => e,
+        };
+        self.passed = false;
+        self.error = Some(format!("{error:#}"));
+        bail!(error)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<()> {
+        std::fs::write(path, serde_json::to_string_pretty(self)?)
+            .with_context(|| format!("Failed to write FT result to {path:?}"))
+    }
+
+    /// Returns the step and operation durations in the Prometheus text exposition format.
+    ///
+    /// The operations run several times in a step, e.g. JTAG connects, are summed up; a step run
+    /// again, e.g. after a resume, is reported with its last run.
+    pub fn prometheus_metrics(&self) -> String {
+        let labels = format!("sku=\"{}\",device_id=\"{}\"", self.sku, self.device_id);
+        let mut out = String::new();
+        out.push_str("# HELP ft_step_duration_seconds Wall-clock duration of the FT steps.\n");
+        out.push_str("# TYPE ft_step_duration_seconds gauge\n");
+        let steps: IndexMap<_, _> = self.steps.iter().map(|s| (s.step, s)).collect();
+        for step in steps.values() {
+            let _ = writeln!(
+                out,
+                "ft_step_duration_seconds{{{labels},step=\"{}\",passed=\"{}\"}} {}",
+                step.step,
+                step.passed,
+                step.duration_us as f64 / 1e6
+            );
+        }
+        let mut operations = IndexMap::<_, (u64, u64)>::new();
+        for timing in &self.timings {
+            let entry = operations
+                .entry((timing.step.unwrap_or(""), timing.operation))
+                .or_default();
+            entry.0 += timing.duration_us;
+            entry.1 += 1;
+        }
+        out.push_str(
+            "# HELP ft_operation_duration_seconds Wall-clock duration of the FT operations.\n",
+        );
+        out.push_str("# TYPE ft_operation_duration_seconds summary\n");
+        for ((step, operation), (duration_us, count)) in operations {
+            let labels = format!("{labels},step=\"{step}\",operation=\"{operation}\"");
+            let _ = writeln!(
+                out,
+                "ft_operation_duration_seconds_sum{{{labels}}} {}",
+                duration_us as f64 / 1e6
+            );
+            let _ = writeln!(
+                out,
+                "ft_operation_duration_seconds_count{{{labels}}} {count}"
+            );
+        }
+        out
+    }
+
+    /// Writes the `prometheus_metrics` to `path`, atomically for the textfile collectors.
+    pub fn save_metrics(&self, path: &Path) -> Result<()> {
+        let tmp = path.with_extension("prom.tmp");
+        std::fs::write(&tmp, self.prometheus_metrics())
+            .and_then(|()| std::fs::rename(&tmp, path))
+            .with_context(|| format!("Failed to write FT metrics to {path:?}"))
+    }
+}
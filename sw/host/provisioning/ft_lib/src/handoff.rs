@@ -0,0 +1,109 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Handoff bundles carrying individualized devices to a later personalization site.
+//!
+//! A bundle is exported at the end of an individualize-only flow, and signed with the ECDSA
+//! P-256 key of the individualization site, a key file or a key of its HSM (see `host_key`). The
+//! personalization site checks the signature, and that the device is still in the state recorded
+//! in the bundle, before resuming the flow.
+
+use std::path::Path;
+
+use anyhow::{ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use opentitanlib::crypto::ecdsa::{EcdsaPublicKey, EcdsaRawSignature};
+use opentitanlib::crypto::sha256::sha256;
+use opentitanlib::dif::lc_ctrl::DifLcCtrlState;
+
+use crate::host_key::HostKey;
+
+/// Version of the handoff bundle format.
+pub const HANDOFF_SCHEMA_VERSION: u32 = 1;
+
+/// Number of hex digits of a token ID.
+const TOKEN_ID_LEN: usize = 16;
+
+/// Everything the personalization site needs to resume the FT flow of a device.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HandoffBundle {
+    pub schema_version: u32,
+    /// SKU the device was individualized for.
+    pub sku: String,
+    pub device_id: String,
+    /// LC state the device was left in by individualization.
+    pub lc_state: DifLcCtrlState,
+    /// ID of the test exit token the device was individualized with, see `token_id`.
+    pub test_exit_token_id: String,
+}
+
+/// On-disk format of a bundle: the serialized bundle and its signature, as a hex string of the
+/// raw `R || S` signature of its SHA-256 digest.
+#[derive(Serialize, Deserialize)]
+struct SignedHandoffBundle {
+    payload: String,
+    signature: String,
+}
+
+/// Returns the ID of an LC token.
+///
+/// The ID is a truncated SHA-256 digest of the little-endian token, matching the token IDs
+/// recorded by the provisioning orchestrator, so tokens can be referenced without revealing them.
+pub fn token_id(token: &[u32]) -> String {
+    let bytes: Vec<u8> = token.iter().flat_map(|w| w.to_le_bytes()).collect();
+    let mut id = hex::encode(Sha256::digest(bytes));
+    id.truncate(TOKEN_ID_LEN);
+    id
+}
+
+impl HandoffBundle {
+    /// Signs the bundle with `key` and saves it to `path`.
+    pub fn save_signed(&self, path: &Path, key: &dyn HostKey) -> Result<()> {
+        let payload = serde_json::to_string(self)?;
+        let signature = key.digest_and_sign(payload.as_bytes())?;
+        let doc = serde_json::to_string_pretty(&SignedHandoffBundle {
+            payload,
+            signature: hex::encode(signature.to_vec()?),
+        })?;
+        std::fs::write(path, doc)
+            .with_context(|| format!("Failed to write handoff bundle {path:?}"))
+    }
+
+    /// Loads the bundle saved to `path`, after checking it was signed with `key`.
+    pub fn load_signed(path: &Path, key: &EcdsaPublicKey) -> Result<Self> {
+        let doc = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read handoff bundle {path:?}"))?;
+        let signed: SignedHandoffBundle = serde_json::from_str(&doc)
+            .with_context(|| format!("Failed to parse handoff bundle {path:?}"))?;
+        let signature = EcdsaRawSignature::try_from(hex::decode(&signed.signature)?.as_slice())?;
+        key.verify(&sha256(signed.payload.as_bytes()), &signature)
+            .with_context(|| format!("Invalid signature of handoff bundle {path:?}"))?;
+        let bundle: HandoffBundle = serde_json::from_str(&signed.payload)
+            .with_context(|| format!("Failed to parse handoff bundle {path:?}"))?;
+        ensure!(
+            bundle.schema_version == HANDOFF_SCHEMA_VERSION,
+            "Unsupported handoff bundle version {}, expected {HANDOFF_SCHEMA_VERSION}",
+            bundle.schema_version
+        );
+        Ok(bundle)
+    }
+
+    /// Checks personalization of a device of `sku` in `lc_state` can resume from the bundle.
+    pub fn check_resume(&self, sku: &str, lc_state: DifLcCtrlState) -> Result<()> {
+        ensure!(
+            self.sku == sku,
+            "Handoff bundle is for SKU {}, not {sku}",
+            self.sku
+        );
+        ensure!(
+            self.lc_state == lc_state,
+            "Device {} is in LC state {lc_state}, the handoff bundle recorded {}",
+            self.device_id,
+            self.lc_state
+        );
+        Ok(())
+    }
+}
@@ -0,0 +1,109 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::time::Duration;
+
+use anyhow::Result;
+use serde::Serialize;
+use serde_json::{json, Value};
+
+use opentitanlib::chip::boolean::MultiBitBool4;
+use opentitanlib::dif::lc_ctrl::DifLcCtrlState;
+use opentitanlib::io::console::ConsoleDevice;
+use ujson_lib::provisioning_data::ManufHealthSnapshot;
+
+use crate::retry::RetryPolicy;
+
+/// Health of a personalized device in mission mode, as reported by the
+/// personalization firmware at the end of FT.
+#[derive(Clone, Debug, Serialize)]
+pub struct HealthSnapshot {
+    pub lc_state: DifLcCtrlState,
+    /// ROM_EXT measurement recorded by the ROM, as a hex string.
+    pub rom_ext_measurement: String,
+    pub keymgr_state: String,
+    pub flash_scrambling: MultiBitBool4,
+    pub flash_ecc: MultiBitBool4,
+    pub flash_high_endurance: MultiBitBool4,
+}
+
+/// Returns the name of a `sc_keymgr_state_t` value.
+///
+/// The values must be kept in sync with
+/// sw/device/silicon_creator/lib/drivers/keymgr.h.
+fn keymgr_state_name(state: u32) -> String {
+    match state {
+        0 => "Reset".into(),
+        1 => "Init".into(),
+        2 => "CreatorRootKey".into(),
+        3 => "OwnerIntermediateKey".into(),
+        4 => "OwnerKey".into(),
+        5 => "Disabled".into(),
+        6 => "Invalid".into(),
+        _ => format!("{state:#x}"),
+    }
+}
+
+impl From<&ManufHealthSnapshot> for HealthSnapshot {
+    fn from(snapshot: &ManufHealthSnapshot) -> Self {
+        HealthSnapshot {
+            lc_state: DifLcCtrlState(snapshot.lc_state),
+            rom_ext_measurement: snapshot
+                .rom_ext_measurement
+                .iter()
+                .rev()
+                .map(|w| format!("{w:08x}"))
+                .collect(),
+            keymgr_state: keymgr_state_name(snapshot.keymgr_state),
+            flash_scrambling: MultiBitBool4(snapshot.flash_scrambling as u8),
+            flash_ecc: MultiBitBool4(snapshot.flash_ecc as u8),
+            flash_high_endurance: MultiBitBool4(snapshot.flash_high_endurance as u8),
+        }
+    }
+}
+
+impl HealthSnapshot {
+    /// Receives the health snapshot sent by the personalization firmware.
+    pub(crate) fn recv(
+        console: &dyn ConsoleDevice,
+        timeout: Duration,
+        retry: &RetryPolicy,
+    ) -> Result<Self> {
+        let _ = retry.wait_for(console, r"Exporting health snapshot ...", timeout)?;
+        let snapshot: ManufHealthSnapshot = retry.recv(console, timeout, true)?;
+        let snapshot = HealthSnapshot::from(&snapshot);
+        log::info!(
+            "Device health: {} keymgr {} flash scrambling {} ecc {}",
+            snapshot.lc_state,
+            snapshot.keymgr_state,
+            snapshot.flash_scrambling,
+            snapshot.flash_ecc
+        );
+        Ok(snapshot)
+    }
+}
+
+/// Returns the JSON Schema of a serialized `HealthSnapshot`.
+pub(crate) fn health_snapshot_schema() -> Value {
+    let mubi = json!({ "type": ["string", "integer"] });
+    json!({
+        "type": "object",
+        "required": [
+            "lc_state",
+            "rom_ext_measurement",
+            "keymgr_state",
+            "flash_scrambling",
+            "flash_ecc",
+            "flash_high_endurance"
+        ],
+        "properties": {
+            "lc_state": { "type": "string" },
+            "rom_ext_measurement": { "type": "string", "pattern": "^[0-9a-f]{64}$" },
+            "keymgr_state": { "type": "string" },
+            "flash_scrambling": mubi,
+            "flash_ecc": mubi,
+            "flash_high_endurance": mubi
+        }
+    })
+}
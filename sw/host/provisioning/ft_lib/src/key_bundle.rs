@@ -0,0 +1,348 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Provisioning key bundles listing the public keys and key handles a provisioning station uses.
+//!
+//! A key bundle is a JSON document, generated along with the keys it lists and signed by the key
+//! custodian:
+//!
+//! ```json
+//! {
+//!   "schema_version": 1,
+//!   "name": "fake",
+//!   "skus": ["sival"],
+//!   "not_after": "2027-01-01T00:00:00Z",
+//!   "rma_wrap_key": "<RSA, X25519 or P-384 public key DER, hex>",
+//!   "cas": {
+//!     "dice": {
+//!       "certificate": "-----BEGIN CERTIFICATE-----\n...",
+//!       "key_id": "0xfe584ae7_53790cfd_8601a312_fb32d3c1_b822d112",
+//!       "key_type": "Token",
+//!       "key": "<PKCS#11 token key ID>"
+//!     },
+//!     "ext": { ... }
+//!   }
+//! }
+//! ```
+//!
+//! The detached signature is an ECDSA P-256 signature (raw `R || S` as produced by
+//! `opentitantool ecdsa sign`, or ASN.1 DER) of the SHA-256 digest of the bundle file.
+//!
+//! Private keys are never part of a bundle: the `key` of a CA with a `Raw` key type only names
+//! the key, and the key itself, a key file or an HSM or KMS key, is passed separately to the
+//! tools using it.
+
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::SystemTime;
+
+use anyhow::{bail, ensure, Context, Result};
+use indexmap::IndexMap;
+use openssl::x509::X509;
+use serde::Deserialize;
+
+use cert_lib::{CaConfig, CaKeyType, HostCaKey};
+use opentitanlib::crypto::ecdsa::{EcdsaPublicKey, EcdsaRawPublicKey, EcdsaRawSignature};
+use opentitanlib::crypto::sha256::sha256;
+use opentitanlib::util::tmpfilename;
+
+use crate::host_key::raw_public_key;
+use crate::rma_token::RmaWrapKey;
+
+/// Version of the key bundle format.
+pub const KEY_BUNDLE_SCHEMA_VERSION: u32 = 1;
+
+/// Names of the CAs a key bundle must list.
+const REQUIRED_CAS: [&str; 2] = ["dice", "ext"];
+
+/// A CA listed in a key bundle.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BundledCa {
+    /// CA certificate, in PEM format.
+    pub certificate: String,
+    /// CA certificate key ID (160-bit hex string).
+    pub key_id: String,
+    pub key_type: CaKeyType,
+    /// PKCS#11 token key ID, PIV slot (`<slot>[@<reader>]`), or name of the raw key DER file.
+    pub key: String,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ProvisioningKeyBundle {
+    pub schema_version: u32,
+    /// Name of the bundle, e.g. of the key ceremony the keys were generated in.
+    pub name: String,
+    /// SKUs the bundle may be used for.
+    pub skus: Vec<String>,
+    /// Expiry of the bundle, in RFC 3339 format.
+    pub not_after: String,
+    /// RSA, X25519 or P-384 public key wrapping the RMA unlock tokens, as a hex string of its SPKI
+    /// DER, or PKCS#1 DER for RSA keys. See `rma_token` for the wrapping suite of each key type.
+    pub rma_wrap_key: String,
+    /// CAs endorsing the device certificates, by name.
+    pub cas: IndexMap<String, BundledCa>,
+}
+
+impl ProvisioningKeyBundle {
+    /// Loads a key bundle, after checking its detached `signature` against the DER encoded public
+    /// `key`, and its schema.
+    pub fn load_signed(path: &Path, signature: &Path, key: &Path) -> Result<Self> {
+        let doc =
+            std::fs::read(path).with_context(|| format!("Failed to read key bundle {path:?}"))?;
+        let key = EcdsaPublicKey::load(key)
+            .with_context(|| format!("Failed to load key bundle key {key:?}"))?;
+        let signature = EcdsaRawSignature::read_from_file(signature)?;
+        key.verify(&sha256(&doc), &signature)
+            .with_context(|| format!("Invalid signature of key bundle {path:?}"))?;
+        let bundle: Self = serde_json::from_slice(&doc)
+            .with_context(|| format!("Failed to parse key bundle {path:?}"))?;
+        bundle
+            .validate()
+            .with_context(|| format!("Invalid key bundle {path:?}"))?;
+        Ok(bundle)
+    }
+
+    fn validate(&self) -> Result<()> {
+        ensure!(
+            self.schema_version == KEY_BUNDLE_SCHEMA_VERSION,
+            "Unsupported key bundle version {}, expected {KEY_BUNDLE_SCHEMA_VERSION}",
+            self.schema_version
+        );
+        humantime::parse_rfc3339(&self.not_after)
+            .with_context(|| format!("Invalid expiry {}", self.not_after))?;
+        self.rma_wrap_key()?;
+        for name in REQUIRED_CAS {
+            ensure!(self.cas.contains_key(name), "No {name} CA");
+        }
+        for (name, ca) in &self.cas {
+            X509::from_pem(ca.certificate.as_bytes())
+                .with_context(|| format!("Invalid {name} CA certificate"))?;
+            let key_id = hex::decode(ca.key_id.trim_start_matches("0x").replace('_', ""))
+                .with_context(|| format!("Invalid {name} CA key ID {}", ca.key_id))?;
+            ensure!(
+                key_id.len() == 20,
+                "The {name} CA key ID {} is not 160-bit long",
+                ca.key_id
+            );
+            ensure!(!ca.key.is_empty(), "No key for the {name} CA");
+        }
+        Ok(())
+    }
+
+    /// Checks the bundle may be used for `sku` at time `now`.
+    pub fn check(&self, sku: &str, now: SystemTime) -> Result<()> {
+        ensure!(
+            self.skus.iter().any(|s| s == sku),
+            "Key bundle {} is not for SKU {sku} (SKUs: {})",
+            self.name,
+            self.skus.join(", ")
+        );
+        let not_after = humantime::parse_rfc3339(&self.not_after)?;
+        ensure!(
+            now <= not_after,
+            "Key bundle {} expired on {}",
+            self.name,
+            self.not_after
+        );
+        Ok(())
+    }
+
+    pub fn rma_wrap_key_der(&self) -> Result<Vec<u8>> {
+        hex::decode(&self.rma_wrap_key).context("Invalid RMA wrap key")
+    }
+
+    /// Returns the key the RMA unlock tokens are wrapped to.
+    pub fn rma_wrap_key(&self) -> Result<RmaWrapKey> {
+        RmaWrapKey::from_der(&self.rma_wrap_key_der()?)
+    }
+
+    /// Returns the configurations of the CAs, with their certificates written to temporary files.
+    pub fn ca_configs(&self) -> Result<HashMap<String, CaConfig>> {
+        let mut ca_cfgs = HashMap::new();
+        for (name, ca) in &self.cas {
+            let certificate = tmpfilename(&format!("key_bundle_{}_{name}_ca.pem", self.name));
+            std::fs::write(&certificate, &ca.certificate)
+                .with_context(|| format!("Failed to write the {name} CA certificate"))?;
+            ca_cfgs.insert(
+                name.clone(),
+                CaConfig {
+                    certificate: certificate.into(),
+                    key_id: ca.key_id.clone(),
+                    key_type: ca.key_type.clone(),
+                    key: ca.key.clone(),
+                },
+            );
+        }
+        Ok(ca_cfgs)
+    }
+
+    /// Checks the raw key `key` is the key of the certificate of CA `name`.
+    pub fn check_raw_key(&self, name: &str, key: &HostCaKey) -> Result<()> {
+        let Some(ca) = self.cas.get(name) else {
+            bail!("Key bundle {} has no {name} CA", self.name);
+        };
+        ensure!(
+            matches!(ca.key_type, CaKeyType::Raw),
+            "The {name} CA of key bundle {} does not use a raw key",
+            self.name
+        );
+        let certificate = X509::from_pem(ca.certificate.as_bytes())?;
+        let ca_public_key = raw_public_key(&certificate.public_key()?)?;
+        let public_key = EcdsaRawPublicKey::try_from(&key.key().public_key()?)?;
+        ensure!(
+            ca_public_key.x == public_key.x && ca_public_key.y == public_key.y,
+            "Key {} is not the key of the {name} CA of key bundle {}",
+            key.spec(),
+            self.name
+        );
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::asn1::Asn1Time;
+    use openssl::ec::{EcGroup, EcKey};
+    use openssl::hash::MessageDigest;
+    use openssl::nid::Nid;
+    use openssl::pkey::PKey;
+    use opentitanlib::crypto::ecdsa::EcdsaPrivateKey;
+    use serde_json::{json, Value};
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    /// Returns a self-signed CA certificate, in PEM format.
+    fn certificate() -> String {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let mut builder = X509::builder().unwrap();
+        builder.set_pubkey(&key).unwrap();
+        builder
+            .set_not_before(&Asn1Time::days_from_now(0).unwrap())
+            .unwrap();
+        builder
+            .set_not_after(&Asn1Time::days_from_now(1).unwrap())
+            .unwrap();
+        builder.sign(&key, MessageDigest::sha256()).unwrap();
+        String::from_utf8(builder.build().to_pem().unwrap()).unwrap()
+    }
+
+    fn bundle() -> Value {
+        let ca = json!({
+            "certificate": certificate(),
+            "key_id": "0xfe584ae7_53790cfd_8601a312_fb32d3c1_b822d112",
+            "key_type": "Token",
+            "key": "0x01",
+        });
+        let group = EcGroup::from_curve_name(Nid::SECP384R1).unwrap();
+        let rma_wrap_key = PKey::from_ec_key(EcKey::generate(&group).unwrap())
+            .unwrap()
+            .public_key_to_der()
+            .unwrap();
+        json!({
+            "schema_version": 1,
+            "name": "test",
+            "skus": ["sival"],
+            "not_after": "2027-01-01T00:00:00Z",
+            "rma_wrap_key": hex::encode(rma_wrap_key),
+            "cas": { "dice": ca.clone(), "ext": ca },
+        })
+    }
+
+    fn parse(bundle: &Value) -> Result<ProvisioningKeyBundle> {
+        let bundle: ProvisioningKeyBundle = serde_json::from_value(bundle.clone())?;
+        bundle.validate()?;
+        Ok(bundle)
+    }
+
+    /// Writes `doc` and its signature by `key`, returning the paths of the bundle and signature.
+    fn write_signed(name: &str, doc: &[u8], key: &EcdsaPrivateKey) -> (PathBuf, PathBuf) {
+        let path = PathBuf::from(tmpfilename(&format!("{name}.json")));
+        let signature = PathBuf::from(tmpfilename(&format!("{name}.sig")));
+        std::fs::write(&path, doc).unwrap();
+        let mut file = std::fs::File::create(&signature).unwrap();
+        key.digest_and_sign(doc).unwrap().write(&mut file).unwrap();
+        (path, signature)
+    }
+
+    #[test]
+    fn test_load_signed() {
+        let key = EcdsaPrivateKey::new();
+        let public_key = PathBuf::from(tmpfilename("test_key_bundle_key.der"));
+        key.public_key().save(&public_key).unwrap();
+        let doc = serde_json::to_vec(&bundle()).unwrap();
+
+        let (path, signature) = write_signed("test_key_bundle", &doc, &key);
+        let loaded = ProvisioningKeyBundle::load_signed(&path, &signature, &public_key).unwrap();
+        assert_eq!(loaded.name, "test");
+
+        // A bundle modified after signing.
+        std::fs::write(&path, [doc.as_slice(), b" "].concat()).unwrap();
+        let err = ProvisioningKeyBundle::load_signed(&path, &signature, &public_key).unwrap_err();
+        assert!(err.to_string().contains("Invalid signature"), "{err}");
+
+        // A bundle signed by another key.
+        let (path, signature) =
+            write_signed("test_key_bundle_other", &doc, &EcdsaPrivateKey::new());
+        let err = ProvisioningKeyBundle::load_signed(&path, &signature, &public_key).unwrap_err();
+        assert!(err.to_string().contains("Invalid signature"), "{err}");
+
+        // A signed but invalid bundle.
+        let mut invalid = bundle();
+        invalid["schema_version"] = json!(2);
+        let doc = serde_json::to_vec(&invalid).unwrap();
+        let (path, signature) = write_signed("test_key_bundle_invalid", &doc, &key);
+        let err = ProvisioningKeyBundle::load_signed(&path, &signature, &public_key).unwrap_err();
+        assert!(err.to_string().contains("Invalid key bundle"), "{err}");
+    }
+
+    #[test]
+    fn test_validate() {
+        parse(&bundle()).unwrap();
+
+        let invalid: [(&str, fn(&mut Value)); 6] = [
+            ("Unsupported key bundle version 2", |b| {
+                b["schema_version"] = json!(2)
+            }),
+            ("Invalid expiry", |b| b["not_after"] = json!("2027-01-01")),
+            ("Invalid RMA wrap key", |b| b["rma_wrap_key"] = json!("xyz")),
+            ("No ext CA", |b| {
+                b["cas"].as_object_mut().unwrap().remove("ext");
+            }),
+            ("Invalid dice CA certificate", |b| {
+                b["cas"]["dice"]["certificate"] = json!("")
+            }),
+            ("is not 160-bit long", |b| {
+                b["cas"]["dice"]["key_id"] = json!("0xfe584ae7")
+            }),
+        ];
+        for (expected, modify) in invalid {
+            let mut b = bundle();
+            modify(&mut b);
+            let err = parse(&b).unwrap_err();
+            assert!(err.to_string().contains(expected), "{err}");
+        }
+
+        let mut b = bundle();
+        b["private_key"] = json!("");
+        assert!(parse(&b).is_err());
+    }
+
+    #[test]
+    fn test_check() {
+        let bundle = parse(&bundle()).unwrap();
+        let not_after = humantime::parse_rfc3339("2027-01-01T00:00:00Z").unwrap();
+        bundle.check("sival", not_after).unwrap();
+
+        let err = bundle.check("prodc", not_after).unwrap_err();
+        assert!(err.to_string().contains("is not for SKU prodc"), "{err}");
+        let err = bundle
+            .check("sival", not_after + Duration::from_secs(1))
+            .unwrap_err();
+        assert!(err.to_string().contains("expired on"), "{err}");
+    }
+}
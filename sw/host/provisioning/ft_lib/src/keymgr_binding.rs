@@ -0,0 +1,145 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Per-device sealing binding values of the keymgr stages of personalization.
+//!
+//! The personalization firmware advances the keymgr through the OwnerIntermediate and Owner stages
+//! to generate the CDI_0 and CDI_1 keys. Their attestation bindings are the measurements attested
+//! by the certificates, but their sealing bindings are constants of the flow. With a
+//! diversification key of the SKU, e.g. exported from the HSM with the CA keys, the host derives
+//! them per device instead:
+//!
+//! ```text
+//! <stage>_sealing = HMAC-SHA256(key, "OpenTitan keymgr <stage> sealing" || device_id)
+//! ```
+//!
+//! with `<stage>` one of `owner_int` or `owner`, and `device_id` the hex string of the device ID.
+//! The device answers with the values it binds and its device ID in the HW_CFG0 partition of OTP,
+//! checked against the derived values and the device ID they were derived for. The values are
+//! recorded in the FT result and in the RMA escrow record.
+
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{ensure, Context, Result};
+use arrayvec::ArrayVec;
+use openssl::hash::MessageDigest;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use serde::{Deserialize, Serialize};
+
+use opentitanlib::io::console::ConsoleDevice;
+use opentitanlib::test_utils::rpc::ConsoleSend;
+use ujson_lib::limits::{KeymgrBindingWords, DEVICE_ID_WORDS, KEYMGR_BINDING_WORDS};
+use ujson_lib::provisioning_data::ManufKeymgrBinding;
+use util_lib::{format_device_id, hex_string_to_u32_arrayvec};
+
+use crate::command_id::{recv_answer, CommandIds};
+use crate::retry::RetryPolicy;
+use crate::secret::Secret;
+
+/// Minimum size of a diversification key, in bytes.
+const MIN_KEY_SIZE: usize = 32;
+
+/// Sealing binding values bound by a device during personalization.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeymgrBinding {
+    /// Hex string of the words of the OwnerIntermediate sealing binding value.
+    pub owner_int_sealing: String,
+    /// Hex string of the words of the Owner sealing binding value.
+    pub owner_sealing: String,
+}
+
+fn format_words(words: &[u32]) -> String {
+    words.iter().map(|w| format!("{w:08x}")).collect()
+}
+
+/// Diversification key of a SKU, deriving the sealing binding values of its devices.
+#[derive(Debug)]
+pub struct KeymgrDiversification {
+    key: Secret<Vec<u8>>,
+}
+
+impl KeymgrDiversification {
+    /// Loads the raw diversification key of `path`.
+    pub fn load(path: &Path) -> Result<Self> {
+        let key = Secret::new(
+            std::fs::read(path)
+                .with_context(|| format!("Failed to read keymgr diversification key {path:?}"))?,
+        );
+        ensure!(
+            key.expose().len() >= MIN_KEY_SIZE,
+            "Keymgr diversification key {path:?} is {} bytes, expected at least {MIN_KEY_SIZE}",
+            key.expose().len()
+        );
+        Ok(Self { key })
+    }
+
+    fn derive(&self, stage: &str, device_id: &str) -> Result<KeymgrBindingWords> {
+        let key = PKey::hmac(self.key.expose())?;
+        let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+        signer.update(format!("OpenTitan keymgr {stage} sealing").as_bytes())?;
+        signer.update(device_id.as_bytes())?;
+        Ok(signer
+            .sign_to_vec()?
+            .chunks(4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect())
+    }
+
+    /// Returns the sealing binding values of the device `device_id`.
+    pub fn binding(&self, device_id: &str) -> Result<KeymgrBinding> {
+        Ok(KeymgrBinding {
+            owner_int_sealing: format_words(&self.derive("owner_int", device_id)?),
+            owner_sealing: format_words(&self.derive("owner", device_id)?),
+        })
+    }
+}
+
+/// Sends the sealing binding values `diversification` derives for `device_id` to the device, or
+/// zeros to keep the defaults of the personalization firmware, and returns the values the device
+/// binds.
+pub(crate) fn send_keymgr_binding(
+    console: &dyn ConsoleDevice,
+    diversification: Option<&KeymgrDiversification>,
+    device_id: &str,
+    timeout: Duration,
+    retry: &RetryPolicy,
+    cmd_ids: &CommandIds,
+) -> Result<KeymgrBinding> {
+    let _ = retry.wait_for(console, r"Waiting for keymgr binding values ...", timeout)?;
+    let derived = diversification.map(|d| d.binding(device_id)).transpose()?;
+    let words = |value: Option<&String>| -> Result<KeymgrBindingWords> {
+        match value {
+            Some(value) => hex_string_to_u32_arrayvec(value),
+            None => Ok(ArrayVec::from([0; KEYMGR_BINDING_WORDS])),
+        }
+    };
+    let cmd_id = cmd_ids.next();
+    ManufKeymgrBinding {
+        cmd_id,
+        device_id: hex_string_to_u32_arrayvec::<DEVICE_ID_WORDS>(device_id)?,
+        owner_int_sealing: words(derived.as_ref().map(|b| &b.owner_int_sealing))?,
+        owner_sealing: words(derived.as_ref().map(|b| &b.owner_sealing))?,
+    }
+    .send(console)?;
+    let bound: ManufKeymgrBinding = recv_answer(console, cmd_id, timeout, retry)?;
+    let otp_device_id = format_device_id(&bound.device_id);
+    ensure!(
+        otp_device_id == device_id,
+        "Device ID {otp_device_id} in OTP does not match device ID {device_id} of the keymgr \
+         binding values"
+    );
+    let bound = KeymgrBinding {
+        owner_int_sealing: format_words(&bound.owner_int_sealing),
+        owner_sealing: format_words(&bound.owner_sealing),
+    };
+    if let Some(derived) = derived {
+        ensure!(
+            bound == derived,
+            "Device binds keymgr sealing values {bound:?} instead of {derived:?}"
+        );
+    }
+    Ok(bound)
+}
@@ -8,68 +8,174 @@ use std::collections::HashSet;
 use std::path::PathBuf;
 use std::time::{Duration, Instant};
 
-use anyhow::{bail, Result};
+use anyhow::{bail, ensure, Context, Result};
 use arrayvec::ArrayVec;
+use clap::ValueEnum;
+use openssl::x509::X509;
 use zerocopy::IntoBytes;
 
+use cert_lib::chain::{check_cert_chain, load_ca_cert};
+use cert_lib::extensions::CertExtensions;
+use cert_lib::offline::OfflineCerts;
+use cert_lib::policy::SigningPolicy;
 use cert_lib::{parse_and_endorse_x509_cert, validate_cert_chain, CaConfig, CaKey, EndorsedCert};
 use ft_ext_lib::ft_ext;
 use opentitanlib::app::TransportWrapper;
-use opentitanlib::console::spi::SpiConsoleDevice;
-use opentitanlib::dif::lc_ctrl::{DifLcCtrlState, LcCtrlReg};
+use opentitanlib::dif::lc_ctrl::DifLcCtrlState;
+use opentitanlib::dif::otp_ctrl::{DaiParam, Partition};
+use opentitanlib::io::console::ConsoleDevice;
 use opentitanlib::io::jtag::{JtagParams, JtagTap};
 use opentitanlib::test_utils::init::InitializeTest;
-use opentitanlib::test_utils::lc_transition::trigger_lc_transition;
+use opentitanlib::test_utils::lc_transition::{trigger_lc_transition_with_timeout, LcStateCheck};
 use opentitanlib::test_utils::load_sram_program::{
-    ExecutionMode, ExecutionResult, SramProgramParams,
+    ExecutionResult, JtagClockRamp, SramProgramParams,
 };
-use opentitanlib::test_utils::rpc::{ConsoleRecv, ConsoleSend};
-use opentitanlib::uart::console::UartConsole;
+use opentitanlib::test_utils::otp_ctrl::{OtpParam, OtpPartition};
+use opentitanlib::test_utils::rpc::ConsoleSend;
+use ot_certs::template::Value;
 use ot_certs::x509::parse_certificate;
 use ot_certs::CertFormat;
 use perso_tlv_lib::perso_tlv_get_field;
 use perso_tlv_lib::{CertHeader, CertHeaderType, ObjHeader, ObjHeaderType, ObjType};
+use ujson_lib::limits::{PersoBlobBody, PERSO_BLOB_BODY_SIZE};
 use ujson_lib::provisioning_data::{
-    LcTokenHash, ManufCertgenInputs, ManufFtIndividualizeData, PersoBlob, SerdesSha256Hash,
+    LcTokenHash, ManufCertgenInputs, ManufCreatorManufState, ManufFtIndividualizeData,
+    ManufPersoExportOptions, PersoBlob, SerdesSha256Hash,
 };
-use util_lib::hash_lc_token;
-
+use util_lib::{format_device_id, hash_lc_token};
+
+pub mod alert_cfg;
+pub mod audit;
+pub mod checkpoint;
+pub mod command_id;
+#[cfg(feature = "debug-tools")]
+pub mod debug_regs;
+pub mod entropy;
+pub mod error;
+pub mod events;
+pub mod flow;
+pub mod flow_result;
+pub mod handoff;
+pub mod health;
+pub mod key_bundle;
+pub mod keymgr_binding;
+pub mod log_level;
+pub mod manuf_state;
+pub mod otp_dump;
+pub mod otp_preload;
+pub mod pass_criteria;
+pub mod perso_compression;
+pub mod provisioner;
+pub mod release;
 pub mod response;
+pub mod retry;
+pub mod rma_escrow;
+pub mod rma_token;
+pub mod rma_token_escrow;
+pub mod smoke_test;
+pub mod step_plan;
+pub mod step_state;
+pub mod telemetry;
+pub mod trim;
+pub mod verify;
+
+use alert_cfg::{send_alert_cfg, AlertCfg};
+use command_id::{recv_answer, CommandIds};
+use entropy::{EntropyCheck, GeneratedValue};
+use error::{check_lc_state, token_generation_label, wait_for, ProvisioningError};
+use events::EventSink;
+use health::HealthSnapshot;
+use keymgr_binding::{send_keymgr_binding, KeymgrDiversification};
+use log_level::{send_log_level, DeviceLogLevel};
+use manuf_state::{send_creator_manuf_state, verify_creator_manuf_state, CreatorManufState};
+use perso_compression::{recv_perso_blob, send_perso_blob, PersoCompression};
+pub use provisioning::{host_key, secret, timeouts};
 use response::*;
+use retry::RetryPolicy;
+use secret::LcTokenSecret;
+use step_state::StepJournal;
+use timeouts::Timeouts;
+
+/// Checks a test unlock from the `from` to the `to` LC state is legal: from a `TEST_LOCKED<n>`
+/// state to any later `TEST_UNLOCKED<m>` state, `m > n`.
+pub fn check_test_unlock_transition(from: DifLcCtrlState, to: DifLcCtrlState) -> Result<()> {
+    let from_locked = matches!(
+        from,
+        DifLcCtrlState::TestLocked0
+            | DifLcCtrlState::TestLocked1
+            | DifLcCtrlState::TestLocked2
+            | DifLcCtrlState::TestLocked3
+            | DifLcCtrlState::TestLocked4
+            | DifLcCtrlState::TestLocked5
+            | DifLcCtrlState::TestLocked6
+    );
+    let to_unlocked = matches!(
+        to,
+        DifLcCtrlState::TestUnlocked1
+            | DifLcCtrlState::TestUnlocked2
+            | DifLcCtrlState::TestUnlocked3
+            | DifLcCtrlState::TestUnlocked4
+            | DifLcCtrlState::TestUnlocked5
+            | DifLcCtrlState::TestUnlocked6
+            | DifLcCtrlState::TestUnlocked7
+    );
+    ensure!(
+        from_locked && to_unlocked && from.check_transition(to).valid,
+        "Invalid test unlock transition from {} to {}",
+        from.lc_state_to_str(),
+        to.lc_state_to_str()
+    );
+    Ok(())
+}
 
+#[allow(clippy::too_many_arguments)]
+#[deprecated(note = "use `FtProvisioner`, which checks its capabilities")]
 pub fn test_unlock(
     transport: &TransportWrapper,
     jtag_params: &JtagParams,
     reset_delay: Duration,
-    test_unlock_token: &ArrayVec<u32, 4>,
+    test_unlock_token: &LcTokenSecret,
+    from: DifLcCtrlState,
+    to: DifLcCtrlState,
+    lc_state_check: &LcStateCheck,
+    lc_transition_timeout: Duration,
+    retry: &RetryPolicy,
+    events: &EventSink,
 ) -> Result<()> {
+    check_test_unlock_transition(from, to)?;
+
     // Connect to LC TAP.
     transport.pin_strapping("PINMUX_TAP_LC")?.apply()?;
-    transport.reset_target(reset_delay, true)?;
-    let mut jtag = jtag_params.create(transport)?.connect(JtagTap::LcTap)?;
+    events.time("reset", || transport.reset_target(reset_delay, true))?;
+    let mut jtag = events.time("jtag-connect", || {
+        retry.connect_jtag(transport, jtag_params, JtagTap::LcTap)
+    })?;
 
-    // Check that LC state is currently `TEST_LOCKED0`.
-    let state = jtag.read_lc_ctrl_reg(&LcCtrlReg::LcState)?;
-    assert_eq!(state, DifLcCtrlState::TestLocked0.redundant_encoding());
+    // Check that LC state is currently `from`.
+    check_lc_state(&mut *jtag, from, lc_state_check)?;
 
     // ROM execution is not yet enabled in OTP so we can safely reconnect to the LC TAP after
     // the transition without risking the chip resetting.
-    trigger_lc_transition(
-        transport,
-        jtag,
-        DifLcCtrlState::TestUnlocked1,
-        Some(test_unlock_token.clone().into_inner().unwrap()),
-        /*use_external_clk=*/
-        false, // AST will be calibrated by now, so no need for ext_clk.
-        reset_delay,
-        /*reset_tap_straps=*/ Some(JtagTap::LcTap),
-    )?;
+    events.time("lc-transition", || {
+        trigger_lc_transition_with_timeout(
+            transport,
+            jtag,
+            to,
+            Some(*test_unlock_token.expose()),
+            /*use_external_clk=*/
+            false, // AST will be calibrated by now, so no need for ext_clk.
+            reset_delay,
+            /*reset_tap_straps=*/ Some(JtagTap::LcTap),
+            lc_transition_timeout,
+        )
+    })?;
 
-    jtag = jtag_params.create(transport)?.connect(JtagTap::LcTap)?;
+    jtag = events.time("jtag-connect", || {
+        retry.connect_jtag(transport, jtag_params, JtagTap::LcTap)
+    })?;
 
-    // Check that LC state has transitioned to `TestUnlocked1`.
-    let state = jtag.read_lc_ctrl_reg(&LcCtrlReg::LcState)?;
-    assert_eq!(state, DifLcCtrlState::TestUnlocked1.redundant_encoding());
+    // Check that LC state has transitioned to `to`.
+    check_lc_state(&mut *jtag, to, lc_state_check)?;
 
     jtag.disconnect()?;
     transport.pin_strapping("PINMUX_TAP_LC")?.remove()?;
@@ -77,56 +183,265 @@ pub fn test_unlock(
     Ok(())
 }
 
+/// Policy applied when the OTP HW_CFG0 partition (device ID) was already programmed, e.g. during
+/// CP, before the FT individualize step runs.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum HwCfgPolicy {
+    /// Check the programmed device ID matches the one provided on the command line.
+    #[default]
+    Verify,
+    /// Keep the programmed device ID, even if it differs from the one provided.
+    Skip,
+    /// Refuse to individualize a device with a pre-programmed HW_CFG0 partition.
+    Fail,
+}
+
+/// OTP partitions individualized by the FT individualize SRAM program.
+///
+/// The discriminants are bit positions in `ManufFtIndividualizeData::partitions`, and must match
+/// `manuf_ft_individualize_partition_t` in sram_ft_individualize.c.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+#[value(rename_all = "snake_case")]
+pub enum IndividualizePartition {
+    HwCfg = 0,
+    CreatorSwCfg = 1,
+    OwnerSwCfg = 2,
+    RotCreatorAuthCodesign = 3,
+    RotCreatorAuthState = 4,
+}
+
+impl IndividualizePartition {
+    /// Returns the `ManufFtIndividualizeData::partitions` bitmask selecting all partitions.
+    pub fn all() -> u32 {
+        Self::bitmask(Self::value_variants())
+    }
+
+    /// Returns the `ManufFtIndividualizeData::partitions` bitmask selecting `partitions`.
+    pub fn bitmask(partitions: &[IndividualizePartition]) -> u32 {
+        partitions
+            .iter()
+            .fold(0, |mask, partition| mask | partition.bit())
+    }
+
+    /// Returns the `ManufFtIndividualizeData::partitions` bit selecting this partition.
+    pub fn bit(self) -> u32 {
+        1 << self as u32
+    }
+
+    /// Returns the name of the partition in the OTP memory map and the OTP dump.
+    pub fn otp_partition(self) -> &'static str {
+        match self {
+            Self::HwCfg => "HW_CFG0",
+            Self::CreatorSwCfg => "CREATOR_SW_CFG",
+            Self::OwnerSwCfg => "OWNER_SW_CFG",
+            Self::RotCreatorAuthCodesign => "ROT_CREATOR_AUTH_CODESIGN",
+            Self::RotCreatorAuthState => "ROT_CREATOR_AUTH_STATE",
+        }
+    }
+}
+
+/// Checks whether the HW_CFG0 partition was already programmed, and applies `policy` if so.
+///
+/// Returns the device ID that will end up in OTP once the individualize step completes: either
+/// `device_id`, or the pre-programmed device ID under the `Skip` policy.
+pub fn check_hw_cfg_device_id(
+    transport: &TransportWrapper,
+    jtag_params: &JtagParams,
+    reset_delay: Duration,
+    device_id: &ArrayVec<u32, 8>,
+    policy: HwCfgPolicy,
+    retry: &RetryPolicy,
+) -> Result<ArrayVec<u32, 8>> {
+    // Set CPU TAP straps, reset, and connect to the JTAG interface.
+    transport.pin_strapping("PINMUX_TAP_RISCV")?.apply()?;
+    transport.reset_target(reset_delay, true)?;
+    let mut jtag = retry.connect_jtag(transport, jtag_params, JtagTap::RiscvTap)?;
+    jtag.reset(/*run=*/ false)?;
+
+    let mut otp_device_id = [0u32; 8];
+    OtpParam::read_param(&mut *jtag, DaiParam::DeviceId, &mut otp_device_id)
+        .context("failed to read DEVICE_ID from OTP")?;
+    let digest = OtpPartition::read_digest(&mut *jtag, Partition::HW_CFG0)
+        .context("failed to read HW_CFG0 partition digest")?;
+
+    jtag.disconnect()?;
+    transport.pin_strapping("PINMUX_TAP_RISCV")?.remove()?;
+
+    let locked = digest != [0u32; 2];
+    if !locked && otp_device_id == [0u32; 8] {
+        log::info!("HW_CFG0 is not programmed yet.");
+        return Ok(device_id.clone());
+    }
+    log::info!(
+        "HW_CFG0 already programmed (locked: {locked}), device ID: {}",
+        format_device_id(&otp_device_id)
+    );
+
+    match policy {
+        HwCfgPolicy::Fail => bail!("HW_CFG0 partition is already programmed."),
+        HwCfgPolicy::Verify => {
+            if otp_device_id != device_id.as_slice() {
+                bail!(
+                    "Pre-programmed device ID ({:x?}) does not match the provided device ID ({:x?}).",
+                    otp_device_id,
+                    device_id.as_slice()
+                );
+            }
+            Ok(device_id.clone())
+        }
+        HwCfgPolicy::Skip => {
+            if !locked {
+                // The individualize program only skips HW_CFG0 programming if the partition is
+                // locked, so a partially programmed partition would cause a conflicting write.
+                bail!("HW_CFG0 partition is partially programmed but not locked.");
+            }
+            if otp_device_id != device_id.as_slice() {
+                log::warn!("Keeping pre-programmed device ID instead of the provided one.");
+            }
+            Ok(ArrayVec::from(otp_device_id))
+        }
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
+#[deprecated(note = "use `FtProvisioner`, which checks its capabilities")]
 pub fn run_sram_ft_individualize(
     transport: &TransportWrapper,
     jtag_params: &JtagParams,
     reset_delay: Duration,
     sram_program: &SramProgramParams,
+    clock_ramp: &JtagClockRamp,
     ft_individualize_data_in: &ManufFtIndividualizeData,
-    timeout: Duration,
-    spi_console: &SpiConsoleDevice,
+    alert_cfg: &AlertCfg,
+    timeouts: &Timeouts,
+    retry: &RetryPolicy,
+    console: &dyn ConsoleDevice,
+    dmi_console: bool,
+    events: &EventSink,
 ) -> Result<()> {
     // Set CPU TAP straps, reset, and connect to the JTAG interface.
     transport.pin_strapping("PINMUX_TAP_RISCV")?.apply()?;
-    transport.reset_target(reset_delay, true)?;
-    let mut jtag = jtag_params.create(transport)?.connect(JtagTap::RiscvTap)?;
+    events.time("reset", || transport.reset_target(reset_delay, true))?;
+    let mut jtag = events.time("jtag-connect", || {
+        retry.connect_jtag(transport, jtag_params, JtagTap::RiscvTap)
+    })?;
 
     // Reset and halt the CPU to ensure we are in a known state, and clear out any ROM messages
     // printed over the console.
     jtag.reset(/*run=*/ false)?;
 
     // Load and execute the SRAM program that contains the provisioning code.
-    let result = sram_program.load_and_execute(&mut *jtag, ExecutionMode::Jump)?;
+    let result = events.time("sram-load", || {
+        sram_program.load_and_jump_ramped(&mut *jtag, jtag_params.adapter_speed_khz, clock_ramp)
+    })?;
     match result {
         ExecutionResult::Executing => log::info!("SRAM program loaded and is executing."),
-        _ => panic!("SRAM program load/execution failed: {:?}.", result),
+        _ => return Err(ProvisioningError::SramLoadFailed(format!("{result:?}")).into()),
     }
+    // Free the JTAG adapter for the console if it reads over the debug module.
+    let jtag = if dmi_console {
+        jtag.disconnect()?;
+        None
+    } else {
+        Some(jtag)
+    };
 
     // Wait for SRAM program to complete execution.
-    let _ = UartConsole::wait_for(
-        spi_console,
+    let _ = retry.wait_for(
+        console,
         r"Waiting for FT SRAM provisioning data ...",
-        timeout,
+        timeouts.sram_start,
     )?;
 
     // Inject provisioning data into the device.
-    ft_individualize_data_in.send(spi_console)?;
+    ft_individualize_data_in.send(console)?;
+    events.command_sent("ft-individualize-data");
+    if ft_individualize_data_in.partitions & IndividualizePartition::OwnerSwCfg.bit() != 0 {
+        send_alert_cfg(console, alert_cfg, timeouts.sram_start, retry)?;
+        events.command_sent("alert-cfg");
+    }
 
     // Wait for provisioning operations to complete.
-    let _ = UartConsole::wait_for(spi_console, r"FT SRAM provisioning done.", timeout)?;
+    let _ = events.time("otp-write-individualize", || {
+        retry.wait_for(console, r"FT SRAM provisioning done.", timeouts.otp_write)
+    })?;
+    events.status_received("ft-individualize-done");
+
+    if let Some(jtag) = jtag {
+        jtag.disconnect()?;
+    }
+    transport.pin_strapping("PINMUX_TAP_RISCV")?.remove()?;
+
+    Ok(())
+}
+
+/// Checks that the test exit token hash in OTP SECRET0 is the hash of `test_exit_token`, i.e.
+/// that the device was CP-provisioned with the token generation the host is configured with.
+///
+/// SECRET0 can only be read back over the DAI until its digest is computed, i.e. not after CP
+/// locked it. The token of a locked SECRET0 is left to the LC controller: `test_exit` reports the
+/// token error it raises as the same generation mismatch.
+pub(crate) fn check_test_exit_token(
+    transport: &TransportWrapper,
+    jtag_params: &JtagParams,
+    test_exit_token: &LcTokenSecret,
+    token_generation: &str,
+    retry: &RetryPolicy,
+) -> Result<()> {
+    // As for the test exit, do not reset the chip: the CPU was halted by the FT individualize
+    // SRAM program, and TAP straps are continuously sampled in TEST_UNLOCKED* LC states.
+    transport.pin_strapping("PINMUX_TAP_RISCV")?.apply()?;
+    let mut jtag = retry.connect_jtag(transport, jtag_params, JtagTap::RiscvTap)?;
+
+    let digest = OtpPartition::read_digest(&mut *jtag, Partition::SECRET0)
+        .context("failed to read SECRET0 partition digest")?;
+    let mut otp_token_hash = [0u32; 4];
+    let readable = digest == [0u32; 2];
+    if readable {
+        OtpParam::read_param(&mut *jtag, DaiParam::TestExitToken, &mut otp_token_hash)
+            .context("failed to read TEST_EXIT_TOKEN from OTP")?;
+    }
 
     jtag.disconnect()?;
     transport.pin_strapping("PINMUX_TAP_RISCV")?.remove()?;
 
+    let generation = token_generation_label(token_generation);
+    if !readable {
+        log::info!(
+            "SECRET0 partition is locked, the LC controller checks the test exit token \
+             (generation {generation})."
+        );
+        return Ok(());
+    }
+    let otp_hash = [
+        u64::from(otp_token_hash[0]) | (u64::from(otp_token_hash[1]) << 32),
+        u64::from(otp_token_hash[2]) | (u64::from(otp_token_hash[3]) << 32),
+    ];
+    if otp_hash == [0u64; 2] {
+        bail!("OTP SECRET0 holds no test exit token hash.");
+    }
+    if hash_lc_token(test_exit_token.expose().as_bytes())?.as_slice() != otp_hash {
+        return Err(ProvisioningError::TokenGenerationMismatch {
+            generation: generation.to_string(),
+        }
+        .into());
+    }
+    log::info!("Test exit token matches OTP SECRET0 (generation {generation}).");
     Ok(())
 }
 
+#[allow(clippy::too_many_arguments)]
+#[deprecated(note = "use `FtProvisioner`, which checks its capabilities")]
 pub fn test_exit(
     transport: &TransportWrapper,
     jtag_params: &JtagParams,
     reset_delay: Duration,
-    test_exit_token: &ArrayVec<u32, 4>,
+    test_exit_token: &LcTokenSecret,
     target_mission_mode_lc_state: DifLcCtrlState,
+    lc_state_check: &LcStateCheck,
+    lc_transition_timeout: Duration,
+    retry: &RetryPolicy,
+    events: &EventSink,
 ) -> Result<()> {
     // Connect to LC TAP.
     //
@@ -134,27 +449,32 @@ pub fn test_exit(
     // executed should have unlocked ROM execution and halted the CPU already. If we reset the
     // chip, the ROM will attempt to boot the flash image, which we do not want to do until we
     // transition to a mission mode state. We do not need to reset the chip to switch TAPs because
-    // TAP straps are continuously sampled in TEST_UNLOCKED* LC state.
+    // TAP straps are continuously sampled in TEST_UNLOCKED* LC state. `FtProvisioner` refuses
+    // operations resetting the chip until this transition succeeds.
     transport.pin_strapping("PINMUX_TAP_LC")?.apply()?;
-    let mut jtag = jtag_params.create(transport)?.connect(JtagTap::LcTap)?;
+    let mut jtag = events.time("jtag-connect", || {
+        retry.connect_jtag(transport, jtag_params, JtagTap::LcTap)
+    })?;
 
     // Check that LC state is currently `TEST_UNLOCKED1`.
-    let state = jtag.read_lc_ctrl_reg(&LcCtrlReg::LcState)?;
-    assert_eq!(state, DifLcCtrlState::TestUnlocked1.redundant_encoding());
+    check_lc_state(&mut *jtag, DifLcCtrlState::TestUnlocked1, lc_state_check)?;
 
     // ROM execution should now be enabled in OTP so we cannot safely reconnect to the LC TAP after
     // the transition without risking the chip resetting. Therefore, it is the responsibility of the
     // flash program that is subsequently bootstrapped / run to check the LC state is as expected.
-    trigger_lc_transition(
-        transport,
-        jtag,
-        target_mission_mode_lc_state,
-        Some(test_exit_token.clone().into_inner().unwrap()),
-        /*use_external_clk=*/
-        false, // AST will be calibrated by now, so no need for ext_clk.
-        reset_delay,
-        /*reset_tap_straps=*/ None,
-    )?;
+    events.time("lc-transition", || {
+        trigger_lc_transition_with_timeout(
+            transport,
+            jtag,
+            target_mission_mode_lc_state,
+            Some(*test_exit_token.expose()),
+            /*use_external_clk=*/
+            false, // AST will be calibrated by now, so no need for ext_clk.
+            reset_delay,
+            /*reset_tap_straps=*/ None,
+            lc_transition_timeout,
+        )
+    })?;
 
     transport.pin_strapping("PINMUX_TAP_LC")?.remove()?;
 
@@ -162,21 +482,18 @@ pub fn test_exit(
 }
 
 fn send_rma_unlock_token_hash(
-    rma_unlock_token: &ArrayVec<u32, 4>,
+    rma_unlock_token: &LcTokenSecret,
     timeout: Duration,
-    spi_console: &SpiConsoleDevice,
+    retry: &RetryPolicy,
+    console: &dyn ConsoleDevice,
 ) -> Result<()> {
     let rma_token_hash = LcTokenHash {
-        hash: hash_lc_token(rma_unlock_token.as_bytes())?,
+        hash: hash_lc_token(rma_unlock_token.expose().as_bytes())?,
     };
 
     // Wait for test to start running.
-    let _ = UartConsole::wait_for(
-        spi_console,
-        r"Waiting For RMA Unlock Token Hash ...",
-        timeout,
-    )?;
-    rma_token_hash.send_with_crc(spi_console)?;
+    let _ = retry.wait_for(console, r"Waiting For RMA Unlock Token Hash ...", timeout)?;
+    rma_token_hash.send_with_crc(console)?;
     Ok(())
 }
 
@@ -243,13 +560,19 @@ fn get_cert(data: &[u8]) -> Result<CertHeader> {
 fn push_endorsed_cert(
     cert: &Vec<u8>,
     ref_cert: &CertHeader,
-    output: &mut ArrayVec<u8, 4096>,
+    output: &mut PersoBlobBody,
 ) -> Result<()> {
     // Need to wrap the new cert in CertHeader
     let total_size = std::mem::size_of::<ObjHeaderType>()
         + std::mem::size_of::<CertHeaderType>()
         + ref_cert.cert_name.len()
         + cert.len();
+    ensure!(
+        total_size <= output.remaining_capacity(),
+        "Endorsed {} certificate overflows the {PERSO_BLOB_BODY_SIZE}-byte perso blob by {} bytes",
+        ref_cert.cert_name,
+        total_size - output.remaining_capacity()
+    );
 
     let obj_header = perso_tlv_lib::make_obj_header(total_size, ObjType::EndorsedX509Cert)?;
     let cert_wrapper_header =
@@ -279,27 +602,106 @@ fn process_dev_seeds(seeds: &[u8]) -> Result<Vec<Vec<u8>>> {
     Ok(response)
 }
 
+/// Options of the personalization data exported off the device.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct PersoExportOptions {
+    /// Compression requested for the perso blob.
+    pub compression: PersoCompression,
+    /// Whether to retrieve a health snapshot once the device is personalized.
+    pub health_snapshot: bool,
+    /// Whether to import the endorsed certificates in acknowledged chunks.
+    pub chunked_import: bool,
+}
+
+/// Sends the export options to the device and returns the options it accepted.
+fn negotiate_export_options(
+    console: &dyn ConsoleDevice,
+    requested: PersoExportOptions,
+    timeout: Duration,
+    retry: &RetryPolicy,
+    cmd_ids: &CommandIds,
+    events: &EventSink,
+) -> Result<PersoExportOptions> {
+    let _ = retry.wait_for(console, r"Waiting for export options ...", timeout)?;
+    let cmd_id = cmd_ids.next();
+    ManufPersoExportOptions {
+        cmd_id,
+        compression: requested.compression.mask(),
+        health_snapshot: requested.health_snapshot,
+        chunked_import: requested.chunked_import,
+    }
+    .send(console)?;
+    events.command_sent("export-options");
+    let options: ManufPersoExportOptions = recv_answer(console, cmd_id, timeout, retry)?;
+    events.status_received("export-options");
+    let accepted = PersoExportOptions {
+        compression: PersoCompression::from_mask(options.compression)?,
+        health_snapshot: options.health_snapshot,
+        chunked_import: options.chunked_import,
+    };
+    if accepted.compression != requested.compression {
+        log::warn!(
+            "Device does not support {:?} compression, using {:?}.",
+            requested.compression,
+            accepted.compression
+        );
+    }
+    if requested.health_snapshot && !accepted.health_snapshot {
+        log::warn!("Device declined to send a health snapshot.");
+    }
+    if requested.chunked_import && !accepted.chunked_import {
+        log::warn!("Device does not support chunked imports, sending the certificates at once.");
+    }
+    Ok(accepted)
+}
+
+#[allow(clippy::too_many_arguments)]
 fn provision_certificates(
     ca_cfgs: HashMap<String, CaConfig>,
     ca_keys: HashMap<String, CaKey>,
+    cert_policy: &SigningPolicy,
+    offline_certs: Option<&OfflineCerts>,
     perso_certgen_inputs: &ManufCertgenInputs,
+    export_options: PersoExportOptions,
+    creator_manuf_state: Option<CreatorManufState>,
+    keymgr_diversification: Option<&KeymgrDiversification>,
+    entropy_check: &EntropyCheck,
     timeout: Duration,
-    spi_console: &SpiConsoleDevice,
+    retry: &RetryPolicy,
+    cmd_ids: &CommandIds,
+    console: &dyn ConsoleDevice,
+    events: &EventSink,
     response: &mut PersonalizeResponse,
-) -> Result<()> {
+) -> Result<(PersoExportOptions, ManufCreatorManufState)> {
     // Send attestation TCB measurements for generating DICE certificates.
     let t0 = Instant::now();
-    let _ = UartConsole::wait_for(spi_console, r"Waiting for certificate inputs ...", timeout)?;
+    let _ = retry.wait_for(console, r"Waiting for certificate inputs ...", timeout)?;
     response.stats.log_elapsed_time("perso-wait-ready", t0);
 
     let t0 = Instant::now();
-    perso_certgen_inputs.send(spi_console)?;
+    perso_certgen_inputs.send(console)?;
+    events.command_sent("certgen-inputs");
+    let export_options =
+        negotiate_export_options(console, export_options, timeout, retry, cmd_ids, events)?;
+    let creator_manuf_state =
+        send_creator_manuf_state(console, creator_manuf_state, timeout, retry, cmd_ids)?;
+    events.command_sent("creator-manuf-state");
+    response.keymgr_binding = Some(send_keymgr_binding(
+        console,
+        keymgr_diversification,
+        &response.device_id,
+        timeout,
+        retry,
+        cmd_ids,
+    )?);
+    events.command_sent("keymgr-binding");
     response.stats.log_elapsed_time("perso-certgen-inputs", t0);
 
     // Wait until the device exports the TBS certificates.
     let t0 = Instant::now();
-    let _ = UartConsole::wait_for(spi_console, r"Exporting TBS certificates ...", timeout)?;
-    let perso_blob = PersoBlob::recv(spi_console, timeout, true)?;
+    let _ = retry.wait_for(console, r"Exporting TBS certificates ...", timeout)?;
+    let perso_blob = recv_perso_blob(console, export_options.compression, timeout, retry)?;
+    events.status_received("tbs-certs");
     response.stats.log_elapsed_time("perso-tbs-export", t0);
 
     // Extract certificate byte vectors, endorse TBS certs, and ensure they parse with OpenSSL.
@@ -312,13 +714,12 @@ fn provision_certificates(
     let mut dice_cert_chain: Vec<EndorsedCert> = Vec::new();
     let mut sku_specific_certs: Vec<EndorsedCert> = Vec::new();
     let mut num_host_endorsed_certs = 0;
-    let mut endorsed_cert_concat = ArrayVec::<u8, 4096>::new();
+    let mut endorsed_cert_concat = PersoBlobBody::new();
+    let mut generated_values: Vec<GeneratedValue> = Vec::new();
 
-    // Extract CAs.
+    // Extract CAs. Their keys are not needed if the certs were issued offline.
     let dice_ca_cert = &ca_cfgs["dice"].certificate;
-    let dice_ca_key = &ca_keys["dice"];
     let ext_ca_cert = &ca_cfgs["ext"].certificate;
-    let ext_ca_key = &ca_keys["ext"];
 
     // DICE certificate names.
     let dice_cert_names = HashSet::from(["UDS", "CDI_0", "CDI_1"]);
@@ -341,6 +742,9 @@ fn provision_certificates(
                 cert_hasher.update(seeds);
                 let r = process_dev_seeds(seeds)?;
                 start += dev_seed_size;
+                generated_values.extend(r.iter().enumerate().map(|(i, seed)| {
+                    GeneratedValue::new(format!("seed #{}", response.seeds.number + i), seed)
+                }));
                 response.seeds.number += r.len();
                 response.seeds.seed.extend(r);
                 continue;
@@ -355,10 +759,22 @@ fn provision_certificates(
         // Extract the certificate bytes and endorse the cert if needed.
         let cert_bytes = if header.obj_type == ObjType::UnendorsedX509Cert {
             // Endorse the cert and updates its size.
-            let cert_bytes = if dice_cert_names.contains(cert.cert_name) {
-                parse_and_endorse_x509_cert(cert.cert_body.clone(), dice_ca_key)?
-            } else {
-                parse_and_endorse_x509_cert(cert.cert_body.clone(), ext_ca_key)?
+            cert_policy.check_tbs(cert.cert_name, &cert.cert_body)?;
+            let cert_bytes = match offline_certs {
+                Some(offline_certs) => {
+                    offline_certs.endorse(&response.device_id, cert.cert_name, &cert.cert_body)?
+                }
+                None => {
+                    let ca = if dice_cert_names.contains(cert.cert_name) {
+                        "dice"
+                    } else {
+                        "ext"
+                    };
+                    let ca_key = ca_keys
+                        .get(ca)
+                        .with_context(|| format!("No key for the {ca} CA"))?;
+                    parse_and_endorse_x509_cert(cert.cert_body.clone(), ca_key)?
+                }
             };
 
             // Prepare a collection of (SKU-specific) certs whose endorsements should be verified.
@@ -381,18 +797,18 @@ fn provision_certificates(
             cert.cert_body
         };
 
-        // Collect all DICE certs to validate the chain.
+        // Collect all DICE certs to validate the chain, including those endorsed on the device.
         // TODO(lowRISC/opentitan:#24281): Add CWT verifier
-        if dice_cert_names.contains(cert.cert_name)
-            && header.obj_type == ObjType::UnendorsedX509Cert
-        {
+        if dice_cert_names.contains(cert.cert_name) && header.obj_type != ObjType::EndorsedCwtCert {
             let ec = EndorsedCert {
                 format: CertFormat::X509,
                 name: cert.cert_name.to_string(),
                 bytes: cert_bytes.clone(),
                 ignore_critical: true,
             };
-            response.certs.insert(ec.name.clone(), ec.clone());
+            if header.obj_type == ObjType::UnendorsedX509Cert {
+                response.certs.insert(ec.name.clone(), ec.clone());
+            }
             dice_cert_chain.push(ec);
         }
 
@@ -400,7 +816,27 @@ fn provision_certificates(
         log::info!("{} Cert: {}", cert.cert_name, hex::encode(&cert_bytes));
         // TODO(lowRISC/opentitan:#24281): Add CWT parser
         if header.obj_type != ObjType::DevSeed && header.obj_type != ObjType::EndorsedCwtCert {
-            let _ = parse_certificate(&cert_bytes)?;
+            let parsed = parse_certificate(&cert_bytes)?;
+            let extensions = CertExtensions::decode(&parsed);
+            if !extensions.is_empty() {
+                response
+                    .cert_extensions
+                    .insert(cert.cert_name.to_string(), extensions);
+            }
+            if let Value::Literal(serial_number) = parsed.serial_number {
+                generated_values.push(GeneratedValue::new(
+                    format!("{} serial number", cert.cert_name),
+                    &serial_number.to_bytes_be(),
+                ));
+            }
+            // A public key exported by another device reveals a broken key generation.
+            let public_key = X509::from_der(&cert_bytes)?
+                .public_key()?
+                .public_key_to_der()?;
+            generated_values.push(GeneratedValue::public_key(
+                format!("{} public key", cert.cert_name),
+                &public_key,
+            ));
         }
         // Push the cert into the hasher so we can ensure the certs written to the device's flash
         // info pages match those verified on the host.
@@ -408,6 +844,13 @@ fn provision_certificates(
     }
     response.stats.log_elapsed_time("perso-process-blobs", t0);
 
+    // Reject the device before writing anything back if its generated values look suspicious,
+    // e.g. on a lot with a broken CSRNG.
+    let min_entropy = entropy_check.check(&response.device_id, &generated_values)?;
+    response
+        .stats
+        .log_string("min-value-entropy", &format!("{min_entropy:.2}"));
+
     // Execute extension hook.
     let t0 = Instant::now();
     endorsed_cert_concat = ft_ext(endorsed_cert_concat)?;
@@ -424,14 +867,23 @@ fn provision_certificates(
         body: endorsed_cert_concat,
     };
     let t0 = Instant::now();
-    let _ = UartConsole::wait_for(spi_console, r"Importing endorsed certificates ...", timeout)?;
-    manuf_perso_data_back.send(spi_console)?;
-    let _ = UartConsole::wait_for(spi_console, r"Finished importing certificates.", timeout)?;
+    let _ = retry.wait_for(console, r"Importing endorsed certificates ...", timeout)?;
+    send_perso_blob(
+        console,
+        &manuf_perso_data_back,
+        export_options.chunked_import,
+        timeout,
+        retry,
+        cmd_ids,
+    )?;
+    events.command_sent("endorsed-certs");
+    let _ = retry.wait_for(console, r"Finished importing certificates.", timeout)?;
     response.stats.log_elapsed_time("perso-import-certs", t0);
 
     // Check the integrity of the certificates written to the device's flash by comparing a
     // SHA256 over all certificates computed on the host and device sides.
-    let device_computed_certs_hash = SerdesSha256Hash::recv(spi_console, timeout, false)?;
+    let device_computed_certs_hash: SerdesSha256Hash = retry.recv(console, timeout, false)?;
+    events.status_received("certs-hash");
     if !device_computed_certs_hash
         .data
         .as_bytes()
@@ -451,6 +903,8 @@ fn provision_certificates(
     // TODO(lowRISC/opentitan:#24281): Add CWT verifier
     let t0 = Instant::now();
     if !dice_cert_chain.is_empty() {
+        log::info!("Checking DICE certificate chain links up to the DICE CA ...");
+        check_cert_chain(&load_ca_cert(dice_ca_cert)?, &dice_cert_chain)?;
         log::info!("Validating DICE certificate chain with OpenSSL ...");
         validate_cert_chain(dice_ca_cert.to_str().unwrap(), &dice_cert_chain)?;
         log::info!("Success.");
@@ -460,68 +914,160 @@ fn provision_certificates(
     let t0 = Instant::now();
     if !sku_specific_certs.is_empty() {
         log::info!("Validating SKU-specific certificates with OpenSSL ...");
+        let ext_ca = load_ca_cert(ext_ca_cert)?;
         for sku_specific_cert in sku_specific_certs.iter() {
+            check_cert_chain(&ext_ca, std::slice::from_ref(sku_specific_cert))?;
             validate_cert_chain(ext_ca_cert.to_str().unwrap(), &[sku_specific_cert.clone()])?;
         }
         log::info!("Success.");
     }
     response.stats.log_elapsed_time("perso-validate-sku", t0);
-    Ok(())
+    Ok((export_options, creator_manuf_state))
 }
 
 #[allow(clippy::too_many_arguments)]
+#[deprecated(note = "use `FtProvisioner`, which checks its capabilities")]
 pub fn run_ft_personalize(
     transport: &TransportWrapper,
     init: &InitializeTest,
-    rma_unlock_token: &ArrayVec<u32, 4>,
+    rma_unlock_token: &LcTokenSecret,
     ca_cfgs: HashMap<String, CaConfig>,
     ca_keys: HashMap<String, CaKey>,
+    cert_policy: &SigningPolicy,
+    offline_certs: Option<&OfflineCerts>,
     perso_certgen_inputs: &ManufCertgenInputs,
+    export_options: PersoExportOptions,
+    creator_manuf_state: Option<CreatorManufState>,
+    keymgr_diversification: Option<&KeymgrDiversification>,
+    device_log_level: DeviceLogLevel,
+    entropy_check: &EntropyCheck,
     second_bootstrap: PathBuf,
-    spi_console: &SpiConsoleDevice,
-    timeout: Duration,
+    console: &dyn ConsoleDevice,
+    timeouts: &Timeouts,
+    retry: &RetryPolicy,
+    cmd_ids: &CommandIds,
+    journal: &StepJournal,
     response: &mut PersonalizeResponse,
 ) -> Result<()> {
     // Bootstrap only personalization binary into ROM_EXT slot A in flash.
+    journal.enter("personalize", "first-bootstrap")?;
+    let events = journal.events();
     let t0 = Instant::now();
-    init.bootstrap.init(transport)?;
+    events.time("bootstrap", || init.bootstrap.init(transport))?;
     response.stats.log_elapsed_time("first-bootstrap", t0);
+    send_log_level(
+        console,
+        device_log_level,
+        timeouts.bootstrap_boot,
+        retry,
+        cmd_ids,
+    )?;
+    journal.events().command_sent("log-level");
+    response
+        .stats
+        .log_string("device-log-level", device_log_level.name());
 
     // Bootstrap personalization + ROM_EXT + Owner FW binaries into flash, since
     // flash scrambling seeds were provisioned in the previous step.
     let t0 = Instant::now();
-    let _ = UartConsole::wait_for(spi_console, r"Bootstrap requested.", timeout)?;
+    let _ = events.time("otp-write-flash-seeds", || {
+        retry.wait_for(console, r"Bootstrap requested.", timeouts.otp_write)
+    })?;
     response.stats.log_elapsed_time("first-bootstrap-done", t0);
 
+    journal.enter("personalize", "second-bootstrap")?;
     let t0 = Instant::now();
-    init.bootstrap.load(transport, &second_bootstrap)?;
+    events.time("bootstrap", || {
+        init.bootstrap.load(transport, &second_bootstrap)
+    })?;
     response.stats.log_elapsed_time("second-bootstrap", t0);
 
     // Send RMA unlock token digest to device.
+    journal.enter("personalize", "rma-unlock-token")?;
     let second_t0 = Instant::now();
     let t0 = second_t0;
-    send_rma_unlock_token_hash(rma_unlock_token, timeout, spi_console)?;
+    // The answer to the log level of the first boot may still be buffered.
+    send_log_level(
+        console,
+        device_log_level,
+        timeouts.bootstrap_boot,
+        retry,
+        cmd_ids,
+    )?;
+    journal.events().command_sent("log-level");
+    events.time("perso-data-exchange-rma-unlock-token", || {
+        send_rma_unlock_token_hash(
+            rma_unlock_token,
+            timeouts.perso_data_exchange,
+            retry,
+            console,
+        )
+    })?;
+    journal.events().command_sent("rma-unlock-token-hash");
     response.stats.log_elapsed_time("send-rma-unlock-token", t0);
 
     // Provision all device certificates.
+    journal.enter("personalize", "certificates")?;
     let t0 = Instant::now();
-    provision_certificates(
-        ca_cfgs,
-        ca_keys,
-        perso_certgen_inputs,
-        timeout,
-        spi_console,
-        response,
-    )?;
+    let (export_options, creator_manuf_state) =
+        events.time("perso-data-exchange-certificates", || {
+            provision_certificates(
+                ca_cfgs,
+                ca_keys,
+                cert_policy,
+                offline_certs,
+                perso_certgen_inputs,
+                export_options,
+                creator_manuf_state,
+                keymgr_diversification,
+                entropy_check,
+                timeouts.perso_data_exchange,
+                retry,
+                cmd_ids,
+                console,
+                events,
+                response,
+            )
+        })?;
     response.stats.log_elapsed_time("perso-all-certs-done", t0);
 
-    let _ = UartConsole::wait_for(spi_console, r"Personalization done.", timeout)?;
+    verify_creator_manuf_state(
+        console,
+        &creator_manuf_state,
+        timeouts.perso_data_exchange,
+        retry,
+    )?;
+    response.stats.log_string(
+        "creator-manuf-state",
+        &format!("{:#010x}", creator_manuf_state.value),
+    );
+
+    if export_options.health_snapshot {
+        let t0 = Instant::now();
+        response.health = Some(HealthSnapshot::recv(
+            console,
+            timeouts.perso_data_exchange,
+            retry,
+        )?);
+        journal.events().status_received("health-snapshot");
+        response.stats.log_elapsed_time("perso-health-snapshot", t0);
+    }
+
+    let _ = events.time("perso-finish", || {
+        retry.wait_for(
+            console,
+            r"Personalization done.",
+            timeouts.perso_data_exchange,
+        )
+    })?;
+    events.status_received("personalize-done");
     response
         .stats
         .log_elapsed_time("second-bootstrap-done", second_t0);
     Ok(())
 }
 
+#[deprecated(note = "use `FtProvisioner::check_slot_b_boot_up`, which refuses untimely resets")]
 pub fn check_slot_b_boot_up(
     transport: &TransportWrapper,
     init: &InitializeTest,
@@ -531,7 +1077,7 @@ pub fn check_slot_b_boot_up(
 ) -> Result<()> {
     transport.reset_target(init.bootstrap.options.reset_delay, true)?;
     let uart_console = transport.uart("console")?;
-    let result = UartConsole::wait_for(&*uart_console, r"ROM_EXT:(.*)\r\n", timeout)?;
+    let result = wait_for(&*uart_console, r"ROM_EXT:(.*)\r\n", timeout)?;
     response.stats.log_string(
         "rom_ext-version",
         result
@@ -561,18 +1107,24 @@ pub fn check_slot_b_boot_up(
         rom_ext_failure_msg.to_string()
     };
 
-    let result =
-        UartConsole::wait_for(&*uart_console, anchor_text.as_str(), slot_b_startup_timeout);
+    let result = wait_for(&*uart_console, anchor_text.as_str(), slot_b_startup_timeout);
 
     match result {
         Ok(captures) => {
             if captures[0] == *rom_ext_failure_msg {
                 // Error message found.
-                bail!("Invalid UDS certificate detected!");
+                return Err(
+                    ProvisioningError::DeviceStatus(rom_ext_failure_msg.to_string()).into(),
+                );
             }
         }
         Err(e) => {
-            if owner_fw_success_string.is_none() && e.to_string().contains("Timed Out") {
+            if owner_fw_success_string.is_none()
+                && matches!(
+                    ProvisioningError::find(&e),
+                    Some(ProvisioningError::Timeout { .. })
+                )
+            {
                 // Error message not found after timeout. This is the expected behavior.
             } else {
                 // An unexpected error occurred while waiting for the console output.
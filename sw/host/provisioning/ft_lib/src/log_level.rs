@@ -0,0 +1,86 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::time::Duration;
+
+use anyhow::{bail, ensure, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use opentitanlib::io::console::ConsoleDevice;
+use opentitanlib::test_utils::rpc::ConsoleSend;
+use ujson_lib::provisioning_data::ManufLogLevel;
+
+use crate::command_id::{recv_answer, CommandIds};
+use crate::retry::RetryPolicy;
+
+/// Verbosity of the console logs of the personalization firmware.
+///
+/// The lines the host synchronizes on are logged at every level.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeviceLogLevel {
+    /// Log all lines, e.g. in the lab.
+    #[default]
+    Verbose,
+    /// Log warnings and errors only.
+    Warnings,
+    /// Log errors only, e.g. on production lines.
+    Errors,
+}
+
+impl DeviceLogLevel {
+    /// Returns the minimum `log_severity_t` the device logs at this level.
+    ///
+    /// The values must be kept in sync with `log_severity_t` in sw/device/lib/runtime/log.h.
+    pub fn min_severity(self) -> u32 {
+        match self {
+            Self::Verbose => 0,
+            Self::Warnings => 1,
+            Self::Errors => 2,
+        }
+    }
+
+    pub fn from_min_severity(min_severity: u32) -> Result<Self> {
+        Ok(match min_severity {
+            0 => Self::Verbose,
+            1 => Self::Warnings,
+            2 => Self::Errors,
+            _ => bail!("Unknown device log severity {min_severity}"),
+        })
+    }
+
+    /// Returns the name of this level on the command line.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::Verbose => "verbose",
+            Self::Warnings => "warnings",
+            Self::Errors => "errors",
+        }
+    }
+}
+
+/// Sets the log level of the personalization firmware for the rest of its current boot.
+pub(crate) fn send_log_level(
+    console: &dyn ConsoleDevice,
+    level: DeviceLogLevel,
+    timeout: Duration,
+    retry: &RetryPolicy,
+    cmd_ids: &CommandIds,
+) -> Result<()> {
+    let _ = retry.wait_for(console, r"Waiting for log level ...", timeout)?;
+    let cmd_id = cmd_ids.next();
+    ManufLogLevel {
+        cmd_id,
+        min_severity: level.min_severity(),
+    }
+    .send(console)?;
+    let applied: ManufLogLevel = recv_answer(console, cmd_id, timeout, retry)?;
+    let applied = DeviceLogLevel::from_min_severity(applied.min_severity)?;
+    ensure!(
+        applied == level,
+        "Device applied log level {applied:?} instead of {level:?}"
+    );
+    Ok(())
+}
@@ -0,0 +1,115 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::time::Duration;
+
+use anyhow::{ensure, Result};
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use opentitanlib::dif::lc_ctrl::DifLcCtrlState;
+use opentitanlib::io::console::ConsoleDevice;
+use opentitanlib::test_utils::rpc::ConsoleSend;
+use ujson_lib::provisioning_data::ManufCreatorManufState;
+
+use crate::command_id::{recv_answer, CommandIds};
+use crate::retry::RetryPolicy;
+
+/// Marker provisioned in CREATOR_SW_CFG_MANUF_STATE at the end of personalization.
+///
+/// The values must be kept in sync with `CONST.MANUF_STATE` in rules/const.bzl.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CreatorManufState {
+    /// Pre-production device, in the DEV LC state.
+    PreProduction,
+    /// Production device, in the PROD or PROD_END LC states.
+    Production,
+}
+
+impl CreatorManufState {
+    /// Returns the CREATOR_SW_CFG_MANUF_STATE value of this marker.
+    pub fn value(self) -> u32 {
+        match self {
+            // ASCII `PREP`.
+            Self::PreProduction => 0x5045_5250,
+            // ASCII `PERS`.
+            Self::Production => 0x5352_4550,
+        }
+    }
+
+    /// Returns the mission mode LC states a device with this marker may be in.
+    pub fn lc_states(self) -> &'static [DifLcCtrlState] {
+        match self {
+            Self::PreProduction => &[DifLcCtrlState::Dev],
+            Self::Production => &[DifLcCtrlState::Prod, DifLcCtrlState::ProdEnd],
+        }
+    }
+
+    /// Checks a device in `lc_state` may be provisioned with this marker.
+    pub fn check_lc_state(self, lc_state: DifLcCtrlState) -> Result<()> {
+        ensure!(
+            self.lc_states().contains(&lc_state),
+            "Creator manufacturing state {self:?} is not allowed in LC state {lc_state}, expected one of {:?}",
+            self.lc_states()
+        );
+        Ok(())
+    }
+}
+
+/// Sends the `requested` creator manufacturing state to the device, or zero to provision the value
+/// of the SKU OTP image, and returns the answer of the device, with the value it will provision.
+pub(crate) fn send_creator_manuf_state(
+    console: &dyn ConsoleDevice,
+    requested: Option<CreatorManufState>,
+    timeout: Duration,
+    retry: &RetryPolicy,
+    cmd_ids: &CommandIds,
+) -> Result<ManufCreatorManufState> {
+    let _ = retry.wait_for(
+        console,
+        r"Waiting for creator manufacturing state ...",
+        timeout,
+    )?;
+    let cmd_id = cmd_ids.next();
+    ManufCreatorManufState {
+        cmd_id,
+        value: requested.map_or(0, CreatorManufState::value),
+    }
+    .send(console)?;
+    let accepted: ManufCreatorManufState = recv_answer(console, cmd_id, timeout, retry)?;
+    if let Some(requested) = requested {
+        ensure!(
+            accepted.value == requested.value(),
+            "Device will provision creator manufacturing state {:#010x} instead of {requested:?}",
+            accepted.value
+        );
+    }
+    Ok(accepted)
+}
+
+/// Checks the creator manufacturing state read back by the device once the CREATOR_SW_CFG
+/// partition is locked is the `expected` value the device answered `send_creator_manuf_state`
+/// with.
+pub(crate) fn verify_creator_manuf_state(
+    console: &dyn ConsoleDevice,
+    expected: &ManufCreatorManufState,
+    timeout: Duration,
+    retry: &RetryPolicy,
+) -> Result<()> {
+    let _ = retry.wait_for(
+        console,
+        r"Exporting creator manufacturing state ...",
+        timeout,
+    )?;
+    let provisioned: ManufCreatorManufState =
+        recv_answer(console, expected.cmd_id, timeout, retry)?;
+    ensure!(
+        provisioned.value == expected.value,
+        "Creator manufacturing state reads back as {:#010x}, expected {:#010x}",
+        provisioned.value,
+        expected.value
+    );
+    Ok(())
+}
@@ -0,0 +1,562 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::fmt::{self, Write};
+use std::time::Duration;
+
+use anyhow::{bail, ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use opentitanlib::app::TransportWrapper;
+use opentitanlib::io::console::ConsoleDevice;
+use opentitanlib::io::jtag::{JtagParams, JtagTap};
+use opentitanlib::otp::lc_state::LcSecded;
+use opentitanlib::test_utils::load_sram_program::{
+    ExecutionMode, ExecutionResult, SramProgramParams,
+};
+use ujson_lib::provisioning_data::ManufOtpDumpChunk;
+
+use crate::error::ProvisioningError;
+use crate::retry::RetryPolicy;
+
+/// Names of the OTP partitions dumped by the OTP dump SRAM program, indexed by the `partition`
+/// field of each dump chunk.
+///
+/// Must be kept in sync with `kDumpPartitions` in sram_otp_dump.c.
+pub const OTP_DUMP_PARTITIONS: [&str; 7] = [
+    "VENDOR_TEST",
+    "CREATOR_SW_CFG",
+    "OWNER_SW_CFG",
+    "ROT_CREATOR_AUTH_CODESIGN",
+    "ROT_CREATOR_AUTH_STATE",
+    "HW_CFG0",
+    "HW_CFG1",
+];
+
+/// Upper bound on the number of chunks in a dump, guarding against a runaway device.
+const MAX_DUMP_CHUNKS: usize = 256;
+
+/// OTP memory map, as described in hw/top_earlgrey/data/otp/otp_ctrl_mmap.hjson.
+#[derive(Debug, Deserialize)]
+struct OtpMmap {
+    partitions: Vec<OtpMmapPartition>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OtpMmapPartition {
+    name: String,
+    #[serde(default)]
+    items: Vec<OtpMmapItem>,
+}
+
+#[derive(Debug, Deserialize)]
+struct OtpMmapItem {
+    name: String,
+    size: String,
+}
+
+impl OtpMmap {
+    /// Returns the OTP memory map the host tools were built against.
+    fn embedded() -> Result<Self> {
+        deser_hjson::from_str(include_str!(env!("otp_mmap"))).context("Invalid OTP memory map")
+    }
+
+    /// Returns the `(name, offset, size)` of the items of `partition`, in bytes.
+    fn items(&self, partition: &str) -> Result<Vec<(String, usize, usize)>> {
+        let Some(part) = self.partitions.iter().find(|p| p.name == partition) else {
+            bail!("Partition {partition} is not in the OTP memory map");
+        };
+        let mut offset = 0;
+        let mut items = Vec::new();
+        for item in &part.items {
+            let size: usize = item
+                .size
+                .parse()
+                .with_context(|| format!("Invalid size of OTP item {}", item.name))?;
+            items.push((item.name.clone(), offset, size));
+            offset += size;
+        }
+        Ok(items)
+    }
+}
+
+/// A named field of a dumped OTP partition.
+#[derive(Clone, Debug, Serialize)]
+pub struct OtpFieldDump {
+    pub name: String,
+    /// Byte offset of the field from the start of the partition.
+    pub offset: usize,
+    /// Size of the field in bytes.
+    pub size: usize,
+    /// Field value as a little-endian integer, for fields of up to 64 bits.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<u64>,
+    /// Field contents as 32-bit words, for larger word-aligned fields.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub words: Vec<u32>,
+    /// Field contents as a hex string, in OTP byte order.
+    pub bytes: String,
+}
+
+/// A dumped OTP partition.
+#[derive(Clone, Debug, Serialize)]
+pub struct OtpPartitionDump {
+    pub name: String,
+    /// Absolute OTP byte address of the start of the partition.
+    pub address: u32,
+    /// Partition digest; zero while the partition is not locked.
+    pub digest: u64,
+    /// Raw partition contents, excluding the digest.
+    pub words: Vec<u32>,
+    /// Partition contents decoded into the fields of the OTP memory map.
+    pub fields: Vec<OtpFieldDump>,
+}
+
+/// Decoded contents of all readable OTP partitions, as archived for failure analysis.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct OtpDump {
+    pub partitions: Vec<OtpPartitionDump>,
+}
+
+impl OtpPartitionDump {
+    fn decode(
+        name: &str,
+        address: u32,
+        digest: u64,
+        words: Vec<u32>,
+        mmap: &OtpMmap,
+    ) -> Result<Self> {
+        let bytes: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+        let mut fields = Vec::new();
+        for (item, offset, size) in mmap.items(name)? {
+            let Some(data) = bytes.get(offset..offset + size) else {
+                bail!(
+                    "OTP item {item} [{offset}+:{size}] exceeds the {} dumped bytes of {name}",
+                    bytes.len()
+                );
+            };
+            let value = (size <= 8).then(|| {
+                data.iter()
+                    .rev()
+                    .fold(0u64, |acc, byte| (acc << 8) | *byte as u64)
+            });
+            let words = if size > 8 && offset % 4 == 0 && size % 4 == 0 {
+                data.chunks(4)
+                    .map(|w| u32::from_le_bytes(w.try_into().unwrap()))
+                    .collect()
+            } else {
+                Vec::new()
+            };
+            fields.push(OtpFieldDump {
+                name: item,
+                offset,
+                size,
+                value,
+                words,
+                bytes: hex::encode(data),
+            });
+        }
+        Ok(OtpPartitionDump {
+            name: name.into(),
+            address,
+            digest,
+            words,
+            fields,
+        })
+    }
+
+    pub fn is_locked(&self) -> bool {
+        self.digest != 0
+    }
+
+    /// Returns the partition contents followed by the digest, in OTP byte order.
+    fn bytes(&self) -> Vec<u8> {
+        self.words
+            .iter()
+            .flat_map(|w| w.to_le_bytes())
+            .chain(self.digest.to_le_bytes())
+            .collect()
+    }
+
+    /// Returns the annotation of each byte of `bytes()`, in the format used by the OTP image
+    /// tooling.
+    fn annotations(&self) -> Vec<String> {
+        let mut annotations = vec!["unallocated".to_string(); self.words.len() * 4];
+        for field in &self.fields {
+            for annotation in &mut annotations[field.offset..field.offset + field.size] {
+                *annotation = format!("{}: {}", self.name, field.name);
+            }
+        }
+        annotations.resize(annotations.len() + 8, format!("{0}: {0}_DIGEST", self.name));
+        annotations
+    }
+}
+
+impl OtpDump {
+    /// Reassembles and decodes the chunks received from the OTP dump SRAM program.
+    pub fn decode(chunks: &[ManufOtpDumpChunk]) -> Result<Self> {
+        let mmap = OtpMmap::embedded()?;
+        let mut dump = OtpDump::default();
+        let mut current: Option<(usize, u32, u64, Vec<u32>)> = None;
+        for chunk in chunks {
+            let index = chunk.partition as usize;
+            ensure!(
+                index < OTP_DUMP_PARTITIONS.len(),
+                "Unknown OTP dump partition index {index}"
+            );
+            ensure!(
+                chunk.offset % 4 == 0 && chunk.num_words as usize <= chunk.data.len(),
+                "Malformed OTP dump chunk: {chunk:?}"
+            );
+            match &mut current {
+                Some((i, address, _, words)) if *i == index => {
+                    ensure!(
+                        chunk.address == *address,
+                        "OTP dump chunk of {} at offset {:#x} has inconsistent address {:#x}",
+                        OTP_DUMP_PARTITIONS[index],
+                        chunk.offset,
+                        chunk.address
+                    );
+                    ensure!(
+                        chunk.offset as usize == words.len() * 4,
+                        "OTP dump chunk of {} at offset {:#x} is out of order",
+                        OTP_DUMP_PARTITIONS[index],
+                        chunk.offset
+                    );
+                }
+                _ => {
+                    if let Some((i, address, digest, words)) = current.take() {
+                        dump.partitions.push(OtpPartitionDump::decode(
+                            OTP_DUMP_PARTITIONS[i],
+                            address,
+                            digest,
+                            words,
+                            &mmap,
+                        )?);
+                    }
+                    ensure!(
+                        chunk.offset == 0,
+                        "OTP dump of {} does not start at offset 0",
+                        OTP_DUMP_PARTITIONS[index]
+                    );
+                    ensure!(
+                        chunk.address % 8 == 0,
+                        "OTP dump of {} starts at misaligned address {:#x}",
+                        OTP_DUMP_PARTITIONS[index],
+                        chunk.address
+                    );
+                    current = Some((index, chunk.address, chunk.digest, Vec::new()));
+                }
+            }
+            if let Some((_, _, _, words)) = &mut current {
+                words.extend_from_slice(&chunk.data[..chunk.num_words as usize]);
+            }
+        }
+        if let Some((i, address, digest, words)) = current {
+            dump.partitions.push(OtpPartitionDump::decode(
+                OTP_DUMP_PARTITIONS[i],
+                address,
+                digest,
+                words,
+                &mmap,
+            )?);
+        }
+        Ok(dump)
+    }
+
+    /// Exports the dumped partitions, including their digests, as an OTP MEM file with ECC.
+    ///
+    /// The format matches the images generated by util/design/gen-otp-img.py, so the file can be
+    /// loaded into simulation with `$readmemh`. Only the dumped partitions are present; the
+    /// secret and life cycle partitions are not readable and are left out.
+    pub fn to_otp_vmem(&self) -> Result<String> {
+        let secded: LcSecded = include_str!(env!("lc_ctrl_state")).parse()?;
+        let word_size = secded.data_byte_len();
+        let mut num_words = 0;
+        let mut lines = String::new();
+        for part in &self.partitions {
+            let bytes = part.bytes();
+            let annotations = part.annotations();
+            for (i, word) in bytes.chunks(word_size).enumerate() {
+                let mut encoded = secded.ecc_encode(word.to_vec())?;
+                encoded.reverse();
+                let mut word_annotations = annotations[i * word_size..(i + 1) * word_size].to_vec();
+                word_annotations.sort();
+                word_annotations.dedup();
+                writeln!(
+                    lines,
+                    "@{:06x} {} // {}",
+                    part.address as usize / word_size + i,
+                    hex::encode(encoded),
+                    word_annotations.join(", ")
+                )?;
+                num_words += 1;
+            }
+        }
+        Ok(format!(
+            "// OTP MEM file with {} x {}bit layout\n{lines}",
+            num_words,
+            (word_size + secded.ecc_byte_len()) * 8
+        ))
+    }
+
+    /// Exports the dumped partitions, including their digests, as 32-bit words without ECC.
+    ///
+    /// Addresses are absolute OTP word addresses, readable by `opentitanlib::util::vmem::Vmem`.
+    pub fn to_hex_vmem(&self) -> Result<String> {
+        let mut vmem = String::new();
+        for part in &self.partitions {
+            writeln!(vmem, "// {}", part.name)?;
+            for (i, word) in part.bytes().chunks(4).enumerate() {
+                let word = u32::from_le_bytes(word.try_into().unwrap());
+                writeln!(vmem, "@{:08x} {:08x}", part.address as usize / 4 + i, word)?;
+            }
+        }
+        Ok(vmem)
+    }
+}
+
+impl fmt::Display for OtpDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for part in &self.partitions {
+            writeln!(
+                f,
+                "{} ({} bytes, {}, digest {:#018x})",
+                part.name,
+                part.words.len() * 4,
+                if part.is_locked() {
+                    "locked"
+                } else {
+                    "unlocked"
+                },
+                part.digest
+            )?;
+            for field in &part.fields {
+                write!(f, "  [{:#05x}] {:<48}", field.offset, field.name)?;
+                if let Some(value) = field.value {
+                    writeln!(f, " {:#0width$x}", value, width = 2 + 2 * field.size)?;
+                } else if !field.words.is_empty() {
+                    writeln!(f)?;
+                    for (i, line) in field.words.chunks(4).enumerate() {
+                        let line: Vec<String> = line.iter().map(|w| format!("{w:08x}")).collect();
+                        writeln!(f, "    +{:#05x}: {}", i * 16, line.join(" "))?;
+                    }
+                } else {
+                    writeln!(f, " {}", field.bytes)?;
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Loads the OTP dump SRAM program and collects the dump of all readable OTP partitions.
+///
+/// Unless `reset_target` is set, the device is not reset before the CPU is halted over JTAG, e.g.
+/// once ROM execution is enabled in OTP: the TAP straps are sampled continuously in the
+/// `TEST_UNLOCKED*` LC states.
+#[allow(clippy::too_many_arguments)]
+pub fn run_sram_otp_dump(
+    transport: &TransportWrapper,
+    jtag_params: &JtagParams,
+    reset_delay: Duration,
+    reset_target: bool,
+    sram_program: &SramProgramParams,
+    timeout: Duration,
+    retry: &RetryPolicy,
+    console: &dyn ConsoleDevice,
+) -> Result<OtpDump> {
+    // Set CPU TAP straps, reset, and connect to the JTAG interface.
+    transport.pin_strapping("PINMUX_TAP_RISCV")?.apply()?;
+    if reset_target {
+        transport.reset_target(reset_delay, true)?;
+    }
+    let mut jtag = retry.connect_jtag(transport, jtag_params, JtagTap::RiscvTap)?;
+
+    // Reset and halt the CPU to ensure we are in a known state, and clear out any ROM messages
+    // printed over the console.
+    jtag.reset(/*run=*/ false)?;
+
+    // Load and execute the SRAM program that dumps OTP.
+    let result = sram_program.load_and_execute(&mut *jtag, ExecutionMode::Jump)?;
+    match result {
+        ExecutionResult::Executing => log::info!("SRAM program loaded and is executing."),
+        _ => return Err(ProvisioningError::SramLoadFailed(format!("{result:?}")).into()),
+    }
+    // Free the JTAG adapter for consoles reading over the debug module, e.g. `DmiConsoleDevice`.
+    jtag.disconnect()?;
+
+    let _ = retry.wait_for(console, r"Dumping OTP partitions ...", timeout)?;
+    let mut chunks = Vec::new();
+    loop {
+        ensure!(
+            chunks.len() < MAX_DUMP_CHUNKS,
+            "OTP dump exceeds {MAX_DUMP_CHUNKS} chunks"
+        );
+        let chunk: ManufOtpDumpChunk = retry.recv(console, timeout, true)?;
+        let last = chunk.last;
+        chunks.push(chunk);
+        if last {
+            break;
+        }
+    }
+    let _ = retry.wait_for(console, r"OTP dump done.", timeout)?;
+
+    transport.pin_strapping("PINMUX_TAP_RISCV")?.remove()?;
+
+    OtpDump::decode(&chunks)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn mmap(items: &[(&str, usize)]) -> OtpMmap {
+        OtpMmap {
+            partitions: vec![OtpMmapPartition {
+                name: "TEST".into(),
+                items: items
+                    .iter()
+                    .map(|(name, size)| OtpMmapItem {
+                        name: name.to_string(),
+                        size: size.to_string(),
+                    })
+                    .collect(),
+            }],
+        }
+    }
+
+    fn chunk(
+        partition: u32,
+        address: u32,
+        offset: u32,
+        words: &[u32],
+        last: bool,
+    ) -> ManufOtpDumpChunk {
+        serde_json::from_value(json!({
+            "partition": partition,
+            "address": address,
+            "offset": offset,
+            "num_words": words.len(),
+            "data": words,
+            "digest": 0,
+            "last": last,
+        }))
+        .unwrap()
+    }
+
+    /// The chunks of a dump of the 56-byte `VENDOR_TEST` partition.
+    fn vendor_test_chunks() -> Vec<ManufOtpDumpChunk> {
+        let words: Vec<u32> = (0..14).collect();
+        vec![
+            chunk(0, 0, 0, &words[..8], false),
+            chunk(0, 0, 32, &words[8..], true),
+        ]
+    }
+
+    #[test]
+    fn test_partition_decode() {
+        let mmap = mmap(&[("A", 4), ("B", 1), ("C", 3), ("D", 16)]);
+        let words = vec![0x04030201, 0x08070605, 0x0c0b0a09, 0, 0, 0x100f0e0d];
+        let part = OtpPartitionDump::decode("TEST", 0x40, 0, words.clone(), &mmap).unwrap();
+        assert!(!part.is_locked());
+        let fields: Vec<_> = part
+            .fields
+            .iter()
+            .map(|f| (f.name.as_str(), f.offset, f.size, f.value, f.words.clone()))
+            .collect();
+        assert_eq!(
+            fields,
+            [
+                ("A", 0, 4, Some(0x04030201), vec![]),
+                ("B", 4, 1, Some(0x05), vec![]),
+                ("C", 5, 3, Some(0x080706), vec![]),
+                ("D", 8, 16, None, vec![0x0c0b0a09, 0, 0, 0x100f0e0d]),
+            ]
+        );
+        assert_eq!(part.fields[2].bytes, "060708");
+
+        let err =
+            OtpPartitionDump::decode("TEST", 0x40, 0, words[..4].to_vec(), &mmap).unwrap_err();
+        assert!(
+            err.to_string().contains("OTP item D [8+:16] exceeds"),
+            "{err}"
+        );
+        let err = OtpPartitionDump::decode("OTHER", 0x40, 0, words, &mmap).unwrap_err();
+        assert!(
+            err.to_string().contains("not in the OTP memory map"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_decode() {
+        let dump = OtpDump::decode(&vendor_test_chunks()).unwrap();
+        assert_eq!(dump.partitions.len(), 1);
+        let part = &dump.partitions[0];
+        assert_eq!(part.name, "VENDOR_TEST");
+        assert_eq!(part.words, (0..14).collect::<Vec<u32>>());
+        assert_eq!(part.fields.len(), 1);
+        assert_eq!(part.fields[0].name, "SCRATCH");
+        assert_eq!(part.fields[0].words, part.words);
+    }
+
+    #[test]
+    fn test_decode_malformed() {
+        let malformed = [
+            (
+                "Unknown OTP dump partition",
+                vec![chunk(7, 0, 0, &[0], true)],
+            ),
+            (
+                "does not start at offset 0",
+                vec![chunk(0, 0, 4, &[0], true)],
+            ),
+            ("misaligned address", vec![chunk(0, 4, 0, &[0], true)]),
+            (
+                "is out of order",
+                vec![chunk(0, 0, 0, &[0], false), chunk(0, 0, 8, &[0], true)],
+            ),
+            (
+                "inconsistent address",
+                vec![chunk(0, 0, 0, &[0], false), chunk(0, 8, 4, &[0], true)],
+            ),
+        ];
+        for (expected, chunks) in malformed {
+            let err = OtpDump::decode(&chunks).unwrap_err();
+            assert!(err.to_string().contains(expected), "{err}");
+        }
+    }
+
+    #[test]
+    fn test_to_hex_vmem() {
+        let vmem = OtpDump::decode(&vendor_test_chunks())
+            .unwrap()
+            .to_hex_vmem()
+            .unwrap();
+        let lines: Vec<&str> = vmem.lines().collect();
+        // The contents, followed by the two words of the digest.
+        assert_eq!(lines.len(), 1 + 14 + 2);
+        assert_eq!(lines[0], "// VENDOR_TEST");
+        assert_eq!(lines[1], "@00000000 00000000");
+        assert_eq!(lines[14], "@0000000d 0000000d");
+        assert_eq!(lines[16], "@0000000f 00000000");
+    }
+
+    #[test]
+    fn test_to_otp_vmem() {
+        let chunks = [chunk(0, 0, 0, &[0; 14], true)];
+        let vmem = OtpDump::decode(&chunks).unwrap().to_otp_vmem().unwrap();
+        let lines: Vec<&str> = vmem.lines().collect();
+        // 16-bit words with 6 ECC bits: the 56 bytes of contents and the 8-byte digest.
+        assert_eq!(lines.len(), 1 + 32);
+        assert_eq!(lines[0], "// OTP MEM file with 32 x 22bit layout");
+        assert_eq!(lines[1], "@000000 000000 // VENDOR_TEST: SCRATCH");
+        assert_eq!(
+            lines[32],
+            "@00001f 000000 // VENDOR_TEST: VENDOR_TEST_DIGEST"
+        );
+    }
+}
@@ -0,0 +1,123 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! FPGA fast path of the FT individualize step.
+//!
+//! Instead of running the FT individualize SRAM program, the FPGA is loaded with a bitstream whose
+//! OTP image already holds the individualized partitions, e.g. a `bitstream_splice` of an
+//! `otp_image` of the SKU in `TEST_UNLOCKED1`. This skips the OTP programming when iterating on
+//! the host logic of the later FT steps; the device is then checked to hold the device ID and
+//! locked partitions the SRAM program would have programmed.
+
+use std::path::Path;
+use std::time::Duration;
+
+use anyhow::{bail, ensure, Context, Result};
+use clap::ValueEnum;
+
+use opentitanlib::app::{StagedProgressBar, TransportWrapper};
+use opentitanlib::dif::otp_ctrl::{DaiParam, Partition};
+use opentitanlib::io::jtag::{JtagParams, JtagTap};
+use opentitanlib::test_utils::load_bitstream::LoadBitstream;
+use opentitanlib::test_utils::otp_ctrl::{OtpParam, OtpPartition};
+use opentitanlib::transport::common::fpga::FpgaProgram;
+use ujson_lib::provisioning_data::ManufFtIndividualizeData;
+
+use crate::alert_cfg::AlertCfg;
+use crate::retry::RetryPolicy;
+use crate::IndividualizePartition;
+
+/// Returns the OTP partitions holding `partition`, if they can be checked over the DAI.
+fn otp_partitions(partition: IndividualizePartition) -> &'static [Partition] {
+    match partition {
+        IndividualizePartition::HwCfg => &[Partition::HW_CFG0],
+        IndividualizePartition::CreatorSwCfg => &[Partition::CREATOR_SW_CFG],
+        IndividualizePartition::OwnerSwCfg => &[Partition::OWNER_SW_CFG],
+        // The ROT_CREATOR_AUTH_* partitions have no DAI description yet.
+        IndividualizePartition::RotCreatorAuthCodesign
+        | IndividualizePartition::RotCreatorAuthState => &[],
+    }
+}
+
+/// Loads `bitstream`, whose OTP image holds the individualized partitions, and checks the device
+/// holds the device ID and locked partitions selected in `ft_individualize_data_in`.
+///
+/// The CPU is left halted, as after the FT individualize SRAM program.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn preload_individualized_otp(
+    transport: &TransportWrapper,
+    load_bitstream: &LoadBitstream,
+    jtag_params: &JtagParams,
+    reset_delay: Duration,
+    bitstream: &Path,
+    ft_individualize_data_in: &ManufFtIndividualizeData,
+    alert_cfg: &AlertCfg,
+    retry: &RetryPolicy,
+) -> Result<()> {
+    // The OTP image is fixed at splice time, so per-device data can't be preloaded.
+    ensure!(
+        ft_individualize_data_in
+            .ast_trim_mask
+            .iter()
+            .all(|&w| w == 0),
+        "AST trim data can't be provisioned by OTP preload."
+    );
+    ensure!(
+        *alert_cfg == AlertCfg::default(),
+        "Alert configuration can't be provisioned by OTP preload."
+    );
+
+    // Always program the FPGA, even with the bitstream already loaded: the previous device may
+    // have left its OTP image in another LC state.
+    log::info!("Preloading individualized OTP from bitstream {bitstream:?}");
+    let payload = std::fs::read(bitstream)
+        .with_context(|| format!("Failed to read bitstream {bitstream:?}"))?;
+    transport.dispatch(&FpgaProgram {
+        bitstream: payload,
+        rom_reset_pulse: load_bitstream.rom_reset_pulse,
+        rom_timeout: load_bitstream.rom_timeout,
+        progress: Box::new(StagedProgressBar::new()),
+    })?;
+
+    // Set CPU TAP straps, reset, and halt the CPU before the ROM boots.
+    transport.pin_strapping("PINMUX_TAP_RISCV")?.apply()?;
+    transport.reset_target(reset_delay, true)?;
+    let mut jtag = retry.connect_jtag(transport, jtag_params, JtagTap::RiscvTap)?;
+    jtag.reset(/*run=*/ false)?;
+
+    let mut otp_device_id = [0u32; 8];
+    OtpParam::read_param(&mut *jtag, DaiParam::DeviceId, &mut otp_device_id)
+        .context("failed to read DEVICE_ID from OTP")?;
+    let mut unlocked = Vec::new();
+    for partition in IndividualizePartition::value_variants() {
+        if ft_individualize_data_in.partitions & partition.bit() == 0 {
+            continue;
+        }
+        for otp_partition in otp_partitions(*partition) {
+            let digest = OtpPartition::read_digest(&mut *jtag, otp_partition.clone())
+                .with_context(|| format!("failed to read {partition:?} partition digest"))?;
+            if digest == [0u32; 2] {
+                unlocked.push(*partition);
+            }
+        }
+    }
+
+    jtag.disconnect()?;
+    transport.pin_strapping("PINMUX_TAP_RISCV")?.remove()?;
+
+    if !unlocked.is_empty() {
+        bail!("OTP preload left partitions {unlocked:?} unlocked.");
+    }
+    if ft_individualize_data_in.partitions & IndividualizePartition::HwCfg.bit() != 0
+        && otp_device_id != ft_individualize_data_in.device_id.as_slice()
+    {
+        bail!(
+            "Preloaded device ID ({:x?}) does not match the provided device ID ({:x?}).",
+            otp_device_id,
+            ft_individualize_data_in.device_id.as_slice()
+        );
+    }
+    log::info!("Individualized OTP preloaded.");
+    Ok(())
+}
@@ -0,0 +1,423 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Pass criteria of a SKU: an expression over the fields of the FT result deciding the final
+//! verdict of a flow, so that the pass policy of a SKU changes without code changes, e.g.:
+//!
+//! ```text
+//! all_steps_ok AND cert_chain_valid AND lc_state == PROD AND certs >= 4
+//! ```
+//!
+//! Expressions combine comparisons (`==`, `!=`, `<`, `<=`, `>`, `>=`) of fields, strings, numbers
+//! and `true` / `false` with `AND`, `OR`, `NOT` (or `&&`, `||`, `!`) and parentheses. LC state
+//! names, e.g. `PROD` or `dev`, stand for themselves. The fields are listed in
+//! `FlowResult::criteria_fields`; criteria referring to unknown fields are rejected when parsed,
+//! so that a misspelled field can't go unnoticed in an operand `AND` or `OR` don't evaluate.
+//!
+//! The criteria only decide the verdict of flows which passed: a failed flow fails regardless.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::str::FromStr;
+
+use anyhow::{bail, ensure, Context, Result};
+
+use opentitanlib::dif::lc_ctrl::DifLcCtrlState;
+
+use crate::flow_result::FlowResult;
+
+/// Value of a field of the FT result, or of an expression.
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+    Bool(bool),
+    Number(f64),
+    String(String),
+    /// A field unset in the result, e.g. the `error_kind` of a passing flow.
+    Null,
+}
+
+impl fmt::Display for Value {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Bool(b) => write!(f, "{b}"),
+            Self::Number(n) => write!(f, "{n}"),
+            Self::String(s) => write!(f, "{s:?}"),
+            Self::Null => write!(f, "null"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Expr {
+    Literal(Value),
+    Field(String),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Cmp(CmpOp, Box<Expr>, Box<Expr>),
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum Token {
+    Ident(String),
+    String(String),
+    Number(f64),
+    Cmp(CmpOp),
+    And,
+    Or,
+    Not,
+    LParen,
+    RParen,
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+            continue;
+        }
+        if c.is_ascii_alphabetic() || c == '_' {
+            let mut ident = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_ascii_alphanumeric() || c == '_' || c == '.') {
+                    break;
+                }
+                ident.push(c);
+                chars.next();
+            }
+            tokens.push(match ident.to_ascii_uppercase().as_str() {
+                "AND" => Token::And,
+                "OR" => Token::Or,
+                "NOT" => Token::Not,
+                _ => Token::Ident(ident),
+            });
+            continue;
+        }
+        if c.is_ascii_digit() {
+            let mut number = String::new();
+            while let Some(&c) = chars.peek() {
+                if !(c.is_ascii_digit() || c == '.') {
+                    break;
+                }
+                number.push(c);
+                chars.next();
+            }
+            let n = number
+                .parse()
+                .with_context(|| format!("Invalid number {number}"))?;
+            tokens.push(Token::Number(n));
+            continue;
+        }
+        chars.next();
+        let token = match (c, chars.peek().copied()) {
+            ('"', _) => {
+                let mut string = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some(c) => string.push(c),
+                        None => bail!("Unterminated string \"{string}"),
+                    }
+                }
+                Token::String(string)
+            }
+            ('(', _) => Token::LParen,
+            (')', _) => Token::RParen,
+            ('&', Some('&')) => Token::And,
+            ('|', Some('|')) => Token::Or,
+            ('=', Some('=')) => Token::Cmp(CmpOp::Eq),
+            ('!', Some('=')) => Token::Cmp(CmpOp::Ne),
+            ('<', Some('=')) => Token::Cmp(CmpOp::Le),
+            ('>', Some('=')) => Token::Cmp(CmpOp::Ge),
+            ('!', _) => Token::Not,
+            ('<', _) => Token::Cmp(CmpOp::Lt),
+            ('>', _) => Token::Cmp(CmpOp::Gt),
+            _ => bail!("Unexpected character {c:?}"),
+        };
+        // Consume the second character of the two-character operators.
+        if matches!(
+            token,
+            Token::And | Token::Or | Token::Cmp(CmpOp::Eq | CmpOp::Ne | CmpOp::Le | CmpOp::Ge)
+        ) {
+            chars.next();
+        }
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+/// Recursive descent parser, from the lowest precedence: `OR`, `AND`, `NOT`, comparisons.
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut expr = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr> {
+        let mut expr = self.not()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+        Ok(expr)
+    }
+
+    fn not(&mut self) -> Result<Expr> {
+        if self.peek() == Some(&Token::Not) {
+            self.next();
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+        self.cmp()
+    }
+
+    fn cmp(&mut self) -> Result<Expr> {
+        let lhs = self.atom()?;
+        if let Some(&Token::Cmp(op)) = self.peek() {
+            self.next();
+            let rhs = self.atom()?;
+            return Ok(Expr::Cmp(op, Box::new(lhs), Box::new(rhs)));
+        }
+        Ok(lhs)
+    }
+
+    fn atom(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::LParen) => {
+                let expr = self.or()?;
+                ensure!(self.next() == Some(Token::RParen), "Expected `)`");
+                Ok(expr)
+            }
+            Some(Token::Ident(ident)) => Ok(match ident.as_str() {
+                "true" => Expr::Literal(Value::Bool(true)),
+                "false" => Expr::Literal(Value::Bool(false)),
+                _ => match DifLcCtrlState::parse_lc_state_str(&ident.to_ascii_lowercase())? {
+                    DifLcCtrlState::StateInvalid => Expr::Field(ident),
+                    state => Expr::Literal(Value::String(state.lc_state_to_str().into())),
+                },
+            }),
+            Some(Token::String(s)) => Ok(Expr::Literal(Value::String(s))),
+            Some(Token::Number(n)) => Ok(Expr::Literal(Value::Number(n))),
+            Some(token) => bail!("Unexpected {token:?}"),
+            None => bail!("Unexpected end of expression"),
+        }
+    }
+}
+
+impl Expr {
+    /// Checks the fields the expression refers to are in `fields`.
+    fn check_fields(&self, fields: &BTreeSet<String>) -> Result<()> {
+        match self {
+            Expr::Literal(_) => Ok(()),
+            Expr::Field(name) => {
+                ensure!(
+                    fields.contains(name),
+                    "Unknown field `{name}`; the fields are {}",
+                    fields.iter().cloned().collect::<Vec<_>>().join(", ")
+                );
+                Ok(())
+            }
+            Expr::Not(expr) => expr.check_fields(fields),
+            Expr::And(lhs, rhs) | Expr::Or(lhs, rhs) | Expr::Cmp(_, lhs, rhs) => {
+                lhs.check_fields(fields)?;
+                rhs.check_fields(fields)
+            }
+        }
+    }
+}
+
+/// Pass criteria of a SKU, see the module documentation.
+#[derive(Clone, Debug)]
+pub struct PassCriteria {
+    source: String,
+    expr: Expr,
+}
+
+impl FromStr for PassCriteria {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let mut parser = Parser {
+            tokens: tokenize(s).with_context(|| format!("Invalid pass criteria `{s}`"))?,
+            pos: 0,
+        };
+        let expr = parser
+            .or()
+            .and_then(|expr| {
+                ensure!(parser.peek().is_none(), "Unexpected {:?}", parser.peek());
+                expr.check_fields(&FlowResult::criteria_field_names())?;
+                Ok(expr)
+            })
+            .with_context(|| format!("Invalid pass criteria `{s}`"))?;
+        Ok(Self {
+            source: s.to_string(),
+            expr,
+        })
+    }
+}
+
+impl fmt::Display for PassCriteria {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.source)
+    }
+}
+
+fn eval(expr: &Expr, fields: &BTreeMap<String, Value>) -> Result<Value> {
+    let bool_of = |expr: &Expr| -> Result<bool> {
+        match eval(expr, fields)? {
+            Value::Bool(b) => Ok(b),
+            value => bail!("Expected a boolean, got {value}"),
+        }
+    };
+    Ok(match expr {
+        Expr::Literal(value) => value.clone(),
+        Expr::Field(name) => match fields.get(name) {
+            Some(value) => value.clone(),
+            None => bail!(
+                "Unknown field `{name}`; the fields are {}",
+                fields.keys().cloned().collect::<Vec<_>>().join(", ")
+            ),
+        },
+        Expr::Not(expr) => Value::Bool(!bool_of(expr)?),
+        Expr::And(lhs, rhs) => Value::Bool(bool_of(lhs)? && bool_of(rhs)?),
+        Expr::Or(lhs, rhs) => Value::Bool(bool_of(lhs)? || bool_of(rhs)?),
+        Expr::Cmp(op, lhs, rhs) => {
+            let (lhs, rhs) = (eval(lhs, fields)?, eval(rhs, fields)?);
+            let ordering = match (&lhs, &rhs) {
+                (Value::Number(a), Value::Number(b)) => a.partial_cmp(b),
+                (Value::String(a), Value::String(b)) => Some(a.cmp(b)),
+                _ => None,
+            };
+            Value::Bool(match (op, ordering) {
+                (CmpOp::Eq, _) => lhs == rhs,
+                (CmpOp::Ne, _) => lhs != rhs,
+                (CmpOp::Lt, Some(o)) => o.is_lt(),
+                (CmpOp::Le, Some(o)) => o.is_le(),
+                (CmpOp::Gt, Some(o)) => o.is_gt(),
+                (CmpOp::Ge, Some(o)) => o.is_ge(),
+                _ => bail!("Cannot compare {lhs} with {rhs}"),
+            })
+        }
+    })
+}
+
+impl PassCriteria {
+    /// Evaluates the criteria over `fields`, returning whether they are met.
+    pub fn is_met(&self, fields: &BTreeMap<String, Value>) -> Result<bool> {
+        match eval(&self.expr, fields)
+            .with_context(|| format!("Failed to evaluate pass criteria `{self}`"))?
+        {
+            Value::Bool(met) => Ok(met),
+            value => bail!("Pass criteria `{self}` evaluate to {value}, not to a boolean"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fields() -> BTreeMap<String, Value> {
+        BTreeMap::from([
+            ("passed".into(), Value::Bool(true)),
+            ("all_steps_ok".into(), Value::Bool(false)),
+            ("cert_chain_valid".into(), Value::Bool(true)),
+            ("certs".into(), Value::Number(4.0)),
+            ("lc_state".into(), Value::String("prod".into())),
+            ("sku".into(), Value::String("sival".into())),
+            ("error_kind".into(), Value::Null),
+        ])
+    }
+
+    fn is_met(criteria: &str) -> bool {
+        criteria
+            .parse::<PassCriteria>()
+            .unwrap()
+            .is_met(&fields())
+            .unwrap()
+    }
+
+    #[test]
+    fn test_precedence() {
+        // `AND` binds tighter than `OR`, and `NOT` tighter than `AND`.
+        assert!(is_met("passed OR all_steps_ok AND false"));
+        assert!(!is_met("all_steps_ok AND passed OR false"));
+        assert!(is_met("NOT all_steps_ok AND passed"));
+        assert!(!is_met("!passed || all_steps_ok && passed"));
+        // Comparisons bind tighter than `NOT`.
+        assert!(is_met("NOT certs < 4 AND certs >= 4"));
+    }
+
+    #[test]
+    fn test_parentheses() {
+        assert!(!is_met("(passed OR all_steps_ok) AND false"));
+        assert!(!is_met("NOT (all_steps_ok OR passed)"));
+        assert!(is_met("((passed))"));
+        assert!("(passed".parse::<PassCriteria>().is_err());
+        assert!("passed)".parse::<PassCriteria>().is_err());
+    }
+
+    #[test]
+    fn test_string_comparisons() {
+        assert!(is_met("sku == \"sival\""));
+        assert!(is_met("sku != \"prodc\""));
+        assert!(!is_met("sku == \"SIVAL\""));
+        // LC state names stand for the names of the states, case-insensitively.
+        assert!(is_met("lc_state == PROD"));
+        assert!(is_met("lc_state != dev"));
+        assert!(is_met("error_kind != \"timeout\""));
+        assert!(!is_met("error_kind == \"timeout\""));
+        assert!("sku > 4"
+            .parse::<PassCriteria>()
+            .unwrap()
+            .is_met(&fields())
+            .is_err());
+    }
+
+    #[test]
+    fn test_unknown_fields() {
+        // Rejected although the operands they are in would not be evaluated.
+        for criteria in [
+            "passed OR typo_field",
+            "all_steps_ok AND typo_field",
+            "NOT (certs > 3 OR step.typo)",
+        ] {
+            let err = criteria.parse::<PassCriteria>().unwrap_err();
+            assert!(format!("{err:#}").contains("Unknown field"), "{err:#}");
+        }
+        assert!("step.personalize == true AND device_id != \"\""
+            .parse::<PassCriteria>()
+            .is_ok());
+    }
+}
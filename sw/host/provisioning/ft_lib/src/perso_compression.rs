@@ -0,0 +1,316 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Transfers of the perso blob between the host and the personalization firmware.
+//!
+//! The perso blob the device exports may be compressed, with a scheme negotiated in the export
+//! options: the device sends it as an LZ4 block (see `perso_lz4_compress` in
+//! sw/device/silicon_creator/manuf/base/perso_lz4.h) in chunks, which the host reassembles,
+//! decompresses and checks against the size and CRC-32 of the uncompressed blob. The perso blob of
+//! endorsed certificates sent back may be imported in acknowledged chunks, resent when rejected.
+
+use std::time::Duration;
+
+use anyhow::{bail, ensure, Context, Result};
+use clap::ValueEnum;
+use crc::Crc;
+use serde::{Deserialize, Serialize};
+
+use opentitanlib::io::console::ConsoleDevice;
+use opentitanlib::test_utils::rpc::ConsoleSend;
+use ujson_lib::limits::{self, PersoBlobBody, PERSO_BLOB_CHUNK_SIZE};
+use ujson_lib::provisioning_data::{
+    PersoBlob, PersoBlobChunk, PersoBlobChunkAck, PersoBlobImportChunk,
+};
+
+use crate::command_id::{recv_answer, CommandIds};
+use crate::retry::RetryPolicy;
+
+/// Number of times a chunk of the endorsed certificates is resent after the device rejected it.
+///
+/// Must match `kPersoBlobImportMaxRetries` in
+/// sw/device/silicon_creator/manuf/base/ft_personalize.c.
+const PERSO_BLOB_IMPORT_MAX_RETRIES: usize = 3;
+
+/// Compression of the perso blob exported off the device.
+///
+/// The discriminants must be kept in sync with `perso_blob_compression_t` in
+/// sw/device/silicon_creator/manuf/base/perso_lz4.h.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PersoCompression {
+    /// Export the perso blob uncompressed.
+    #[default]
+    None = 0,
+    /// Export the perso blob as an LZ4 block.
+    Lz4 = 1,
+}
+
+impl PersoCompression {
+    /// Returns the `ManufPersoExportOptions::compression` bitmask requesting this scheme.
+    pub(crate) fn mask(self) -> u32 {
+        match self {
+            Self::None => 0,
+            scheme => 1 << scheme as u32,
+        }
+    }
+
+    pub(crate) fn from_mask(mask: u32) -> Result<Self> {
+        match mask {
+            0 => Ok(Self::None),
+            m if m == Self::Lz4.mask() => Ok(Self::Lz4),
+            _ => bail!("Device picked an unsupported perso blob compression {mask:#x}"),
+        }
+    }
+}
+
+/// Receives the perso blob exported off the device with the negotiated `compression`.
+pub(crate) fn recv_perso_blob(
+    console: &dyn ConsoleDevice,
+    compression: PersoCompression,
+    timeout: Duration,
+    retry: &RetryPolicy,
+) -> Result<PersoBlob> {
+    if compression == PersoCompression::None {
+        return retry.recv(console, timeout, true);
+    }
+
+    let mut compressed = Vec::new();
+    let last = loop {
+        let chunk: PersoBlobChunk = retry.recv(console, timeout, true)?;
+        ensure!(
+            chunk.offset == compressed.len()
+                && chunk.num_bytes <= chunk.data.len()
+                && chunk.offset + chunk.num_bytes <= chunk.size,
+            "Malformed perso blob chunk at offset {:#x}",
+            chunk.offset
+        );
+        compressed.extend_from_slice(&chunk.data[..chunk.num_bytes]);
+        if chunk.last {
+            ensure!(
+                compressed.len() == chunk.size,
+                "Perso blob truncated: received {} of {} compressed bytes",
+                compressed.len(),
+                chunk.size
+            );
+            break chunk;
+        }
+    };
+    ensure!(
+        last.compression == compression as u32,
+        "Perso blob compressed with {} instead of the negotiated {compression:?}",
+        last.compression
+    );
+
+    let mut body = PersoBlobBody::new();
+    let data = lz4_decompress(&compressed, body.capacity())?;
+    ensure!(
+        data.len() == last.next_free,
+        "Perso blob decompressed to {} bytes, expected {}",
+        data.len(),
+        last.next_free
+    );
+    let crc32 = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC).checksum(&data);
+    ensure!(
+        crc32 == last.crc32,
+        "Perso blob CRC mismatch: {crc32:#010x} vs {:#010x} computed on the device",
+        last.crc32
+    );
+    log::info!(
+        "Received a {} byte perso blob as {} compressed bytes.",
+        data.len(),
+        compressed.len()
+    );
+    body.try_extend_from_slice(&data)?;
+    body.extend(std::iter::repeat(0).take(body.remaining_capacity()));
+    Ok(PersoBlob {
+        num_objs: last.num_objs,
+        next_free: last.next_free,
+        body,
+    })
+}
+
+/// Sends the perso blob of endorsed certificates to the device, in chunks acknowledged one by one
+/// if `chunked` was negotiated.
+///
+/// A chunk the device rejects, e.g. because a byte was corrupted on the console, is resent alone,
+/// and a failed import reports the offset the device stopped at.
+pub(crate) fn send_perso_blob(
+    console: &dyn ConsoleDevice,
+    blob: &PersoBlob,
+    chunked: bool,
+    timeout: Duration,
+    retry: &RetryPolicy,
+    cmd_ids: &CommandIds,
+) -> Result<()> {
+    if !chunked {
+        return blob.send(console);
+    }
+
+    let crc = Crc::<u32>::new(&crc::CRC_32_ISO_HDLC);
+    let body = &blob.body[..blob.next_free];
+    let mut offset = 0;
+    loop {
+        let data = &body[offset..body.len().min(offset + PERSO_BLOB_CHUNK_SIZE)];
+        let mut chunk = PersoBlobImportChunk {
+            cmd_id: 0,
+            num_objs: blob.num_objs,
+            next_free: blob.next_free,
+            offset,
+            num_bytes: data.len(),
+            crc32: crc.checksum(data),
+            data: limits::fit("Perso blob import chunk", data)?,
+            last: offset + data.len() == body.len(),
+        };
+        let mut retries = 0;
+        loop {
+            // A resent chunk gets a new ID, the lost acknowledgment of an earlier attempt may
+            // still come in.
+            chunk.cmd_id = cmd_ids.next();
+            chunk.send(console)?;
+            let ack: PersoBlobChunkAck = recv_answer(console, chunk.cmd_id, timeout, retry)?;
+            if ack.accepted {
+                ensure!(
+                    ack.offset == offset + data.len(),
+                    "Device acknowledged the endorsed certificates chunk at offset {offset:#x} up to offset {:#x}",
+                    ack.offset
+                );
+                break;
+            }
+            retries += 1;
+            ensure!(
+                retries <= PERSO_BLOB_IMPORT_MAX_RETRIES,
+                "Device rejected the endorsed certificates chunk at offset {offset:#x} {retries} times"
+            );
+            log::warn!(
+                "Device rejected the endorsed certificates chunk at offset {offset:#x}, resending."
+            );
+        }
+        offset += data.len();
+        if chunk.last {
+            return Ok(());
+        }
+    }
+}
+
+/// Reads an LZ4 sequence length extension, adding it to `len`.
+fn lz4_read_length(input: &[u8], pos: &mut usize, mut len: usize) -> Result<usize> {
+    loop {
+        let byte = *input.get(*pos).context("LZ4 block truncated in length")?;
+        *pos += 1;
+        len += byte as usize;
+        if byte != 255 {
+            return Ok(len);
+        }
+    }
+}
+
+/// Decompresses a single LZ4 block of at most `max_len` decompressed bytes.
+pub fn lz4_decompress(input: &[u8], max_len: usize) -> Result<Vec<u8>> {
+    let mut output = Vec::new();
+    let mut pos = 0;
+    while pos < input.len() {
+        let token = input[pos];
+        pos += 1;
+
+        let mut literal_len = (token >> 4) as usize;
+        if literal_len == 15 {
+            literal_len = lz4_read_length(input, &mut pos, literal_len)?;
+        }
+        let literals = input
+            .get(pos..pos + literal_len)
+            .context("LZ4 block truncated in literals")?;
+        ensure!(
+            output.len() + literal_len <= max_len,
+            "LZ4 block exceeds {max_len} bytes"
+        );
+        output.extend_from_slice(literals);
+        pos += literal_len;
+        if pos == input.len() {
+            break;
+        }
+
+        let offset = input
+            .get(pos..pos + 2)
+            .context("LZ4 block truncated in match offset")?;
+        let offset = u16::from_le_bytes([offset[0], offset[1]]) as usize;
+        pos += 2;
+        ensure!(
+            offset != 0 && offset <= output.len(),
+            "Invalid LZ4 match offset {offset}"
+        );
+        let mut match_len = (token & 0xf) as usize;
+        if match_len == 15 {
+            match_len = lz4_read_length(input, &mut pos, match_len)?;
+        }
+        match_len += 4;
+        ensure!(
+            output.len() + match_len <= max_len,
+            "LZ4 block exceeds {max_len} bytes"
+        );
+        // Matches may overlap the bytes they produce, so copy byte by byte.
+        let start = output.len() - offset;
+        for i in 0..match_len {
+            output.push(output[start + i]);
+        }
+    }
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lz4_decompress() {
+        assert_eq!(lz4_decompress(&[], 16).unwrap(), b"");
+        assert_eq!(lz4_decompress(&[0x00], 16).unwrap(), b"");
+        assert_eq!(lz4_decompress(b"\x30abc", 16).unwrap(), b"abc");
+        // The output of `perso_lz4_compress` for 32 `a`s: a literal, a 26 byte match overlapping
+        // the bytes it produces, and 5 literals.
+        assert_eq!(
+            lz4_decompress(b"\x1fa\x01\x00\x07\x50aaaaa", 32).unwrap(),
+            [b'a'; 32]
+        );
+        // Length extensions: 15 + 255 + 3 literals, and a 4 + 15 + 2 byte match.
+        let mut block = vec![0xff, 255, 3];
+        block.extend((0..273).map(|i| i as u8));
+        block.extend([0x10, 0x00, 2, 0x50]);
+        block.extend(b"tail!");
+        let output = lz4_decompress(&block, 1024).unwrap();
+        assert_eq!(output.len(), 273 + 21 + 5);
+        assert_eq!(output[..273], (0..273).map(|i| i as u8).collect::<Vec<_>>());
+        assert_eq!(output[273..294], output[257..278]);
+        assert!(output.ends_with(b"tail!"));
+    }
+
+    #[test]
+    fn test_lz4_decompress_malformed() {
+        for (block, error) in [
+            // Offsets of zero, and before the start of the output.
+            (&b"\x10a\x00\x00\x00"[..], "Invalid LZ4 match offset 0"),
+            (b"\x10a\x02\x00\x00", "Invalid LZ4 match offset 2"),
+            (b"\x00\x01\x00", "Invalid LZ4 match offset 1"),
+            // Truncated literals, match offsets and lengths.
+            (b"\x30ab", "truncated in literals"),
+            (b"\xf0", "truncated in length"),
+            (b"\xf0\xff", "truncated in length"),
+            (b"\x10a\x01", "truncated in match offset"),
+            (b"\x1fa\x01\x00", "truncated in length"),
+        ] {
+            let err = lz4_decompress(block, 64).unwrap_err();
+            assert!(err.to_string().contains(error), "{block:x?}: {err}");
+        }
+    }
+
+    #[test]
+    fn test_lz4_decompress_max_len() {
+        // Output past `max_len`, in literals or in a match.
+        let block = b"\x1fa\x01\x00\x07\x50aaaaa";
+        assert!(lz4_decompress(block, 31).is_err());
+        assert!(lz4_decompress(block, 26).is_err());
+        assert!(lz4_decompress(b"\x30abc", 2).is_err());
+        assert_eq!(lz4_decompress(block, 32).unwrap().len(), 32);
+    }
+}
@@ -0,0 +1,712 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use anyhow::{ensure, Result};
+use arrayvec::ArrayVec;
+
+use cert_lib::offline::OfflineCerts;
+use cert_lib::policy::SigningPolicy;
+use cert_lib::{CaConfig, CaKey};
+use opentitanlib::app::TransportWrapper;
+use opentitanlib::console::dmi::DmiConsoleDevice;
+use opentitanlib::dif::lc_ctrl::DifLcCtrlState;
+use opentitanlib::io::console::ConsoleDevice;
+use opentitanlib::test_utils::init::InitializeTest;
+use opentitanlib::test_utils::lc::read_lc_state_without_reset;
+use opentitanlib::test_utils::lc_transition::LcStateCheck;
+use opentitanlib::test_utils::load_sram_program::{JtagClockRamp, SramProgramParams};
+use provisioning::session::DeviceSession;
+use ujson_lib::provisioning_data::{ManufCertgenInputs, ManufFtIndividualizeData};
+
+use crate::alert_cfg::AlertCfg;
+use crate::audit::{
+    audit_console_certs, audit_lc_facts, read_console_certs, read_lc_facts, AuditResult,
+    SavedReport,
+};
+use crate::checkpoint::Checkpoint;
+use crate::command_id::CommandIds;
+#[cfg(feature = "debug-tools")]
+use crate::debug_regs::DebugSession;
+use crate::entropy::EntropyCheck;
+use crate::error::{check_test_exit_token_error, ProvisioningError};
+use crate::events::{EventConsole, EventSink};
+use crate::keymgr_binding::KeymgrDiversification;
+use crate::log_level::DeviceLogLevel;
+use crate::manuf_state::CreatorManufState;
+use crate::otp_dump::{run_sram_otp_dump, OtpDump};
+use crate::otp_preload::preload_individualized_otp;
+use crate::response::PersonalizeResponse;
+use crate::retry::RetryPolicy;
+use crate::secret::LcTokenSecret;
+use crate::smoke_test::SmokeTestSuite;
+use crate::step_state::StepJournal;
+use crate::telemetry::{Telemetry, TelemetrySample};
+use crate::timeouts::Timeouts;
+use crate::verify::{
+    otp_dump_allowed, verify_console_certs, verify_lc_state, verify_otp_locks, VerifyParams,
+};
+// The free functions of the FT steps are deprecated for use outside of `FtProvisioner`.
+#[allow(deprecated)]
+use crate::{
+    check_hw_cfg_device_id, check_slot_b_boot_up, check_test_exit_token, run_ft_personalize,
+    run_sram_ft_individualize, test_exit, test_unlock, HwCfgPolicy, PersoExportOptions,
+};
+
+/// A class of one-way operations an `FtProvisioner` may be allowed to perform.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Capability {
+    /// Life cycle state transitions (test unlock, test exit).
+    LcTransition,
+    /// OTP programming (individualization, personalization secrets).
+    OtpWrite,
+    /// Arbitrary register peek/poke over JTAG, in debug builds.
+    DebugRegs,
+}
+
+/// How an `FtProvisioner` programs the individualized OTP partitions.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum IndividualizeBackend {
+    /// Run the FT individualize SRAM program, which programs OTP over the DAI.
+    #[default]
+    SramProgram,
+    /// FPGA only: load this bitstream, whose OTP image already holds the individualized
+    /// partitions (see `otp_preload`).
+    OtpPreload(PathBuf),
+}
+
+/// The set of one-way operations an `FtProvisioner` is allowed to perform.
+///
+/// The default is read-only: every one-way operation must be enabled explicitly.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Capabilities {
+    lc_transition: bool,
+    otp_write: bool,
+    debug_regs: bool,
+}
+
+impl Capabilities {
+    /// Capabilities of a read-only diagnostic tool.
+    pub fn read_only() -> Self {
+        Self::default()
+    }
+
+    /// Capabilities of a provisioning tool running the complete FT flow.
+    pub fn all() -> Self {
+        Self::read_only()
+            .with(Capability::LcTransition)
+            .with(Capability::OtpWrite)
+    }
+
+    /// Returns these capabilities with `capability` enabled.
+    pub fn with(mut self, capability: Capability) -> Self {
+        match capability {
+            Capability::LcTransition => self.lc_transition = true,
+            Capability::OtpWrite => self.otp_write = true,
+            Capability::DebugRegs => self.debug_regs = true,
+        }
+        self
+    }
+
+    pub fn allows(&self, capability: Capability) -> bool {
+        match capability {
+            Capability::LcTransition => self.lc_transition,
+            Capability::OtpWrite => self.otp_write,
+            Capability::DebugRegs => self.debug_regs,
+        }
+    }
+}
+
+/// Entry point to the FT provisioning operations on a device.
+///
+/// One-way operations are only available if the matching `Capability` was granted when the
+/// provisioner was constructed; diagnostic tools constructed with `Capabilities::read_only()`
+/// cannot trigger them. Each one-way operation is recorded in the step journal before it starts.
+///
+/// The provisioner also refuses operations that would break the LC state invariants of the flow:
+/// once `individualize` may have enabled ROM execution in OTP, the device must not be reset until
+/// `test_exit` transitioned it to a mission mode, as the ROM would boot the flash image outside of
+/// a mission mode. Operations resetting the device fail in this window.
+pub struct FtProvisioner<'a> {
+    transport: &'a TransportWrapper,
+    init: &'a InitializeTest,
+    console: EventConsole<'a>,
+    timeouts: Timeouts,
+    capabilities: Capabilities,
+    journal: StepJournal,
+    checkpoint: Option<Checkpoint>,
+    lc_state_check: LcStateCheck,
+    retry: RetryPolicy,
+    individualize_backend: IndividualizeBackend,
+    cert_policy: SigningPolicy,
+    offline_certs: Option<OfflineCerts>,
+    keymgr_diversification: Option<KeymgrDiversification>,
+    entry_lc_states: Vec<DifLcCtrlState>,
+    telemetry: Telemetry,
+    telemetry_samples: RefCell<Vec<TelemetrySample>>,
+    dmi_console: Option<&'a DmiConsoleDevice<'a>>,
+    /// IDs of the commands sent to the personalization firmware, see `command_id`.
+    command_ids: CommandIds,
+}
+
+impl<'a> FtProvisioner<'a> {
+    pub fn new(
+        transport: &'a TransportWrapper,
+        init: &'a InitializeTest,
+        console: &'a dyn ConsoleDevice,
+        timeout: Duration,
+        capabilities: Capabilities,
+    ) -> Self {
+        Self {
+            transport,
+            init,
+            console: EventConsole::new(console, EventSink::default()),
+            timeouts: Timeouts::uniform(timeout),
+            capabilities,
+            journal: StepJournal::disabled(),
+            checkpoint: None,
+            lc_state_check: LcStateCheck::default(),
+            retry: RetryPolicy::default(),
+            individualize_backend: IndividualizeBackend::default(),
+            cert_policy: SigningPolicy::default(),
+            offline_certs: None,
+            keymgr_diversification: None,
+            entry_lc_states: Vec::new(),
+            telemetry: Telemetry::default(),
+            telemetry_samples: RefCell::new(Vec::new()),
+            dmi_console: None,
+            command_ids: CommandIds::default(),
+        }
+    }
+
+    /// Returns this provisioner, persisting the step state of the device to `journal`.
+    ///
+    /// The events of the provisioner, if any, are emitted through the new journal. Whether ROM
+    /// execution may be enabled on the device is tracked in the journal, so the state carried over
+    /// from a previous flow applies to this provisioner.
+    pub fn with_journal(mut self, mut journal: StepJournal) -> Self {
+        journal.set_events(self.journal.events().clone());
+        self.journal = journal;
+        self
+    }
+
+    /// Returns this provisioner, recording the steps each device completes in `checkpoint`, and
+    /// skipping the steps it completed in a previous flow if the checkpoint is resumed.
+    ///
+    /// Devices are recorded under the device ID of the journal: steps run before the device ID is
+    /// known are neither recorded nor skipped.
+    pub fn with_checkpoint(mut self, checkpoint: Checkpoint) -> Self {
+        self.checkpoint = Some(checkpoint);
+        self
+    }
+
+    /// Returns this provisioner, emitting the events of the flow, including the lines printed on
+    /// its console, to `events`.
+    pub fn with_events(mut self, events: EventSink) -> Self {
+        self.console.set_events(events.clone());
+        self.journal.set_events(events);
+        self
+    }
+
+    pub fn events(&self) -> &EventSink {
+        self.journal.events()
+    }
+
+    /// Returns this provisioner, verifying the LC state around transitions as set by `check`.
+    pub fn with_lc_state_check(mut self, check: LcStateCheck) -> Self {
+        self.lc_state_check = check;
+        self
+    }
+
+    /// Returns this provisioner, retrying its idempotent operations as allowed by `policy`.
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Returns this provisioner, waiting for each step up to its timeout in `timeouts` rather than
+    /// the uniform timeout it was constructed with.
+    pub fn with_timeouts(mut self, timeouts: Timeouts) -> Self {
+        self.timeouts = timeouts;
+        self
+    }
+
+    /// Returns this provisioner, individualizing devices with `backend`.
+    pub fn with_individualize_backend(mut self, backend: IndividualizeBackend) -> Self {
+        self.individualize_backend = backend;
+        self
+    }
+
+    /// Returns this provisioner, refusing to endorse device certificates outside of `policy`.
+    pub fn with_cert_policy(mut self, policy: SigningPolicy) -> Self {
+        self.cert_policy = policy;
+        self
+    }
+
+    /// Returns this provisioner, injecting the device certificates issued offline in `certs`
+    /// instead of endorsing them. The CA keys are not used then.
+    pub fn with_offline_certs(mut self, certs: OfflineCerts) -> Self {
+        self.offline_certs = Some(certs);
+        self
+    }
+
+    /// Returns this provisioner, binding the keymgr sealing values `diversification` derives for
+    /// each device during personalization, instead of the defaults of the personalization
+    /// firmware.
+    pub fn with_keymgr_diversification(mut self, diversification: KeymgrDiversification) -> Self {
+        self.keymgr_diversification = Some(diversification);
+        self
+    }
+
+    /// Returns this provisioner, accepting only devices entering the flow in one of `lc_states`.
+    /// Devices in any LC state are accepted if `lc_states` is empty.
+    pub fn with_entry_lc_states(mut self, lc_states: Vec<DifLcCtrlState>) -> Self {
+        self.entry_lc_states = lc_states;
+        self
+    }
+
+    /// Returns this provisioner, reading the `telemetry` sensors of the fixture at the start and
+    /// at the end of each step.
+    pub fn with_telemetry(mut self, telemetry: Telemetry) -> Self {
+        self.telemetry = telemetry;
+        self
+    }
+
+    /// Returns this provisioner, releasing the JTAG adapter held by `dmi_console` at the end of
+    /// each step, for the JTAG operations of the next one. Set when the console of the provisioner
+    /// reads from `dmi_console`.
+    pub fn with_dmi_console(mut self, dmi_console: &'a DmiConsoleDevice<'a>) -> Self {
+        self.dmi_console = Some(dmi_console);
+        self
+    }
+
+    pub fn journal(&self) -> &StepJournal {
+        &self.journal
+    }
+
+    /// Returns the transport of the device, for the custom steps of a `Flow`.
+    pub fn transport(&self) -> &'a TransportWrapper {
+        self.transport
+    }
+
+    /// Returns the console of the device, emitting the lines it reads as events.
+    pub fn console(&self) -> &dyn ConsoleDevice {
+        &self.console
+    }
+
+    pub fn capabilities(&self) -> Capabilities {
+        self.capabilities
+    }
+
+    fn require(&self, capability: Capability, operation: &str) -> Result<()> {
+        ensure!(
+            self.capabilities.allows(capability),
+            "{operation} requires the {capability:?} capability, which this provisioner was not granted"
+        );
+        Ok(())
+    }
+
+    /// Whether ROM execution may be enabled in OTP, without the device in a mission mode yet.
+    ///
+    /// Set by `individualize`, and cleared by a successful `test_exit`. Persisted in the step
+    /// journal, so later flows on the same device, e.g. a separate `test-exit`, know it too.
+    pub fn rom_exec_enabled(&self) -> bool {
+        self.journal.rom_exec_enabled()
+    }
+
+    fn require_reset(&self, operation: &str) -> Result<()> {
+        ensure!(
+            !self.rom_exec_enabled(),
+            "{operation} resets the device, which is refused between FT individualization and test exit: the ROM would boot the flash image outside of a mission mode"
+        );
+        Ok(())
+    }
+
+    /// Returns the telemetry samples taken so far.
+    pub fn telemetry_samples(&self) -> Vec<TelemetrySample> {
+        self.telemetry_samples.borrow().clone()
+    }
+
+    fn sample_telemetry(&self, step: &'static str, point: &'static str) {
+        let samples = self.telemetry.sample(self.transport, step, point);
+        self.telemetry_samples.borrow_mut().extend(samples);
+    }
+
+    /// Runs the FT `step`, and emits its outcome and duration.
+    ///
+    /// Steps the device completed in a previous flow are skipped when resuming a checkpoint.
+    fn step(&self, step: &'static str, f: impl FnOnce() -> Result<()>) -> Result<()> {
+        let device_id = self.journal.device_id();
+        let checkpoint = self.checkpoint.as_ref().filter(|_| !device_id.is_empty());
+        if checkpoint.is_some_and(|c| c.skips(&device_id, step)) {
+            log::info!("Skipping step {step}, completed by device {device_id} in a previous flow.");
+            return Ok(());
+        }
+        let t0 = Instant::now();
+        self.sample_telemetry(step, "start");
+        let mut result = f();
+        if let Some(dmi_console) = self.dmi_console {
+            result = result.and(dmi_console.release());
+        }
+        self.sample_telemetry(step, "end");
+        if let Some(checkpoint) = checkpoint {
+            let recorded = checkpoint.record(&device_id, step, result.is_ok());
+            match &result {
+                Ok(()) => result = recorded,
+                // Keep the error of the step.
+                Err(_) => {
+                    if let Err(e) = recorded {
+                        log::warn!("{e:#}");
+                    }
+                }
+            }
+        }
+        self.events()
+            .step_finished(step, t0.elapsed(), result.as_ref().err());
+        result
+    }
+
+    /// Runs the custom step `name` of a `Flow` as the FT steps: skipped if completed in a previous
+    /// flow of a checkpoint, and with its outcome and duration emitted.
+    pub fn custom_step(&self, name: &'static str, f: impl FnOnce() -> Result<()>) -> Result<()> {
+        self.step(name, f)
+    }
+
+    fn reset_delay(&self) -> Duration {
+        self.init.bootstrap.options.reset_delay
+    }
+
+    /// Returns the transport and JTAG parameters the device is provisioned with.
+    pub fn device(&self) -> DeviceSession<'a> {
+        DeviceSession::new(self.transport, self.init)
+    }
+
+    /// Reads the current LC state of the device.
+    ///
+    /// The device is not reset while ROM execution may be enabled: it is then in a
+    /// `TEST_UNLOCKED*` state, whose TAP straps are sampled continuously.
+    pub fn read_lc_state(&self) -> Result<DifLcCtrlState> {
+        if self.rom_exec_enabled() {
+            return read_lc_state_without_reset(self.transport, &self.init.jtag_params);
+        }
+        self.device().read_lc_state()
+    }
+
+    /// Reads the LC state the device enters the flow in, failing with a
+    /// `ProvisioningError::WrongInsertion` if it is not one of the entry LC states.
+    pub fn check_entry_lc_state(&self) -> Result<DifLcCtrlState> {
+        let lc_state = self.read_lc_state()?;
+        if !self.entry_lc_states.is_empty() && !self.entry_lc_states.contains(&lc_state) {
+            return Err(ProvisioningError::WrongInsertion {
+                allowed: self.entry_lc_states.clone(),
+                actual: lc_state,
+            }
+            .into());
+        }
+        Ok(lc_state)
+    }
+
+    /// See `check_hw_cfg_device_id`.
+    pub fn check_hw_cfg_device_id(
+        &self,
+        device_id: &ArrayVec<u32, 8>,
+        policy: HwCfgPolicy,
+    ) -> Result<ArrayVec<u32, 8>> {
+        self.require_reset("Checking the HW_CFG0 device ID")?;
+        check_hw_cfg_device_id(
+            self.transport,
+            &self.init.jtag_params,
+            self.reset_delay(),
+            device_id,
+            policy,
+            &self.retry,
+        )
+    }
+
+    /// Dumps and decodes all readable OTP partitions.
+    ///
+    /// Once ROM execution is enabled, the CPU is halted without resetting the device first.
+    pub fn otp_dump(&self, sram_program: &SramProgramParams) -> Result<OtpDump> {
+        run_sram_otp_dump(
+            self.transport,
+            &self.init.jtag_params,
+            self.reset_delay(),
+            /*reset_target=*/ !self.rom_exec_enabled(),
+            sram_program,
+            self.timeouts.sram_start,
+            &self.retry,
+            &self.console,
+        )
+    }
+
+    /// Checks the facts of a saved `report` that can be re-derived from the device still hold.
+    ///
+    /// The LC state and device ID are read over the LC TAP. If `cert_anchor` is set, the device
+    /// is reset and the certificates logged by its firmware on the console, up to the
+    /// `cert_anchor` regex, are compared with those of the report.
+    pub fn audit(&self, report: &SavedReport, cert_anchor: Option<&str>) -> Result<AuditResult> {
+        self.require_reset("Auditing the device")?;
+        let mut result = AuditResult {
+            device_id: report.device_id.clone(),
+            ..Default::default()
+        };
+        audit_lc_facts(
+            self.transport,
+            &self.init.jtag_params,
+            self.reset_delay(),
+            report,
+            &mut result,
+        )?;
+        if let Some(anchor) = cert_anchor {
+            audit_console_certs(
+                self.transport,
+                self.reset_delay(),
+                anchor,
+                self.timeouts.bootstrap_boot,
+                report,
+                &mut result,
+            )?;
+        }
+        Ok(result)
+    }
+
+    /// Checks an already provisioned device matches `params`, without modifying it; see `verify`.
+    ///
+    /// The OTP partition locks are only checked in the LC states which allow loading the OTP dump
+    /// SRAM program, and skipped with a warning in the others.
+    pub fn verify(&self, params: &VerifyParams) -> Result<AuditResult> {
+        self.require_reset("Verifying the device")?;
+        let (lc_state, device_id) =
+            read_lc_facts(self.transport, &self.init.jtag_params, self.reset_delay())?;
+        log::info!(
+            "Verifying device {device_id} in the {} LC state.",
+            lc_state.lc_state_to_str()
+        );
+        let mut result = AuditResult {
+            device_id,
+            ..Default::default()
+        };
+        verify_lc_state(lc_state, params, &mut result);
+        if let Some(sram_program) = &params.otp_dump {
+            if otp_dump_allowed(lc_state) {
+                let dump = self.otp_dump(sram_program)?;
+                verify_otp_locks(&dump, params, &mut result);
+            } else {
+                log::warn!(
+                    "Skipping the OTP partition lock checks: the OTP dump cannot be loaded in the {} LC state",
+                    lc_state.lc_state_to_str()
+                );
+            }
+        }
+        if let Some(anchor) = &params.cert_anchor {
+            let certs = read_console_certs(
+                self.transport,
+                self.reset_delay(),
+                anchor,
+                self.timeouts.bootstrap_boot,
+            )?;
+            verify_console_certs(&certs, params, &mut result)?;
+        }
+        Ok(result)
+    }
+
+    /// Resets the device and opens a register peek/poke session on it; see `debug_regs`.
+    #[cfg(feature = "debug-tools")]
+    pub fn debug_session(&self) -> Result<DebugSession<'a>> {
+        self.require(Capability::DebugRegs, "Register peek/poke")?;
+        self.require_reset("Register peek/poke")?;
+        DebugSession::open(self.transport, &self.init.jtag_params, self.reset_delay())
+    }
+
+    /// See `check_slot_b_boot_up`.
+    #[allow(deprecated)]
+    pub fn check_slot_b_boot_up(
+        &self,
+        response: &mut PersonalizeResponse,
+        owner_fw_success_string: Option<String>,
+    ) -> Result<()> {
+        self.require_reset("Checking the slot B boot up")?;
+        check_slot_b_boot_up(
+            self.transport,
+            self.init,
+            self.timeouts.bootstrap_boot,
+            response,
+            owner_fw_success_string,
+        )
+    }
+
+    /// Runs the smoke tests of `suite` on the device in mission mode, and records their results in
+    /// `response`; see `PersonalizeResponse::check_smoke_tests`.
+    pub fn run_smoke_tests(
+        &self,
+        suite: &SmokeTestSuite,
+        response: &mut PersonalizeResponse,
+    ) -> Result<()> {
+        self.require_reset("Running the smoke tests")?;
+        let t0 = Instant::now();
+        response.smoke_tests = suite.run(self.transport)?;
+        response.stats.log_elapsed_time("smoke-tests", t0);
+        Ok(())
+    }
+
+    /// Transitions the device from the `from` `TEST_LOCKED*` state to the `to` `TEST_UNLOCKED*`
+    /// state, see `check_test_unlock_transition`.
+    #[allow(deprecated)]
+    pub fn test_unlock(
+        &self,
+        test_unlock_token: &LcTokenSecret,
+        from: DifLcCtrlState,
+        to: DifLcCtrlState,
+    ) -> Result<()> {
+        self.require(Capability::LcTransition, "Test unlock")?;
+        self.require_reset("Test unlock")?;
+        self.step("test-unlock", || {
+            self.journal.enter("test-unlock", "lc-transition")?;
+            test_unlock(
+                self.transport,
+                &self.init.jtag_params,
+                self.reset_delay(),
+                test_unlock_token,
+                from,
+                to,
+                &self.lc_state_check,
+                self.timeouts.lc_transition,
+                &self.retry,
+                self.events(),
+            )
+        })
+    }
+
+    /// Individualizes the OTP partitions selected in `ft_individualize_data_in`.
+    ///
+    /// This enables ROM execution: the device can't be reset until `test_exit`, even if
+    /// individualization fails.
+    #[allow(deprecated)]
+    pub fn individualize(
+        &self,
+        sram_program: &SramProgramParams,
+        clock_ramp: &JtagClockRamp,
+        ft_individualize_data_in: &ManufFtIndividualizeData,
+        alert_cfg: &AlertCfg,
+    ) -> Result<()> {
+        self.require(Capability::OtpWrite, "FT individualization")?;
+        self.step("individualize", || {
+            self.require_reset("FT individualization")?;
+            self.journal.enter("individualize", "otp-program")?;
+            self.journal.set_rom_exec_enabled(true)?;
+            match &self.individualize_backend {
+                IndividualizeBackend::SramProgram => run_sram_ft_individualize(
+                    self.transport,
+                    &self.init.jtag_params,
+                    self.reset_delay(),
+                    sram_program,
+                    clock_ramp,
+                    ft_individualize_data_in,
+                    alert_cfg,
+                    &self.timeouts,
+                    &self.retry,
+                    &self.console,
+                    self.dmi_console.is_some(),
+                    self.events(),
+                ),
+                IndividualizeBackend::OtpPreload(bitstream) => preload_individualized_otp(
+                    self.transport,
+                    &self.init.load_bitstream,
+                    &self.init.jtag_params,
+                    self.reset_delay(),
+                    bitstream,
+                    ft_individualize_data_in,
+                    alert_cfg,
+                    &self.retry,
+                ),
+            }
+        })
+    }
+
+    /// Transitions the device from a `TEST_UNLOCKED1` to `TEST_UNLOCKED7` state to
+    /// `target_mission_mode_lc_state`.
+    ///
+    /// A test exit token of another generation than `token_generation` fails with a
+    /// `ProvisioningError::TokenGenerationMismatch`: checked against its hash in OTP while SECRET0
+    /// can still be read back, or else from the token error of the LC controller.
+    #[allow(deprecated)]
+    pub fn test_exit(
+        &self,
+        test_exit_token: &LcTokenSecret,
+        token_generation: &str,
+        target_mission_mode_lc_state: DifLcCtrlState,
+    ) -> Result<()> {
+        self.require(Capability::LcTransition, "Test exit")?;
+        self.step("test-exit", || {
+            check_test_exit_token(
+                self.transport,
+                &self.init.jtag_params,
+                test_exit_token,
+                token_generation,
+                &self.retry,
+            )?;
+            self.journal.enter("test-exit", "lc-transition")?;
+            test_exit(
+                self.transport,
+                &self.init.jtag_params,
+                self.reset_delay(),
+                test_exit_token,
+                target_mission_mode_lc_state,
+                &self.lc_state_check,
+                self.timeouts.lc_transition,
+                &self.retry,
+                self.events(),
+            )
+            .map_err(|e| check_test_exit_token_error(e, token_generation))?;
+            self.journal.set_rom_exec_enabled(false)
+        })
+    }
+
+    /// Provisions the OTP secrets and endorses the device certificates.
+    #[allow(clippy::too_many_arguments, deprecated)]
+    pub fn personalize(
+        &self,
+        rma_unlock_token: &LcTokenSecret,
+        ca_cfgs: HashMap<String, CaConfig>,
+        ca_keys: HashMap<String, CaKey>,
+        perso_certgen_inputs: &ManufCertgenInputs,
+        export_options: PersoExportOptions,
+        creator_manuf_state: Option<CreatorManufState>,
+        device_log_level: DeviceLogLevel,
+        entropy_check: &EntropyCheck,
+        second_bootstrap: PathBuf,
+        response: &mut PersonalizeResponse,
+    ) -> Result<()> {
+        self.require(Capability::OtpWrite, "FT personalization")?;
+        self.require_reset("FT personalization")?;
+        self.step("personalize", || {
+            run_ft_personalize(
+                self.transport,
+                self.init,
+                rma_unlock_token,
+                ca_cfgs,
+                ca_keys,
+                &self.cert_policy,
+                self.offline_certs.as_ref(),
+                perso_certgen_inputs,
+                export_options,
+                creator_manuf_state,
+                self.keymgr_diversification.as_ref(),
+                device_log_level,
+                entropy_check,
+                second_bootstrap,
+                &self.console,
+                &self.timeouts,
+                &self.retry,
+                &self.command_ids,
+                &self.journal,
+                response,
+            )
+        })
+    }
+}
@@ -0,0 +1,132 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Release manifests pinning the `ft` versions and firmware images approved for a SKU.
+//!
+//! A release manifest is a JSON document signed by the release owner:
+//!
+//! ```json
+//! {
+//!   "sku": "sival",
+//!   "tool_versions": ["earlgrey_1.0.0"],
+//!   "firmware": {
+//!     "individualize": ["<sha256 hex>"],
+//!     "personalize": ["<sha256 hex>"],
+//!     "fw_bundle": ["<sha256 hex>"]
+//!   }
+//! }
+//! ```
+//!
+//! The detached signature is an ECDSA P-256 signature (raw `R || S` as produced by
+//! `opentitantool ecdsa sign`, or ASN.1 DER) of the SHA-256 digest of the manifest file.
+
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use clap::ValueEnum;
+use indexmap::IndexMap;
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use opentitanlib::crypto::ecdsa::{EcdsaPublicKey, EcdsaRawSignature};
+use opentitanlib::crypto::sha256::sha256;
+
+/// How to handle a tool / firmware combination not approved by the release manifest.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, ValueEnum)]
+pub enum ReleasePolicy {
+    /// Refuse to run.
+    #[default]
+    Enforce,
+    /// Log a warning and run anyway.
+    Warn,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReleaseManifest {
+    /// SKU the release is approved for.
+    pub sku: String,
+    /// Approved versions (`git describe` output) of the `ft` tool.
+    pub tool_versions: Vec<String>,
+    /// Approved SHA-256 digests (hex) of the firmware images, per image role.
+    #[serde(default)]
+    pub firmware: IndexMap<String, Vec<String>>,
+}
+
+impl ReleaseManifest {
+    /// Loads a release manifest, after checking its detached `signature` against the DER
+    /// encoded public `key`.
+    pub fn load_signed(path: &Path, signature: &Path, key: &Path) -> Result<Self> {
+        let doc = std::fs::read(path)
+            .with_context(|| format!("Failed to read release manifest {path:?}"))?;
+        let key = EcdsaPublicKey::load(key)
+            .with_context(|| format!("Failed to load release manifest key {key:?}"))?;
+        let signature = EcdsaRawSignature::read_from_file(signature)?;
+        key.verify(&sha256(&doc), &signature)
+            .with_context(|| format!("Invalid signature of release manifest {path:?}"))?;
+        serde_json::from_slice(&doc)
+            .with_context(|| format!("Failed to parse release manifest {path:?}"))
+    }
+
+    /// Returns the reasons why running `tool_version` with the `firmware` images (role and path)
+    /// on `sku` is not approved by the manifest.
+    pub fn violations(
+        &self,
+        sku: &str,
+        tool_version: Option<&str>,
+        firmware: &[(&str, &Path)],
+    ) -> Result<Vec<String>> {
+        let mut violations = Vec::new();
+        if self.sku != sku {
+            violations.push(format!(
+                "release manifest is for SKU {}, not {sku}",
+                self.sku
+            ));
+        }
+        match tool_version {
+            Some(version) if self.tool_versions.iter().any(|v| v == version) => {}
+            Some(version) => violations.push(format!("tool version {version} is not approved")),
+            None => violations.push("tool is not stamped with a version".into()),
+        }
+        for (role, path) in firmware {
+            let image =
+                std::fs::read(path).with_context(|| format!("Failed to read {role} {path:?}"))?;
+            let digest = hex::encode(Sha256::digest(&image));
+            let approved = self.firmware.get(*role).map(Vec::as_slice).unwrap_or(&[]);
+            if !approved.iter().any(|d| d.eq_ignore_ascii_case(&digest)) {
+                violations.push(format!(
+                    "{role} image {path:?} (sha256 {digest}) is not approved"
+                ));
+            }
+        }
+        Ok(violations)
+    }
+
+    /// Checks running `tool_version` with the `firmware` images on `sku` is approved, handling
+    /// violations according to `policy`.
+    pub fn check(
+        &self,
+        policy: ReleasePolicy,
+        sku: &str,
+        tool_version: Option<&str>,
+        firmware: &[(&str, &Path)],
+    ) -> Result<()> {
+        let violations = self.violations(sku, tool_version, firmware)?;
+        if violations.is_empty() {
+            log::info!("Release approved for SKU {sku}.");
+            return Ok(());
+        }
+        match policy {
+            ReleasePolicy::Enforce => {
+                bail!("Unapproved release: {}", violations.join("; "))
+            }
+            ReleasePolicy::Warn => {
+                for violation in &violations {
+                    log::warn!("Unapproved release: {violation}");
+                }
+                Ok(())
+            }
+        }
+    }
+}
@@ -2,22 +2,36 @@
 // Licensed under the Apache License, Version 2.0, see LICENSE for details.
 // SPDX-License-Identifier: Apache-2.0
 
-use std::time::Instant;
-
+use anyhow::{ensure, Result};
+use cert_lib::extensions::{cert_extensions_schema, CertExtensions};
 use cert_lib::EndorsedCert;
 use indexmap::IndexMap;
 use opentitanlib::dif::lc_ctrl::DifLcCtrlState;
 use serde::Serialize;
+use serde_json::{json, Value};
 
-#[derive(Clone, Debug, Serialize)]
-#[serde(rename_all = "snake_case")]
-pub enum Stat {
-    Microseconds(u64),
-    String(String),
-}
+pub use provisioning::report::{Stat, Statistics};
 
-#[derive(Clone, Debug, Serialize, Default)]
-pub struct Statistics(IndexMap<String, Stat>);
+use crate::health::{health_snapshot_schema, HealthSnapshot};
+use crate::keymgr_binding::KeymgrBinding;
+use crate::smoke_test::{smoke_test_results_schema, SmokeTestResult};
+use crate::telemetry::{telemetry_samples_schema, TelemetrySample};
+
+/// Version of the `PersonalizeResponse` JSON report format.
+///
+/// Bump this whenever a field is added, removed or changes meaning, and update
+/// `personalize_response_schema()` accordingly.
+pub const PERSONALIZE_RESPONSE_SCHEMA_VERSION: u32 = 6;
+
+/// Schema version embedded in every serialized report.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct SchemaVersion(pub u32);
+
+impl Default for SchemaVersion {
+    fn default() -> Self {
+        SchemaVersion(PERSONALIZE_RESPONSE_SCHEMA_VERSION)
+    }
+}
 
 #[derive(Clone, Debug, Serialize, Default)]
 pub struct DevSeedResponse {
@@ -35,23 +49,141 @@ pub struct LcStateSequence {
 
 #[derive(Clone, Debug, Serialize, Default)]
 pub struct PersonalizeResponse {
+    pub schema_version: SchemaVersion,
     pub lc_state: LcStateSequence,
     pub device_id: String,
     pub rma_unlock_token: String,
     pub seeds: DevSeedResponse,
     pub certs: IndexMap<String, EndorsedCert>,
+    /// Decoded extensions of the X.509 certificates exported by the device, by cert name.
+    #[serde(skip_serializing_if = "IndexMap::is_empty")]
+    pub cert_extensions: IndexMap<String, CertExtensions>,
     pub stats: Statistics,
+    /// Keymgr sealing binding values bound by the device during personalization.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub keymgr_binding: Option<KeymgrBinding>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub health: Option<HealthSnapshot>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub smoke_tests: Vec<SmokeTestResult>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub telemetry: Vec<TelemetrySample>,
 }
 
-impl Statistics {
-    pub fn log_elapsed_time(&mut self, name: &str, start: Instant) {
-        let end = Instant::now();
-        let duration = end - start;
-        self.0
-            .insert(name.into(), Stat::Microseconds(duration.as_micros() as u64));
+impl PersonalizeResponse {
+    /// Fails if any of the smoke tests run on the device failed.
+    pub fn check_smoke_tests(&self) -> Result<()> {
+        let failed = self
+            .smoke_tests
+            .iter()
+            .filter(|t| !t.passed)
+            .map(|t| t.name.as_str())
+            .collect::<Vec<_>>();
+        ensure!(
+            failed.is_empty(),
+            "Smoke tests failed: {}",
+            failed.join(", ")
+        );
+        Ok(())
     }
+}
 
-    pub fn log_string(&mut self, name: &str, val: &str) {
-        self.0.insert(name.into(), Stat::String(val.into()));
-    }
+/// Returns the JSON Schema (draft 2020-12) describing the `PersonalizeResponse`
+/// report format identified by `PERSONALIZE_RESPONSE_SCHEMA_VERSION`.
+pub fn personalize_response_schema() -> Value {
+    let lc_state = json!({ "type": "string" });
+    let optional_lc_state = json!({ "type": ["string", "null"] });
+    let lc_state_sequence = json!({
+        "type": "object",
+        "required": ["initial", "unlocked", "individualize", "mission_mode"],
+        "properties": {
+            "initial": lc_state,
+            "unlocked": lc_state,
+            "individualize": optional_lc_state,
+            "mission_mode": optional_lc_state
+        }
+    });
+    let seeds = json!({
+        "type": "object",
+        "required": ["number", "seed"],
+        "properties": {
+            "number": { "type": "integer", "minimum": 0 },
+            "seed": {
+                "type": "array",
+                "items": {
+                    "type": "array",
+                    "items": { "type": "integer", "minimum": 0, "maximum": 255 }
+                }
+            }
+        }
+    });
+    let endorsed_cert = json!({
+        "type": "object",
+        "required": ["format", "name", "bytes", "ignore_critical"],
+        "properties": {
+            "format": { "enum": ["X509", "Cwt"] },
+            "name": { "type": "string" },
+            "bytes": { "description": "Base64 encoded certificate.", "type": "string" },
+            "ignore_critical": { "type": "boolean" }
+        }
+    });
+    let keymgr_binding = json!({
+        "type": "object",
+        "required": ["owner_int_sealing", "owner_sealing"],
+        "properties": {
+            "owner_int_sealing": { "type": "string", "pattern": "^[0-9a-f]{64}$" },
+            "owner_sealing": { "type": "string", "pattern": "^[0-9a-f]{64}$" }
+        }
+    });
+    let stat = json!({
+        "oneOf": [
+            {
+                "type": "object",
+                "required": ["microseconds"],
+                "properties": { "microseconds": { "type": "integer", "minimum": 0 } },
+                "additionalProperties": false
+            },
+            {
+                "type": "object",
+                "required": ["string"],
+                "properties": { "string": { "type": "string" } },
+                "additionalProperties": false
+            }
+        ]
+    });
+    json!({
+        "$schema": "https://json-schema.org/draft/2020-12/schema",
+        "$id": format!(
+            "https://opentitan.org/schemas/provisioning/ft_personalize_response/v{}.json",
+            PERSONALIZE_RESPONSE_SCHEMA_VERSION
+        ),
+        "title": "OpenTitan FT personalization report",
+        "type": "object",
+        "required": [
+            "schema_version",
+            "lc_state",
+            "device_id",
+            "rma_unlock_token",
+            "seeds",
+            "certs",
+            "stats"
+        ],
+        "properties": {
+            "schema_version": { "const": PERSONALIZE_RESPONSE_SCHEMA_VERSION },
+            "lc_state": lc_state_sequence,
+            "device_id": { "type": "string", "pattern": "^[0-9A-F]*$" },
+            "rma_unlock_token": {
+                "description": "Base64 encoded RMA unlock token, encrypted with the token encryption key.",
+                "type": "string"
+            },
+            "seeds": seeds,
+            "certs": { "type": "object", "additionalProperties": endorsed_cert },
+            "cert_extensions": cert_extensions_schema(),
+            "stats": { "type": "object", "additionalProperties": stat },
+            "keymgr_binding": keymgr_binding,
+            "health": health_snapshot_schema(),
+            "smoke_tests": smoke_test_results_schema(),
+            "telemetry": telemetry_samples_schema()
+        }
+    })
 }
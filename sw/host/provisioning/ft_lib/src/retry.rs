@@ -0,0 +1,280 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Retries of the idempotent operations of the flow on transient failures.
+//!
+//! UART timeouts and JTAG connect glitches are common on factory harnesses. Rather than failing
+//! the whole device on the first glitch, the operations which can safely be repeated, i.e.
+//! connecting to a JTAG TAP, waiting for a console message and receiving a ujson response, are
+//! retried as allowed by a `RetryPolicy`, logging each retry. A retried wait only sees the output
+//! printed after the failed attempt timed out: retries cover devices late to print a message, not
+//! messages lost on the line.
+
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Result;
+use clap::{Args, ValueEnum};
+use humantime::parse_duration;
+
+use opentitanlib::app::TransportWrapper;
+use opentitanlib::io::console::{ConsoleDevice, ConsoleError};
+use opentitanlib::io::jtag::{Jtag, JtagParams, JtagTap};
+use opentitanlib::test_utils::rpc::ConsoleRecv;
+
+use crate::error::{wait_for, ProvisioningError};
+
+/// A class of transient failures a `RetryPolicy` may retry.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, ValueEnum)]
+pub enum RetryOn {
+    /// Failures to connect to a JTAG TAP.
+    JtagConnect,
+    /// Timeouts waiting for a console message or a ujson response.
+    ConsoleTimeout,
+}
+
+/// How the idempotent operations of the flow are retried on transient failures.
+#[derive(Clone, Debug, Args)]
+pub struct RetryPolicy {
+    /// Number of attempts of an idempotent operation before failing the device; 1 fails on the
+    /// first error.
+    #[arg(
+        long = "retry-max-attempts",
+        default_value = "1",
+        value_parser = clap::value_parser!(u32).range(1..)
+    )]
+    pub max_attempts: u32,
+
+    /// Delay before the first retry, doubled after each retry.
+    #[arg(long = "retry-backoff", value_parser = parse_duration, default_value = "100ms")]
+    pub backoff: Duration,
+
+    /// Comma-separated list of the failures to retry.
+    #[arg(
+        long = "retry-on",
+        value_enum,
+        value_delimiter = ',',
+        default_values_t = RetryOn::value_variants().to_vec()
+    )]
+    pub retry_on: Vec<RetryOn>,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_attempts: 1,
+            backoff: Duration::from_millis(100),
+            retry_on: RetryOn::value_variants().to_vec(),
+        }
+    }
+}
+
+/// Whether `error` is a timeout of a console wait or a ujson reception.
+fn is_console_timeout(error: &anyhow::Error) -> bool {
+    matches!(
+        ProvisioningError::find(error),
+        Some(ProvisioningError::Timeout { .. })
+    ) || error.chain().any(|e| {
+        matches!(
+            e.downcast_ref::<ConsoleError>(),
+            Some(ConsoleError::GenericError(msg)) if msg.starts_with("Timed Out")
+        )
+    })
+}
+
+impl RetryPolicy {
+    /// Runs `operation` until it succeeds, fails with an error not retried on `failure`, or runs
+    /// out of attempts.
+    pub fn run<T>(
+        &self,
+        failure: RetryOn,
+        name: &str,
+        mut operation: impl FnMut() -> Result<T>,
+    ) -> Result<T> {
+        let mut backoff = self.backoff;
+        let mut attempt = 1;
+        loop {
+            match operation() {
+                Ok(value) => {
+                    if attempt > 1 {
+                        log::info!("{name} succeeded after {attempt} attempts.");
+                    }
+                    return Ok(value);
+                }
+                Err(e) => {
+                    let transient = match failure {
+                        RetryOn::JtagConnect => true,
+                        RetryOn::ConsoleTimeout => is_console_timeout(&e),
+                    };
+                    if !(transient && self.retry_on.contains(&failure))
+                        || attempt >= self.max_attempts
+                    {
+                        return Err(e);
+                    }
+                    log::warn!(
+                        "{name} failed (attempt {attempt}/{}), retrying in {backoff:?}: {e:#}",
+                        self.max_attempts
+                    );
+                    thread::sleep(backoff);
+                    backoff *= 2;
+                    attempt += 1;
+                }
+            }
+        }
+    }
+
+    /// Connects to `tap`, as `JtagParams::create` and `connect`.
+    pub(crate) fn connect_jtag(
+        &self,
+        transport: &TransportWrapper,
+        jtag_params: &JtagParams,
+        tap: JtagTap,
+    ) -> Result<Box<dyn Jtag>> {
+        self.run(
+            RetryOn::JtagConnect,
+            &format!("Connecting to the {tap:?}"),
+            || jtag_params.create(transport)?.connect(tap),
+        )
+    }
+
+    /// Waits for the device to print `rx` on `console`, as `error::wait_for`.
+    pub(crate) fn wait_for<T>(
+        &self,
+        console: &T,
+        rx: &str,
+        timeout: Duration,
+    ) -> Result<Vec<String>>
+    where
+        T: ConsoleDevice + ?Sized,
+    {
+        self.run(
+            RetryOn::ConsoleTimeout,
+            &format!("Waiting for `{rx}`"),
+            || wait_for(console, rx, timeout),
+        )
+    }
+
+    /// Receives a `U` ujson response on `console`, as `ConsoleRecv::recv_window`.
+    pub(crate) fn recv<T, U>(&self, console: &T, timeout: Duration, quiet: bool) -> Result<U>
+    where
+        T: ConsoleDevice + ?Sized,
+        U: ConsoleRecv<T>,
+    {
+        self.run(
+            RetryOn::ConsoleTimeout,
+            &format!(
+                "Receiving a {}",
+                std::any::type_name::<U>().rsplit("::").next().unwrap()
+            ),
+            || U::recv_window(console, timeout, quiet),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use anyhow::{anyhow, Context};
+    use std::time::Instant;
+
+    fn policy(max_attempts: u32) -> RetryPolicy {
+        RetryPolicy {
+            max_attempts,
+            backoff: Duration::from_millis(1),
+            ..Default::default()
+        }
+    }
+
+    fn timeout() -> anyhow::Error {
+        ProvisioningError::Timeout {
+            expected: "PASS".into(),
+            timeout: Duration::from_secs(1),
+        }
+        .into()
+    }
+
+    /// Runs an operation failing with `error` on its first `failures` attempts, returning the
+    /// result of `policy` and the number of attempts.
+    fn run(
+        policy: &RetryPolicy,
+        failure: RetryOn,
+        failures: u32,
+        error: impl Fn() -> anyhow::Error,
+    ) -> (Result<u32>, u32) {
+        let mut attempts = 0;
+        let result = policy.run(failure, "test", || {
+            attempts += 1;
+            if attempts <= failures {
+                Err(error())
+            } else {
+                Ok(attempts)
+            }
+        });
+        (result, attempts)
+    }
+
+    #[test]
+    fn test_attempts() {
+        let (result, attempts) = run(&policy(3), RetryOn::ConsoleTimeout, 2, timeout);
+        assert_eq!((result.unwrap(), attempts), (3, 3));
+        let (result, attempts) = run(&policy(2), RetryOn::ConsoleTimeout, 2, timeout);
+        assert!(ProvisioningError::find(&result.unwrap_err()).is_some());
+        assert_eq!(attempts, 2);
+        // By default, operations are attempted once.
+        let (result, attempts) = run(&RetryPolicy::default(), RetryOn::JtagConnect, 1, || {
+            anyhow!("no TAP")
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_transient_failures() {
+        // Any JTAG connect failure is retried...
+        let (result, attempts) = run(&policy(3), RetryOn::JtagConnect, 2, || anyhow!("no TAP"));
+        assert_eq!((result.unwrap(), attempts), (3, 3));
+        // ...but only the timeouts of console operations, under any context.
+        let console_timeout = || {
+            Err::<(), _>(ConsoleError::GenericError("Timed Out".into()))
+                .context("Failed to receive a response")
+                .unwrap_err()
+        };
+        let (result, attempts) = run(&policy(3), RetryOn::ConsoleTimeout, 2, console_timeout);
+        assert_eq!((result.unwrap(), attempts), (3, 3));
+        let (result, attempts) = run(&policy(3), RetryOn::ConsoleTimeout, 2, || {
+            anyhow!("Invalid response")
+        });
+        assert_eq!(result.unwrap_err().to_string(), "Invalid response");
+        assert_eq!(attempts, 1);
+
+        // Failures not listed in `retry_on` are not retried.
+        let policy = RetryPolicy {
+            retry_on: vec![RetryOn::JtagConnect],
+            ..policy(3)
+        };
+        let (result, attempts) = run(&policy, RetryOn::ConsoleTimeout, 2, timeout);
+        assert!(result.is_err());
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn test_backoff() {
+        let policy = RetryPolicy {
+            max_attempts: 4,
+            backoff: Duration::from_millis(10),
+            ..Default::default()
+        };
+        let mut attempts = Vec::new();
+        let result: Result<()> = policy.run(RetryOn::JtagConnect, "test", || {
+            attempts.push(Instant::now());
+            Err(anyhow!("no TAP"))
+        });
+        assert!(result.is_err());
+        assert_eq!(attempts.len(), 4);
+        // The delay doubles after each retry.
+        for (i, pair) in attempts.windows(2).enumerate() {
+            assert!(pair[1] - pair[0] >= Duration::from_millis(10 << i));
+        }
+    }
+}
@@ -0,0 +1,121 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! RMA escrow envelopes, carrying the RMA unlock token of a device to the RMA support team.
+//!
+//! The envelope is a CMS (PKCS#7) EnvelopedData structure, encrypted to the certificate of the
+//! RMA support team, so it can be opened by standard key escrow tooling, e.g.:
+//!
+//! ```text
+//! openssl cms -decrypt -inform DER -in <device_id>.rma.p7m -recip rma.pem -inkey rma.key
+//! ```
+//!
+//! The envelope content is a JSON `RmaEscrowRecord`.
+
+use std::path::Path;
+
+use anyhow::{Context, Result};
+use openssl::cms::{CMSOptions, CmsContentInfo};
+use openssl::stack::Stack;
+use openssl::symm::Cipher;
+use openssl::x509::X509;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use cert_lib::EndorsedCert;
+use ot_certs::CertFormat;
+
+use crate::keymgr_binding::KeymgrBinding;
+use crate::secret::{LcTokenSecret, Secret};
+
+/// Version of the RMA escrow record format.
+pub const RMA_ESCROW_SCHEMA_VERSION: u32 = 2;
+
+/// Metadata of a certificate endorsed during personalization.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct CertMetadata {
+    pub name: String,
+    pub format: CertFormat,
+    /// Hex string of the SHA-256 digest of the certificate.
+    pub sha256: String,
+}
+
+impl CertMetadata {
+    pub fn new(cert: &EndorsedCert) -> Self {
+        Self {
+            name: cert.name.clone(),
+            format: cert.format.clone(),
+            sha256: hex::encode(Sha256::digest(&cert.bytes)),
+        }
+    }
+}
+
+/// Sensitive outputs of the personalization of a device.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct RmaEscrowRecord {
+    pub schema_version: u32,
+    /// SKU the device was personalized for.
+    pub sku: String,
+    pub device_id: String,
+    /// RMA unlock token; a 128-bit hex string, as passed on the command line.
+    pub rma_unlock_token: Secret<String>,
+    pub certs: Vec<CertMetadata>,
+    /// Keymgr sealing binding values bound by the device during personalization.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub keymgr_binding: Option<KeymgrBinding>,
+}
+
+impl RmaEscrowRecord {
+    pub fn new<'a>(
+        sku: &str,
+        device_id: &str,
+        rma_unlock_token: &LcTokenSecret,
+        certs: impl IntoIterator<Item = &'a EndorsedCert>,
+    ) -> Self {
+        Self {
+            schema_version: RMA_ESCROW_SCHEMA_VERSION,
+            sku: sku.to_string(),
+            device_id: device_id.to_string(),
+            rma_unlock_token: rma_unlock_token.to_hex(),
+            certs: certs.into_iter().map(CertMetadata::new).collect(),
+            keymgr_binding: None,
+        }
+    }
+
+    /// Returns this record, with the keymgr sealing binding values of the device.
+    pub fn with_keymgr_binding(mut self, binding: Option<KeymgrBinding>) -> Self {
+        self.keymgr_binding = binding;
+        self
+    }
+
+    /// Encrypts the record to `recipient` in a DER-encoded CMS EnvelopedData structure.
+    pub fn envelope(&self, recipient: &X509) -> Result<Vec<u8>> {
+        let mut certs = Stack::new()?;
+        certs.push(recipient.clone())?;
+        let content = Secret::new(serde_json::to_vec(self)?);
+        let envelope = CmsContentInfo::encrypt(
+            &certs,
+            content.expose(),
+            Cipher::aes_256_cbc(),
+            CMSOptions::BINARY,
+        )
+        .context("Failed to encrypt the RMA escrow envelope")?;
+        Ok(envelope.to_der()?)
+    }
+
+    /// Encrypts the record to `recipient`, and saves the envelope to `path`.
+    pub fn save_envelope(&self, path: &Path, recipient: &X509) -> Result<()> {
+        std::fs::write(path, self.envelope(recipient)?)
+            .with_context(|| format!("Failed to write RMA escrow envelope {path:?}"))
+    }
+}
+
+/// Loads the certificate of the RMA support team, in PEM or DER format.
+pub fn load_recipient_cert(path: &Path) -> Result<X509> {
+    let bytes = std::fs::read(path)
+        .with_context(|| format!("Failed to read RMA escrow certificate {path:?}"))?;
+    X509::from_pem(&bytes)
+        .or_else(|_| X509::from_der(&bytes))
+        .with_context(|| format!("Failed to parse RMA escrow certificate {path:?}"))
+}
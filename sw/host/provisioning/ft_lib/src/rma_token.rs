@@ -0,0 +1,581 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Wrapped RMA unlock tokens, as archived by the FT personalize step.
+//!
+//! The RMA unlock token is wrapped on the host, before it is sent to the device, to the
+//! `rma_wrap_key` public key of the key bundle, with the suite of the key type:
+//!
+//! - RSA keys: PKCS#1 v1.5 encryption (`rsa-pkcs1-v1_5`).
+//! - X25519 keys, for SKUs whose HSMs only expose Curve25519 operations
+//!   (`x25519-hkdf-sha256-aes-256-gcm`): an ephemeral X25519 key agreement with the wrap key,
+//!   whose shared secret is expanded to a one-time AES-256-GCM key by HKDF-SHA256, with the salt
+//!   `ephemeral_public_key || wrap_public_key` and the info `"OpenTitan RMA unlock token"`. The
+//!   wrapped token is `ephemeral_public_key (32) || ciphertext (16) || tag (16)`, encrypted with a
+//!   zero nonce, and unwrapped with an X25519 derive operation of the HSM.
+//! - NIST P-384 keys, for SKUs whose key management policy mandates P-384
+//!   (`ecdh-p384-hkdf-sha384-aes-256-gcm`): as the X25519 suite, with an ephemeral P-384 ECDH key
+//!   agreement and HKDF-SHA384. The public keys are uncompressed SEC1 points, so the wrapped token
+//!   is `ephemeral_public_key (97) || ciphertext (16) || tag (16)`.
+//!
+//! The key type, and so the suite, is chosen by the key bundle of the SKU; a SKU may also mandate
+//! a suite, see `check_sku_wrapping`.
+//!
+//! The token is the TRANSITION_TOKEN register values, each in little-endian byte order. It is
+//! archived as `<device_id>.rma_token.bin`, the raw wrapped token, and `<device_id>.rma_token.json`,
+//! a `WrappedRmaToken` record naming the suite, or in another `rma_token_escrow::TokenEscrow`. The
+//! RMA desk recovers the token with the matching private key.
+
+use std::path::Path;
+
+use anyhow::{anyhow, ensure, Context, Result};
+use base64ct::{Base64, Encoding};
+use openssl::bn::BigNumContext;
+use openssl::derive::Deriver;
+use openssl::ec::{EcGroup, EcKey, EcPoint, PointConversionForm};
+use openssl::md::{Md, MdRef};
+use openssl::nid::Nid;
+use openssl::pkey::{HasParams, HasPublic, Id, PKey, Private, Public};
+use openssl::pkey_ctx::PkeyCtx;
+use openssl::symm::{decrypt_aead, encrypt_aead, Cipher};
+use rsa::{RsaPrivateKey, RsaPublicKey};
+use serde::{Deserialize, Serialize};
+use zerocopy::IntoBytes;
+
+use ujson_lib::limits::LC_TOKEN_WORDS;
+use util_lib::{decrypt_token, encrypt_token, load_rsa_private_key, parse_rsa_public_key};
+
+use crate::secret::{LcTokenSecret, Secret};
+
+/// Wrapping suite of the RMA unlock tokens wrapped to RSA keys.
+pub const RMA_TOKEN_WRAPPING: &str = "rsa-pkcs1-v1_5";
+/// Wrapping suite of the RMA unlock tokens wrapped to X25519 keys.
+pub const RMA_TOKEN_WRAPPING_X25519: &str = "x25519-hkdf-sha256-aes-256-gcm";
+/// Wrapping suite of the RMA unlock tokens wrapped to P-384 keys.
+pub const RMA_TOKEN_WRAPPING_P384: &str = "ecdh-p384-hkdf-sha384-aes-256-gcm";
+/// All the wrapping suites.
+pub const RMA_TOKEN_WRAPPINGS: [&str; 3] = [
+    RMA_TOKEN_WRAPPING,
+    RMA_TOKEN_WRAPPING_X25519,
+    RMA_TOKEN_WRAPPING_P384,
+];
+
+const X25519_KEY_SIZE: usize = 32;
+/// Size of an uncompressed SEC1 P-384 point.
+const P384_POINT_SIZE: usize = 1 + 2 * 48;
+const AES_GCM_TAG_SIZE: usize = 16;
+const HKDF_INFO: &[u8] = b"OpenTitan RMA unlock token";
+
+/// Wrapped RMA unlock token of a device, and where it comes from.
+#[derive(Clone, Debug, Serialize, Deserialize)]
+pub struct WrappedRmaToken {
+    pub device_id: String,
+    pub sku: String,
+    /// Name of the key bundle holding the key the token is wrapped to.
+    pub key_bundle: String,
+    /// Wrapping suite, one of `RMA_TOKEN_WRAPPINGS`.
+    pub wrapping: String,
+    /// Base64 string of the wrapped RMA unlock token.
+    pub wrapped_rma_unlock_token: String,
+}
+
+impl WrappedRmaToken {
+    pub fn new(
+        sku: &str,
+        device_id: &str,
+        key_bundle: &str,
+        wrapping: &str,
+        wrapped_token: &[u8],
+    ) -> Self {
+        Self {
+            device_id: device_id.to_string(),
+            sku: sku.to_string(),
+            key_bundle: key_bundle.to_string(),
+            wrapping: wrapping.to_string(),
+            wrapped_rma_unlock_token: Base64::encode_string(wrapped_token),
+        }
+    }
+
+    /// Parses a `WrappedRmaToken` JSON record.
+    pub fn from_json(json: &str) -> Result<Self> {
+        let record: Self =
+            serde_json::from_str(json).context("Failed to parse wrapped RMA unlock token")?;
+        ensure!(
+            RMA_TOKEN_WRAPPINGS.contains(&record.wrapping.as_str()),
+            "Unsupported RMA unlock token wrapping {:?}",
+            record.wrapping
+        );
+        Ok(record)
+    }
+
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read wrapped RMA unlock token {path:?}"))?;
+        Self::from_json(&json).with_context(|| format!("Invalid wrapped RMA unlock token {path:?}"))
+    }
+
+    pub fn wrapped_token(&self) -> Result<Vec<u8>> {
+        Base64::decode_vec(&self.wrapped_rma_unlock_token)
+            .map_err(|e| anyhow!("Invalid wrapped RMA unlock token: {e}"))
+    }
+}
+
+/// Derives the AES-256-GCM key of an X25519 or P-384 wrapping from the key agreement of
+/// `private_key` with `peer`, expanded by HKDF with `md`.
+fn ecdh_wrapping_key(
+    private_key: &PKey<Private>,
+    peer: &PKey<Public>,
+    md: &MdRef,
+    ephemeral_public_key: &[u8],
+    wrap_public_key: &[u8],
+) -> Result<Secret<[u8; 32]>> {
+    let mut deriver = Deriver::new(private_key)?;
+    deriver.set_peer(peer)?;
+    let shared_secret = Secret::new(deriver.derive_to_vec()?);
+    let mut hkdf = PkeyCtx::new_id(Id::HKDF)?;
+    hkdf.derive_init()?;
+    hkdf.set_hkdf_md(md)?;
+    hkdf.set_hkdf_key(shared_secret.expose())?;
+    hkdf.set_hkdf_salt(&[ephemeral_public_key, wrap_public_key].concat())?;
+    hkdf.add_hkdf_info(HKDF_INFO)?;
+    let mut key = Secret::new([0; 32]);
+    hkdf.derive(Some(key.expose_mut()))?;
+    Ok(key)
+}
+
+/// Encrypts `token` with the one-time AES-256-GCM key `aes_key`, returning `ciphertext || tag`.
+fn seal_token(aes_key: &Secret<[u8; 32]>, token: &LcTokenSecret) -> Result<Vec<u8>> {
+    let mut tag = [0; AES_GCM_TAG_SIZE];
+    let ciphertext = encrypt_aead(
+        Cipher::aes_256_gcm(),
+        aes_key.expose(),
+        Some(&[0; 12]),
+        &[],
+        token.expose().as_bytes(),
+        &mut tag,
+    )?;
+    Ok([ciphertext, tag.to_vec()].concat())
+}
+
+/// Decrypts the `ciphertext || tag` of `sealed` with `aes_key`, returning the token words.
+fn open_token(aes_key: &Secret<[u8; 32]>, sealed: &[u8]) -> Result<Secret<Vec<u32>>> {
+    let (ciphertext, tag) = sealed.split_at(sealed.len() - AES_GCM_TAG_SIZE);
+    let plaintext = Secret::new(decrypt_aead(
+        Cipher::aes_256_gcm(),
+        aes_key.expose(),
+        Some(&[0; 12]),
+        &[],
+        ciphertext,
+        tag,
+    )?);
+    ensure!(
+        plaintext.expose().len() % 4 == 0,
+        "Unwrapped token is not a whole number of words"
+    );
+    Ok(Secret::new(
+        plaintext
+            .expose()
+            .chunks(4)
+            .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+            .collect(),
+    ))
+}
+
+fn p384_group() -> Result<EcGroup> {
+    Ok(EcGroup::from_curve_name(Nid::SECP384R1)?)
+}
+
+/// Returns the uncompressed SEC1 encoding of the P-384 public key of `key`.
+fn p384_point<T: HasPublic>(key: &PKey<T>) -> Result<Vec<u8>> {
+    let key = key.ec_key()?;
+    let mut ctx = BigNumContext::new()?;
+    Ok(key
+        .public_key()
+        .to_bytes(key.group(), PointConversionForm::UNCOMPRESSED, &mut ctx)?)
+}
+
+/// Returns whether `key` is a P-384 EC key.
+fn is_p384<T: HasParams>(key: &PKey<T>) -> bool {
+    key.id() == Id::EC
+        && key
+            .ec_key()
+            .is_ok_and(|k| k.group().curve_name() == Some(Nid::SECP384R1))
+}
+
+/// Public key of the key bundle the RMA unlock tokens are wrapped to.
+pub enum RmaWrapKey {
+    Rsa(RsaPublicKey),
+    X25519(PKey<Public>),
+    P384(PKey<Public>),
+}
+
+impl RmaWrapKey {
+    /// Parses an SPKI DER X25519 or P-384 public key, or a PKCS#1 or SPKI DER RSA public key.
+    pub fn from_der(der: &[u8]) -> Result<Self> {
+        match PKey::public_key_from_der(der) {
+            Ok(key) if key.id() == Id::X25519 => Ok(Self::X25519(key)),
+            Ok(key) if is_p384(&key) => Ok(Self::P384(key)),
+            _ => Ok(Self::Rsa(parse_rsa_public_key(der).context(
+                "RMA wrap key is neither an X25519, P-384 nor RSA key",
+            )?)),
+        }
+    }
+
+    /// Returns the wrapping suite of the key.
+    pub fn wrapping(&self) -> &'static str {
+        match self {
+            Self::Rsa(_) => RMA_TOKEN_WRAPPING,
+            Self::X25519(_) => RMA_TOKEN_WRAPPING_X25519,
+            Self::P384(_) => RMA_TOKEN_WRAPPING_P384,
+        }
+    }
+
+    /// Checks the suite of the key is `sku_wrapping`, if the SKU mandates one.
+    pub fn check_sku_wrapping(&self, sku: &str, sku_wrapping: Option<&str>) -> Result<()> {
+        if let Some(sku_wrapping) = sku_wrapping {
+            ensure!(
+                self.wrapping() == sku_wrapping,
+                "SKU {sku} wraps the RMA unlock tokens with {sku_wrapping}, but the RMA wrap key \
+                 of the key bundle is for {}",
+                self.wrapping()
+            );
+        }
+        Ok(())
+    }
+
+    /// Wraps the RMA unlock token `token` to the key.
+    pub fn wrap(&self, token: &LcTokenSecret) -> Result<Vec<u8>> {
+        match self {
+            Self::Rsa(key) => encrypt_token(key, token.expose()),
+            Self::X25519(key) => {
+                let ephemeral = PKey::generate_x25519()?;
+                let ephemeral_public_key = ephemeral.raw_public_key()?;
+                let aes_key = ecdh_wrapping_key(
+                    &ephemeral,
+                    key,
+                    Md::sha256(),
+                    &ephemeral_public_key,
+                    &key.raw_public_key()?,
+                )?;
+                Ok([ephemeral_public_key, seal_token(&aes_key, token)?].concat())
+            }
+            Self::P384(key) => {
+                let group = p384_group()?;
+                let ephemeral = PKey::from_ec_key(EcKey::generate(&group)?)?;
+                let ephemeral_public_key = p384_point(&ephemeral)?;
+                let aes_key = ecdh_wrapping_key(
+                    &ephemeral,
+                    key,
+                    Md::sha384(),
+                    &ephemeral_public_key,
+                    &p384_point(key)?,
+                )?;
+                Ok([ephemeral_public_key, seal_token(&aes_key, token)?].concat())
+            }
+        }
+    }
+}
+
+/// Private key of the `rma_wrap_key` of a key bundle, unwrapping its RMA unlock tokens.
+pub enum RmaUnwrapKey {
+    Rsa(Box<RsaPrivateKey>),
+    X25519(PKey<Private>),
+    P384(PKey<Private>),
+}
+
+impl RmaUnwrapKey {
+    /// Loads a PKCS#8 DER or PEM X25519 or P-384 private key, or a PKCS#1 or PKCS#8 DER RSA
+    /// private key.
+    pub fn load(path: &Path) -> Result<Self> {
+        let bytes = Secret::new(
+            std::fs::read(path).with_context(|| format!("Failed to read RMA wrap key {path:?}"))?,
+        );
+        match PKey::private_key_from_der(bytes.expose())
+            .or_else(|_| PKey::private_key_from_pem(bytes.expose()))
+        {
+            Ok(key) if key.id() == Id::X25519 => Ok(Self::X25519(key)),
+            Ok(key) if is_p384(&key) => Ok(Self::P384(key)),
+            _ => Ok(Self::Rsa(Box::new(
+                load_rsa_private_key(path).with_context(|| {
+                    format!("RMA wrap key {path:?} is neither an X25519, P-384 nor RSA key")
+                })?,
+            ))),
+        }
+    }
+
+    /// Returns the wrapping suite of the key.
+    pub fn wrapping(&self) -> &'static str {
+        match self {
+            Self::Rsa(_) => RMA_TOKEN_WRAPPING,
+            Self::X25519(_) => RMA_TOKEN_WRAPPING_X25519,
+            Self::P384(_) => RMA_TOKEN_WRAPPING_P384,
+        }
+    }
+
+    fn unwrap_words(&self, wrapped_token: &[u8]) -> Result<Secret<Vec<u32>>> {
+        match self {
+            Self::Rsa(key) => decrypt_token(key, wrapped_token).map(Secret::new),
+            Self::X25519(key) => {
+                ensure!(
+                    wrapped_token.len() > X25519_KEY_SIZE + AES_GCM_TAG_SIZE,
+                    "Wrapped RMA unlock token is only {} bytes long",
+                    wrapped_token.len()
+                );
+                let (ephemeral_public_key, sealed) = wrapped_token.split_at(X25519_KEY_SIZE);
+                let aes_key = ecdh_wrapping_key(
+                    key,
+                    &PKey::public_key_from_raw_bytes(ephemeral_public_key, Id::X25519)?,
+                    Md::sha256(),
+                    ephemeral_public_key,
+                    &key.raw_public_key()?,
+                )?;
+                open_token(&aes_key, sealed)
+            }
+            Self::P384(key) => {
+                ensure!(
+                    wrapped_token.len() > P384_POINT_SIZE + AES_GCM_TAG_SIZE,
+                    "Wrapped RMA unlock token is only {} bytes long",
+                    wrapped_token.len()
+                );
+                let (ephemeral_public_key, sealed) = wrapped_token.split_at(P384_POINT_SIZE);
+                let group = p384_group()?;
+                let mut ctx = BigNumContext::new()?;
+                let point = EcPoint::from_bytes(&group, ephemeral_public_key, &mut ctx)
+                    .context("Invalid ephemeral P-384 public key")?;
+                let aes_key = ecdh_wrapping_key(
+                    key,
+                    &PKey::from_ec_key(EcKey::from_public_key(&group, &point)?)?,
+                    Md::sha384(),
+                    ephemeral_public_key,
+                    &p384_point(key)?,
+                )?;
+                open_token(&aes_key, sealed)
+            }
+        }
+    }
+}
+
+/// Unwraps `wrapped_token`, wrapped with the suite `wrapping`, with the private key of the
+/// `rma_wrap_key` it was wrapped to, returning the RMA unlock token.
+pub fn unwrap_rma_token(
+    wrap_key: &RmaUnwrapKey,
+    wrapping: &str,
+    wrapped_token: &[u8],
+) -> Result<LcTokenSecret> {
+    ensure!(
+        wrapping == wrap_key.wrapping(),
+        "RMA unlock token is wrapped with {wrapping}, not the {} suite of the wrap key",
+        wrap_key.wrapping()
+    );
+    let token = wrap_key
+        .unwrap_words(wrapped_token)
+        .context("Failed to unwrap the RMA unlock token; is it wrapped to this key?")?;
+    let words = token.expose().as_slice();
+    ensure!(
+        words.len() == LC_TOKEN_WORDS,
+        "Unwrapped RMA unlock token is {} bits long, expected 128",
+        words.len() * 32
+    );
+    Ok(LcTokenSecret::new(words.try_into()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::rsa::Rsa;
+    use rsa::pkcs1::DecodeRsaPrivateKey;
+
+    const TOKEN: [u32; LC_TOKEN_WORDS] = [0x03020100, 0x07060504, 0x0b0a0908, 0x0f0e0d0c];
+
+    fn x25519_keys() -> (RmaWrapKey, RmaUnwrapKey) {
+        let private_key = PKey::generate_x25519().unwrap();
+        let wrap_key = RmaWrapKey::from_der(&private_key.public_key_to_der().unwrap()).unwrap();
+        (wrap_key, RmaUnwrapKey::X25519(private_key))
+    }
+
+    fn p384_keys() -> (RmaWrapKey, RmaUnwrapKey) {
+        let group = p384_group().unwrap();
+        let private_key = PKey::from_ec_key(EcKey::generate(&group).unwrap()).unwrap();
+        let wrap_key = RmaWrapKey::from_der(&private_key.public_key_to_der().unwrap()).unwrap();
+        (wrap_key, RmaUnwrapKey::P384(private_key))
+    }
+
+    fn rsa_keys() -> (RmaWrapKey, RmaUnwrapKey) {
+        let private_key = Rsa::generate(2048).unwrap();
+        let wrap_key =
+            RmaWrapKey::from_der(&private_key.public_key_to_der_pkcs1().unwrap()).unwrap();
+        let unwrap_key =
+            RsaPrivateKey::from_pkcs1_der(&private_key.private_key_to_der().unwrap()).unwrap();
+        (wrap_key, RmaUnwrapKey::Rsa(Box::new(unwrap_key)))
+    }
+
+    fn wrap(wrap_key: &RmaWrapKey) -> Vec<u8> {
+        wrap_key.wrap(&LcTokenSecret::new(TOKEN)).unwrap()
+    }
+
+    #[test]
+    fn test_x25519_roundtrip() {
+        let (wrap_key, unwrap_key) = x25519_keys();
+        assert_eq!(wrap_key.wrapping(), RMA_TOKEN_WRAPPING_X25519);
+        let wrapped = wrap(&wrap_key);
+        assert_eq!(wrapped.len(), X25519_KEY_SIZE + 16 + AES_GCM_TAG_SIZE);
+        let token = unwrap_rma_token(&unwrap_key, RMA_TOKEN_WRAPPING_X25519, &wrapped).unwrap();
+        assert_eq!(token.expose(), &TOKEN);
+        // Each wrapping uses a new ephemeral key.
+        assert_ne!(wrap(&wrap_key), wrapped);
+    }
+
+    #[test]
+    fn test_x25519_tampered() {
+        let (wrap_key, unwrap_key) = x25519_keys();
+        let wrapped = wrap(&wrap_key);
+        // The ephemeral public key, the ciphertext and the tag.
+        for i in [0, X25519_KEY_SIZE, wrapped.len() - 1] {
+            let mut tampered = wrapped.clone();
+            tampered[i] ^= 1;
+            assert!(
+                unwrap_rma_token(&unwrap_key, RMA_TOKEN_WRAPPING_X25519, &tampered).is_err(),
+                "tampered byte {i} accepted"
+            );
+        }
+        // A token wrapped to another key.
+        let (other_wrap_key, _) = x25519_keys();
+        let other = wrap(&other_wrap_key);
+        assert!(unwrap_rma_token(&unwrap_key, RMA_TOKEN_WRAPPING_X25519, &other).is_err());
+    }
+
+    #[test]
+    fn test_x25519_truncated() {
+        let (wrap_key, unwrap_key) = x25519_keys();
+        let wrapped = wrap(&wrap_key);
+        for len in [
+            0,
+            X25519_KEY_SIZE,
+            X25519_KEY_SIZE + AES_GCM_TAG_SIZE,
+            wrapped.len() - 1,
+        ] {
+            assert!(
+                unwrap_rma_token(&unwrap_key, RMA_TOKEN_WRAPPING_X25519, &wrapped[..len]).is_err(),
+                "token truncated to {len} bytes accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn test_p384_roundtrip() {
+        let (wrap_key, unwrap_key) = p384_keys();
+        assert_eq!(wrap_key.wrapping(), RMA_TOKEN_WRAPPING_P384);
+        let wrapped = wrap(&wrap_key);
+        assert_eq!(wrapped.len(), P384_POINT_SIZE + 16 + AES_GCM_TAG_SIZE);
+        let token = unwrap_rma_token(&unwrap_key, RMA_TOKEN_WRAPPING_P384, &wrapped).unwrap();
+        assert_eq!(token.expose(), &TOKEN);
+        // Each wrapping uses a new ephemeral key.
+        assert_ne!(wrap(&wrap_key), wrapped);
+
+        // P-256 keys are not P-384 wrap keys.
+        let p256 = EcKey::generate(&EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap());
+        let der = PKey::from_ec_key(p256.unwrap())
+            .unwrap()
+            .public_key_to_der()
+            .unwrap();
+        assert!(RmaWrapKey::from_der(&der).is_err());
+    }
+
+    #[test]
+    fn test_p384_tampered() {
+        let (wrap_key, unwrap_key) = p384_keys();
+        let wrapped = wrap(&wrap_key);
+        // The point encoding, a coordinate of the ephemeral public key, the ciphertext and the tag.
+        for i in [0, 1, P384_POINT_SIZE, wrapped.len() - 1] {
+            let mut tampered = wrapped.clone();
+            tampered[i] ^= 1;
+            assert!(
+                unwrap_rma_token(&unwrap_key, RMA_TOKEN_WRAPPING_P384, &tampered).is_err(),
+                "tampered byte {i} accepted"
+            );
+        }
+        // A token wrapped to another key.
+        let (other_wrap_key, _) = p384_keys();
+        let other = wrap(&other_wrap_key);
+        assert!(unwrap_rma_token(&unwrap_key, RMA_TOKEN_WRAPPING_P384, &other).is_err());
+        // Tokens of the other elliptic curve suite.
+        let (x25519_wrap_key, _) = x25519_keys();
+        let x25519_wrapped = wrap(&x25519_wrap_key);
+        assert!(unwrap_rma_token(&unwrap_key, RMA_TOKEN_WRAPPING_P384, &x25519_wrapped).is_err());
+    }
+
+    #[test]
+    fn test_p384_truncated() {
+        let (wrap_key, unwrap_key) = p384_keys();
+        let wrapped = wrap(&wrap_key);
+        for len in [
+            0,
+            P384_POINT_SIZE,
+            P384_POINT_SIZE + AES_GCM_TAG_SIZE,
+            wrapped.len() - 1,
+        ] {
+            assert!(
+                unwrap_rma_token(&unwrap_key, RMA_TOKEN_WRAPPING_P384, &wrapped[..len]).is_err(),
+                "token truncated to {len} bytes accepted"
+            );
+        }
+    }
+
+    #[test]
+    fn test_check_sku_wrapping() {
+        let (wrap_key, _) = p384_keys();
+        wrap_key.check_sku_wrapping("sival", None).unwrap();
+        wrap_key
+            .check_sku_wrapping("sival", Some(RMA_TOKEN_WRAPPING_P384))
+            .unwrap();
+        let err = wrap_key
+            .check_sku_wrapping("sival", Some(RMA_TOKEN_WRAPPING_X25519))
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("SKU sival wraps the RMA unlock tokens with x25519"),
+            "{err}"
+        );
+    }
+
+    #[test]
+    fn test_wrapping_mismatch() {
+        let (x25519_wrap_key, x25519_unwrap_key) = x25519_keys();
+        let (rsa_wrap_key, rsa_unwrap_key) = rsa_keys();
+        let x25519_wrapped = wrap(&x25519_wrap_key);
+        let rsa_wrapped = wrap(&rsa_wrap_key);
+        // The suite of the record must be that of the unwrap key...
+        assert!(unwrap_rma_token(&x25519_unwrap_key, RMA_TOKEN_WRAPPING, &x25519_wrapped).is_err());
+        assert!(
+            unwrap_rma_token(&rsa_unwrap_key, RMA_TOKEN_WRAPPING_X25519, &rsa_wrapped).is_err()
+        );
+        // ...and a token of the other suite fails to unwrap.
+        assert!(
+            unwrap_rma_token(&x25519_unwrap_key, RMA_TOKEN_WRAPPING_X25519, &rsa_wrapped).is_err()
+        );
+        assert!(unwrap_rma_token(&rsa_unwrap_key, RMA_TOKEN_WRAPPING, &x25519_wrapped).is_err());
+        // Records of unknown suites are rejected.
+        let mut record = WrappedRmaToken::new("sival", "0x00", "fake", "aes-kw", &rsa_wrapped);
+        assert!(WrappedRmaToken::from_json(&serde_json::to_string(&record).unwrap()).is_err());
+        record.wrapping = RMA_TOKEN_WRAPPING.into();
+        let record = WrappedRmaToken::from_json(&serde_json::to_string(&record).unwrap()).unwrap();
+        assert_eq!(record.wrapped_token().unwrap(), rsa_wrapped);
+        // P-384 tokens do not unwrap with the X25519 key.
+        let (p384_wrap_key, _) = p384_keys();
+        let p384_wrapped = wrap(&p384_wrap_key);
+        assert!(
+            unwrap_rma_token(&x25519_unwrap_key, RMA_TOKEN_WRAPPING_X25519, &p384_wrapped).is_err()
+        );
+    }
+
+    #[test]
+    fn test_rsa_roundtrip() {
+        let (wrap_key, unwrap_key) = rsa_keys();
+        assert_eq!(wrap_key.wrapping(), RMA_TOKEN_WRAPPING);
+        let wrapped = wrap(&wrap_key);
+        assert_eq!(wrapped.len(), 256);
+        let token = unwrap_rma_token(&unwrap_key, RMA_TOKEN_WRAPPING, &wrapped).unwrap();
+        assert_eq!(token.expose(), &TOKEN);
+    }
+}
@@ -0,0 +1,238 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Storage of the wrapped RMA unlock tokens.
+//!
+//! Where the wrapped RMA unlock tokens are kept is up to the RMA process of each factory, so their
+//! storage is a `TokenEscrow`, opened from a JSON escrow configuration selecting one of the
+//! backends:
+//!
+//! ```json
+//! { "backend": "file", "dir": "rma_tokens" }
+//! { "backend": "sqlite", "db": "provisioning.sqlite" }
+//! { "backend": "secret_manager",
+//!   "store": ["gcloud", "secrets", "create", "rma-token-{device_id}", "--data-file=-"],
+//!   "fetch": ["gcloud", "secrets", "versions", "access", "latest",
+//!             "--secret=rma-token-{device_id}"] }
+//! ```
+//!
+//! - `file` writes the `<device_id>.rma_token.bin` and `<device_id>.rma_token.json` files to
+//!   `dir`, as `--rma-token-out`.
+//! - `sqlite` inserts the `WrappedRmaToken` record in the `rma_tokens` table of the `db` SQLite
+//!   database, with the `sqlite3` command line tool. The rows of a device personalized again are
+//!   kept, and the latest one is fetched.
+//! - `secret_manager` runs the `store` command with the `WrappedRmaToken` JSON record on its
+//!   standard input, and the `fetch` command printing it on its standard output, `{device_id}`
+//!   being replaced in their arguments. This fits the CLI of most secret managers (gcloud, aws,
+//!   vault).
+
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+use anyhow::{bail, ensure, Context, Result};
+use serde::Deserialize;
+
+use crate::rma_token::WrappedRmaToken;
+
+/// Storage of the wrapped RMA unlock tokens.
+pub trait TokenEscrow {
+    /// Stores `token`, returning the files written, if any.
+    fn store(&self, token: &WrappedRmaToken) -> Result<Vec<PathBuf>>;
+
+    /// Returns the latest wrapped RMA unlock token stored for `device_id`.
+    fn fetch(&self, device_id: &str) -> Result<WrappedRmaToken>;
+}
+
+/// Wrapped RMA unlock tokens stored as files in a directory.
+pub struct FileEscrow {
+    pub dir: PathBuf,
+}
+
+impl TokenEscrow for FileEscrow {
+    fn store(&self, token: &WrappedRmaToken) -> Result<Vec<PathBuf>> {
+        let dir = &self.dir;
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create RMA token directory {dir:?}"))?;
+        let bin = dir.join(format!("{}.rma_token.bin", token.device_id));
+        std::fs::write(&bin, token.wrapped_token()?)
+            .with_context(|| format!("Failed to write the wrapped RMA unlock token to {bin:?}"))?;
+        let json = dir.join(format!("{}.rma_token.json", token.device_id));
+        std::fs::write(&json, serde_json::to_string_pretty(token)?)
+            .with_context(|| format!("Failed to write the wrapped RMA unlock token to {json:?}"))?;
+        log::info!("Wrapped RMA unlock token written to {json:?}");
+        Ok(vec![bin, json])
+    }
+
+    fn fetch(&self, device_id: &str) -> Result<WrappedRmaToken> {
+        WrappedRmaToken::load(&self.dir.join(format!("{device_id}.rma_token.json")))
+    }
+}
+
+/// Runs `command` with `input` on its standard input, returning its standard output.
+fn run_with_input(mut command: Command, input: &str) -> Result<String> {
+    let mut child = command
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run {command:?}"))?;
+    child
+        .stdin
+        .take()
+        .expect("stdin is piped")
+        .write_all(input.as_bytes())?;
+    let output = child.wait_with_output()?;
+    ensure!(
+        output.status.success(),
+        "{command:?} failed: {}",
+        String::from_utf8_lossy(&output.stderr).trim()
+    );
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
+
+/// Wrapped RMA unlock tokens stored in the `rma_tokens` table of a SQLite database.
+pub struct SqliteEscrow {
+    pub db: PathBuf,
+}
+
+const RMA_TOKENS_TABLE: &str = "CREATE TABLE IF NOT EXISTS rma_tokens (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    device_id TEXT NOT NULL,
+    sku TEXT NOT NULL,
+    key_bundle TEXT NOT NULL,
+    wrapping TEXT NOT NULL,
+    wrapped_rma_unlock_token TEXT NOT NULL,
+    stored_at TEXT NOT NULL DEFAULT CURRENT_TIMESTAMP
+);";
+
+/// Quotes `value` as an SQL string literal.
+fn sql_quote(value: &str) -> String {
+    format!("'{}'", value.replace('\'', "''"))
+}
+
+impl SqliteEscrow {
+    fn sqlite3(&self, sql: &str) -> Result<String> {
+        let mut command = Command::new("sqlite3");
+        command.arg("-bail").arg(&self.db);
+        run_with_input(command, sql)
+            .with_context(|| format!("Failed to access the RMA token database {:?}", self.db))
+    }
+}
+
+impl TokenEscrow for SqliteEscrow {
+    fn store(&self, token: &WrappedRmaToken) -> Result<Vec<PathBuf>> {
+        let values = [
+            &token.device_id,
+            &token.sku,
+            &token.key_bundle,
+            &token.wrapping,
+            &token.wrapped_rma_unlock_token,
+        ]
+        .map(|v| sql_quote(v))
+        .join(", ");
+        self.sqlite3(&format!(
+            "{RMA_TOKENS_TABLE}\nINSERT INTO rma_tokens (device_id, sku, key_bundle, wrapping, \
+             wrapped_rma_unlock_token) VALUES ({values});"
+        ))?;
+        log::info!("Wrapped RMA unlock token stored in {:?}", self.db);
+        Ok(Vec::new())
+    }
+
+    fn fetch(&self, device_id: &str) -> Result<WrappedRmaToken> {
+        let output = self.sqlite3(&format!(
+            "{RMA_TOKENS_TABLE}\nSELECT json_object('device_id', device_id, 'sku', sku, \
+             'key_bundle', key_bundle, 'wrapping', wrapping, 'wrapped_rma_unlock_token', \
+             wrapped_rma_unlock_token) FROM rma_tokens WHERE device_id = {} ORDER BY id DESC \
+             LIMIT 1;",
+            sql_quote(device_id)
+        ))?;
+        let Some(record) = output.lines().next() else {
+            bail!(
+                "No wrapped RMA unlock token of device {device_id} in {:?}",
+                self.db
+            );
+        };
+        WrappedRmaToken::from_json(record)
+    }
+}
+
+/// Wrapped RMA unlock tokens stored in a secret manager, with its command line tool.
+pub struct SecretManagerEscrow {
+    /// Command storing the JSON record read on its standard input.
+    pub store: Vec<String>,
+    /// Command printing the JSON record on its standard output.
+    pub fetch: Vec<String>,
+}
+
+/// Returns `argv` as a command, with `{device_id}` replaced in its arguments.
+fn secret_command(argv: &[String], device_id: &str) -> Command {
+    let mut argv = argv.iter().map(|arg| arg.replace("{device_id}", device_id));
+    let mut command = Command::new(argv.next().expect("command is not empty"));
+    command.args(argv);
+    command
+}
+
+impl TokenEscrow for SecretManagerEscrow {
+    fn store(&self, token: &WrappedRmaToken) -> Result<Vec<PathBuf>> {
+        run_with_input(
+            secret_command(&self.store, &token.device_id),
+            &serde_json::to_string(token)?,
+        )
+        .context("Failed to store the wrapped RMA unlock token")?;
+        log::info!(
+            "Wrapped RMA unlock token stored with `{}`",
+            self.store.join(" ")
+        );
+        Ok(Vec::new())
+    }
+
+    fn fetch(&self, device_id: &str) -> Result<WrappedRmaToken> {
+        let output =
+            run_with_input(secret_command(&self.fetch, device_id), "").with_context(|| {
+                format!("Failed to fetch the wrapped RMA unlock token of {device_id}")
+            })?;
+        WrappedRmaToken::from_json(&output)
+    }
+}
+
+/// Configuration of the storage of the wrapped RMA unlock tokens.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "backend", rename_all = "snake_case", deny_unknown_fields)]
+pub enum TokenEscrowConfig {
+    File {
+        dir: PathBuf,
+    },
+    Sqlite {
+        db: PathBuf,
+    },
+    SecretManager {
+        store: Vec<String>,
+        fetch: Vec<String>,
+    },
+}
+
+impl TokenEscrowConfig {
+    pub fn load(path: &Path) -> Result<Self> {
+        let json = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read RMA token escrow config {path:?}"))?;
+        let config: Self = serde_json::from_str(&json)
+            .with_context(|| format!("Failed to parse RMA token escrow config {path:?}"))?;
+        if let Self::SecretManager { store, fetch } = &config {
+            ensure!(
+                !store.is_empty() && !fetch.is_empty(),
+                "The store and fetch commands of {path:?} must not be empty"
+            );
+        }
+        Ok(config)
+    }
+
+    pub fn open(self) -> Box<dyn TokenEscrow> {
+        match self {
+            Self::File { dir } => Box::new(FileEscrow { dir }),
+            Self::Sqlite { db } => Box::new(SqliteEscrow { db }),
+            Self::SecretManager { store, fetch } => Box::new(SecretManagerEscrow { store, fetch }),
+        }
+    }
+}
@@ -0,0 +1,157 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Console-driven smoke tests run on a device in mission mode, after personalization.
+//!
+//! A SKU may list smoke tests exercising its owner firmware, e.g. a crypto KAT or a key manager
+//! advance. Each test optionally sends a command on the console, then waits for its pass or fail
+//! pattern:
+//!
+//! ```json
+//! [
+//!   {
+//!     "name": "aes_kat",
+//!     "command": "kat aes\r\n",
+//!     "pass": "AES KAT: PASS",
+//!     "fail": "AES KAT: FAIL.*",
+//!     "timeout_ms": 1000
+//!   }
+//! ]
+//! ```
+//!
+//! All the tests run, so the report records the result of each of them; personalization fails if
+//! any of them fails.
+
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use anyhow::{ensure, Context, Result};
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use opentitanlib::app::TransportWrapper;
+
+use crate::error::wait_for;
+
+/// Default timeout of a smoke test.
+const DEFAULT_SMOKE_TEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SmokeTest {
+    pub name: String,
+    /// Command to send on the console to start the test, if any.
+    #[serde(default)]
+    pub command: Option<String>,
+    /// Regex matching the console output of a passing test.
+    pub pass: String,
+    /// Regex matching the console output of a failing test, if any; tests without one fail on
+    /// timeout only.
+    #[serde(default)]
+    pub fail: Option<String>,
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+}
+
+/// Result of a smoke test, as recorded in the report.
+#[derive(Clone, Debug, Serialize)]
+pub struct SmokeTestResult {
+    pub name: String,
+    pub passed: bool,
+    /// Console output matched by the pass or fail pattern, or the error of the test.
+    pub output: String,
+    pub microseconds: u64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct SmokeTestSuite(pub Vec<SmokeTest>);
+
+impl SmokeTestSuite {
+    /// Loads and validates a smoke test suite file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let doc = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read smoke tests {path:?}"))?;
+        let suite = SmokeTestSuite(
+            serde_json::from_str(&doc)
+                .with_context(|| format!("Failed to parse smoke tests {path:?}"))?,
+        );
+        suite.validate()?;
+        Ok(suite)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for (i, test) in self.0.iter().enumerate() {
+            ensure!(!test.name.is_empty(), "Smoke test #{i} has no name");
+            ensure!(
+                !self.0[..i].iter().any(|t| t.name == test.name),
+                "Duplicate smoke test {}",
+                test.name
+            );
+            Regex::new(&test.pass)
+                .with_context(|| format!("Invalid pass pattern of smoke test {}", test.name))?;
+            if let Some(fail) = &test.fail {
+                Regex::new(fail)
+                    .with_context(|| format!("Invalid fail pattern of smoke test {}", test.name))?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the smoke tests on the console of a device in mission mode.
+    pub fn run(&self, transport: &TransportWrapper) -> Result<Vec<SmokeTestResult>> {
+        let uart_console = transport.uart("console")?;
+        let mut results = Vec::new();
+        for test in &self.0 {
+            let t0 = Instant::now();
+            let timeout = test
+                .timeout_ms
+                .map(Duration::from_millis)
+                .unwrap_or(DEFAULT_SMOKE_TEST_TIMEOUT);
+            // The first group of the anchor is always the pass pattern.
+            let anchor = match &test.fail {
+                Some(fail) => format!("({})|({fail})", test.pass),
+                None => format!("({})", test.pass),
+            };
+            let result = test
+                .command
+                .as_ref()
+                .map_or(Ok(()), |command| uart_console.write(command.as_bytes()))
+                .and_then(|_| wait_for(&*uart_console, &anchor, timeout));
+            let (passed, output) = match result {
+                Ok(captures) => (!captures[1].is_empty(), captures[0].clone()),
+                Err(e) => (false, e.to_string()),
+            };
+            if passed {
+                log::info!("Smoke test {} passed", test.name);
+            } else {
+                log::error!("Smoke test {} failed: {output}", test.name);
+            }
+            results.push(SmokeTestResult {
+                name: test.name.clone(),
+                passed,
+                output,
+                microseconds: t0.elapsed().as_micros() as u64,
+            });
+        }
+        Ok(results)
+    }
+}
+
+/// Returns the JSON Schema of the smoke test results of the report.
+pub fn smoke_test_results_schema() -> Value {
+    json!({
+        "type": "array",
+        "items": {
+            "type": "object",
+            "required": ["name", "passed", "output", "microseconds"],
+            "properties": {
+                "name": { "type": "string" },
+                "passed": { "type": "boolean" },
+                "output": { "type": "string" },
+                "microseconds": { "type": "integer", "minimum": 0 }
+            }
+        }
+    })
+}
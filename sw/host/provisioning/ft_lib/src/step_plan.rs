@@ -0,0 +1,138 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Selection of the steps of the complete FT flow, with their prerequisites.
+//!
+//! The steps of the flow depend on each other: each one requires the previous one to be either
+//! run by the same flow, or already done on the device, as told by its LC state. A flow selecting
+//! steps with `--only` or `--skip` is checked against the LC state of the device before running
+//! any of them.
+
+use anyhow::{ensure, Result};
+use clap::ValueEnum;
+
+use opentitanlib::dif::lc_ctrl::DifLcCtrlState;
+
+/// A step of the complete FT flow, in flow order.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, ValueEnum)]
+pub enum FtStep {
+    TestUnlock,
+    Individualize,
+    TestExit,
+    Personalize,
+}
+
+impl FtStep {
+    pub const ALL: [FtStep; 4] = [
+        FtStep::TestUnlock,
+        FtStep::Individualize,
+        FtStep::TestExit,
+        FtStep::Personalize,
+    ];
+
+    /// Returns the name of the step, as reported in the events of the flow.
+    pub fn name(self) -> &'static str {
+        match self {
+            FtStep::TestUnlock => "test-unlock",
+            FtStep::Individualize => "individualize",
+            FtStep::TestExit => "test-exit",
+            FtStep::Personalize => "personalize",
+        }
+    }
+
+    /// Returns the step which must be run or done before this one, if any.
+    pub fn prerequisite(self) -> Option<FtStep> {
+        match self {
+            FtStep::TestUnlock => None,
+            FtStep::Individualize => Some(FtStep::TestUnlock),
+            FtStep::TestExit => Some(FtStep::Individualize),
+            FtStep::Personalize => Some(FtStep::TestExit),
+        }
+    }
+
+    /// Returns whether a device in `lc_state` is known to be past this step.
+    pub fn done_in(self, lc_state: DifLcCtrlState) -> bool {
+        let mission_mode = matches!(
+            lc_state,
+            DifLcCtrlState::Dev
+                | DifLcCtrlState::Prod
+                | DifLcCtrlState::ProdEnd
+                | DifLcCtrlState::Rma
+        );
+        match self {
+            FtStep::TestUnlock => {
+                mission_mode
+                    || matches!(
+                        lc_state,
+                        DifLcCtrlState::TestUnlocked1
+                            | DifLcCtrlState::TestUnlocked2
+                            | DifLcCtrlState::TestUnlocked3
+                            | DifLcCtrlState::TestUnlocked4
+                            | DifLcCtrlState::TestUnlocked5
+                            | DifLcCtrlState::TestUnlocked6
+                            | DifLcCtrlState::TestUnlocked7
+                    )
+            }
+            // Individualization is only known to be done once the device left the test states.
+            FtStep::Individualize | FtStep::TestExit => mission_mode,
+            // Personalization is not reflected in the LC state.
+            FtStep::Personalize => false,
+        }
+    }
+}
+
+/// The steps of the complete FT flow a run is scheduled to run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct StepPlan(Vec<FtStep>);
+
+impl Default for StepPlan {
+    fn default() -> Self {
+        Self(FtStep::ALL.to_vec())
+    }
+}
+
+impl StepPlan {
+    /// Returns the plan running the `only` steps if any, else all the steps but the `skip` ones.
+    pub fn new(only: &[FtStep], skip: &[FtStep]) -> Self {
+        Self(
+            FtStep::ALL
+                .into_iter()
+                .filter(|step| only.is_empty() || only.contains(step))
+                .filter(|step| !skip.contains(step))
+                .collect(),
+        )
+    }
+
+    pub fn steps(&self) -> &[FtStep] {
+        &self.0
+    }
+
+    pub fn runs(&self, step: FtStep) -> bool {
+        self.0.contains(&step)
+    }
+
+    /// Returns whether the plan runs all the steps.
+    pub fn is_complete(&self) -> bool {
+        self.0.len() == FtStep::ALL.len()
+    }
+
+    /// Checks the prerequisite of each step of the plan is either run before it, or already done
+    /// on a device entering the flow in `lc_state`.
+    pub fn check(&self, lc_state: DifLcCtrlState) -> Result<()> {
+        ensure!(!self.0.is_empty(), "No FT step selected");
+        for step in &self.0 {
+            if let Some(prerequisite) = step.prerequisite() {
+                ensure!(
+                    self.runs(prerequisite) || prerequisite.done_in(lc_state),
+                    "The {} step requires the {} step, which is neither selected nor done on a \
+                     device in the {} LC state",
+                    step.name(),
+                    prerequisite.name(),
+                    lc_state.lc_state_to_str()
+                );
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,280 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Durable record of the FT step a device is in, for stations losing power mid-flow.
+//!
+//! The step and sub-step are persisted before each irreversible action (LC transitions, OTP and
+//! flash programming), so that after a power loss the operator can tell exactly which action a
+//! device was going through. The state is written to a temporary file, synced and renamed over
+//! the previous state, so a power loss leaves either the previous or the new state on disk.
+
+use std::cell::RefCell;
+use std::fs::{self, File};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use serde::{Deserialize, Serialize};
+
+use crate::events::{EventSink, ProvisioningEvent};
+
+/// The step of the FT flow a device is in.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StepState {
+    /// Device ID, once known.
+    pub device_id: String,
+    pub step: String,
+    pub sub_step: String,
+    /// Start of the flow, in seconds since the Unix epoch.
+    pub started: u64,
+    /// Time the step was entered, in seconds since the Unix epoch.
+    pub updated: u64,
+    /// Set once the flow completed; a device whose state is not completed was in flight.
+    pub completed: bool,
+    /// Whether ROM execution may be enabled in the OTP of the device, without the device in a
+    /// mission mode yet. Carried over to the next flows until test exit, see
+    /// `FtProvisioner::rom_exec_enabled`.
+    #[serde(default)]
+    pub rom_exec_enabled: bool,
+}
+
+impl std::fmt::Display for StepState {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let device_id = if self.device_id.is_empty() {
+            "<unknown>"
+        } else {
+            &self.device_id
+        };
+        write!(
+            f,
+            "device {device_id} at step {}/{} (entered at {}, flow started at {})",
+            self.step, self.sub_step, self.updated, self.started
+        )
+    }
+}
+
+pub(crate) fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or_default()
+}
+
+/// Persists the step state of a device, if a path was provided.
+#[derive(Default)]
+pub struct StepJournal {
+    path: Option<PathBuf>,
+    state: RefCell<StepState>,
+    events: EventSink,
+}
+
+impl StepJournal {
+    /// Returns a journal that persists nothing.
+    pub fn disabled() -> Self {
+        Self::default()
+    }
+
+    /// Returns a journal persisting the step state to `path`.
+    ///
+    /// A previous state left in flight at `path` is reported before being replaced. If ROM
+    /// execution may be enabled on the device of the previous state, the new state still records
+    /// it for that device.
+    pub fn open(path: &Path) -> Result<Self> {
+        Ok(Self {
+            path: Some(path.to_path_buf()),
+            ..Self::carry_over(path)?
+        })
+    }
+
+    /// Returns a journal persisting nothing, but carrying over the ROM execution state of the
+    /// device of the state persisted at `path`, if any, as `open` does. For the diagnostic tools,
+    /// which must not reset such a device either.
+    pub fn read_only(path: &Path) -> Result<Self> {
+        Self::carry_over(path)
+    }
+
+    fn carry_over(path: &Path) -> Result<Self> {
+        let now = now();
+        let mut state = StepState {
+            started: now,
+            updated: now,
+            ..Default::default()
+        };
+        if path.exists() {
+            let previous = Self::load(path)?;
+            if !previous.completed {
+                log::warn!("Previous FT run stopped before completion: {previous}");
+            }
+            if previous.rom_exec_enabled {
+                log::warn!(
+                    "ROM execution may be enabled on device {} outside of a mission mode: resetting it is refused until test exit.",
+                    previous.device_id
+                );
+                state.device_id = previous.device_id;
+                state.rom_exec_enabled = true;
+            }
+        }
+        Ok(Self {
+            path: None,
+            state: RefCell::new(state),
+            events: EventSink::default(),
+        })
+    }
+
+    /// Sets the sink the steps entered, and the other events of the flow, are emitted to.
+    pub fn set_events(&mut self, events: EventSink) {
+        self.events = events;
+    }
+
+    pub fn events(&self) -> &EventSink {
+        &self.events
+    }
+
+    /// Loads the step state persisted at `path`.
+    pub fn load(path: &Path) -> Result<StepState> {
+        let doc = fs::read_to_string(path)
+            .with_context(|| format!("Failed to read step state {path:?}"))?;
+        serde_json::from_str(&doc).with_context(|| format!("Failed to parse step state {path:?}"))
+    }
+
+    /// Returns the ID of the device, empty until known.
+    pub fn device_id(&self) -> String {
+        self.state.borrow().device_id.clone()
+    }
+
+    /// Records the ID of the device, as soon as it is known.
+    ///
+    /// The ROM execution state carried over for another device does not apply to this one.
+    pub fn set_device_id(&self, device_id: &str) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+        if state.device_id == device_id {
+            return Ok(());
+        }
+        if state.rom_exec_enabled && !state.device_id.is_empty() {
+            log::info!(
+                "Device {device_id} is not device {}, whose ROM execution state is discarded.",
+                state.device_id
+            );
+            state.rom_exec_enabled = false;
+        }
+        state.device_id = device_id.to_string();
+        self.persist(&state)
+    }
+
+    /// Whether ROM execution may be enabled on the device, as last recorded.
+    pub fn rom_exec_enabled(&self) -> bool {
+        self.state.borrow().rom_exec_enabled
+    }
+
+    /// Durably records whether ROM execution may be enabled on the device; must be called before
+    /// the OTP writes enabling it.
+    pub fn set_rom_exec_enabled(&self, enabled: bool) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+        if state.rom_exec_enabled == enabled {
+            return Ok(());
+        }
+        state.rom_exec_enabled = enabled;
+        self.persist(&state)
+    }
+
+    /// Durably records the device enters `step`/`sub_step`; must be called before the
+    /// irreversible action of the sub-step.
+    pub fn enter(&self, step: &str, sub_step: &str) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+        state.step = step.to_string();
+        state.sub_step = sub_step.to_string();
+        state.updated = now();
+        log::info!("Entering step {step}/{sub_step}.");
+        self.persist(&state)?;
+        self.events.emit(ProvisioningEvent::StepStarted {
+            step: step.to_string(),
+            sub_step: sub_step.to_string(),
+        });
+        Ok(())
+    }
+
+    /// Durably records the flow completed.
+    pub fn complete(&self) -> Result<()> {
+        let mut state = self.state.borrow_mut();
+        state.completed = true;
+        state.updated = now();
+        self.persist(&state)
+    }
+
+    fn persist(&self, state: &StepState) -> Result<()> {
+        let Some(path) = &self.path else {
+            return Ok(());
+        };
+        write_durably(path, serde_json::to_string_pretty(state)?.as_bytes())
+            .with_context(|| format!("Failed to write step state {path:?}"))
+    }
+}
+
+/// Writes `contents` to `path` through a synced temporary file renamed over `path`, so a power
+/// loss leaves either the previous or the new contents on disk.
+pub(crate) fn write_durably(path: &Path, contents: &[u8]) -> Result<()> {
+    let mut tmp = path.to_path_buf().into_os_string();
+    tmp.push(".tmp");
+    let tmp = PathBuf::from(tmp);
+    let mut file = File::create(&tmp).with_context(|| format!("Failed to create {tmp:?}"))?;
+    file.write_all(contents)?;
+    file.sync_all()?;
+    fs::rename(&tmp, path)?;
+    // Sync the directory, so the rename itself survives a power loss.
+    let dir = path
+        .parent()
+        .filter(|p| !p.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    File::open(dir)?.sync_all()?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentitanlib::util::tmpfilename;
+
+    #[test]
+    fn test_rom_exec_enabled() {
+        let path = PathBuf::from(tmpfilename("test_step_state_rom_exec.json"));
+        let _ = fs::remove_file(&path);
+        let journal = StepJournal::open(&path).unwrap();
+        journal.set_device_id("0x01").unwrap();
+        journal.enter("individualize", "otp-program").unwrap();
+        journal.set_rom_exec_enabled(true).unwrap();
+        journal.complete().unwrap();
+
+        // A later flow of the same device, e.g. a separate test exit.
+        let journal = StepJournal::open(&path).unwrap();
+        assert!(journal.rom_exec_enabled());
+        assert_eq!(journal.device_id(), "0x01");
+        assert!(StepJournal::read_only(&path).unwrap().rom_exec_enabled());
+        journal.set_device_id("0x01").unwrap();
+        assert!(journal.rom_exec_enabled());
+        journal.set_rom_exec_enabled(false).unwrap();
+        assert!(!StepJournal::open(&path).unwrap().rom_exec_enabled());
+    }
+
+    #[test]
+    fn test_rom_exec_enabled_other_device() {
+        let path = PathBuf::from(tmpfilename("test_step_state_rom_exec_other.json"));
+        let _ = fs::remove_file(&path);
+        let journal = StepJournal::open(&path).unwrap();
+        journal.set_device_id("0x01").unwrap();
+        journal.set_rom_exec_enabled(true).unwrap();
+
+        let journal = StepJournal::open(&path).unwrap();
+        journal.set_device_id("0x02").unwrap();
+        assert!(!journal.rom_exec_enabled());
+        assert!(!StepJournal::load(&path).unwrap().rom_exec_enabled);
+
+        // The read-only journal persists nothing.
+        let path = PathBuf::from(tmpfilename("test_step_state_read_only.json"));
+        let _ = fs::remove_file(&path);
+        let journal = StepJournal::read_only(&path).unwrap();
+        journal.set_rom_exec_enabled(true).unwrap();
+        assert!(!path.exists());
+    }
+}
@@ -0,0 +1,198 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Supply voltage and temperature telemetry of the fixture, sampled around the OTP writes and LC
+//! transitions of the flow.
+//!
+//! Marginal supply conditions correlate with OTP programming failures, so the sensors of the
+//! fixture, if any, are read at the start and at the end of each step, and the readings recorded
+//! in the report. A sensor is either a transport pin read as an analog input, e.g. a HyperDebug
+//! ADC channel, or a command printing a reading on its standard output, e.g. a script querying an
+//! external instrument over VISA:
+//!
+//! ```json
+//! [
+//!   { "name": "vcc", "kind": "voltage", "adc": "VCC_SENSE", "scale": 2.0 },
+//!   { "name": "socket", "kind": "temperature", "command": ["read_socket_temp.py"] }
+//! ]
+//! ```
+//!
+//! A reading is `scale * raw + offset`, in volts or degrees Celsius. Sensors which cannot be read
+//! are logged and skipped: telemetry never fails the flow.
+
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::{bail, ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+
+use opentitanlib::app::TransportWrapper;
+use opentitanlib::io::gpio::PinMode;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SensorKind {
+    /// In volts.
+    Voltage,
+    /// In degrees Celsius.
+    Temperature,
+}
+
+fn default_scale() -> f64 {
+    1.0
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Sensor {
+    pub name: String,
+    pub kind: SensorKind,
+    /// Transport pin to read as an analog input, in volts.
+    #[serde(default)]
+    pub adc: Option<String>,
+    /// Command (program and arguments) printing a reading on its standard output.
+    #[serde(default)]
+    pub command: Option<Vec<String>>,
+    #[serde(default = "default_scale")]
+    pub scale: f64,
+    #[serde(default)]
+    pub offset: f64,
+}
+
+impl Sensor {
+    fn read_raw(&self, transport: &TransportWrapper) -> Result<f64> {
+        match (&self.adc, &self.command) {
+            (Some(pin), None) => {
+                let pin = transport.gpio_pin(pin)?;
+                pin.set_mode(PinMode::AnalogInput)?;
+                Ok(pin.analog_read()? as f64)
+            }
+            (None, Some(command)) => {
+                let output = Command::new(&command[0])
+                    .args(&command[1..])
+                    .output()
+                    .with_context(|| format!("Failed to run {command:?}"))?;
+                ensure!(
+                    output.status.success(),
+                    "{command:?} failed: {}",
+                    String::from_utf8_lossy(&output.stderr).trim()
+                );
+                let stdout = String::from_utf8_lossy(&output.stdout);
+                stdout
+                    .trim()
+                    .parse()
+                    .with_context(|| format!("Invalid reading {:?}", stdout.trim()))
+            }
+            _ => bail!(
+                "Sensor {} must have exactly one of adc and command",
+                self.name
+            ),
+        }
+    }
+
+    /// Reads the sensor, in volts or degrees Celsius.
+    pub fn read(&self, transport: &TransportWrapper) -> Result<f64> {
+        Ok(self.scale * self.read_raw(transport)? + self.offset)
+    }
+}
+
+/// A sensor reading, as recorded in the report.
+#[derive(Clone, Debug, Serialize)]
+pub struct TelemetrySample {
+    /// FT step the sample was taken around, e.g. `individualize`.
+    pub step: &'static str,
+    /// `start` or `end` of the step.
+    pub point: &'static str,
+    pub sensor: String,
+    pub kind: SensorKind,
+    pub value: f64,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct Telemetry(pub Vec<Sensor>);
+
+impl Telemetry {
+    /// Loads and validates a telemetry sensor list file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let doc = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read telemetry sensors {path:?}"))?;
+        let telemetry = Telemetry(
+            serde_json::from_str(&doc)
+                .with_context(|| format!("Failed to parse telemetry sensors {path:?}"))?,
+        );
+        telemetry.validate()?;
+        Ok(telemetry)
+    }
+
+    pub fn validate(&self) -> Result<()> {
+        for (i, sensor) in self.0.iter().enumerate() {
+            ensure!(!sensor.name.is_empty(), "Telemetry sensor #{i} has no name");
+            ensure!(
+                !self.0[..i].iter().any(|s| s.name == sensor.name),
+                "Duplicate telemetry sensor {}",
+                sensor.name
+            );
+            match (&sensor.adc, &sensor.command) {
+                (Some(_), None) => {}
+                (None, Some(command)) => ensure!(
+                    !command.is_empty(),
+                    "Telemetry sensor {} has an empty command",
+                    sensor.name
+                ),
+                _ => bail!(
+                    "Telemetry sensor {} must have exactly one of adc and command",
+                    sensor.name
+                ),
+            }
+        }
+        Ok(())
+    }
+
+    /// Reads all the sensors at the `point` of `step`.
+    pub fn sample(
+        &self,
+        transport: &TransportWrapper,
+        step: &'static str,
+        point: &'static str,
+    ) -> Vec<TelemetrySample> {
+        self.0
+            .iter()
+            .filter_map(|sensor| match sensor.read(transport) {
+                Ok(value) => {
+                    log::info!("Telemetry {step} {point}: {} = {value}", sensor.name);
+                    Some(TelemetrySample {
+                        step,
+                        point,
+                        sensor: sensor.name.clone(),
+                        kind: sensor.kind,
+                        value,
+                    })
+                }
+                Err(e) => {
+                    log::warn!("Failed to read telemetry sensor {}: {e:#}", sensor.name);
+                    None
+                }
+            })
+            .collect()
+    }
+}
+
+/// Returns the JSON Schema of the telemetry samples of the report.
+pub fn telemetry_samples_schema() -> Value {
+    json!({
+        "type": "array",
+        "items": {
+            "type": "object",
+            "required": ["step", "point", "sensor", "kind", "value"],
+            "properties": {
+                "step": { "type": "string" },
+                "point": { "enum": ["start", "end"] },
+                "sensor": { "type": "string" },
+                "kind": { "enum": ["voltage", "temperature"] },
+                "value": { "type": "number" }
+            }
+        }
+    })
+}
@@ -0,0 +1,253 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+use std::path::Path;
+
+use anyhow::{bail, ensure, Context, Result};
+use arrayvec::ArrayVec;
+use serde::Deserialize;
+
+use ujson_lib::limits::DEVICE_ID_WORDS;
+use ujson_lib::provisioning_data::ManufFtIndividualizeData;
+use util_lib::hex_string_to_u32_arrayvec;
+
+/// Number of AST calibration words; must match `kFlashInfoAstCalibrationDataSizeIn32BitWords`.
+pub use ujson_lib::limits::AST_CFG_WORDS;
+
+/// Trim file format version supported by this library.
+pub const TRIM_FILE_VERSION: u32 = 1;
+
+/// Per-device calibration / trim data produced by an earlier parametric test.
+///
+/// Example:
+/// ```json
+/// {
+///   "version": 1,
+///   "device_id": "0x00000000_..._00024001",
+///   "fields": [
+///     { "name": "vcaon", "word": 3, "lsb": 0, "width": 5,
+///       "value": "1.12V", "step": "10mV", "offset": "1V" },
+///     { "name": "rc_osc", "word": 7, "lsb": 8, "width": 8, "value": 93 }
+///   ]
+/// }
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TrimFile {
+    /// Trim file format version.
+    pub version: u32,
+    /// Device the trim data was measured on; checked against the device being provisioned.
+    #[serde(default)]
+    pub device_id: Option<String>,
+    /// Trim fields overriding the AST calibration data provisioned during CP.
+    pub fields: Vec<TrimField>,
+}
+
+/// A bit field of an AST calibration word.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TrimField {
+    /// Field name, used in error messages.
+    pub name: String,
+    /// Index of the AST calibration word holding the field.
+    pub word: usize,
+    /// Least significant bit of the field in the word.
+    pub lsb: u32,
+    /// Field width in bits.
+    pub width: u32,
+    /// Raw field code, or a physical quantity encoded as `(value - offset) / step`.
+    pub value: TrimValue,
+    /// Physical quantity of one code step; required for physical values.
+    #[serde(default)]
+    pub step: Option<String>,
+    /// Physical quantity encoded by code 0; defaults to zero.
+    #[serde(default)]
+    pub offset: Option<String>,
+}
+
+/// Value of a trim field.
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum TrimValue {
+    /// Raw field code.
+    Code(u32),
+    /// Physical quantity with an SI unit, e.g. `1.12V` or `32kHz`.
+    Quantity(String),
+}
+
+/// AST calibration bits to override, as sent in the FT individualize payload.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AstTrim {
+    pub mask: [u32; AST_CFG_WORDS],
+    pub value: [u32; AST_CFG_WORDS],
+}
+
+impl Default for AstTrim {
+    fn default() -> Self {
+        AstTrim {
+            mask: [0; AST_CFG_WORDS],
+            value: [0; AST_CFG_WORDS],
+        }
+    }
+}
+
+/// Units accepted in physical trim values, longest suffix first.
+const UNITS: [&str; 5] = ["Ohm", "Hz", "V", "A", "s"];
+
+/// Parses a physical quantity into its value in base SI units and its unit.
+fn parse_quantity(quantity: &str) -> Result<(f64, &'static str)> {
+    let quantity = quantity.trim();
+    let Some(unit) = UNITS.iter().find(|u| quantity.ends_with(*u)) else {
+        bail!("Quantity {quantity:?} has no unit, expected one of {UNITS:?}");
+    };
+    let number = quantity[..quantity.len() - unit.len()].trim_end();
+    let (number, scale) = match number.chars().last() {
+        Some('p') => (&number[..number.len() - 1], 1e-12),
+        Some('n') => (&number[..number.len() - 1], 1e-9),
+        Some('u') => (&number[..number.len() - 1], 1e-6),
+        Some('µ') => (&number[..number.len() - 'µ'.len_utf8()], 1e-6),
+        Some('m') => (&number[..number.len() - 1], 1e-3),
+        Some('k') => (&number[..number.len() - 1], 1e3),
+        Some('M') => (&number[..number.len() - 1], 1e6),
+        Some('G') => (&number[..number.len() - 1], 1e9),
+        _ => (number, 1.0),
+    };
+    let value: f64 = number
+        .trim()
+        .parse()
+        .with_context(|| format!("Invalid quantity {quantity:?}"))?;
+    Ok((value * scale, unit))
+}
+
+impl TrimField {
+    /// Returns the field-aligned code encoding the field value.
+    fn code(&self) -> Result<u32> {
+        let code = match &self.value {
+            TrimValue::Code(code) => {
+                ensure!(
+                    self.step.is_none() && self.offset.is_none(),
+                    "Trim field {}: step and offset only apply to physical values",
+                    self.name
+                );
+                *code as f64
+            }
+            TrimValue::Quantity(quantity) => {
+                let (value, unit) = parse_quantity(quantity)?;
+                let Some(step) = &self.step else {
+                    bail!("Trim field {}: physical values require a step", self.name);
+                };
+                let (step, step_unit) = parse_quantity(step)?;
+                let (offset, offset_unit) = match &self.offset {
+                    Some(offset) => parse_quantity(offset)?,
+                    None => (0.0, unit),
+                };
+                ensure!(
+                    unit == step_unit && unit == offset_unit,
+                    "Trim field {}: value, step and offset units differ",
+                    self.name
+                );
+                ensure!(
+                    step > 0.0,
+                    "Trim field {}: step must be positive",
+                    self.name
+                );
+                ((value - offset) / step).round()
+            }
+        };
+        let max = ((1u64 << self.width) - 1) as f64;
+        if !(0.0..=max).contains(&code) {
+            bail!(
+                "Trim field {}: code {code} does not fit in {} bits",
+                self.name,
+                self.width
+            );
+        }
+        Ok(code as u32)
+    }
+
+    fn mask(&self) -> u32 {
+        (((1u64 << self.width) - 1) << self.lsb) as u32
+    }
+}
+
+impl TrimFile {
+    /// Loads and validates a trim file.
+    pub fn load(path: &Path) -> Result<Self> {
+        let trim_file: TrimFile = serde_json::from_str(
+            &std::fs::read_to_string(path)
+                .with_context(|| format!("Failed to read trim file {path:?}"))?,
+        )
+        .with_context(|| format!("Invalid trim file {path:?}"))?;
+        trim_file.validate()?;
+        Ok(trim_file)
+    }
+
+    fn validate(&self) -> Result<()> {
+        ensure!(
+            self.version == TRIM_FILE_VERSION,
+            "Unsupported trim file version {}, expected {}",
+            self.version,
+            TRIM_FILE_VERSION
+        );
+        let mut used = [0u32; AST_CFG_WORDS];
+        for field in &self.fields {
+            ensure!(
+                field.word < AST_CFG_WORDS,
+                "Trim field {}: word {} out of range, the AST has {} calibration words",
+                field.name,
+                field.word,
+                AST_CFG_WORDS
+            );
+            ensure!(
+                field.width > 0
+                    && field
+                        .lsb
+                        .checked_add(field.width)
+                        .is_some_and(|msb| msb <= 32),
+                "Trim field {}: bits [{}+:{}] do not fit in a 32-bit word",
+                field.name,
+                field.lsb,
+                field.width
+            );
+            ensure!(
+                used[field.word] & field.mask() == 0,
+                "Trim field {} overlaps another field",
+                field.name
+            );
+            used[field.word] |= field.mask();
+            field.code()?;
+        }
+        Ok(())
+    }
+
+    /// Computes the AST calibration bits to override on device `device_id`.
+    pub fn ast_trim(&self, device_id: &[u32]) -> Result<AstTrim> {
+        if let Some(expected) = &self.device_id {
+            let expected = hex_string_to_u32_arrayvec::<DEVICE_ID_WORDS>(expected)?;
+            ensure!(
+                expected.as_slice() == device_id,
+                "Trim file was measured on device {expected:08x?}, not {device_id:08x?}"
+            );
+        }
+        let mut trim = AstTrim::default();
+        for field in &self.fields {
+            trim.mask[field.word] |= field.mask();
+            trim.value[field.word] |= field.code()? << field.lsb;
+        }
+        Ok(trim)
+    }
+}
+
+impl AstTrim {
+    /// Returns true if no AST calibration bit is overridden.
+    pub fn is_empty(&self) -> bool {
+        self.mask.iter().all(|w| *w == 0)
+    }
+
+    /// Folds the trim values into an FT individualize payload.
+    pub fn apply(&self, data: &mut ManufFtIndividualizeData) {
+        data.ast_trim_mask = ArrayVec::from(self.mask);
+        data.ast_trim_value = ArrayVec::from(self.value);
+    }
+}
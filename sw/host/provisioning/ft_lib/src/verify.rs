@@ -0,0 +1,141 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Verification of an already provisioned device, without modifying it.
+//!
+//! QA audits random samples of a provisioned lot, for which no saved report may be at hand: unlike
+//! `audit`, the device is checked against the expectations of the SKU instead. The LC state and
+//! device ID are read over the LC TAP; the lock status of the OTP partitions is read from an OTP
+//! dump, in the LC states which allow loading the OTP dump SRAM program; and the device is booted
+//! and the certificates logged by its firmware are checked to link up to their CA.
+
+use std::path::PathBuf;
+
+use anyhow::{anyhow, Result};
+use base64ct::{Base64, Encoding};
+
+use cert_lib::chain::{check_cert_chain, load_ca_cert};
+use cert_lib::EndorsedCert;
+use opentitanlib::dif::lc_ctrl::DifLcCtrlState;
+use opentitanlib::test_utils::load_sram_program::SramProgramParams;
+use ot_certs::CertFormat;
+
+use crate::audit::{AuditCheck, AuditResult};
+use crate::otp_dump::OtpDump;
+use crate::IndividualizePartition;
+
+/// What a provisioned device is expected to look like.
+#[derive(Clone, Debug, Default)]
+pub struct VerifyParams {
+    /// LC states the device may be in; any mission mode LC state if empty.
+    pub lc_states: Vec<DifLcCtrlState>,
+    /// OTP dump SRAM program, to check the lock status of the OTP partitions.
+    pub otp_dump: Option<SramProgramParams>,
+    /// OTP partitions which must be locked.
+    pub locked_partitions: Vec<IndividualizePartition>,
+    /// Regex marking the end of the certificates logged by the firmware after reset. The
+    /// certificates are not fetched if unset.
+    pub cert_anchor: Option<String>,
+    /// Names of the DICE certificates logged by the firmware, ordered from root to leaf.
+    pub dice_chain: Vec<String>,
+    /// CA the DICE certificates must link up to.
+    pub dice_ca_cert: Option<PathBuf>,
+}
+
+/// Whether the OTP dump SRAM program may be loaded over the RISC-V debug module in `lc_state`.
+pub(crate) fn otp_dump_allowed(lc_state: DifLcCtrlState) -> bool {
+    matches!(
+        lc_state,
+        DifLcCtrlState::TestUnlocked0
+            | DifLcCtrlState::TestUnlocked1
+            | DifLcCtrlState::TestUnlocked2
+            | DifLcCtrlState::TestUnlocked3
+            | DifLcCtrlState::TestUnlocked4
+            | DifLcCtrlState::TestUnlocked5
+            | DifLcCtrlState::TestUnlocked6
+            | DifLcCtrlState::TestUnlocked7
+            | DifLcCtrlState::Dev
+            | DifLcCtrlState::Rma
+    )
+}
+
+/// Checks the device is in one of the LC states of `params`.
+pub(crate) fn verify_lc_state(
+    lc_state: DifLcCtrlState,
+    params: &VerifyParams,
+    result: &mut AuditResult,
+) {
+    let allowed = if params.lc_states.is_empty() {
+        vec![
+            DifLcCtrlState::Dev,
+            DifLcCtrlState::Prod,
+            DifLcCtrlState::ProdEnd,
+        ]
+    } else {
+        params.lc_states.clone()
+    };
+    let expected = allowed
+        .iter()
+        .map(|s| s.lc_state_to_str())
+        .collect::<Vec<_>>()
+        .join("|");
+    let actual = lc_state.lc_state_to_str();
+    result.checks.push(AuditCheck {
+        name: "lc_state".into(),
+        expected,
+        actual: actual.into(),
+        matches: allowed.contains(&lc_state),
+    });
+}
+
+/// Checks the partitions of `params` are locked in `dump`.
+pub(crate) fn verify_otp_locks(dump: &OtpDump, params: &VerifyParams, result: &mut AuditResult) {
+    for partition in &params.locked_partitions {
+        let name = partition.otp_partition();
+        let actual = match dump.partitions.iter().find(|p| p.name == name) {
+            Some(part) if part.is_locked() => {
+                log::info!("{name} is locked, digest {:#018x}", part.digest);
+                "locked"
+            }
+            Some(_) => "unlocked",
+            None => "<not dumped>",
+        };
+        result.check(&format!("otp:{name}"), "locked", actual);
+    }
+}
+
+/// Checks the `certs` logged by the firmware include the DICE chain of `params`, linking up to
+/// its CA.
+pub(crate) fn verify_console_certs(
+    certs: &[(String, String)],
+    params: &VerifyParams,
+    result: &mut AuditResult,
+) -> Result<()> {
+    let mut chain = Vec::new();
+    for name in &params.dice_chain {
+        let Some((_, cert)) = certs.iter().find(|(logged, _)| logged == name) else {
+            result.check(&format!("cert:{name}"), "logged", "<not logged>");
+            continue;
+        };
+        let bytes =
+            Base64::decode_vec(cert).map_err(|e| anyhow!("Invalid base64 {name} cert: {e}"))?;
+        result.check(&format!("cert:{name}"), "logged", "logged");
+        chain.push(EndorsedCert {
+            format: CertFormat::X509,
+            name: name.clone(),
+            bytes,
+            ignore_critical: true,
+        });
+    }
+    if let Some(ca_cert) = &params.dice_ca_cert {
+        if chain.len() == params.dice_chain.len() && !chain.is_empty() {
+            let actual = match check_cert_chain(&load_ca_cert(ca_cert)?, &chain) {
+                Ok(()) => "valid".to_string(),
+                Err(e) => format!("{e:#}"),
+            };
+            result.check("dice_chain", "valid", &actual);
+        }
+    }
+    Ok(())
+}
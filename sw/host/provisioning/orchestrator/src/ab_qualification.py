@@ -0,0 +1,272 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""A/B qualification of a new provisioning firmware bundle.
+
+In A/B mode, the orchestrator provisions alternate devices with the current
+FT firmware bundle (variant A) and with a candidate bundle (variant B), and
+records the variant, outcome and step durations of each device in
+`ab_results.jsonl` under the logs root directory. The report compares the
+two variants:
+
+- failures: failure rates and FT error kinds, with the p-value of a
+  two-proportion z-test;
+- timings: the durations of the orchestrator steps, FT steps and FT
+  operations, with the p-value of a Welch test;
+- output diffs: the fields of the FT results which are the same on all the
+  devices of a variant but differ between the variants, or are only output
+  by one of them. Per-device values, e.g. device IDs and certificates, vary
+  within each variant and are not reported.
+
+The p-values use the normal approximation, sound from a few tens of devices
+per variant; smaller samples are reported without them.
+"""
+
+import argparse
+import json
+import logging
+import math
+import os
+import statistics
+import sys
+from typing import Dict, List, Optional, Tuple
+
+# A/B records, in the logs root directory.
+AB_RESULTS_FILE = "ab_results.jsonl"
+
+VARIANTS = ("A", "B")
+
+# Minimum number of samples per variant to compute p-values from.
+MIN_SAMPLES = 2
+
+# FT result fields which are measurements rather than outputs of the flow,
+# compared as timings or not at all.
+_NON_OUTPUT_FIELDS = ("timings", "telemetry", "artifacts")
+
+
+class AbQualification(object):
+    """Assigns the variants of the devices of a station, and records them."""
+
+    def __init__(self, logs_root_dir: str, firmware_dir_b: str):
+        """
+        Args:
+            logs_root_dir: Root directory of the device log directories.
+            firmware_dir_b: Directory of the FT firmware bundle of variant B,
+              with the file names of the default bundle.
+        """
+        self.path = os.path.join(logs_root_dir, AB_RESULTS_FILE)
+        self.firmware_dir_b = firmware_dir_b
+
+    def next_variant(self) -> str:
+        """Returns the variant of the next device: A and B alternate.
+
+        The alternation carries across runs of the orchestrator on the same
+        logs root directory.
+        """
+        return VARIANTS[len(load_records(self.path)) % len(VARIANTS)]
+
+    def firmware_dir(self, variant: str) -> Optional[str]:
+        """Returns the FT firmware directory of `variant`, None by default."""
+        return self.firmware_dir_b if variant == "B" else None
+
+    def record(self, device_id: str, variant: str, passed: bool,
+               step_results: Dict[str, Tuple[float, bool]],
+               log_dir: str) -> None:
+        """Records the outcome of a device provisioned with `variant`.
+
+        Args:
+            step_results: Duration in seconds and outcome of each step run, as
+              `OtDut.step_results`.
+            log_dir: Log directory of the device, holding its FT result.
+        """
+        record = {
+            "device_id": device_id,
+            "variant": variant,
+            "passed": passed,
+            "step_durations": {
+                step: duration
+                for step, (duration, _) in step_results.items()
+            },
+            "ft_result": os.path.join(log_dir, "ft_result.json"),
+        }
+        with open(self.path, "a") as fp:
+            fp.write(json.dumps(record) + "\n")
+
+
+def load_records(path: str) -> List[dict]:
+    """Loads the A/B records of `path`, if any."""
+    if not os.path.exists(path):
+        return []
+    with open(path, "r") as fp:
+        return [json.loads(line) for line in fp if line.strip()]
+
+
+def _load_ft_result(record: dict) -> Optional[dict]:
+    try:
+        with open(record["ft_result"], "r") as fp:
+            return json.load(fp)
+    except FileNotFoundError:
+        # The FT flow did not run, or the log directory was retired.
+        return None
+
+
+def _normal_p_value(z: float) -> float:
+    """Returns the two-sided p-value of the standard normal statistic `z`."""
+    return math.erfc(abs(z) / math.sqrt(2))
+
+
+def proportions_p_value(failures_a: int, n_a: int, failures_b: int,
+                        n_b: int) -> Optional[float]:
+    """Returns the p-value of a two-proportion z-test of the failure rates."""
+    if n_a < MIN_SAMPLES or n_b < MIN_SAMPLES:
+        return None
+    pooled = (failures_a + failures_b) / (n_a + n_b)
+    se = math.sqrt(pooled * (1 - pooled) * (1 / n_a + 1 / n_b))
+    if se == 0:
+        return None
+    return _normal_p_value((failures_b / n_b - failures_a / n_a) / se)
+
+
+def welch_p_value(a: List[float], b: List[float]) -> Optional[float]:
+    """Returns the p-value of a Welch test of the means of `a` and `b`."""
+    if len(a) < MIN_SAMPLES or len(b) < MIN_SAMPLES:
+        return None
+    se = math.sqrt(
+        statistics.variance(a) / len(a) + statistics.variance(b) / len(b))
+    if se == 0:
+        return None
+    return _normal_p_value((statistics.mean(b) - statistics.mean(a)) / se)
+
+
+def _summary(values: List[float]) -> dict:
+    return {
+        "n": len(values),
+        "mean": statistics.mean(values) if values else None,
+        "median": statistics.median(values) if values else None,
+        "stdev": statistics.stdev(values) if len(values) > 1 else None,
+    }
+
+
+def _timings(ft_result: dict) -> Dict[str, float]:
+    """Returns the FT step and operation durations of `ft_result`, in s."""
+    timings = {}
+    for step in ft_result.get("steps", []):
+        timings[f"ft.{step['step']}"] = step["duration_us"] / 1e6
+    for timing in ft_result.get("timings", []):
+        timings[f"ft.op.{timing['operation']}"] = timing["duration_us"] / 1e6
+    return timings
+
+
+def _flatten(doc, prefix: str = "") -> Dict[str, str]:
+    """Flattens `doc` to its leaves, by dotted path."""
+    if isinstance(doc, dict):
+        items = doc.items()
+    elif isinstance(doc, list):
+        items = enumerate(doc)
+    else:
+        return {prefix: json.dumps(doc)}
+    leaves = {}
+    for key, value in items:
+        if not prefix and key in _NON_OUTPUT_FIELDS:
+            continue
+        if key == "duration_us":
+            continue
+        leaves.update(_flatten(value, f"{prefix}.{key}" if prefix else key))
+    return leaves
+
+
+def output_diffs(results: Dict[str, List[dict]]) -> List[dict]:
+    """Returns the fields of the FT results telling the variants apart.
+
+    Args:
+        results: The FT results of the devices of each variant.
+    """
+    values = {variant: {} for variant in VARIANTS}
+    for variant in VARIANTS:
+        for result in results[variant]:
+            for path, value in _flatten(result).items():
+                values[variant].setdefault(path, set()).add(value)
+    diffs = []
+    for path in sorted(set(values["A"]) | set(values["B"])):
+        a, b = values["A"].get(path), values["B"].get(path)
+        if a is None or b is None:
+            diffs.append({
+                "field": path,
+                "A": sorted(a) if a else None,
+                "B": sorted(b) if b else None,
+            })
+        elif len(a) == 1 and len(b) == 1 and a != b:
+            diffs.append({"field": path, "A": sorted(a), "B": sorted(b)})
+    return diffs
+
+
+def compare(records: List[dict]) -> dict:
+    """Compares the timings, failures and outputs of the two variants."""
+    devices = {variant: [] for variant in VARIANTS}
+    for record in records:
+        devices[record["variant"]].append(record)
+    results = {variant: [] for variant in VARIANTS}
+    timings = {variant: {} for variant in VARIANTS}
+    failures = {}
+    for variant in VARIANTS:
+        error_kinds = {}
+        for record in devices[variant]:
+            for step, duration in record["step_durations"].items():
+                timings[variant].setdefault(step, []).append(duration)
+            ft_result = _load_ft_result(record)
+            if ft_result is None:
+                continue
+            results[variant].append(ft_result)
+            for name, duration in _timings(ft_result).items():
+                timings[variant].setdefault(name, []).append(duration)
+            if ft_result.get("error_kind"):
+                kind = ft_result["error_kind"]
+                error_kinds[kind] = error_kinds.get(kind, 0) + 1
+        n = len(devices[variant])
+        failed = sum(not record["passed"] for record in devices[variant])
+        failures[variant] = {
+            "devices": n,
+            "failed": failed,
+            "failure_rate": failed / n if n else None,
+            "error_kinds": error_kinds,
+        }
+    failures["p_value"] = proportions_p_value(failures["A"]["failed"],
+                                              failures["A"]["devices"],
+                                              failures["B"]["failed"],
+                                              failures["B"]["devices"])
+    timing_report = {}
+    for name in sorted(set(timings["A"]) | set(timings["B"])):
+        a, b = timings["A"].get(name, []), timings["B"].get(name, [])
+        timing_report[name] = {
+            "A": _summary(a),
+            "B": _summary(b),
+            "p_value": welch_p_value(a, b),
+        }
+    return {
+        "failures": failures,
+        "timings": timing_report,
+        "output_diffs": output_diffs(results),
+    }
+
+
+def main(args_in):
+    parser = argparse.ArgumentParser(
+        description="""Compares the timings, failures and outputs of the two
+        provisioning firmware bundles of an A/B qualification run.""")
+    parser.add_argument(
+        "--log-dir",
+        required=True,
+        help="Root directory the orchestrator stores log files under.",
+    )
+    args = parser.parse_args(args_in)
+
+    logging.basicConfig(level=logging.INFO)
+    records = load_records(os.path.join(args.log_dir, AB_RESULTS_FILE))
+    if not records:
+        logging.error(f"No A/B records in {args.log_dir}.")
+        sys.exit(1)
+    print(json.dumps(compare(records), indent=2))
+
+
+if __name__ == "__main__":
+    main(sys.argv[1:])
@@ -1,8 +1,9 @@
 # Copyright lowRISC contributors (OpenTitan project).
 # Licensed under the Apache License, Version 2.0, see LICENSE for details.
 # SPDX-License-Identifier: Apache-2.0
-"""Module for loading and validating OpenTitan SKU configuration."""
+"""Module for validating the CA configurations of a key bundle."""
 
+import re
 from dataclasses import dataclass
 from pathlib import Path
 
@@ -11,14 +12,13 @@ from pathlib import Path
 class CaConfig:
     """Class for Certificate Authority configuration."""
     name: str  # valid: must be in ["dice_ca", "ext_ca"]
-    certificate: str  # valid: any valid path to a CA PEM file
-    key_type: str  # valid: must be in ["Raw", "Token"]
+    certificate: str  # valid: PEM CA certificate
+    key_type: str  # valid: must be in ["Raw", "Token", "Piv"]
     key_id: str  # valid: 160-bit serial number of CA certificate
-    key: str  # valid: valid path to DER CA private key file or key token ID
+    key: str  # valid: path to DER CA private key file, token ID or PIV slot
 
     def __post_init__(self):
-        # Update certificate and key members to Path objs if necessary.
-        self.certificate = Path(self.certificate)
+        # Update the key member to a Path obj if necessary.
         if self.key_type == "Raw":
             self.key = Path(self.key)
         self.validate()
@@ -28,17 +28,15 @@ class CaConfig:
         # Validate name.
         if self.name not in {"dice_ca", "ext_ca"}:
             raise ValueError("CA name must be in [\"dice_ca\", \"ext_ca\"]")
-        # Validate certificate path.
-        if not self.certificate.exists():
-            raise ValueError("CA certificate file ({}) does not exist.".format(
-                self.certificate))
-        if self.certificate.suffix != ".pem":
+        # Validate certificate.
+        if not self.certificate.startswith("-----BEGIN CERTIFICATE-----"):
             raise ValueError(
-                "CA certificate file ({}) must be a PEM file.".format(
-                    self.certificate))
+                "CA certificate ({}) must be a PEM certificate.".format(
+                    self.name))
         # Validate key_type.
-        if self.key_type not in {"Raw", "Token"}:
-            raise ValueError("CA key type must be in [\"Raw\", \"Token\"]")
+        if self.key_type not in {"Raw", "Token", "Piv"}:
+            raise ValueError(
+                "CA key type must be in [\"Raw\", \"Token\", \"Piv\"]")
         # TODO: validate key_id
         # Validate key.
         if self.key_type == "Raw":
@@ -51,11 +49,8 @@ class CaConfig:
         elif self.key_type == "Token":
             # TODO: check if Cloud KMS / Nitokey token ID exists.
             pass
-
-    def to_dict_entry(self) -> dict:
-        return {
-            "certificate": str(self.certificate),
-            "key": str(self.key),
-            "key_type": self.key_type,
-            "key_id": self.key_id,
-        }
+        elif self.key_type == "Piv":
+            if not re.fullmatch(r"[0-9a-fA-F]{2}(@.+)?", self.key):
+                raise ValueError(
+                    "CA PIV key ({}) must be a slot, as <slot>[@<reader>].".
+                    format(self.key))
@@ -35,6 +35,12 @@ class DB(object):
             raise RuntimeError("No connection to commit")
         self._conn.commit()
 
+    def rollback(self):
+        """Rolls back the current transaction."""
+        if not self._conn:
+            raise RuntimeError("No connection to roll back")
+        self._conn.rollback()
+
 
 @dataclass
 class DeviceRecord(object):
@@ -49,6 +55,7 @@ class DeviceRecord(object):
     dice_cdi0: str
     dice_cdi1: str
     sku_specific_data: str
+    fingerprint: str
 
     @staticmethod
     def schema_list():
@@ -105,6 +112,25 @@ class DeviceRecord(object):
             return None
         return DeviceRecord(*record)
 
+    @staticmethod
+    def query_by_fingerprint(db: DB, fingerprint: str) -> 'DeviceRecord':
+        """Queries the database for a record with the given fingerprint.
+
+        Args:
+            db: The database object.
+            fingerprint: The device fingerprint to look up.
+        Returns:
+            The record from the database.
+        """
+        c = db.try_cursor()
+        c.execute(
+            f"SELECT * FROM {DeviceRecord.table_name()} WHERE fingerprint=?",
+            (fingerprint, ))
+        record = c.fetchone()
+        if record is None:
+            return None
+        return DeviceRecord(*record)
+
     @staticmethod
     def query_all(db: DB) -> ['DeviceRecord']:
         """Queries the database for all records.
@@ -126,22 +152,15 @@ class DeviceRecord(object):
             db: The database object.
         """
         c = db.try_cursor()
-        c.execute(
-            f"INSERT INTO {DeviceRecord.table_name()} VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
-            (
-                self.device_id,
-                self.sku,
-                self.provisioning_state,
-                self.provisioning_log,
-                self.timestamp,
-                self.rma_unlock_token,
-                self.dice_uds,
-                self.dice_cdi0,
-                self.dice_cdi1,
-                self.sku_specific_data,
-            ))
+        self._insert(c)
         db.commit()
 
+    def _insert(self, c):
+        keys = DeviceRecord.__annotations__.keys()
+        c.execute(
+            f"INSERT INTO {DeviceRecord.table_name()} VALUES ({', '.join(['?'] * len(keys))})",
+            [getattr(self, field) for field in keys])
+
     def update(self, db: DB):
         """Updates the record in the database.
 
@@ -149,6 +168,10 @@ class DeviceRecord(object):
             db: The database object.
         """
         c = db.try_cursor()
+        self._update(c)
+        db.commit()
+
+    def _update(self, c):
         keys = DeviceRecord.__annotations__.keys()
         update_params = [
             f"{field}=?" for field in keys if field != "device_id"
@@ -160,7 +183,6 @@ class DeviceRecord(object):
         c.execute(
             f"UPDATE {DeviceRecord.table_name()} SET {', '.join(update_params)} WHERE device_id=?",
             update_vals)
-        db.commit()
 
     def upsert(self, db: DB):
         """Inserts or updates the record in the database.
@@ -172,3 +194,389 @@ class DeviceRecord(object):
             self.insert(db)
         else:
             self.update(db)
+
+    def upsert_unless_duplicate(self, db: DB) -> 'DeviceRecord':
+        """Upserts the record, unless a record with the same fingerprint exists.
+
+        The lookup and write are done in a single write transaction, so
+        concurrent provisioning processes sharing the database cannot both
+        record the same results.
+
+        Args:
+            db: The database object.
+        Returns:
+            The existing record if this record is a duplicate, else None.
+        """
+        c = db.try_cursor()
+        c.execute("BEGIN IMMEDIATE")
+        try:
+            c.execute(
+                f"SELECT * FROM {DeviceRecord.table_name()} WHERE fingerprint=?",
+                (self.fingerprint, ))
+            duplicate = c.fetchone()
+            if duplicate is None:
+                c.execute(
+                    f"SELECT 1 FROM {DeviceRecord.table_name()} WHERE device_id=?",
+                    (self.device_id, ))
+                if c.fetchone() is None:
+                    self._insert(c)
+                else:
+                    self._update(c)
+        except Exception:
+            db.rollback()
+            raise
+        db.commit()
+        if duplicate is not None:
+            return DeviceRecord(*duplicate)
+        return None
+
+
+@dataclass
+class TokenUsageRecord(object):
+    """Class for holding the LC transition tokens consumed by a device.
+
+    Tokens are only stored as token IDs, see token_usage.token_id.
+    """
+    device_id: str
+    sku: str
+    lot: str
+    token_generation: str
+    test_unlock_token_id: str
+    test_exit_token_id: str
+    timestamp: int
+    passed: int
+
+    @staticmethod
+    def table_name() -> str:
+        return "token_usage"
+
+    @staticmethod
+    def create_table(db: DB):
+        """Creates a table in the database.
+
+        Args:
+            db: The database object.
+        """
+        type_map = {"str": "text", "int": "int"}
+        schema = [
+            f"{key} {type_map[value.__name__]}"
+            for key, value in TokenUsageRecord.__annotations__.items()
+        ]
+        c = db.try_cursor()
+        c.execute(
+            f"CREATE TABLE IF NOT EXISTS {TokenUsageRecord.table_name()} ({', '.join(schema)})"
+        )
+        db.commit()
+
+    @staticmethod
+    def query_all(db: DB) -> ['TokenUsageRecord']:
+        """Queries the database for all records, oldest first.
+
+        Args:
+            db: The database object.
+        Returns:
+            All records from the database.
+        """
+        c = db.try_cursor()
+        c.execute(
+            f"SELECT * FROM {TokenUsageRecord.table_name()} ORDER BY timestamp"
+        )
+        return [TokenUsageRecord(*record) for record in c.fetchall()]
+
+    def insert(self, db: DB):
+        """Inserts the record into the database.
+
+        Devices provisioned multiple times get one record per run, as each
+        run consumes the tokens again.
+
+        Args:
+            db: The database object.
+        """
+        keys = TokenUsageRecord.__annotations__.keys()
+        c = db.try_cursor()
+        c.execute(
+            f"INSERT INTO {TokenUsageRecord.table_name()} VALUES ({', '.join(['?'] * len(keys))})",
+            [getattr(self, field) for field in keys])
+        db.commit()
+
+
+@dataclass
+class StepDurationRecord(object):
+    """Class for holding the duration of a provisioning step of a device."""
+    device_id: str
+    sku: str
+    step: str
+    duration_ms: int
+    timestamp: int
+    passed: int
+
+    @staticmethod
+    def table_name() -> str:
+        return "step_durations"
+
+    @staticmethod
+    def create_table(db: DB):
+        """Creates a table in the database.
+
+        Args:
+            db: The database object.
+        """
+        type_map = {"str": "text", "int": "int"}
+        schema = [
+            f"{key} {type_map[value.__name__]}"
+            for key, value in StepDurationRecord.__annotations__.items()
+        ]
+        c = db.try_cursor()
+        c.execute(
+            f"CREATE TABLE IF NOT EXISTS {StepDurationRecord.table_name()} ({', '.join(schema)})"
+        )
+        db.commit()
+
+    @staticmethod
+    def query_passed_durations(db: DB, sku: str, step: str,
+                               limit: int) -> [int]:
+        """Queries the durations of the most recent passing runs of a step.
+
+        Args:
+            db: The database object.
+            sku: The SKU name of the devices.
+            step: The provisioning step.
+            limit: The maximum number of durations returned.
+        Returns:
+            The durations in milliseconds, newest first.
+        """
+        c = db.try_cursor()
+        c.execute(
+            f"SELECT duration_ms FROM {StepDurationRecord.table_name()} "
+            "WHERE sku=? AND step=? AND passed=1 "
+            "ORDER BY timestamp DESC LIMIT ?", (sku, step, limit))
+        return [row[0] for row in c.fetchall()]
+
+    def insert(self, db: DB):
+        """Inserts the record into the database.
+
+        Args:
+            db: The database object.
+        """
+        keys = StepDurationRecord.__annotations__.keys()
+        c = db.try_cursor()
+        c.execute(
+            f"INSERT INTO {StepDurationRecord.table_name()} VALUES ({', '.join(['?'] * len(keys))})",
+            [getattr(self, field) for field in keys])
+        db.commit()
+
+
+@dataclass
+class RegistrationRecord(object):
+    """Class for holding the registry upload state of a device.
+
+    Records are keyed by the device fingerprint, so resubmitting the same
+    results (e.g. after a crash) does not queue another upload.
+    """
+    device_id: str
+    sku: str
+    fingerprint: str
+    payload: str
+    state: str
+    batch_id: str
+    attempts: int
+    registry_id: str
+    error: str
+    timestamp: int
+
+    @staticmethod
+    def table_name() -> str:
+        return "registrations"
+
+    @staticmethod
+    def create_table(db: DB):
+        """Creates a table in the database.
+
+        Args:
+            db: The database object.
+        """
+        type_map = {"str": "text", "int": "int"}
+        schema = [
+            f"{key} {type_map[value.__name__]}"
+            for key, value in RegistrationRecord.__annotations__.items()
+        ]
+        c = db.try_cursor()
+        c.execute(
+            f"CREATE TABLE IF NOT EXISTS {RegistrationRecord.table_name()} ({', '.join(schema)})"
+        )
+        c.execute(
+            f"CREATE UNIQUE INDEX IF NOT EXISTS {RegistrationRecord.table_name()}_fingerprint "
+            f"ON {RegistrationRecord.table_name()} (fingerprint)")
+        db.commit()
+
+    @staticmethod
+    def query_by_state(db: DB, state: str) -> ['RegistrationRecord']:
+        """Queries the database for all records in a state, oldest first.
+
+        Args:
+            db: The database object.
+            state: The registration state.
+        Returns:
+            The records from the database.
+        """
+        c = db.try_cursor()
+        c.execute(
+            f"SELECT * FROM {RegistrationRecord.table_name()} WHERE state=? "
+            "ORDER BY timestamp, rowid", (state, ))
+        return [RegistrationRecord(*record) for record in c.fetchall()]
+
+    @staticmethod
+    def query_by_fingerprint(db: DB,
+                             fingerprint: str) -> 'RegistrationRecord':
+        """Queries the database for the record of a device fingerprint.
+
+        Args:
+            db: The database object.
+            fingerprint: The device fingerprint to look up.
+        Returns:
+            The record from the database.
+        """
+        c = db.try_cursor()
+        c.execute(
+            f"SELECT * FROM {RegistrationRecord.table_name()} WHERE fingerprint=?",
+            (fingerprint, ))
+        record = c.fetchone()
+        if record is None:
+            return None
+        return RegistrationRecord(*record)
+
+    def insert_unless_duplicate(self, db: DB) -> 'RegistrationRecord':
+        """Inserts the record, unless a record with the same fingerprint exists.
+
+        Args:
+            db: The database object.
+        Returns:
+            The existing record if this record is a duplicate, else None.
+        """
+        keys = RegistrationRecord.__annotations__.keys()
+        c = db.try_cursor()
+        c.execute(
+            f"INSERT OR IGNORE INTO {RegistrationRecord.table_name()} VALUES ({', '.join(['?'] * len(keys))})",
+            [getattr(self, field) for field in keys])
+        inserted = c.rowcount == 1
+        db.commit()
+        if inserted:
+            return None
+        return RegistrationRecord.query_by_fingerprint(db, self.fingerprint)
+
+    def update(self, db: DB):
+        """Updates the record in the database.
+
+        Args:
+            db: The database object.
+        """
+        keys = [
+            field for field in RegistrationRecord.__annotations__.keys()
+            if field != "fingerprint"
+        ]
+        c = db.try_cursor()
+        c.execute(
+            f"UPDATE {RegistrationRecord.table_name()} SET {', '.join(f'{field}=?' for field in keys)} "
+            "WHERE fingerprint=?",
+            [getattr(self, field) for field in keys] + [self.fingerprint])
+        db.commit()
+
+
+@dataclass
+class QuotaUsageRecord(object):
+    """Class for holding the quota usage of a device.
+
+    Devices are recorded before they are started, so devices whose flows
+    fail still count against the quotas.
+    """
+    device_id: str
+    sku: str
+    dice_key_id: str
+    ext_key_id: str
+    lot: str
+    shift: str
+    override: str
+    timestamp: int
+
+    @staticmethod
+    def table_name() -> str:
+        return "quota_usage"
+
+    @staticmethod
+    def create_table(db: DB):
+        """Creates a table in the database.
+
+        Args:
+            db: The database object.
+        """
+        type_map = {"str": "text", "int": "int"}
+        schema = [
+            f"{key} {type_map[value.__name__]}"
+            for key, value in QuotaUsageRecord.__annotations__.items()
+        ]
+        c = db.try_cursor()
+        c.execute(
+            f"CREATE TABLE IF NOT EXISTS {QuotaUsageRecord.table_name()} ({', '.join(schema)})"
+        )
+        db.commit()
+
+    @staticmethod
+    def count_by_key(db: DB, key_id: str) -> int:
+        """Counts the devices certified by a CA key.
+
+        Args:
+            db: The database object.
+            key_id: The ID of the DICE or extension CA key.
+        Returns:
+            The number of devices.
+        """
+        c = db.try_cursor()
+        c.execute(
+            f"SELECT COUNT(*) FROM {QuotaUsageRecord.table_name()} "
+            "WHERE dice_key_id=? OR ext_key_id=?", (key_id, key_id))
+        return c.fetchone()[0]
+
+    @staticmethod
+    def count_by_lot(db: DB, lot: str) -> int:
+        """Counts the devices of a production lot.
+
+        Args:
+            db: The database object.
+            lot: The name of the production lot.
+        Returns:
+            The number of devices.
+        """
+        c = db.try_cursor()
+        c.execute(
+            f"SELECT COUNT(*) FROM {QuotaUsageRecord.table_name()} WHERE lot=?",
+            (lot, ))
+        return c.fetchone()[0]
+
+    @staticmethod
+    def count_by_shift(db: DB, shift: str) -> int:
+        """Counts the devices started during a shift.
+
+        Args:
+            db: The database object.
+            shift: The name of the shift.
+        Returns:
+            The number of devices.
+        """
+        c = db.try_cursor()
+        c.execute(
+            f"SELECT COUNT(*) FROM {QuotaUsageRecord.table_name()} WHERE shift=?",
+            (shift, ))
+        return c.fetchone()[0]
+
+    def insert(self, db: DB):
+        """Inserts the record into the database.
+
+        Args:
+            db: The database object.
+        """
+        keys = QuotaUsageRecord.__annotations__.keys()
+        c = db.try_cursor()
+        c.execute(
+            f"INSERT INTO {QuotaUsageRecord.table_name()} VALUES ({', '.join(['?'] * len(keys))})",
+            [getattr(self, field) for field in keys])
+        db.commit()
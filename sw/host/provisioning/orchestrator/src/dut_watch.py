@@ -0,0 +1,256 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Watch mode of engineering stations, for benches without a handler.
+
+The operator inserts the devices in the socket by hand. In watch mode, the
+orchestrator polls the debug interface until a device answers, runs the flows
+on it, then waits for the device to be removed before watching for the next
+one.
+
+A device is present when its LC TAP answers over JTAG, i.e. OpenOCD finds the
+IDCODE of the device on the scan chain. A device half-seated in the socket may
+answer intermittently, so its presence (or absence) must be seen on several
+consecutive polls. See `ot_dut.presence_probe`.
+
+For shift changes or handler jams, the operator pauses the station by creating
+its pause file, and resumes it by deleting the file, without stopping the
+orchestrator and losing its session state. The device being provisioned
+finishes its flows, and no device is started while paused.
+
+When the station runs as a service, the service manager stops it through a
+`StopControl`: the device being provisioned finishes its flows, then the
+orchestrator exits instead of watching for the next device. Its configuration
+is reloaded through a `ReloadControl`, between two devices.
+"""
+
+import logging
+import os
+import signal
+import threading
+import time
+from typing import Callable
+
+
+class DutWatcher(object):
+    """Waits for devices to be inserted in and removed from the socket."""
+
+    def __init__(self,
+                 probe: Callable[[], bool],
+                 poll_interval: float = 1.0,
+                 debounce: int = 3,
+                 sleep: Callable[[float], None] = time.sleep,
+                 stopped: Callable[[], bool] = lambda: False):
+        """
+        Args:
+            probe: Returns True if a device answers on the debug interface.
+            poll_interval: Seconds between two probes.
+            debounce: Number of consecutive probes a change must be seen on.
+            sleep: Sleeps between two probes; replaced in tests.
+            stopped: Returns True once the station is stopped, ending the
+              waits.
+        """
+        if debounce < 1:
+            raise ValueError("Debounce must be at least one probe.")
+        self.probe = probe
+        self.poll_interval = poll_interval
+        self.debounce = debounce
+        self._sleep = sleep
+        self._stopped = stopped
+
+    def _wait_for(self, present: bool, timeout: float = None) -> bool:
+        deadline = None if timeout is None else time.monotonic() + timeout
+        seen = 0
+        while True:
+            seen = seen + 1 if self.probe() == present else 0
+            if seen >= self.debounce:
+                return True
+            if deadline is not None and time.monotonic() >= deadline:
+                return False
+            if self._stopped():
+                return False
+            self._sleep(self.poll_interval)
+
+    def wait_for_insertion(self, timeout: float = None) -> bool:
+        """Waits for a device to answer on the debug interface.
+
+        Returns:
+            False if no device was inserted within `timeout` seconds, or
+            before the station was stopped.
+        """
+        logging.info("Waiting for a device to be inserted ...")
+        if not self._wait_for(True, timeout):
+            return False
+        logging.info("Device inserted.")
+        return True
+
+    def wait_for_removal(self, timeout: float = None) -> bool:
+        """Waits for the device to stop answering on the debug interface.
+
+        Returns:
+            False if the device was not removed within `timeout` seconds, or
+            before the station was stopped.
+        """
+        logging.info("Remove the device from the socket.")
+        if not self._wait_for(False, timeout):
+            return False
+        logging.info("Device removed.")
+        return True
+
+
+class PauseControl(object):
+    """Holds the start of new devices while the pause file exists."""
+
+    def __init__(self,
+                 pause_file: str,
+                 poll_interval: float = 1.0,
+                 sleep: Callable[[float], None] = time.sleep,
+                 stopped: Callable[[], bool] = lambda: False):
+        """
+        Args:
+            pause_file: Path of the pause file. Its contents, if any, are
+              logged as the reason of the pause.
+            poll_interval: Seconds between two checks of the pause file.
+            sleep: Sleeps between two checks; replaced in tests.
+            stopped: Returns True once the station is stopped, ending the
+              pause.
+        """
+        self.pause_file = pause_file
+        self.poll_interval = poll_interval
+        self._sleep = sleep
+        self._stopped = stopped
+
+    def paused(self) -> bool:
+        return os.path.exists(self.pause_file)
+
+    def pause(self, reason: str = "") -> None:
+        with open(self.pause_file, "w") as f:
+            f.write(reason)
+
+    def resume(self) -> None:
+        try:
+            os.remove(self.pause_file)
+        except FileNotFoundError:
+            pass
+
+    def _reason(self) -> str:
+        try:
+            with open(self.pause_file, "r") as f:
+                return f.read().strip()
+        except FileNotFoundError:
+            return ""
+
+    def wait_while_paused(self) -> bool:
+        """Waits for the station to be resumed, if it is paused.
+
+        Returns:
+            True if the station was paused.
+        """
+        if not self.paused():
+            return False
+        reason = self._reason()
+        logging.warning(f"Station paused{': ' + reason if reason else ''}. "
+                        f"Delete {self.pause_file} to resume.")
+        start = time.monotonic()
+        while self.paused():
+            if self._stopped():
+                return True
+            self._sleep(self.poll_interval)
+        logging.info(
+            f"Station resumed after {time.monotonic() - start:.0f}s.")
+        return True
+
+
+class StopControl(object):
+    """Stop requests of the service manager running the station."""
+
+    def __init__(self):
+        self._stop = threading.Event()
+        self.reason = ""
+
+    def request_stop(self, reason: str = "") -> None:
+        """Requests the station to stop after the device being provisioned.
+
+        Called by the signal handlers, or directly by a service wrapper, e.g.
+        from the stop callback of a Windows service.
+        """
+        if not self._stop.is_set():
+            self.reason = reason
+            logging.warning(
+                f"Station stopping{': ' + reason if reason else ''}. The "
+                "device being provisioned finishes its flows.")
+        self._stop.set()
+
+    def stopped(self) -> bool:
+        return self._stop.is_set()
+
+    def install_signal_handlers(self) -> None:
+        """Requests a stop on SIGTERM, and on SIGBREAK on Windows.
+
+        Service managers stop their services with these signals: systemd
+        sends SIGTERM, and the Windows service wrappers (e.g. NSSM) send the
+        CTRL_BREAK event, raised as SIGBREAK in Python. Must be called from
+        the main thread.
+        """
+        for name in ("SIGTERM", "SIGBREAK"):
+            signum = getattr(signal, name, None)
+            if signum is not None:
+                signal.signal(
+                    signum,
+                    lambda signum, frame, name=name: self.request_stop(name))
+
+
+class ReloadControl(object):
+    """Configuration reload requests of the station.
+
+    A reload is requested with SIGHUP, by creating the reload file (e.g. from
+    an admin script, or on Windows, which has no SIGHUP), or directly by a
+    service wrapper. It is applied by the orchestrator before the next device,
+    so the device being provisioned keeps the configuration it started with.
+    """
+
+    def __init__(self, reload_file: str = None):
+        """
+        Args:
+            reload_file: Path of the reload file. Its contents, if any, are
+              logged as the reason of the reload; it is deleted once taken.
+        """
+        self.reload_file = reload_file
+        self._reload = threading.Event()
+        self.reason = ""
+
+    def request_reload(self, reason: str = "") -> None:
+        """Requests the configuration to be reloaded before the next device."""
+        self.reason = reason
+        logging.info(
+            f"Configuration reload requested{': ' + reason if reason else ''}."
+        )
+        self._reload.set()
+
+    def _take_reload_file(self) -> bool:
+        if self.reload_file is None:
+            return False
+        try:
+            with open(self.reload_file, "r") as f:
+                reason = f.read().strip()
+            os.remove(self.reload_file)
+        except FileNotFoundError:
+            return False
+        self.request_reload(reason or self.reload_file)
+        return True
+
+    def take(self) -> bool:
+        """Returns whether a reload was requested since the last call."""
+        self._take_reload_file()
+        requested = self._reload.is_set()
+        self._reload.clear()
+        return requested
+
+    def install_signal_handlers(self) -> None:
+        """Requests a reload on SIGHUP, where it exists.
+
+        Must be called from the main thread.
+        """
+        if hasattr(signal, "SIGHUP"):
+            signal.signal(signal.SIGHUP,
+                          lambda signum, frame: self.request_reload("SIGHUP"))
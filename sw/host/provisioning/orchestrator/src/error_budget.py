@@ -0,0 +1,122 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Error budget halting the line on repeated identical failures.
+
+A fixture fault, e.g. a bent pogo pin or a dead JTAG adapter, fails every
+device the same way. Rather than scrapping a whole tray, the line is halted
+once a number of consecutive devices failed with the same error class, and
+stays halted until an operator acknowledges the halt, after fixing the
+fixture.
+
+The error class of a failed run is the `error_kind` of its FT result, if any,
+else the step it failed in. The streak is persisted to a state file, so it is
+tracked across orchestrator invocations (one per device).
+"""
+
+import json
+import logging
+import os
+from typing import Optional
+
+
+def failure_class(log_dir: str, step_results: dict,
+                  timed_out_steps: list) -> str:
+    """Returns the error class of a failed run.
+
+    Args:
+        log_dir: Log directory of the device.
+        step_results: Duration and outcome of each step run, by step, see
+          `OtDut.step_results`.
+        timed_out_steps: Steps terminated after exceeding their timeout.
+    """
+    failed = [
+        s for s in ["cp", "ft"] if s in step_results and not step_results[s][1]
+    ]
+    if not failed:
+        # The run was aborted, e.g. by the operator, outside of the steps.
+        return "aborted"
+    step = failed[0]
+    if step in timed_out_steps:
+        return f"{step}-timeout"
+    if step == "cp":
+        return "cp"
+    try:
+        with open(f"{log_dir}/ft_result.json", "r") as fp:
+            result = json.load(fp)
+    except (OSError, ValueError):
+        return "ft"
+    if result.get("error_kind"):
+        return result["error_kind"]
+    for ft_step in result.get("steps", []):
+        if not ft_step.get("passed", True):
+            return f"ft:{ft_step['step']}"
+    return "ft"
+
+
+class ErrorBudget(object):
+    """Tracks the consecutive devices failing with the same error class."""
+
+    def __init__(self, max_consecutive: int, state_file: str,
+                 sku: str) -> None:
+        if max_consecutive < 1:
+            raise ValueError("Error budget must be at least one device.")
+        self.max_consecutive = max_consecutive
+        self.state_file = state_file
+        self.sku = sku
+        self.error_class = None
+        self.devices = []
+        self.halted = False
+        if os.path.exists(state_file):
+            with open(state_file, "r") as fp:
+                state = json.load(fp)
+            self.error_class = state.get("error_class")
+            self.devices = state.get("devices", [])
+            self.halted = state.get("halted", False)
+
+    def _save(self) -> None:
+        state = {
+            "error_class": self.error_class,
+            "devices": self.devices,
+            "halted": self.halted,
+        }
+        tmp_file = f"{self.state_file}.tmp"
+        with open(tmp_file, "w") as fp:
+            json.dump(state, fp, indent=2)
+        os.replace(tmp_file, self.state_file)
+
+    @property
+    def reason(self) -> str:
+        return (f"Line halted for SKU {self.sku}: {len(self.devices)} "
+                f"consecutive devices failed with {self.error_class} "
+                f"({', '.join(self.devices)}).")
+
+    def record(self, device_id: str, error_class: Optional[str]) -> bool:
+        """Records a device outcome; `error_class` is None if it passed.
+
+        Returns:
+            True if the line was halted by this outcome.
+        """
+        if error_class is None:
+            self.error_class = None
+            self.devices = []
+        elif error_class == self.error_class:
+            self.devices.append(device_id)
+        else:
+            self.error_class = error_class
+            self.devices = [device_id]
+        triggered = (not self.halted and
+                     len(self.devices) >= self.max_consecutive)
+        if triggered:
+            self.halted = True
+            logging.error(self.reason)
+        self._save()
+        return triggered
+
+    def acknowledge(self) -> None:
+        """Acknowledges a halt of the line, and restarts the streak."""
+        logging.warning(f"Acknowledged: {self.reason}")
+        self.error_class = None
+        self.devices = []
+        self.halted = False
+        self._save()
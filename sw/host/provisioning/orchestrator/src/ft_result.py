@@ -0,0 +1,90 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Module for parsing and fingerprinting FT provisioning results."""
+
+import hashlib
+import json
+import time
+
+from db import DeviceRecord
+
+# Prefix of the line carrying the FT results in the FT host binary output.
+_PROVISIONING_DATA_PREFIX = "PROVISIONING_DATA: "
+
+# Names of the DICE certificates stored in dedicated device record columns.
+DICE_CERTS = {"UDS": "dice_uds", "CDI_0": "dice_cdi0", "CDI_1": "dice_cdi1"}
+
+
+def parse_provisioning_data(log_file: str) -> dict:
+    """Extracts the FT results from the FT host binary output.
+
+    Args:
+        log_file: Path to the captured stdout of the FT host binary.
+    Returns:
+        The FT results, as a dict.
+    """
+    with open(log_file, "r") as fp:
+        for line in fp:
+            if line.startswith(_PROVISIONING_DATA_PREFIX):
+                return json.loads(line[len(_PROVISIONING_DATA_PREFIX):])
+    raise ValueError(f"No provisioning data found in {log_file}.")
+
+
+def final_lc_state(ft_data: dict) -> str:
+    """Returns the LC state the device was left in by the FT flow."""
+    lc_state = ft_data["lc_state"]
+    return lc_state.get("mission_mode") or lc_state["unlocked"]
+
+
+def device_fingerprint(ft_data: dict) -> str:
+    """Computes the fingerprint of a device's FT results.
+
+    The fingerprint covers the final LC state, the device ID and the hash of
+    all endorsed certificates, so two runs producing the exact same device
+    identity have the same fingerprint.
+
+    Args:
+        ft_data: The FT results, as returned by `parse_provisioning_data`.
+    Returns:
+        The fingerprint, as a SHA256 hexstring.
+    """
+    certs_hash = hashlib.sha256()
+    for name in sorted(ft_data["certs"]):
+        certs_hash.update(name.encode("utf-8"))
+        certs_hash.update(ft_data["certs"][name]["bytes"].encode("utf-8"))
+    fingerprint = {
+        "lc_state": final_lc_state(ft_data),
+        "device_id": ft_data["device_id"],
+        "certs": certs_hash.hexdigest(),
+    }
+    return hashlib.sha256(
+        json.dumps(fingerprint, sort_keys=True).encode("utf-8")).hexdigest()
+
+
+def device_record(ft_data: dict, sku: str, log_dir: str) -> DeviceRecord:
+    """Builds a device record from a device's FT results.
+
+    Args:
+        ft_data: The FT results, as returned by `parse_provisioning_data`.
+        sku: The SKU name of the device.
+        log_dir: The directory holding the provisioning logs of the device.
+    Returns:
+        The device record.
+    """
+    certs = {name: cert["bytes"] for name, cert in ft_data["certs"].items()}
+    dice_certs = {
+        column: certs.pop(name, "")
+        for name, column in DICE_CERTS.items()
+    }
+    return DeviceRecord(
+        device_id=ft_data["device_id"],
+        sku=sku,
+        provisioning_state=final_lc_state(ft_data),
+        provisioning_log=log_dir,
+        timestamp=int(time.time()),
+        rma_unlock_token=ft_data["rma_unlock_token"],
+        sku_specific_data=json.dumps(certs, sort_keys=True),
+        fingerprint=device_fingerprint(ft_data),
+        **dice_certs,
+    )
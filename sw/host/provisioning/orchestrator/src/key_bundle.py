@@ -0,0 +1,117 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Module for loading and validating signed provisioning key bundles.
+
+A key bundle lists the public keys and key handles a provisioning station
+uses: the CA certificates and key references, and the RMA token wrap key. It
+is signed by the key custodian; see
+sw/host/provisioning/ft_lib/src/key_bundle.rs for the format.
+"""
+
+import json
+from dataclasses import dataclass
+from datetime import datetime, timezone
+
+from Crypto.Hash import SHA256
+from Crypto.PublicKey import ECC
+from Crypto.Signature import DSS
+
+KEY_BUNDLE_SCHEMA_VERSION = 1
+
+# Names of the CAs a key bundle must list.
+_REQUIRED_CAS = ("dice", "ext")
+
+# Size of a raw `R || S` ECDSA P-256 signature.
+_RAW_SIGNATURE_SIZE = 64
+
+
+def _verify_signature(doc: bytes, signature: bytes, key_der: bytes) -> None:
+    """Checks an ECDSA P-256 signature of `doc`, in raw or DER format.
+
+    Raw signatures are encoded as by opentitantool: `R || S`, each half
+    little-endian.
+
+    Raises:
+        ValueError: if the signature is invalid.
+    """
+    if len(signature) == _RAW_SIGNATURE_SIZE:
+        half = _RAW_SIGNATURE_SIZE // 2
+        signature = signature[:half][::-1] + signature[half:][::-1]
+        encoding = "binary"
+    else:
+        encoding = "der"
+    verifier = DSS.new(ECC.import_key(key_der), "fips-186-3", encoding)
+    verifier.verify(SHA256.new(doc), signature)
+
+
+@dataclass
+class KeyBundle:
+    """Class for storing a provisioning key bundle."""
+    schema_version: int  # valid: KEY_BUNDLE_SCHEMA_VERSION
+    name: str
+    skus: list  # valid: names of the SKUs the bundle may be used for
+    not_after: str  # valid: RFC 3339 expiry of the bundle
+    rma_wrap_key: str  # valid: hex string of an RSA, X25519 or P-384 public key DER
+    cas: dict  # valid: must include _REQUIRED_CAS; see CaConfig
+
+    @staticmethod
+    def load_signed(path: str, signature: str, key: str) -> "KeyBundle":
+        """Loads a key bundle, after checking its detached signature.
+
+        Raises:
+            ValueError: if the signature or the bundle is invalid.
+        """
+        with open(path, "rb") as fp:
+            doc = fp.read()
+        with open(signature, "rb") as fp:
+            sig = fp.read()
+        with open(key, "rb") as fp:
+            key_der = fp.read()
+        try:
+            _verify_signature(doc, sig, key_der)
+        except ValueError:
+            raise ValueError(
+                f"Invalid signature of key bundle {path}.") from None
+        bundle = KeyBundle(**json.loads(doc))
+        bundle.validate()
+        return bundle
+
+    @property
+    def expiry(self) -> datetime:
+        return datetime.fromisoformat(self.not_after.replace("Z", "+00:00"))
+
+    def validate(self) -> None:
+        """Validates this object's attributes."""
+        if self.schema_version != KEY_BUNDLE_SCHEMA_VERSION:
+            raise ValueError(
+                f"Key bundle version ({self.schema_version}) must be "
+                f"{KEY_BUNDLE_SCHEMA_VERSION}.")
+        try:
+            expiry = self.expiry
+        except ValueError:
+            raise ValueError(
+                f"Key bundle expiry ({self.not_after}) must be an RFC 3339 "
+                "timestamp.") from None
+        if expiry.tzinfo is None:
+            raise ValueError(
+                f"Key bundle expiry ({self.not_after}) must have a timezone.")
+        try:
+            bytes.fromhex(self.rma_wrap_key)
+        except ValueError:
+            raise ValueError("Key bundle RMA wrap key must be hex.") from None
+        for name in _REQUIRED_CAS:
+            if name not in self.cas:
+                raise ValueError(f"Key bundle must list a {name} CA.")
+
+    def check(self, sku: str, now: datetime = None) -> None:
+        """Checks the bundle may be used for `sku` at time `now`."""
+        if now is None:
+            now = datetime.now(timezone.utc)
+        if sku not in self.skus:
+            raise ValueError(
+                f"Key bundle {self.name} is not for SKU {sku} (SKUs: "
+                f"{', '.join(self.skus)}).")
+        if now > self.expiry:
+            raise ValueError(
+                f"Key bundle {self.name} expired on {self.not_after}.")
@@ -4,6 +4,8 @@
 """Earlgrey benchtop provisioning orchestrator."""
 
 import argparse
+import contextlib
+import getpass
 import logging
 import os
 import shlex
@@ -12,10 +14,30 @@ import sys
 
 import hjson
 
+import ft_result
+from ab_qualification import AbQualification
+from db import (DB, DBConfig, DeviceRecord, QuotaUsageRecord,
+                StepDurationRecord, TokenUsageRecord)
 from device_id import DeviceId, DeviceIdentificationNumber
-from ot_dut import OtDut
+from dut_watch import DutWatcher, PauseControl, ReloadControl, StopControl
+from error_budget import ErrorBudget, failure_class
+from ot_dut import DEVICE_LOG_LEVELS, OtDut, presence_probe
+from probe_card import ProbeCardConfig, ResetDomainLock
+from quota import QuotaConfig, QuotaEnforcer, QuotaExceeded
+from registration import HttpRegistry, RegistrationConfig, RegistrationQueue
+from report_timestamp import (HttpTimestampAuthority, TimestampError,
+                              timestamp_report)
+from retention import RetentionConfig, apply_retention
+from secrets_broker import SecretsBroker
 from sku_config import SkuConfig
+from step_state import report_interrupted_devices
+from step_timeouts import (StepTimeoutConfig, record_step_duration,
+                           step_timeouts)
+from tenant_config import TenantConfig
+from token_usage import lot_name, record_token_usage
 from util import confirm, parse_hexstring_to_int
+from worker_pool import JobStatus, WorkerPool
+from yield_monitor import ALARM_ACTIONS, YieldAlarmConfig, YieldMonitor
 
 
 def get_user_confirmation(
@@ -35,27 +57,171 @@ def get_user_confirmation(
 SKU:      {sku_config.name}
 LC State: {sku_config.target_lc_state}
 
+[KEY BUNDLE]
+name:      {sku_config.keys.name}
+not after: {sku_config.keys.not_after}
+
 [DICE CA]
-certificate: {sku_config.dice_ca.certificate}
 key:         {sku_config.dice_ca.key}
 key type:    {sku_config.dice_ca.key_type}
 key ID:      {sku_config.dice_ca.key_id}
 
 [EXTENSION CA]
-certificate: {sku_config.ext_ca.certificate}
 key:         {sku_config.ext_ca.key}
 key type:    {sku_config.ext_ca.key_type}
 key ID:      {sku_config.ext_ca.key_id}
 
 [OTHER]
 fpga:          {args.fpga}
+tenant config: {args.tenant_config}
+probe card:    {args.probe_card_config}
+site:          {args.site}
 > commit hash: {commit_hash}
 """)
     if not args.non_interactive:
         confirm()
 
 
-def main(args_in):
+def record_ft_result(db: DB,
+                     dut: OtDut,
+                     sku_config: SkuConfig,
+                     registration: RegistrationQueue = None) -> bool:
+    """Records the FT results of a device, unless they were recorded already.
+
+    Runs whose results match an existing record (same device fingerprint) are
+    marked as duplicates instead of being recorded again. Recorded devices are
+    queued for registration with the fleet registry, if any.
+
+    Returns:
+        False if the run is a duplicate.
+    """
+    ft_data = ft_result.parse_provisioning_data(
+        f"{dut.log_dir}/ft_out.log.txt")
+    record = ft_result.device_record(ft_data, sku_config.name, dut.log_dir)
+    duplicate = record.upsert_unless_duplicate(db)
+    if duplicate is None:
+        logging.info(f"Recorded FT results of device {record.device_id}.")
+        if registration is not None:
+            registration.enqueue(record)
+        return True
+    logging.warning(
+        f"FT results of device {record.device_id} already recorded from "
+        f"{duplicate.provisioning_log}; marking run as duplicate.")
+    with open(f"{dut.log_dir}/DUPLICATE", "w") as fp:
+        fp.write(f"fingerprint: {record.fingerprint}\n")
+        fp.write(f"original: {duplicate.provisioning_log}\n")
+    return False
+
+
+def timestamp_ft_result(tsa: HttpTimestampAuthority, dut: OtDut) -> bool:
+    """Saves the FT report of a device and timestamps it with `tsa`.
+
+    A device whose report could not be timestamped stays provisioned: the
+    failure is logged, and the report is saved without its timestamp.
+
+    Returns:
+        False if the report could not be timestamped.
+    """
+    ft_data = ft_result.parse_provisioning_data(
+        f"{dut.log_dir}/ft_out.log.txt")
+    try:
+        path, gen_time = timestamp_report(tsa, ft_data, dut.log_dir)
+    except TimestampError as e:
+        logging.error(f"Failed to timestamp the FT report: {e}")
+        return False
+    logging.info(f"Timestamped FT report {path} at {gen_time}.")
+    return True
+
+
+def load_secrets(broker: SecretsBroker, sku_config: SkuConfig,
+                 args: argparse.Namespace) -> None:
+    """Loads the LC tokens and the private keys of the SKU into `broker`."""
+    broker.add_token("test_unlock_token", args.test_unlock_token)
+    broker.add_token("test_exit_token", args.test_exit_token)
+    for ca in [sku_config.dice_ca, sku_config.ext_ca]:
+        if ca.key_type == "Raw":
+            broker.load_key_file(f"{ca.name}_key", ca.key)
+
+
+def reserve_quota(quotas: QuotaEnforcer, device_id: str, sku_config: SkuConfig,
+                  lot: str, supervisor: str, tenant: TenantConfig) -> None:
+    """Counts a device against the quotas, before it is started.
+
+    Exits if a quota would be exceeded, unless `supervisor` authorizes an
+    override with their passphrase.
+    """
+    violations = quotas.violations(sku_config, lot)
+    override = None
+    if violations:
+        for violation in violations:
+            logging.error(f"Quota exceeded: {violation}")
+        if supervisor is None:
+            sys.exit("Quotas exceeded; an override requires supervisor "
+                     "authorization (--quota-override-supervisor).")
+        passphrase = getpass.getpass(
+            f"Passphrase of supervisor {supervisor}: ")
+        override = quotas.authorize_override(supervisor, passphrase)
+        if tenant is not None:
+            tenant.audit("quota_override",
+                         sku=sku_config.name,
+                         device_id=device_id,
+                         supervisor=supervisor,
+                         violations=violations)
+    try:
+        quotas.reserve(device_id, sku_config, lot, override)
+    except QuotaExceeded as e:
+        # Another station used up the quota since the check.
+        sys.exit(f"Quotas exceeded: {e}")
+
+
+def load_sku_config(path: str, tenant: TenantConfig = None) -> SkuConfig:
+    """Loads a SKU configuration and its key bundle, for `tenant` if any."""
+    with open(path, "r") as fp:
+        sku_config = SkuConfig(**hjson.load(fp))
+    if tenant is not None:
+        tenant.check_sku(sku_config)
+    return sku_config
+
+
+def load_tenant_config(path: str) -> TenantConfig:
+    with open(path, "r") as fp:
+        return TenantConfig(**hjson.load(fp))
+
+
+def load_quota_config(path: str) -> QuotaConfig:
+    with open(path, "r") as fp:
+        return QuotaConfig(**hjson.load(fp))
+
+
+def run_flows(dut: OtDut, non_interactive: bool) -> bool:
+    """Runs the CP and FT flows on `dut`.
+
+    Non-interactive runs drive the DUT transports from a worker, so that the
+    flows can be cancelled (e.g. on a KeyboardInterrupt) and only the
+    coordinator logs to the console. Interactive runs need the console for
+    confirmations, and run the flows inline.
+
+    Returns:
+        True if both flows completed successfully.
+    """
+    if not non_interactive:
+        return dut.run_flows()
+    with WorkerPool(num_workers=1) as pool:
+        pool.submit(str(dut.device_id), dut.run_flows)
+        result = next(pool.results())
+    return result.status == JobStatus.DONE and result.result
+
+
+def main(args_in, stop: StopControl = None, reload: ReloadControl = None):
+    """Runs the orchestrator with the command line arguments `args_in`.
+
+    Args:
+        stop: Stop requests of the service hosting the orchestrator, e.g.
+          from the stop callback of a Windows service; ends watch mode after
+          the device being provisioned.
+        reload: Configuration reload requests of the service hosting the
+          orchestrator, applied in watch mode before the next device.
+    """
     # Setup logging.
     logging.basicConfig(
         level=logging.DEBUG,
@@ -91,6 +257,13 @@ def main(args_in):
         type=parse_hexstring_to_int,
         help="Raw test exit token to inject into OTP SECRET0 partition.",
     )
+    parser.add_argument(
+        "--token-generation",
+        default="",
+        help="""Label of the generation of the test unlock / exit tokens,
+        recorded with the tokens consumed per lot, and reported by FT on a
+        test exit token mismatch.""",
+    )
     parser.add_argument(
         "--fpga",
         choices=["hyper310", "cw340"],
@@ -109,53 +282,581 @@ def main(args_in):
     )
     parser.add_argument(
         "--log-dir",
-        default="logs",
-        help="Root directory to store log files under.",
+        help="""Root directory to store log files under (default: logs, or the
+        tenant log directory).""",
+    )
+    parser.add_argument(
+        "--tenant-config",
+        type=str,
+        help="""Tenant HJSON configuration file. Restricts the SKU and keys
+        used, and namespaces all outputs under the tenant output directory.""",
+    )
+    parser.add_argument(
+        "--db-path",
+        type=str,
+        help="""SQLite database to record provisioning results into (default:
+        none, or the tenant database).""",
+    )
+    parser.add_argument(
+        "--quota-config",
+        type=str,
+        help="""Quota HJSON configuration file. Caps the devices provisioned
+        per CA key, per lot and per shift, counted in the provisioning
+        database.""",
+    )
+    parser.add_argument(
+        "--quota-override-supervisor",
+        type=str,
+        help="""Supervisor authorizing to exceed the quotas; prompts for the
+        passphrase of the supervisor if a quota is exceeded.""",
+    )
+    parser.add_argument(
+        "--registry-url",
+        type=str,
+        help="""URL of the fleet registry batch upload endpoint. Recorded
+        devices are queued in the database, and uploaded in batches.""",
+    )
+    parser.add_argument(
+        "--registry-batch-size",
+        type=int,
+        default=16,
+        help="Number of devices uploaded to the fleet registry per batch.",
+    )
+    parser.add_argument(
+        "--registry-max-retries",
+        type=int,
+        default=3,
+        help="Number of times a failed registry batch upload is retried.",
+    )
+    parser.add_argument(
+        "--tsa-url",
+        type=str,
+        help="""URL of an RFC 3161 timestamping authority. The FT report of
+        each passing run is saved to the device log directory, and
+        timestamped.""",
+    )
+    parser.add_argument(
+        "--yield-window",
+        type=int,
+        default=20,
+        help="Number of most recent devices the failure rate is tracked on.",
+    )
+    parser.add_argument(
+        "--yield-min-devices",
+        type=int,
+        default=5,
+        help="Minimum number of devices tracked before the yield alarm fires.",
+    )
+    parser.add_argument(
+        "--yield-alarm-threshold",
+        type=float,
+        default=0.4,
+        help="Failure rate (0.0 - 1.0) at which the yield alarm fires.",
+    )
+    parser.add_argument(
+        "--yield-alarm-action",
+        action="append",
+        choices=ALARM_ACTIONS,
+        help="Yield alarm action; may be repeated (default: beep).",
+    )
+    parser.add_argument(
+        "--yield-webhook-url",
+        type=str,
+        help="URL to POST yield alarm notifications to.",
+    )
+    parser.add_argument(
+        "--yield-email-to",
+        type=str,
+        help="E-mail address to send yield alarm notifications to.",
+    )
+    parser.add_argument(
+        "--yield-smtp-host",
+        default="localhost",
+        help="SMTP server used to send yield alarm e-mails.",
+    )
+    parser.add_argument(
+        "--error-budget",
+        type=int,
+        help="""Number of consecutive devices failing with the same error
+        class which halt the line until an operator acknowledges the halt
+        (default: never halt).""",
+    )
+    parser.add_argument(
+        "--acknowledge-halt",
+        action="store_true",
+        default=False,
+        help="""Acknowledge a halt of the line by the error budget, after
+        fixing its cause.""",
+    )
+    parser.add_argument(
+        "--step-timeout-multiplier",
+        type=float,
+        default=3.0,
+        help="""Timeout of a provisioning step, as a multiple of the P99
+        duration of its recent passing runs recorded in the database.""",
+    )
+    parser.add_argument(
+        "--step-timeout-min-samples",
+        type=int,
+        default=20,
+        help="Passing runs of a step recorded before its timeout is enforced.",
+    )
+    parser.add_argument(
+        "--step-timeout-history",
+        type=int,
+        default=200,
+        help="Number of most recent passing runs the P99 is computed on.",
+    )
+    parser.add_argument(
+        "--step-timeout-min",
+        type=float,
+        default=60.0,
+        help="Lower bound of the step timeouts, in seconds.",
+    )
+    parser.add_argument(
+        "--no-step-timeouts",
+        action="store_true",
+        default=False,
+        help="Run the provisioning steps without timeouts.",
+    )
+    parser.add_argument(
+        "--device-log-level",
+        choices=DEVICE_LOG_LEVELS,
+        default="verbose",
+        help="""Verbosity of the personalization firmware console logs, e.g.
+        errors only on production lines.""",
+    )
+    parser.add_argument(
+        "--telemetry-config",
+        type=str,
+        help="""JSON list of the voltage and temperature sensors of the
+        fixture to sample around the OTP writes and LC transitions of FT.""",
+    )
+    parser.add_argument(
+        "--probe-card-config",
+        type=str,
+        help="""Probe card HJSON configuration file. Requires --site; waits
+        for the other sites sharing its reset line to finish.""",
+    )
+    parser.add_argument(
+        "--site",
+        type=str,
+        help="Probe card site of the device to provision.",
+    )
+    parser.add_argument(
+        "--watch",
+        action="store_true",
+        default=False,
+        help="""Provision each device inserted in the socket, until
+        interrupted. Requires --non-interactive.""",
+    )
+    parser.add_argument(
+        "--service",
+        action="store_true",
+        default=False,
+        help="""Run as the service of a service manager: systemd, or on Windows
+        a service wrapper such as NSSM or WinSW. Implies --watch and
+        --non-interactive; SIGTERM, or CTRL_BREAK on Windows, stops the
+        station once the device being provisioned finishes its flows, and
+        SIGHUP reloads its configuration before the next device.""",
+    )
+    parser.add_argument(
+        "--watch-poll-interval",
+        type=float,
+        default=1.0,
+        help="Seconds between two probes of the socket in watch mode.",
+    )
+    parser.add_argument(
+        "--watch-debounce",
+        type=int,
+        default=3,
+        help="""Consecutive probes a device insertion or removal must be seen
+        on in watch mode.""",
+    )
+    parser.add_argument(
+        "--pause-file",
+        type=str,
+        help="""File pausing the station in watch mode while it exists: the
+        device in flight finishes, and no new device is started until the file
+        is deleted (default: PAUSE in the log directory).""",
+    )
+    parser.add_argument(
+        "--reload-file",
+        type=str,
+        help="""File requesting a reload of the SKU, key bundle, tenant and
+        quota configurations in watch mode when created: the device in flight
+        finishes with the current configuration, and the file is deleted once
+        the configuration is reloaded (default: RELOAD in the log
+        directory).""",
+    )
+    parser.add_argument(
+        "--retention-days",
+        type=float,
+        help="""Days the log directory of a device is kept after its flows
+        completed (default: forever). Applied before each device.""",
+    )
+    parser.add_argument(
+        "--retention-archive-dir",
+        type=str,
+        help="""Directory to archive the expired log directories to, before
+        deleting them (default: delete without an archive).""",
+    )
+    parser.add_argument(
+        "--retention-delete-failures",
+        action="store_true",
+        default=False,
+        help="Also delete the expired log directories of failed runs.",
+    )
+    parser.add_argument(
+        "--ab-firmware-dir",
+        type=str,
+        help="""A/B qualification: directory of a candidate FT firmware bundle,
+        with the file names of the default bundle. Alternate devices are
+        provisioned with it; compare the two bundles with
+        ab_qualification.py.""",
     )
     args = parser.parse_args(args_in)
+    if args.service:
+        args.watch = True
+        args.non_interactive = True
+    if args.watch:
+        if not args.non_interactive:
+            parser.error("--watch requires --non-interactive.")
+        if args.fpga or args.probe_card_config:
+            parser.error("--watch is only supported on a silicon socket.")
 
     # All relative paths are relative to the runfiles directory.
     if args.runfiles_dir:
         os.chdir(args.runfiles_dir)
 
-    # Load and validate a SKU configuration file.
-    sku_config_args = {}
-    with open(args.sku_config, "r") as fp:
-        sku_config_args = hjson.load(fp)
-    sku_config = SkuConfig(**sku_config_args)
+    # Load a tenant configuration file, and a SKU configuration file, checking
+    # the SKU and outputs belong to the tenant.
+    tenant = None
+    if args.tenant_config:
+        tenant = load_tenant_config(args.tenant_config)
+        tenant.claim_output_dir()
+    sku_config = load_sku_config(args.sku_config, tenant)
+    if tenant is not None:
+        if args.log_dir is None:
+            args.log_dir = tenant.log_dir
+        if args.db_path is None:
+            args.db_path = tenant.db_path
+        tenant.check_output_path(args.log_dir)
+        tenant.check_output_path(args.db_path)
+        if args.retention_archive_dir:
+            tenant.check_output_path(args.retention_archive_dir)
+    elif args.log_dir is None:
+        args.log_dir = "logs"
 
-    # Create a (unique) device identification number and device ID.
-    # TODO: update this by extracting data from the device during CP.
-    din = DeviceIdentificationNumber(
-        year=0,
-        week=0,
-        lot=0,
-        wafer=0,
-        wafer_x_coord=0,
-        wafer_y_coord=0,
+    # Load the probe card site of the device, sharing a reset line with the
+    # other sites of its reset domain.
+    site = None
+    if (args.probe_card_config is None) != (args.site is None):
+        parser.error("--probe-card-config and --site must be set together.")
+    if args.probe_card_config:
+        if args.fpga:
+            parser.error("--probe-card-config is not supported on FPGA.")
+        with open(args.probe_card_config, "r") as fp:
+            site = ProbeCardConfig(**hjson.load(fp)).site(args.site)
+
+    # Setup yield tracking, shared by all runs logging to the same directory.
+    os.makedirs(args.log_dir, exist_ok=True)
+    yield_config = YieldAlarmConfig(
+        window=args.yield_window,
+        min_devices=args.yield_min_devices,
+        threshold=args.yield_alarm_threshold,
+        actions=args.yield_alarm_action or ["beep"],
+        webhook_url=args.yield_webhook_url,
+        email_to=args.yield_email_to,
+        smtp_host=args.yield_smtp_host,
     )
-    device_id = DeviceId(sku_config, din)
+    yield_monitor = YieldMonitor(
+        yield_config,
+        state_file=f"{args.log_dir}/yield_{sku_config.name}.json",
+        sku=sku_config.name)
+
+    # Halt the line on repeated identical failures, e.g. a fixture fault,
+    # until the operator acknowledges the halt.
+    error_budget = None
+    if args.error_budget is not None:
+        error_budget = ErrorBudget(
+            args.error_budget,
+            state_file=f"{args.log_dir}/error_budget_{sku_config.name}.json",
+            sku=sku_config.name)
+        if error_budget.halted and not args.acknowledge_halt:
+            if args.non_interactive:
+                sys.exit(f"{error_budget.reason} Fix the fault, then "
+                         "acknowledge the halt with --acknowledge-halt.")
+            logging.error(error_budget.reason)
+            print("Fix the fault, then confirm to acknowledge the halt.")
+            confirm()
+        if error_budget.halted:
+            if tenant is not None:
+                tenant.audit("halt_acknowledged",
+                             sku=sku_config.name,
+                             reason=error_budget.reason)
+            error_budget.acknowledge()
+    elif args.acknowledge_halt:
+        parser.error("--acknowledge-halt requires --error-budget.")
+
+    # Tell the operator where the devices interrupted by an earlier crash or
+    # power loss stopped.
+    report_interrupted_devices(args.log_dir)
+
+    # Expire the log directories of the devices provisioned earlier, so the
+    # station disk does not fill up mid-shift.
+    retention = None
+    if args.retention_days is not None:
+        retention = RetentionConfig(
+            keep_days=args.retention_days,
+            archive_dir=args.retention_archive_dir,
+            keep_failures=not args.retention_delete_failures)
+    elif args.retention_archive_dir or args.retention_delete_failures:
+        parser.error("--retention-* options require --retention-days.")
+
+    # Setup local DB connection.
+    # TODO: Setup remote DB connections.
+    db = None
+    if args.db_path:
+        db = DB(DBConfig(db_path=args.db_path))
+        DeviceRecord.create_table(db)
+        TokenUsageRecord.create_table(db)
+        StepDurationRecord.create_table(db)
+        QuotaUsageRecord.create_table(db)
+
+    # Load the volume quotas, counted in the local DB.
+    quotas = None
+    if args.quota_config:
+        if db is None:
+            parser.error("--quota-config requires a provisioning database.")
+        quotas = QuotaEnforcer(db, load_quota_config(args.quota_config))
 
-    # TODO: Setup remote and/or local DV connections.
-    # TODO: Check if the device ID is present in the DB.
+    # Setup the fleet registration queue, stored in the local DB.
+    registration = None
+    if args.registry_url:
+        if db is None:
+            parser.error("--registry-url requires a provisioning database.")
+        registration = RegistrationQueue(
+            db, HttpRegistry(args.registry_url),
+            RegistrationConfig(batch_size=args.registry_batch_size,
+                               max_retries=args.registry_max_retries))
+
+    # Setup the timestamping of the FT reports.
+    tsa = None
+    if args.tsa_url:
+        tsa = HttpTimestampAuthority(args.tsa_url)
+
+    # Learn the step timeouts from the run history of the SKU.
+    timeouts = {}
+    if db is not None and not args.no_step_timeouts:
+        timeouts = step_timeouts(
+            db, sku_config.name, ["cp", "ft"],
+            StepTimeoutConfig(multiplier=args.step_timeout_multiplier,
+                              min_samples=args.step_timeout_min_samples,
+                              history=args.step_timeout_history,
+                              min_timeout=args.step_timeout_min))
+        for step, timeout in timeouts.items():
+            logging.info(f"Timeout of the {step} step: {timeout:.0f}s")
 
     # Generate commit hash of current provisioning run.
     commit_hash = subprocess.run(shlex.split("git rev-parse HEAD"),
                                  capture_output=True,
                                  text=True).stdout.strip()
 
-    # Run all provisioning flows.
-    get_user_confirmation(sku_config, device_id, commit_hash, args)
-    dut = OtDut(logs_root_dir=args.log_dir,
-                sku_config=sku_config,
-                device_id=device_id,
-                test_unlock_token=args.test_unlock_token,
-                test_exit_token=args.test_exit_token,
-                fpga=args.fpga,
-                require_confirmation=not args.non_interactive)
-    dut.run_cp()
-    dut.run_ft()
-    # TODO: Extract provisioning data from logs and commit to DB.
+    # Alternate the FT firmware bundles of an A/B qualification.
+    ab = None
+    if args.ab_firmware_dir:
+        if not os.path.isdir(args.ab_firmware_dir):
+            parser.error(f"{args.ab_firmware_dir} is not a directory.")
+        ab = AbQualification(args.log_dir, args.ab_firmware_dir)
+
+    def reload_config() -> None:
+        """Reloads the SKU, key bundle, tenant and quota configurations.
+
+        The current configuration is kept if the new one is invalid, or
+        changes the SKU or the tenant, which the state of the station (yield,
+        error budget, step timeouts, outputs) is tied to.
+        """
+        nonlocal sku_config, tenant
+        try:
+            new_tenant = None
+            if args.tenant_config:
+                new_tenant = load_tenant_config(args.tenant_config)
+                if (new_tenant.name != tenant.name
+                        or new_tenant.output_dir != tenant.output_dir):
+                    raise ValueError("The tenant name and output directory "
+                                     "cannot change.")
+            new_sku_config = load_sku_config(args.sku_config, new_tenant)
+            if new_sku_config.name != sku_config.name:
+                raise ValueError(
+                    f"The SKU cannot change from {sku_config.name} to "
+                    f"{new_sku_config.name}.")
+            new_quota_config = None
+            if quotas is not None:
+                new_quota_config = load_quota_config(args.quota_config)
+        except Exception as e:
+            logging.error("Configuration reload failed, keeping the current "
+                          f"configuration: {e}")
+            return
+        sku_config, tenant = new_sku_config, new_tenant
+        if quotas is not None:
+            quotas.config = new_quota_config
+        logging.info(
+            f"Configuration reloaded: SKU {sku_config.name}, key bundle "
+            f"{sku_config.keys.name} (not after {sku_config.keys.not_after}).")
+        if tenant is not None:
+            tenant.audit("config_reloaded",
+                         sku=sku_config.name,
+                         key_bundle=sku_config.keys.name)
+
+    def provision_device() -> None:
+        """Runs all provisioning flows on the device in the socket."""
+        if retention is not None:
+            apply_retention(args.log_dir, retention)
+
+        # Create a (unique) device identification number and device ID.
+        # TODO: update this by extracting data from the device during CP.
+        din = DeviceIdentificationNumber(
+            year=0,
+            week=0,
+            lot=0,
+            wafer=0,
+            wafer_x_coord=0 if site is None else site.wafer_x_coord,
+            wafer_y_coord=0 if site is None else site.wafer_y_coord,
+        )
+        device_id = DeviceId(sku_config, din)
+
+        # Run all provisioning flows.
+        get_user_confirmation(sku_config, device_id, commit_hash, args)
+        if tenant is not None:
+            tenant.audit("provisioning_start",
+                         sku=sku_config.name,
+                         device_id=str(device_id),
+                         commit_hash=commit_hash)
+        if quotas is not None:
+            reserve_quota(quotas, str(device_id), sku_config, lot_name(din),
+                          args.quota_override_supervisor, tenant)
+        broker = SecretsBroker()
+        load_secrets(broker, sku_config, args)
+        variant = None if ab is None else ab.next_variant()
+        if variant is not None:
+            logging.info(f"Provisioning {device_id} with variant {variant} "
+                         "of the FT firmware.")
+        dut = OtDut(logs_root_dir=args.log_dir,
+                    sku_config=sku_config,
+                    device_id=device_id,
+                    secrets=broker.handle(str(device_id)),
+                    fpga=args.fpga,
+                    require_confirmation=not args.non_interactive,
+                    step_timeouts=timeouts,
+                    device_log_level=args.device_log_level,
+                    telemetry_config=args.telemetry_config,
+                    token_generation=args.token_generation,
+                    interface="teacup" if site is None else site.interface,
+                    jtag_chain=None if site is None else site.jtag_chain,
+                    ft_firmware_dir=None
+                    if ab is None else ab.firmware_dir(variant))
+        passed = False
+        recorded = None
+        timestamped = None
+        # Resetting the device resets the other sites of its reset domain: wait
+        # for them to finish before starting the flows.
+        site_lock = (contextlib.nullcontext()
+                     if site is None else ResetDomainLock(args.log_dir, site))
+        try:
+            with site_lock:
+                passed = run_flows(dut, args.non_interactive)
+            if passed and tsa is not None:
+                timestamped = timestamp_ft_result(tsa, dut)
+            if passed and db is not None:
+                recorded = record_ft_result(db, dut, sku_config, registration)
+        finally:
+            # Zeroize the secrets as soon as the flows are done.
+            broker.shutdown()
+            # Also record runs aborted by the operator after a failure.
+            yield_monitor.record(str(device_id), passed)
+            if ab is not None:
+                ab.record(str(device_id), variant, passed, dut.step_results,
+                          dut.log_dir)
+            if error_budget is not None:
+                error_class = None if passed else failure_class(
+                    dut.log_dir, dut.step_results, dut.timed_out_steps)
+                if error_budget.record(str(device_id), error_class):
+                    if tenant is not None:
+                        tenant.audit("line_halted",
+                                     sku=sku_config.name,
+                                     reason=error_budget.reason)
+            # The tokens are consumed as soon as CP injects them, whatever the
+            # outcome of the run.
+            if db is not None:
+                record_token_usage(db,
+                                   device_id=str(device_id),
+                                   sku=sku_config.name,
+                                   din=din,
+                                   token_generation=args.token_generation,
+                                   test_unlock_token=args.test_unlock_token,
+                                   test_exit_token=args.test_exit_token,
+                                   passed=passed)
+                for step, (duration, step_passed) in dut.step_results.items():
+                    record_step_duration(db,
+                                         device_id=str(device_id),
+                                         sku=sku_config.name,
+                                         step=step,
+                                         duration=duration,
+                                         passed=step_passed)
+            # Upload the full batches queued so far; failed uploads are retried
+            # by the next run.
+            if registration is not None:
+                registration.flush()
+            if tenant is not None:
+                tenant.audit("provisioning_end",
+                             sku=sku_config.name,
+                             device_id=str(device_id),
+                             passed=passed,
+                             duplicate=recorded is False,
+                             timestamped=timestamped,
+                             timed_out_steps=dut.timed_out_steps,
+                             log_dir=dut.log_dir)
+
+    if not args.watch:
+        provision_device()
+        return
+    if stop is None:
+        stop = StopControl()
+        if args.service:
+            stop.install_signal_handlers()
+    if reload is None:
+        reload = ReloadControl(args.reload_file or f"{args.log_dir}/RELOAD")
+        if args.service:
+            reload.install_signal_handlers()
+    watcher = DutWatcher(presence_probe("teacup"),
+                         poll_interval=args.watch_poll_interval,
+                         debounce=args.watch_debounce,
+                         stopped=stop.stopped)
+    pause = PauseControl(args.pause_file or f"{args.log_dir}/PAUSE",
+                         poll_interval=args.watch_poll_interval,
+                         stopped=stop.stopped)
+    while not stop.stopped():
+        if not watcher.wait_for_insertion():
+            continue
+        if pause.wait_while_paused():
+            # Resuming a station halted by the error budget acknowledges the
+            # halt.
+            if error_budget is not None and error_budget.halted:
+                if tenant is not None:
+                    tenant.audit("halt_acknowledged",
+                                 sku=sku_config.name,
+                                 reason=error_budget.reason)
+                error_budget.acknowledge()
+            # The socket may have been emptied or reloaded during the pause.
+            continue
+        if reload.take():
+            reload_config()
+        provision_device()
+        if error_budget is not None and error_budget.halted:
+            pause.pause(error_budget.reason)
+        watcher.wait_for_removal()
+    logging.info("Station stopped.")
 
 
 if __name__ == "__main__":
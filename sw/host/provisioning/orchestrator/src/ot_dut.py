@@ -2,15 +2,23 @@
 # Licensed under the Apache License, Version 2.0, see LICENSE for details.
 # SPDX-License-Identifier: Apache-2.0
 
+import contextlib
 import json
 import logging
 import os
+import shlex
+import subprocess
 import tempfile
-from dataclasses import dataclass
+import time
+from dataclasses import dataclass, field
+from typing import Callable, Dict
 
 from device_id import DeviceId
+from secrets_broker import SecretHandle
 from sku_config import SkuConfig
+from step_state import FT_STEP_STATE_FILE, StepJournal
 from util import confirm, format_hex, run
+from worker_pool import JobContext
 
 # FPGA bitstream.
 _FPGA_UNIVERSAL_SPLICE_BITSTREAM = "hw/bitstream/universal/splice.bit"
@@ -24,6 +32,9 @@ _BASE_PROVISIONING_FLAGS = """
     --openocd-adapter-config={openocd_cfg} \
 """
 _ZERO_256BIT_HEXSTR = "0x" + "_".join(["00000000"] * 8)
+# Times an interrupted FT bootstrap transfer resumes from its last sent
+# page before failing, see sw/host/opentitanlib/src/bootstrap/resume.rs.
+_BOOTSTRAP_RESUME_RETRIES = 3
 
 # yapf: disable
 # CP & FT Device Firmware
@@ -35,8 +46,41 @@ _FT_FW_BUNDLE_BIN      = "{base_dir}/ft_fw_bundle_{sku}_{target}.img"  # noqa: E
 # CP & FT Host Binaries
 _CP_HOST_BIN = "sw/host/provisioning/cp/cp"
 _FT_HOST_BIN = "sw/host/provisioning/ft/ft_{sku}"
+_OPENTITANTOOL_BIN = "sw/host/opentitantool/opentitantool"
 # yapf: enable
 
+# Verbosities of the personalization firmware console logs, see
+# sw/host/provisioning/ft_lib/src/log_level.rs.
+DEVICE_LOG_LEVELS = ["verbose", "warnings", "errors"]
+
+
+def presence_probe(interface: str,
+                   timeout: float = 30.0) -> Callable[[], bool]:
+    """Returns a probe of the silicon DUT on `interface`, see dut_watch.py.
+
+    The probe reads the LC state of the device over its LC TAP, which OpenOCD
+    only connects to once it finds the IDCODE of the device on the scan chain.
+    A probe hung for `timeout` seconds counts as no device.
+    """
+    cmd = shlex.split(f"""{_OPENTITANTOOL_BIN} \
+        --rcfile= \
+        --logging=error \
+        --interface={interface} \
+        --openocd-adapter-config={_OPENOCD_ADAPTER_CONFIG} \
+        --disable-dft-on-reset \
+        lc read \
+        --openocd={_OPENOCD_BIN}
+        """)
+
+    def probe() -> bool:
+        try:
+            res = subprocess.run(cmd, capture_output=True, timeout=timeout)
+        except subprocess.TimeoutExpired:
+            return False
+        return res.returncode == 0
+
+    return probe
+
 
 @dataclass
 class OtDut():
@@ -44,14 +88,40 @@ class OtDut():
     logs_root_dir: str
     sku_config: SkuConfig
     device_id: DeviceId
-    test_unlock_token: str
-    test_exit_token: str
+    # Handle to the LC tokens and keys of the device, see secrets_broker.py.
+    secrets: SecretHandle
     fpga: str
     require_confirmation: bool = True
+    # Timeouts in seconds of the "cp" and "ft" steps, see step_timeouts.py.
+    step_timeouts: Dict[str, float] = field(default_factory=dict)
+    # Verbosity of the personalization firmware console logs, one of
+    # DEVICE_LOG_LEVELS.
+    device_log_level: str = "verbose"
+    # JSON list of the sensors of the fixture FT samples, see
+    # sw/host/provisioning/ft_lib/src/telemetry.rs.
+    telemetry_config: str = None
+    # Label of the generation of the test unlock / exit tokens, see
+    # token_usage.py.
+    token_generation: str = ""
+    # opentitantool interface of the silicon DUT, e.g. its probe card site.
+    interface: str = "teacup"
+    # JTAG scan chain of the silicon DUT, if shared with other parts, see
+    # probe_card.py.
+    jtag_chain: list = None
+    # Directory of the FT firmware bundle to provision the DUT with instead of
+    # the default one, with the same file names, see ab_qualification.py.
+    ft_firmware_dir: str = None
 
     def __post_init__(self):
         self.log_dir = f"{self.logs_root_dir}/{str(self.device_id)[2:]}"
         self._make_log_dir()
+        self.journal = StepJournal(self.log_dir, str(self.device_id))
+        # Worker pool job the flows currently run in, see `run_flows`.
+        self._job = None
+        # Duration in seconds and outcome of each step run, by step.
+        self.step_results = {}
+        # Steps terminated after exceeding their timeout.
+        self.timed_out_steps = []
 
     def _make_log_dir(self) -> None:
         if self.require_confirmation and os.path.exists(self.log_dir):
@@ -64,14 +134,120 @@ class OtDut():
     def _base_dev_dir(self) -> str:
         return _BASE_DEV_DIR
 
-    def run_cp(self) -> None:
-        """Runs the CP provisioning flow on the target DUT."""
-        logging.info("Running CP provisioning ...")
+    def _logger(self):
+        return logging if self._job is None else self._job
+
+    def _run(self, cmd: str, flow: str):
+        """Runs the command of a provisioning `flow` ("cp" or "ft").
+
+        Returns:
+            The completed command, or None if it exceeded the step timeout.
+        """
+        timeout = self.step_timeouts.get(flow)
+        start = time.monotonic()
+        try:
+            res = run(
+                cmd,
+                f"{self.log_dir}/{flow}_out.log.txt",
+                f"{self.log_dir}/{flow}_err.log.txt",
+                echo=self._job is None,
+                cancel=None if self._job is None else self._job.cancel_event,
+                timeout=timeout)
+        except subprocess.TimeoutExpired:
+            res = None
+        duration = time.monotonic() - start
+        self.step_results[flow] = (duration,
+                                   res is not None and res.returncode == 0)
+        if self._job is not None:
+            self._job.check_cancelled()
+        if res is None:
+            self._flag_timeout(flow, timeout)
+        return res
+
+    def _flag_timeout(self, flow: str, timeout: float) -> None:
+        """Flags the device as anomalous after `flow` exceeded its timeout."""
+        self._logger().error(
+            f"{flow.upper()} exceeded its timeout of {timeout:.0f}s learned "
+            "from the run history; flagging device as anomalous.")
+        self.timed_out_steps.append(flow)
+        with open(f"{self.log_dir}/TIMEOUT", "a") as fp:
+            fp.write(f"{flow}: {timeout:.0f}s\n")
+
+    def _release_flags(self) -> str:
+        """Returns the ft flags checking the release manifest of the SKU."""
+        if self.sku_config.release_manifest is None:
+            return ""
+        return (f"--release-manifest={self.sku_config.release_manifest} "
+                f"--release-manifest-key={self.sku_config.release_manifest_key}")
+
+    def _console_flags(self) -> str:
+        """Returns the ft flags selecting the console of the SKU."""
+        flags = f"--console={self.sku_config.console}"
+        if self.sku_config.console_uart is not None:
+            flags += f" --console-uart={self.sku_config.console_uart}"
+        uart_config = self.sku_config.console_uart_config or {}
+        if "baudrate" in uart_config:
+            flags += f" --console-uart-baudrate={uart_config['baudrate']}"
+        if "parity" in uart_config:
+            flags += f" --console-uart-parity={uart_config['parity']}"
+        if "stop_bits" in uart_config:
+            flags += f" --console-uart-stop-bits={uart_config['stop_bits']}"
+        return flags
+
+    def _jtag_chain_flags(self) -> str:
+        """Returns the cp / ft flags declaring the JTAG chain of the DUT."""
+        if not self.jtag_chain:
+            return ""
+        return " --jtag-chain={}".format(",".join(self.jtag_chain))
+
+    def _entry_lc_state_flags(self) -> str:
+        """Returns the ft flags restricting the LC states devices enter FT in."""
+        if not self.sku_config.entry_lc_states:
+            return ""
+        return "--entry-lc-states={}".format(",".join(
+            self.sku_config.entry_lc_states))
+
+    def _confirm_failure(self) -> None:
+        # Jobs never ask for confirmation, the coordinator handles failures.
+        if self._job is None:
+            confirm()
+
+    def run_flows(self, job: JobContext = None) -> bool:
+        """Runs the CP and FT provisioning flows on the target DUT.
+
+        Args:
+            job: Worker pool job the flows run in, if any. The flows then log
+              through the job, do not echo the output of the provisioning
+              commands to the console, and are terminated when the job is
+              cancelled.
+
+        Returns:
+            True if both flows completed successfully.
+        """
+        if job is not None and self.require_confirmation:
+            raise ValueError(
+                "Flows requiring confirmation cannot run in a worker pool job.")
+        self._job = job
+        try:
+            cp_passed = self.run_cp()
+            ft_passed = self.run_ft()
+        finally:
+            self._job = None
+        # Runs interrupted by a crash or a power loss are left in flight.
+        self.journal.complete(cp_passed and ft_passed)
+        return cp_passed and ft_passed
+
+    def run_cp(self) -> bool:
+        """Runs the CP provisioning flow on the target DUT.
+
+        Returns:
+            True if the CP flow completed successfully.
+        """
+        self._logger().info("Running CP provisioning ...")
 
         # Set cmd args and device ELF.
         host_flags = _BASE_PROVISIONING_FLAGS
         device_elf = _CP_DEVICE_ELF
-        print(f"device_elf: {device_elf}")
         if self.fpga:
             # Set host flags and device binary for FPGA DUT.
             host_flags = host_flags.format(target=self.fpga,
@@ -84,43 +260,55 @@ class OtDut():
                 target=f"fpga_{self.fpga}_rom_with_fake_keys")
         else:
             # Set host flags and device binary for Silicon DUT.
-            host_flags = host_flags.format(target="teacup",
+            host_flags = host_flags.format(target=self.interface,
                                            openocd_bin=_OPENOCD_BIN,
                                            openocd_cfg=_OPENOCD_ADAPTER_CONFIG)
             host_flags += " --disable-dft-on-reset"
+            host_flags += self._jtag_chain_flags()
             device_elf = device_elf.format(base_dir=self._base_dev_dir(),
                                            target="silicon_creator")
 
         # Assemble CP command.
+        test_unlock_token = self.secrets.token("test_unlock_token", "cp")
+        test_exit_token = self.secrets.token("test_exit_token", "cp")
         cmd = f"""{_CP_HOST_BIN} \
         --rcfile= \
         --logging=info \
         {host_flags} \
         --elf={device_elf} \
-        --test-unlock-token="{format_hex(self.test_unlock_token, width=32)}" \
-        --test-exit-token="{format_hex(self.test_exit_token, width=32)}" \
+        --test-unlock-token="{format_hex(test_unlock_token, width=32)}" \
+        --test-exit-token="{format_hex(test_exit_token, width=32)}" \
         --wafer-auth-secret="{_ZERO_256BIT_HEXSTR}" \
         """
 
         # TODO: capture DIN portion of device ID and update device ID.
 
         # Get user confirmation before running command.
-        logging.info(f"Running command: {cmd}")
+        self._logger().info(f"Running command: {cmd}")
         if self.require_confirmation:
             confirm()
 
         # Run provisioning flow and collect logs.
-        res = run(cmd, f"{self.log_dir}/cp_out.log.txt",
-                  f"{self.log_dir}/cp_err.log.txt")
+        self.journal.enter("cp")
+        res = self._run(cmd, "cp")
+        if res is None:
+            self._confirm_failure()
+            return False
         if res.returncode != 0:
-            logging.warning(f"CP failed with exit code: {res.returncode}.")
-            confirm()
+            self._logger().warning(
+                f"CP failed with exit code: {res.returncode}.")
+            self._confirm_failure()
         else:
-            logging.info("CP completed successfully.")
+            self._logger().info("CP completed successfully.")
+        return res.returncode == 0
 
-    def run_ft(self) -> None:
-        """Runs the FT provisioning flow on the target DUT."""
-        logging.info("Running FT provisioning ...")
+    def run_ft(self) -> bool:
+        """Runs the FT provisioning flow on the target DUT.
+
+        Returns:
+            True if the FT flow completed successfully.
+        """
+        self._logger().info("Running FT provisioning ...")
 
         # Set cmd args and device ELF.
         host_bin = _FT_HOST_BIN.format(sku=self.sku_config.name)
@@ -128,6 +316,7 @@ class OtDut():
         individ_elf = _FT_INDIVID_DEVICE_ELF
         perso_bin = _FT_PERSO_DEVICE_BIN
         fw_bundle_bin = _FT_FW_BUNDLE_BIN
+        ft_dir = self.ft_firmware_dir or self._base_dev_dir()
         if self.fpga:
             # Set host flags and device binaries for FPGA DUT.
             # No need to load another bitstream, we will take over where CP
@@ -136,76 +325,147 @@ class OtDut():
                                            openocd_bin=_OPENOCD_BIN,
                                            openocd_cfg=_OPENOCD_ADAPTER_CONFIG)
             individ_elf = individ_elf.format(
-                base_dir=self._base_dev_dir(),
+                base_dir=ft_dir,
                 sku=self.sku_config.name,
                 target=f"fpga_{self.fpga}_rom_with_fake_keys")
             perso_bin = perso_bin.format(
-                base_dir=self._base_dev_dir(),
+                base_dir=ft_dir,
                 sku=self.sku_config.name,
                 target=f"fpga_{self.fpga}_rom_with_fake_keys")
             fw_bundle_bin = fw_bundle_bin.format(
-                base_dir=self._base_dev_dir(),
+                base_dir=ft_dir,
                 sku=self.sku_config.name,
                 target=f"fpga_{self.fpga}_rom_with_fake_keys")
         else:
             # Set host flags and device binaries for Silicon DUT.
-            host_flags = host_flags.format(target="teacup",
+            host_flags = host_flags.format(target=self.interface,
                                            openocd_bin=_OPENOCD_BIN,
                                            openocd_cfg=_OPENOCD_ADAPTER_CONFIG)
             host_flags += " --disable-dft-on-reset"
-            individ_elf = individ_elf.format(base_dir=self._base_dev_dir(),
+            host_flags += self._jtag_chain_flags()
+            individ_elf = individ_elf.format(base_dir=ft_dir,
                                              sku=self.sku_config.name,
                                              target="silicon_creator")
-            perso_bin = perso_bin.format(base_dir=self._base_dev_dir(),
+            perso_bin = perso_bin.format(base_dir=ft_dir,
                                          sku=self.sku_config.name,
                                          target="silicon_creator")
-            fw_bundle_bin = fw_bundle_bin.format(base_dir=self._base_dev_dir(),
+            fw_bundle_bin = fw_bundle_bin.format(base_dir=ft_dir,
                                                  sku=self.sku_config.name,
                                                  target="silicon_creator")
 
-        # Write CA configs to a JSON tmpfile.
-        ca_config_dict = {
-            "dice": self.sku_config.dice_ca.to_dict_entry(),
-            "ext": self.sku_config.ext_ca.to_dict_entry(),
-        }
-
-        with tempfile.NamedTemporaryFile(mode="w+") as ca_config_file:
-            json.dump(ca_config_dict, ca_config_file)
-            ca_config_file.flush()
+        with contextlib.ExitStack() as stack:
+            # Point the raw CA keys of the key bundle at copies held by the
+            # secrets broker. Certs issued offline are injected without them.
+            raw_ca_keys = ""
+            for name, ca in [("dice", self.sku_config.dice_ca),
+                             ("ext", self.sku_config.ext_ca)]:
+                if (ca.key_type == "Raw"
+                        and not self.sku_config.offline_certs_dir):
+                    key = stack.enter_context(
+                        self.secrets.key_file(f"{ca.name}_key", "ft"))
+                    raw_ca_keys += f" --raw-ca-key={name}={key}"
+            alert_cfg_file = stack.enter_context(
+                tempfile.NamedTemporaryFile(mode="w+"))
+            smoke_tests_file = stack.enter_context(
+                tempfile.NamedTemporaryFile(mode="w+"))
+            cert_policy_file = stack.enter_context(
+                tempfile.NamedTemporaryFile(mode="w+"))
+            rma_token_escrow_file = stack.enter_context(
+                tempfile.NamedTemporaryFile(mode="w+"))
+            if self.sku_config.alert_cfg:
+                json.dump(self.sku_config.alert_cfg, alert_cfg_file)
+                alert_cfg_file.flush()
+            if self.sku_config.smoke_tests:
+                json.dump(self.sku_config.smoke_tests, smoke_tests_file)
+                smoke_tests_file.flush()
+            if self.sku_config.cert_policy:
+                json.dump(self.sku_config.cert_policy, cert_policy_file)
+                cert_policy_file.flush()
+            if self.sku_config.rma_token_escrow:
+                json.dump(self.sku_config.rma_token_escrow,
+                          rma_token_escrow_file)
+                rma_token_escrow_file.flush()
 
             # Assemble FT command.
             # TODO: autocompute measurements of expected ROM_EXT + Owner FW payloads
             # TODO: add expected ROM_EXT / Owner security versions
+            test_unlock_token = self.secrets.token("test_unlock_token", "ft")
+            test_exit_token = self.secrets.token("test_exit_token", "ft")
             cmd = f"""{host_bin}
             --rcfile= \
             --logging=info \
             {host_flags} \
-            --elf={individ_elf} \
+            {self._release_flags()} \
+            {self._console_flags()} \
+            {self._entry_lc_state_flags()} \
+            --step-state={self.log_dir}/{FT_STEP_STATE_FILE} \
+            --result-json={self.log_dir}/ft_result.json \
+            --metrics-out={self.log_dir}/ft_metrics.prom \
+            --events-out={self.log_dir}/ft_events.jsonl \
+            --resume-retries={_BOOTSTRAP_RESUME_RETRIES} \
             --bootstrap={perso_bin} \
+            run \
+            --elf={individ_elf} \
             --second-bootstrap={fw_bundle_bin} \
             --device-id="{self.device_id}" \
-            --test-unlock-token="{format_hex(self.test_unlock_token, width=32)}" \
-            --test-exit-token="{format_hex(self.test_exit_token, width=32)}" \
+            --test-unlock-token="{format_hex(test_unlock_token, width=32)}" \
+            --test-exit-token="{format_hex(test_exit_token, width=32)}" \
+            --token-generation="{self.token_generation}" \
             --target-mission-mode-lc-state="{self.sku_config.target_lc_state}" \
             --rom-ext-measurement="{_ZERO_256BIT_HEXSTR}" \
             --owner-manifest-measurement="{_ZERO_256BIT_HEXSTR}" \
             --owner-measurement="{_ZERO_256BIT_HEXSTR}" \
             --rom-ext-security-version="0" \
             --owner-security-version="0" \
-            --ca-config={ca_config_file.name} \
-            --token-encrypt-key-der-file={self.sku_config.token_encrypt_key} \
+            --key-bundle={self.sku_config.key_bundle} \
+            --key-bundle-signature={self.sku_config.key_bundle_signature} \
+            --key-bundle-key={self.sku_config.key_bundle_key} \
+            {raw_ca_keys} \
+            --device-log-level={self.device_log_level} \
+            --seen-values-file={self.logs_root_dir}/seen_device_values.txt \
+            --cert-export-dir={self.log_dir}/certs \
             """
+            if self.telemetry_config is not None:
+                cmd += f" --telemetry={self.telemetry_config}"
+            if self.sku_config.creator_manuf_state is not None:
+                cmd += f" --creator-manuf-state={self.sku_config.creator_manuf_state}"
+            if self.sku_config.alert_cfg:
+                cmd += f" --owner-sw-cfg-alert-cfg={alert_cfg_file.name}"
+            if self.sku_config.smoke_tests:
+                cmd += f" --smoke-tests={smoke_tests_file.name}"
+            if self.sku_config.cert_policy:
+                cmd += f" --cert-policy={cert_policy_file.name}"
+            if self.sku_config.rma_token_escrow:
+                cmd += f" --rma-token-escrow={rma_token_escrow_file.name}"
+            else:
+                cmd += f" --rma-token-out={self.log_dir}"
+            if self.sku_config.offline_certs_dir:
+                cmd += f" --offline-certs-dir={self.sku_config.offline_certs_dir}"
+            if self.sku_config.keymgr_diversification_key:
+                key = self.sku_config.keymgr_diversification_key
+                cmd += f" --keymgr-diversification-key={key}"
+            if self.sku_config.pass_criteria:
+                criteria = shlex.quote(self.sku_config.pass_criteria)
+                cmd += f" --pass-criteria={criteria}"
+            if self.sku_config.rma_escrow_cert is not None:
+                cmd += f" --rma-escrow-cert={self.sku_config.rma_escrow_cert}"
+                cmd += f" --rma-escrow-dir={self.logs_root_dir}/rma_escrow"
 
             # Get user confirmation before running command.
-            logging.info(f"Running command: {cmd}")
+            self._logger().info(f"Running command: {cmd}")
             if self.require_confirmation:
                 confirm()
 
             # Run provisioning flow and collect logs.
-            res = run(cmd, f"{self.log_dir}/ft_out.log.txt",
-                      f"{self.log_dir}/ft_err.log.txt")
+            self.journal.enter("ft")
+            res = self._run(cmd, "ft")
+            if res is None:
+                self._confirm_failure()
+                return False
             if res.returncode != 0:
-                logging.warning(f"FT failed with exit code: {res.returncode}.")
-                confirm()
+                self._logger().warning(
+                    f"FT failed with exit code: {res.returncode}.")
+                self._confirm_failure()
             else:
-                logging.info("FT completed successfully.")
+                self._logger().info("FT completed successfully.")
+            return res.returncode == 0
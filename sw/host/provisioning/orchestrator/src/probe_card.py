@@ -0,0 +1,167 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Topology of multi-site probe cards, and sequencing of their reset domains.
+
+A multi-site probe card contacts several dies at once, each site with its own
+debug interface. Sites may share a reset line: resetting one of them resets
+all the sites of its reset domain, which interrupts any OTP write or LC
+transition in progress on them. The CP and FT flows reset the device
+throughout, so at most one site of each reset domain runs its flows at a time,
+while sites of different reset domains run in parallel.
+
+Each site is driven by its own orchestrator process; the processes sequence
+the sites of a reset domain with an exclusive lock file in the shared log
+directory.
+"""
+
+import fcntl
+import logging
+import os
+import time
+from dataclasses import dataclass
+from typing import Dict, List
+
+# Poll interval of a site waiting for its reset domain.
+_LOCK_POLL_INTERVAL = 0.5
+
+
+@dataclass
+class ProbeSite(object):
+    """Class for storing the configuration of a probe card site."""
+    name: str
+    reset_domain: str  # valid: name of the reset line of the site
+    interface: str = "teacup"  # valid: opentitantool interface of the site
+    wafer_x_coord: int = 0  # valid: >= 0, wafer X offset of the site
+    wafer_y_coord: int = 0  # valid: >= 0, wafer Y offset of the site
+    # JTAG scan chain of the site, if the device shares it with other parts:
+    # "<name>:<irlen>[:<IDCODE>]" TAPs and "opentitan", in chain order, see
+    # `--jtag-chain` in sw/host/opentitanlib/src/io/jtag.rs.
+    jtag_chain: list = None
+
+    def __post_init__(self):
+        if not self.reset_domain:
+            raise ValueError(f"Site {self.name} must set a reset domain.")
+        if (self.jtag_chain is not None and
+                self.jtag_chain.count("opentitan") != 1):
+            raise ValueError(
+                f"JTAG chain of site {self.name} must list the OpenTitan "
+                "TAP once, as \"opentitan\".")
+        if self.wafer_x_coord < 0 or self.wafer_y_coord < 0:
+            raise ValueError(
+                f"Wafer coordinates of site {self.name} must be non-negative.")
+
+
+@dataclass
+class ProbeCardConfig(object):
+    """Class for storing a probe card configuration."""
+    name: str
+    sites: dict  # valid: site name -> ProbeSite attributes
+
+    def __post_init__(self):
+        self.sites = {
+            name: ProbeSite(name=name, **site)
+            for name, site in self.sites.items()
+        }
+        self.validate()
+
+    def validate(self) -> None:
+        """Validates this object's attributes."""
+        if not self.sites:
+            raise ValueError(
+                f"Probe card {self.name} must list at least one site.")
+        interfaces = {}
+        coords = {}
+        for site in self.sites.values():
+            other = interfaces.setdefault(site.interface, site.name)
+            if other != site.name:
+                raise ValueError(
+                    f"Sites {other} and {site.name} share interface "
+                    f"{site.interface}.")
+            coord = (site.wafer_x_coord, site.wafer_y_coord)
+            other = coords.setdefault(coord, site.name)
+            if other != site.name:
+                raise ValueError(
+                    f"Sites {other} and {site.name} share wafer coordinates "
+                    f"{coord}.")
+
+    def site(self, name: str) -> ProbeSite:
+        if name not in self.sites:
+            raise ValueError(
+                f"Probe card {self.name} has no site {name} (sites: "
+                f"{', '.join(sorted(self.sites))}).")
+        return self.sites[name]
+
+    def reset_domains(self) -> Dict[str, List[str]]:
+        """Returns the names of the sites of each reset domain."""
+        domains = {}
+        for site in sorted(self.sites.values(), key=lambda s: s.name):
+            domains.setdefault(site.reset_domain, []).append(site.name)
+        return domains
+
+
+class ResetDomainLock(object):
+    """Exclusive lock of a reset domain, held by a site while it runs.
+
+    The lock is shared by all the orchestrator processes using the same lock
+    directory, and released when the process holding it exits.
+    """
+
+    def __init__(self, lock_dir: str, site: ProbeSite):
+        self.site = site
+        self.path = os.path.join(lock_dir,
+                                 f"reset_domain_{site.reset_domain}.lock")
+        self._fd = None
+
+    def _holder(self) -> str:
+        try:
+            with open(self.path, "r") as fp:
+                return fp.read().strip() or "unknown site"
+        except OSError:
+            return "unknown site"
+
+    def acquire(self, timeout: float = None) -> None:
+        """Waits for the other sites of the reset domain to finish.
+
+        Raises:
+            TimeoutError: if the reset domain is still busy after `timeout`
+              seconds.
+        """
+        fd = os.open(self.path, os.O_RDWR | os.O_CREAT, 0o644)
+        deadline = None if timeout is None else time.monotonic() + timeout
+        waiting = False
+        while True:
+            try:
+                fcntl.flock(fd, fcntl.LOCK_EX | fcntl.LOCK_NB)
+                break
+            except BlockingIOError:
+                if deadline is not None and time.monotonic() >= deadline:
+                    os.close(fd)
+                    raise TimeoutError(
+                        f"Reset domain {self.site.reset_domain} still busy "
+                        f"with {self._holder()}.") from None
+                if not waiting:
+                    logging.info(
+                        f"Site {self.site.name} waiting for reset domain "
+                        f"{self.site.reset_domain}, busy with "
+                        f"{self._holder()}.")
+                    waiting = True
+                time.sleep(_LOCK_POLL_INTERVAL)
+        os.ftruncate(fd, 0)
+        os.write(fd, f"site {self.site.name} (pid {os.getpid()})\n".encode())
+        self._fd = fd
+
+    def release(self) -> None:
+        if self._fd is None:
+            return
+        os.ftruncate(self._fd, 0)
+        fcntl.flock(self._fd, fcntl.LOCK_UN)
+        os.close(self._fd)
+        self._fd = None
+
+    def __enter__(self) -> "ResetDomainLock":
+        self.acquire()
+        return self
+
+    def __exit__(self, exc_type, exc_value, traceback) -> None:
+        self.release()
@@ -0,0 +1,231 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Enforcement of the provisioning volume quotas.
+
+Quotas cap the number of devices certified by each CA key, provisioned from
+each production lot, and started during each shift, e.g. to enforce licensing
+terms or contract manufacturing volumes. Each device is counted in the
+provisioning database before it is started, whatever the outcome of its
+flows.
+
+A device exceeding a quota is only started with an override, authorized by one
+of the supervisors listed in the quota configuration. Supervisors are listed
+with a salted hash of their passphrase, generated with:
+
+    quota.py hash-passphrase
+"""
+
+import argparse
+import getpass
+import hashlib
+import hmac
+import logging
+import os
+import sys
+import time
+from dataclasses import dataclass
+
+from db import DB, QuotaUsageRecord
+from sku_config import SkuConfig
+
+# Hash function and number of iterations of the supervisor passphrase hashes.
+_HASH_NAME = "sha256"
+_HASH_ITERATIONS = 200000
+
+
+class QuotaExceeded(Exception):
+    """Raised when starting a device would exceed a quota."""
+
+    def __init__(self, violations: [str]):
+        super().__init__("; ".join(violations))
+        self.violations = violations
+
+
+class OverrideDenied(PermissionError):
+    """Raised when a quota override is not authorized."""
+
+
+@dataclass
+class QuotaConfig(object):
+    """Class for storing a quota configuration.
+
+    Quotas left unset are not enforced.
+    """
+    max_devices_per_key: int = None  # valid: > 0
+    max_devices_per_lot: int = None  # valid: > 0
+    max_devices_per_shift: int = None  # valid: > 0
+    shift_hours: int = 8  # valid: divides 24
+    shift_start_hour: int = 0  # valid: 0 - 23, start of the first shift
+    supervisors: dict = None  # valid: name -> passphrase hash
+
+    def __post_init__(self):
+        if self.supervisors is None:
+            self.supervisors = {}
+        self.validate()
+
+    def validate(self) -> None:
+        """Validates this object's attributes."""
+        for name in [
+                "max_devices_per_key", "max_devices_per_lot",
+                "max_devices_per_shift"
+        ]:
+            value = getattr(self, name)
+            if value is not None and value <= 0:
+                raise ValueError(f"{name} ({value}) must be positive.")
+        if self.shift_hours <= 0 or 24 % self.shift_hours != 0:
+            raise ValueError(
+                f"shift_hours ({self.shift_hours}) must divide 24.")
+        if not 0 <= self.shift_start_hour < 24:
+            raise ValueError(
+                f"shift_start_hour ({self.shift_start_hour}) must be 0 - 23.")
+        for name, passphrase_hash in self.supervisors.items():
+            if len(passphrase_hash.split("$")) != 4:
+                raise ValueError(
+                    f"Passphrase hash of supervisor {name} is malformed.")
+
+
+def hash_passphrase(passphrase: str, salt: bytes = None) -> str:
+    """Returns the salted hash of a supervisor passphrase."""
+    if salt is None:
+        salt = os.urandom(16)
+    digest = hashlib.pbkdf2_hmac(_HASH_NAME, passphrase.encode(), salt,
+                                 _HASH_ITERATIONS)
+    return f"pbkdf2_{_HASH_NAME}${_HASH_ITERATIONS}${salt.hex()}${digest.hex()}"
+
+
+def check_passphrase(passphrase: str, passphrase_hash: str) -> bool:
+    """Checks a supervisor passphrase against its salted hash."""
+    algorithm, iterations, salt, digest = passphrase_hash.split("$")
+    if not algorithm.startswith("pbkdf2_"):
+        return False
+    expected = hashlib.pbkdf2_hmac(algorithm[len("pbkdf2_"):],
+                                   passphrase.encode(), bytes.fromhex(salt),
+                                   int(iterations))
+    return hmac.compare_digest(expected.hex(), digest)
+
+
+def shift_name(config: QuotaConfig, timestamp: float) -> str:
+    """Returns the name of the shift `timestamp` falls in, e.g. 2024-02-07S1.
+
+    Shifts are numbered from the first shift of the day, in local time.
+    """
+    start = time.localtime(timestamp - config.shift_start_hour * 3600)
+    return f"{time.strftime('%Y-%m-%d', start)}S{start.tm_hour // config.shift_hours}"
+
+
+class QuotaEnforcer(object):
+    """Counts the devices started against the quotas."""
+
+    def __init__(self, db: DB, config: QuotaConfig):
+        self.db = db
+        self.config = config
+
+    def violations(self, sku_config: SkuConfig, lot: str,
+                   timestamp: float = None) -> [str]:
+        """Returns the quotas starting one more device would exceed."""
+        if timestamp is None:
+            timestamp = time.time()
+        violations = []
+        limit = self.config.max_devices_per_key
+        if limit is not None:
+            for ca in [sku_config.dice_ca, sku_config.ext_ca]:
+                count = QuotaUsageRecord.count_by_key(self.db, ca.key_id)
+                if count >= limit:
+                    violations.append(
+                        f"{count} devices certified by {ca.name} key "
+                        f"{ca.key_id} (quota: {limit})")
+        limit = self.config.max_devices_per_lot
+        if limit is not None:
+            count = QuotaUsageRecord.count_by_lot(self.db, lot)
+            if count >= limit:
+                violations.append(
+                    f"{count} devices provisioned from lot {lot} (quota: "
+                    f"{limit})")
+        limit = self.config.max_devices_per_shift
+        if limit is not None:
+            shift = shift_name(self.config, timestamp)
+            count = QuotaUsageRecord.count_by_shift(self.db, shift)
+            if count >= limit:
+                violations.append(
+                    f"{count} devices started during shift {shift} (quota: "
+                    f"{limit})")
+        return violations
+
+    def authorize_override(self, supervisor: str, passphrase: str) -> str:
+        """Checks the passphrase of a supervisor authorizing an override.
+
+        Returns:
+            The name of the supervisor.
+        """
+        passphrase_hash = self.config.supervisors.get(supervisor)
+        if passphrase_hash is None or not check_passphrase(
+                passphrase, passphrase_hash):
+            raise OverrideDenied(
+                f"Quota override not authorized by supervisor {supervisor}.")
+        return supervisor
+
+    def reserve(self,
+                device_id: str,
+                sku_config: SkuConfig,
+                lot: str,
+                override: str = None) -> QuotaUsageRecord:
+        """Counts a device against the quotas, before it is started.
+
+        The quotas are checked and the device recorded in a single
+        transaction, so stations sharing the database cannot overshoot them.
+
+        Args:
+            override: The supervisor who authorized exceeding the quotas, see
+              authorize_override.
+        Returns:
+            The recorded quota usage.
+        """
+        timestamp = time.time()
+        c = self.db.try_cursor()
+        c.execute("BEGIN IMMEDIATE")
+        try:
+            violations = self.violations(sku_config, lot, timestamp)
+            if violations and override is None:
+                raise QuotaExceeded(violations)
+            if violations:
+                logging.warning(
+                    f"Quotas exceeded for device {device_id}, overridden by "
+                    f"supervisor {override}: {'; '.join(violations)}")
+            record = QuotaUsageRecord(
+                device_id=device_id,
+                sku=sku_config.name,
+                dice_key_id=sku_config.dice_ca.key_id,
+                ext_key_id=sku_config.ext_ca.key_id,
+                lot=lot,
+                shift=shift_name(self.config, timestamp),
+                override=override if violations else "",
+                timestamp=int(timestamp),
+            )
+            record.insert(self.db)
+        except BaseException:
+            self.db.rollback()
+            raise
+        return record
+
+
+def main(args_in):
+    parser = argparse.ArgumentParser(
+        description="Manages the provisioning volume quotas.")
+    subparsers = parser.add_subparsers(dest="command", required=True)
+    subparsers.add_parser(
+        "hash-passphrase",
+        help="""Prompts for a supervisor passphrase, and prints its hash for
+        the supervisors of the quota configuration.""",
+    )
+    args = parser.parse_args(args_in)
+
+    if args.command == "hash-passphrase":
+        passphrase = getpass.getpass("Supervisor passphrase: ")
+        if passphrase != getpass.getpass("Confirm passphrase: "):
+            sys.exit("Passphrases do not match.")
+        print(hash_passphrase(passphrase))
+
+
+if __name__ == "__main__":
+    main(sys.argv[1:])
@@ -0,0 +1,296 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Registration of provisioned devices with the fleet registry.
+
+Devices recorded in the provisioning database are queued for registration,
+and uploaded to the registry in batches. The queue lives in the provisioning
+database, so it survives crashes and is shared by all orchestrator runs
+recording into the same database:
+
+- A device is queued at most once per fingerprint, so re-recording the same
+  results does not upload them again.
+- A batch is marked as submitted before it is uploaded. Batches left
+  submitted by a crash are resubmitted first, under the same batch ID, so the
+  registry can deduplicate them.
+- The registry acknowledges or rejects each device of a batch, and the
+  outcome is recorded with the device. Devices missing from the response are
+  resubmitted with their batch.
+"""
+
+import argparse
+import hashlib
+import json
+import logging
+import sys
+import time
+import urllib.request
+from dataclasses import dataclass
+
+from db import DB, DBConfig, DeviceRecord, RegistrationRecord
+from ft_result import DICE_CERTS
+
+# Registration states.
+PENDING = "pending"
+SUBMITTED = "submitted"
+ACKED = "acked"
+REJECTED = "rejected"
+
+
+@dataclass
+class RegistrationConfig(object):
+    """Registry upload settings."""
+    batch_size: int = 16
+    max_retries: int = 3
+    # Delay before the first retry of a batch, doubled on each retry.
+    retry_delay: float = 5.0
+
+    def __post_init__(self):
+        if self.batch_size < 1:
+            raise ValueError("The registration batch size must be positive.")
+        if self.max_retries < 0:
+            raise ValueError("The registration retries must not be negative.")
+
+
+class RegistryError(Exception):
+    """Raised when the registry fails to process a batch."""
+
+
+class HttpRegistry(object):
+    """Client of a registry accepting batches as JSON POST requests.
+
+    The request body is `{"batch_id": str, "devices": [payload, ...]}`, and
+    the response body `{"acked": [{"fingerprint": str, "registry_id": str}],
+    "rejected": [{"fingerprint": str, "error": str}]}`.
+    """
+
+    def __init__(self, url: str, timeout: float = 30.0):
+        self.url = url
+        self.timeout = timeout
+
+    def submit(self, batch_id: str, devices: [dict]) -> dict:
+        req = urllib.request.Request(
+            self.url,
+            data=json.dumps({
+                "batch_id": batch_id,
+                "devices": devices
+            }).encode("utf-8"),
+            headers={"Content-Type": "application/json"},
+            method="POST",
+        )
+        try:
+            with urllib.request.urlopen(req, timeout=self.timeout) as resp:
+                return json.loads(resp.read())
+        except Exception as e:
+            raise RegistryError(f"Batch {batch_id} upload failed: {e}") from e
+
+
+def registration_payload(record: DeviceRecord) -> dict:
+    """Returns the registry upload of a device record."""
+    certs = json.loads(record.sku_specific_data or "{}")
+    for name, column in DICE_CERTS.items():
+        if getattr(record, column):
+            certs[name] = getattr(record, column)
+    return {
+        "device_id": record.device_id,
+        "sku": record.sku,
+        "lc_state": record.provisioning_state,
+        "fingerprint": record.fingerprint,
+        "certs": certs,
+    }
+
+
+def batch_id(records: [RegistrationRecord]) -> str:
+    """Returns the ID of a batch, derived from the fingerprints it holds."""
+    digest = hashlib.sha256()
+    for fingerprint in sorted(r.fingerprint for r in records):
+        digest.update(fingerprint.encode("utf-8"))
+    return digest.hexdigest()[:16]
+
+
+class RegistrationQueue(object):
+    """Queue of the devices to register, stored in the provisioning DB."""
+
+    def __init__(self,
+                 db: DB,
+                 registry,
+                 config: RegistrationConfig,
+                 sleep=time.sleep):
+        self.db = db
+        self.registry = registry
+        self.config = config
+        self._sleep = sleep
+        RegistrationRecord.create_table(db)
+
+    def enqueue(self, record: DeviceRecord) -> bool:
+        """Queues a device record for registration.
+
+        Returns:
+            False if the device was already queued with the same fingerprint.
+        """
+        registration = RegistrationRecord(
+            device_id=record.device_id,
+            sku=record.sku,
+            fingerprint=record.fingerprint,
+            payload=json.dumps(registration_payload(record), sort_keys=True),
+            state=PENDING,
+            batch_id="",
+            attempts=0,
+            registry_id="",
+            error="",
+            timestamp=int(time.time()),
+        )
+        existing = registration.insert_unless_duplicate(self.db)
+        if existing is not None:
+            logging.info(f"Device {record.device_id} already queued for "
+                         f"registration ({existing.state}).")
+            return False
+        return True
+
+    def pending_batches(self, force: bool) -> [[RegistrationRecord]]:
+        """Returns the batches to upload, resubmitted batches first.
+
+        Args:
+            force: Also return the last, partially filled, batch.
+        """
+        batches = {}
+        for r in RegistrationRecord.query_by_state(self.db, SUBMITTED):
+            batches.setdefault(r.batch_id, []).append(r)
+        pending = RegistrationRecord.query_by_state(self.db, PENDING)
+        size = self.config.batch_size
+        groups = [pending[i:i + size] for i in range(0, len(pending), size)]
+        if groups and len(groups[-1]) < size and not force:
+            groups.pop()
+        return list(batches.values()) + groups
+
+    def flush(self, force: bool = False) -> int:
+        """Uploads the queued devices to the registry.
+
+        Stops at the first batch the registry fails to process after all
+        retries; the batch is resubmitted by the next flush.
+
+        Args:
+            force: Also upload a partially filled batch.
+        Returns:
+            The number of devices acknowledged by the registry.
+        """
+        acked = 0
+        for batch in self.pending_batches(force):
+            bid = batch[0].batch_id or batch_id(batch)
+            for r in batch:
+                r.state = SUBMITTED
+                r.batch_id = bid
+                r.update(self.db)
+            try:
+                response = self._submit(bid, batch)
+            except RegistryError as e:
+                logging.error(f"Registration of batch {bid} failed: {e}")
+                for r in batch:
+                    r.error = str(e)
+                    r.update(self.db)
+                break
+            acked += self._reconcile(bid, batch, response)
+        return acked
+
+    def _submit(self, bid: str, batch: [RegistrationRecord]) -> dict:
+        delay = self.config.retry_delay
+        for attempt in range(self.config.max_retries + 1):
+            for r in batch:
+                r.attempts += 1
+                r.update(self.db)
+            try:
+                return self.registry.submit(
+                    bid, [json.loads(r.payload) for r in batch])
+            except RegistryError as e:
+                if attempt == self.config.max_retries:
+                    raise
+                logging.warning(f"{e}; retrying in {delay:.0f}s.")
+                self._sleep(delay)
+                delay *= 2
+
+    def _reconcile(self, bid: str, batch: [RegistrationRecord],
+                   response: dict) -> int:
+        """Records the outcome of a batch reported by the registry.
+
+        Returns:
+            The number of devices of the batch acknowledged.
+        """
+        records = {r.fingerprint: r for r in batch}
+        acked = 0
+        for ack in response.get("acked", []):
+            r = records.get(ack.get("fingerprint"))
+            if r is None:
+                logging.warning(f"Registry acknowledged {ack} which is not "
+                                f"part of batch {bid}.")
+                continue
+            r.state = ACKED
+            r.registry_id = ack.get("registry_id", "")
+            r.error = ""
+            r.update(self.db)
+            acked += 1
+        for reject in response.get("rejected", []):
+            r = records.get(reject.get("fingerprint"))
+            if r is None or r.state == ACKED:
+                continue
+            r.state = REJECTED
+            r.error = reject.get("error", "")
+            r.update(self.db)
+            logging.error(
+                f"Registry rejected device {r.device_id}: {r.error}")
+        missing = [
+            r.device_id for r in batch if r.state not in (ACKED, REJECTED)
+        ]
+        if missing:
+            logging.warning(f"Registry did not report on devices {missing} "
+                            f"of batch {bid}; they will be resubmitted.")
+        return acked
+
+
+def main(args_in):
+    parser = argparse.ArgumentParser(
+        description="""Uploads the devices queued for registration in a
+        provisioning database to the fleet registry.""")
+    parser.add_argument(
+        "--db-path",
+        required=True,
+        help="SQLite database the orchestrator recorded results into.",
+    )
+    parser.add_argument(
+        "--registry-url",
+        required=True,
+        help="URL of the fleet registry batch upload endpoint.",
+    )
+    parser.add_argument(
+        "--batch-size",
+        type=int,
+        default=16,
+        help="Number of devices uploaded per batch.",
+    )
+    parser.add_argument(
+        "--max-retries",
+        type=int,
+        default=3,
+        help="Number of times a failed batch upload is retried.",
+    )
+    parser.add_argument(
+        "--partial",
+        action="store_true",
+        default=False,
+        help="Also upload the last, partially filled, batch.",
+    )
+    args = parser.parse_args(args_in)
+
+    logging.basicConfig(level=logging.INFO)
+    db = DB(DBConfig(db_path=args.db_path))
+    queue = RegistrationQueue(
+        db, HttpRegistry(args.registry_url),
+        RegistrationConfig(batch_size=args.batch_size,
+                           max_retries=args.max_retries))
+    acked = queue.flush(force=args.partial)
+    logging.info(f"{acked} devices registered.")
+    if queue.pending_batches(force=args.partial):
+        sys.exit(1)
+
+
+if __name__ == "__main__":
+    main(sys.argv[1:])
@@ -0,0 +1,214 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""RFC 3161 timestamping of the FT reports of provisioned devices.
+
+The FT report of each passing run is saved to `ft_report.json` in the log
+directory of the device, and its SHA256 digest is timestamped by a
+timestamping authority (TSA). The TSA response is saved next to the report,
+as `ft_report.json.tsr`, so the time a device was provisioned can be verified
+independently of the provisioning station, e.g. with `openssl ts -verify`.
+
+Only the status, message imprint and nonce of the timestamp token are checked
+here; its signature is left to the verifier of the report.
+"""
+
+import hashlib
+import json
+import secrets
+import urllib.request
+from typing import Tuple
+
+# DER tags.
+_BOOLEAN = 0x01
+_INTEGER = 0x02
+_OCTET_STRING = 0x04
+_NULL = 0x05
+_OID = 0x06
+_GENERALIZED_TIME = 0x18
+_SEQUENCE = 0x30
+_SET = 0x31
+_CONTEXT_0 = 0xa0
+
+# DER encoding of the SHA256 object identifier, 2.16.840.1.101.3.4.2.1.
+_SHA256_OID = bytes.fromhex("608648016503040201")
+
+# PKIStatus values of a TSA response granting the request.
+_GRANTED = 0
+_GRANTED_WITH_MODS = 1
+
+REPORT_FILE = "ft_report.json"
+TIMESTAMP_SUFFIX = ".tsr"
+
+
+class TimestampError(Exception):
+    """Raised when a TSA fails to timestamp a report."""
+
+
+def _der(tag: int, value: bytes) -> bytes:
+    """Encodes a DER TLV."""
+    if len(value) < 0x80:
+        length = bytes([len(value)])
+    else:
+        size = (len(value).bit_length() + 7) // 8
+        length = bytes([0x80 | size]) + len(value).to_bytes(size, "big")
+    return bytes([tag]) + length + value
+
+
+def _der_integer(value: int) -> bytes:
+    """Encodes a non-negative DER INTEGER."""
+    return _der(_INTEGER, value.to_bytes(value.bit_length() // 8 + 1, "big"))
+
+
+def _der_read(data: bytes, offset: int = 0) -> Tuple[int, bytes, int]:
+    """Decodes the DER TLV at `offset`.
+
+    Returns:
+        The tag, the value and the offset of the next TLV.
+    """
+    try:
+        tag = data[offset]
+        length = data[offset + 1]
+        offset += 2
+        if length & 0x80:
+            size = length & 0x7f
+            length = int.from_bytes(data[offset:offset + size], "big")
+            offset += size
+    except IndexError:
+        raise TimestampError("Truncated DER encoding.")
+    if offset + length > len(data):
+        raise TimestampError("Truncated DER encoding.")
+    return tag, data[offset:offset + length], offset + length
+
+
+def _message_imprint(digest: bytes) -> bytes:
+    """Returns the DER MessageImprint of a SHA256 `digest`."""
+    algorithm = _der(_SEQUENCE, _der(_OID, _SHA256_OID) + _der(_NULL, b""))
+    return _der(_SEQUENCE, algorithm + _der(_OCTET_STRING, digest))
+
+
+def timestamp_request(digest: bytes, nonce: int) -> bytes:
+    """Returns the DER TimeStampReq of a SHA256 `digest`.
+
+    The TSA is asked to include its certificate in the response, so the
+    response can be verified on its own.
+    """
+    return _der(
+        _SEQUENCE,
+        _der_integer(1) + _message_imprint(digest) + _der_integer(nonce) +
+        _der(_BOOLEAN, b"\xff"))
+
+
+def _expect(data: bytes, offset: int, tag: int,
+            what: str) -> Tuple[bytes, int]:
+    """Decodes the DER TLV at `offset`, which must be tagged `tag`."""
+    actual, value, offset = _der_read(data, offset)
+    if actual != tag:
+        raise TimestampError(f"Malformed {what}.")
+    return value, offset
+
+
+def _tst_info(token: bytes) -> bytes:
+    """Returns the TSTInfo of a TimeStampToken, i.e. a CMS SignedData."""
+    content_info, _ = _expect(token, 0, _SEQUENCE, "timestamp token")
+    _, offset = _expect(content_info, 0, _OID, "timestamp token")
+    explicit, _ = _expect(content_info, offset, _CONTEXT_0, "timestamp token")
+    signed_data, _ = _expect(explicit, 0, _SEQUENCE, "timestamp token")
+    # Skip the version and digest algorithms of the SignedData.
+    _, offset = _expect(signed_data, 0, _INTEGER, "timestamp token")
+    _, offset = _expect(signed_data, offset, _SET, "timestamp token")
+    encap, _ = _expect(signed_data, offset, _SEQUENCE, "timestamp token")
+    _, offset = _expect(encap, 0, _OID, "timestamp token")
+    explicit, _ = _expect(encap, offset, _CONTEXT_0, "timestamp token")
+    tst_info, _ = _expect(explicit, 0, _OCTET_STRING, "timestamp token")
+    return tst_info
+
+
+def check_timestamp_response(response: bytes, digest: bytes,
+                             nonce: int) -> str:
+    """Checks a DER TimeStampResp grants a timestamp of `digest`.
+
+    Returns:
+        The time of the timestamp, as an ASN.1 GeneralizedTime string.
+    Raises:
+        TimestampError: the TSA rejected the request, or the timestamp token
+          does not cover `digest` or `nonce`.
+    """
+    body, _ = _expect(response, 0, _SEQUENCE, "TSA response")
+    status_info, offset = _expect(body, 0, _SEQUENCE, "TSA response status")
+    status, _ = _expect(status_info, 0, _INTEGER, "TSA response status")
+    status = int.from_bytes(status, "big")
+    if status not in (_GRANTED, _GRANTED_WITH_MODS):
+        raise TimestampError(f"TSA rejected the request (status {status}).")
+    if offset >= len(body):
+        raise TimestampError("TSA response holds no timestamp token.")
+    tst_info, _ = _expect(_tst_info(body[offset:]), 0, _SEQUENCE,
+                          "timestamp token info")
+    # TSTInfo: version, policy, messageImprint, serialNumber, genTime, then
+    # optional fields, of which the nonce is the only INTEGER.
+    _, offset = _expect(tst_info, 0, _INTEGER, "timestamp token info")
+    _, offset = _expect(tst_info, offset, _OID, "timestamp token info")
+    imprint_start = offset
+    _, offset = _expect(tst_info, offset, _SEQUENCE, "timestamp token info")
+    if tst_info[imprint_start:offset] != _message_imprint(digest):
+        raise TimestampError("Timestamp token does not cover the report.")
+    _, offset = _expect(tst_info, offset, _INTEGER, "timestamp token info")
+    gen_time, offset = _expect(tst_info, offset, _GENERALIZED_TIME,
+                               "timestamp token info")
+    token_nonce = None
+    while offset < len(tst_info):
+        tag, value, offset = _der_read(tst_info, offset)
+        if tag == _INTEGER:
+            token_nonce = int.from_bytes(value, "big")
+            break
+    if token_nonce != nonce:
+        raise TimestampError("Timestamp token does not match the request.")
+    return gen_time.decode("ascii")
+
+
+class HttpTimestampAuthority(object):
+    """Client of a TSA accepting timestamp requests as HTTP POST requests."""
+
+    def __init__(self, url: str, timeout: float = 30.0):
+        self.url = url
+        self.timeout = timeout
+
+    def timestamp(self, request: bytes) -> bytes:
+        req = urllib.request.Request(
+            self.url,
+            data=request,
+            headers={"Content-Type": "application/timestamp-query"},
+            method="POST",
+        )
+        try:
+            with urllib.request.urlopen(req, timeout=self.timeout) as resp:
+                return resp.read()
+        except Exception as e:
+            raise TimestampError(f"TSA request failed: {e}") from e
+
+
+def timestamp_report(tsa, ft_data: dict, log_dir: str) -> Tuple[str, str]:
+    """Saves the FT report of a device and timestamps it.
+
+    Args:
+        tsa: The TSA client, e.g. a `HttpTimestampAuthority`.
+        ft_data: The FT results, as returned by
+          `ft_result.parse_provisioning_data`.
+        log_dir: The directory holding the provisioning logs of the device.
+    Returns:
+        The path of the saved report, and the time of its timestamp.
+    Raises:
+        TimestampError: the report could not be timestamped; the report is
+          saved regardless.
+    """
+    path = f"{log_dir}/{REPORT_FILE}"
+    report = json.dumps(ft_data, indent=2, sort_keys=True).encode("utf-8")
+    with open(path, "wb") as fp:
+        fp.write(report)
+    digest = hashlib.sha256(report).digest()
+    nonce = secrets.randbits(64)
+    response = tsa.timestamp(timestamp_request(digest, nonce))
+    gen_time = check_timestamp_response(response, digest, nonce)
+    with open(path + TIMESTAMP_SUFFIX, "wb") as fp:
+        fp.write(response)
+    return path, gen_time
@@ -0,0 +1,167 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Retention of the device log directories of a station.
+
+Each provisioned device leaves a log directory under the logs root directory.
+The maintenance routine removes the directories of devices provisioned more
+than `keep_days` ago, optionally archiving them first, so stations with small
+disks do not fill up mid-shift. The directories of failed runs are kept by
+default, and the directories of runs in flight or interrupted by a crash are
+never touched.
+"""
+
+import argparse
+import json
+import logging
+import os
+import shutil
+import sys
+import tarfile
+import time
+from dataclasses import dataclass, field
+from typing import List, Optional
+
+from step_state import STEP_STATE_FILE, StepState
+
+_SECONDS_PER_DAY = 24 * 60 * 60
+
+
+@dataclass
+class RetentionConfig:
+    """Log retention configuration.
+
+    Attributes:
+        keep_days: Days the log directory of a device is kept on the station
+          after its flows completed.
+        archive_dir: Directory to archive the log directories to, as gzipped
+          tarballs, before deleting them. Log directories are deleted without
+          an archive if not set.
+        keep_failures: Never delete the log directories of failed runs.
+    """
+    keep_days: float
+    archive_dir: Optional[str] = None
+    keep_failures: bool = True
+
+    def __post_init__(self):
+        if self.keep_days <= 0:
+            raise ValueError("Retention keep days must be positive.")
+
+
+@dataclass
+class RetentionReport:
+    """Log directories handled by one maintenance pass."""
+    archived: List[str] = field(default_factory=list)
+    deleted: List[str] = field(default_factory=list)
+    kept_failures: List[str] = field(default_factory=list)
+
+
+def _archive(log_dir: str, archive_dir: str) -> str:
+    """Archives `log_dir` to `archive_dir`, returning the archive path.
+
+    The archive is written under a temporary name and renamed once complete,
+    so an interrupted pass never leaves a truncated archive behind.
+    """
+    name = os.path.basename(os.path.normpath(log_dir))
+    path = os.path.join(archive_dir, f"{name}.tar.gz")
+    tmp = f"{path}.tmp"
+    with tarfile.open(tmp, "w:gz") as tar:
+        tar.add(log_dir, arcname=name)
+    os.replace(tmp, path)
+    return path
+
+
+def apply_retention(logs_root_dir: str,
+                    config: RetentionConfig,
+                    now: Optional[float] = None) -> RetentionReport:
+    """Applies the retention policy to the device log directories.
+
+    Only directories with a completed step state are candidates: other entries
+    of the logs root directory (yield state, site locks) and the directories
+    of runs without a recorded outcome are left alone.
+
+    Args:
+        logs_root_dir: Root directory of the device log directories.
+        config: Retention policy.
+        now: Current time, in seconds since the epoch (default: now).
+
+    Returns:
+        The log directories archived, deleted, and kept as failures.
+    """
+    report = RetentionReport()
+    if not os.path.isdir(logs_root_dir):
+        return report
+    if now is None:
+        now = time.time()
+    cutoff = now - config.keep_days * _SECONDS_PER_DAY
+    if config.archive_dir:
+        os.makedirs(config.archive_dir, exist_ok=True)
+    for entry in sorted(os.scandir(logs_root_dir), key=lambda e: e.name):
+        path = os.path.join(entry.path, STEP_STATE_FILE)
+        if not entry.is_dir() or not os.path.exists(path):
+            continue
+        with open(path, "r") as fp:
+            state = StepState(**json.load(fp))
+        if not state.completed or state.updated >= cutoff:
+            continue
+        # Runs recorded before their outcome was are treated as failures.
+        if config.keep_failures and not state.passed:
+            report.kept_failures.append(entry.path)
+            continue
+        if config.archive_dir:
+            archive = _archive(entry.path, config.archive_dir)
+            logging.info(f"Archived logs of {state.device_id} to {archive}")
+            report.archived.append(entry.path)
+        shutil.rmtree(entry.path)
+        report.deleted.append(entry.path)
+    if report.deleted:
+        logging.info(f"Log retention removed {len(report.deleted)} device "
+                     f"log directories older than {config.keep_days} days")
+    return report
+
+
+def main(args_in):
+    parser = argparse.ArgumentParser(
+        description="""Applies a retention policy to the device log
+        directories of a provisioning station, e.g. from a cron job.""")
+    parser.add_argument(
+        "--log-dir",
+        required=True,
+        help="Root directory the orchestrator stores log files under.",
+    )
+    parser.add_argument(
+        "--keep-days",
+        required=True,
+        type=float,
+        help="Days the log directory of a device is kept.",
+    )
+    parser.add_argument(
+        "--archive-dir",
+        help="Directory to archive the log directories to before deletion.",
+    )
+    parser.add_argument(
+        "--delete-failures",
+        action="store_true",
+        default=False,
+        help="Also delete the log directories of failed runs.",
+    )
+    args = parser.parse_args(args_in)
+
+    logging.basicConfig(level=logging.INFO)
+    report = apply_retention(
+        args.log_dir,
+        RetentionConfig(keep_days=args.keep_days,
+                        archive_dir=args.archive_dir,
+                        keep_failures=not args.delete_failures))
+    print(
+        json.dumps(
+            {
+                "archived": len(report.archived),
+                "deleted": len(report.deleted),
+                "kept_failures": len(report.kept_failures),
+            },
+            indent=2))
+
+
+if __name__ == "__main__":
+    main(sys.argv[1:])
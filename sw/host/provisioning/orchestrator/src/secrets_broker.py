@@ -0,0 +1,206 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""In-memory broker of the provisioning secrets.
+
+The broker loads the LC tokens and private key files once, and hands out
+handles to the per-device workers. A handle is scoped to a device and to the
+provisioning flows it runs, and each secret can only be accessed from the
+flows allowed by the access policy.
+
+Key files are only read by the broker. A flow needing a key file gets a
+private copy, written from memory, which is wiped and removed as soon as the
+flow is done. On shutdown, all handles are revoked, the in-memory secrets are
+zeroized and any leftover key file copies are wiped.
+
+Values handed out to the flows (e.g. tokens as integers, or on command lines)
+are copies the broker cannot zeroize.
+"""
+
+import atexit
+import contextlib
+import logging
+import os
+import shutil
+import stat
+import tempfile
+import threading
+from typing import Dict, FrozenSet, Iterator
+
+# Provisioning flows.
+FLOWS = frozenset({"cp", "ft"})
+
+# Flows each secret can be accessed from.
+DEFAULT_POLICY = {
+    "test_unlock_token": frozenset({"cp", "ft"}),
+    "test_exit_token": frozenset({"cp", "ft"}),
+    "dice_ca_key": frozenset({"ft"}),
+    "ext_ca_key": frozenset({"ft"}),
+}
+
+# Size of a LC token in bytes.
+_TOKEN_SIZE = 16
+
+# Directory key file copies are written under, if it exists (not backed by
+# persistent storage).
+_SHM_DIR = "/dev/shm"
+
+
+class SecretAccessError(PermissionError):
+    """Raised when a secret is accessed in violation of the policy."""
+
+
+class SecretsBroker(object):
+    """Holds the provisioning secrets, and hands out scoped handles."""
+
+    def __init__(self, policy: Dict[str, FrozenSet[str]] = None):
+        self.policy = dict(DEFAULT_POLICY if policy is None else policy)
+        self._secrets = {}
+        self._handles = []
+        self._lock = threading.Lock()
+        self._closed = False
+        self._runtime_dir = tempfile.mkdtemp(
+            prefix="provisioning_secrets_",
+            dir=_SHM_DIR if os.path.isdir(_SHM_DIR) else None)
+        os.chmod(self._runtime_dir, stat.S_IRWXU)
+        atexit.register(self.shutdown)
+
+    def __enter__(self) -> 'SecretsBroker':
+        return self
+
+    def __exit__(self, *exc) -> None:
+        self.shutdown()
+
+    def _add(self, name: str, value: bytearray) -> None:
+        if name not in self.policy:
+            raise ValueError(f"Secret {name} is not covered by the policy.")
+        with self._lock:
+            if self._closed:
+                raise RuntimeError("The secrets broker is shut down.")
+            if name in self._secrets:
+                raise ValueError(f"Secret {name} is already loaded.")
+            self._secrets[name] = value
+
+    def add_token(self, name: str, token: int) -> None:
+        """Adds a 128-bit LC token."""
+        self._add(name,
+                  bytearray(token.to_bytes(_TOKEN_SIZE, byteorder="little")))
+
+    def load_key_file(self, name: str, path: str) -> None:
+        """Reads a private key file into the broker."""
+        with open(path, "rb") as fp:
+            value = bytearray(os.fstat(fp.fileno()).st_size)
+            fp.readinto(value)
+        self._add(name, value)
+
+    def handle(self, device_id: str, flows=FLOWS) -> 'SecretHandle':
+        """Returns a handle to the secrets, for the flows run on a device."""
+        handle = SecretHandle(self, device_id, frozenset(flows))
+        with self._lock:
+            if self._closed:
+                raise RuntimeError("The secrets broker is shut down.")
+            self._handles.append(handle)
+        return handle
+
+    def _access(self, handle: 'SecretHandle', name: str,
+                flow: str) -> bytearray:
+        """Returns a secret, if `handle` can access it from `flow`.
+
+        Must be called with the lock held.
+        """
+        if self._closed or handle.revoked:
+            raise SecretAccessError(
+                f"Secret handle of device {handle.device_id} is revoked.")
+        if flow not in handle.flows or flow not in self.policy.get(
+                name, ()):
+            raise SecretAccessError(
+                f"Flow {flow} of device {handle.device_id} may not access "
+                f"secret {name}.")
+        if name not in self._secrets:
+            raise KeyError(f"Secret {name} is not loaded.")
+        return self._secrets[name]
+
+    def _release(self, handle: 'SecretHandle') -> None:
+        with self._lock:
+            handle.revoked = True
+            if handle in self._handles:
+                self._handles.remove(handle)
+
+    def shutdown(self) -> None:
+        """Revokes all handles, and zeroizes all secrets."""
+        with self._lock:
+            if self._closed:
+                return
+            self._closed = True
+            for handle in self._handles:
+                handle.revoked = True
+            self._handles.clear()
+            for value in self._secrets.values():
+                value[:] = bytes(len(value))
+            self._secrets.clear()
+            for entry in os.scandir(self._runtime_dir):
+                _wipe(entry.path)
+            shutil.rmtree(self._runtime_dir, ignore_errors=True)
+        atexit.unregister(self.shutdown)
+        logging.info("Provisioning secrets zeroized.")
+
+
+class SecretHandle(object):
+    """Access to the secrets of the broker, scoped to a device and flows."""
+
+    def __init__(self, broker: SecretsBroker, device_id: str,
+                 flows: FrozenSet[str]):
+        self._broker = broker
+        self.device_id = device_id
+        self.flows = flows
+        self.revoked = False
+
+    def __enter__(self) -> 'SecretHandle':
+        return self
+
+    def __exit__(self, *exc) -> None:
+        self.close()
+
+    def close(self) -> None:
+        """Revokes the handle."""
+        self._broker._release(self)
+
+    def token(self, name: str, flow: str) -> int:
+        """Returns a LC token."""
+        with self._broker._lock:
+            value = self._broker._access(self, name, flow)
+            return int.from_bytes(value, byteorder="little")
+
+    @contextlib.contextmanager
+    def key_file(self, name: str, flow: str) -> Iterator[str]:
+        """Writes a private copy of a key file, wiped when the context exits.
+
+        Yields:
+            The path of the key file copy.
+        """
+        with self._broker._lock:
+            value = self._broker._access(self, name, flow)
+            fd, path = tempfile.mkstemp(prefix=f"{name}_",
+                                        suffix=".der",
+                                        dir=self._broker._runtime_dir)
+            try:
+                os.write(fd, value)
+            finally:
+                os.close(fd)
+        try:
+            yield path
+        finally:
+            _wipe(path)
+
+
+def _wipe(path: str) -> None:
+    """Overwrites a file with zeros, and removes it."""
+    try:
+        size = os.path.getsize(path)
+        with open(path, "r+b") as fp:
+            fp.write(bytes(size))
+            fp.flush()
+            os.fsync(fp.fileno())
+        os.remove(path)
+    except FileNotFoundError:
+        pass
@@ -3,16 +3,73 @@
 # SPDX-License-Identifier: Apache-2.0
 """Module for loading and validating OpenTitan SKU configuration."""
 
-from collections import OrderedDict
+import os
 from dataclasses import dataclass
 
 import hjson
 
 from ca_config import CaConfig
+from key_bundle import KeyBundle
 
 _PRODUCT_IDS_HJSON = "sw/host/provisioning/orchestrator/data/products.hjson"
 _PACKAGE_IDS_HJSON = "sw/host/provisioning/orchestrator/data/packages/earlgrey_a1.hjson"
 
+# Target LC states allowed for each creator manufacturing state.
+_CREATOR_MANUF_STATE_LC_STATES = {
+    "pre_production": {"dev"},
+    "production": {"prod", "prod_end"},
+}
+
+# OWNER_SW_CFG alert handler fields a SKU may override; see
+# sw/host/provisioning/ft_lib/src/alert_cfg.rs.
+_ALERT_CFG_FIELDS = {
+    "class_en",
+    "escalation",
+    "accum_thresh",
+    "timeout_cycles",
+    "phase_cycles",
+    "digest",
+}
+
+# Fields of a mission-mode smoke test; see
+# sw/host/provisioning/ft_lib/src/smoke_test.rs.
+_SMOKE_TEST_FIELDS = {"name", "command", "pass", "fail", "timeout_ms"}
+_SMOKE_TEST_REQUIRED_FIELDS = {"name", "pass"}
+
+# Fields of a device certificate signing policy, and its serial number schemes;
+# see sw/host/provisioning/cert_lib/src/policy.rs.
+_CERT_POLICY_FIELDS = {
+    "not_before",
+    "not_after",
+    "serial_number",
+    "max_path_len",
+}
+_CERT_POLICY_SERIAL_NUMBERS = {"any", "key-id"}
+
+# Fields of each backend of the wrapped RMA unlock token escrow; see
+# sw/host/provisioning/ft_lib/src/rma_token_escrow.rs.
+_RMA_TOKEN_ESCROW_FIELDS = {
+    "file": {"dir"},
+    "sqlite": {"db"},
+    "secret_manager": {"store", "fetch"},
+}
+
+# Interfaces of the OTTF console of the provisioning firmware; see `--console`
+# of sw/host/provisioning/ft/src/main.rs.
+_CONSOLE_BACKENDS = {"spi", "dmi"}
+
+# Serial settings of the console UART; see `--console-uart-*` of
+# sw/host/provisioning/ft/src/main.rs.
+_CONSOLE_UART_FIELDS = {"baudrate", "parity", "stop_bits"}
+_CONSOLE_UART_PARITIES = {"none", "odd", "even"}
+
+# LC states a device may enter FT in; see `--entry-lc-states` of
+# sw/host/provisioning/ft/src/main.rs.
+_ENTRY_LC_STATES = (
+    {f"test_locked{i}" for i in range(7)}
+    | {f"test_unlocked{i}" for i in range(8)}
+    | {"dev", "prod", "prod_end", "rma"})
+
 
 @dataclass
 class SkuConfig:
@@ -22,14 +79,71 @@ class SkuConfig:
     si_creator: str  # valid: any SiliconCreator that exists in product database
     package: str  # valid: any package that exists in package database
     target_lc_state: str  # valid: must be in ["dev", "prod", "prod_end"]
-    dice_ca: OrderedDict  # valid: see CaConfig
-    ext_ca: OrderedDict  # valid: see CaConfig
-    token_encrypt_key: str
+    # valid: signed key bundle listing the CAs and the RMA token wrap key of
+    # the SKU, along with its detached signature and the public key it is
+    # signed with; see key_bundle.py
+    key_bundle: str
+    key_bundle_signature: str
+    key_bundle_key: str
+    # valid: None (provision the value of the SKU OTP image), or a key of
+    # _CREATOR_MANUF_STATE_LC_STATES allowing `target_lc_state`
+    creator_manuf_state: str = None
+    # valid: None, or a signed release manifest path, along with the public key
+    # it is signed with; see sw/host/provisioning/ft_lib/src/release.rs
+    release_manifest: str = None
+    release_manifest_key: str = None
+    # valid: None (provision the values of the SKU OTP image), or a dict of
+    # _ALERT_CFG_FIELDS, which must include the digests if any field is set
+    alert_cfg: dict = None
+    # valid: None, or the certificate of the RMA support team to escrow the
+    # RMA unlock tokens to; see sw/host/provisioning/ft_lib/src/rma_escrow.rs
+    rma_escrow_cert: str = None
+    # valid: None (archive the wrapped RMA unlock tokens in the device log
+    # directory), or a dict with the "backend" of _RMA_TOKEN_ESCROW_FIELDS to
+    # store them in, and its fields
+    rma_token_escrow: dict = None
+    # valid: None, or a list of console-driven smoke tests to run in mission
+    # mode after personalization, each a dict of _SMOKE_TEST_FIELDS
+    smoke_tests: list = None
+    # valid: None, or a dict of _CERT_POLICY_FIELDS the device certificates
+    # must comply with to be endorsed, with per-cert overrides in `certs`
+    cert_policy: dict = None
+    # valid: None (endorse the device certificates with the CA keys), or a
+    # directory of device certificates issued offline by the CA, as
+    # `<device_id>_<cert name>.der` files, to inject instead
+    offline_certs_dir: str = None
+    # valid: None (bind the keymgr sealing values of the personalization
+    # firmware), or a raw key file of at least 32 bytes deriving per-device
+    # sealing values; see sw/host/provisioning/ft_lib/src/keymgr_binding.rs
+    keymgr_diversification_key: str = None
+    # valid: one of _CONSOLE_BACKENDS; "dmi" for packages without an
+    # accessible SPI device, whose firmware uses the debug module console
+    console: str = "spi"
+    # valid: None, or the name of a UART to read in parallel to the console,
+    # e.g. for the ROM output
+    console_uart: str = None
+    # valid: None (keep the transport defaults), or a dict of the
+    # _CONSOLE_UART_FIELDS to set on console_uart, e.g. for interposer boards
+    # level-shifting through adapters with non-default settings
+    console_uart_config: dict = None
+    # valid: None (accept devices in any LC state), or a list of
+    # _ENTRY_LC_STATES devices may enter FT in, e.g. ["test_locked0"]; devices
+    # in another LC state fail FT as a wrong insertion
+    entry_lc_states: list = None
+    # valid: None (pass the devices FT passes), or an expression over the
+    # fields of the FT result deciding the verdict of FT, e.g.
+    # "all_steps_ok AND cert_chain_valid AND lc_state == PROD"; see
+    # sw/host/provisioning/ft_lib/src/pass_criteria.rs
+    pass_criteria: str = None
 
     def __post_init__(self):
-        # Load CA configs.
-        self.dice_ca = CaConfig(name="dice_ca", **self.dice_ca)
-        self.ext_ca = CaConfig(name="ext_ca", **self.ext_ca)
+        # Load the key bundle, and the CA configs it lists.
+        self.keys = KeyBundle.load_signed(self.key_bundle,
+                                          self.key_bundle_signature,
+                                          self.key_bundle_key)
+        self.keys.check(self.name)
+        self.dice_ca = CaConfig(name="dice_ca", **self.keys.cas["dice"])
+        self.ext_ca = CaConfig(name="ext_ca", **self.keys.cas["ext"])
 
         # Load product IDs database.
         self._product_ids = None
@@ -73,3 +187,133 @@ class SkuConfig:
             raise ValueError(
                 "Target LC state ({}) must be in [\"dev\", \"prod\", \"prod_end\"]"
                 .format(self.target_lc_state))
+        # Validate release manifest settings.
+        if (self.release_manifest is None) != (self.release_manifest_key is
+                                               None):
+            raise ValueError(
+                "Release manifest and release manifest key must be set together."
+            )
+        # Validate creator_manuf_state against target_lc_state.
+        if self.creator_manuf_state is not None:
+            if self.creator_manuf_state not in _CREATOR_MANUF_STATE_LC_STATES:
+                raise ValueError(
+                    "Creator manufacturing state ({}) must be in {}".format(
+                        self.creator_manuf_state,
+                        sorted(_CREATOR_MANUF_STATE_LC_STATES)))
+            lc_states = _CREATOR_MANUF_STATE_LC_STATES[
+                self.creator_manuf_state]
+            if self.target_lc_state not in lc_states:
+                raise ValueError(
+                    "Creator manufacturing state ({}) requires a target LC state in {}, not {}"
+                    .format(self.creator_manuf_state, sorted(lc_states),
+                            self.target_lc_state))
+        # Validate alert handler configuration overrides.
+        if self.alert_cfg is not None:
+            unknown = set(self.alert_cfg) - _ALERT_CFG_FIELDS
+            if unknown:
+                raise ValueError(
+                    "Alert configuration fields {} must be in {}".format(
+                        sorted(unknown), sorted(_ALERT_CFG_FIELDS)))
+            if self.alert_cfg and "digest" not in self.alert_cfg:
+                raise ValueError(
+                    "Alert handler configuration must include the digests.")
+        # Validate smoke tests.
+        if self.smoke_tests is not None:
+            names = set()
+            for test in self.smoke_tests:
+                unknown = set(test) - _SMOKE_TEST_FIELDS
+                if unknown:
+                    raise ValueError(
+                        "Smoke test fields {} must be in {}".format(
+                            sorted(unknown), sorted(_SMOKE_TEST_FIELDS)))
+                missing = _SMOKE_TEST_REQUIRED_FIELDS - set(test)
+                if missing:
+                    raise ValueError(
+                        "Smoke test {} must set {}".format(
+                            test.get("name"), sorted(missing)))
+                if test["name"] in names:
+                    raise ValueError("Duplicate smoke test {}".format(
+                        test["name"]))
+                names.add(test["name"])
+        # Validate the certificate signing policy.
+        if self.cert_policy is not None:
+            policies = [{
+                field: value
+                for field, value in self.cert_policy.items()
+                if field != "certs"
+            }]
+            policies += self.cert_policy.get("certs", {}).values()
+            for policy in policies:
+                unknown = set(policy) - _CERT_POLICY_FIELDS
+                if unknown:
+                    raise ValueError(
+                        "Cert policy fields {} must be in {}".format(
+                            sorted(unknown), sorted(_CERT_POLICY_FIELDS)))
+                serial_number = policy.get("serial_number", "any")
+                if serial_number not in _CERT_POLICY_SERIAL_NUMBERS:
+                    raise ValueError(
+                        "Cert policy serial number ({}) must be in {}".format(
+                            serial_number,
+                            sorted(_CERT_POLICY_SERIAL_NUMBERS)))
+        # Validate the wrapped RMA unlock token escrow.
+        if self.rma_token_escrow is not None:
+            backend = self.rma_token_escrow.get("backend")
+            if backend not in _RMA_TOKEN_ESCROW_FIELDS:
+                raise ValueError(
+                    "RMA token escrow backend ({}) must be in {}".format(
+                        backend, sorted(_RMA_TOKEN_ESCROW_FIELDS)))
+            fields = set(self.rma_token_escrow) - {"backend"}
+            if fields != _RMA_TOKEN_ESCROW_FIELDS[backend]:
+                raise ValueError(
+                    "RMA token escrow backend {} must set exactly {}".format(
+                        backend, sorted(_RMA_TOKEN_ESCROW_FIELDS[backend])))
+        # Validate the offline certificates directory.
+        if self.offline_certs_dir is not None and not os.path.isdir(
+                self.offline_certs_dir):
+            raise ValueError("Offline certs directory ({}) not found".format(
+                self.offline_certs_dir))
+        # Validate the keymgr diversification key.
+        if self.keymgr_diversification_key is not None and not os.path.isfile(
+                self.keymgr_diversification_key):
+            raise ValueError(
+                "Keymgr diversification key ({}) not found".format(
+                    self.keymgr_diversification_key))
+        # Validate the console backend.
+        if self.console not in _CONSOLE_BACKENDS:
+            raise ValueError("Console ({}) must be in {}".format(
+                self.console, sorted(_CONSOLE_BACKENDS)))
+        # Validate the console UART serial settings.
+        if self.console_uart_config is not None:
+            if self.console_uart is None:
+                raise ValueError("Console UART config requires a console UART")
+            unknown = set(self.console_uart_config) - _CONSOLE_UART_FIELDS
+            if unknown:
+                raise ValueError(
+                    "Console UART config fields ({}) must be in {}".format(
+                        sorted(unknown), sorted(_CONSOLE_UART_FIELDS)))
+            baudrate = self.console_uart_config.get("baudrate")
+            if baudrate is not None and (not isinstance(baudrate, int) or
+                                         baudrate <= 0):
+                raise ValueError(
+                    "Console UART baudrate ({}) must be a positive integer".
+                    format(baudrate))
+            parity = self.console_uart_config.get("parity")
+            if parity is not None and parity not in _CONSOLE_UART_PARITIES:
+                raise ValueError(
+                    "Console UART parity ({}) must be in {}".format(
+                        parity, sorted(_CONSOLE_UART_PARITIES)))
+            stop_bits = self.console_uart_config.get("stop_bits")
+            if stop_bits is not None and stop_bits not in (1, 2):
+                raise ValueError(
+                    "Console UART stop bits ({}) must be 1 or 2".format(
+                        stop_bits))
+        # Validate the entry LC states.
+        if self.entry_lc_states is not None:
+            unknown = set(self.entry_lc_states) - _ENTRY_LC_STATES
+            if not self.entry_lc_states or unknown:
+                raise ValueError(
+                    "Entry LC states ({}) must be a non-empty list of {}".
+                    format(sorted(unknown), sorted(_ENTRY_LC_STATES)))
+        # Validate the pass criteria; their syntax is checked by FT.
+        if self.pass_criteria is not None and not self.pass_criteria.strip():
+            raise ValueError("Pass criteria must not be empty.")
@@ -0,0 +1,179 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Durable record of the provisioning step each device is in.
+
+The orchestrator records the step ("cp" or "ft") of a device in its log
+directory before running it, and the FT host binary records its own sub-steps
+(see sw/host/provisioning/ft_lib/src/step_state.rs) before each irreversible
+action. States are replaced atomically, so after a station power loss they
+tell exactly where each in-flight device stopped.
+"""
+
+import json
+import logging
+import os
+import socket
+import time
+from dataclasses import asdict, dataclass
+from typing import Optional
+
+# Step state files, in the log directory of a device.
+STEP_STATE_FILE = "step_state.json"
+FT_STEP_STATE_FILE = "ft_step_state.json"
+
+
+@dataclass
+class StepState(object):
+    """The provisioning step a device is in."""
+    device_id: str
+    step: str
+    started: int
+    updated: int
+    completed: bool = False
+    host: str = ""
+    pid: int = 0
+    # Outcome of the completed flows; unknown for runs recorded before the
+    # outcome was.
+    passed: Optional[bool] = None
+
+
+def write_atomic(path: str, doc: dict) -> None:
+    """Replaces the JSON file at `path`, surviving a power loss."""
+    tmp = f"{path}.tmp"
+    with open(tmp, "w") as fp:
+        json.dump(doc, fp, indent=2)
+        fp.flush()
+        os.fsync(fp.fileno())
+    os.replace(tmp, path)
+    # Directories can't be opened on Windows, whose file system journals the
+    # rename itself.
+    if os.name == "nt":
+        return
+    fd = os.open(os.path.dirname(path) or ".", os.O_RDONLY)
+    try:
+        os.fsync(fd)
+    finally:
+        os.close(fd)
+
+
+class StepJournal(object):
+    """Persists the step state of a device to its log directory."""
+
+    def __init__(self, log_dir: str, device_id: str):
+        self.path = os.path.join(log_dir, STEP_STATE_FILE)
+        now = int(time.time())
+        self.state = StepState(device_id=device_id,
+                               step="",
+                               started=now,
+                               updated=now,
+                               host=socket.gethostname(),
+                               pid=os.getpid())
+
+    def enter(self, step: str) -> None:
+        """Durably records the device enters `step`."""
+        self.state.step = step
+        self.state.updated = int(time.time())
+        write_atomic(self.path, asdict(self.state))
+
+    def complete(self, passed: bool) -> None:
+        """Durably records the flows of the device completed."""
+        self.state.completed = True
+        self.state.passed = passed
+        self.state.updated = int(time.time())
+        write_atomic(self.path, asdict(self.state))
+
+
+def _is_running_windows(pid: int) -> bool:
+    """Returns whether the process `pid` is running, on Windows.
+
+    `os.kill` terminates the process on Windows whatever the signal, so the
+    process is looked up with the Win32 API instead.
+    """
+    import ctypes
+    PROCESS_QUERY_LIMITED_INFORMATION = 0x1000
+    STILL_ACTIVE = 259
+    ERROR_ACCESS_DENIED = 5
+    kernel32 = ctypes.windll.kernel32
+    handle = kernel32.OpenProcess(PROCESS_QUERY_LIMITED_INFORMATION, False,
+                                  pid)
+    if not handle:
+        # Access is denied to the processes of other users, which exist.
+        return ctypes.GetLastError() == ERROR_ACCESS_DENIED
+    try:
+        code = ctypes.c_ulong()
+        if not kernel32.GetExitCodeProcess(handle, ctypes.byref(code)):
+            return True
+        return code.value == STILL_ACTIVE
+    finally:
+        kernel32.CloseHandle(handle)
+
+
+def _is_running(state: StepState) -> bool:
+    """Returns whether the process which recorded `state` is still running."""
+    if state.host != socket.gethostname() or not state.pid:
+        return False
+    if os.name == "nt":
+        return _is_running_windows(state.pid)
+    try:
+        os.kill(state.pid, 0)
+    except ProcessLookupError:
+        return False
+    except PermissionError:
+        pass
+    return True
+
+
+def interrupted_devices(logs_root_dir: str) -> [dict]:
+    """Finds the devices whose flows stopped before completion.
+
+    Devices whose flows are still running in another process of this host are
+    not reported.
+
+    Returns:
+        One dict per device, with the step state of the orchestrator and the
+        FT sub-step state, if any, and the log directory of the device.
+    """
+    interrupted = []
+    if not os.path.isdir(logs_root_dir):
+        return interrupted
+    for entry in sorted(os.scandir(logs_root_dir), key=lambda e: e.name):
+        path = os.path.join(entry.path, STEP_STATE_FILE)
+        if not entry.is_dir() or not os.path.exists(path):
+            continue
+        with open(path, "r") as fp:
+            state = StepState(**json.load(fp))
+        if state.completed or _is_running(state):
+            continue
+        ft_state = None
+        ft_path = os.path.join(entry.path, FT_STEP_STATE_FILE)
+        if state.step == "ft" and os.path.exists(ft_path):
+            with open(ft_path, "r") as fp:
+                ft_state = json.load(fp)
+        interrupted.append({
+            "log_dir": entry.path,
+            "state": state,
+            "ft_state": ft_state,
+        })
+    return interrupted
+
+
+def report_interrupted_devices(logs_root_dir: str) -> int:
+    """Logs where each device interrupted under `logs_root_dir` stopped.
+
+    Returns:
+        The number of interrupted devices.
+    """
+    interrupted = interrupted_devices(logs_root_dir)
+    for device in interrupted:
+        state = device["state"]
+        where = state.step or "before CP"
+        ft_state = device["ft_state"]
+        if ft_state is not None and not ft_state.get("completed"):
+            where += f" ({ft_state['step']}/{ft_state['sub_step']})"
+        stopped = time.strftime("%Y-%m-%d %H:%M:%S",
+                                time.localtime(state.updated))
+        logging.warning(
+            f"Device {state.device_id} stopped in step {where}, entered at "
+            f"{stopped}; logs: {device['log_dir']}")
+    return len(interrupted)
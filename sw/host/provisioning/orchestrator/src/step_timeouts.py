@@ -0,0 +1,93 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Per-step timeouts learned from the run history of a SKU.
+
+The duration of every provisioning step is recorded in the results database.
+The timeout of a step is a multiple of the P99 duration of its most recent
+passing runs, so devices hanging in a step are flagged quickly, while the
+legitimate variation of e.g. certificate generation stays well within the
+timeout. Steps without enough history run without a timeout.
+"""
+
+import math
+import time
+from dataclasses import dataclass
+from typing import Dict, Optional
+
+from db import DB, StepDurationRecord
+
+
+@dataclass
+class StepTimeoutConfig:
+    """Configuration of the adaptive step timeouts.
+
+    Attributes:
+        multiplier: Timeout of a step, as a multiple of its P99 duration.
+        min_samples: Number of passing runs of a step required before its
+          timeout is enforced.
+        history: Number of most recent passing runs the P99 is computed on.
+        min_timeout: Lower bound of the timeouts, in seconds.
+    """
+    multiplier: float = 3.0
+    min_samples: int = 20
+    history: int = 200
+    min_timeout: float = 60.0
+
+    def __post_init__(self):
+        if self.multiplier < 1.0:
+            raise ValueError("Step timeout multiplier must be at least 1.")
+        if self.min_samples < 1 or self.history < self.min_samples:
+            raise ValueError(
+                "Step timeout history must cover at least min_samples runs.")
+
+
+def percentile(values: [float], q: float) -> float:
+    """Returns the nearest-rank `q` percentile (0 - 100) of `values`."""
+    if not values:
+        raise ValueError("Percentile of no values.")
+    ordered = sorted(values)
+    rank = max(1, math.ceil(q / 100 * len(ordered)))
+    return ordered[rank - 1]
+
+
+def step_timeout(db: DB, sku: str, step: str,
+                 config: StepTimeoutConfig) -> Optional[float]:
+    """Returns the timeout of a step in seconds, or None if the step does not
+    have enough history yet."""
+    durations = StepDurationRecord.query_passed_durations(
+        db, sku, step, config.history)
+    if len(durations) < config.min_samples:
+        return None
+    p99 = percentile(durations, 99) / 1000
+    return max(config.min_timeout, config.multiplier * p99)
+
+
+def step_timeouts(db: DB, sku: str, steps: [str],
+                  config: StepTimeoutConfig) -> Dict[str, float]:
+    """Returns the timeouts of the `steps` having enough history."""
+    timeouts = {}
+    for step in steps:
+        timeout = step_timeout(db, sku, step, config)
+        if timeout is not None:
+            timeouts[step] = timeout
+    return timeouts
+
+
+def record_step_duration(db: DB, device_id: str, sku: str, step: str,
+                         duration: float, passed: bool) -> StepDurationRecord:
+    """Records the `duration` in seconds of a step of a provisioning run.
+
+    Returns:
+        The recorded step duration.
+    """
+    record = StepDurationRecord(
+        device_id=device_id,
+        sku=sku,
+        step=step,
+        duration_ms=int(duration * 1000),
+        timestamp=int(time.time()),
+        passed=int(passed),
+    )
+    record.insert(db)
+    return record
@@ -0,0 +1,139 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Module for loading and enforcing tenant (customer profile) configuration."""
+
+import getpass
+import json
+import os
+import re
+import time
+from dataclasses import dataclass
+from pathlib import Path
+
+from sku_config import SkuConfig
+
+# Name of the file claiming an output directory for a tenant.
+_TENANT_MARKER = ".tenant"
+
+
+class TenantViolation(Exception):
+    """Raised when an operation crosses a tenant boundary."""
+
+
+@dataclass
+class TenantConfig:
+    """Class for storing a tenant configuration.
+
+    A tenant is a customer profile a contract manufacturer provisions parts
+    for. All keys, SKUs and outputs used by a provisioning run are scoped to
+    a single tenant.
+    """
+    name: str  # valid: lower snake case only
+    skus: list  # valid: names of the SKUs this tenant may provision
+    key_dirs: list  # valid: directories all raw keys and certificates live in
+    output_dir: str  # valid: root directory of all tenant outputs
+    token_keys: list = None  # valid: token IDs of HSM-backed CA keys
+
+    def __post_init__(self):
+        self.key_dirs = [Path(d).resolve() for d in self.key_dirs]
+        self.output_dir = Path(self.output_dir).resolve()
+        if self.token_keys is None:
+            self.token_keys = []
+        self.validate()
+
+    def validate(self) -> None:
+        """Validates this object's attributes."""
+        if not re.fullmatch(r"[a-z][a-z0-9_]*", self.name):
+            raise ValueError(
+                f"Tenant name ({self.name}) must be lower snake case.")
+        if not self.skus:
+            raise ValueError(f"Tenant {self.name} must list at least one SKU.")
+        if not self.key_dirs:
+            raise ValueError(
+                f"Tenant {self.name} must list at least one key directory.")
+        for key_dir in self.key_dirs:
+            if self._within(key_dir, self.output_dir):
+                raise ValueError(
+                    f"Tenant {self.name} key directory ({key_dir}) must not "
+                    "be inside its output directory.")
+
+    @property
+    def log_dir(self) -> str:
+        return str(self.output_dir / "logs")
+
+    @property
+    def db_path(self) -> str:
+        return str(self.output_dir / "provisioning.db")
+
+    @property
+    def audit_log(self) -> str:
+        return str(self.output_dir / "audit.log.jsonl")
+
+    @staticmethod
+    def _within(path: Path, root: Path) -> bool:
+        return path == root or root in path.parents
+
+    def _check_key_path(self, what: str, path) -> None:
+        resolved = Path(path).resolve()
+        if not any(self._within(resolved, d) for d in self.key_dirs):
+            raise TenantViolation(
+                f"{what} ({path}) is outside the key directories of tenant "
+                f"{self.name}.")
+
+    def check_sku(self, sku_config: SkuConfig) -> None:
+        """Checks a SKU, and all keys it references, belong to this tenant."""
+        if sku_config.name not in self.skus:
+            raise TenantViolation(
+                f"SKU {sku_config.name} is not provisioned for tenant "
+                f"{self.name}.")
+        self._check_key_path("Key bundle", sku_config.key_bundle)
+        self._check_key_path("Key bundle signature",
+                             sku_config.key_bundle_signature)
+        self._check_key_path("Key bundle key", sku_config.key_bundle_key)
+        for ca in [sku_config.dice_ca, sku_config.ext_ca]:
+            if ca.key_type == "Raw":
+                self._check_key_path(f"{ca.name} key", ca.key)
+            elif ca.key not in self.token_keys:
+                raise TenantViolation(
+                    f"{ca.name} token key ({ca.key}) is not a key of tenant "
+                    f"{self.name}.")
+        if sku_config.rma_escrow_cert is not None:
+            self._check_key_path("RMA escrow certificate",
+                                 sku_config.rma_escrow_cert)
+
+    def check_output_path(self, path: str) -> None:
+        """Checks an output path lives inside this tenant's output directory."""
+        if not self._within(Path(path).resolve(), self.output_dir):
+            raise TenantViolation(
+                f"Output path ({path}) is outside the output directory of "
+                f"tenant {self.name}.")
+
+    def claim_output_dir(self) -> None:
+        """Claims the output directory, refusing one claimed by another tenant."""
+        os.makedirs(self.output_dir, exist_ok=True)
+        marker = self.output_dir / _TENANT_MARKER
+        try:
+            # Atomically claim the directory if it is not claimed yet.
+            with open(marker, "x") as fp:
+                fp.write(self.name)
+            return
+        except FileExistsError:
+            pass
+        owner = marker.read_text().strip()
+        if owner != self.name:
+            raise TenantViolation(
+                f"Output directory ({self.output_dir}) belongs to tenant "
+                f"{owner}, not {self.name}.")
+
+    def audit(self, event: str, **details) -> None:
+        """Appends an event to this tenant's audit log."""
+        entry = {
+            "timestamp": time.time(),
+            "tenant": self.name,
+            "operator": getpass.getuser(),
+            "event": event,
+        }
+        entry.update(details)
+        with open(self.audit_log, "a") as fp:
+            fp.write(json.dumps(entry, sort_keys=True) + "\n")
@@ -0,0 +1,134 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Tracking of the LC transition tokens consumed per production lot.
+
+Each provisioning run records the token generation and lot of the device, and
+IDs of the test unlock / exit tokens it was provisioned with. The per-lot
+summaries exported from these records are used to plan the rotation of tokens
+across production lots.
+"""
+
+import argparse
+import hashlib
+import json
+import sys
+import time
+
+from db import DB, DBConfig, TokenUsageRecord
+from device_id import DeviceIdentificationNumber
+
+# Number of hex digits of a token ID.
+_TOKEN_ID_LEN = 16
+
+
+def token_id(token: int) -> str:
+    """Returns the ID of a 128-bit LC token.
+
+    The ID is a truncated SHA256 digest of the token, so tokens can be told
+    apart in exports without revealing them.
+    """
+    digest = hashlib.sha256(token.to_bytes(16, byteorder="little"))
+    return digest.hexdigest()[:_TOKEN_ID_LEN]
+
+
+def lot_name(din: DeviceIdentificationNumber) -> str:
+    """Returns the name of the production lot of a device, e.g. Y4W07L123."""
+    return f"Y{din.year}W{din.week:02d}L{din.lot:03d}"
+
+
+def record_token_usage(db: DB, device_id: str, sku: str,
+                       din: DeviceIdentificationNumber, token_generation: str,
+                       test_unlock_token: int, test_exit_token: int,
+                       passed: bool) -> TokenUsageRecord:
+    """Records the tokens consumed by a provisioning run.
+
+    Returns:
+        The recorded token usage.
+    """
+    record = TokenUsageRecord(
+        device_id=device_id,
+        sku=sku,
+        lot=lot_name(din),
+        token_generation=token_generation,
+        test_unlock_token_id=token_id(test_unlock_token),
+        test_exit_token_id=token_id(test_exit_token),
+        timestamp=int(time.time()),
+        passed=int(passed),
+    )
+    record.insert(db)
+    return record
+
+
+def summarize(records: [TokenUsageRecord]) -> list:
+    """Summarizes token usage per lot.
+
+    Returns:
+        One summary per (SKU, lot), in order of first use, listing the tokens
+        consumed in the lot.
+    """
+    lots = {}
+    for r in records:
+        lot = lots.setdefault((r.sku, r.lot), {
+            "sku": r.sku,
+            "lot": r.lot,
+            "first_use": r.timestamp,
+            "last_use": r.timestamp,
+            "tokens": {},
+        })
+        lot["first_use"] = min(lot["first_use"], r.timestamp)
+        lot["last_use"] = max(lot["last_use"], r.timestamp)
+        key = (r.token_generation, r.test_unlock_token_id,
+               r.test_exit_token_id)
+        tokens = lot["tokens"].setdefault(
+            key, {
+                "token_generation": r.token_generation,
+                "test_unlock_token_id": r.test_unlock_token_id,
+                "test_exit_token_id": r.test_exit_token_id,
+                "runs": 0,
+                "passed": 0,
+                "devices": set(),
+            })
+        tokens["runs"] += 1
+        tokens["passed"] += r.passed
+        tokens["devices"].add(r.device_id)
+    summaries = []
+    for lot in lots.values():
+        tokens = list(lot["tokens"].values())
+        for t in tokens:
+            t["devices"] = len(t["devices"])
+        lot["tokens"] = tokens
+        lot["token_generations"] = sorted(
+            {t["token_generation"]
+             for t in tokens})
+        summaries.append(lot)
+    return summaries
+
+
+def main(args_in):
+    parser = argparse.ArgumentParser(
+        description="""Exports the LC transition tokens consumed per lot,
+        as recorded by the orchestrator in a provisioning database.""")
+    parser.add_argument(
+        "--db-path",
+        required=True,
+        help="SQLite database the orchestrator recorded results into.",
+    )
+    parser.add_argument(
+        "--output",
+        help="JSON file to export the per-lot summaries to (default: stdout).",
+    )
+    args = parser.parse_args(args_in)
+
+    db = DB(DBConfig(db_path=args.db_path))
+    TokenUsageRecord.create_table(db)
+    doc = json.dumps(summarize(TokenUsageRecord.query_all(db)), indent=2)
+    if args.output:
+        with open(args.output, "w") as fp:
+            fp.write(doc + "\n")
+    else:
+        print(doc)
+
+
+if __name__ == "__main__":
+    main(sys.argv[1:])
@@ -5,6 +5,13 @@
 import logging
 import shlex
 import subprocess
+import sys
+import threading
+import time
+
+# Interval at which `run` checks whether its command was cancelled or timed
+# out.
+_POLL_INTERVAL = 0.1
 
 
 def parse_hexstring_to_int(x):
@@ -33,17 +40,70 @@ def confirm():
         exit(1)
 
 
-def run(cmd, stdout_logfile, stderr_logfile):
-    out_tee = subprocess.Popen(['/usr/bin/tee', stdout_logfile],
-                               stdin=subprocess.PIPE)
-    err_tee = subprocess.Popen(['/usr/bin/tee', stderr_logfile],
-                               stdin=subprocess.PIPE)
+def _tee(src, logfile, dst):
+    """Copies the lines of `src` to the file `logfile` and to `dst`.
+
+    Done in-process rather than with `tee`, which Windows stations lack.
+    """
+    with open(logfile, "w") as log:
+        for line in src:
+            log.write(line)
+            log.flush()
+            dst.write(line)
+            dst.flush()
+    src.close()
+
+
+def run(cmd,
+        stdout_logfile,
+        stderr_logfile,
+        echo=True,
+        cancel=None,
+        timeout=None):
+    """Runs `cmd`, logging its stdout and stderr to files.
+
+    Args:
+        echo: Also print the output of `cmd` on the console.
+        cancel: threading.Event terminating `cmd` when set.
+        timeout: Seconds after which `cmd` is terminated.
 
+    Raises:
+        subprocess.TimeoutExpired: if `cmd` was terminated after `timeout`.
+    """
     cmd_list = shlex.split(cmd)
-    res = subprocess.run(cmd_list,
-                         text=True,
-                         stdout=out_tee.stdin,
-                         stderr=err_tee.stdin)
-    out_tee.stdin.close()
-    err_tee.stdin.close()
-    return res
+    if echo:
+        proc = subprocess.Popen(cmd_list,
+                                text=True,
+                                stdout=subprocess.PIPE,
+                                stderr=subprocess.PIPE)
+        streams = ((proc.stdout, stdout_logfile, sys.stdout),
+                   (proc.stderr, stderr_logfile, sys.stderr))
+        tees = [threading.Thread(target=_tee, args=s) for s in streams]
+        for tee in tees:
+            tee.start()
+    else:
+        with open(stdout_logfile, "w") as out, open(stderr_logfile,
+                                                    "w") as err:
+            proc = subprocess.Popen(cmd_list,
+                                    text=True,
+                                    stdout=out,
+                                    stderr=err)
+        tees = []
+    deadline = None if timeout is None else time.monotonic() + timeout
+    timed_out = False
+    while (cancel is not None or deadline is not None) and proc.poll() is None:
+        if deadline is not None and time.monotonic() >= deadline:
+            timed_out = True
+            proc.terminate()
+            break
+        if cancel is None:
+            time.sleep(_POLL_INTERVAL)
+        elif cancel.wait(_POLL_INTERVAL):
+            proc.terminate()
+            break
+    proc.wait()
+    for tee in tees:
+        tee.join()
+    if timed_out:
+        raise subprocess.TimeoutExpired(cmd_list, timeout)
+    return subprocess.CompletedProcess(cmd_list, proc.returncode)
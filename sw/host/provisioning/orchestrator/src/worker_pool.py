@@ -0,0 +1,259 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Worker pool running provisioning transport I/O off the coordinator thread.
+
+Each job runs on a dedicated worker thread and reports to the coordinator
+through messages only. The coordinator is the single thread emitting log
+records and collecting results, so the log of a high-parallelism run stays
+ordered per device. Submitting blocks while the job queue is full, and each job
+can be cancelled individually.
+"""
+
+import collections
+import enum
+import logging
+import queue
+import threading
+import time
+from dataclasses import dataclass
+from typing import Any, Callable, Dict, Iterator, Optional
+
+# Poll interval of the coordinator while it waits on a full job queue.
+_SUBMIT_POLL_INTERVAL = 0.1
+
+
+class JobStatus(enum.Enum):
+    DONE = "done"
+    FAILED = "failed"
+    CANCELLED = "cancelled"
+
+
+class Cancelled(Exception):
+    """Raised in a job when its cancellation was requested."""
+
+
+@dataclass
+class _Message:
+    """Message posted by a worker to the coordinator."""
+    job_id: str
+    level: int = logging.INFO
+    text: str = ""
+    # Set on the final message of a job.
+    status: Optional[JobStatus] = None
+    result: Any = None
+
+
+@dataclass
+class JobResult:
+    """Outcome of a job.
+
+    Attributes:
+        job_id: ID the job was submitted with.
+        status: Whether the job completed, raised or was cancelled.
+        result: Value returned by the job, if it completed.
+        error: Description of the exception raised by the job, if it failed.
+    """
+    job_id: str
+    status: JobStatus
+    result: Any = None
+    error: str = ""
+
+
+class JobContext:
+    """Handle passed to a job for reporting progress and observing cancellation.
+
+    The logging methods mirror those of the `logging` module, so a job context
+    can be used wherever a logger is expected.
+    """
+
+    def __init__(self, job_id: str, events: queue.Queue,
+                 cancel_event: threading.Event):
+        self.job_id = job_id
+        self.cancel_event = cancel_event
+        self._events = events
+
+    def log(self, level: int, text: str) -> None:
+        self._events.put(_Message(self.job_id, level=level, text=text))
+
+    def debug(self, text: str) -> None:
+        self.log(logging.DEBUG, text)
+
+    def info(self, text: str) -> None:
+        self.log(logging.INFO, text)
+
+    def warning(self, text: str) -> None:
+        self.log(logging.WARNING, text)
+
+    def error(self, text: str) -> None:
+        self.log(logging.ERROR, text)
+
+    @property
+    def cancelled(self) -> bool:
+        return self.cancel_event.is_set()
+
+    def check_cancelled(self) -> None:
+        """Raises `Cancelled` if the cancellation of the job was requested."""
+        if self.cancelled:
+            raise Cancelled(f"Job {self.job_id} cancelled.")
+
+
+class WorkerPool:
+    """Pool of worker threads running jobs on behalf of a coordinator thread.
+
+    The thread creating the pool is the coordinator: all other methods must be
+    called from it. Log records of the jobs are emitted, prefixed with the job
+    ID, while the coordinator submits jobs or waits for their results.
+    """
+
+    def __init__(self, num_workers: int = 1, max_pending: int = None):
+        """Starts `num_workers` workers.
+
+        Args:
+            num_workers: Number of jobs running concurrently.
+            max_pending: Number of submitted jobs waiting for a worker before
+              `submit` blocks (default: `num_workers`).
+        """
+        if num_workers < 1:
+            raise ValueError("Worker pool requires at least one worker.")
+        self._jobs = queue.Queue(maxsize=max_pending or num_workers)
+        self._events = queue.Queue()
+        self._cancel_events: Dict[str, threading.Event] = {}
+        self._pending = set()
+        # Results of the jobs that finished while the coordinator submitted.
+        self._finished = collections.deque()
+        self._workers = [
+            threading.Thread(target=self._work,
+                             name=f"provisioning-worker-{i}",
+                             daemon=True) for i in range(num_workers)
+        ]
+        for worker in self._workers:
+            worker.start()
+
+    def __enter__(self) -> "WorkerPool":
+        return self
+
+    def __exit__(self, exc_type, exc_value, traceback) -> None:
+        # Do not leave transports running when the coordinator bails out, e.g.
+        # on a KeyboardInterrupt.
+        if exc_type is not None:
+            self.cancel_all()
+        self.close()
+
+    def submit(self,
+               job_id: str,
+               job: Callable[[JobContext], Any],
+               timeout: float = None) -> None:
+        """Queues `job` to be called with its `JobContext` on a worker.
+
+        Blocks while the job queue is full, emitting the log records of the
+        running jobs in the meantime.
+
+        Raises:
+            ValueError: if a job with the same ID was already submitted.
+            queue.Full: if the job could not be queued within `timeout` seconds.
+        """
+        if job_id in self._cancel_events:
+            raise ValueError(f"Job {job_id} already submitted.")
+        cancel_event = threading.Event()
+        deadline = None if timeout is None else time.monotonic() + timeout
+        while True:
+            try:
+                self._jobs.put((job_id, job, cancel_event),
+                               timeout=_SUBMIT_POLL_INTERVAL)
+                break
+            except queue.Full:
+                self._drain()
+                if deadline is not None and time.monotonic() >= deadline:
+                    raise
+        self._cancel_events[job_id] = cancel_event
+        self._pending.add(job_id)
+
+    def cancel(self, job_id: str) -> None:
+        """Requests the cancellation of a job.
+
+        A queued job is dropped, and a running job is expected to return once
+        it observes its cancellation.
+        """
+        self._cancel_events[job_id].set()
+
+    def cancel_all(self) -> None:
+        for cancel_event in self._cancel_events.values():
+            cancel_event.set()
+
+    def results(self, timeout: float = None) -> Iterator[JobResult]:
+        """Yields the results of the submitted jobs, as they finish.
+
+        Raises:
+            TimeoutError: if no job reported within `timeout` seconds.
+        """
+        while self._finished or self._pending:
+            if self._finished:
+                yield self._finished.popleft()
+                continue
+            result = self._handle(self._next_message(timeout))
+            if result is not None:
+                yield result
+
+    def close(self) -> None:
+        """Waits for the submitted jobs to finish, and stops the workers."""
+        for result in self.results():
+            pass
+        for _ in self._workers:
+            self._jobs.put(None)
+        for worker in self._workers:
+            worker.join()
+
+    def _next_message(self, timeout: float = None) -> _Message:
+        try:
+            return self._events.get(timeout=timeout)
+        except queue.Empty:
+            raise TimeoutError(
+                f"No job reported within {timeout} seconds.") from None
+
+    def _drain(self) -> None:
+        while True:
+            try:
+                message = self._events.get_nowait()
+            except queue.Empty:
+                return
+            result = self._handle(message)
+            if result is not None:
+                self._finished.append(result)
+
+    def _handle(self, message: _Message) -> Optional[JobResult]:
+        """Emits the log record of `message`, and returns the job result if
+        `message` is the final message of a job."""
+        if message.status is None:
+            logging.log(message.level, f"[{message.job_id}] {message.text}")
+            return None
+        self._pending.discard(message.job_id)
+        if message.status == JobStatus.FAILED:
+            logging.error(f"[{message.job_id}] job failed: {message.text}")
+        elif message.status == JobStatus.CANCELLED:
+            logging.warning(f"[{message.job_id}] job cancelled.")
+        return JobResult(message.job_id, message.status, message.result,
+                         message.text)
+
+    def _work(self) -> None:
+        while True:
+            item = self._jobs.get()
+            if item is None:
+                return
+            job_id, job, cancel_event = item
+            if cancel_event.is_set():
+                self._events.put(
+                    _Message(job_id, status=JobStatus.CANCELLED))
+                continue
+            try:
+                result = job(JobContext(job_id, self._events, cancel_event))
+                message = _Message(job_id,
+                                   status=JobStatus.DONE,
+                                   result=result)
+            except Cancelled:
+                message = _Message(job_id, status=JobStatus.CANCELLED)
+            except Exception as e:
+                message = _Message(job_id,
+                                   text=f"{type(e).__name__}: {e}",
+                                   status=JobStatus.FAILED)
+            self._events.put(message)
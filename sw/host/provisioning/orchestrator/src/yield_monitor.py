@@ -0,0 +1,178 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Rolling provisioning yield tracking with operator alarms."""
+
+import json
+import logging
+import os
+import smtplib
+import sys
+import urllib.request
+from dataclasses import dataclass, field
+from email.message import EmailMessage
+from typing import List, Optional
+
+ALARM_ACTIONS = ["beep", "webhook", "email"]
+
+
+@dataclass
+class YieldAlarmConfig:
+    """Yield alarm configuration.
+
+    Attributes:
+        window: Number of most recent devices the failure rate is computed on.
+        min_devices: Minimum number of devices in the window before the alarm
+          may trigger.
+        threshold: Failure rate (0.0 - 1.0) at which the alarm triggers.
+        actions: Alarm actions to take, see `ALARM_ACTIONS`.
+        webhook_url: URL to POST a JSON alarm notification to.
+        email_to: E-mail address to send alarm notifications to.
+        email_from: Sender address of alarm notification e-mails.
+        smtp_host: SMTP server used to send alarm notification e-mails.
+    """
+    window: int = 20
+    min_devices: int = 5
+    threshold: float = 0.4
+    actions: List[str] = field(default_factory=lambda: ["beep"])
+    webhook_url: Optional[str] = None
+    email_to: Optional[str] = None
+    email_from: str = "provisioning-orchestrator@localhost"
+    smtp_host: str = "localhost"
+
+    def __post_init__(self):
+        if self.window < 1:
+            raise ValueError("Yield window must contain at least one device.")
+        if not 1 <= self.min_devices <= self.window:
+            raise ValueError(
+                f"Yield min devices must be within [1, {self.window}].")
+        if not 0.0 < self.threshold <= 1.0:
+            raise ValueError("Yield threshold must be within (0.0, 1.0].")
+        for action in self.actions:
+            if action not in ALARM_ACTIONS:
+                raise ValueError(f"Unknown yield alarm action: {action}.")
+        if "webhook" in self.actions and not self.webhook_url:
+            raise ValueError("The webhook alarm action requires a URL.")
+        if "email" in self.actions and not self.email_to:
+            raise ValueError("The email alarm action requires an address.")
+
+
+class YieldMonitor:
+    """Tracks the rolling failure rate of provisioned devices.
+
+    The outcome of each device is persisted to `state_file`, so the failure
+    rate is tracked across orchestrator invocations (one per device).
+
+    The alarm triggers once when the failure rate crosses the configured
+    threshold, and is re-armed once the failure rate drops below it again.
+    """
+
+    def __init__(self, config: YieldAlarmConfig, state_file: str,
+                 sku: str) -> None:
+        self.config = config
+        self.state_file = state_file
+        self.sku = sku
+        self.outcomes = []
+        self.alarm_active = False
+        if os.path.exists(state_file):
+            with open(state_file, "r") as fp:
+                state = json.load(fp)
+            self.outcomes = state.get("outcomes", [])[-config.window:]
+            self.alarm_active = state.get("alarm_active", False)
+
+    def _save(self) -> None:
+        state = {"outcomes": self.outcomes, "alarm_active": self.alarm_active}
+        tmp_file = f"{self.state_file}.tmp"
+        with open(tmp_file, "w") as fp:
+            json.dump(state, fp, indent=2)
+        os.replace(tmp_file, self.state_file)
+
+    @property
+    def failures(self) -> int:
+        return sum(1 for o in self.outcomes if not o["passed"])
+
+    @property
+    def failure_rate(self) -> float:
+        if not self.outcomes:
+            return 0.0
+        return self.failures / len(self.outcomes)
+
+    def record(self, device_id: str, passed: bool) -> bool:
+        """Records a device outcome.
+
+        Returns:
+            True if the alarm was triggered by this outcome.
+        """
+        self.outcomes.append({"device_id": device_id, "passed": passed})
+        self.outcomes = self.outcomes[-self.config.window:]
+        logging.info(
+            f"Yield: {len(self.outcomes) - self.failures}/{len(self.outcomes)} "
+            f"devices passed (failure rate {self.failure_rate:.0%}).")
+
+        triggered = False
+        over_threshold = (len(self.outcomes) >= self.config.min_devices and
+                          self.failure_rate >= self.config.threshold)
+        if over_threshold and not self.alarm_active:
+            self.alarm_active = True
+            triggered = True
+        elif not over_threshold:
+            self.alarm_active = False
+        self._save()
+
+        if triggered:
+            self._alarm()
+        return triggered
+
+    def _message(self) -> str:
+        failed = [o["device_id"] for o in self.outcomes if not o["passed"]]
+        return (f"Provisioning yield alarm for SKU {self.sku}: "
+                f"{self.failures} of the last {len(self.outcomes)} devices "
+                f"failed (failure rate {self.failure_rate:.0%}, threshold "
+                f"{self.config.threshold:.0%}). Failed devices: "
+                f"{', '.join(failed)}.")
+
+    def _alarm(self) -> None:
+        message = self._message()
+        logging.error(message)
+        for action in self.config.actions:
+            try:
+                if action == "beep":
+                    self._beep()
+                elif action == "webhook":
+                    self._webhook(message)
+                elif action == "email":
+                    self._email(message)
+            except Exception as e:
+                # A failing notification channel must not abort provisioning.
+                logging.error(f"Yield alarm action {action} failed: {e}")
+
+    def _beep(self) -> None:
+        sys.stdout.write("\a" * 3)
+        sys.stdout.flush()
+
+    def _webhook(self, message: str) -> None:
+        payload = {
+            "sku": self.sku,
+            "message": message,
+            "devices": len(self.outcomes),
+            "failures": self.failures,
+            "failure_rate": self.failure_rate,
+            "threshold": self.config.threshold,
+        }
+        req = urllib.request.Request(
+            self.config.webhook_url,
+            data=json.dumps(payload).encode("utf-8"),
+            headers={"Content-Type": "application/json"},
+            method="POST",
+        )
+        with urllib.request.urlopen(req, timeout=10):
+            pass
+
+    def _email(self, message: str) -> None:
+        msg = EmailMessage()
+        msg["Subject"] = f"Provisioning yield alarm: {self.sku}"
+        msg["From"] = self.config.email_from
+        msg["To"] = self.config.email_to
+        msg.set_content(message)
+        with smtplib.SMTP(self.config.smtp_host, timeout=10) as smtp:
+            smtp.send_message(msg)
@@ -0,0 +1,132 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Unittests for ab_qualification.py module."""
+
+import json
+import os
+import tempfile
+import unittest
+
+from ab_qualification import (AB_RESULTS_FILE, AbQualification, compare,
+                              load_records, proportions_p_value,
+                              welch_p_value)
+
+
+def ft_result(device_id, passed=True, perso_us=1000000, certs=4,
+              error_kind=None):
+    return {
+        "schema_version": 3,
+        "device_id": device_id,
+        "passed": passed,
+        "error_kind": error_kind,
+        "steps": [{
+            "step": "personalize",
+            "passed": passed,
+            "duration_us": perso_us,
+        }],
+        "timings": [{
+            "step": "personalize",
+            "operation": "perso-certgen",
+            "duration_us": perso_us // 2,
+        }],
+        "certs": [{
+            "name": f"CERT_{i}"
+        } for i in range(certs)],
+    }
+
+
+class TestAbQualification(unittest.TestCase):
+
+    def setUp(self):
+        self.dir = tempfile.TemporaryDirectory()
+        self.ab = AbQualification(self.dir.name, "/firmware/b")
+
+    def tearDown(self):
+        self.dir.cleanup()
+
+    def provision(self, device_id, passed=True, ft_duration=10.0, **kwargs):
+        variant = self.ab.next_variant()
+        log_dir = os.path.join(self.dir.name, device_id)
+        os.makedirs(log_dir)
+        with open(os.path.join(log_dir, "ft_result.json"), "w") as fp:
+            json.dump(ft_result(device_id, passed, **kwargs), fp)
+        self.ab.record(device_id, variant, passed, {
+            "cp": (5.0, True),
+            "ft": (ft_duration, passed)
+        }, log_dir)
+        return variant
+
+    def records(self):
+        return load_records(os.path.join(self.dir.name, AB_RESULTS_FILE))
+
+    def test_variants_alternate(self):
+        variants = [self.provision(f"dev{i}") for i in range(4)]
+        self.assertEqual(variants, ["A", "B", "A", "B"])
+        self.assertIsNone(self.ab.firmware_dir("A"))
+        self.assertEqual(self.ab.firmware_dir("B"), "/firmware/b")
+        # The alternation carries across runs of the orchestrator.
+        ab = AbQualification(self.dir.name, "/firmware/b")
+        self.assertEqual(ab.next_variant(), "A")
+
+    def test_compare(self):
+        for i in range(20):
+            b = i % 2 == 1
+            self.provision(f"dev{i}",
+                           passed=not (b and i % 4 == 1),
+                           ft_duration=(12.0 if b else 10.0) + i // 2 % 3,
+                           perso_us=2000000 if b else 1000000,
+                           certs=5 if b else 4,
+                           error_kind="perso" if b and i % 4 == 1 else None)
+        report = compare(self.records())
+
+        failures = report["failures"]
+        self.assertEqual(failures["A"]["devices"], 10)
+        self.assertEqual(failures["A"]["failed"], 0)
+        self.assertEqual(failures["B"]["failed"], 5)
+        self.assertEqual(failures["B"]["error_kinds"], {"perso": 5})
+        self.assertLess(failures["p_value"], 0.05)
+
+        ft = report["timings"]["ft"]
+        self.assertEqual(ft["A"]["n"], 10)
+        self.assertAlmostEqual(ft["B"]["mean"] - ft["A"]["mean"], 2.0)
+        self.assertLess(ft["p_value"], 0.05)
+        self.assertIsNone(report["timings"]["cp"]["p_value"])
+        self.assertEqual(report["timings"]["ft.op.perso-certgen"]["B"]["mean"],
+                         1.0)
+
+        # The certificate count differs, the device IDs are per device.
+        fields = {diff["field"]: diff for diff in report["output_diffs"]}
+        self.assertIn("certs.4.name", fields)
+        self.assertIsNone(fields["certs.4.name"]["A"])
+        self.assertNotIn("device_id", fields)
+        self.assertNotIn("steps.0.duration_us", fields)
+        self.assertNotIn("timings.0.operation", fields)
+
+    def test_missing_ft_result(self):
+        variant = self.ab.next_variant()
+        self.ab.record("dev0", variant, False, {"cp": (5.0, False)},
+                       os.path.join(self.dir.name, "dev0"))
+        report = compare(self.records())
+        self.assertEqual(report["failures"]["A"]["failed"], 1)
+        self.assertEqual(report["output_diffs"], [])
+
+
+class TestStatistics(unittest.TestCase):
+
+    def test_proportions(self):
+        self.assertIsNone(proportions_p_value(0, 1, 0, 10))
+        self.assertIsNone(proportions_p_value(0, 10, 0, 10))
+        self.assertAlmostEqual(proportions_p_value(2, 10, 2, 10), 1.0)
+        self.assertLess(proportions_p_value(0, 50, 10, 50), 0.01)
+
+    def test_welch(self):
+        self.assertIsNone(welch_p_value([1.0], [1.0, 2.0]))
+        self.assertIsNone(welch_p_value([1.0, 1.0], [1.0, 1.0]))
+        self.assertAlmostEqual(welch_p_value([1.0, 2.0], [1.0, 2.0]), 1.0)
+        self.assertLess(welch_p_value([1.0, 1.1, 0.9], [2.0, 2.1, 1.9]),
+                        0.01)
+
+
+if __name__ == "__main__":
+    unittest.main()
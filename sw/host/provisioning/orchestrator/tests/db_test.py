@@ -3,6 +3,7 @@
 # SPDX-License-Identifier: Apache-2.0
 """Unittests for db.py module."""
 
+import dataclasses
 import random
 import string
 import unittest
@@ -39,7 +40,8 @@ class TestDeviceRecord(unittest.TestCase):
                                dice_uds=random_string_build(),
                                dice_cdi0=random_string_build(),
                                dice_cdi1=random_string_build(),
-                               sku_specific_data=random_string_build())
+                               sku_specific_data=random_string_build(),
+                               fingerprint=random_string_build())
 
     def test_insert_and_query(self):
         device_record = self._random_device_record()
@@ -77,6 +79,26 @@ class TestDeviceRecord(unittest.TestCase):
         got_device_records = db.DeviceRecord.query_all(self.db)
         self.assertEqual(device_records, got_device_records)
 
+    def test_upsert_unless_duplicate(self):
+        device_record = self._random_device_record()
+        self.assertIsNone(device_record.upsert_unless_duplicate(self.db))
+
+        # Same results recorded by a second run are reported as duplicates.
+        rerun_record = dataclasses.replace(device_record,
+                                           provisioning_log='rerun')
+        duplicate = rerun_record.upsert_unless_duplicate(self.db)
+        self.assertEqual(device_record, duplicate)
+        got_device_record = db.DeviceRecord.query(self.db,
+                                                  device_record.device_id)
+        self.assertEqual(device_record, got_device_record)
+
+        # Different results for the same device replace the existing record.
+        rerun_record.fingerprint = random_string_build()
+        self.assertIsNone(rerun_record.upsert_unless_duplicate(self.db))
+        got_device_record = db.DeviceRecord.query(self.db,
+                                                  device_record.device_id)
+        self.assertEqual(rerun_record, got_device_record)
+
 
 if __name__ == '__main__':
     unittest.main()
@@ -0,0 +1,194 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Unittests for dut_watch.py module."""
+
+import os
+import signal
+import tempfile
+import unittest
+
+from dut_watch import DutWatcher, PauseControl, ReloadControl, StopControl
+
+
+class FakeSocket(object):
+    """Answers the probes with a scripted sequence of presences."""
+
+    def __init__(self, presences):
+        self.presences = list(presences)
+        self.probes = 0
+
+    def probe(self):
+        self.probes += 1
+        if len(self.presences) > 1:
+            return self.presences.pop(0)
+        return self.presences[0]
+
+
+class TestDutWatcher(unittest.TestCase):
+
+    def setUp(self):
+        self.sleeps = []
+
+    def watcher(self, socket, debounce=3):
+        return DutWatcher(socket.probe,
+                          poll_interval=0.5,
+                          debounce=debounce,
+                          sleep=self.sleeps.append)
+
+    def test_insertion(self):
+        socket = FakeSocket([False, False, True])
+        self.assertTrue(self.watcher(socket).wait_for_insertion())
+        self.assertEqual(socket.probes, 5)
+        self.assertEqual(self.sleeps, [0.5] * 4)
+
+    def test_insertion_debounced(self):
+        # A half-seated device answering intermittently is not inserted yet.
+        socket = FakeSocket([True, True, False, True, False, True])
+        self.assertTrue(self.watcher(socket).wait_for_insertion())
+        self.assertEqual(socket.probes, 8)
+
+    def test_removal(self):
+        socket = FakeSocket([True, False, True, False])
+        self.assertTrue(self.watcher(socket).wait_for_removal())
+        self.assertEqual(socket.probes, 6)
+
+    def test_timeout(self):
+        watcher = self.watcher(FakeSocket([False]))
+        self.assertFalse(watcher.wait_for_insertion(timeout=0))
+        watcher = self.watcher(FakeSocket([True]))
+        self.assertFalse(watcher.wait_for_removal(timeout=0))
+        # The device is seen before the timeout.
+        watcher = self.watcher(FakeSocket([True]), debounce=1)
+        self.assertTrue(watcher.wait_for_insertion(timeout=0))
+
+    def test_stopped(self):
+        # The station stops while waiting for a device.
+        stop = StopControl()
+        watcher = DutWatcher(FakeSocket([False]).probe,
+                             poll_interval=0.5,
+                             sleep=lambda _: stop.request_stop("test"),
+                             stopped=stop.stopped)
+        with self.assertLogs(level="WARNING"):
+            self.assertFalse(watcher.wait_for_insertion())
+        self.assertEqual(stop.reason, "test")
+
+    def test_invalid_debounce(self):
+        with self.assertRaises(ValueError):
+            DutWatcher(lambda: True, debounce=0)
+
+
+class TestPauseControl(unittest.TestCase):
+
+    def setUp(self):
+        self.dir = tempfile.TemporaryDirectory()
+        self.control = PauseControl(os.path.join(self.dir.name, "PAUSE"),
+                                    poll_interval=0.5,
+                                    sleep=self.sleep)
+        self.sleeps = []
+        # Number of polls before the operator resumes the station.
+        self.resume_after = 0
+
+    def tearDown(self):
+        self.dir.cleanup()
+
+    def sleep(self, seconds):
+        self.sleeps.append(seconds)
+        if len(self.sleeps) >= self.resume_after:
+            self.control.resume()
+
+    def test_not_paused(self):
+        self.assertFalse(self.control.paused())
+        self.assertFalse(self.control.wait_while_paused())
+        self.assertEqual(self.sleeps, [])
+
+    def test_paused(self):
+        self.control.pause("handler jam")
+        self.assertTrue(self.control.paused())
+        self.resume_after = 3
+        with self.assertLogs(level="WARNING") as logs:
+            self.assertTrue(self.control.wait_while_paused())
+        self.assertIn("handler jam", logs.output[0])
+        self.assertEqual(self.sleeps, [0.5] * 3)
+        self.assertFalse(self.control.paused())
+
+    def test_resume_not_paused(self):
+        self.control.resume()
+        self.assertFalse(self.control.paused())
+
+    def test_stopped_while_paused(self):
+        stop = StopControl()
+        control = PauseControl(self.control.pause_file,
+                               sleep=lambda _: stop.request_stop(),
+                               stopped=stop.stopped)
+        control.pause()
+        with self.assertLogs(level="WARNING"):
+            self.assertTrue(control.wait_while_paused())
+        self.assertTrue(control.paused())
+
+
+class TestStopControl(unittest.TestCase):
+
+    def test_request_stop(self):
+        stop = StopControl()
+        self.assertFalse(stop.stopped())
+        with self.assertLogs(level="WARNING") as logs:
+            stop.request_stop("maintenance")
+            stop.request_stop("again")
+        self.assertTrue(stop.stopped())
+        self.assertEqual(stop.reason, "maintenance")
+        self.assertEqual(len(logs.output), 1)
+
+    def test_signal_handlers(self):
+        previous = signal.getsignal(signal.SIGTERM)
+        self.addCleanup(signal.signal, signal.SIGTERM, previous)
+        stop = StopControl()
+        stop.install_signal_handlers()
+        with self.assertLogs(level="WARNING"):
+            signal.raise_signal(signal.SIGTERM)
+        self.assertTrue(stop.stopped())
+        self.assertEqual(stop.reason, "SIGTERM")
+
+
+
+class TestReloadControl(unittest.TestCase):
+
+    def setUp(self):
+        self.dir = tempfile.TemporaryDirectory()
+        self.addCleanup(self.dir.cleanup)
+        self.reload_file = os.path.join(self.dir.name, "RELOAD")
+        self.reload = ReloadControl(self.reload_file)
+
+    def test_no_reload(self):
+        self.assertFalse(self.reload.take())
+
+    def test_request_reload(self):
+        with self.assertLogs(level="INFO"):
+            self.reload.request_reload("new key bundle")
+        self.assertTrue(self.reload.take())
+        self.assertEqual(self.reload.reason, "new key bundle")
+        # Each request is taken once.
+        self.assertFalse(self.reload.take())
+
+    def test_reload_file(self):
+        with open(self.reload_file, "w") as f:
+            f.write("quota update\n")
+        with self.assertLogs(level="INFO"):
+            self.assertTrue(self.reload.take())
+        self.assertEqual(self.reload.reason, "quota update")
+        self.assertFalse(os.path.exists(self.reload_file))
+        self.assertFalse(self.reload.take())
+
+    @unittest.skipUnless(hasattr(signal, "SIGHUP"), "no SIGHUP")
+    def test_signal_handlers(self):
+        previous = signal.getsignal(signal.SIGHUP)
+        self.addCleanup(signal.signal, signal.SIGHUP, previous)
+        self.reload.install_signal_handlers()
+        with self.assertLogs(level="INFO"):
+            signal.raise_signal(signal.SIGHUP)
+        self.assertTrue(self.reload.take())
+        self.assertEqual(self.reload.reason, "SIGHUP")
+
+
+if __name__ == "__main__":
+    unittest.main()
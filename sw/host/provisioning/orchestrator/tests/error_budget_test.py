@@ -0,0 +1,108 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Unittests for error_budget.py module."""
+
+import json
+import os
+import tempfile
+import unittest
+
+from error_budget import ErrorBudget, failure_class
+
+
+class TestErrorBudget(unittest.TestCase):
+
+    def setUp(self):
+        self.tmp_dir = tempfile.TemporaryDirectory()
+        self.state_file = os.path.join(self.tmp_dir.name, "budget.json")
+
+    def tearDown(self):
+        self.tmp_dir.cleanup()
+
+    def _budget(self) -> ErrorBudget:
+        return ErrorBudget(3, self.state_file, sku="sival")
+
+    def test_halts_on_consecutive_identical_failures(self):
+        budget = self._budget()
+        self.assertFalse(budget.record("dev0", "timeout"))
+        self.assertFalse(budget.record("dev1", "timeout"))
+        self.assertTrue(budget.record("dev2", "timeout"))
+        self.assertTrue(budget.halted)
+        self.assertIn("dev0, dev1, dev2", budget.reason)
+        # The halt is only reported once.
+        self.assertFalse(budget.record("dev3", "timeout"))
+
+    def test_streak_restarts_on_pass_or_other_class(self):
+        budget = self._budget()
+        budget.record("dev0", "timeout")
+        budget.record("dev1", "timeout")
+        budget.record("dev2", None)
+        budget.record("dev3", "timeout")
+        budget.record("dev4", "timeout")
+        self.assertFalse(budget.record("dev5", "wrong-lc-state"))
+        self.assertFalse(budget.halted)
+        self.assertEqual(budget.devices, ["dev5"])
+
+    def test_halt_persists_until_acknowledged(self):
+        budget = self._budget()
+        for i in range(3):
+            budget.record(f"dev{i}", "cp")
+        self.assertTrue(self._budget().halted)
+        self._budget().acknowledge()
+        budget = self._budget()
+        self.assertFalse(budget.halted)
+        self.assertFalse(budget.record("dev3", "cp"))
+
+    def test_invalid_budget(self):
+        with self.assertRaises(ValueError):
+            ErrorBudget(0, self.state_file, sku="sival")
+
+
+class TestFailureClass(unittest.TestCase):
+
+    def setUp(self):
+        self.tmp_dir = tempfile.TemporaryDirectory()
+        self.log_dir = self.tmp_dir.name
+
+    def tearDown(self):
+        self.tmp_dir.cleanup()
+
+    def _write_ft_result(self, result: dict) -> None:
+        with open(os.path.join(self.log_dir, "ft_result.json"), "w") as fp:
+            json.dump(result, fp)
+
+    def test_cp_failure(self):
+        results = {"cp": (1.0, False), "ft": (1.0, False)}
+        self.assertEqual(failure_class(self.log_dir, results, []), "cp")
+        self.assertEqual(failure_class(self.log_dir, results, ["cp"]),
+                         "cp-timeout")
+
+    def test_ft_error_kind(self):
+        self._write_ft_result({"error_kind": "wrong-lc-state", "steps": []})
+        results = {"cp": (1.0, True), "ft": (1.0, False)}
+        self.assertEqual(failure_class(self.log_dir, results, []),
+                         "wrong-lc-state")
+
+    def test_ft_failed_step(self):
+        self._write_ft_result({
+            "error_kind": None,
+            "steps": [
+                {"step": "test-unlock", "passed": True},
+                {"step": "personalize", "passed": False},
+            ],
+        })
+        results = {"cp": (1.0, True), "ft": (1.0, False)}
+        self.assertEqual(failure_class(self.log_dir, results, []),
+                         "ft:personalize")
+
+    def test_ft_without_result(self):
+        results = {"cp": (1.0, True), "ft": (1.0, False)}
+        self.assertEqual(failure_class(self.log_dir, results, []), "ft")
+
+    def test_aborted(self):
+        self.assertEqual(failure_class(self.log_dir, {}, []), "aborted")
+
+
+if __name__ == "__main__":
+    unittest.main()
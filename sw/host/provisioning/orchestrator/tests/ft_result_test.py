@@ -0,0 +1,78 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Unittests for ft_result.py module."""
+
+import copy
+import json
+import os
+import tempfile
+import unittest
+
+import ft_result
+
+_FT_DATA = {
+    "schema_version": 1,
+    "lc_state": {
+        "initial": "TestLocked0",
+        "unlocked": "TestUnlocked1",
+        "individualize": "TestUnlocked1",
+        "mission_mode": "Prod",
+    },
+    "device_id": "00000000" * 7 + "00024001",
+    "rma_unlock_token": "dG9rZW4=",
+    "certs": {
+        "UDS": {
+            "format": "X509",
+            "name": "UDS",
+            "bytes": "dWRz",
+            "ignore_critical": False,
+        },
+        "TPM_EK": {
+            "format": "X509",
+            "name": "TPM_EK",
+            "bytes": "ZWs=",
+            "ignore_critical": True,
+        },
+    },
+}
+
+
+class TestFtResult(unittest.TestCase):
+
+    def test_parse_provisioning_data(self):
+        with tempfile.TemporaryDirectory() as tmp_dir:
+            log_file = os.path.join(tmp_dir, "ft_out.log.txt")
+            with open(log_file, "w") as fp:
+                fp.write("Provisioning Done\n")
+                fp.write(f"PROVISIONING_DATA: {json.dumps(_FT_DATA)}\n")
+            self.assertEqual(ft_result.parse_provisioning_data(log_file),
+                             _FT_DATA)
+
+    def test_fingerprint(self):
+        fingerprint = ft_result.device_fingerprint(_FT_DATA)
+        self.assertEqual(fingerprint,
+                         ft_result.device_fingerprint(copy.deepcopy(_FT_DATA)))
+
+        other = copy.deepcopy(_FT_DATA)
+        other["certs"]["UDS"]["bytes"] = "b3RoZXI="
+        self.assertNotEqual(fingerprint, ft_result.device_fingerprint(other))
+
+        other = copy.deepcopy(_FT_DATA)
+        other["lc_state"]["mission_mode"] = "Dev"
+        self.assertNotEqual(fingerprint, ft_result.device_fingerprint(other))
+
+    def test_device_record(self):
+        record = ft_result.device_record(_FT_DATA, "sival", "logs/dev")
+        self.assertEqual(record.device_id, _FT_DATA["device_id"])
+        self.assertEqual(record.provisioning_state, "Prod")
+        self.assertEqual(record.dice_uds, "dWRz")
+        self.assertEqual(record.dice_cdi0, "")
+        self.assertEqual(json.loads(record.sku_specific_data),
+                         {"TPM_EK": "ZWs="})
+        self.assertEqual(record.fingerprint,
+                         ft_result.device_fingerprint(_FT_DATA))
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,66 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Unittests for key_bundle.py module."""
+
+import json
+import tempfile
+import unittest
+from datetime import datetime, timezone
+
+from key_bundle import KeyBundle
+
+_KEYS_DIR = "sw/device/silicon_creator/manuf/keys/fake"
+_KEY_BUNDLE = f"{_KEYS_DIR}/key_bundle.json"
+_KEY_BUNDLE_SIGNATURE = f"{_KEYS_DIR}/key_bundle.json.sig"
+_KEY_BUNDLE_KEY = f"{_KEYS_DIR}/key_bundle_signer.pub.der"
+
+
+class TestKeyBundle(unittest.TestCase):
+
+    def setUp(self):
+        with open(_KEY_BUNDLE, "r") as fp:
+            self.bundle_args = json.load(fp)
+
+    def test_load_signed(self):
+        bundle = KeyBundle.load_signed(_KEY_BUNDLE, _KEY_BUNDLE_SIGNATURE,
+                                       _KEY_BUNDLE_KEY)
+        self.assertEqual(bundle.name, "fake")
+        self.assertIn("dice", bundle.cas)
+        self.assertIn("ext", bundle.cas)
+
+    def test_load_signed_tampered(self):
+        self.bundle_args["skus"].append("acme_sku")
+        with tempfile.NamedTemporaryFile(mode="w+") as fp:
+            json.dump(self.bundle_args, fp)
+            fp.flush()
+            with self.assertRaises(ValueError):
+                KeyBundle.load_signed(fp.name, _KEY_BUNDLE_SIGNATURE,
+                                      _KEY_BUNDLE_KEY)
+
+    def test_check(self):
+        bundle = KeyBundle(**self.bundle_args)
+        bundle.not_after = "2030-01-01T00:00:00Z"
+        bundle.check("sival", datetime(2029, 1, 1, tzinfo=timezone.utc))
+        with self.assertRaises(ValueError):
+            bundle.check("acme_sku", datetime(2029, 1, 1,
+                                              tzinfo=timezone.utc))
+        with self.assertRaises(ValueError):
+            bundle.check("sival", datetime(2030, 1, 2, tzinfo=timezone.utc))
+
+    def test_validate(self):
+        KeyBundle(**self.bundle_args).validate()
+        for field, value in [("schema_version", 2),
+                             ("not_after", "2030-01-01T00:00:00"),
+                             ("rma_wrap_key", "not hex")]:
+            bundle = KeyBundle(**self.bundle_args)
+            setattr(bundle, field, value)
+            with self.assertRaises(ValueError):
+                bundle.validate()
+        del self.bundle_args["cas"]["ext"]
+        with self.assertRaises(ValueError):
+            KeyBundle(**self.bundle_args).validate()
+
+
+if __name__ == "__main__":
+    unittest.main()
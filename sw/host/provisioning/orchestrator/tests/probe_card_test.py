@@ -0,0 +1,75 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Unittests for probe_card.py module."""
+
+import tempfile
+import unittest
+
+import hjson
+
+from probe_card import ProbeCardConfig, ResetDomainLock
+
+_QUAD_SITE_CONFIG = "sw/host/provisioning/orchestrator/configs/probe_cards/quad_site.hjson"
+
+
+class TestProbeCard(unittest.TestCase):
+
+    def setUp(self):
+        with open(_QUAD_SITE_CONFIG, "r") as fp:
+            self.config_args = hjson.load(fp)
+        self.lock_dir = tempfile.TemporaryDirectory()
+        self.addCleanup(self.lock_dir.cleanup)
+
+    def test_reset_domains(self):
+        config = ProbeCardConfig(**self.config_args)
+        self.assertEqual(config.reset_domains(), {
+            "rst_a": ["site0", "site1"],
+            "rst_b": ["site2", "site3"],
+        })
+        self.assertEqual(config.site("site2").interface, "teacup2")
+        with self.assertRaises(ValueError):
+            config.site("site4")
+
+    def test_validation(self):
+        self.config_args["sites"]["site1"]["interface"] = "teacup0"
+        with self.assertRaises(ValueError):
+            ProbeCardConfig(**self.config_args)
+        self.config_args["sites"]["site1"]["interface"] = "teacup1"
+        self.config_args["sites"]["site1"]["wafer_x_coord"] = 0
+        with self.assertRaises(ValueError):
+            ProbeCardConfig(**self.config_args)
+        self.config_args["sites"]["site1"]["wafer_x_coord"] = 1
+        self.config_args["sites"]["site1"]["reset_domain"] = ""
+        with self.assertRaises(ValueError):
+            ProbeCardConfig(**self.config_args)
+        with self.assertRaises(ValueError):
+            ProbeCardConfig(name="empty", sites={})
+
+    def test_jtag_chain(self):
+        self.config_args["sites"]["site0"]["jtag_chain"] = [
+            "fpga:6", "opentitan", "cpld:8:0x020a10dd"
+        ]
+        config = ProbeCardConfig(**self.config_args)
+        self.assertEqual(config.site("site0").jtag_chain[1], "opentitan")
+        self.assertIsNone(config.site("site1").jtag_chain)
+        self.config_args["sites"]["site0"]["jtag_chain"] = ["fpga:6"]
+        with self.assertRaises(ValueError):
+            ProbeCardConfig(**self.config_args)
+
+    def test_reset_domain_lock(self):
+        config = ProbeCardConfig(**self.config_args)
+        with ResetDomainLock(self.lock_dir.name, config.site("site0")):
+            # The other site of the reset domain waits for site0.
+            other = ResetDomainLock(self.lock_dir.name, config.site("site1"))
+            with self.assertRaisesRegex(TimeoutError, "site site0"):
+                other.acquire(timeout=0)
+            # Sites of other reset domains run in parallel.
+            with ResetDomainLock(self.lock_dir.name, config.site("site2")):
+                pass
+        other.acquire(timeout=0)
+        other.release()
+
+
+if __name__ == "__main__":
+    unittest.main()
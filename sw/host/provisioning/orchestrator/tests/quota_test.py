@@ -0,0 +1,89 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Unittests for quota.py module."""
+
+import time
+import unittest
+from types import SimpleNamespace
+
+import db
+import quota
+from quota import OverrideDenied, QuotaConfig, QuotaEnforcer, QuotaExceeded
+
+_SKU = SimpleNamespace(
+    name="sival",
+    dice_ca=SimpleNamespace(name="dice_ca", key_id="0x01"),
+    ext_ca=SimpleNamespace(name="ext_ca", key_id="0x02"),
+)
+
+
+class TestQuota(unittest.TestCase):
+
+    def setUp(self):
+        self.db = db.DB(db.DBConfig(db_path=":memory:"))
+        db.QuotaUsageRecord.create_table(self.db)
+
+    def _enforcer(self, **kwargs):
+        return QuotaEnforcer(self.db, QuotaConfig(**kwargs))
+
+    def test_config_validation(self):
+        with self.assertRaises(ValueError):
+            QuotaConfig(max_devices_per_lot=0)
+        with self.assertRaises(ValueError):
+            QuotaConfig(shift_hours=7)
+        with self.assertRaises(ValueError):
+            QuotaConfig(supervisors={"alice": "not a hash"})
+
+    def test_key_and_lot_quotas(self):
+        quotas = self._enforcer(max_devices_per_key=3, max_devices_per_lot=2)
+        quotas.reserve("dev0", _SKU, "Y4W07L001")
+        quotas.reserve("dev1", _SKU, "Y4W07L001")
+        with self.assertRaises(QuotaExceeded) as e:
+            quotas.reserve("dev2", _SKU, "Y4W07L001")
+        self.assertEqual(len(e.exception.violations), 1)
+        quotas.reserve("dev2", _SKU, "Y4W07L002")
+        # Both CA keys are used up.
+        self.assertEqual(len(quotas.violations(_SKU, "Y4W07L003")), 2)
+
+    def test_shift_quota(self):
+        config = QuotaConfig(max_devices_per_shift=1,
+                             shift_hours=8,
+                             shift_start_hour=6)
+        day = time.mktime((2024, 2, 7, 0, 0, 0, 0, 0, -1))
+        self.assertEqual(quota.shift_name(config, day + 6 * 3600),
+                         "2024-02-07S0")
+        self.assertEqual(quota.shift_name(config, day + 22 * 3600),
+                         "2024-02-07S2")
+        self.assertEqual(quota.shift_name(config, day + 5 * 3600),
+                         "2024-02-06S2")
+        quotas = QuotaEnforcer(self.db, config)
+        quotas.reserve("dev0", _SKU, "Y4W07L001")
+        self.assertEqual(len(quotas.violations(_SKU, "Y4W07L001")), 1)
+
+    def test_override(self):
+        quotas = self._enforcer(
+            max_devices_per_lot=1,
+            supervisors={"alice": quota.hash_passphrase("secret")})
+        quotas.reserve("dev0", _SKU, "Y4W07L001")
+        with self.assertRaises(OverrideDenied):
+            quotas.authorize_override("alice", "wrong")
+        with self.assertRaises(OverrideDenied):
+            quotas.authorize_override("bob", "secret")
+        override = quotas.authorize_override("alice", "secret")
+        record = quotas.reserve("dev1", _SKU, "Y4W07L001", override)
+        self.assertEqual(record.override, "alice")
+        self.assertEqual(db.QuotaUsageRecord.count_by_lot(self.db, "Y4W07L001"),
+                         2)
+
+    def test_rejected_device_not_counted(self):
+        quotas = self._enforcer(max_devices_per_lot=1)
+        quotas.reserve("dev0", _SKU, "Y4W07L001")
+        with self.assertRaises(QuotaExceeded):
+            quotas.reserve("dev1", _SKU, "Y4W07L001")
+        self.assertEqual(db.QuotaUsageRecord.count_by_lot(self.db, "Y4W07L001"),
+                         1)
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,139 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Unittests for registration.py module."""
+
+import unittest
+
+import db
+import registration
+
+
+def device_record(n: int) -> db.DeviceRecord:
+    return db.DeviceRecord(device_id=f"dev{n}",
+                           sku="sival",
+                           provisioning_state="PROD",
+                           provisioning_log=f"logs/dev{n}",
+                           timestamp=0,
+                           rma_unlock_token="",
+                           dice_uds=f"uds{n}",
+                           dice_cdi0=f"cdi0{n}",
+                           dice_cdi1="",
+                           sku_specific_data="{}",
+                           fingerprint=f"fp{n}")
+
+
+class FakeRegistry(object):
+    """Registry acknowledging all devices, after `failures` failed calls."""
+
+    def __init__(self, failures=0, reject=()):
+        self.failures = failures
+        self.reject = reject
+        self.batches = []
+
+    def submit(self, batch_id, devices):
+        self.batches.append((batch_id, [d["device_id"] for d in devices]))
+        if self.failures:
+            self.failures -= 1
+            raise registration.RegistryError("unavailable")
+        return {
+            "acked": [{
+                "fingerprint": d["fingerprint"],
+                "registry_id": f"reg-{d['device_id']}"
+            } for d in devices if d["device_id"] not in self.reject],
+            "rejected": [{
+                "fingerprint": d["fingerprint"],
+                "error": "bad cert"
+            } for d in devices if d["device_id"] in self.reject],
+        }
+
+
+class TestRegistrationQueue(unittest.TestCase):
+
+    def setUp(self):
+        self.db = db.DB(db.DBConfig(db_path=":memory:"))
+        self.delays = []
+
+    def _queue(self, registry, batch_size=2, max_retries=2):
+        return registration.RegistrationQueue(
+            self.db,
+            registry,
+            registration.RegistrationConfig(batch_size=batch_size,
+                                            max_retries=max_retries,
+                                            retry_delay=1.0),
+            sleep=self.delays.append)
+
+    def _state(self, n):
+        return db.RegistrationRecord.query_by_fingerprint(self.db, f"fp{n}")
+
+    def test_payload(self):
+        payload = registration.registration_payload(device_record(0))
+        self.assertEqual(payload["device_id"], "dev0")
+        self.assertEqual(payload["certs"], {"UDS": "uds0", "CDI_0": "cdi00"})
+
+    def test_batches_full_groups(self):
+        registry = FakeRegistry()
+        queue = self._queue(registry)
+        for n in range(5):
+            self.assertTrue(queue.enqueue(device_record(n)))
+        self.assertEqual(queue.flush(), 4)
+        self.assertEqual([b[1] for b in registry.batches],
+                         [["dev0", "dev1"], ["dev2", "dev3"]])
+        self.assertEqual(self._state(3).state, registration.ACKED)
+        self.assertEqual(self._state(3).registry_id, "reg-dev3")
+        self.assertEqual(self._state(4).state, registration.PENDING)
+
+        self.assertEqual(queue.flush(force=True), 1)
+        self.assertEqual(registry.batches[-1][1], ["dev4"])
+
+    def test_deduplicates_resubmissions(self):
+        queue = self._queue(FakeRegistry())
+        self.assertTrue(queue.enqueue(device_record(0)))
+        self.assertFalse(queue.enqueue(device_record(0)))
+        self.assertEqual(len(queue.pending_batches(force=True)), 1)
+        self.assertEqual(len(queue.pending_batches(force=True)[0]), 1)
+
+    def test_retries_with_backoff(self):
+        registry = FakeRegistry(failures=2)
+        queue = self._queue(registry)
+        queue.enqueue(device_record(0))
+        queue.enqueue(device_record(1))
+        self.assertEqual(queue.flush(), 2)
+        self.assertEqual(self.delays, [1.0, 2.0])
+        self.assertEqual(self._state(0).attempts, 3)
+        # All attempts carry the same batch ID.
+        self.assertEqual(len({b[0] for b in registry.batches}), 1)
+
+    def test_resubmits_after_failure(self):
+        registry = FakeRegistry(failures=3)
+        queue = self._queue(registry)
+        for n in range(4):
+            queue.enqueue(device_record(n))
+        self.assertEqual(queue.flush(), 0)
+        self.assertEqual(self._state(0).state, registration.SUBMITTED)
+        self.assertEqual(self._state(2).state, registration.PENDING)
+        bid = self._state(0).batch_id
+
+        # A new queue, e.g. after a crash, resubmits the batch first.
+        registry = FakeRegistry()
+        queue = self._queue(registry)
+        self.assertEqual(queue.flush(), 4)
+        self.assertEqual(registry.batches[0], (bid, ["dev0", "dev1"]))
+
+    def test_reconciles_rejections(self):
+        registry = FakeRegistry(reject=("dev1", ))
+        queue = self._queue(registry)
+        queue.enqueue(device_record(0))
+        queue.enqueue(device_record(1))
+        self.assertEqual(queue.flush(), 1)
+        self.assertEqual(self._state(1).state, registration.REJECTED)
+        self.assertEqual(self._state(1).error, "bad cert")
+        self.assertEqual(queue.pending_batches(force=True), [])
+
+    def test_invalid_config(self):
+        with self.assertRaises(ValueError):
+            registration.RegistrationConfig(batch_size=0)
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,127 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Unittests for report_timestamp.py module."""
+
+import hashlib
+import json
+import os
+import tempfile
+import unittest
+
+import report_timestamp
+from report_timestamp import (TimestampError, check_timestamp_response,
+                              timestamp_report, timestamp_request)
+
+_DIGEST = bytes(range(32))
+_NONCE = 0x8000000000000001
+_GEN_TIME = "20261014120000Z"
+
+
+def _der(tag, value):
+    return report_timestamp._der(tag, value)
+
+
+def _integer(value):
+    return report_timestamp._der_integer(value)
+
+
+def _response(status, digest=None, nonce=None):
+    """Builds a TSA response, holding an unsigned token if `digest` is set."""
+    status_info = _der(0x30, _der(0x02, bytes([status])))
+    if digest is None:
+        return _der(0x30, status_info)
+    tst_info = _der(
+        0x30,
+        _integer(1) + _der(0x06, bytes.fromhex("2a03")) +
+        report_timestamp._message_imprint(digest) + _integer(42) +
+        _der(0x18, _GEN_TIME.encode("ascii")) + _der(0x30, _integer(1)) +
+        _integer(nonce))
+    encap = _der(0x30, (_der(0x06, bytes.fromhex("2a864886f70d01091001")) +
+                        _der(0xa0, _der(0x04, tst_info))))
+    signed_data = _der(0x30, _integer(3) + _der(0x31, b"") + encap +
+                       _der(0x31, b""))
+    token = _der(0x30, (_der(0x06, bytes.fromhex("2a864886f70d010702")) +
+                        _der(0xa0, signed_data)))
+    return _der(0x30, status_info + token)
+
+
+class FakeTsa(object):
+
+    def __init__(self, status=0):
+        self.status = status
+        self.requests = []
+
+    def timestamp(self, request):
+        self.requests.append(request)
+        # Echo the message imprint and nonce of the request.
+        _, body, _ = report_timestamp._der_read(request)
+        _, _, offset = report_timestamp._der_read(body)
+        _, imprint, offset = report_timestamp._der_read(body, offset)
+        _, nonce, _ = report_timestamp._der_read(body, offset)
+        return _response(self.status, imprint[-32:],
+                         int.from_bytes(nonce, "big"))
+
+
+class TestReportTimestamp(unittest.TestCase):
+
+    def test_timestamp_request(self):
+        request = timestamp_request(_DIGEST, _NONCE)
+        tag, body, end = report_timestamp._der_read(request)
+        self.assertEqual((tag, end), (0x30, len(request)))
+        tag, version, offset = report_timestamp._der_read(body)
+        self.assertEqual((tag, version), (0x02, b"\x01"))
+        tag, imprint, offset = report_timestamp._der_read(body, offset)
+        self.assertEqual(tag, 0x30)
+        self.assertTrue(imprint.endswith(_der(0x04, _DIGEST)))
+        tag, nonce, offset = report_timestamp._der_read(body, offset)
+        # The nonce has its top bit set, so it is encoded with a sign byte.
+        self.assertEqual((tag, nonce),
+                         (0x02, b"\x00" + _NONCE.to_bytes(8, "big")))
+        tag, cert_req, _ = report_timestamp._der_read(body, offset)
+        self.assertEqual((tag, cert_req), (0x01, b"\xff"))
+
+    def test_check_timestamp_response(self):
+        self.assertEqual(
+            check_timestamp_response(_response(0, _DIGEST, _NONCE), _DIGEST,
+                                     _NONCE), _GEN_TIME)
+        check_timestamp_response(_response(1, _DIGEST, _NONCE), _DIGEST,
+                                 _NONCE)
+        with self.assertRaisesRegex(TimestampError, "rejected"):
+            check_timestamp_response(_response(2, _DIGEST, _NONCE), _DIGEST,
+                                     _NONCE)
+        with self.assertRaisesRegex(TimestampError, "no timestamp token"):
+            check_timestamp_response(_response(0), _DIGEST, _NONCE)
+        with self.assertRaisesRegex(TimestampError, "does not cover"):
+            check_timestamp_response(_response(0, bytes(32), _NONCE),
+                                     _DIGEST, _NONCE)
+        with self.assertRaisesRegex(TimestampError, "does not match"):
+            check_timestamp_response(_response(0, _DIGEST, 1), _DIGEST,
+                                     _NONCE)
+        with self.assertRaisesRegex(TimestampError, "Truncated"):
+            check_timestamp_response(
+                _response(0, _DIGEST, _NONCE)[:-1], _DIGEST, _NONCE)
+
+    def test_timestamp_report(self):
+        ft_data = {"device_id": "0x1234", "certs": {}}
+        with tempfile.TemporaryDirectory() as log_dir:
+            tsa = FakeTsa()
+            path, gen_time = timestamp_report(tsa, ft_data, log_dir)
+            self.assertEqual(gen_time, _GEN_TIME)
+            with open(path, "rb") as fp:
+                report = fp.read()
+            self.assertEqual(json.loads(report), ft_data)
+            self.assertIn(_der(0x04, hashlib.sha256(report).digest()),
+                          tsa.requests[0])
+            self.assertTrue(os.path.exists(path + ".tsr"))
+
+            # A rejected request keeps the report, without its timestamp.
+            os.remove(path + ".tsr")
+            with self.assertRaises(TimestampError):
+                timestamp_report(FakeTsa(status=2), ft_data, log_dir)
+            self.assertTrue(os.path.exists(path))
+            self.assertFalse(os.path.exists(path + ".tsr"))
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,92 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Unittests for retention.py module."""
+
+import os
+import tarfile
+import tempfile
+import time
+import unittest
+from dataclasses import asdict
+
+import retention
+import step_state
+
+_DAY = 24 * 60 * 60
+
+
+class TestRetention(unittest.TestCase):
+
+    def setUp(self):
+        self.tmp = tempfile.TemporaryDirectory()
+        self.addCleanup(self.tmp.cleanup)
+        self.root = os.path.join(self.tmp.name, "logs")
+        self.archive_dir = os.path.join(self.tmp.name, "archive")
+        self.now = time.time()
+
+    def _device(self, name, age_days, passed=True, completed=True):
+        log_dir = os.path.join(self.root, name)
+        os.makedirs(log_dir)
+        with open(os.path.join(log_dir, "ft_out.log.txt"), "w") as fp:
+            fp.write("log\n")
+        journal = step_state.StepJournal(log_dir, f"0x{name}")
+        journal.enter("ft")
+        if completed:
+            journal.complete(passed)
+        journal.state.updated = int(self.now - age_days * _DAY)
+        step_state.write_atomic(journal.path, asdict(journal.state))
+        return log_dir
+
+    def test_config(self):
+        with self.assertRaises(ValueError):
+            retention.RetentionConfig(keep_days=0)
+
+    def test_delete_old_passing_runs(self):
+        old = self._device("dev0", age_days=10)
+        recent = self._device("dev1", age_days=1)
+        report = retention.apply_retention(
+            self.root, retention.RetentionConfig(keep_days=7), now=self.now)
+        self.assertEqual(report.deleted, [old])
+        self.assertEqual(report.archived, [])
+        self.assertFalse(os.path.exists(old))
+        self.assertTrue(os.path.exists(recent))
+
+    def test_never_delete_failures(self):
+        failed = self._device("dev0", age_days=10, passed=False)
+        in_flight = self._device("dev1", age_days=10, completed=False)
+        config = retention.RetentionConfig(keep_days=7)
+        report = retention.apply_retention(self.root, config, now=self.now)
+        self.assertEqual(report.deleted, [])
+        self.assertEqual(report.kept_failures, [failed])
+        self.assertTrue(os.path.exists(failed))
+
+        # Interrupted runs are kept even when failures may be deleted.
+        config.keep_failures = False
+        report = retention.apply_retention(self.root, config, now=self.now)
+        self.assertEqual(report.deleted, [failed])
+        self.assertTrue(os.path.exists(in_flight))
+
+    def test_archive_then_delete(self):
+        old = self._device("dev0", age_days=10)
+        # Other entries of the logs root directory are not device logs.
+        with open(os.path.join(self.root, "yield_sival.json"), "w") as fp:
+            fp.write("{}")
+        report = retention.apply_retention(
+            self.root,
+            retention.RetentionConfig(keep_days=7,
+                                      archive_dir=self.archive_dir),
+            now=self.now)
+        self.assertEqual(report.archived, [old])
+        self.assertEqual(report.deleted, [old])
+        self.assertFalse(os.path.exists(old))
+        self.assertTrue(
+            os.path.exists(os.path.join(self.root, "yield_sival.json")))
+        with tarfile.open(os.path.join(self.archive_dir,
+                                       "dev0.tar.gz")) as tar:
+            self.assertIn("dev0/ft_out.log.txt", tar.getnames())
+        self.assertEqual(os.listdir(self.archive_dir), ["dev0.tar.gz"])
+
+
+if __name__ == "__main__":
+    unittest.main()
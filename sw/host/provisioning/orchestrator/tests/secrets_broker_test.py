@@ -0,0 +1,74 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Unittests for secrets_broker.py module."""
+
+import os
+import tempfile
+import unittest
+
+from secrets_broker import SecretAccessError, SecretsBroker
+
+_TOKEN = 0x0123456789abcdef0123456789abcdef
+_KEY = b"\x30\x82\x01\x02private key"
+
+
+class TestSecretsBroker(unittest.TestCase):
+
+    def setUp(self):
+        self.broker = SecretsBroker()
+        self.addCleanup(self.broker.shutdown)
+        self.broker.add_token("test_unlock_token", _TOKEN)
+        with tempfile.NamedTemporaryFile(suffix=".der", delete=False) as fp:
+            fp.write(_KEY)
+        self.addCleanup(os.remove, fp.name)
+        self.broker.load_key_file("dice_ca_key", fp.name)
+
+    def test_token(self):
+        handle = self.broker.handle("dev0")
+        self.assertEqual(handle.token("test_unlock_token", "cp"), _TOKEN)
+
+    def test_key_file_is_wiped(self):
+        handle = self.broker.handle("dev0")
+        with handle.key_file("dice_ca_key", "ft") as path:
+            with open(path, "rb") as fp:
+                self.assertEqual(fp.read(), _KEY)
+            self.assertEqual(os.stat(path).st_mode & 0o077, 0)
+        self.assertFalse(os.path.exists(path))
+
+    def test_policy(self):
+        handle = self.broker.handle("dev0")
+        with self.assertRaises(SecretAccessError):
+            handle.token("test_unlock_token", "rma")
+        with self.assertRaises(SecretAccessError):
+            with handle.key_file("dice_ca_key", "cp"):
+                pass
+        with self.assertRaises(ValueError):
+            self.broker.add_token("wafer_auth_secret", _TOKEN)
+
+    def test_handle_scope(self):
+        handle = self.broker.handle("dev0", flows=["cp"])
+        with self.assertRaises(SecretAccessError):
+            handle.token("test_unlock_token", "ft")
+        handle.close()
+        with self.assertRaises(SecretAccessError):
+            handle.token("test_unlock_token", "cp")
+
+    def test_shutdown_zeroizes(self):
+        handle = self.broker.handle("dev0")
+        secrets = list(self.broker._secrets.values())
+        cm = handle.key_file("dice_ca_key", "ft")
+        path = cm.__enter__()
+        self.broker.shutdown()
+        for value in secrets:
+            self.assertEqual(value, bytearray(len(value)))
+        self.assertFalse(os.path.exists(path))
+        with self.assertRaises(SecretAccessError):
+            handle.token("test_unlock_token", "cp")
+        with self.assertRaises(RuntimeError):
+            self.broker.handle("dev1")
+        cm.__exit__(None, None, None)
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,173 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Unittests for sku_config.py module."""
+
+import tempfile
+import unittest
+
+import hjson
+from sku_config import SkuConfig
+
+_SIVAL_SKU_CONFIG = "sw/host/provisioning/orchestrator/configs/skus/sival.hjson"
+
+
+class TestSkuConfig(unittest.TestCase):
+
+    def setUp(self):
+        with open(_SIVAL_SKU_CONFIG, "r") as fp:
+            self.sku_config_args = hjson.load(fp)
+
+    def test_creator_manuf_state_default(self):
+        del self.sku_config_args["creator_manuf_state"]
+        sku_config = SkuConfig(**self.sku_config_args)
+        self.assertIsNone(sku_config.creator_manuf_state)
+
+    def test_creator_manuf_state_matches_lc_state(self):
+        self.sku_config_args["creator_manuf_state"] = "pre_production"
+        self.sku_config_args["target_lc_state"] = "dev"
+        sku_config = SkuConfig(**self.sku_config_args)
+        self.assertEqual(sku_config.creator_manuf_state, "pre_production")
+
+    def test_creator_manuf_state_lc_state_mismatch(self):
+        self.sku_config_args["creator_manuf_state"] = "pre_production"
+        self.sku_config_args["target_lc_state"] = "prod"
+        with self.assertRaises(ValueError):
+            SkuConfig(**self.sku_config_args)
+
+    def test_key_bundle_sku_mismatch(self):
+        self.sku_config_args["name"] = "acme_sku"
+        with self.assertRaises(ValueError):
+            SkuConfig(**self.sku_config_args)
+
+    def test_release_manifest_requires_key(self):
+        self.sku_config_args["release_manifest"] = "release.json"
+        with self.assertRaises(ValueError):
+            SkuConfig(**self.sku_config_args)
+
+    def test_invalid_creator_manuf_state(self):
+        self.sku_config_args["creator_manuf_state"] = "personalized"
+        with self.assertRaises(ValueError):
+            SkuConfig(**self.sku_config_args)
+
+    def test_alert_cfg_requires_digest(self):
+        self.sku_config_args["alert_cfg"] = {"class_en": "0xa9a9a9a9"}
+        with self.assertRaises(ValueError):
+            SkuConfig(**self.sku_config_args)
+
+    def test_alert_cfg_unknown_field(self):
+        self.sku_config_args["alert_cfg"] = {
+            "rom_ext_bootstrap_en": "0x739",
+            "digest": {},
+        }
+        with self.assertRaises(ValueError):
+            SkuConfig(**self.sku_config_args)
+
+    def test_smoke_tests(self):
+        self.sku_config_args["smoke_tests"] = [
+            {
+                "name": "aes_kat",
+                "command": "kat aes\r\n",
+                "pass": "AES KAT: PASS",
+            },
+        ]
+        SkuConfig(**self.sku_config_args)
+        self.sku_config_args["smoke_tests"].append({"name": "aes_kat"})
+        with self.assertRaises(ValueError):
+            SkuConfig(**self.sku_config_args)
+        self.sku_config_args["smoke_tests"][1]["pass"] = "AES KAT: PASS"
+        with self.assertRaises(ValueError):
+            SkuConfig(**self.sku_config_args)
+
+    def test_cert_policy(self):
+        self.sku_config_args["cert_policy"] = {
+            "not_after": "99991231235959Z",
+            "serial_number": "key-id",
+            "certs": {
+                "CDI_1": {
+                    "max_path_len": 0
+                }
+            },
+        }
+        SkuConfig(**self.sku_config_args)
+        self.sku_config_args["cert_policy"]["certs"]["CDI_1"]["ca"] = True
+        with self.assertRaises(ValueError):
+            SkuConfig(**self.sku_config_args)
+        del self.sku_config_args["cert_policy"]["certs"]
+        self.sku_config_args["cert_policy"]["serial_number"] = "random"
+        with self.assertRaises(ValueError):
+            SkuConfig(**self.sku_config_args)
+
+    def test_rma_token_escrow(self):
+        self.sku_config_args["rma_token_escrow"] = {
+            "backend": "sqlite",
+            "db": "provisioning.sqlite",
+        }
+        SkuConfig(**self.sku_config_args)
+        self.sku_config_args["rma_token_escrow"]["backend"] = "secret_manager"
+        with self.assertRaises(ValueError):
+            SkuConfig(**self.sku_config_args)
+        self.sku_config_args["rma_token_escrow"]["backend"] = "database"
+        with self.assertRaises(ValueError):
+            SkuConfig(**self.sku_config_args)
+
+    def test_pass_criteria(self):
+        self.sku_config_args["pass_criteria"] = (
+            "all_steps_ok AND lc_state == PROD")
+        SkuConfig(**self.sku_config_args)
+        self.sku_config_args["pass_criteria"] = " "
+        with self.assertRaises(ValueError):
+            SkuConfig(**self.sku_config_args)
+
+    def test_offline_certs_dir(self):
+        with tempfile.TemporaryDirectory() as tmp:
+            self.sku_config_args["offline_certs_dir"] = tmp
+            SkuConfig(**self.sku_config_args)
+        with self.assertRaises(ValueError):
+            SkuConfig(**self.sku_config_args)
+
+    def test_keymgr_diversification_key(self):
+        with tempfile.NamedTemporaryFile() as key:
+            self.sku_config_args["keymgr_diversification_key"] = key.name
+            SkuConfig(**self.sku_config_args)
+        with self.assertRaises(ValueError):
+            SkuConfig(**self.sku_config_args)
+
+    def test_console(self):
+        SkuConfig(**self.sku_config_args)
+        self.sku_config_args["console"] = "dmi"
+        self.sku_config_args["console_uart"] = "CONSOLE"
+        SkuConfig(**self.sku_config_args)
+        self.sku_config_args["console"] = "uart"
+        with self.assertRaises(ValueError):
+            SkuConfig(**self.sku_config_args)
+
+    def test_console_uart_config(self):
+        self.sku_config_args["console_uart_config"] = {"baudrate": 9600}
+        with self.assertRaises(ValueError):
+            SkuConfig(**self.sku_config_args)
+        self.sku_config_args["console_uart"] = "CONSOLE"
+        self.sku_config_args["console_uart_config"] = {
+            "baudrate": 9600,
+            "parity": "even",
+            "stop_bits": 2,
+        }
+        SkuConfig(**self.sku_config_args)
+        for config in ({"baudrate": 0}, {"baudrate": "fast"},
+                       {"parity": "mark"}, {"stop_bits": 1.5},
+                       {"flow_control": "rts_cts"}):
+            self.sku_config_args["console_uart_config"] = config
+            with self.assertRaises(ValueError):
+                SkuConfig(**self.sku_config_args)
+
+    def test_entry_lc_states(self):
+        self.sku_config_args["entry_lc_states"] = ["test_locked0"]
+        SkuConfig(**self.sku_config_args)
+        for lc_states in ([], ["raw"], ["test_locked0", "test_locked7"]):
+            self.sku_config_args["entry_lc_states"] = lc_states
+            with self.assertRaises(ValueError):
+                SkuConfig(**self.sku_config_args)
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,77 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Unittests for step_state.py module."""
+
+import json
+import os
+import tempfile
+import unittest
+from dataclasses import asdict
+
+import step_state
+
+
+class TestStepState(unittest.TestCase):
+
+    def setUp(self):
+        self.tmp = tempfile.TemporaryDirectory()
+        self.addCleanup(self.tmp.cleanup)
+        self.root = self.tmp.name
+
+    def _device_dir(self, name):
+        path = os.path.join(self.root, name)
+        os.makedirs(path)
+        return path
+
+    def test_journal_round_trip(self):
+        log_dir = self._device_dir("dev0")
+        journal = step_state.StepJournal(log_dir, "0xdev0")
+        journal.enter("cp")
+        with open(os.path.join(log_dir, step_state.STEP_STATE_FILE)) as fp:
+            state = step_state.StepState(**json.load(fp))
+        self.assertEqual(state.step, "cp")
+        self.assertFalse(state.completed)
+        self.assertFalse(
+            os.path.exists(
+                os.path.join(log_dir, step_state.STEP_STATE_FILE + ".tmp")))
+
+    def test_running_devices_are_not_interrupted(self):
+        journal = step_state.StepJournal(self._device_dir("dev0"), "0xdev0")
+        journal.enter("cp")
+        self.assertEqual(step_state.interrupted_devices(self.root), [])
+
+    def test_interrupted_devices(self):
+        done = step_state.StepJournal(self._device_dir("dev0"), "0xdev0")
+        done.enter("ft")
+        done.complete(True)
+
+        log_dir = self._device_dir("dev1")
+        crashed = step_state.StepJournal(log_dir, "0xdev1")
+        # Recorded by a process which is no longer running.
+        crashed.state.host = "other-station"
+        crashed.enter("ft")
+        ft_state = {
+            "device_id": "DEV1",
+            "step": "personalize",
+            "sub_step": "rma-unlock-token",
+            "started": 0,
+            "updated": 0,
+            "completed": False,
+        }
+        step_state.write_atomic(
+            os.path.join(log_dir, step_state.FT_STEP_STATE_FILE), ft_state)
+
+        interrupted = step_state.interrupted_devices(self.root)
+        self.assertEqual(len(interrupted), 1)
+        self.assertEqual(asdict(interrupted[0]["state"])["device_id"],
+                         "0xdev1")
+        self.assertEqual(interrupted[0]["ft_state"], ft_state)
+        with self.assertLogs(level="WARNING") as logs:
+            self.assertEqual(
+                step_state.report_interrupted_devices(self.root), 1)
+        self.assertIn("ft (personalize/rma-unlock-token)", logs.output[0])
+
+
+if __name__ == "__main__":
+    unittest.main()
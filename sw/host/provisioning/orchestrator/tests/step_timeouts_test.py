@@ -0,0 +1,90 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Unittests for step_timeouts.py module."""
+
+import unittest
+from unittest import mock
+
+import db
+import step_timeouts
+from step_timeouts import StepTimeoutConfig
+
+
+class TestStepTimeouts(unittest.TestCase):
+
+    def setUp(self):
+        self.db = db.DB(db.DBConfig(db_path=":memory:"))
+        db.StepDurationRecord.create_table(self.db)
+        self.config = StepTimeoutConfig(multiplier=3.0,
+                                        min_samples=5,
+                                        history=100,
+                                        min_timeout=10.0)
+
+    def _record(self, durations, step="ft", sku="sival", passed=True):
+        for i, duration in enumerate(durations):
+            step_timeouts.record_step_duration(self.db,
+                                               device_id=f"dev{i}",
+                                               sku=sku,
+                                               step=step,
+                                               duration=duration,
+                                               passed=passed)
+
+    def test_percentile(self):
+        values = list(range(1, 101))
+        self.assertEqual(step_timeouts.percentile(values, 99), 99)
+        self.assertEqual(step_timeouts.percentile(values, 50), 50)
+        self.assertEqual(step_timeouts.percentile([7], 99), 7)
+        with self.assertRaises(ValueError):
+            step_timeouts.percentile([], 99)
+
+    def test_no_timeout_without_history(self):
+        self._record([30.0] * 4)
+        self.assertIsNone(
+            step_timeouts.step_timeout(self.db, "sival", "ft", self.config))
+
+    def test_timeout_is_multiple_of_p99(self):
+        self._record([20.0] * 98 + [30.0, 40.0])
+        self.assertEqual(
+            step_timeouts.step_timeout(self.db, "sival", "ft", self.config),
+            90.0)
+
+    def test_timeout_lower_bound(self):
+        self._record([1.0] * 10)
+        self.assertEqual(
+            step_timeouts.step_timeout(self.db, "sival", "ft", self.config),
+            10.0)
+
+    def test_failed_runs_and_other_steps_ignored(self):
+        self._record([20.0] * 10)
+        self._record([500.0] * 10, passed=False)
+        self._record([500.0] * 10, step="cp")
+        self._record([500.0] * 10, sku="other")
+        self.assertEqual(
+            step_timeouts.step_timeouts(self.db, "sival", ["cp", "ft"],
+                                        self.config), {
+                                            "cp": 1500.0,
+                                            "ft": 60.0
+                                        })
+
+    @mock.patch("time.time")
+    def test_history_limited_to_recent_runs(self, time):
+        time.side_effect = range(20)
+        self._record([100.0] * 10)
+        self._record([20.0] * 10)
+        config = StepTimeoutConfig(multiplier=3.0,
+                                   min_samples=5,
+                                   history=10,
+                                   min_timeout=10.0)
+        self.assertEqual(
+            step_timeouts.step_timeout(self.db, "sival", "ft", config), 60.0)
+
+    def test_invalid_config(self):
+        with self.assertRaises(ValueError):
+            StepTimeoutConfig(multiplier=0.5)
+        with self.assertRaises(ValueError):
+            StepTimeoutConfig(min_samples=10, history=5)
+
+
+if __name__ == "__main__":
+    unittest.main()
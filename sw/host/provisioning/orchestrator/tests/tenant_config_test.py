@@ -0,0 +1,113 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Unittests for tenant_config.py module."""
+
+import json
+import os
+import tempfile
+import unittest
+from pathlib import Path
+from types import SimpleNamespace
+
+from tenant_config import TenantConfig, TenantViolation
+
+
+class TestTenantConfig(unittest.TestCase):
+
+    def setUp(self):
+        self.tmp_dir = tempfile.TemporaryDirectory()
+        self.root = Path(self.tmp_dir.name)
+        for tenant in ["acme", "globex"]:
+            os.makedirs(self.root / "keys" / tenant)
+            for f in ["bundle.json", "bundle.json.sig", "bundle.pub.der",
+                      "ca.der"]:
+                (self.root / "keys" / tenant / f).touch()
+
+    def tearDown(self):
+        self.tmp_dir.cleanup()
+
+    def _tenant(self, name="acme", output_dir=None) -> TenantConfig:
+        return TenantConfig(
+            name=name,
+            skus=[f"{name}_sku"],
+            key_dirs=[str(self.root / "keys" / name)],
+            output_dir=output_dir or str(self.root / "out" / name),
+            token_keys=[f"{name}_hsm_key"])
+
+    def _sku(self, name="acme_sku", keys="acme", ca_key_type="Raw"):
+        key_dir = self.root / "keys" / keys
+        ca = SimpleNamespace(
+            name="dice_ca",
+            key_type=ca_key_type,
+            key=key_dir / "ca.der" if ca_key_type == "Raw" else keys +
+            "_hsm_key")
+        return SimpleNamespace(name=name,
+                               dice_ca=ca,
+                               ext_ca=ca,
+                               key_bundle=str(key_dir / "bundle.json"),
+                               key_bundle_signature=str(key_dir /
+                                                        "bundle.json.sig"),
+                               key_bundle_key=str(key_dir / "bundle.pub.der"),
+                               rma_escrow_cert=None)
+
+    def test_check_sku(self):
+        tenant = self._tenant()
+        tenant.check_sku(self._sku())
+        tenant.check_sku(self._sku(ca_key_type="Token"))
+        tenant.check_sku(self._sku(ca_key_type="Piv"))
+        with self.assertRaises(TenantViolation):
+            tenant.check_sku(self._sku(name="globex_sku"))
+        with self.assertRaises(TenantViolation):
+            tenant.check_sku(self._sku(keys="globex"))
+        with self.assertRaises(TenantViolation):
+            tenant.check_sku(self._sku(keys="globex", ca_key_type="Token"))
+
+    def test_key_path_traversal(self):
+        tenant = self._tenant()
+        sku = self._sku()
+        sku.key_bundle = str(self.root / "keys" / "acme" / ".." / "globex" /
+                             "bundle.json")
+        with self.assertRaises(TenantViolation):
+            tenant.check_sku(sku)
+
+    def test_output_paths(self):
+        tenant = self._tenant()
+        tenant.check_output_path(tenant.log_dir)
+        tenant.check_output_path(tenant.db_path)
+        with self.assertRaises(TenantViolation):
+            tenant.check_output_path(str(self.root / "out" / "globex"))
+        with self.assertRaises(TenantViolation):
+            tenant.check_output_path(tenant.log_dir + "/../../globex")
+
+    def test_claim_output_dir(self):
+        shared_dir = str(self.root / "out" / "shared")
+        self._tenant("acme", shared_dir).claim_output_dir()
+        # Claiming again as the same tenant is fine.
+        self._tenant("acme", shared_dir).claim_output_dir()
+        with self.assertRaises(TenantViolation):
+            self._tenant("globex", shared_dir).claim_output_dir()
+
+    def test_audit(self):
+        tenant = self._tenant()
+        tenant.claim_output_dir()
+        tenant.audit("provisioning_start", sku="acme_sku", device_id="0x1")
+        with open(tenant.audit_log, "r") as fp:
+            entries = [json.loads(line) for line in fp]
+        self.assertEqual(len(entries), 1)
+        self.assertEqual(entries[0]["tenant"], "acme")
+        self.assertEqual(entries[0]["event"], "provisioning_start")
+        self.assertEqual(entries[0]["device_id"], "0x1")
+
+    def test_invalid_config(self):
+        with self.assertRaises(ValueError):
+            self._tenant(name="Acme")
+        with self.assertRaises(ValueError):
+            TenantConfig(name="acme",
+                         skus=["acme_sku"],
+                         key_dirs=[str(self.root / "out" / "acme" / "keys")],
+                         output_dir=str(self.root / "out" / "acme"))
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,74 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Unittests for token_usage.py module."""
+
+import unittest
+from unittest import mock
+
+import db
+import token_usage
+from device_id import DeviceIdentificationNumber
+
+_TOKEN_A = 0x0123456789abcdef0123456789abcdef
+_TOKEN_B = 0xfedcba9876543210fedcba9876543210
+
+
+class TestTokenUsage(unittest.TestCase):
+
+    def setUp(self):
+        self.db = db.DB(db.DBConfig(db_path=":memory:"))
+        db.TokenUsageRecord.create_table(self.db)
+
+    def _record(self, device_id, lot, generation, unlock, exit, passed=True):
+        token_usage.record_token_usage(self.db,
+                                       device_id=device_id,
+                                       sku="sival",
+                                       din=DeviceIdentificationNumber(
+                                           year=4, week=7, lot=lot),
+                                       token_generation=generation,
+                                       test_unlock_token=unlock,
+                                       test_exit_token=exit,
+                                       passed=passed)
+
+    def test_token_id(self):
+        self.assertEqual(token_usage.token_id(_TOKEN_A),
+                         token_usage.token_id(_TOKEN_A))
+        self.assertNotEqual(token_usage.token_id(_TOKEN_A),
+                            token_usage.token_id(_TOKEN_B))
+        self.assertNotIn(
+            token_usage.token_id(_TOKEN_A),
+            format(_TOKEN_A, "032x"),
+        )
+
+    def test_lot_name(self):
+        din = DeviceIdentificationNumber(year=4, week=7, lot=123)
+        self.assertEqual(token_usage.lot_name(din), "Y4W07L123")
+
+    @mock.patch("time.time")
+    def test_summarize(self, time):
+        time.side_effect = [100, 200, 300, 400]
+        self._record("dev0", 1, "gen1", _TOKEN_A, _TOKEN_B)
+        self._record("dev0", 1, "gen1", _TOKEN_A, _TOKEN_B, passed=False)
+        self._record("dev1", 1, "gen2", _TOKEN_B, _TOKEN_A)
+        self._record("dev2", 2, "gen2", _TOKEN_B, _TOKEN_A)
+        summaries = token_usage.summarize(
+            db.TokenUsageRecord.query_all(self.db))
+
+        self.assertEqual([s["lot"] for s in summaries],
+                         ["Y4W07L001", "Y4W07L002"])
+        lot1 = summaries[0]
+        self.assertEqual(lot1["first_use"], 100)
+        self.assertEqual(lot1["last_use"], 300)
+        self.assertEqual(lot1["token_generations"], ["gen1", "gen2"])
+        gen1 = lot1["tokens"][0]
+        self.assertEqual(gen1["test_unlock_token_id"],
+                         token_usage.token_id(_TOKEN_A))
+        self.assertEqual(gen1["runs"], 2)
+        self.assertEqual(gen1["passed"], 1)
+        self.assertEqual(gen1["devices"], 1)
+        self.assertEqual(summaries[1]["token_generations"], ["gen2"])
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,114 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Unittests for worker_pool.py module."""
+
+import queue
+import threading
+import unittest
+
+from worker_pool import JobStatus, WorkerPool
+
+
+def _wait_for_cancel(job):
+    job.info("waiting")
+    job.cancel_event.wait()
+    job.check_cancelled()
+
+
+class TestWorkerPool(unittest.TestCase):
+
+    def test_results(self):
+        with WorkerPool(num_workers=2) as pool:
+            for i in range(4):
+                pool.submit(f"dut{i}", lambda job, i=i: i * 2)
+            results = {r.job_id: r for r in pool.results()}
+        self.assertEqual(len(results), 4)
+        for i in range(4):
+            self.assertEqual(results[f"dut{i}"].status, JobStatus.DONE)
+            self.assertEqual(results[f"dut{i}"].result, i * 2)
+
+    def test_failed_job(self):
+
+        def fail(job):
+            raise RuntimeError("transport lost")
+
+        with WorkerPool() as pool:
+            pool.submit("dut0", fail)
+            result = next(pool.results())
+        self.assertEqual(result.status, JobStatus.FAILED)
+        self.assertIn("transport lost", result.error)
+
+    def test_duplicate_job_id(self):
+        with WorkerPool() as pool:
+            pool.submit("dut0", lambda job: None)
+            with self.assertRaises(ValueError):
+                pool.submit("dut0", lambda job: None)
+
+    def test_cancel_running_job(self):
+        with WorkerPool(num_workers=2) as pool:
+            pool.submit("dut0", _wait_for_cancel)
+            pool.submit("dut1", lambda job: "done")
+            self.assertEqual(next(pool.results()).job_id, "dut1")
+            pool.cancel("dut0")
+            result = next(pool.results())
+        self.assertEqual(result.job_id, "dut0")
+        self.assertEqual(result.status, JobStatus.CANCELLED)
+
+    def test_cancel_queued_job(self):
+        started = threading.Event()
+
+        def block(job):
+            started.set()
+            _wait_for_cancel(job)
+
+        with WorkerPool(num_workers=1, max_pending=1) as pool:
+            pool.submit("dut0", block)
+            started.wait()
+            pool.submit("dut1", lambda job: "done")
+            pool.cancel("dut1")
+            pool.cancel("dut0")
+            results = {r.job_id: r.status for r in pool.results()}
+        self.assertEqual(results, {
+            "dut0": JobStatus.CANCELLED,
+            "dut1": JobStatus.CANCELLED,
+        })
+
+    def test_backpressure(self):
+        started = threading.Event()
+
+        def block(job):
+            started.set()
+            _wait_for_cancel(job)
+
+        with WorkerPool(num_workers=1, max_pending=1) as pool:
+            pool.submit("dut0", block)
+            started.wait()
+            pool.submit("dut1", lambda job: None)
+            with self.assertRaises(queue.Full):
+                pool.submit("dut2", lambda job: None, timeout=0.2)
+            pool.cancel_all()
+
+    def test_log_order(self):
+
+        def log(job):
+            for i in range(10):
+                job.info(f"step {i}")
+
+        with self.assertLogs(level="INFO") as logs:
+            with WorkerPool(num_workers=3) as pool:
+                for i in range(3):
+                    pool.submit(f"dut{i}", log)
+        for i in range(3):
+            steps = [
+                r.getMessage() for r in logs.records
+                if r.getMessage().startswith(f"[dut{i}] ")
+            ]
+            self.assertEqual(steps, [f"[dut{i}] step {j}" for j in range(10)])
+        self.assertTrue(
+            all(r.threadName == threading.current_thread().name
+                for r in logs.records))
+
+
+if __name__ == "__main__":
+    unittest.main()
@@ -0,0 +1,79 @@
+# Copyright lowRISC contributors (OpenTitan project).
+# Licensed under the Apache License, Version 2.0, see LICENSE for details.
+# SPDX-License-Identifier: Apache-2.0
+"""Unittests for yield_monitor.py module."""
+
+import os
+import tempfile
+import unittest
+from unittest import mock
+
+from yield_monitor import YieldAlarmConfig, YieldMonitor
+
+
+class TestYieldMonitor(unittest.TestCase):
+
+    def setUp(self):
+        self.tmp_dir = tempfile.TemporaryDirectory()
+        self.state_file = os.path.join(self.tmp_dir.name, "yield.json")
+        self.config = YieldAlarmConfig(window=10,
+                                       min_devices=5,
+                                       threshold=0.4,
+                                       actions=["beep"])
+
+    def tearDown(self):
+        self.tmp_dir.cleanup()
+
+    def _monitor(self) -> YieldMonitor:
+        return YieldMonitor(self.config, self.state_file, sku="sival")
+
+    @mock.patch.object(YieldMonitor, "_beep")
+    def test_alarm_after_min_devices(self, beep):
+        monitor = self._monitor()
+        outcomes = [False, False, True, True]
+        for i, passed in enumerate(outcomes):
+            self.assertFalse(monitor.record(f"dev{i}", passed))
+        self.assertTrue(monitor.record("dev4", True))
+        beep.assert_called_once()
+
+    @mock.patch.object(YieldMonitor, "_beep")
+    def test_alarm_fires_once_and_rearms(self, beep):
+        monitor = self._monitor()
+        for i in range(5):
+            monitor.record(f"dev{i}", False)
+        self.assertEqual(beep.call_count, 1)
+        # Recover below the threshold, then cross it again.
+        for i in range(5, 15):
+            monitor.record(f"dev{i}", True)
+        self.assertFalse(monitor.alarm_active)
+        for i in range(15, 19):
+            monitor.record(f"dev{i}", False)
+        self.assertEqual(beep.call_count, 2)
+
+    @mock.patch.object(YieldMonitor, "_beep")
+    def test_state_persists_across_runs(self, beep):
+        for i in range(4):
+            self._monitor().record(f"dev{i}", False)
+        beep.assert_not_called()
+        self.assertTrue(self._monitor().record("dev4", False))
+        self.assertEqual(len(self._monitor().outcomes), 5)
+
+    @mock.patch.object(YieldMonitor, "_webhook", side_effect=OSError("down"))
+    def test_failing_action_does_not_raise(self, webhook):
+        self.config = YieldAlarmConfig(window=5,
+                                       min_devices=1,
+                                       threshold=1.0,
+                                       actions=["webhook"],
+                                       webhook_url="http://localhost/alarm")
+        self.assertTrue(self._monitor().record("dev0", False))
+        webhook.assert_called_once()
+
+    def test_invalid_config(self):
+        with self.assertRaises(ValueError):
+            YieldAlarmConfig(window=5, min_devices=6)
+        with self.assertRaises(ValueError):
+            YieldAlarmConfig(actions=["email"])
+
+
+if __name__ == "__main__":
+    unittest.main()
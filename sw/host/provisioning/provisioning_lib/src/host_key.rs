@@ -0,0 +1,439 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! ECDSA P-256 private keys of the host, e.g. the key handoff bundles are signed with, or the raw
+//! CA keys certificates are endorsed with.
+//!
+//! A key is given as a PKCS#8 DER file, or as a key which never leaves the HSM keeping it, see
+//! `HostKeySpec`:
+//!
+//! * As the CA keys of `cert_lib`, PKCS#11 keys are used through the `pkcs11` engine of openssl,
+//!   configured for the HSM by the openssl configuration of the host (`OPENSSL_CONF`), including
+//!   its PIN.
+//! * GCP Cloud KMS keys are used through the KMS REST API with the access token of the `gcloud`
+//!   credentials of the host, and AWS KMS keys through the `aws` CLI and its credentials.
+
+use std::fmt;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+use std::str::FromStr;
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use base64ct::{Base64, Encoding};
+use openssl::bn::{BigNum, BigNumContext};
+use openssl::ecdsa::EcdsaSig;
+use openssl::nid::Nid;
+use openssl::pkey::{PKey, Public};
+use serde::Deserialize;
+
+use opentitanlib::crypto::ecdsa::{
+    EcdsaPrivateKey, EcdsaPublicKey, EcdsaRawPublicKey, EcdsaRawSignature,
+};
+use opentitanlib::crypto::sha256::{sha256, Sha256Digest};
+
+/// Endpoint of the GCP Cloud KMS REST API.
+const GCP_KMS_API: &str = "https://cloudkms.googleapis.com/v1";
+
+/// Size of the coordinates of P-256 points and of the components of P-256 signatures, in bytes.
+const P256_SIZE: usize = 32;
+
+/// An ECDSA P-256 private key of the host.
+pub trait HostKey {
+    /// Returns the public key of the key.
+    fn public_key(&self) -> Result<EcdsaPublicKey>;
+
+    /// Signs the SHA-256 digest `digest`.
+    fn sign(&self, digest: &Sha256Digest) -> Result<EcdsaRawSignature>;
+
+    /// Signs the SHA-256 digest of `data`.
+    fn digest_and_sign(&self, data: &[u8]) -> Result<EcdsaRawSignature> {
+        self.sign(&sha256(data))
+    }
+}
+
+impl HostKey for EcdsaPrivateKey {
+    fn public_key(&self) -> Result<EcdsaPublicKey> {
+        Ok(EcdsaPrivateKey::public_key(self))
+    }
+
+    fn sign(&self, digest: &Sha256Digest) -> Result<EcdsaRawSignature> {
+        EcdsaPrivateKey::sign(self, digest)
+    }
+}
+
+/// Returns the little-endian `P256_SIZE` bytes of the big-endian integer `be`, as in the raw keys
+/// and signatures of opentitanlib.
+fn to_le_bytes(be: Vec<u8>) -> Result<Vec<u8>> {
+    ensure!(be.len() <= P256_SIZE, "{} bytes P-256 integer", be.len());
+    let mut le: Vec<u8> = be.into_iter().rev().collect();
+    le.resize(P256_SIZE, 0);
+    Ok(le)
+}
+
+/// Returns the raw public key of the P-256 key `key`.
+pub fn raw_public_key(key: &PKey<Public>) -> Result<EcdsaRawPublicKey> {
+    let key = key.ec_key()?;
+    ensure!(
+        key.group().curve_name() == Some(Nid::X9_62_PRIME256V1),
+        "Not a P-256 key"
+    );
+    let (mut x, mut y) = (BigNum::new()?, BigNum::new()?);
+    let mut ctx = BigNumContext::new()?;
+    key.public_key()
+        .affine_coordinates(key.group(), &mut x, &mut y, &mut ctx)?;
+    Ok(EcdsaRawPublicKey {
+        x: to_le_bytes(x.to_vec())?,
+        y: to_le_bytes(y.to_vec())?,
+    })
+}
+
+/// Returns the raw signature of the ASN.1 DER ECDSA signature `der`.
+fn raw_signature(der: &[u8]) -> Result<EcdsaRawSignature> {
+    let signature = EcdsaSig::from_der(der).context("Invalid ECDSA signature")?;
+    Ok(EcdsaRawSignature {
+        r: to_le_bytes(signature.r().to_vec())?,
+        s: to_le_bytes(signature.s().to_vec())?,
+    })
+}
+
+/// Runs `program` with `args`, writing `input` to its standard input, and returns its output.
+fn run_tool(program: &str, args: &[&str], input: &[u8]) -> Result<Vec<u8>> {
+    let mut child = Command::new(program)
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Failed to run {program}"))?;
+    child.stdin.take().unwrap().write_all(input)?;
+    let output = child.wait_with_output()?;
+    if !output.status.success() {
+        bail!(
+            "{program} {} failed: {}",
+            args[0],
+            String::from_utf8_lossy(&output.stderr).trim()
+        );
+    }
+    Ok(output.stdout)
+}
+
+/// A key of an HSM, used through the `pkcs11` engine of openssl.
+#[derive(Debug)]
+pub struct Pkcs11Key {
+    uri: String,
+    public_key: EcdsaRawPublicKey,
+}
+
+impl Pkcs11Key {
+    /// Opens the key `uri` of the HSM, checking it is a P-256 key.
+    pub fn open(uri: &str) -> Result<Self> {
+        let der = run_tool(
+            "openssl",
+            &[
+                "pkey", "-engine", "pkcs11", "-inform", "engine", "-in", uri, "-pubout",
+                "-outform", "DER",
+            ],
+            &[],
+        )
+        .with_context(|| format!("Failed to read the public key of {uri}"))?;
+        Ok(Self {
+            uri: uri.to_string(),
+            public_key: raw_public_key(&PKey::public_key_from_der(&der)?)
+                .with_context(|| format!("Invalid key {uri}"))?,
+        })
+    }
+}
+
+impl HostKey for Pkcs11Key {
+    fn public_key(&self) -> Result<EcdsaPublicKey> {
+        Ok(EcdsaPublicKey::try_from(&self.public_key)?)
+    }
+
+    fn sign(&self, digest: &Sha256Digest) -> Result<EcdsaRawSignature> {
+        let der = run_tool(
+            "openssl",
+            &[
+                "pkeyutl", "-sign", "-engine", "pkcs11", "-keyform", "engine", "-inkey", &self.uri,
+            ],
+            &digest.to_be_bytes(),
+        )
+        .with_context(|| format!("Failed to sign with {}", self.uri))?;
+        raw_signature(&der)
+    }
+}
+
+/// Public key of a GCP KMS key version.
+#[derive(Deserialize)]
+struct GcpPublicKey {
+    pem: String,
+}
+
+/// Signature of a GCP KMS key version.
+#[derive(Deserialize)]
+struct GcpSignature {
+    signature: String,
+}
+
+/// A key version of GCP Cloud KMS, used through its REST API with the credentials of `gcloud`.
+#[derive(Debug)]
+pub struct GcpKmsKey {
+    /// Resource name of the key version,
+    /// `projects/<p>/locations/<l>/keyRings/<r>/cryptoKeys/<k>/cryptoKeyVersions/<v>`.
+    name: String,
+    public_key: EcdsaRawPublicKey,
+}
+
+impl GcpKmsKey {
+    /// Opens the key version `name`, checking it is a P-256 key.
+    pub fn open(name: &str) -> Result<Self> {
+        ensure!(
+            name.starts_with("projects/") && name.contains("/cryptoKeyVersions/"),
+            "Invalid GCP KMS key version {name}, expected \
+             projects/<p>/locations/<l>/keyRings/<r>/cryptoKeys/<k>/cryptoKeyVersions/<v>"
+        );
+        let url = format!("{GCP_KMS_API}/{name}/publicKey");
+        let public_key: GcpPublicKey = serde_json::from_slice(&Self::request(&url, None)?)
+            .with_context(|| format!("Invalid public key of {name}"))?;
+        Ok(Self {
+            name: name.to_string(),
+            public_key: raw_public_key(&PKey::public_key_from_pem(public_key.pem.as_bytes())?)
+                .with_context(|| format!("Invalid key {name}"))?,
+        })
+    }
+
+    /// Sends a request to `url` with `curl`, `POST`ing `body` if any, and returns the response.
+    ///
+    /// The access token is passed in the configuration `curl` reads from its standard input, so
+    /// it does not show in the command line of the process.
+    fn request(url: &str, body: Option<&str>) -> Result<Vec<u8>> {
+        let token = run_tool("gcloud", &["auth", "print-access-token"], &[])
+            .context("Failed to get a GCP access token")?;
+        let config = format!(
+            "header = \"Authorization: Bearer {}\"\n",
+            String::from_utf8(token)?.trim()
+        );
+        let mut args = vec!["--silent", "--show-error", "--fail", "--config", "-"];
+        if let Some(body) = body {
+            args.extend(["--header", "Content-Type: application/json", "--data", body]);
+        }
+        args.push(url);
+        run_tool("curl", &args, config.as_bytes())
+            .with_context(|| format!("GCP KMS request {url} failed"))
+    }
+}
+
+impl HostKey for GcpKmsKey {
+    fn public_key(&self) -> Result<EcdsaPublicKey> {
+        Ok(EcdsaPublicKey::try_from(&self.public_key)?)
+    }
+
+    fn sign(&self, digest: &Sha256Digest) -> Result<EcdsaRawSignature> {
+        let body = serde_json::json!({
+            "digest": { "sha256": Base64::encode_string(&digest.to_be_bytes()) },
+        });
+        let url = format!("{GCP_KMS_API}/{}:asymmetricSign", self.name);
+        let response = Self::request(&url, Some(&body.to_string()))
+            .with_context(|| format!("Failed to sign with {}", self.name))?;
+        let signature: GcpSignature = serde_json::from_slice(&response)?;
+        raw_signature(
+            &Base64::decode_vec(&signature.signature)
+                .map_err(|e| anyhow!("Invalid base64 signature of {}: {e}", self.name))?,
+        )
+    }
+}
+
+/// A key of AWS KMS, used through the `aws` CLI with its configured credentials.
+#[derive(Debug)]
+pub struct AwsKmsKey {
+    /// ID, ARN or alias of the key.
+    key_id: String,
+    public_key: EcdsaRawPublicKey,
+}
+
+impl AwsKmsKey {
+    /// Opens the key `key_id`, checking it is a P-256 key.
+    pub fn open(key_id: &str) -> Result<Self> {
+        let der = Self::aws_kms(&["get-public-key", "--key-id", key_id, "--query", "PublicKey"])
+            .with_context(|| format!("Failed to read the public key of {key_id}"))?;
+        Ok(Self {
+            key_id: key_id.to_string(),
+            public_key: raw_public_key(&PKey::public_key_from_der(&der)?)
+                .with_context(|| format!("Invalid key {key_id}"))?,
+        })
+    }
+
+    /// Runs `aws kms` with `args`, and returns the base64 decoded value it outputs.
+    fn aws_kms(args: &[&str]) -> Result<Vec<u8>> {
+        let mut all_args = vec!["kms"];
+        all_args.extend_from_slice(args);
+        all_args.extend(["--output", "text"]);
+        let output = run_tool("aws", &all_args, &[])?;
+        Base64::decode_vec(String::from_utf8(output)?.trim())
+            .map_err(|e| anyhow!("Invalid base64 output of aws kms {}: {e}", args[0]))
+    }
+}
+
+impl HostKey for AwsKmsKey {
+    fn public_key(&self) -> Result<EcdsaPublicKey> {
+        Ok(EcdsaPublicKey::try_from(&self.public_key)?)
+    }
+
+    fn sign(&self, digest: &Sha256Digest) -> Result<EcdsaRawSignature> {
+        // The digest is passed inline, base64 encoded as set by `--cli-binary-format`.
+        let message = Base64::encode_string(&digest.to_be_bytes());
+        let der = Self::aws_kms(&[
+            "sign",
+            "--key-id",
+            &self.key_id,
+            "--cli-binary-format",
+            "base64",
+            "--message",
+            &message,
+            "--message-type",
+            "DIGEST",
+            "--signing-algorithm",
+            "ECDSA_SHA_256",
+            "--query",
+            "Signature",
+        ])
+        .with_context(|| format!("Failed to sign with {}", self.key_id))?;
+        raw_signature(&der)
+    }
+}
+
+/// Where a host key is:
+///
+/// * a PKCS#8 DER file, given by its path;
+/// * a key of a PKCS#11 HSM, given by its URI, e.g. `pkcs11:object=handoff-signing-key`;
+/// * a GCP Cloud KMS key version, e.g.
+///   `gcpkms:projects/<p>/locations/<l>/keyRings/<r>/cryptoKeys/<k>/cryptoKeyVersions/<v>`;
+/// * an AWS KMS key, given by its ID, ARN or alias, e.g. `awskms:alias/handoff-signing-key`.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum HostKeySpec {
+    File(PathBuf),
+    Pkcs11(String),
+    GcpKms(String),
+    AwsKms(String),
+}
+
+impl FromStr for HostKeySpec {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(if s.starts_with("pkcs11:") {
+            Self::Pkcs11(s.to_string())
+        } else if let Some(name) = s.strip_prefix("gcpkms:") {
+            Self::GcpKms(name.to_string())
+        } else if let Some(key_id) = s.strip_prefix("awskms:") {
+            Self::AwsKms(key_id.to_string())
+        } else {
+            Self::File(PathBuf::from(s))
+        })
+    }
+}
+
+impl fmt::Display for HostKeySpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::File(path) => write!(f, "{}", path.display()),
+            Self::Pkcs11(uri) => f.write_str(uri),
+            Self::GcpKms(name) => write!(f, "gcpkms:{name}"),
+            Self::AwsKms(key_id) => write!(f, "awskms:{key_id}"),
+        }
+    }
+}
+
+impl HostKeySpec {
+    /// Loads the key file, or opens the key of the HSM or KMS.
+    pub fn load(&self) -> Result<Box<dyn HostKey>> {
+        Ok(match self {
+            Self::File(path) => Box::new(
+                EcdsaPrivateKey::load(path)
+                    .with_context(|| format!("Failed to load host key {path:?}"))?,
+            ),
+            Self::Pkcs11(uri) => Box::new(Pkcs11Key::open(uri)?),
+            Self::GcpKms(name) => Box::new(GcpKmsKey::open(name)?),
+            Self::AwsKms(key_id) => Box::new(AwsKmsKey::open(key_id)?),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use openssl::ec::{EcGroup, EcKey};
+
+    /// Coordinates of the generator of P-256, big-endian.
+    const P256_GX: &str = "6b17d1f2e12c4247f8bce6e563a440f277037d812deb33a0f4a13945d898c296";
+    const P256_GY: &str = "4fe342e2fe1a7f9b8ee7eb4a7c0f9e162bce33576b315ececbb6406837bf51f5";
+
+    fn reversed(hex_be: &str) -> Vec<u8> {
+        hex::decode(hex_be).unwrap().into_iter().rev().collect()
+    }
+
+    #[test]
+    fn test_to_le_bytes() {
+        let mut expected = vec![0u8; P256_SIZE];
+        expected[..3].copy_from_slice(&[3, 2, 1]);
+        assert_eq!(to_le_bytes(vec![1, 2, 3]).unwrap(), expected);
+        assert_eq!(to_le_bytes(Vec::new()).unwrap(), vec![0u8; P256_SIZE]);
+        assert_eq!(
+            to_le_bytes(hex::decode(P256_GX).unwrap()).unwrap(),
+            reversed(P256_GX)
+        );
+        assert!(to_le_bytes(vec![1; P256_SIZE + 1]).is_err());
+    }
+
+    #[test]
+    fn test_raw_public_key() {
+        let group = EcGroup::from_curve_name(Nid::X9_62_PRIME256V1).unwrap();
+        let key = EcKey::from_public_key(&group, group.generator()).unwrap();
+        let raw = raw_public_key(&PKey::from_ec_key(key).unwrap()).unwrap();
+        assert_eq!(raw.x, reversed(P256_GX));
+        assert_eq!(raw.y, reversed(P256_GY));
+
+        let group = EcGroup::from_curve_name(Nid::SECP384R1).unwrap();
+        let key = EcKey::from_public_key(&group, group.generator()).unwrap();
+        let err = raw_public_key(&PKey::from_ec_key(key).unwrap()).unwrap_err();
+        assert_eq!(err.to_string(), "Not a P-256 key");
+    }
+
+    #[test]
+    fn test_raw_signature() {
+        // SEQUENCE { INTEGER 0x0102..20, INTEGER 0x81 }; DER prefixes 0x81 with a zero byte.
+        let r: Vec<u8> = (1..=32).collect();
+        let mut der = vec![0x30, 0x26, 0x02, 0x20];
+        der.extend_from_slice(&r);
+        der.extend_from_slice(&[0x02, 0x02, 0x00, 0x81]);
+        let signature = raw_signature(&der).unwrap();
+        assert_eq!(signature.r, r.into_iter().rev().collect::<Vec<u8>>());
+        let mut s = vec![0u8; P256_SIZE];
+        s[0] = 0x81;
+        assert_eq!(signature.s, s);
+
+        assert!(raw_signature(&der[..der.len() - 1]).is_err());
+        // A 33-byte component does not fit P-256.
+        let mut der = vec![0x30, 0x27, 0x02, 0x21, 0x01];
+        der.extend_from_slice(&[0; 32]);
+        der.extend_from_slice(&[0x02, 0x01, 0x01]);
+        assert!(raw_signature(&der).is_err());
+    }
+
+    #[test]
+    fn test_host_key_spec() {
+        for spec in [
+            "key.der",
+            "pkcs11:object=handoff-signing-key",
+            "gcpkms:projects/p/locations/l/keyRings/r/cryptoKeys/k/cryptoKeyVersions/1",
+            "awskms:alias/handoff-signing-key",
+        ] {
+            assert_eq!(spec.parse::<HostKeySpec>().unwrap().to_string(), spec);
+        }
+        assert_eq!(
+            "awskms:alias/k".parse::<HostKeySpec>().unwrap(),
+            HostKeySpec::AwsKms("alias/k".into())
+        );
+    }
+}
@@ -0,0 +1,21 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Host code shared by the provisioning stages, CP and FT.
+//!
+//! `cp_lib` and `ft_lib` build on the types of this crate instead of keeping their own, and
+//! re-export its modules under their own paths:
+//! - `session`: the transport and JTAG parameters a stage drives the device with;
+//! - `timeouts`: the step timeouts, set on the command line or in a JSON file;
+//! - `report`: the JSON report lines the tools print for the orchestrator;
+//! - `host_key`: the providers of the host keys signing on behalf of the station;
+//! - `secret`: secret values, wiped from memory on drop;
+//! - `token_escrow`: the tokens CP provisioned, for FT to look them up.
+
+pub mod host_key;
+pub mod report;
+pub mod secret;
+pub mod session;
+pub mod timeouts;
+pub mod token_escrow;
@@ -0,0 +1,89 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! JSON reports of the provisioning tools.
+//!
+//! Each tool prints its report as a single line of stdout, prefixed so the orchestrator can find
+//! it among the logs: `CHIP_PROBE_DATA: {...}` for `cp`, `PROVISIONING_DATA: {...}` for `ft`.
+
+use std::time::Instant;
+
+use anyhow::Result;
+use indexmap::IndexMap;
+use serde::Serialize;
+
+/// Prefix of the report line printed by the `cp` tool.
+pub const CP_REPORT_PREFIX: &str = "CHIP_PROBE_DATA: ";
+/// Prefix of the report line printed by the `ft` tool.
+pub const FT_REPORT_PREFIX: &str = "PROVISIONING_DATA: ";
+
+#[derive(Clone, Debug, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stat {
+    Microseconds(u64),
+    String(String),
+}
+
+#[derive(Clone, Debug, Serialize, Default)]
+pub struct Statistics(IndexMap<String, Stat>);
+
+impl Statistics {
+    pub fn log_elapsed_time(&mut self, name: &str, start: Instant) {
+        let end = Instant::now();
+        let duration = end - start;
+        self.0
+            .insert(name.into(), Stat::Microseconds(duration.as_micros() as u64));
+    }
+
+    pub fn log_string(&mut self, name: &str, val: &str) {
+        self.0.insert(name.into(), Stat::String(val.into()));
+    }
+}
+
+/// Returns the report line of `report`, after `prefix`.
+///
+/// A `pretty` report spans several lines, for people to read rather than the orchestrator.
+pub fn report_line(prefix: &str, report: &impl Serialize, pretty: bool) -> Result<String> {
+    let doc = if pretty {
+        serde_json::to_string_pretty(report)?
+    } else {
+        serde_json::to_string(report)?
+    };
+    Ok(format!("{prefix}{doc}"))
+}
+
+/// Returns the JSON report of the report line of `prefix` in the logs `doc`, or `doc` itself if
+/// it has no such line, e.g. a report saved as JSON.
+pub fn find_report<'a>(prefix: &str, doc: &'a str) -> &'a str {
+    doc.lines()
+        .find_map(|line| line.trim().strip_prefix(prefix))
+        .unwrap_or(doc)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_report_line() -> Result<()> {
+        let mut stats = Statistics::default();
+        stats.log_string("key-bundle", "fake");
+        let report = json!({ "device_id": "0x01", "stats": stats });
+        let line = report_line(FT_REPORT_PREFIX, &report, false)?;
+        assert_eq!(
+            line,
+            r#"PROVISIONING_DATA: {"device_id":"0x01","stats":{"key-bundle":{"string":"fake"}}}"#
+        );
+
+        let logs = format!("I00000 boot\n{line}\nProvisioning Done\n");
+        let found: serde_json::Value = serde_json::from_str(find_report(FT_REPORT_PREFIX, &logs))?;
+        assert_eq!(found, report);
+        // A report saved as JSON, and the report of the other tool.
+        let doc = serde_json::to_string(&report)?;
+        assert_eq!(find_report(FT_REPORT_PREFIX, &doc), doc);
+        assert_eq!(find_report(CP_REPORT_PREFIX, &logs), logs);
+        Ok(())
+    }
+}
@@ -0,0 +1,106 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Secret values of the flows, wiped from memory on drop and never logged.
+//!
+//! The raw LC tokens, the unwrapped RMA unlock tokens and the host secret keys are held as
+//! `Secret`s: their memory is zeroized when they are dropped, and their `Debug` output is
+//! redacted, so the `{:?}` of a value holding one can't log them. Their value is reached with
+//! `expose` where it is used; copies made from it, e.g. the token passed to the JTAG transport or
+//! the key expanded by OpenSSL, are not wiped.
+
+use std::fmt::{self, Write};
+
+use anyhow::{Context, Result};
+use arrayvec::ArrayVec;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use zeroize::Zeroize;
+
+use ujson_lib::limits::LC_TOKEN_WORDS;
+
+/// A secret value, zeroized on drop.
+pub struct Secret<T: Zeroize>(T);
+
+impl<T: Zeroize> Secret<T> {
+    pub fn new(value: T) -> Self {
+        Self(value)
+    }
+
+    /// Returns the secret value.
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+
+    /// Returns the secret value, to be written in place.
+    pub fn expose_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl<T: Zeroize> Drop for Secret<T> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl<T: Zeroize> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret(<redacted>)")
+    }
+}
+
+impl<T: Zeroize + Clone> Clone for Secret<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T: Zeroize> From<T> for Secret<T> {
+    fn from(value: T) -> Self {
+        Self(value)
+    }
+}
+
+/// Secrets are serialized as their value, e.g. in the RMA escrow envelopes.
+impl<T: Zeroize + Serialize> Serialize for Secret<T> {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.0.serialize(serializer)
+    }
+}
+
+impl<'de, T: Zeroize + Deserialize<'de>> Deserialize<'de> for Secret<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Self)
+    }
+}
+
+/// A raw life cycle token: the TRANSITION_TOKEN register values.
+pub type LcTokenSecret = Secret<[u32; LC_TOKEN_WORDS]>;
+
+impl Secret<[u32; LC_TOKEN_WORDS]> {
+    /// Moves the words of `token` to a secret, wiping them from `token`.
+    pub fn from_words(mut token: ArrayVec<u32, LC_TOKEN_WORDS>) -> Result<Self> {
+        let secret = <[u32; LC_TOKEN_WORDS]>::try_from(token.as_slice()).map(Self);
+        let len = token.len();
+        token.as_mut_slice().zeroize();
+        secret.ok().with_context(|| {
+            format!(
+                "LC token is {} bits long, expected {}",
+                len * 32,
+                LC_TOKEN_WORDS * 32
+            )
+        })
+    }
+
+    /// Returns the token as a hex string, in the format of `util_lib::parse_lc_token`.
+    pub fn to_hex(&self) -> Secret<String> {
+        // Formatted in place, without reallocations leaving copies behind.
+        let mut hex = Secret::new(String::with_capacity(2 + 8 * LC_TOKEN_WORDS));
+        hex.0.push_str("0x");
+        for word in &self.0 {
+            write!(hex.0, "{word:08x}").unwrap();
+        }
+        hex
+    }
+}
@@ -0,0 +1,40 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! The transport and JTAG parameters a provisioning stage drives the device with.
+
+use std::time::Duration;
+
+use anyhow::Result;
+
+use opentitanlib::app::TransportWrapper;
+use opentitanlib::dif::lc_ctrl::DifLcCtrlState;
+use opentitanlib::io::jtag::JtagParams;
+use opentitanlib::test_utils::init::InitializeTest;
+use opentitanlib::test_utils::lc::read_lc_state;
+
+/// A session with the device being provisioned.
+#[derive(Clone, Copy)]
+pub struct DeviceSession<'a> {
+    pub transport: &'a TransportWrapper,
+    pub jtag_params: &'a JtagParams,
+    /// Delay after resetting the device.
+    pub reset_delay: Duration,
+}
+
+impl<'a> DeviceSession<'a> {
+    /// Returns the session of `transport`, as initialized from the options `init` of the tool.
+    pub fn new(transport: &'a TransportWrapper, init: &'a InitializeTest) -> Self {
+        Self {
+            transport,
+            jtag_params: &init.jtag_params,
+            reset_delay: init.bootstrap.options.reset_delay,
+        }
+    }
+
+    /// Reads the LC state of the device over the LC TAP, resetting it.
+    pub fn read_lc_state(&self) -> Result<DifLcCtrlState> {
+        read_lc_state(self.transport, self.jtag_params, self.reset_delay)
+    }
+}
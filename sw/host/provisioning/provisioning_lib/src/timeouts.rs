@@ -0,0 +1,219 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Timeouts of the steps of the flow.
+//!
+//! OTP writes and the perso crypto take wildly different times on silicon and on FPGA, so each
+//! class of wait has a timeout of its own. A step timeout is set on the command line, else in a
+//! JSON timeouts file, else defaults to `--timeout` (the LC transition timeout defaults to
+//! `LC_TRANSITION_TIMEOUT` instead):
+//!
+//! ```json
+//! { "otp_write": "30s", "perso_data_exchange": "5m", "lc_transition": "10s" }
+//! ```
+
+use std::path::PathBuf;
+use std::time::Duration;
+
+use anyhow::{Context, Result};
+use clap::Args;
+use humantime::parse_duration;
+use serde::Deserialize;
+
+use opentitanlib::test_utils::lc_transition::LC_TRANSITION_TIMEOUT;
+
+/// Resolved timeouts of the steps of the flow.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Timeouts {
+    /// SRAM programs loaded over JTAG to start and ask for their inputs.
+    pub sram_start: Duration,
+    /// SRAM programs and the personalization firmware to program OTP.
+    pub otp_write: Duration,
+    /// Exchanges of perso data with the personalization firmware, including the host side crypto
+    /// endorsing the certificates.
+    pub perso_data_exchange: Duration,
+    /// Bootstrapped firmware to boot up to its first console message.
+    pub bootstrap_boot: Duration,
+    /// LC transitions to complete.
+    pub lc_transition: Duration,
+}
+
+impl Timeouts {
+    /// Timeouts of `timeout` for all console waits, and of `LC_TRANSITION_TIMEOUT` for the LC
+    /// transitions.
+    pub fn uniform(timeout: Duration) -> Self {
+        Self {
+            sram_start: timeout,
+            otp_write: timeout,
+            perso_data_exchange: timeout,
+            bootstrap_boot: timeout,
+            lc_transition: LC_TRANSITION_TIMEOUT,
+        }
+    }
+}
+
+/// Step timeouts of a JSON timeouts file; the unset ones fall back to the defaults.
+#[derive(Clone, Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TimeoutsFile {
+    #[serde(default, with = "humantime_serde")]
+    sram_start: Option<Duration>,
+    #[serde(default, with = "humantime_serde")]
+    otp_write: Option<Duration>,
+    #[serde(default, with = "humantime_serde")]
+    perso_data_exchange: Option<Duration>,
+    #[serde(default, with = "humantime_serde")]
+    bootstrap_boot: Option<Duration>,
+    #[serde(default, with = "humantime_serde")]
+    lc_transition: Option<Duration>,
+}
+
+/// Command line options setting the timeouts of the steps of the flow.
+#[derive(Clone, Debug, Args)]
+pub struct TimeoutArgs {
+    /// Default timeout of the console waits of the steps, see the `--*-timeout` options.
+    #[arg(long, value_parser = parse_duration, default_value = "600s")]
+    pub timeout: Duration,
+
+    /// JSON file setting the timeouts of the steps, overridden by the `--*-timeout` options.
+    #[arg(long)]
+    pub timeouts_config: Option<PathBuf>,
+
+    /// Timeout of the SRAM programs to start and ask for their inputs.
+    #[arg(long, value_parser = parse_duration)]
+    pub sram_start_timeout: Option<Duration>,
+
+    /// Timeout of the OTP writes of the SRAM programs and the personalization firmware.
+    #[arg(long, value_parser = parse_duration)]
+    pub otp_write_timeout: Option<Duration>,
+
+    /// Timeout of the perso data exchanges with the personalization firmware.
+    #[arg(long, value_parser = parse_duration)]
+    pub perso_data_exchange_timeout: Option<Duration>,
+
+    /// Timeout of a bootstrapped firmware to boot.
+    #[arg(long, value_parser = parse_duration)]
+    pub bootstrap_boot_timeout: Option<Duration>,
+
+    /// Timeout of an LC transition to complete (default: 3s).
+    #[arg(long, value_parser = parse_duration)]
+    pub lc_transition_timeout: Option<Duration>,
+}
+
+impl TimeoutArgs {
+    /// Resolves the step timeouts from the options, the timeouts file and the defaults.
+    pub fn resolve(&self) -> Result<Timeouts> {
+        let file = match &self.timeouts_config {
+            Some(path) => {
+                let doc = std::fs::read_to_string(path)
+                    .with_context(|| format!("Failed to read timeouts {path:?}"))?;
+                serde_json::from_str(&doc)
+                    .with_context(|| format!("Failed to parse timeouts {path:?}"))?
+            }
+            None => TimeoutsFile::default(),
+        };
+        let default = Timeouts::uniform(self.timeout);
+        let timeouts = Timeouts {
+            sram_start: self
+                .sram_start_timeout
+                .or(file.sram_start)
+                .unwrap_or(default.sram_start),
+            otp_write: self
+                .otp_write_timeout
+                .or(file.otp_write)
+                .unwrap_or(default.otp_write),
+            perso_data_exchange: self
+                .perso_data_exchange_timeout
+                .or(file.perso_data_exchange)
+                .unwrap_or(default.perso_data_exchange),
+            bootstrap_boot: self
+                .bootstrap_boot_timeout
+                .or(file.bootstrap_boot)
+                .unwrap_or(default.bootstrap_boot),
+            lc_transition: self
+                .lc_transition_timeout
+                .or(file.lc_transition)
+                .unwrap_or(default.lc_transition),
+        };
+        log::info!("Step timeouts: {timeouts:?}");
+        Ok(timeouts)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentitanlib::util::tmpfilename;
+
+    fn args(timeouts_config: Option<PathBuf>) -> TimeoutArgs {
+        TimeoutArgs {
+            timeout: Duration::from_secs(600),
+            timeouts_config,
+            sram_start_timeout: None,
+            otp_write_timeout: None,
+            perso_data_exchange_timeout: None,
+            bootstrap_boot_timeout: None,
+            lc_transition_timeout: None,
+        }
+    }
+
+    fn config(name: &str, doc: &str) -> PathBuf {
+        let path = PathBuf::from(tmpfilename(name));
+        std::fs::write(&path, doc).unwrap();
+        path
+    }
+
+    #[test]
+    fn test_defaults() {
+        let timeouts = args(None).resolve().unwrap();
+        assert_eq!(timeouts, Timeouts::uniform(Duration::from_secs(600)));
+        assert_eq!(timeouts.lc_transition, LC_TRANSITION_TIMEOUT);
+    }
+
+    #[test]
+    fn test_timeouts_config() {
+        let path = config(
+            "test_timeouts_config.json",
+            r#"{ "otp_write": "30s", "perso_data_exchange": "5m", "lc_transition": "10s" }"#,
+        );
+        let timeouts = TimeoutArgs {
+            otp_write_timeout: Some(Duration::from_secs(45)),
+            ..args(Some(path))
+        }
+        .resolve()
+        .unwrap();
+        assert_eq!(
+            timeouts,
+            Timeouts {
+                sram_start: Duration::from_secs(600),
+                // The command line overrides the file.
+                otp_write: Duration::from_secs(45),
+                perso_data_exchange: Duration::from_secs(300),
+                bootstrap_boot: Duration::from_secs(600),
+                lc_transition: Duration::from_secs(10),
+            }
+        );
+    }
+
+    #[test]
+    fn test_timeouts_config_invalid() {
+        let path = config("test_timeouts_config_unknown.json", r#"{ "otp": "30s" }"#);
+        let err = args(Some(path)).resolve().unwrap_err();
+        assert!(
+            format!("{err:#}").contains("unknown field `otp`"),
+            "{err:#}"
+        );
+
+        let path = config(
+            "test_timeouts_config_duration.json",
+            r#"{ "otp_write": "30" }"#,
+        );
+        assert!(args(Some(path)).resolve().is_err());
+
+        let path = PathBuf::from(tmpfilename("test_timeouts_config_missing.json"));
+        let _ = std::fs::remove_file(&path);
+        let err = args(Some(path)).resolve().unwrap_err();
+        assert!(err.to_string().contains("Failed to read timeouts"), "{err}");
+    }
+}
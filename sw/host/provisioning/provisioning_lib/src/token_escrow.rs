@@ -0,0 +1,137 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Escrow of the test unlock / exit tokens provisioned during CP.
+//!
+//! When CP and FT run on the same infrastructure, CP appends the tokens it provisioned to an
+//! escrow file (one JSON record per line), keyed by the CP device ID it programs into flash info.
+//! FT looks the tokens up by the CP device ID of the device ID it individualizes the device with,
+//! instead of requiring them on the command line: FT individualization checks that the device ID
+//! starts with the CP device ID. Neither reads the device ID of HW_CFG0, which FT only programs
+//! at individualization, after it needs the tokens.
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::path::Path;
+
+use anyhow::{ensure, Context, Result};
+use serde::{Deserialize, Serialize};
+
+use ujson_lib::limits::CP_DEVICE_ID_WORDS;
+use util_lib::format_device_id;
+
+/// Tokens provisioned into a device during CP.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EscrowRecord {
+    /// CP device ID, formatted as in the CP reports, see `format_cp_device_id`.
+    pub cp_device_id: String,
+    /// TestUnlock token; a 128-bit hex string.
+    pub test_unlock_token: String,
+    /// TestExit token; a 128-bit hex string.
+    pub test_exit_token: String,
+}
+
+impl EscrowRecord {
+    /// Appends the record to the escrow file at `path`, creating it if needed.
+    pub fn append(&self, path: &Path) -> Result<()> {
+        let mut file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .with_context(|| format!("Failed to open token escrow {path:?}"))?;
+        // A single write per record, so concurrent CP runs do not interleave records.
+        let line = format!("{}\n", serde_json::to_string(self)?);
+        file.write_all(line.as_bytes())
+            .with_context(|| format!("Failed to write token escrow {path:?}"))
+    }
+
+    /// Looks up the tokens of the device of CP device ID `cp_device_id` in the escrow file at
+    /// `path`.
+    ///
+    /// The most recent record of the device is returned, as CP may run again on a device from a
+    /// later TEST_UNLOCKED* state.
+    pub fn lookup(path: &Path, cp_device_id: &str) -> Result<EscrowRecord> {
+        let doc = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read token escrow {path:?}"))?;
+        let mut found = None;
+        for (n, line) in doc.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: EscrowRecord = serde_json::from_str(line)
+                .with_context(|| format!("Malformed token escrow record {path:?}:{}", n + 1))?;
+            if record.cp_device_id.eq_ignore_ascii_case(cp_device_id) {
+                found = Some(record);
+            }
+        }
+        found.with_context(|| {
+            format!("No tokens escrowed for CP device ID {cp_device_id} in {path:?}")
+        })
+    }
+}
+
+/// Formats the CP device ID words exported by the device during CP, as in the CP reports: the
+/// most significant word first.
+pub fn format_cp_device_id(cp_device_id: &[u32]) -> String {
+    let words: Vec<u32> = cp_device_id.iter().rev().copied().collect();
+    format_device_id(&words)
+}
+
+/// Returns the CP device ID of the device ID `device_id` of FT individualization, formatted as in
+/// the CP reports.
+pub fn cp_device_id_of(device_id: &[u32]) -> Result<String> {
+    ensure!(
+        device_id.len() >= CP_DEVICE_ID_WORDS,
+        "A device ID of {} words has no CP device ID",
+        device_id.len()
+    );
+    Ok(format_cp_device_id(&device_id[..CP_DEVICE_ID_WORDS]))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use opentitanlib::util::tmpfilename;
+
+    #[test]
+    fn test_cp_store_ft_lookup() -> Result<()> {
+        let path = std::path::PathBuf::from(tmpfilename("test_token_escrow.jsonl"));
+        let _ = std::fs::remove_file(&path);
+
+        // CP exports the CP device ID it programs into flash info, HW_CFG0 being unprogrammed.
+        let cp_device_id = [0x0002_4001, 0x0B00_0001, 0x0012_3456, 0];
+        let record = EscrowRecord {
+            cp_device_id: format_cp_device_id(&cp_device_id),
+            test_unlock_token: "0x11111111_11111111_11111111_11111111".into(),
+            test_exit_token: "0x22222222_22222222_22222222_22222222".into(),
+        };
+        assert_eq!(record.cp_device_id, "00000000001234560B00000100024001");
+        record.append(&path)?;
+        // CP runs again on the device, from a later TEST_UNLOCKED* state.
+        let again = EscrowRecord {
+            test_exit_token: "0x33333333_33333333_33333333_33333333".into(),
+            ..record.clone()
+        };
+        again.append(&path)?;
+
+        // FT individualizes the device with a device ID starting with the CP device ID.
+        let device_id = [
+            0x0002_4001,
+            0x0B00_0001,
+            0x0012_3456,
+            0,
+            0x0000_0001,
+            0x5349_5641,
+            0,
+            0,
+        ];
+        let found = EscrowRecord::lookup(&path, &cp_device_id_of(&device_id)?)?;
+        assert_eq!(found, again);
+
+        let err = EscrowRecord::lookup(&path, &format_cp_device_id(&[1, 2, 3, 4])).unwrap_err();
+        assert!(err.to_string().contains("No tokens escrowed"), "{err}");
+        assert!(cp_device_id_of(&[1, 2]).is_err());
+        Ok(())
+    }
+}
@@ -0,0 +1,138 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Sizes of the fixed-size fields of the provisioning ujson payloads.
+//!
+//! The payloads are declared in sw/device/lib/testing/json/provisioning_data.h, and their array
+//! fields are generated as `ArrayVec`s of the firmware buffer sizes. The host code sizes its
+//! buffers with the constants and types below instead of literal sizes, and each constant is checked
+//! at compile time against the fields it sizes: a firmware-side size change fails the build with
+//! the name of the constant to update, rather than truncating or rejecting payloads at run time.
+//!
+//! Payloads received from the device are bounded by the same sizes: an array larger than its field
+//! fails to deserialize. Values sent to the device are converted with [`fit`] or [`exact`], which
+//! name the field that doesn't fit.
+
+use anyhow::{ensure, Result};
+use arrayvec::ArrayVec;
+
+use opentitanlib::otp::lc_token::LC_TOKEN_SIZE;
+
+use crate::provisioning_data::{
+    LcTokenHash, ManufCertgenInputs, ManufCpProvisioningData, ManufCpProvisioningDataOut,
+    ManufFtIndividualizeData, ManufHealthSnapshot, ManufKeymgrBinding, PersoBlob, PersoBlobChunk,
+    PersoBlobImportChunk,
+};
+
+/// Words of a device ID.
+pub const DEVICE_ID_WORDS: usize = 8;
+/// Words of the CP device ID, the first words of the device ID.
+pub const CP_DEVICE_ID_WORDS: usize = 4;
+/// Words of the wafer authentication secret.
+pub const WAFER_AUTH_SECRET_WORDS: usize = 8;
+/// Words of a life cycle token, as written to the TRANSITION_TOKEN registers.
+pub const LC_TOKEN_WORDS: usize = 4;
+/// Words of a hashed life cycle token, as stored in OTP.
+pub const LC_TOKEN_HASH_WORDS: usize = 2;
+/// Words of a SHA-256 measurement attested by the certificates.
+pub const MEASUREMENT_WORDS: usize = 8;
+/// Words of a keymgr sealing binding value.
+pub const KEYMGR_BINDING_WORDS: usize = 8;
+/// Bytes of the key ID of a CA key.
+pub const KEY_ID_BYTES: usize = 20;
+/// Words of the AST configuration of the CREATOR_SW_CFG partition.
+pub const AST_CFG_WORDS: usize = 39;
+/// Bytes of the body of a perso blob.
+pub const PERSO_BLOB_BODY_SIZE: usize = 4096;
+/// Bytes of the data of a perso blob chunk, exported or imported.
+pub const PERSO_BLOB_CHUNK_SIZE: usize = 512;
+
+pub type DeviceId = ArrayVec<u32, DEVICE_ID_WORDS>;
+pub type WaferAuthSecret = ArrayVec<u32, WAFER_AUTH_SECRET_WORDS>;
+pub type LcTokenWords = ArrayVec<u32, LC_TOKEN_WORDS>;
+pub type LcTokenHashWords = ArrayVec<u64, LC_TOKEN_HASH_WORDS>;
+pub type Measurement = ArrayVec<u32, MEASUREMENT_WORDS>;
+pub type KeymgrBindingWords = ArrayVec<u32, KEYMGR_BINDING_WORDS>;
+pub type KeyId = ArrayVec<u8, KEY_ID_BYTES>;
+pub type PersoBlobBody = ArrayVec<u8, PERSO_BLOB_BODY_SIZE>;
+pub type PersoBlobChunkData = ArrayVec<u8, PERSO_BLOB_CHUNK_SIZE>;
+
+/// Returns the capacity of the field `_field` selects.
+const fn capacity<S, T, const N: usize>(_field: fn(&S) -> &ArrayVec<T, N>) -> usize {
+    N
+}
+
+/// Checks at compile time that `$size` is the capacity of each of the struct fields.
+macro_rules! check_size {
+    ($size:ident: $($struct:ident.$field:ident),+ $(,)?) => {
+        $(
+            const _: () = assert!(
+                capacity(|s: &$struct| &s.$field) == $size,
+                concat!(
+                    stringify!($size),
+                    " does not match the size of ",
+                    stringify!($struct),
+                    ".",
+                    stringify!($field),
+                    " in provisioning_data.h"
+                )
+            );
+        )+
+    };
+}
+
+check_size!(DEVICE_ID_WORDS: ManufFtIndividualizeData.device_id, ManufKeymgrBinding.device_id);
+check_size!(CP_DEVICE_ID_WORDS: ManufCpProvisioningDataOut.cp_device_id);
+check_size!(WAFER_AUTH_SECRET_WORDS: ManufCpProvisioningData.wafer_auth_secret);
+check_size!(
+    LC_TOKEN_HASH_WORDS: ManufCpProvisioningData.test_unlock_token_hash,
+    ManufCpProvisioningData.test_exit_token_hash,
+    LcTokenHash.hash,
+);
+check_size!(
+    MEASUREMENT_WORDS: ManufCertgenInputs.rom_ext_measurement,
+    ManufCertgenInputs.owner_manifest_measurement,
+    ManufCertgenInputs.owner_measurement,
+    ManufHealthSnapshot.rom_ext_measurement,
+);
+check_size!(
+    KEYMGR_BINDING_WORDS: ManufKeymgrBinding.owner_int_sealing,
+    ManufKeymgrBinding.owner_sealing,
+);
+check_size!(
+    KEY_ID_BYTES: ManufCertgenInputs.dice_auth_key_key_id,
+    ManufCertgenInputs.ext_auth_key_key_id,
+);
+check_size!(
+    AST_CFG_WORDS: ManufFtIndividualizeData.ast_trim_mask,
+    ManufFtIndividualizeData.ast_trim_value,
+);
+check_size!(PERSO_BLOB_BODY_SIZE: PersoBlob.body);
+check_size!(PERSO_BLOB_CHUNK_SIZE: PersoBlobChunk.data, PersoBlobImportChunk.data);
+
+// LC tokens are not a ujson payload, but are sized as the opentitanlib LC transitions expect.
+const _: () = assert!(
+    LC_TOKEN_WORDS * 4 == LC_TOKEN_SIZE,
+    "LC_TOKEN_WORDS does not match LC_TOKEN_SIZE of opentitanlib"
+);
+
+/// Copies `values` to an `N`-element field, failing if they don't fit in it.
+pub fn fit<T: Clone, const N: usize>(name: &str, values: &[T]) -> Result<ArrayVec<T, N>> {
+    ensure!(
+        values.len() <= N,
+        "{name} is {} elements long, the firmware accepts at most {N}",
+        values.len()
+    );
+    Ok(values.iter().cloned().collect())
+}
+
+/// Copies `values` to an `N`-element field, failing unless they fill it exactly.
+pub fn exact<T: Clone, const N: usize>(name: &str, values: &[T]) -> Result<ArrayVec<T, N>> {
+    ensure!(
+        values.len() == N,
+        "{name} is {} elements long, the firmware expects {N}",
+        values.len()
+    );
+    Ok(values.iter().cloned().collect())
+}
@@ -0,0 +1,219 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! Replays golden ujson transcripts of the provisioning steps against the host marshaling code.
+//!
+//! Each transcript in `testdata/` records the console traffic of a provisioning step, one entry
+//! per line:
+//!
+//! - `< <output>`: a line of device console output.
+//! - `~ <regex>`: the host waits for `<regex>` in the device output.
+//! - `> <Type> <json>`: the host sends a `<Type>`; `<json>` is exactly what the host writes to
+//!   the console, including the trailing `{"crc":...}` frame of messages sent with a CRC.
+//! - `? <Type>`: the host receives a `<Type>` from the device output; the value must match the
+//!   JSON of the last `RESP_OK` frame of the device output.
+//!
+//! Lines starting with `#` and blank lines are ignored.
+
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::time::Duration;
+
+use anyhow::{anyhow, bail, ensure, Context, Result};
+use regex::Regex;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::Value;
+
+use opentitanlib::io::console::ConsoleDevice;
+use opentitanlib::test_utils::rpc::{ConsoleRecv, ConsoleSend};
+use opentitanlib::uart::console::UartConsole;
+use ujson_lib::provisioning_data::*;
+
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+/// Console replaying the recorded device output, and recording the host writes.
+#[derive(Default)]
+struct ReplayConsole {
+    rx: RefCell<VecDeque<u8>>,
+    tx: RefCell<Vec<u8>>,
+}
+
+impl ConsoleDevice for ReplayConsole {
+    fn console_read(&self, buf: &mut [u8], timeout: Duration) -> Result<usize> {
+        let mut rx = self.rx.borrow_mut();
+        if rx.is_empty() {
+            std::thread::sleep(timeout.min(Duration::from_millis(1)));
+            return Ok(0);
+        }
+        let len = buf.len().min(rx.len());
+        for (dst, src) in buf.iter_mut().zip(rx.drain(..len)) {
+            *dst = src;
+        }
+        Ok(len)
+    }
+
+    fn console_write(&self, buf: &[u8]) -> Result<()> {
+        self.tx.borrow_mut().extend_from_slice(buf);
+        Ok(())
+    }
+}
+
+/// Parses the `<json>` of a host entry, re-sends it and checks the host writes it verbatim.
+fn replay_send<T: Serialize + DeserializeOwned>(console: &ReplayConsole, wire: &str) -> Result<()> {
+    let mut values = serde_json::Deserializer::from_str(wire).into_iter::<Value>();
+    let message = values.next().context("Missing message")??;
+    let with_crc = match values.next() {
+        None => false,
+        Some(crc) => {
+            ensure!(
+                crc?.get("crc").is_some(),
+                "Trailing data is not a CRC frame"
+            );
+            ensure!(values.next().is_none(), "Trailing data after the CRC frame");
+            true
+        }
+    };
+    let message: T = serde_json::from_value(message)?;
+    console.tx.borrow_mut().clear();
+    if with_crc {
+        message.send_with_crc(console)?;
+    } else {
+        message.send(console)?;
+    }
+    let sent = String::from_utf8(console.tx.borrow().clone())?;
+    ensure!(sent == wire, "Host sent:\n{sent}\nexpected:\n{wire}");
+    Ok(())
+}
+
+/// Receives a `T` from the device output, and checks it matches the JSON of `frame`.
+fn replay_recv<T: Serialize + DeserializeOwned>(
+    console: &ReplayConsole,
+    frame: Option<&str>,
+) -> Result<()> {
+    let frame = frame.context("No RESP_OK frame in the device output")?;
+    let received = T::recv_window(console, TIMEOUT, true)?;
+    let expected: Value = serde_json::from_str(frame)?;
+    ensure!(
+        serde_json::to_value(&received)? == expected,
+        "Host received:\n{}\nexpected:\n{frame}",
+        serde_json::to_string(&received)?
+    );
+    Ok(())
+}
+
+/// Calls `$f::<T>($args)` for the ujson type `T` named `$name`.
+macro_rules! dispatch {
+    ($name:expr, $f:ident($($args:expr),*), [$($ty:ident),* $(,)?]) => {
+        match $name {
+            $(stringify!($ty) => $f::<$ty>($($args),*),)*
+            name => Err(anyhow!("Unknown ujson type {name}")),
+        }
+    };
+}
+
+macro_rules! dispatch_provisioning_data {
+    ($name:expr, $f:ident($($args:expr),*)) => {
+        dispatch!(
+            $name,
+            $f($($args),*),
+            [
+                EccP256PublicKey,
+                LcTokenHash,
+                ManufCertgenInputs,
+                ManufCpProvisioningData,
+                ManufCpProvisioningDataOut,
+                ManufCpTestData,
+                ManufCreatorManufState,
+                ManufFtIndividualizeData,
+                ManufHealthSnapshot,
+                ManufKeymgrBinding,
+                ManufLogLevel,
+                ManufOtpDumpChunk,
+                ManufOwnerSwCfgAlertCfg,
+                ManufPersoExportOptions,
+                PersoBlob,
+                PersoBlobChunk,
+                PersoBlobChunkAck,
+                PersoBlobImportChunk,
+                SerdesSha256Hash,
+            ]
+        )
+    };
+}
+
+fn replay(transcript: &str) -> Result<()> {
+    let console = ReplayConsole::default();
+    let resp_ok = Regex::new(r"RESP_OK:(.*) CRC:([0-9]+)$")?;
+    let mut last_frame = None;
+    for (n, line) in transcript.lines().enumerate() {
+        let line = line.trim_end();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let (kind, entry) = line.split_at(1);
+        let entry = entry.strip_prefix(' ').unwrap_or(entry);
+        let result = match kind {
+            "<" => {
+                if let Some(cap) = resp_ok.captures(entry) {
+                    last_frame = Some(cap[1].to_string());
+                }
+                let mut rx = console.rx.borrow_mut();
+                rx.extend(entry.as_bytes());
+                rx.push_back(b'\n');
+                Ok(())
+            }
+            "~" => UartConsole::wait_for(&console, entry, TIMEOUT).map(|_| ()),
+            ">" => {
+                let (ty, wire) = entry
+                    .split_once(' ')
+                    .with_context(|| format!("Malformed host entry: {entry}"))?;
+                dispatch_provisioning_data!(ty, replay_send(&console, wire))
+            }
+            "?" => {
+                let frame = last_frame.as_deref();
+                dispatch_provisioning_data!(entry, replay_recv(&console, frame))
+            }
+            _ => bail!("Unknown transcript entry on line {}: {line}", n + 1),
+        };
+        result.with_context(|| format!("Transcript line {}: {line}", n + 1))?;
+    }
+    // Only the newline ending the last line waited for may be left.
+    let pending = String::from_utf8(console.rx.borrow().iter().copied().collect())?;
+    ensure!(
+        pending.trim().is_empty(),
+        "Device output not consumed: {pending}"
+    );
+    Ok(())
+}
+
+#[test]
+fn test_cp() -> Result<()> {
+    replay(include_str!("testdata/cp.txt"))
+}
+
+#[test]
+fn test_ft_individualize() -> Result<()> {
+    replay(include_str!("testdata/ft_individualize.txt"))
+}
+
+#[test]
+fn test_ft_otp_dump() -> Result<()> {
+    replay(include_str!("testdata/ft_otp_dump.txt"))
+}
+
+#[test]
+fn test_ft_personalize() -> Result<()> {
+    replay(include_str!("testdata/ft_personalize.txt"))
+}
+
+#[test]
+fn test_detects_wire_format_change() {
+    // A field renamed on the host side no longer parses.
+    let transcript = r#"> LcTokenHash {"hashes":[1,2]}"#;
+    assert!(replay(transcript).is_err());
+    // A CRC that does not match the message.
+    let transcript = r#"> LcTokenHash {"hash":[1,2]}{"crc":1}"#;
+    assert!(replay(transcript).is_err());
+}
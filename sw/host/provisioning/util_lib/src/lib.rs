@@ -2,65 +2,143 @@
 // Licensed under the Apache License, Version 2.0, see LICENSE for details.
 // SPDX-License-Identifier: Apache-2.0
 
-use anyhow::{Context, Result};
+use anyhow::{bail, ensure, Context, Result};
 use arrayvec::ArrayVec;
 use hex::decode;
+use opentitanlib::otp::lc_token::{HashedLcToken, LcToken, LC_TOKEN_SIZE};
+use opentitanlib::otp::otp_img::OtpImg;
 use rand::rngs::OsRng;
 use rand::{CryptoRng, Rng};
-use rsa::pkcs1::DecodeRsaPublicKey;
+use rsa::pkcs1::{DecodeRsaPrivateKey, DecodeRsaPublicKey};
 use rsa::pkcs1v15::Pkcs1v15Encrypt;
-use rsa::pkcs8::DecodePublicKey;
+use rsa::pkcs8::{DecodePrivateKey, DecodePublicKey};
 use rsa::traits::PaddingScheme;
-use rsa::RsaPublicKey;
+use rsa::{RsaPrivateKey, RsaPublicKey};
+use std::collections::HashMap;
 use std::path::Path;
-use tiny_keccak::{CShake, Hasher};
 use zerocopy::IntoBytes;
 
+/// Decodes a hex string, optionally `0x`-prefixed and `_`-separated.
+///
+/// The errors don't quote the string, which may be a secret.
+fn decode_hex_string(hex_str: &str) -> Result<Vec<u8>> {
+    let digits = hex_str
+        .strip_prefix("0x")
+        .unwrap_or(hex_str)
+        .replace('_', "");
+    decode(&digits).context("Not a hex string")
+}
+
+/// Parses the `N` words of a ujson field from a hex string, each word big-endian.
+///
+/// The string must hold exactly `N` words, the size of the firmware buffer: see
+/// `ujson_lib::limits` for the sizes of the fields.
 pub fn hex_string_to_u32_arrayvec<const N: usize>(hex_str: &str) -> Result<ArrayVec<u32, N>> {
-    let hex_str_no_sep = hex_str.replace('_', "");
-    let hex_str_prefix = "0x";
-    let sanitized_hex_str = if hex_str.starts_with(hex_str_prefix) {
-        hex_str_no_sep.strip_prefix(hex_str_prefix).unwrap()
-    } else {
-        hex_str_no_sep.as_str()
-    };
-    Ok(decode(sanitized_hex_str)?
+    let bytes = decode_hex_string(hex_str)?;
+    ensure!(
+        bytes.len() == 4 * N,
+        "Hex string is {} bytes long, the firmware expects {N} words ({} hex digits)",
+        bytes.len(),
+        8 * N
+    );
+    Ok(bytes
         .chunks(4)
         .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
-        .collect::<ArrayVec<u32, N>>())
+        .collect())
+}
+
+/// Formats a device ID as a hex string, each word as 8 uppercase hex digits, in order.
+pub fn format_device_id(device_id: &[u32]) -> String {
+    device_id.iter().map(|v| format!("{v:08X}")).collect()
 }
 
+/// Parses the `N` bytes of a ujson field from a hex string, which must hold exactly `N` bytes.
 pub fn hex_string_to_u8_arrayvec<const N: usize>(hex_str: &str) -> Result<ArrayVec<u8, N>> {
-    let hex_str_no_sep = hex_str.replace('_', "");
-    let hex_str_prefix = "0x";
-    let sanitized_hex_str = if hex_str.starts_with(hex_str_prefix) {
-        hex_str_no_sep.strip_prefix(hex_str_prefix).unwrap()
-    } else {
-        hex_str_no_sep.as_str()
-    };
-    Ok(decode(sanitized_hex_str)?
-        .into_iter()
-        .collect::<ArrayVec<u8, N>>())
+    let bytes = decode_hex_string(hex_str)?;
+    ensure!(
+        bytes.len() == N,
+        "Hex string is {} bytes long, the firmware expects {N} bytes ({} hex digits)",
+        bytes.len(),
+        2 * N
+    );
+    Ok(bytes.into_iter().collect())
 }
 
 /// Life cycle tokens are hashed using a keccak hashing algorithm. The result is
 /// a 16 byte value represented as a vector of two u64s.
+///
+/// See `opentitanlib::otp::lc_token` for the typed API.
 pub fn hash_lc_token(input: &[u8]) -> Result<ArrayVec<u64, 2>> {
-    let name = b"";
-    let customazation = b"LC_CTRL";
-    let mut csh = CShake::v128(name, customazation);
-    let mut output = [0u8; 16];
-
-    csh.update(input);
-    csh.finalize(&mut output);
-
-    Ok(output
-        .chunks_exact(8)
-        .map(|chunk| {
-            let arr: [u8; 8] = chunk.try_into().expect("chunk is the wrong size");
-            u64::from_le_bytes(arr)
-        })
-        .collect::<ArrayVec<u64, 2>>())
+    let token = LcToken::from_bytes(
+        input
+            .try_into()
+            .with_context(|| format!("LC token must be 16 bytes long, got {}", input.len()))?,
+    );
+    Ok(ArrayVec::from(token.hash().to_u64s()))
+}
+
+/// Parses a life cycle token from a hex string: the TRANSITION_TOKEN register values in order,
+/// each as 8 hex digits, optionally `0x`-prefixed and `_`-separated.
+///
+/// The token must be exactly 128 bits long.
+pub fn parse_lc_token(hex_str: &str) -> Result<ArrayVec<u32, 4>> {
+    let digits = hex_str
+        .strip_prefix("0x")
+        .unwrap_or(hex_str)
+        .replace('_', "");
+    ensure!(
+        digits.len() == 2 * LC_TOKEN_SIZE,
+        "LC token must be 128 bits long (32 hex digits), got {} hex digits",
+        digits.len()
+    );
+    let bytes = decode(&digits).context("LC token is not a hex string")?;
+    Ok(bytes
+        .chunks(4)
+        .map(|bytes| u32::from_be_bytes(bytes.try_into().unwrap()))
+        .collect())
+}
+
+/// Loads the life cycle token `name` (e.g. `test_unlock_token`) from `path`.
+///
+/// `.hjson` and `.json` files map token names to hex strings, as parsed by `parse_lc_token`.
+/// Other files hold the raw 16-byte token, as written to the TRANSITION_TOKEN registers: each
+/// register value in little-endian byte order.
+pub fn load_lc_token(path: &Path, name: &str) -> Result<ArrayVec<u32, 4>> {
+    let bytes =
+        std::fs::read(path).with_context(|| format!("Failed to read token file {path:?}"))?;
+    let extension = path.extension().and_then(|e| e.to_str());
+    if matches!(extension, Some("hjson" | "json")) {
+        let tokens: HashMap<String, String> =
+            deser_hjson::from_str(std::str::from_utf8(&bytes)?)
+                .with_context(|| format!("Failed to parse token file {path:?}"))?;
+        let token = tokens
+            .get(name)
+            .with_context(|| format!("Token file {path:?} holds no {name}"))?;
+        return parse_lc_token(token).with_context(|| format!("Invalid {name} in {path:?}"));
+    }
+    let Ok(bytes) = <[u8; LC_TOKEN_SIZE]>::try_from(bytes.as_slice()) else {
+        let text = String::from_utf8_lossy(&bytes);
+        if parse_lc_token(text.trim()).is_ok() {
+            bail!("Token file {path:?} holds a hex string, expected a raw 16-byte token");
+        }
+        bail!(
+            "Token file {path:?} holds {} bytes, expected a raw 16-byte token",
+            bytes.len()
+        );
+    };
+    Ok(ArrayVec::from(LcToken::from_bytes(bytes).to_words()))
+}
+
+/// Checks `token` hashes to the `item` token hash (e.g. `TEST_UNLOCK_TOKEN`) of the SECRET0
+/// partition of an OTP image.
+pub fn check_lc_token_hash(token: &ArrayVec<u32, 4>, otp_img: &OtpImg, item: &str) -> Result<()> {
+    let expected = HashedLcToken::from_otp(otp_img, item)?;
+    ensure!(
+        LcToken::from_word_slice(token)?.hash() == expected,
+        "Token does not match the {item} hash of the OTP image; check the token and its word \
+         order: TRANSITION_TOKEN register values in order, each as 8 hex digits"
+    );
+    Ok(())
 }
 
 fn _random_data<RNG>(rng: &mut RNG, data: &mut [u32]) -> Result<()>
@@ -90,6 +168,38 @@ pub fn load_rsa_public_key(path: impl AsRef<Path>) -> Result<RsaPublicKey> {
     }
 }
 
+pub fn parse_rsa_public_key(der: &[u8]) -> Result<RsaPublicKey> {
+    match RsaPublicKey::from_pkcs1_der(der).context("parse PKCS#1 der") {
+        Ok(key) => Ok(key),
+        Err(e) => Ok(RsaPublicKey::from_public_key_der(der)
+            .with_context(|| format!("parse PKCS#8 der (previous error: {e})"))?),
+    }
+}
+
+pub fn load_rsa_private_key(path: impl AsRef<Path>) -> Result<RsaPrivateKey> {
+    let path = path.as_ref();
+    match DecodeRsaPrivateKey::read_pkcs1_der_file(path)
+        .with_context(|| format!("read PKCS#1 der {path:?}"))
+    {
+        Ok(key) => Ok(key),
+        Err(e) => Ok(DecodePrivateKey::read_pkcs8_der_file(path)
+            .with_context(|| format!("read PKCS#8 der {path:?} (previous error: {e})"))?),
+    }
+}
+
 pub fn encrypt_token(pub_key: &RsaPublicKey, token: &[u32]) -> Result<Vec<u8>> {
     Ok(Pkcs1v15Encrypt.encrypt(&mut OsRng, pub_key, token.as_bytes())?)
 }
+
+/// Inverse of `encrypt_token`.
+pub fn decrypt_token(priv_key: &RsaPrivateKey, encrypted_token: &[u8]) -> Result<Vec<u32>> {
+    let token = Pkcs1v15Encrypt.decrypt(Some(&mut OsRng), priv_key, encrypted_token)?;
+    ensure!(
+        token.len() % 4 == 0,
+        "Decrypted token is not a whole number of words"
+    );
+    Ok(token
+        .chunks(4)
+        .map(|bytes| u32::from_ne_bytes(bytes.try_into().unwrap()))
+        .collect())
+}
@@ -17,12 +17,14 @@ use opentitanlib::console::spi::SpiConsoleDevice;
 use opentitanlib::dif::lc_ctrl::{DifLcCtrlState, LcCtrlReg};
 use opentitanlib::io::jtag::JtagTap;
 use opentitanlib::test_utils::init::InitializeTest;
-use opentitanlib::test_utils::lc_transition;
+use opentitanlib::test_utils::lc_transition::{self, LcStateCheck};
 use opentitanlib::test_utils::load_sram_program::{
     ExecutionMode, ExecutionResult, SramProgramParams,
 };
 use opentitanlib::test_utils::rpc::ConsoleSend;
 use opentitanlib::uart::console::UartConsole;
+use provisioning::session::DeviceSession;
+use provisioning::timeouts::Timeouts;
 use ujson_lib::provisioning_data::{ManufCpProvisioningData, ManufCpTestData};
 use util_lib::hash_lc_token;
 
@@ -44,6 +46,9 @@ pub struct Opts {
     /// Name of the SPI interface to connect to the OTTF console.
     #[arg(long, default_value = "BOOTSTRAP")]
     console_spi: String,
+
+    #[command(flatten)]
+    lc_state_check: LcStateCheck,
 }
 
 fn cp_provision(
@@ -57,21 +62,16 @@ fn cp_provision(
         elf: opts.provisioning_sram_elf.clone(),
         ..Default::default()
     };
+    let session = DeviceSession::new(transport, &opts.init);
     run_sram_cp_provision(
-        transport,
-        &opts.init.jtag_params,
-        opts.init.bootstrap.options.reset_delay,
+        &session,
         &provisioning_sram_program,
         provisioning_data,
         spi_console,
         response,
-        opts.timeout,
-    )?;
-    reset_and_lock(
-        transport,
-        &opts.init.jtag_params,
-        opts.init.bootstrap.options.reset_delay,
+        &Timeouts::uniform(opts.timeout),
     )?;
+    reset_and_lock(&session, &opts.lc_state_check)?;
     Ok(())
 }
 
@@ -228,6 +228,7 @@ fn main() -> Result<()> {
         &transport,
         &opts.init.jtag_params,
         opts.init.bootstrap.options.reset_delay,
+        &opts.lc_state_check,
     )?;
 
     // Generate random test wafer data.
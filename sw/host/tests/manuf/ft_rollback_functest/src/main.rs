@@ -0,0 +1,184 @@
+// Copyright lowRISC contributors (OpenTitan project).
+// Licensed under the Apache License, Version 2.0, see LICENSE for details.
+// SPDX-License-Identifier: Apache-2.0
+
+//! This test exercises the FT flow error paths around `test_exit` repeatedly on a single
+//! FPGA-emulated device.
+//!
+//! The device is test unlocked and individualized once, then checkpointed. Each iteration
+//! attempts a `test_exit` with a corrupted token, which must fail and leave the device in
+//! `TEST_UNLOCKED1`, followed by a `test_exit` with the correct token, after which the device is
+//! rolled back to the checkpoint.
+
+use std::time::Duration;
+
+use anyhow::{ensure, Result};
+use arrayvec::ArrayVec;
+use clap::Parser;
+
+use ft_lib::alert_cfg::AlertCfg;
+use ft_lib::provisioner::{Capabilities, FtProvisioner};
+use ft_lib::secret::LcTokenSecret;
+use ft_lib::trim::AstTrim;
+use ft_lib::IndividualizePartition;
+use opentitanlib::app::TransportWrapper;
+use opentitanlib::console::spi::SpiConsoleDevice;
+use opentitanlib::dif::lc_ctrl::DifLcCtrlState;
+use opentitanlib::execute_test;
+use opentitanlib::test_utils::init::InitializeTest;
+use opentitanlib::test_utils::load_sram_program::{JtagClockRamp, SramProgramParams};
+use opentitanlib::test_utils::rollback::FpgaRollback;
+use ujson_lib::provisioning_data::ManufFtIndividualizeData;
+use util_lib::hex_string_to_u32_arrayvec;
+
+#[derive(Debug, Parser)]
+struct Opts {
+    #[command(flatten)]
+    init: InitializeTest,
+
+    #[command(flatten)]
+    sram_program: SramProgramParams,
+
+    /// Console receive timeout.
+    #[arg(long, value_parser = humantime::parse_duration, default_value = "600s")]
+    timeout: Duration,
+
+    /// Name of the SPI interface to connect to the OTTF console.
+    #[arg(long, default_value = "BOOTSTRAP")]
+    console_spi: String,
+
+    /// TestUnlock token; a 128-bit hex string.
+    #[arg(long)]
+    test_unlock_token: String,
+
+    /// TestExit token; a 128-bit hex string.
+    #[arg(long)]
+    test_exit_token: String,
+
+    /// Device ID to provision.
+    #[arg(long)]
+    device_id: String,
+
+    /// LC state to transition to from TEST_UNLOCKED1.
+    #[arg(long, value_parser = DifLcCtrlState::parse_lc_state_str)]
+    target_mission_mode_lc_state: DifLcCtrlState,
+
+    /// Number of times to exercise the `test_exit` error paths.
+    #[arg(long, default_value = "3")]
+    iterations: usize,
+}
+
+fn main() -> Result<()> {
+    let opts = Opts::parse();
+    opts.init.init_logging();
+    let transport = opts.init.init_target()?;
+
+    let mut rollback = FpgaRollback::new(&opts.init.load_bitstream)?;
+    execute_test!(
+        checkpoint_before_test_exit,
+        &opts,
+        &transport,
+        &mut rollback
+    );
+    for iteration in 0..opts.iterations {
+        if iteration > 0 {
+            rollback.restore(&transport)?;
+        }
+        log::info!("Iteration {}/{}.", iteration + 1, opts.iterations);
+        execute_test!(test_exit_bad_token, &opts, &transport);
+        execute_test!(test_exit_good_token, &opts, &transport);
+    }
+
+    Ok(())
+}
+
+/// Runs `f` on a provisioner allowed to perform all one-way operations.
+fn with_provisioner<T>(
+    opts: &Opts,
+    transport: &TransportWrapper,
+    f: impl FnOnce(&FtProvisioner) -> Result<T>,
+) -> Result<T> {
+    let spi = transport.spi(&opts.console_spi)?;
+    let spi_console = SpiConsoleDevice::new(&*spi, None)?;
+    let ft = FtProvisioner::new(
+        transport,
+        &opts.init,
+        &spi_console,
+        opts.timeout,
+        Capabilities::all(),
+    );
+    f(&ft)
+}
+
+/// Runs all FT stages preceding `test_exit`, recording them in the rollback checkpoint.
+fn checkpoint_before_test_exit<'a>(
+    opts: &'a Opts,
+    transport: &TransportWrapper,
+    rollback: &mut FpgaRollback<'a>,
+) -> Result<()> {
+    let test_unlock_token = LcTokenSecret::from_words(hex_string_to_u32_arrayvec::<4>(
+        opts.test_unlock_token.as_str(),
+    )?)?;
+    rollback.run_stage(transport, "test-unlock", move |transport| {
+        with_provisioner(opts, transport, |ft| {
+            ft.test_unlock(
+                &test_unlock_token,
+                DifLcCtrlState::TestLocked0,
+                DifLcCtrlState::TestUnlocked1,
+            )
+        })
+    })?;
+
+    let no_trim = AstTrim::default();
+    let ft_individualize_data_in = ManufFtIndividualizeData {
+        device_id: hex_string_to_u32_arrayvec::<8>(opts.device_id.as_str())?,
+        partitions: IndividualizePartition::all(),
+        ast_trim_mask: ArrayVec::from(no_trim.mask),
+        ast_trim_value: ArrayVec::from(no_trim.value),
+    };
+    rollback.run_stage(transport, "ft-individualize", move |transport| {
+        with_provisioner(opts, transport, |ft| {
+            ft.individualize(
+                &opts.sram_program,
+                &JtagClockRamp::default(),
+                &ft_individualize_data_in,
+                &AlertCfg::default(),
+            )
+        })
+    })
+}
+
+/// Checks a `test_exit` with a corrupted token fails and leaves the device test unlocked.
+fn test_exit_bad_token(opts: &Opts, transport: &TransportWrapper) -> Result<()> {
+    let mut bad_token = hex_string_to_u32_arrayvec::<4>(opts.test_exit_token.as_str())?;
+    bad_token[0] ^= 1;
+    let bad_token = LcTokenSecret::from_words(bad_token)?;
+    let state = with_provisioner(opts, transport, |ft| {
+        let result = ft.test_exit(&bad_token, "", opts.target_mission_mode_lc_state);
+        ensure!(result.is_err(), "test_exit succeeded with a bad token");
+        transport.pin_strapping("PINMUX_TAP_LC")?.remove()?;
+        ft.read_lc_state()
+    })?;
+    ensure!(
+        state == DifLcCtrlState::TestUnlocked1,
+        "Device in {state:?} after a failed test_exit"
+    );
+    Ok(())
+}
+
+/// Checks a `test_exit` with the correct token reaches the mission mode LC state.
+fn test_exit_good_token(opts: &Opts, transport: &TransportWrapper) -> Result<()> {
+    let token = LcTokenSecret::from_words(hex_string_to_u32_arrayvec::<4>(
+        opts.test_exit_token.as_str(),
+    )?)?;
+    let state = with_provisioner(opts, transport, |ft| {
+        ft.test_exit(&token, "", opts.target_mission_mode_lc_state)?;
+        ft.read_lc_state()
+    })?;
+    ensure!(
+        state == opts.target_mission_mode_lc_state,
+        "Device in {state:?} after test_exit, expected {:?}",
+        opts.target_mission_mode_lc_state
+    );
+    Ok(())
+}